@@ -0,0 +1,264 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// let an external command approve removals
+// With --approve-with <COMMAND>, trim, clean-unref and --remove-if-{older,younger}-than pass the
+// list of items they are about to remove to the command before removing anything, so that
+// organizations can plug in their own retention policies.
+// The command is run through the shell and gets this on stdin:
+//   {"operation": "trim", "dry_run": false, "candidates": [{"path": "/home/user/.cargo/...", "size": 1234}]}
+// and must print the paths that may be removed on stdout:
+//   {"approved": ["/home/user/.cargo/..."]}
+// Approving a subset of the candidates keeps the rest, paths that were not candidates are refused.
+// If the command fails or prints something else, nothing is removed.
+// Talking json to the command needs the "json" feature.
+
+#[cfg(feature = "json")]
+use std::collections::HashSet;
+#[cfg(feature = "json")]
+use std::io::Write;
+use std::path::PathBuf;
+#[cfg(feature = "json")]
+use std::process::{Command, Stdio};
+
+use once_cell::sync::OnceCell;
+#[cfg(feature = "json")]
+use serde_json::{json, Value};
+
+use crate::library::Error;
+
+/// the --approve-with command of this run, set up once in `main()`
+static APPROVE_COMMAND: OnceCell<Option<String>> = OnceCell::new();
+
+/// set the command that has to approve removals, `None` approves everything
+pub(crate) fn init(command: Option<&str>) -> Result<(), Error> {
+    if command.is_some() && !cfg!(feature = "json") {
+        return Err(Error::FeatureDisabled(
+            String::from("--approve-with"),
+            "json",
+        ));
+    }
+    let _ = APPROVE_COMMAND.set(command.map(ToString::to_string));
+    Ok(())
+}
+
+/// the json that is passed to the command on stdin
+#[cfg(feature = "json")]
+fn request(operation: &str, dry_run: bool, candidates: &[(PathBuf, u64)]) -> Value {
+    let candidates = candidates
+        .iter()
+        .map(|(path, size)| json!({ "path": path, "size": size }))
+        .collect::<Vec<Value>>();
+    json!({
+        "operation": operation,
+        "dry_run": dry_run,
+        "candidates": candidates,
+    })
+}
+
+/// parse the output of the command into the set of approved paths
+#[cfg(feature = "json")]
+fn parse_response(
+    command: &str,
+    stdout: &str,
+    candidates: &[(PathBuf, u64)],
+) -> Result<HashSet<PathBuf>, Error> {
+    let invalid = |reason: String| Error::ApproveCommandFailed(command.to_string(), reason);
+
+    let response: Value = serde_json::from_str(stdout)
+        .map_err(|error| invalid(format!("failed to parse its output as json: {error}")))?;
+    let approved = response
+        .get("approved")
+        .and_then(Value::as_array)
+        .ok_or_else(|| invalid(String::from("its output has no \"approved\" list")))?;
+
+    approved
+        .iter()
+        .map(|path| {
+            let path = path
+                .as_str()
+                .map(PathBuf::from)
+                .ok_or_else(|| invalid(format!("approved path {path} is not a string")))?;
+            if candidates.iter().any(|(candidate, _)| candidate == &path) {
+                Ok(path)
+            } else {
+                Err(invalid(format!(
+                    "it approved \"{}\" which is not up for removal",
+                    path.display()
+                )))
+            }
+        })
+        .collect()
+}
+
+/// run the command, pass the candidates on stdin and read the approved paths from stdout
+#[cfg(feature = "json")]
+fn run_approve_command(
+    command: &str,
+    operation: &str,
+    dry_run: bool,
+    candidates: &[(PathBuf, u64)],
+) -> Result<HashSet<PathBuf>, Error> {
+    let failed = |reason: String| Error::ApproveCommandFailed(command.to_string(), reason);
+
+    let (shell, flag) = if cfg!(windows) {
+        ("cmd", "/C")
+    } else {
+        ("sh", "-c")
+    };
+    let mut child = Command::new(shell)
+        .arg(flag)
+        .arg(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|error| failed(error.to_string()))?;
+
+    // the command may exit without reading its stdin, this is not an error on our side
+    if let Some(mut stdin) = child.stdin.take() {
+        let _ = stdin.write_all(
+            request(operation, dry_run, candidates)
+                .to_string()
+                .as_bytes(),
+        );
+    }
+
+    let output = child
+        .wait_with_output()
+        .map_err(|error| failed(error.to_string()))?;
+    if !output.status.success() {
+        return Err(failed(format!("it exited with {}", output.status)));
+    }
+
+    parse_response(
+        command,
+        &String::from_utf8_lossy(&output.stdout),
+        candidates,
+    )
+}
+
+/// keep only the items that the --approve-with command approved for removal
+/// `path_and_size` is only called if a command was passed
+#[cfg(feature = "json")]
+pub(crate) fn approved<T>(
+    operation: &str,
+    dry_run: bool,
+    items: Vec<T>,
+    path_and_size: impl Fn(&T) -> (PathBuf, u64),
+) -> Result<Vec<T>, Error> {
+    let command = match APPROVE_COMMAND.get() {
+        Some(Some(command)) => command,
+        _ => return Ok(items),
+    };
+    if items.is_empty() {
+        return Ok(items);
+    }
+
+    let candidates = items.iter().map(&path_and_size).collect::<Vec<_>>();
+    let approved = run_approve_command(command, operation, dry_run, &candidates)?;
+
+    let kept = items.len() - approved.len();
+    if kept > 0 {
+        println!("--approve-with: keeping {kept} of {} items", items.len());
+    }
+
+    Ok(items
+        .into_iter()
+        .filter(|item| approved.contains(&path_and_size(item).0))
+        .collect())
+}
+
+/// without json support there is no --approve-with command, everything is approved
+#[cfg(not(feature = "json"))]
+#[allow(clippy::unnecessary_wraps)] // same signature as with json support
+pub(crate) fn approved<T>(
+    _operation: &str,
+    _dry_run: bool,
+    items: Vec<T>,
+    _path_and_size: impl Fn(&T) -> (PathBuf, u64),
+) -> Result<Vec<T>, Error> {
+    Ok(items)
+}
+
+#[cfg(all(test, feature = "json"))]
+mod approve_tests {
+    use super::*;
+    use crate::schema::schema_tests::assert_valid;
+    use pretty_assertions::assert_eq;
+
+    fn candidates() -> Vec<(PathBuf, u64)> {
+        vec![
+            (PathBuf::from("/cargo/registry/cache/foo-1.0.0.crate"), 10),
+            (PathBuf::from("/cargo/git/db/bar-5678"), 20),
+        ]
+    }
+
+    #[test]
+    fn request_json() {
+        assert_eq!(
+            request("trim", true, &candidates()),
+            json!({
+                "operation": "trim",
+                "dry_run": true,
+                "candidates": [
+                    { "path": "/cargo/registry/cache/foo-1.0.0.crate", "size": 10 },
+                    { "path": "/cargo/git/db/bar-5678", "size": 20 },
+                ],
+            })
+        );
+        assert_valid("approve_request", &request("trim", true, &candidates()));
+        assert_valid(
+            "approve_response",
+            &json!({ "approved": ["/cargo/git/db/bar-5678"] }),
+        );
+    }
+
+    #[test]
+    fn response() {
+        let approved = parse_response(
+            "policy",
+            r#"{"approved": ["/cargo/git/db/bar-5678"]}"#,
+            &candidates(),
+        )
+        .unwrap();
+        assert_eq!(
+            approved,
+            HashSet::from([PathBuf::from("/cargo/git/db/bar-5678")])
+        );
+
+        assert!(
+            parse_response("policy", r#"{"approved": []}"#, &candidates())
+                .unwrap()
+                .is_empty()
+        );
+        // never remove anything that was not up for removal
+        assert!(parse_response("policy", r#"{"approved": ["/home"]}"#, &candidates()).is_err());
+        assert!(parse_response("policy", r#"{"approved": [1]}"#, &candidates()).is_err());
+        assert!(parse_response("policy", "{}", &candidates()).is_err());
+        assert!(parse_response("policy", "yes", &candidates()).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn command() {
+        let approved = run_approve_command(
+            r#"cat > /dev/null; echo '{"approved": ["/cargo/registry/cache/foo-1.0.0.crate"]}'"#,
+            "clean-unref",
+            false,
+            &candidates(),
+        )
+        .unwrap();
+        assert_eq!(
+            approved,
+            HashSet::from([PathBuf::from("/cargo/registry/cache/foo-1.0.0.crate")])
+        );
+
+        assert!(run_approve_command("exit 1", "trim", false, &candidates()).is_err());
+    }
+}
@@ -0,0 +1,262 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// parsers for sizes ("1.5G"), durations ("30d") and dates ("2024.01.01")
+// Every option that takes one of these parses it here, so they all accept the same formats and a
+// new format only has to be added once. Commands map a ParseError to their own library::Error if
+// they need to say more than "this is not a size".
+
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+use chrono::{prelude::*, NaiveDateTime};
+
+/// a value that could not be parsed, holds the text that was passed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum ParseError {
+    Size(String),
+    Duration(String),
+    Date(String),
+    // neither a duration nor a date
+    Age(String),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Size(size) => write!(
+                f,
+                "Failed to parse \"{size}\" as a size, expected a number with an optional unit B, K, M, G or T, for example 500M or 1.5G"
+            ),
+            Self::Duration(duration) => write!(
+                f,
+                "Failed to parse \"{duration}\" as a duration, expected a number of days (d), weeks (w), months (m) or years (y), for example 30d"
+            ),
+            Self::Date(date) => write!(
+                f,
+                "Failed to parse \"{date}\" as a date, expected YYYY.MM.DD or HH:MM:SS"
+            ),
+            Self::Age(age) => write!(
+                f,
+                "Failed to parse \"{age}\", expected a duration such as 30d, 6w, 3m (months) or 1y, or a date YYYY.MM.DD or HH:MM:SS"
+            ),
+        }
+    }
+}
+
+/// what the units of a size stand for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SizeUnits {
+    /// 1K = 1000 bytes, like the sizes cargo-cache prints
+    Decimal,
+    /// 1K = 1024 bytes, trim --limit has always worked like this
+    Binary,
+}
+
+/// parse a size such as "100", "500M", "1.5g" or "2T", a number without unit is in bytes
+pub(crate) fn size(size: &str, units: SizeUnits) -> Result<u64, ParseError> {
+    let error = || ParseError::Size(size.to_string());
+    let base: u64 = match units {
+        SizeUnits::Decimal => 1000,
+        SizeUnits::Binary => 1024,
+    };
+
+    let (number, multiplier): (&str, u64) = match size.chars().last() {
+        Some('b' | 'B') => (&size[..size.len() - 1], 1),
+        Some('k' | 'K') => (&size[..size.len() - 1], base),
+        Some('m' | 'M') => (&size[..size.len() - 1], base.pow(2)),
+        Some('g' | 'G') => (&size[..size.len() - 1], base.pow(3)),
+        Some('t' | 'T') => (&size[..size.len() - 1], base.pow(4)),
+        Some(c) if c.is_ascii_digit() => (size, 1),
+        _ => return Err(error()),
+    };
+
+    let value: f64 = number
+        .parse()
+        .ok()
+        .filter(|value: &f64| value.is_finite() && *value >= 0.0)
+        .ok_or_else(error)?;
+    // we may truncate the value here but that's ok
+    #[allow(
+        clippy::cast_sign_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_precision_loss
+    )]
+    Ok((value * multiplier as f64) as u64)
+}
+
+/// parse a duration such as "30d", "6w", "3m" (months) or "1y"
+/// months count as 30 days and years as 365 days, we only need rough cutoffs
+pub(crate) fn duration(duration: &str) -> Result<Duration, ParseError> {
+    const DAY: u64 = 24 * 60 * 60;
+    let error = || ParseError::Duration(duration.to_string());
+
+    let seconds_per_unit: u64 = match duration.chars().last() {
+        Some('d') => DAY,
+        Some('w') => 7 * DAY,
+        Some('m') => 30 * DAY,
+        Some('y') => 365 * DAY,
+        _ => return Err(error()),
+    };
+    let amount: u64 = duration[..duration.len() - 1]
+        .parse()
+        .map_err(|_| error())?;
+    amount
+        .checked_mul(seconds_per_unit)
+        .map(Duration::from_secs)
+        .ok_or_else(error)
+}
+
+/// check if the text looks like the shape, 'd' stands for a digit, other characters have to match exactly
+fn has_shape(text: &str, shape: &str) -> bool {
+    text.chars().count() == shape.len()
+        && text.chars().zip(shape.chars()).all(
+            |(c, s)| {
+                if s == 'd' {
+                    c.is_ascii_digit()
+                } else {
+                    c == s
+                }
+            },
+        )
+}
+
+/// parse "YYYY.MM.DD" (at the current time of day) or "HH:MM:SS" (today)
+pub(crate) fn date(date: &str) -> Result<NaiveDateTime, ParseError> {
+    let error = || ParseError::Date(date.to_string());
+    let now = Local::now();
+
+    let numbers = |separator: char| -> Option<Vec<u32>> {
+        date.split(separator)
+            .map(|number| number.parse().ok())
+            .collect()
+    };
+
+    let date_time = if has_shape(date, "dddd.dd.dd") {
+        let ymd = numbers('.').ok_or_else(error)?;
+        #[allow(clippy::cast_possible_wrap)]
+        NaiveDate::from_ymd_opt(ymd[0] as i32, ymd[1], ymd[2])
+            .and_then(|day| day.and_hms_opt(now.hour(), now.minute(), now.second()))
+    } else if has_shape(date, "dd:dd:dd") {
+        let hms = numbers(':').ok_or_else(error)?;
+        now.date_naive().and_hms_opt(hms[0], hms[1], hms[2])
+    } else {
+        None
+    };
+    date_time.ok_or_else(error)
+}
+
+/// the point in time before which items count as too old: either `age` ago ("30d", "6w", "3m")
+/// or a date
+pub(crate) fn age_cutoff(age: &str) -> Result<SystemTime, ParseError> {
+    if let Ok(duration) = duration(age) {
+        // everything is newer than a cutoff before the epoch
+        return Ok(SystemTime::now()
+            .checked_sub(duration)
+            .unwrap_or(SystemTime::UNIX_EPOCH));
+    }
+    date(age)
+        .ok()
+        .and_then(|date| Local.from_local_datetime(&date).earliest())
+        .map(SystemTime::from)
+        .ok_or_else(|| ParseError::Age(age.to_string()))
+}
+
+#[cfg(test)]
+mod parse_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn sizes() {
+        let decimal = |text: &str| size(text, SizeUnits::Decimal);
+        assert_eq!(decimal("100"), Ok(100));
+        assert_eq!(decimal("100B"), Ok(100));
+        assert_eq!(decimal("2K"), Ok(2_000));
+        assert_eq!(decimal("1.5m"), Ok(1_500_000));
+        assert_eq!(decimal("5G"), Ok(5_000_000_000));
+        assert_eq!(decimal("1t"), Ok(1_000_000_000_000));
+        assert_eq!(decimal("0K"), Ok(0));
+
+        let binary = |text: &str| size(text, SizeUnits::Binary);
+        assert_eq!(binary("1k"), Ok(1_024));
+        assert_eq!(binary("1.5k"), Ok(1_536));
+        assert_eq!(binary("42M"), Ok(44_040_192));
+        assert_eq!(binary("1T"), Ok(1_099_511_627_776));
+
+        for invalid in ["", "K", "-1K", "5X", "1_", "fast", "infK", "NaN"] {
+            assert_eq!(decimal(invalid), Err(ParseError::Size(invalid.to_string())));
+        }
+    }
+
+    #[test]
+    fn durations() {
+        let days = |days: u64| Ok(Duration::from_secs(days * 24 * 60 * 60));
+        assert_eq!(duration("30d"), days(30));
+        assert_eq!(duration("6w"), days(42));
+        assert_eq!(duration("3m"), days(90));
+        assert_eq!(duration("1y"), days(365));
+        assert_eq!(duration("0d"), days(0));
+
+        for invalid in ["", "d", "-1d", "1.5w", "30", "30x", "99999999999999999999y"] {
+            assert_eq!(
+                duration(invalid),
+                Err(ParseError::Duration(invalid.to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn dates() {
+        assert!(date("").is_err());
+        assert!(date("a").is_err());
+
+        assert!(date("01.01:2002").is_err());
+        assert!(date("01.01.2002").is_err()); // need yyyy.mm.dd
+        assert!(date("2002.30.30").is_err());
+
+        assert_eq!(
+            date("2002.01.01").unwrap().format("%Y.%m.%d").to_string(),
+            "2002.01.01"
+        );
+        assert_eq!(
+            date("1234.12.08").unwrap().format("%Y.%m.%d").to_string(),
+            "1234.12.08"
+        );
+        assert_eq!(
+            date("1990.12.08").unwrap().format("%Y.%m.%d").to_string(),
+            "1990.12.08"
+        );
+        assert_eq!(
+            date("12:00:00").unwrap().format("%H:%M:%S").to_string(),
+            "12:00:00"
+        );
+        assert_eq!(
+            date("00:00:00").unwrap().format("%H:%M:%S").to_string(),
+            "00:00:00"
+        );
+
+        for invalid in ["24:00:00", "24:30:24", "30:30:24"] {
+            assert_eq!(date(invalid), Err(ParseError::Date(invalid.to_string())));
+        }
+    }
+
+    #[test]
+    fn age_cutoffs() {
+        let thirty_days_ago = SystemTime::now() - Duration::from_secs(30 * 24 * 60 * 60);
+        let cutoff = age_cutoff("30d").unwrap();
+        let difference = cutoff
+            .duration_since(thirty_days_ago)
+            .unwrap_or_else(|e| e.duration());
+        assert!(difference.as_secs() < 60);
+
+        assert!(age_cutoff("2002.01.01").unwrap() < thirty_days_ago);
+        assert_eq!(age_cutoff("30x"), Err(ParseError::Age(String::from("30x"))));
+    }
+}
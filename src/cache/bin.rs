@@ -7,10 +7,11 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use std::fs;
 use std::path::PathBuf;
 
 use crate::cache::caches::Cache;
+use crate::ignore::is_ignored;
+use crate::retry;
 
 use rayon::iter::*;
 
@@ -71,8 +72,8 @@ impl Cache for BinaryCache {
                 .files()
                 .par_iter()
                 .map(|f| {
-                    fs::metadata(f)
-                        .unwrap_or_else(|_| panic!("Failed to get size of file: '{f:?}'"))
+                    retry::metadata(f)
+                        .unwrap_or_else(|_| panic!("Failed to get size of file: '{}'", f.display()))
                         .len()
                 })
                 .sum();
@@ -88,9 +89,10 @@ impl Cache for BinaryCache {
         if self.files_calculated {
             // do nothing and return
         } else {
-            self.files = fs::read_dir(self.path())
-                .unwrap_or_else(|_| panic!("Failed to read directory: '{:?}'", &self.path))
-                .map(|f| f.unwrap().path())
+            self.files = retry::read_dir_sorted(self.path())
+                .unwrap_or_else(|_| panic!("Failed to read directory: '{}'", self.path.display()))
+                .into_iter()
+                .filter(|path| !is_ignored(path))
                 .filter(|f| f.is_file())
                 .collect::<Vec<PathBuf>>();
             self.files_calculated = true;
@@ -11,6 +11,8 @@
 
 use std::path::{Path, PathBuf};
 
+use crate::names;
+
 // this is impl'd by the bin, git_bare_repos and git_checkouts cache
 pub(crate) trait Cache {
     /// creates a new cache object
@@ -34,6 +36,7 @@ pub(crate) trait Cache {
     fn files(&mut self) -> &[PathBuf];
 
     /// list of files of the cache, sorted
+    #[allow(dead_code)]
     fn files_sorted(&mut self) -> &[PathBuf];
 
     /// the cache is known to be empty because it was just cleared / the directory removed
@@ -112,6 +115,7 @@ pub(crate) trait RegistrySubCache {
     fn number_of_files(&mut self) -> usize;
 
     /// sorted list of the files
+    #[allow(dead_code)]
     fn files_sorted(&mut self) -> &[PathBuf];
 
     /// path of the cache
@@ -133,12 +137,53 @@ pub(crate) trait RegistrySubCache {
 /// get the name of a cache directory from a path.
 /// if the full path is bla/github.com-1ecc6299db9ec823, we return github.com
 pub(crate) fn get_cache_name(path: &Path) -> String {
-    // save only the last path element bla/github.com-1ecc6299db9ec823 -> github.com-1ecc6299db9ec823
-    let file_name = path.file_name();
-    let last = file_name.unwrap().to_str().unwrap().to_string();
-    let mut v = last.split('-').collect::<Vec<_>>();
-    // remove the hash
-    let _ = v.pop();
-    // recombine as String
-    v.join("-")
+    names::strip_hash(&names::file_name(path)).to_string()
+}
+
+/// get the name of a crate from the path of its archive or extracted sources
+/// both bla/serde-json-1.0.0.crate and bla/serde-json-1.0.0 become serde-json
+fn get_crate_name(path: &Path) -> String {
+    names::split_name_version(&names::file_name(path))
+        .0
+        .to_string()
+}
+
+/// number of distinct crates among crate archives or source directories, ignoring their versions
+pub(crate) fn number_of_unique_crates(items: &[PathBuf]) -> usize {
+    let mut names = items
+        .iter()
+        .map(|path| get_crate_name(path))
+        .collect::<Vec<_>>();
+    names.sort_unstable();
+    names.dedup();
+    names.len()
+}
+
+#[cfg(test)]
+mod caches_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn crate_names() {
+        assert_eq!(
+            get_crate_name(Path::new("a/serde-json-1.0.0.crate")),
+            "serde-json"
+        );
+        assert_eq!(
+            get_crate_name(Path::new("a/heim-runtime-0.1.0-beta.1")),
+            "heim-runtime"
+        );
+
+        let items = [
+            "serde-1.0.0.crate",
+            "serde-1.0.1.crate",
+            "serde_json-1.0.0.crate",
+        ]
+        .iter()
+        .map(PathBuf::from)
+        .collect::<Vec<_>>();
+        assert_eq!(number_of_unique_crates(&items), 2);
+        assert_eq!(number_of_unique_crates(&[]), 0);
+    }
 }
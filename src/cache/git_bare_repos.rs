@@ -7,13 +7,17 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use std::fs;
 use std::path::PathBuf;
 
 use crate::cache::caches::Cache;
 
+use crate::cancel::is_cancelled;
+use crate::ignore::is_ignored;
+use crate::retry;
+use crate::walk::walk;
+
 use rayon::prelude::*;
-use walkdir::WalkDir;
+use walkdir::DirEntry;
 
 pub(crate) struct GitRepoCache {
     path: PathBuf,
@@ -71,7 +75,7 @@ impl Cache for GitRepoCache {
                 .files()
                 .par_iter()
                 .filter(|f| f.is_file())
-                .map(|f| fs::metadata(f).unwrap().len())
+                .map(|f| retry::metadata(f).unwrap().len())
                 .sum();
             self.total_size = Some(total_size);
             total_size
@@ -86,10 +90,9 @@ impl Cache for GitRepoCache {
         if self.files_calculated {
             // do nothing and return
         } else if self.path_exists() {
-            let walkdir = WalkDir::new(self.path.display().to_string());
-            let v = walkdir
-                .into_iter()
-                .map(|d| d.unwrap().into_path())
+            let v = walk(&self.path)
+                .take_while(|_| !is_cancelled())
+                .map(DirEntry::into_path)
                 .filter(|d| d.is_file())
                 .collect::<Vec<PathBuf>>();
             self.files = v;
@@ -110,9 +113,10 @@ impl Cache for GitRepoCache {
         if self.items_calculated {
             // do nothing and return
         } else if self.path_exists() {
-            let repo_list = fs::read_dir(&self.path)
-                .unwrap_or_else(|_| panic!("Failed to read directory: '{:?}'", &self.path))
-                .map(|cratepath| cratepath.unwrap().path())
+            let repo_list = retry::read_dir_sorted(&self.path)
+                .unwrap_or_else(|_| panic!("Failed to read directory: '{}'", self.path.display()))
+                .into_iter()
+                .filter(|path| !is_ignored(path))
                 .filter(|p| p.is_dir())
                 .collect::<Vec<PathBuf>>();
 
@@ -7,13 +7,28 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use crate::cache::caches::Cache;
 
+use crate::cancel::is_cancelled;
+use crate::ignore::is_ignored;
+use crate::retry;
+use crate::size_cache;
+use crate::walk::walk;
+
 use rayon::prelude::*;
-use walkdir::WalkDir;
+use walkdir::DirEntry;
+
+/// the size of a checked out repo, like the whole cache the directories count as well
+fn entries_size(path: &Path) -> u64 {
+    walk(path)
+        .take_while(|_| !is_cancelled())
+        .map(DirEntry::into_path)
+        .filter_map(|f| retry::metadata(&f).ok())
+        .map(|metadata| metadata.len())
+        .sum()
+}
 
 pub(crate) struct GitCheckoutCache {
     path: PathBuf,
@@ -65,16 +80,25 @@ impl Cache for GitCheckoutCache {
         if let Some(total_size) = self.total_size {
             total_size
         } else if self.path.is_dir() {
-            // get the size of all files in path dir
-            let total_size = self
-                .files()
-                .par_iter()
-                .map(|f| {
-                    fs::metadata(f)
-                        .unwrap_or_else(|_| panic!("Failed to read size of file: '{f:?}'"))
-                        .len()
-                })
-                .sum();
+            // only the repos with new or removed checkouts since the last run need to be scanned,
+            // the directory itself counts as well
+            let root_size = retry::metadata(&self.path).map_or(0, |metadata| metadata.len());
+            let total_size = size_cache::sum_of_entries(&self.path, entries_size).map_or_else(
+                || {
+                    // get the size of all files in path dir
+                    self.files()
+                        .par_iter()
+                        .map(|f| {
+                            retry::metadata(f)
+                                .unwrap_or_else(|_| {
+                                    panic!("Failed to read size of file: '{}'", f.display())
+                                })
+                                .len()
+                        })
+                        .sum()
+                },
+                |size| size + root_size,
+            );
             self.total_size = Some(total_size);
             total_size
         } else {
@@ -87,10 +111,9 @@ impl Cache for GitCheckoutCache {
         if self.files_calculated {
             // do nothing and return
         } else if self.path_exists() {
-            let walkdir = WalkDir::new(self.path.display().to_string());
-            let v = walkdir
-                .into_iter()
-                .map(|d| d.unwrap().into_path())
+            let v = walk(&self.path)
+                .take_while(|_| !is_cancelled())
+                .map(DirEntry::into_path)
                 .filter(|f| f.exists())
                 .collect::<Vec<PathBuf>>();
             self.files = v;
@@ -119,17 +142,19 @@ impl Cache for GitCheckoutCache {
         } else if self.path_exists() {
             let mut collection = Vec::new();
 
-            let crate_list = fs::read_dir(&self.path)
-                .unwrap_or_else(|_| panic!("Failed to read directory: '{:?}'", &self.path))
-                .map(|cratepath| cratepath.unwrap().path())
+            let crate_list = retry::read_dir_sorted(&self.path)
+                .unwrap_or_else(|_| panic!("Failed to read directory: '{}'", self.path.display()))
+                .into_iter()
+                .filter(|path| !is_ignored(path))
                 .filter(|p| p.is_dir())
                 .collect::<Vec<PathBuf>>();
             // need to take 2 levels into account
             let mut both_levels_vec: Vec<PathBuf> = Vec::new();
             for repo in crate_list {
-                for i in fs::read_dir(&repo)
-                    .unwrap_or_else(|_| panic!("Failed to read directory: '{:?}'", &repo))
-                    .map(|cratepath| cratepath.unwrap().path())
+                for i in retry::read_dir_sorted(&repo)
+                    .unwrap_or_else(|_| panic!("Failed to read directory: '{}'", repo.display()))
+                    .into_iter()
+                    .filter(|path| !is_ignored(path))
                     .filter(|f| f.is_dir())
                 {
                     both_levels_vec.push(i);
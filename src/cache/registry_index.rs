@@ -7,13 +7,23 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use crate::cache::caches::{get_cache_name, RegistrySubCache, RegistrySuperCache};
+use crate::cancel::is_cancelled;
+use crate::ignore::is_ignored;
+use crate::names;
+use crate::retry;
+use crate::walk::walk;
 
 use rayon::iter::*;
-use walkdir::WalkDir;
+use walkdir::DirEntry;
+
+/// whether the directory of a registry index holds a sparse index: cargo only keeps the metadata
+/// of the crates it needed in .cache, there is no git repo that could be gc'd
+pub(crate) fn is_sparse_index(path: &Path) -> bool {
+    path.is_dir() && !path.join(".git").exists()
+}
 
 /// describes a single index of a crate registry index
 pub(crate) struct RegistryIndex {
@@ -86,8 +96,10 @@ impl RegistrySubCache for RegistryIndex {
                         .par_iter()
                         .filter(|f| f.is_file())
                         .map(|f| {
-                            fs::metadata(f)
-                                .unwrap_or_else(|_| panic!("Failed to get size of file: '{f:?}'"))
+                            retry::metadata(f)
+                                .unwrap_or_else(|_| {
+                                    panic!("Failed to get size of file: '{}'", f.display())
+                                })
                                 .len()
                         })
                         .sum();
@@ -106,10 +118,9 @@ impl RegistrySubCache for RegistryIndex {
         if self.files_calculated {
             // do nothing and return
         } else if self.path_exists() {
-            let walkdir = WalkDir::new(self.path.display().to_string());
-            let vec = walkdir
-                .into_iter()
-                .map(|direntry| direntry.unwrap().into_path())
+            let vec = walk(&self.path)
+                .take_while(|_| !is_cancelled())
+                .map(DirEntry::into_path)
                 .collect::<Vec<PathBuf>>();
 
             self.number_of_files = Some(vec.len());
@@ -193,13 +204,14 @@ impl RegistrySuperCache for RegistryIndicesCache {
             };
         }
 
-        let indices_dirs = std::fs::read_dir(&path)
+        let indices_dirs = retry::read_dir_sorted(&path)
             .unwrap_or_else(|_| panic!("failed to read directory {}", path.display()));
         // map the dirs to RegistryIndexCaches and return them as vector
         #[allow(clippy::manual_filter_map)]
         let indices = indices_dirs
-            .map(|direntry| direntry.unwrap().path())
-            .filter(|p| p.is_dir() && p.file_name().unwrap().to_str().unwrap().contains('-'))
+            .into_iter()
+            .filter(|dir| !is_ignored(dir))
+            .filter(|p| p.is_dir() && names::file_name(p).contains('-'))
             //.inspect(|p| println!("p: {:?}", p))
             .map(RegistryIndex::new)
             .collect::<Vec<RegistryIndex>>();
@@ -219,7 +231,9 @@ impl RegistrySuperCache for RegistryIndicesCache {
     }
 
     fn invalidate(&mut self) {
-        self.number_of_indices = 0;
+        // forget the indices that were removed
+        self.indices.retain(RegistrySubCache::path_exists);
+        self.number_of_indices = self.indices.len();
         self.total_size = None;
         self.total_number_of_files = None;
         self.indices
@@ -262,16 +276,16 @@ impl RegistrySuperCache for RegistryIndicesCache {
     }
 
     fn total_number_of_files(&mut self) -> usize {
-        match self.total_number_of_files {
-            Some(number) => number,
-            None => {
-                //@TODO make everything used here return usize
-                #[allow(clippy::cast_possible_truncation)]
-                self.indices
-                    .iter_mut()
-                    .map(|index| index.total_size() as usize)
-                    .sum()
-            }
+        if let Some(number) = self.total_number_of_files {
+            number
+        } else {
+            let number = self
+                .indices
+                .iter_mut()
+                .map(RegistrySubCache::number_of_files)
+                .sum();
+            self.total_number_of_files = Some(number);
+            number
         }
     }
 
@@ -290,3 +304,63 @@ impl RegistrySuperCache for RegistryIndicesCache {
         self.caches().len()
     }
 }
+
+impl RegistryIndicesCache {
+    /// the paths of the sparse indices
+    pub(crate) fn sparse_indices(&self) -> Vec<PathBuf> {
+        self.indices
+            .iter()
+            .map(RegistrySubCache::path)
+            .filter(|path| is_sparse_index(path))
+            .cloned()
+            .collect()
+    }
+
+    /// the paths and sizes of the sparse indices
+    pub(crate) fn sparse_sizes(&mut self) -> Vec<(PathBuf, u64)> {
+        self.indices
+            .iter_mut()
+            .filter(|index| is_sparse_index(index.path()))
+            .map(|index| (index.path().clone(), index.total_size()))
+            .collect()
+    }
+
+    /// total size of the sparse indices
+    pub(crate) fn sparse_size(&mut self) -> u64 {
+        self.sparse_sizes().iter().map(|(_, size)| size).sum()
+    }
+}
+
+#[cfg(test)]
+mod registry_index_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::fs;
+
+    #[test]
+    fn sparse_and_git_indices() {
+        let tmp = tempfile::tempdir().unwrap();
+        let git = tmp.path().join("github.com-1ecc6299db9ec823");
+        let sparse = tmp.path().join("index.crates.io-6f17d22bba15001f");
+        fs::create_dir_all(git.join(".git")).unwrap();
+        fs::write(git.join(".git").join("HEAD"), vec![0; 10]).unwrap();
+        fs::create_dir_all(sparse.join(".cache").join("se").join("rd")).unwrap();
+        fs::write(sparse.join(".cache/se/rd/serde"), vec![0; 50]).unwrap();
+        fs::write(sparse.join(".cache/se/rd/serde_json"), vec![0; 50]).unwrap();
+
+        let mut indices = RegistryIndicesCache::new(tmp.path().to_path_buf());
+        assert_eq!(indices.number_of_subcaches(), 2);
+        assert_eq!(indices.sparse_indices(), vec![sparse.clone()]);
+        assert_eq!(indices.sparse_size(), 100);
+        assert_eq!(indices.total_size(), 110);
+        // the number of files, not their size
+        let files = indices.files().len();
+        assert_eq!(indices.total_number_of_files(), files);
+
+        fs::remove_dir_all(&sparse).unwrap();
+        indices.invalidate();
+        assert_eq!(indices.number_of_subcaches(), 1);
+        assert!(indices.sparse_indices().is_empty());
+        assert_eq!(indices.total_size(), 10);
+    }
+}
@@ -7,10 +7,15 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use std::fs;
 use std::path::PathBuf;
 
-use crate::cache::caches::{get_cache_name, RegistrySubCache, RegistrySuperCache};
+use crate::cache::caches::{
+    get_cache_name, number_of_unique_crates, RegistrySubCache, RegistrySuperCache,
+};
+
+use crate::ignore::is_ignored;
+use crate::names;
+use crate::retry;
 
 use rayon::prelude::*;
 
@@ -79,8 +84,10 @@ impl RegistrySubCache for RegistryPkgCache {
                         .par_iter()
                         .filter(|f| f.is_file())
                         .map(|f| {
-                            fs::metadata(f)
-                                .unwrap_or_else(|_| panic!("Failed to get size of file: '{f:?}'"))
+                            retry::metadata(f)
+                                .unwrap_or_else(|_| {
+                                    panic!("Failed to get size of file: '{}'", f.display())
+                                })
                                 .len()
                         })
                         .sum();
@@ -99,9 +106,12 @@ impl RegistrySubCache for RegistryPkgCache {
         if self.files_calculated {
             // just return
         } else if self.path_exists() {
-            let collection = fs::read_dir(&self.path)
-                .unwrap_or_else(|_| panic!("Failed to read directory (repo): '{:?}'", &self.path))
-                .map(|cratepath| cratepath.unwrap().path())
+            let collection = retry::read_dir_sorted(&self.path)
+                .unwrap_or_else(|_| {
+                    panic!("Failed to read directory (repo): '{}'", self.path.display())
+                })
+                .into_iter()
+                .filter(|dir| !is_ignored(dir))
                 .collect::<Vec<_>>();
 
             self.files_calculated = true;
@@ -183,13 +193,14 @@ impl RegistrySuperCache for RegistryPkgCaches {
             };
         }
 
-        let cache_dirs = std::fs::read_dir(&path)
+        let cache_dirs = retry::read_dir_sorted(&path)
             .unwrap_or_else(|_| panic!("failed to read directory {}", path.display()));
         // map the dirs to RegistryIndexCaches and return them as vector
         #[allow(clippy::manual_filter_map)]
         let caches = cache_dirs
-            .map(|direntry| direntry.unwrap().path())
-            .filter(|p| p.is_dir() && p.file_name().unwrap().to_str().unwrap().contains('-'))
+            .into_iter()
+            .filter(|dir| !is_ignored(dir))
+            .filter(|p| p.is_dir() && names::file_name(p).contains('-'))
             //.inspect(|p| println!("p: {:?}", p))
             .map(RegistryPkgCache::new)
             .collect::<Vec<RegistryPkgCache>>();
@@ -278,3 +289,13 @@ impl RegistrySuperCache for RegistryPkgCaches {
         self.items().len()
     }
 }
+
+impl RegistryPkgCaches {
+    /// number of distinct crates over all registries, no matter how many versions of them are cached
+    pub(crate) fn number_of_unique_crates(&mut self) -> usize {
+        self.caches
+            .iter_mut()
+            .map(|cache| number_of_unique_crates(cache.items()))
+            .sum()
+    }
+}
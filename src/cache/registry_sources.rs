@@ -7,13 +7,31 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use crate::cache::caches::{get_cache_name, RegistrySubCache, RegistrySuperCache};
+use crate::cache::caches::{
+    get_cache_name, number_of_unique_crates, RegistrySubCache, RegistrySuperCache,
+};
+
+use crate::cancel::is_cancelled;
+use crate::ignore::is_ignored;
+use crate::names;
+use crate::retry;
+use crate::size_cache;
+use crate::walk::walk;
 
 use rayon::prelude::*;
-use walkdir::WalkDir;
+use walkdir::DirEntry;
+
+/// the size of the files of an extracted crate, like the files of the whole cache are counted
+fn files_size(path: &Path) -> u64 {
+    walk(path)
+        .take_while(|_| !is_cancelled())
+        .map(DirEntry::into_path)
+        .filter(|f| f.is_file())
+        .map(|f| retry::metadata(&f).map_or(0, |metadata| metadata.len()))
+        .sum()
+}
 
 #[derive(Debug, Clone)]
 /// describes one registry source cache (extracted .crates)
@@ -84,10 +102,9 @@ impl RegistrySubCache for RegistrySourceCache {
             // do nothing as everything is already calculated
         }
         if self.path_exists() {
-            let walkdir = WalkDir::new(self.path.display().to_string());
-            let v = walkdir
-                .into_iter()
-                .map(|d| d.unwrap().into_path())
+            let v = walk(&self.path)
+                .take_while(|_| !is_cancelled())
+                .map(DirEntry::into_path)
                 .filter(|d| d.is_file())
                 .collect::<Vec<PathBuf>>();
             self.files = v;
@@ -101,13 +118,15 @@ impl RegistrySubCache for RegistrySourceCache {
         if let Some(size) = self.size {
             return size;
         } else if self.path.is_dir() {
-            // get the size of all files in path dir
-            let size = self
-                .files()
-                .par_iter()
-                .filter(|f| f.is_file())
-                .map(|f| fs::metadata(f).unwrap().len())
-                .sum();
+            // only the extracted crates that changed since the last run need to be scanned
+            let size = size_cache::sum_of_entries(&self.path, files_size).unwrap_or_else(|| {
+                // get the size of all files in path dir
+                self.files()
+                    .par_iter()
+                    .filter(|f| f.is_file())
+                    .map(|f| retry::metadata(f).unwrap().len())
+                    .sum()
+            });
             self.size = Some(size);
         } else {
             self.known_to_be_empty();
@@ -147,10 +166,11 @@ impl RegistrySubCache for RegistrySourceCache {
             self.items_calculated = true;
         } else {
             // calculate the items
-            let folders = std::fs::read_dir(&self.path)
-                .unwrap_or_else(|_| panic!("Failed to read {:?}", self.path.display()))
-                .map(|direntry| direntry.unwrap().path())
-                .filter(|p| p.is_dir() && p.file_name().unwrap().to_str().unwrap().contains('-'))
+            let folders = retry::read_dir_sorted(&self.path)
+                .unwrap_or_else(|_| panic!("Failed to read '{}'", self.path.display()))
+                .into_iter()
+                .filter(|dir| !is_ignored(dir))
+                .filter(|p| p.is_dir() && names::file_name(p).contains('-'))
                 .collect::<Vec<PathBuf>>();
             self.items = folders;
             self.items_calculated = true;
@@ -206,12 +226,13 @@ impl RegistrySuperCache for RegistrySourceCaches {
             };
         }
 
-        let registries = std::fs::read_dir(&path)
+        let registries = retry::read_dir_sorted(&path)
             .unwrap_or_else(|_| panic!("failed to read directory {}", path.display()));
         #[allow(clippy::manual_filter_map)]
         let registry_folders = registries
-            .map(|direntry| direntry.unwrap().path())
-            .filter(|p| p.is_dir() && p.file_name().unwrap().to_str().unwrap().contains('-'))
+            .into_iter()
+            .filter(|dir| !is_ignored(dir))
+            .filter(|p| p.is_dir() && names::file_name(p).contains('-'))
             .map(RegistrySourceCache::new)
             .collect::<Vec<RegistrySourceCache>>();
 
@@ -307,4 +328,12 @@ impl RegistrySourceCaches {
         self.items.sort();
         self.items()
     }
+
+    /// number of distinct crates over all registries, no matter how many versions of them are cached
+    pub(crate) fn number_of_unique_crates(&mut self) -> usize {
+        self.caches
+            .iter_mut()
+            .map(|cache| number_of_unique_crates(cache.items()))
+            .sum()
+    }
 }
@@ -0,0 +1,290 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// cooperative cancellation of long running scans and removals
+// The first Ctrl-C sets a flag that the directory walks and removal loops check: walks stop early
+// and return what they found so far, removals stop before the next item. Whatever was collected
+// until then is reported and we exit with 130 (like a shell does for SIGINT).
+// A second Ctrl-C exits immediately.
+//
+// Scans can also be run in the background as a `ScanTask` which is a `Future` and can be awaited
+// with any executor, `block_on()` is a minimal one so that we do not need to pull in tokio.
+// Every task has its own `CancelToken`: the task runs on its own thread and its own rayon pool,
+// all of their threads know the token so `is_cancelled()` only stops the scans of that task.
+
+use std::cell::RefCell;
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, JoinHandle, Thread};
+
+/// set once the user pressed Ctrl-C
+static CANCELLED: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    /// the token of the task the current thread works for
+    static TOKEN: RefCell<Option<CancelToken>> = const { RefCell::new(None) };
+}
+
+/// exit code of a process that was interrupted by SIGINT
+const EXIT_CANCELLED: i32 = 130;
+
+/// install the Ctrl-C handler
+pub(crate) fn init() {
+    let handler = ctrlc::set_handler(|| {
+        if CANCELLED.swap(true, Ordering::SeqCst) {
+            // second Ctrl-C, the user does not want to wait
+            process::exit(EXIT_CANCELLED);
+        }
+        eprintln!("\nCancelling, press Ctrl-C again to exit immediately...");
+    });
+    if let Err(error) = handler {
+        // we can still do our job, we just can not stop gracefully
+        eprintln!("Warning: failed to set up Ctrl-C handler: {error}");
+    }
+}
+
+/// stops the scans and removals of one task like a Ctrl-C does
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    #[allow(dead_code)] // only used by the library, see ops.rs
+    pub(crate) fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// whether scans and removals should stop: after Ctrl-C or once the task we work for was cancelled
+pub(crate) fn is_cancelled() -> bool {
+    CANCELLED.load(Ordering::SeqCst)
+        || TOKEN.with(|token| {
+            token
+                .borrow()
+                .as_ref()
+                .map_or(false, CancelToken::is_cancelled)
+        })
+}
+
+/// if we were cancelled, tell the user what that means for the output and exit
+pub(crate) fn exit_if_cancelled(consequence: &str) {
+    if is_cancelled() {
+        eprintln!("\nCancelled: {consequence}");
+        process::exit(EXIT_CANCELLED);
+    }
+}
+
+/// the token of the task the current thread works for
+#[allow(dead_code)] // only used by the library, see ops.rs
+pub(crate) fn current_token() -> Option<CancelToken> {
+    TOKEN.with(|token| token.borrow().clone())
+}
+
+/// make the current thread work for the task of `token`
+pub(crate) fn set_token(token: Option<CancelToken>) {
+    TOKEN.with(|current| *current.borrow_mut() = token);
+}
+
+/// run `scan` on the current thread so that `is_cancelled()` follows `token`, the parallel
+/// iterators of the scan run on a rayon pool of their own whose threads follow it as well
+fn run_with_token<T: Send>(token: &CancelToken, scan: impl FnOnce() -> T + Send) -> T {
+    set_token(Some(token.clone()));
+    let pool_token = token.clone();
+    let pool = rayon::ThreadPoolBuilder::new()
+        .start_handler(move |_| set_token(Some(pool_token.clone())))
+        .build();
+    let result = match pool {
+        Ok(pool) => pool.install(scan),
+        // the parallel parts only stop on Ctrl-C then
+        Err(_) => scan(),
+    };
+    set_token(None);
+    result
+}
+
+/// the state shared between a `ScanTask` and the thread running the scan
+#[derive(Debug)]
+struct Shared<T> {
+    /// `Err` with the panic of the scan if it panicked
+    result: Option<thread::Result<T>>,
+    waker: Option<Waker>,
+}
+
+/// a scan running in the background
+/// resolves to the result of the scan, which is incomplete if the scan was cancelled
+/// if the scan panics, awaiting the task panics with the same payload
+#[derive(Debug)]
+pub(crate) struct ScanTask<T> {
+    shared: Arc<Mutex<Shared<T>>>,
+    thread: Option<JoinHandle<()>>,
+    #[allow(dead_code)] // only used by the library, see ops.rs
+    token: CancelToken,
+}
+
+impl<T: Send + 'static> ScanTask<T> {
+    /// start a scan on a new thread, `token` cancels it
+    /// the scan is expected to check `is_cancelled()` and return early once it is set
+    pub(crate) fn spawn(token: CancelToken, scan: impl FnOnce() -> T + Send + 'static) -> Self {
+        let shared = Arc::new(Mutex::new(Shared {
+            result: None,
+            waker: None,
+        }));
+        let thread_shared = Arc::clone(&shared);
+        let thread_token = token.clone();
+
+        let thread = thread::spawn(move || {
+            let result =
+                panic::catch_unwind(AssertUnwindSafe(|| run_with_token(&thread_token, scan)));
+            let mut state = thread_shared
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner);
+            state.result = Some(result);
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        });
+
+        Self {
+            shared,
+            thread: Some(thread),
+            token,
+        }
+    }
+}
+
+impl<T> ScanTask<T> {
+    /// the token that cancels this task
+    #[allow(dead_code)] // only used by the library, see ops.rs
+    pub(crate) fn token(&self) -> &CancelToken {
+        &self.token
+    }
+}
+
+impl<T> Future for ScanTask<T> {
+    type Output = T;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let result = {
+            let mut shared = self
+                .shared
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner);
+            if let Some(result) = shared.result.take() {
+                result
+            } else {
+                shared.waker = Some(cx.waker().clone());
+                return Poll::Pending;
+            }
+        };
+        // the thread is done once it handed over the result
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        match result {
+            Ok(result) => Poll::Ready(result),
+            Err(payload) => panic::resume_unwind(payload),
+        }
+    }
+}
+
+/// wakes up the thread that is blocked in `block_on()`
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// run a future to completion on the current thread
+pub(crate) fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = Box::pin(future);
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            // spurious wake ups are fine, we just poll again
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod cancel_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use rayon::prelude::*;
+    use std::time::Duration;
+
+    #[test]
+    fn scan_tasks() {
+        let slow = ScanTask::spawn(CancelToken::new(), || {
+            thread::sleep(Duration::from_millis(50));
+            1
+        });
+        let fast = ScanTask::spawn(CancelToken::new(), || 2);
+
+        assert_eq!(block_on(async { slow.await + fast.await }), 3);
+    }
+
+    /// counts until it is cancelled, also checks the token from the threads of rayon
+    fn count_until_cancelled() -> usize {
+        let mut count = 0;
+        while !is_cancelled() && (0..4).into_par_iter().all(|_| !is_cancelled()) {
+            count += 1;
+            thread::sleep(Duration::from_millis(1));
+        }
+        count
+    }
+
+    #[test]
+    fn cancel_one_task() {
+        let cancelled = ScanTask::spawn(CancelToken::new(), count_until_cancelled);
+        let token = CancelToken::new();
+        let other = ScanTask::spawn(token.clone(), || {
+            thread::sleep(Duration::from_millis(50));
+            is_cancelled()
+        });
+
+        cancelled.token().cancel();
+        let _ = block_on(cancelled);
+        // the other task and this thread go on
+        assert!(!block_on(other));
+        assert!(!token.is_cancelled());
+        assert!(!is_cancelled());
+        // a token that was already cancelled stops the task right away
+        let cancelled_before = CancelToken::new();
+        cancelled_before.cancel();
+        assert_eq!(
+            block_on(ScanTask::spawn(cancelled_before, count_until_cancelled)),
+            0
+        );
+    }
+
+    #[test]
+    fn panicking_task() {
+        let task = ScanTask::spawn(CancelToken::new(), || -> u32 { panic!("scan failed") });
+        let payload = panic::catch_unwind(AssertUnwindSafe(|| block_on(task))).unwrap_err();
+        assert_eq!(payload.downcast_ref::<&str>(), Some(&"scan failed"));
+    }
+}
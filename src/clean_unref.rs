@@ -8,6 +8,8 @@
 // except according to those terms.
 
 // remove all crates from a cache that are not referenced by a Cargo lockfile
+// With --all-projects, everything that is referenced by the lockfile of any of the registered
+// projects is kept (mark) and the rest is removed (sweep).
 
 //https://github.com/rust-lang/rust-clippy/issues/7202
 #![allow(clippy::needless_collect)]
@@ -15,12 +17,17 @@
 use std::ffi::OsStr;
 use std::path::{Path, PathBuf};
 
+use crate::approve;
 use crate::cache::caches::*;
 use crate::cache::*;
 use crate::library::*;
 use crate::library::{CargoCachePaths, Error};
+use crate::network;
+use crate::projects;
+use crate::rebuild_cost::RebuildCosts;
 use crate::remove::*;
-use cargo_metadata::{CargoOpt, MetadataCommand};
+use cargo_metadata::{CargoOpt, Metadata, MetadataCommand};
+use humansize::{FormatSize, DECIMAL};
 
 // the source of a crate inside the cargo cache can be represented in form of
 // an extracted .crate or a checked out git repository
@@ -74,45 +81,63 @@ fn find_crate_name_crate(toml_path: &Path, cargo_home: &Path) -> Option<SourceKi
     Some(SourceKind::Crate(path))
 }
 
-/// look at a crate manifest and remove all items from the cargo cache that are not referenced, also run --autoclean and invalidate caches
+/// get the dependencies of the project without updating its Cargo.lock
+/// unless `allow_net` is set, cargo may not access the network either and has to resolve everything from the cache
+/// cargo runs with our cargo home so that the dependencies are resolved against the cache we clean
+fn project_metadata(
+    manifest: PathBuf,
+    cargo_home: &Path,
+    allow_net: bool,
+) -> Result<Metadata, Error> {
+    let options = if allow_net {
+        vec![String::from("--locked")]
+    } else {
+        vec![String::from("--frozen"), String::from("--offline")]
+    };
+
+    let result = MetadataCommand::new()
+        .manifest_path(&manifest)
+        .features(CargoOpt::AllFeatures)
+        .other_options(options)
+        .env("CARGO_HOME", cargo_home)
+        .exec();
+
+    result.map_err(|error| {
+        let stderr = match &error {
+            cargo_metadata::Error::CargoMetadata { stderr } => stderr.clone(),
+            _ => String::new(),
+        };
+        if stderr.contains("--frozen was passed") || stderr.contains("--locked was passed") {
+            Error::LockfileNeedsUpdate(manifest)
+        } else if !allow_net && stderr.contains("offline") {
+            Error::MetadataNeedsNetwork(manifest)
+        } else {
+            Error::UnparsableManifest(manifest, error)
+        }
+    })
+}
+
+/// everything in the cache that none of the projects of the manifests need and its size:
+/// all checkouts and sources plus the bare repos and crate archives that are not referenced
+/// `allow_net` is whether cargo may access the network, as decided by the network policy
 #[allow(clippy::too_many_arguments)]
-pub(crate) fn clean_unref(
+pub(crate) fn unreferenced_items(
     cargo_cache_paths: &CargoCachePaths,
-    manifest_path: Option<&str>,
-    bin_cache: &mut bin::BinaryCache,
+    manifests: &[PathBuf],
+    allow_net: bool,
     checkouts_cache: &mut git_checkouts::GitCheckoutCache,
     bare_repos_cache: &mut git_bare_repos::GitRepoCache,
     registry_pkg_caches: &mut registry_pkg_cache::RegistryPkgCaches,
-    registry_index_caches: &mut registry_index::RegistryIndicesCache,
     registry_sources_caches: &mut registry_sources::RegistrySourceCaches,
-    dry_run: bool,
-    size_changed: &mut bool,
-) -> Result<(), Error> {
-    // total cache size before removing, for the summary
-    let original_total_cache_size = bin_cache.total_size()
-        + checkouts_cache.total_size()
-        + bare_repos_cache.total_size()
-        + registry_pkg_caches.total_size()
-        + registry_index_caches.total_size()
-        + registry_sources_caches.total_size();
-
-    // first get a list of all dependencies of the project
+    selection: &ComponentSelection,
+) -> Result<Vec<(PathBuf, u64)>, Error> {
+    // first get a list of all dependencies of the projects
     let cargo_home = &cargo_cache_paths.cargo_home;
-
-    // if "--manifest-path" is passed to the subcommand, take this
-    // if it is not passed, try to find a close manifest somewhere
-    let manifest = match manifest_path {
-        Some(path_str) => PathBuf::from(path_str),
-        None => crate::local::get_manifest()?,
-    };
-
-    let metadata = MetadataCommand::new()
-        .manifest_path(&manifest)
-        .features(CargoOpt::AllFeatures)
-        .exec()
-        .map_err(|e| Error::UnparsableManifest(manifest, e))?;
-
-    let dependencies = metadata.packages;
+    let mut dependencies = Vec::new();
+    for manifest in manifests {
+        let metadata = project_metadata(manifest.clone(), cargo_home, allow_net)?;
+        dependencies.extend(metadata.packages);
+    }
 
     // get the path inside the CARGO_HOME of the source of the dependency
     #[allow(clippy::manual_filter_map)]
@@ -165,7 +190,7 @@ pub(crate) fn clean_unref(
                 // ~/.cargo/git/db/cargo-e7ff1db891893a9e
                 let mut repo_name = gitpath;
                 let _ = repo_name.pop(); // remove /258c896
-                let repo_name = repo_name.iter().last().unwrap(); // cargo-e7ff1db891893a9e
+                let repo_name = repo_name.iter().next_back().unwrap(); // cargo-e7ff1db891893a9e
 
                 let mut db_name = cargo_cache_paths.git_repos_bare.clone();
                 db_name.push(repo_name);
@@ -183,29 +208,24 @@ pub(crate) fn clean_unref(
     // println!("required packages:");
     // required_packages.inspect(|toml| println!("{:?}", toml));
 
+    // everything we are going to remove and its size
+    let mut items_to_remove: Vec<(PathBuf, u64)> = Vec::new();
+
     // remove the git checkout cache since it is not needed
-    remove_file(
-        &cargo_cache_paths.git_checkouts,
-        dry_run,
-        size_changed,
-        None,
-        &DryRunMessage::Default,
-        Some(checkouts_cache.total_size()),
-    );
-    // invalidate cache
-    checkouts_cache.invalidate();
+    if selection.contains(&Component::GitRepos) {
+        items_to_remove.push((
+            cargo_cache_paths.git_checkouts.clone(),
+            checkouts_cache.total_size(),
+        ));
+    }
 
     // remove the registry_sources_cache as well
-    remove_file(
-        &cargo_cache_paths.registry_sources,
-        dry_run,
-        size_changed,
-        None,
-        &DryRunMessage::Default,
-        Some(registry_sources_caches.total_size()),
-    );
-    // invalidate cache
-    registry_sources_caches.invalidate();
+    if selection.contains(&Component::RegistrySources) {
+        items_to_remove.push((
+            cargo_cache_paths.registry_sources.clone(),
+            registry_sources_caches.total_size(),
+        ));
+    }
 
     let (required_crates, required_git_repos): (Vec<SourceKind>, Vec<SourceKind>) =
         required_packages.partition(|dep| match dep {
@@ -233,61 +253,136 @@ pub(crate) fn clean_unref(
         crates.extend(cache.files());
     }
 
-    // filter and remove git repos
-    bare_repos
-        .iter()
-        .filter(|repo_in_cache|
-            // in the iterator, only keep crates that are not contained in
-            // our dependency list and remove them
-
-            !required_git_repos.contains(repo_in_cache))
-        .for_each(|repo| {
-            /* remove the repo */
-
-            remove_file(
-                repo,
-                dry_run,
-                size_changed,
-                None,
-                &DryRunMessage::Default,
-                Some(size_of_path(repo)),
-            );
-        });
+    // in the iterators, only keep git repos and crates that are not contained in
+    // our dependency list and remove them
+    items_to_remove.extend(
+        bare_repos
+            .iter()
+            .filter(|repo_in_cache| !required_git_repos.contains(repo_in_cache))
+            .chain(
+                crates
+                    .iter()
+                    .copied()
+                    .filter(|crate_in_cache| !required_crates.contains(crate_in_cache)),
+            )
+            .map(|path| (path.clone(), size_of_path(path))),
+    );
 
-    // filter and remove crate archives
-    crates
-        .iter()
-        .filter(|crate_in_cache|
-            // in the iterator, only keep crates that are not contained in
-            // our dependency list and remove them
-
-            !required_crates.contains(crate_in_cache))
-        .for_each(|krate| {
-            /* remove the crate */
-            remove_file(
-                krate,
-                dry_run,
-                size_changed,
-                None,
-                &DryRunMessage::Default,
-                Some(size_of_path(krate)),
-            );
-        });
+    Ok(items_to_remove)
+}
 
-    // don't forget to invalidate caches..!
-    bare_repos_cache.invalidate();
-    registry_pkg_caches.invalidate();
+/// look at a crate manifest (or those of all registered projects) and remove all items from the cargo cache that are not referenced, also run --autoclean and invalidate caches
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn clean_unref(
+    cargo_cache_paths: &CargoCachePaths,
+    manifest_path: Option<&str>,
+    all_projects: bool,
+    allow_net: bool,
+    checkouts_cache: &mut git_checkouts::GitCheckoutCache,
+    bare_repos_cache: &mut git_bare_repos::GitRepoCache,
+    registry_pkg_caches: &mut registry_pkg_cache::RegistryPkgCaches,
+    registry_sources_caches: &mut registry_sources::RegistrySourceCaches,
+    dry_run: bool,
+    size_changed: &mut bool,
+    selection: &ComponentSelection,
+) -> Result<(), Error> {
+    let allow_net = network::allow("clean-unref --allow-net", allow_net)?;
+    // "--all-projects" keeps what any registered project needs
+    // if "--manifest-path" is passed to the subcommand, take this
+    // if neither is passed, try to find a close manifest somewhere
+    let manifests = if all_projects {
+        projects::manifests(&cargo_cache_paths.cargo_home)?
+    } else {
+        vec![match manifest_path {
+            Some(path_str) => PathBuf::from(path_str),
+            None => crate::local::get_manifest()?,
+        }]
+    };
 
-    print_size_changed_summary(
-        original_total_cache_size,
+    let items_to_remove = unreferenced_items(
         cargo_cache_paths,
-        bin_cache,
+        &manifests,
+        allow_net,
         checkouts_cache,
         bare_repos_cache,
         registry_pkg_caches,
-        registry_index_caches,
         registry_sources_caches,
+        selection,
+    )?;
+
+    // the --approve-with command may keep some of them
+    let items_to_remove =
+        approve::approved("clean-unref", dry_run, items_to_remove, |(path, size)| {
+            (path.clone(), *size)
+        })?;
+
+    // the dry run shows what it would take to get each item back
+    let mut rebuild_costs = RebuildCosts::new(
+        cargo_cache_paths,
+        items_to_remove.iter().map(|(path, _)| path.clone()),
     );
+
+    for (path, size) in &items_to_remove {
+        let dry_run_msg = if dry_run {
+            let rebuild_cost = if path == &cargo_cache_paths.registry_sources
+                || path == &cargo_cache_paths.git_checkouts
+            {
+                // the whole directory is removed, look at the sources and checkouts inside
+                let items = if path == &cargo_cache_paths.registry_sources {
+                    registry_sources_caches.items().to_vec()
+                } else {
+                    checkouts_cache.items().to_vec()
+                }
+                .into_iter()
+                .map(|item| {
+                    let item_size = size_of_path(&item);
+                    (item, item_size)
+                })
+                .collect::<Vec<_>>();
+                let summary = rebuild_costs.summary(&items);
+                for (item, item_size) in &items {
+                    let _ = rebuild_costs.add(item, *item_size);
+                }
+                summary
+            } else {
+                rebuild_costs
+                    .add(path, *size)
+                    .map(|cost| cost.to_string())
+                    .unwrap_or_default()
+            };
+            format!(
+                "dry-run: would remove: '{}' ({}) [rebuild: {}]",
+                path.display(),
+                size.format_size(DECIMAL),
+                if rebuild_cost.is_empty() {
+                    "nothing"
+                } else {
+                    &rebuild_cost
+                }
+            )
+        } else {
+            String::new()
+        };
+        remove_file(
+            path,
+            dry_run,
+            size_changed,
+            None,
+            &DryRunMessage::Custom(&dry_run_msg),
+            Some(*size),
+        );
+    }
+
+    if dry_run {
+        rebuild_costs.print_table();
+    }
+
+    // don't forget to invalidate caches..!
+    checkouts_cache.invalidate();
+    registry_sources_caches.invalidate();
+    bare_repos_cache.invalidate();
+    registry_pkg_caches.invalidate();
+
     Ok(())
 }
 
@@ -8,13 +8,29 @@
 // except according to those terms.
 
 /// This file provides the command line interface of the cargo-cache crate
-use clap::{App, AppSettings, Arg, ArgMatches};
+use clap::{App, AppSettings, Arg, ArgGroup, ArgMatches};
 
+use crate::commands::doctor::DoctorFix;
+use crate::commands::projects::ProjectsAction;
+use crate::config::Config;
+use crate::env;
 use crate::library::*;
 use rustc_tools_util::*;
 
 /// cargo-cache can perform these operaitons, but only one at a time
 #[derive(Debug)]
+// the fields of commands that need a feature are not read if cargo-cache is built without it
+#[cfg_attr(
+    not(all(
+        feature = "git",
+        feature = "metadata",
+        feature = "query",
+        feature = "verify",
+        feature = "backup",
+        feature = "metrics"
+    )),
+    allow(dead_code)
+)]
 pub(crate) enum CargoCacheCommands<'a> {
     FSCKRepos,
 
@@ -25,16 +41,36 @@ pub(crate) enum CargoCacheCommands<'a> {
     KeepDuplicateCrates {
         dry_run: bool,
         limit: u64,
+        keep_by: KeepBy,
     },
     ListDirs,
+    Estimate,
+    DeleteEmptyRegistries {
+        dry_run: bool,
+    },
     RemoveDir {
         dry_run: bool,
+        allow_cross_device: bool,
+        keep_going: bool,
+        dirs: Option<&'a str>,
     },
     AutoClean {
         dry_run: bool,
+        allow_cross_device: bool,
+        keep_going: bool,
+        yes: bool,
+        preserve_modified: bool,
+        keep_days: u64,
+        registries: RegistryFilter,
     },
     AutoCleanExpensive {
         dry_run: bool,
+        allow_cross_device: bool,
+        keep_going: bool,
+        yes: bool,
+        preserve_modified: bool,
+        keep_days: u64,
+        registries: RegistryFilter,
     },
     TopCacheItems {
         limit: u32,
@@ -44,22 +80,144 @@ pub(crate) enum CargoCacheCommands<'a> {
     Verify {
         clean_corrupted: bool,
         dry_run: bool,
+        report: Option<&'a str>,
+        content: bool,
+        checksums: bool,
     },
     Query {
         query_config: &'a ArgMatches,
+        /// --dry-run of cargo-cache or of the subcommand, only matters with --delete
+        dry_run: bool,
+    }, // subcommand
+    Local {
+        clean_build_dir: bool,
+        clean_stale_deps: bool,
+        clean_stale_build_scripts: bool,
+        dry_run: bool,
+        recursive: Option<&'a str>,
+        clean: bool,
+        clean_release_only: bool,
+        keep_recent: usize,
     }, // subcommand
-    Local,      // subcommand
     Registries, // subcommand
-    SCCache,    // subcommand
+    SCCache {
+        clean: bool,
+        trim_limit: Option<&'a str>,
+        dry_run: bool,
+    }, // subcommand
     CleanUnref {
         dry_run: bool,
         manifest_path: Option<&'a str>,
+        all_projects: bool,
+        allow_net: bool,
     }, // subcommand
     Trim {
         dry_run: bool,
+        plan: bool,
         trim_limit: Option<&'a str>,
+        keep_newer_than: Option<&'a str>,
+        registries: RegistryFilter,
+    }, // subcommand
+    Toolchain {
+        breakdown: bool,
+        remove_toolchain: Option<&'a str>,
+        remove_docs: bool,
+        yes: bool,
+        dry_run: bool,
+    }, // subcommand
+    Stats {
+        bandwidth: Option<&'a str>,
+        history: bool,
+        last: usize,
+        days: Option<u64>,
+        sparkline: bool,
+    }, // subcommand, "--value" or "--history"
+    Check {
+        fail_if_above: &'a str,
+    }, // subcommand
+    Metrics {
+        push: bool,
+        url: Option<String>,
+        label: Option<&'a str>,
+        dry_run: bool,
+    }, // subcommand
+    Examples,   // subcommand
+    OutputSchema,
+    Savings, // subcommand
+    Log {
+        last: usize,
+        paths: bool,
+    }, // subcommand
+    Projects {
+        action: ProjectsAction<'a>,
+    }, // subcommand
+    SelfCheckUpdate {
+        online: bool,
+    }, // subcommand
+    SelftestSizes, // subcommand
+    Backup {
+        output: &'a str,
+        components: Option<&'a str>,
+    }, // subcommand
+    Restore {
+        archive: &'a str,
+        dry_run: bool,
+    }, // subcommand
+    Doctor {
+        fix: DoctorFix,
+        dry_run: bool,
+    }, // subcommand
+    MigrateRegistry {
+        dry_run: bool,
+    }, // subcommand
+    Dedupe {
+        reflink: bool,
+        dry_run: bool,
+    }, // subcommand
+    Maintain {
+        autoclean_above: Option<&'a str>,
+        gc_every_days: Option<u64>,
+        check_every_hours: u64,
+        force: bool,
+        dry_run: bool,
+        allow_cross_device: bool,
+    }, // subcommand
+    Ui {
+        dry_run: bool,
+    }, // subcommand
+    GitExport {
+        repo: &'a str,
+        dest: &'a str,
+        rev: Option<&'a str>,
+    }, // subcommand
+    GitListCheckouts {
+        repo: &'a str,
+    }, // subcommand
+    GitRmCheckout {
+        repo: &'a str,
+        rev: &'a str,
+        dry_run: bool,
+    }, // subcommand
+    RemoveCrate {
+        spec: &'a str,
+        git: bool,
+        also_sccache: bool,
+        dry_run: bool,
+    }, // subcommand
+    Inspect {
+        crate_name: &'a str,
+        version: Option<&'a str>,
+        limit: usize,
+    }, // subcommand
+    PreheatInstall {
+        crate_name: &'a str,
+        version: Option<&'a str>,
+    }, // subcommand
+    Compare {
+        other_cargo_home: &'a str,
+        merge_from: bool,
+        dry_run: bool,
     }, // subcommand
-    Toolchain,  // subcommand
     RemoveIfDate {
         dry_run: bool,
         arg_younger: Option<&'a str>,
@@ -70,8 +228,295 @@ pub(crate) enum CargoCacheCommands<'a> {
     DefaultSummary,
 }
 
-pub(crate) fn clap_to_enum(config: &ArgMatches) -> CargoCacheCommands<'_> {
+impl CargoCacheCommands<'_> {
+    /// whether the command may remove anything from the cache and thus needs to hold the lock
+    /// read-only commands (and dry runs) never wait for the lock
+    pub(crate) fn modifies_cache(&self) -> bool {
+        match self {
+            Self::GitGCRepos { dry_run }
+            | Self::KeepDuplicateCrates { dry_run, .. }
+            | Self::DeleteEmptyRegistries { dry_run }
+            | Self::MigrateRegistry { dry_run }
+            | Self::Dedupe { dry_run, .. }
+            | Self::Maintain { dry_run, .. }
+            | Self::RemoveDir { dry_run, .. }
+            | Self::AutoClean { dry_run, .. }
+            | Self::AutoCleanExpensive { dry_run, .. }
+            | Self::CleanUnref { dry_run, .. }
+            | Self::GitRmCheckout { dry_run, .. }
+            | Self::Restore { dry_run, .. }
+            | Self::Ui { dry_run }
+            | Self::RemoveCrate { dry_run, .. }
+            | Self::RemoveIfDate { dry_run, .. } => !dry_run,
+            // --plan only prints what would be removed
+            Self::Trim { dry_run, plan, .. } => !dry_run && !plan,
+            Self::Verify {
+                clean_corrupted,
+                dry_run,
+                ..
+            } => *clean_corrupted && !dry_run,
+            Self::Compare {
+                merge_from,
+                dry_run,
+                ..
+            } => *merge_from && !dry_run,
+            Self::Doctor { fix, dry_run } => *fix != DoctorFix::Report && !dry_run,
+            Self::Query {
+                query_config,
+                dry_run,
+            } => query_config.is_present("delete") && !dry_run,
+            Self::FSCKRepos
+            | Self::Info
+            | Self::ListDirs
+            | Self::Estimate
+            | Self::TopCacheItems { .. }
+            | Self::Version
+            // only touches the target dir of a project, not the cargo home
+            | Self::Local { .. }
+            | Self::Registries
+            | Self::SCCache { .. }
+            | Self::Toolchain { .. }
+            | Self::Stats { .. }
+            | Self::Check { .. }
+            | Self::Metrics { .. }
+            | Self::Examples
+            | Self::OutputSchema
+            | Self::Savings
+            | Self::Log { .. }
+            // only writes the list of projects
+            | Self::Projects { .. }
+            | Self::SelfCheckUpdate { .. }
+            | Self::SelftestSizes
+            // only writes to the archive
+            | Self::Backup { .. }
+            // only writes to the destination directory
+            | Self::GitExport { .. }
+            | Self::GitListCheckouts { .. }
+            | Self::Inspect { .. }
+            | Self::PreheatInstall { .. }
+            | Self::OnlyDryRun
+            | Self::DefaultSummary => false,
+        }
+    }
+
+    /// whether the command can be repeated for each of several cargo homes (--cargo-home passed
+    /// more than once), the others do not look at the cargo home or write to a single destination
+    pub(crate) fn works_per_cargo_home(&self) -> bool {
+        !matches!(
+            self,
+            Self::Version
+                | Self::Local { .. }
+                | Self::SCCache { .. }
+                | Self::Toolchain { .. }
+                | Self::Examples
+                | Self::OutputSchema
+                | Self::SelfCheckUpdate { .. }
+                | Self::SelftestSizes
+                | Self::Backup { .. }
+                | Self::Restore { .. }
+                | Self::GitExport { .. }
+                | Self::Compare { .. }
+        )
+    }
+
+    /// how the command is spelled on the command line, used in the history of removals
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Self::FSCKRepos => "--fsck",
+            Self::GitGCRepos { .. } => "--gc",
+            Self::Info => "--info",
+            Self::KeepDuplicateCrates { .. } => "--keep-duplicate-crates",
+            Self::ListDirs => "--list-dirs",
+            Self::Estimate => "--estimate",
+            Self::DeleteEmptyRegistries { .. } => "--delete-empty-registries",
+            Self::RemoveDir { .. } => "--remove-dir",
+            Self::AutoClean { .. } => "--autoclean",
+            Self::AutoCleanExpensive { .. } => "--autoclean-expensive",
+            Self::TopCacheItems { .. } => "--top-cache-items",
+            Self::Version => "version",
+            Self::Verify { .. } => "verify",
+            Self::Query { .. } => "query",
+            Self::Local { .. } => "local",
+            Self::Registries => "registry",
+            Self::SCCache { .. } => "sccache",
+            Self::CleanUnref { .. } => "clean-unref",
+            Self::Trim { .. } => "trim",
+            Self::Toolchain { .. } => "toolchain",
+            Self::Stats { .. } => "stats",
+            Self::Check { .. } => "check",
+            Self::Metrics { .. } => "metrics",
+            Self::Examples => "examples",
+            Self::OutputSchema => "--output-schema",
+            Self::Savings => "savings",
+            Self::Log { .. } => "log",
+            Self::Projects { .. } => "projects",
+            Self::SelfCheckUpdate { .. } => "self check-update",
+            Self::SelftestSizes => "selftest-sizes",
+            Self::Backup { .. } => "backup",
+            Self::Restore { .. } => "restore",
+            Self::Doctor { .. } => "doctor",
+            Self::MigrateRegistry { .. } => "migrate-registry",
+            Self::Dedupe { .. } => "dedupe",
+            Self::Maintain { .. } => "maintain",
+            Self::Ui { .. } => "ui",
+            Self::GitExport { .. } => "git export",
+            Self::GitListCheckouts { .. } => "git list",
+            Self::GitRmCheckout { .. } => "git rm-checkout",
+            Self::RemoveCrate { .. } => "remove",
+            Self::Inspect { .. } => "inspect",
+            Self::PreheatInstall { .. } => "preheat-install",
+            Self::Compare { .. } => "compare",
+            Self::RemoveIfDate { .. } => "--remove-if-older-than/--remove-if-younger-than",
+            Self::OnlyDryRun => "--dry-run",
+            Self::DefaultSummary => "",
+        }
+    }
+
+    /// the cargo feature the command needs, cargo-cache can be built without it
+    fn required_feature(&self) -> Option<&'static str> {
+        match self {
+            Self::FSCKRepos
+            | Self::GitGCRepos { .. }
+            | Self::AutoCleanExpensive { .. }
+            | Self::GitExport { .. } => Some("git"),
+            Self::CleanUnref { .. }
+            | Self::Local { .. }
+            | Self::PreheatInstall { .. }
+            | Self::SelfCheckUpdate { .. } => Some("metadata"),
+            Self::Query { .. } => Some("query"),
+            Self::Verify { .. } => Some("verify"),
+            Self::Backup { .. } | Self::Restore { .. } => Some("backup"),
+            Self::Metrics { .. } => Some("metrics"),
+            Self::OutputSchema => Some("json"),
+            _ => None,
+        }
+    }
+
+    /// fail if cargo-cache was built without the feature the command needs
+    pub(crate) fn check_feature(&self) -> Result<(), Error> {
+        match self.required_feature() {
+            Some(feature) if !feature_enabled(feature) => {
+                Err(Error::FeatureDisabled(self.name().to_string(), feature))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl CargoCacheCommands<'_> {
+    /// fail if json output was requested from a command that only prints tables
+    pub(crate) fn check_output_format(&self, output_format: OutputFormat) -> Result<(), Error> {
+        if output_format == OutputFormat::Table {
+            return Ok(());
+        }
+        if !feature_enabled("json") {
+            return Err(Error::FeatureDisabled(
+                String::from("--output-format json"),
+                "json",
+            ));
+        }
+        match self {
+            Self::DefaultSummary
+            | Self::Info
+            | Self::Registries
+            | Self::TopCacheItems { .. }
+            | Self::Trim { plan: true, .. }
+            | Self::OutputSchema => Ok(()),
+            command => Err(Error::OutputFormatUnsupported(command.name().to_string())),
+        }
+    }
+}
+
+/// whether cargo-cache was built with this feature
+pub(crate) fn feature_enabled(feature: &str) -> bool {
+    match feature {
+        "git" => cfg!(feature = "git"),
+        "metadata" => cfg!(feature = "metadata"),
+        "query" => cfg!(feature = "query"),
+        "json" => cfg!(feature = "json"),
+        "verify" => cfg!(feature = "verify"),
+        "backup" => cfg!(feature = "backup"),
+        "metrics" => cfg!(feature = "metrics"),
+        _ => unreachable!("unknown feature \"{}\"", feature),
+    }
+}
+
+/// flags that select what cargo-cache does and how they are spelled on the command line
+/// only one of these (or one of the `COMPOSITIONS`) may be passed at a time
+const OPERATIONS: &[(&str, &str)] = &[
+    ("autoclean", "--autoclean"),
+    ("autoclean-expensive", "--autoclean-expensive"),
+    ("delete-empty-registries", "--delete-empty-registries"),
+    ("estimate", "--estimate"),
+    ("fsck-repos", "--fsck"),
+    ("gc-repos", "--gc"),
+    ("info", "--info"),
+    ("keep-duplicate-crates", "--keep-duplicate-crates"),
+    ("list-dirs", "--list-dirs"),
+    ("output-schema", "--output-schema"),
+    ("remove-dir", "--remove-dir"),
+    ("remove-if-older-than", "--remove-if-older-than"),
+    ("remove-if-younger-than", "--remove-if-younger-than"),
+    ("top-cache-items", "--top-cache-items"),
+];
+
+/// operations that can be passed together
+const COMPOSITIONS: &[&[&str]] = &[
+    // --gc --autoclean is the same as --autoclean-expensive
+    &["autoclean", "autoclean-expensive", "gc-repos"],
+    // only remove the items of the given age, optionally restricted to the dirs of --remove-dir
+    &[
+        "remove-dir",
+        "remove-if-older-than",
+        "remove-if-younger-than",
+    ],
+];
+
+/// make sure we were not asked to do several things at once which we would not do
+/// `operations` are the ids of the passed `OPERATIONS`, `subcommand` the name of the passed subcommand
+fn check_conflicts(operations: &[&str], subcommand: Option<&str>) -> Result<(), Error> {
+    let composable = subcommand.is_none()
+        && COMPOSITIONS.iter().any(|composition| {
+            operations
+                .iter()
+                .all(|operation| composition.contains(operation))
+        });
+
+    if operations.len() + usize::from(subcommand.is_some()) <= 1 || composable {
+        return Ok(());
+    }
+
+    let mut conflicting = OPERATIONS
+        .iter()
+        .filter(|(id, _)| operations.contains(id))
+        .map(|(_, flag)| (*flag).to_string())
+        .collect::<Vec<String>>();
+    conflicting.extend(subcommand.map(|name| format!("the \"{name}\" subcommand")));
+    Err(Error::ConflictingOperations(conflicting))
+}
+
+/// `defaults` are the values of the config file for options that were not passed
+pub(crate) fn clap_to_enum<'a>(
+    config: &'a ArgMatches,
+    defaults: &'a Config,
+) -> CargoCacheCommands<'a> {
     let dry_run = config.is_present("dry-run");
+    let allow_cross_device = config.is_present("allow-cross-device");
+    let keep_going = config.is_present("keep-going");
+    let yes = config.is_present("yes");
+    let preserve_modified = config.is_present("preserve-modified");
+    let keep_days: u64 = if config.is_present("keep-days") {
+        config
+            .value_of_t("keep-days")
+            .map_err(|_| "Error: \"--keep-days\" expected a number of days")
+            .unwrap_or_fatal_error()
+    } else {
+        defaults.autoclean_keep_days.unwrap_or(0)
+    };
+    let registries = RegistryFilter::new(
+        config.value_of("only-registry"),
+        config.value_of("exclude-registry"),
+    );
 
     /*
     // if no args were passed, or ONLY --debug is passed, print the default summary
@@ -96,27 +541,274 @@ pub(crate) fn clap_to_enum(config: &ArgMatches) -> CargoCacheCommands<'_> {
         _ => {}
     }
 
+    // refuse to silently ignore some of the operations we were asked to do,
+    // after this the order of the checks below does not matter (except for the compositions)
+    let operations = OPERATIONS
+        .iter()
+        .map(|(id, _)| *id)
+        .filter(|id| config.is_present(id))
+        .collect::<Vec<&str>>();
+    check_conflicts(&operations, config.subcommand_name()).unwrap_or_fatal_error();
+
     // if config.is_present("debug") {
     // do not check for "--debug" since it is independent of all other flags
     if config.is_present("version") || config.subcommand_matches("version").is_some() {
         CargoCacheCommands::Version
-    } else if config.subcommand_matches("sccache").is_some()
-        || config.subcommand_matches("sc").is_some()
+    } else if let Some(sccache_config) = config
+        .subcommand_matches("sccache")
+        .or_else(|| config.subcommand_matches("sc"))
     {
-        CargoCacheCommands::SCCache
-    } else if config.subcommand_matches("toolchain").is_some() {
-        CargoCacheCommands::Toolchain
+        CargoCacheCommands::SCCache {
+            clean: sccache_config.is_present("clean"),
+            trim_limit: sccache_config.value_of("trim-limit"),
+            dry_run: dry_run || sccache_config.is_present("dry-run"),
+        }
+    } else if let Some(toolchain_config) = config.subcommand_matches("toolchain") {
+        CargoCacheCommands::Toolchain {
+            breakdown: toolchain_config.is_present("breakdown"),
+            remove_toolchain: toolchain_config.value_of("remove-toolchain"),
+            remove_docs: toolchain_config.is_present("remove-docs"),
+            yes: yes || toolchain_config.is_present("yes"),
+            dry_run: dry_run || toolchain_config.is_present("dry-run"),
+        }
+    } else if config.subcommand_matches("examples").is_some() {
+        CargoCacheCommands::Examples
+    } else if config.is_present("output-schema") {
+        CargoCacheCommands::OutputSchema
+    } else if config.subcommand_matches("savings").is_some() {
+        CargoCacheCommands::Savings
+    } else if let Some(log_config) = config.subcommand_matches("log") {
+        let last: usize = if log_config.is_present("last") {
+            log_config
+                .value_of_t("last")
+                .map_err(|_| "Error: \"--last\" expected a number of runs")
+                .unwrap_or_fatal_error()
+        } else {
+            10 /* default*/
+        };
+        CargoCacheCommands::Log {
+            last,
+            paths: log_config.is_present("paths"),
+        }
+    } else if let Some(projects_config) = config.subcommand_matches("projects") {
+        let action = if let Some(add_config) = projects_config.subcommand_matches("add") {
+            // clap makes sure this is present
+            ProjectsAction::Add(add_config.value_of("PATH").unwrap())
+        } else if let Some(remove_config) = projects_config.subcommand_matches("remove") {
+            ProjectsAction::Remove(remove_config.value_of("PATH").unwrap())
+        } else {
+            ProjectsAction::List
+        };
+        CargoCacheCommands::Projects { action }
+    } else if let Some(self_config) = config.subcommand_matches("self") {
+        // clap makes sure "check-update" is the only subcommand of "self"
+        CargoCacheCommands::SelfCheckUpdate {
+            online: self_config
+                .subcommand_matches("check-update")
+                .map_or(false, |check_update_config| {
+                    check_update_config.is_present("online")
+                }),
+        }
+    } else if config.subcommand_matches("selftest-sizes").is_some() {
+        CargoCacheCommands::SelftestSizes
+    } else if let Some(backup_config) = config.subcommand_matches("backup") {
+        CargoCacheCommands::Backup {
+            // clap makes sure this is present
+            output: backup_config.value_of("output").unwrap(),
+            components: backup_config.value_of("components"),
+        }
+    } else if let Some(restore_config) = config.subcommand_matches("restore") {
+        CargoCacheCommands::Restore {
+            // clap makes sure this is present
+            archive: restore_config.value_of("ARCHIVE").unwrap(),
+            dry_run: dry_run || restore_config.is_present("dry-run"),
+        }
+    } else if let Some(doctor_config) = config.subcommand_matches("doctor") {
+        // clap makes sure --quarantine and --remove are not passed together
+        let fix = if doctor_config.is_present("quarantine") {
+            DoctorFix::Quarantine
+        } else if doctor_config.is_present("remove") {
+            DoctorFix::Remove
+        } else {
+            DoctorFix::Report
+        };
+        CargoCacheCommands::Doctor {
+            fix,
+            dry_run: dry_run || doctor_config.is_present("dry-run"),
+        }
+    } else if let Some(migrate_config) = config.subcommand_matches("migrate-registry") {
+        CargoCacheCommands::MigrateRegistry {
+            dry_run: dry_run || migrate_config.is_present("dry-run"),
+        }
+    } else if let Some(dedupe_config) = config.subcommand_matches("dedupe") {
+        CargoCacheCommands::Dedupe {
+            reflink: dedupe_config.is_present("reflink"),
+            dry_run: dry_run || dedupe_config.is_present("dry-run"),
+        }
+    } else if let Some(maintain_config) = config.subcommand_matches("maintain") {
+        let autoclean_above = maintain_config
+            .value_of("autoclean-above")
+            .or(defaults.maintain_autoclean_above.as_deref());
+        let gc_every_days: Option<u64> = if maintain_config.is_present("gc-every-days") {
+            Some(
+                maintain_config
+                    .value_of_t("gc-every-days")
+                    .map_err(|_| "Error: \"--gc-every-days\" expected a number of days")
+                    .unwrap_or_fatal_error(),
+            )
+        } else {
+            defaults.maintain_gc_every_days
+        };
+        let check_every_hours: u64 = if maintain_config.is_present("check-every-hours") {
+            maintain_config
+                .value_of_t("check-every-hours")
+                .map_err(|_| "Error: \"--check-every-hours\" expected a number of hours")
+                .unwrap_or_fatal_error()
+        } else {
+            defaults
+                .maintain_check_every_hours
+                .unwrap_or(24 /* default*/)
+        };
+        if autoclean_above.is_none() && gc_every_days.is_none() {
+            Err::<(), _>(Error::MaintainPolicyMissing).unwrap_or_fatal_error();
+        }
+        CargoCacheCommands::Maintain {
+            autoclean_above,
+            gc_every_days,
+            check_every_hours,
+            force: maintain_config.is_present("force"),
+            dry_run: dry_run || maintain_config.is_present("dry-run"),
+            allow_cross_device: allow_cross_device
+                || maintain_config.is_present("allow-cross-device"),
+        }
+    } else if let Some(ui_config) = config.subcommand_matches("ui") {
+        CargoCacheCommands::Ui {
+            dry_run: dry_run || ui_config.is_present("dry-run"),
+        }
+    } else if let Some(git_config) = config.subcommand_matches("git") {
+        // clap makes sure a subcommand of "git" and its required arguments are present
+        if let Some(list_config) = git_config.subcommand_matches("list") {
+            CargoCacheCommands::GitListCheckouts {
+                repo: list_config.value_of("REPO").unwrap(),
+            }
+        } else if let Some(rm_config) = git_config.subcommand_matches("rm-checkout") {
+            CargoCacheCommands::GitRmCheckout {
+                repo: rm_config.value_of("REPO").unwrap(),
+                rev: rm_config.value_of("REV").unwrap(),
+                dry_run: dry_run || rm_config.is_present("dry-run"),
+            }
+        } else {
+            let export_config = git_config.subcommand_matches("export").unwrap();
+            CargoCacheCommands::GitExport {
+                repo: export_config.value_of("REPO").unwrap(),
+                dest: export_config.value_of("DIR").unwrap(),
+                rev: export_config.value_of("rev"),
+            }
+        }
+    } else if let Some(remove_config) = config.subcommand_matches("remove") {
+        CargoCacheCommands::RemoveCrate {
+            // clap makes sure this is present
+            spec: remove_config.value_of("CRATE").unwrap(),
+            git: remove_config.is_present("git"),
+            also_sccache: remove_config.is_present("also-sccache"),
+            dry_run: dry_run || remove_config.is_present("dry-run"),
+        }
+    } else if let Some(inspect_config) = config.subcommand_matches("inspect") {
+        CargoCacheCommands::Inspect {
+            // clap makes sure this is present
+            crate_name: inspect_config.value_of("CRATE").unwrap(),
+            version: inspect_config.value_of("VERSION"),
+            limit: inspect_config
+                .value_of("limit")
+                .unwrap_or("20" /* default*/)
+                .parse()
+                .unwrap_or(20 /* default*/),
+        }
+    } else if let Some(preheat_config) = config.subcommand_matches("preheat-install") {
+        CargoCacheCommands::PreheatInstall {
+            // clap makes sure this is present
+            crate_name: preheat_config.value_of("CRATE").unwrap(),
+            version: preheat_config.value_of("crate-version"),
+        }
+    } else if let Some(compare_config) = config.subcommand_matches("compare") {
+        CargoCacheCommands::Compare {
+            // clap makes sure this is present
+            other_cargo_home: compare_config.value_of("OTHER_CARGO_HOME").unwrap(),
+            merge_from: compare_config.is_present("merge-from"),
+            dry_run: dry_run || compare_config.is_present("dry-run"),
+        }
+    } else if let Some(stats_config) = config.subcommand_matches("stats") {
+        let last: usize = if stats_config.is_present("last") {
+            stats_config
+                .value_of_t("last")
+                .map_err(|_| "Error: \"--last\" expected a number of runs")
+                .unwrap_or_fatal_error()
+        } else {
+            10 /* default*/
+        };
+        let days: Option<u64> = stats_config.is_present("days").then(|| {
+            stats_config
+                .value_of_t("days")
+                .map_err(|_| "Error: \"--days\" expected a number of days")
+                .unwrap_or_fatal_error()
+        });
+        CargoCacheCommands::Stats {
+            bandwidth: stats_config.value_of("bandwidth"),
+            history: stats_config.is_present("history"),
+            last,
+            days,
+            sparkline: stats_config.is_present("sparkline"),
+        }
+    } else if let Some(check_config) = config.subcommand_matches("check") {
+        CargoCacheCommands::Check {
+            // clap makes sure this is present
+            fail_if_above: check_config.value_of("fail-if-above").unwrap(),
+        }
+    } else if let Some(metrics_config) = config.subcommand_matches("metrics") {
+        // the url is only used with --push, configuring it never sends anything by itself
+        let url = metrics_config
+            .value_of("push")
+            .map(str::to_string)
+            .or_else(|| env::value(env::METRICS_URL))
+            .or_else(|| defaults.metrics_url.clone());
+        CargoCacheCommands::Metrics {
+            push: metrics_config.is_present("push"),
+            url,
+            label: metrics_config
+                .value_of("label")
+                .or(defaults.metrics_label.as_deref()),
+            dry_run: dry_run || metrics_config.is_present("dry-run"),
+        }
     } else if let Some(trimconfig) = config.subcommand_matches("trim") {
         let trim_dry_run = dry_run || trimconfig.is_present("dry-run");
+        let trim_limit = trimconfig
+            .value_of("trim_limit")
+            .or(defaults.trim_limit.as_deref());
+        let keep_newer_than = trimconfig.value_of("keep-newer-than");
+        if trim_limit.is_none() && keep_newer_than.is_none() {
+            Err::<(), _>(Error::TrimPolicyMissing).unwrap_or_fatal_error();
+        }
         CargoCacheCommands::Trim {
             dry_run: trim_dry_run,
-            trim_limit: trimconfig.value_of("trim_limit"),
+            plan: trimconfig.is_present("plan"),
+            trim_limit,
+            keep_newer_than,
+            registries: RegistryFilter::new(
+                trimconfig
+                    .value_of("only-registry")
+                    .or_else(|| config.value_of("only-registry")),
+                trimconfig
+                    .value_of("exclude-registry")
+                    .or_else(|| config.value_of("exclude-registry")),
+            ),
         } // take config trim_config.value_of("trim_limit")
     } else if let Some(clean_unref_config) = config.subcommand_matches("clean-unref") {
         let arg_dry_run = dry_run || clean_unref_config.is_present("dry-run");
         CargoCacheCommands::CleanUnref {
             dry_run: arg_dry_run,
             manifest_path: clean_unref_config.value_of("manifest-path"),
+            all_projects: clean_unref_config.is_present("all-projects"),
+            allow_net: clean_unref_config.is_present("allow-net"),
         } // clean_unref_cfg.value_of("manifest-path"),
     } else if config.is_present("top-cache-items") {
         let limit = config
@@ -129,11 +821,31 @@ pub(crate) fn clap_to_enum(config: &ArgMatches) -> CargoCacheCommands<'_> {
         .subcommand_matches("query")
         .or_else(|| config.subcommand_matches("q"))
     {
-        CargoCacheCommands::Query { query_config }
-    } else if config.subcommand_matches("local").is_some()
-        || config.subcommand_matches("l").is_some()
+        CargoCacheCommands::Query {
+            query_config,
+            dry_run: dry_run || query_config.is_present("dry-run"),
+        }
+    } else if let Some(local_config) = config
+        .subcommand_matches("local")
+        .or_else(|| config.subcommand_matches("l"))
     {
-        CargoCacheCommands::Local
+        CargoCacheCommands::Local {
+            clean_build_dir: local_config.is_present("clean-build-dir"),
+            clean_stale_deps: local_config.is_present("clean-stale-deps"),
+            clean_stale_build_scripts: local_config.is_present("clean-stale-build-scripts"),
+            dry_run: dry_run || local_config.is_present("dry-run"),
+            recursive: local_config.value_of("recursive"),
+            clean: local_config.is_present("clean"),
+            clean_release_only: local_config.is_present("clean-release-only"),
+            keep_recent: if local_config.is_present("keep-recent") {
+                local_config
+                    .value_of_t("keep-recent")
+                    .map_err(|_| "Error: \"--keep-recent\" expected a number of projects")
+                    .unwrap_or_fatal_error()
+            } else {
+                0
+            },
+        }
     } else if config.is_present("info") {
         CargoCacheCommands::Info
     } else if config.is_present("remove-dir")
@@ -141,25 +853,52 @@ pub(crate) fn clap_to_enum(config: &ArgMatches) -> CargoCacheCommands<'_> {
             || config.is_present("remove-if-older-than"))
     {
         // This one must come BEFORE RemoveIfDate because that one also uses --remove dir
-        CargoCacheCommands::RemoveDir { dry_run } //need more info
+        CargoCacheCommands::RemoveDir {
+            dry_run,
+            allow_cross_device,
+            keep_going,
+            dirs: config.value_of("remove-dir"),
+        }
     } else if config.is_present("autoclean-expensive")
         || (config.is_present("gc-repos") && config.is_present("autoclean"))
     {
         // if we pass both --gc and --autoclean-expensive, we want autoclean-expensive to run
         // since is already includes --gc
-        CargoCacheCommands::AutoCleanExpensive { dry_run }
+        CargoCacheCommands::AutoCleanExpensive {
+            dry_run,
+            allow_cross_device,
+            keep_going,
+            yes,
+            preserve_modified,
+            keep_days,
+            registries,
+        }
     } else if config.is_present("fsck-repos") {
         CargoCacheCommands::FSCKRepos
     } else if config.is_present("gc-repos") {
         CargoCacheCommands::GitGCRepos { dry_run }
     } else if config.is_present("autoclean") {
-        CargoCacheCommands::AutoClean { dry_run }
+        CargoCacheCommands::AutoClean {
+            dry_run,
+            allow_cross_device,
+            keep_going,
+            yes,
+            preserve_modified,
+            keep_days,
+            registries,
+        }
+    } else if config.is_present("delete-empty-registries") {
+        CargoCacheCommands::DeleteEmptyRegistries { dry_run }
     } else if config.is_present("keep-duplicate-crates") {
         let limit: u64 = config
             .value_of_t("keep-duplicate-crates")
             .map_err(|_| "Error: \"--keep-duplicate-crates\" expected an integer argument")
             .unwrap_or_fatal_error();
-        CargoCacheCommands::KeepDuplicateCrates { dry_run, limit }
+        CargoCacheCommands::KeepDuplicateCrates {
+            dry_run,
+            limit,
+            keep_by: KeepBy::new(config.value_of("keep-by")),
+        }
     } else if config.subcommand_matches("registry").is_some()
         || config.subcommand_matches("r").is_some()
         || config.subcommand_matches("registries").is_some()
@@ -167,6 +906,8 @@ pub(crate) fn clap_to_enum(config: &ArgMatches) -> CargoCacheCommands<'_> {
         CargoCacheCommands::Registries
     } else if config.is_present("list-dirs") {
         CargoCacheCommands::ListDirs
+    } else if config.is_present("estimate") {
+        CargoCacheCommands::Estimate
     } else if config.is_present("remove-if-younger-than")
         || config.is_present("remove-if-older-than")
     {
@@ -182,17 +923,527 @@ pub(crate) fn clap_to_enum(config: &ArgMatches) -> CargoCacheCommands<'_> {
         CargoCacheCommands::Verify {
             clean_corrupted,
             dry_run: dry_run2,
+            report: verify_cfg.value_of("report"),
+            content: verify_cfg.is_present("content"),
+            checksums: verify_cfg.is_present("checksums"),
         }
     } else if dry_run {
         // none of the flags that do on-disk changes are present
 
         // we got "cargo cache --dry-run"
         CargoCacheCommands::OnlyDryRun
+    } else if config.is_present("components") || config.is_present("no-default-scan") {
+        // "cargo cache --components registry-index", print the summary of these components
+        CargoCacheCommands::DefaultSummary
+    } else if config.is_present("hyperlinks")
+        || config.is_present("assume-tty")
+        || config.is_present("no-tty")
+        || config.is_present("cargo-home")
+        || config.is_present("output-format")
+        || config.is_present("lang")
+    {
+        // "cargo cache --hyperlinks on", "cargo cache --no-tty", "cargo cache --cargo-home /tmp/ci-cache",
+        // "cargo cache --output-format json", "cargo cache --lang de"
+        CargoCacheCommands::DefaultSummary
     } else {
         unreachable!("Failed to map all clap options to enum?")
     }
 }
 
+/// an example invocation of a subcommand
+/// shown in the --help of the subcommand and by "cargo cache examples"
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Example {
+    pub(crate) command: &'static str,
+    pub(crate) description: &'static str,
+}
+
+// the examples of each subcommand, keep these in sync with the subcommand definitions in gen_clap()
+
+const QUERY_EXAMPLES: &[Example] = &[
+    Example {
+        command: "cargo cache query serde",
+        description: "list all cache items whose names contain \"serde\"",
+    },
+    Example {
+        command: "cargo cache query '^tokio-' --sort-by size --human-readable",
+        description: "list all tokio-* items, largest first, with human readable sizes",
+    },
+    Example {
+        command: "cargo cache query '^serde' '^tokio' --not json",
+        description: "list all serde* and tokio* items except the json ones",
+    },
+    Example {
+        command: "cargo cache query tokio serde --intersect",
+        description: "list the items whose names contain both \"tokio\" and \"serde\"",
+    },
+    Example {
+        command: "cargo cache query 'serde.*' --larger-than 10M --older-than 2023.01.01 --delete",
+        description: "remove all serde items bigger than 10 MB that were not used since 2023",
+    },
+];
+
+const LOCAL_EXAMPLES: &[Example] = &[
+    Example {
+        command: "cargo cache local",
+        description: "show the size of the target directory of the current project",
+    },
+    Example {
+        command: "cargo cache local --clean-build-dir --dry-run",
+        description: "show which intermediate build files would be removed",
+    },
+    Example {
+        command: "cargo cache local --clean-stale-deps",
+        description: "remove the build output of dependencies that are no longer in the Cargo.lock",
+    },
+    Example {
+        command: "cargo cache local --clean-stale-build-scripts --dry-run",
+        description:
+            "show which stale build script outputs (often compiled C libraries) would be removed",
+    },
+    Example {
+        command: "cargo cache local --recursive ~/projects",
+        description: "list the target dir sizes of all projects in ~/projects, biggest first",
+    },
+    Example {
+        command: "cargo cache local --recursive ~/projects --clean --keep-recent 3 --dry-run",
+        description: "show which target dirs would be removed, except the ones of the 3 most recently built projects",
+    },
+    Example {
+        command: "cargo cache local --clean-release-only",
+        description: "remove the release build output of the current project, keep the debug one",
+    },
+];
+
+const REGISTRY_EXAMPLES: &[Example] = &[
+    Example {
+        command: "cargo cache registry",
+        description: "show the cache summary of each registry separately",
+    },
+    Example {
+        command: "cargo cache registry --output-format json",
+        description: "print the sizes of each registry in bytes as json, for scripts",
+    },
+];
+
+const SCCACHE_EXAMPLES: &[Example] = &[
+    Example {
+        command: "cargo cache sccache",
+        description: "show the size and the number of files of the local sccache cache",
+    },
+    Example {
+        command: "cargo cache sccache --clean",
+        description: "remove the least recently used files until the sccache cache is below its configured maximum size",
+    },
+    Example {
+        command: "cargo cache sccache --trim-limit 2G --dry-run",
+        description: "show which files would be removed to shrink the sccache cache to 2G",
+    },
+];
+
+const CLEAN_UNREF_EXAMPLES: &[Example] = &[
+    Example {
+        command: "cargo cache clean-unref --dry-run",
+        description: "show which crates the project in the current directory does not use",
+    },
+    Example {
+        command: "cargo cache clean-unref --manifest-path ../foo/Cargo.toml",
+        description: "remove everything from the cache that the foo project does not use",
+    },
+    Example {
+        command: "cargo cache clean-unref --all-projects --dry-run",
+        description:
+            "show what none of the projects registered with \"cargo cache projects add\" use",
+    },
+];
+
+const TRIM_EXAMPLES: &[Example] = &[
+    Example {
+        command: "cargo cache trim --limit 5G",
+        description: "remove the least recently used items until the cache is at most 5 GB",
+    },
+    Example {
+        command: "cargo cache trim --keep-newer-than 30d",
+        description: "remove everything that was not used within the last 30 days",
+    },
+    Example {
+        command: "cargo cache trim --limit 500M --dry-run",
+        description: "show what would be removed to get the cache below 500 MB",
+    },
+    Example {
+        command: "cargo cache trim --limit 5G --plan --output-format json",
+        description: "write the ordered list of items trim would remove as json, for review",
+    },
+    Example {
+        command: "cargo cache trim --limit 1G --only-registry crates.io",
+        description: "only trim the crates of crates.io, other registries and git repos are kept",
+    },
+    Example {
+        command: "cargo cache --approve-with ./retention-policy.sh trim --limit 5G",
+        description: "let ./retention-policy.sh decide which of the items trim picked are removed",
+    },
+];
+
+const TOOLCHAIN_EXAMPLES: &[Example] = &[
+    Example {
+        command: "cargo cache toolchain",
+        description: "show the size of each toolchain installed via rustup",
+    },
+    Example {
+        command: "cargo cache toolchain --breakdown",
+        description: "also show the size of the components of each toolchain (rustc, docs, std of each target...)",
+    },
+    Example {
+        command: "cargo cache toolchain --remove-toolchain nightly-2023-01-01 --dry-run",
+        description: "show how much removing an old toolchain would free",
+    },
+    Example {
+        command: "cargo cache toolchain --remove-docs",
+        description: "remove the offline documentation (rust-docs) of all toolchains",
+    },
+];
+
+const SELFTEST_SIZES_EXAMPLES: &[Example] = &[
+    Example {
+        command: "cargo cache selftest-sizes",
+        description: "compare the sizes cargo-cache calculates with a full scan of each component",
+    },
+    Example {
+        command: "cargo cache --components registry-sources selftest-sizes",
+        description: "only check the extracted crate sources",
+    },
+];
+
+const BACKUP_EXAMPLES: &[Example] = &[
+    Example {
+        command: "cargo cache backup --output cache-snapshot.tar.gz",
+        description: "archive the crate archives, the registry indices and the git repos",
+    },
+    Example {
+        command: "cargo cache backup --output index.tar --components registry-index",
+        description: "only archive the registry indices, without compression",
+    },
+];
+
+const RESTORE_EXAMPLES: &[Example] = &[
+    Example {
+        command: "cargo cache restore cache-snapshot.tar.gz",
+        description: "unpack a backup into the cargo home, existing files are kept",
+    },
+    Example {
+        command: "cargo cache restore cache-snapshot.tar.gz --dry-run",
+        description: "show how much would be restored",
+    },
+];
+
+const DOCTOR_EXAMPLES: &[Example] = &[
+    Example {
+        command: "cargo cache doctor",
+        description: "list entries of the cache that are not where cargo expects them",
+    },
+    Example {
+        command: "cargo cache doctor --quarantine",
+        description: "move them into cargo-cache-quarantine inside the cargo home",
+    },
+    Example {
+        command: "cargo cache doctor --remove --dry-run",
+        description: "show which misplaced entries would be removed",
+    },
+];
+
+const UI_EXAMPLES: &[Example] = &[
+    Example {
+        command: "cargo cache ui",
+        description: "browse the components and crates of the cache and remove the marked ones",
+    },
+    Example {
+        command: "cargo cache ui --dry-run",
+        description: "browse the cache, removing only prints what would be removed",
+    },
+];
+
+const GIT_EXAMPLES: &[Example] = &[
+    Example {
+        command: "cargo cache git export serde ../serde",
+        description:
+            "clone the cached git repo of serde into ../serde and check out its newest commit",
+    },
+    Example {
+        command: "cargo cache git export serde-1ecc6299db9ec823 ../serde --rev origin/master",
+        description:
+            "check out the branch cargo fetched instead, useful if several repos are named serde",
+    },
+    Example {
+        command: "cargo cache git list rust-analyzer",
+        description: "list the checked out revisions of rust-analyzer and their sizes",
+    },
+    Example {
+        command: "cargo cache git rm-checkout rust-analyzer 1ecc62",
+        description:
+            "remove the checkout of the revision 1ecc62.., keep the repo and the other checkouts",
+    },
+];
+
+const INSPECT_EXAMPLES: &[Example] = &[
+    Example {
+        command: "cargo cache inspect openssl-src",
+        description: "list the largest files of the newest cached version of openssl-src",
+    },
+    Example {
+        command: "cargo cache inspect serde 1.0.188 --limit 5",
+        description: "only list the 5 largest files of serde 1.0.188",
+    },
+];
+
+const REMOVE_EXAMPLES: &[Example] = &[
+    Example {
+        command: "cargo cache remove openssl-sys",
+        description: "remove the archives and sources of all cached versions of openssl-sys",
+    },
+    Example {
+        command: "cargo cache remove serde@1.0.188 --dry-run",
+        description: "show what would be removed of serde 1.0.188",
+    },
+    Example {
+        command: "cargo cache remove my-git-dep --git",
+        description: "also remove the cached git repo and checkouts named my-git-dep",
+    },
+    Example {
+        command: "cargo cache remove openssl-sys --also-sccache",
+        description:
+            "also remove the objects sccache compiled of openssl-sys, the next build starts fresh",
+    },
+];
+
+const PREHEAT_INSTALL_EXAMPLES: &[Example] = &[
+    Example {
+        command: "cargo cache preheat-install ripgrep",
+        description: "check if the latest ripgrep can be installed offline",
+    },
+    Example {
+        command: "cargo cache preheat-install cargo-cache --version '^0.8'",
+        description: "check if cargo-cache 0.8.x can be installed offline",
+    },
+];
+
+const COMPARE_EXAMPLES: &[Example] = &[
+    Example {
+        command: "cargo cache compare /mnt/backup/.cargo",
+        description: "show what only exists in either the local cache or the backup",
+    },
+    Example {
+        command: "cargo cache compare /mnt/backup/.cargo --merge-from --dry-run",
+        description: "show which crate archives and git repos would be copied from the backup",
+    },
+];
+
+const STATS_EXAMPLES: &[Example] = &[
+    Example {
+        command: "cargo cache stats --value",
+        description: "estimate how long downloading and extracting everything again would take",
+    },
+    Example {
+        command: "cargo cache stats --value --bandwidth 2M",
+        description: "the same, assuming a download speed of 2 MB/s",
+    },
+    Example {
+        command: "cargo cache stats --history --days 30 --sparkline",
+        description: "show how the cache grew over the last 30 days (needs \"snapshots\" in the [stats] table of the config file)",
+    },
+];
+
+const CHECK_EXAMPLES: &[Example] = &[
+    Example {
+        command: "cargo cache check --fail-if-above 20G",
+        description: "exit with 1 if the cache is bigger than 20 GB",
+    },
+    Example {
+        command: "cargo cache check --fail-if-above registry=5G,git=2G",
+        description:
+            "exit with 1 if the registry is bigger than 5 GB or the git repos are bigger than 2 GB",
+    },
+];
+
+const METRICS_EXAMPLES: &[Example] = &[
+    Example {
+        command: "cargo cache metrics",
+        description: "print the sizes and counts of the cache as json, without any paths",
+    },
+    Example {
+        command:
+            "cargo cache metrics --push http://metrics.internal:9091/cargo-cache --label ci-linux",
+        description: "post them to a collector, labeled as coming from \"ci-linux\"",
+    },
+    Example {
+        command: "cargo cache metrics --push --dry-run",
+        description: "show what would be posted to the metrics.url of the config file",
+    },
+];
+
+const MIGRATE_REGISTRY_EXAMPLES: &[Example] = &[
+    Example {
+        command: "cargo cache migrate-registry --dry-run",
+        description: "show which registry directories of older cargo versions would be removed",
+    },
+    Example {
+        command: "cargo cache migrate-registry",
+        description:
+            "move the crate archives only they have to the directory cargo uses and remove them",
+    },
+];
+
+const MAINTAIN_EXAMPLES: &[Example] = &[
+    Example {
+        command: "cargo cache maintain --autoclean-above 10G --gc-every-days 7",
+        description: "clean the cache once it is bigger than 10 GB and recompress the git repos weekly, from a shell profile or cron",
+    },
+    Example {
+        command: "cargo cache maintain --force --dry-run",
+        description: "show what the policy of the config file would do now, even if it is not due yet",
+    },
+];
+
+const SAVINGS_EXAMPLES: &[Example] = &[Example {
+    command: "cargo cache savings",
+    description: "show how much space cargo-cache freed in the last week, month and in total",
+}];
+
+const LOG_EXAMPLES: &[Example] = &[
+    Example {
+        command: "cargo cache log",
+        description: "list what the last 10 runs that removed anything removed",
+    },
+    Example {
+        command: "cargo cache log --last 3 --paths",
+        description: "list every item the last 3 of them removed",
+    },
+];
+
+const PROJECTS_EXAMPLES: &[Example] = &[
+    Example {
+        command: "cargo cache projects add ~/code/foo",
+        description: "keep what the foo project needs when running clean-unref --all-projects",
+    },
+    Example {
+        command: "cargo cache projects",
+        description: "list the registered projects, the ones that do not exist anymore are marked",
+    },
+    Example {
+        command: "cargo cache projects remove ~/code/foo",
+        description: "forget the foo project again",
+    },
+];
+
+const SELF_EXAMPLES: &[Example] = &[
+    Example {
+        command: "cargo cache self check-update",
+        description: "check the local crates.io index for a newer release of cargo-cache",
+    },
+    Example {
+        command: "cargo cache self check-update --online",
+        description: "ask crates.io for the latest release of cargo-cache",
+    },
+];
+
+const VERIFY_EXAMPLES: &[Example] = &[
+    Example {
+        command: "cargo cache verify",
+        description: "check the extracted crate sources against their archives",
+    },
+    Example {
+        command: "cargo cache verify --clean-corrupted",
+        description: "remove the sources that do not match their archives",
+    },
+    Example {
+        command: "cargo cache verify --report report.json",
+        description: "also write the list of mismatching files of each crate to report.json",
+    },
+    Example {
+        command: "cargo cache verify --checksums --clean-corrupted",
+        description: "remove the archives whose checksum does not match the registry index",
+    },
+];
+
+const DEDUPE_EXAMPLES: &[Example] = &[
+    Example {
+        command: "cargo cache dedupe --dry-run",
+        description: "show how much space linking identical files of the crate sources would save",
+    },
+    Example {
+        command: "cargo cache dedupe",
+        description: "replace identical files of the crate sources with hardlinks to one of them",
+    },
+    Example {
+        command: "cargo cache dedupe --reflink",
+        description: "use reflinks instead, on Btrfs, XFS or APFS",
+    },
+];
+
+/// all subcommands and their examples, in the order of "cargo cache --help"
+pub(crate) const EXAMPLES: &[(&str, &[Example])] = &[
+    ("backup", BACKUP_EXAMPLES),
+    ("check", CHECK_EXAMPLES),
+    ("clean-unref", CLEAN_UNREF_EXAMPLES),
+    ("compare", COMPARE_EXAMPLES),
+    ("dedupe", DEDUPE_EXAMPLES),
+    ("doctor", DOCTOR_EXAMPLES),
+    ("git", GIT_EXAMPLES),
+    ("inspect", INSPECT_EXAMPLES),
+    ("local", LOCAL_EXAMPLES),
+    ("log", LOG_EXAMPLES),
+    ("maintain", MAINTAIN_EXAMPLES),
+    ("metrics", METRICS_EXAMPLES),
+    ("migrate-registry", MIGRATE_REGISTRY_EXAMPLES),
+    ("preheat-install", PREHEAT_INSTALL_EXAMPLES),
+    ("projects", PROJECTS_EXAMPLES),
+    ("query", QUERY_EXAMPLES),
+    ("registry", REGISTRY_EXAMPLES),
+    ("remove", REMOVE_EXAMPLES),
+    ("restore", RESTORE_EXAMPLES),
+    ("savings", SAVINGS_EXAMPLES),
+    ("sccache", SCCACHE_EXAMPLES),
+    ("self", SELF_EXAMPLES),
+    ("selftest-sizes", SELFTEST_SIZES_EXAMPLES),
+    ("stats", STATS_EXAMPLES),
+    ("toolchain", TOOLCHAIN_EXAMPLES),
+    ("trim", TRIM_EXAMPLES),
+    ("ui", UI_EXAMPLES),
+    ("verify", VERIFY_EXAMPLES),
+];
+
+/// the command and the indented description of each example
+fn example_lines(examples: &[Example]) -> Vec<String> {
+    examples
+        .iter()
+        .flat_map(|example| {
+            [
+                format!("    {}", example.command),
+                format!("        {}", example.description),
+            ]
+        })
+        .collect()
+}
+
+/// the "EXAMPLES:" section of the --help of a subcommand
+fn examples_help(examples: &[Example]) -> String {
+    let mut lines = vec![String::from("EXAMPLES:")];
+    lines.extend(example_lines(examples));
+    lines.join("\n")
+}
+
+/// the examples of all subcommands, printed by "cargo cache examples"
+pub(crate) fn all_examples() -> String {
+    EXAMPLES
+        .iter()
+        .map(|(subcommand, examples)| {
+            let mut lines = vec![format!("cargo cache {subcommand}:")];
+            lines.extend(example_lines(examples));
+            lines.join("\n")
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
 /// generates the version info with what we have in the build.rs
 pub(crate) fn get_version() -> String {
     // remove the "cargo-cache" since CLAP already adds that by itself
@@ -205,14 +1456,47 @@ pub(crate) fn get_version() -> String {
 #[allow(clippy::too_many_lines)]
 pub(crate) fn gen_clap() -> ArgMatches {
     let version_string = get_version();
+    // the EXAMPLES: sections of the subcommand --help, these need to outlive all the Apps and Args
+    let query_examples = examples_help(QUERY_EXAMPLES);
+    let local_examples = examples_help(LOCAL_EXAMPLES);
+    let registry_examples = examples_help(REGISTRY_EXAMPLES);
+    let sccache_examples = examples_help(SCCACHE_EXAMPLES);
+    let clean_unref_examples = examples_help(CLEAN_UNREF_EXAMPLES);
+    let trim_examples = examples_help(TRIM_EXAMPLES);
+    let toolchain_examples = examples_help(TOOLCHAIN_EXAMPLES);
+    let preheat_install_examples = examples_help(PREHEAT_INSTALL_EXAMPLES);
+    let compare_examples = examples_help(COMPARE_EXAMPLES);
+    let stats_examples = examples_help(STATS_EXAMPLES);
+    let check_examples = examples_help(CHECK_EXAMPLES);
+    let metrics_examples = examples_help(METRICS_EXAMPLES);
+    let migrate_registry_examples = examples_help(MIGRATE_REGISTRY_EXAMPLES);
+    let dedupe_examples = examples_help(DEDUPE_EXAMPLES);
+    let maintain_examples = examples_help(MAINTAIN_EXAMPLES);
+    let savings_examples = examples_help(SAVINGS_EXAMPLES);
+    let log_examples = examples_help(LOG_EXAMPLES);
+    let projects_examples = examples_help(PROJECTS_EXAMPLES);
+    let self_examples = examples_help(SELF_EXAMPLES);
+    let git_examples = examples_help(GIT_EXAMPLES);
+    let doctor_examples = examples_help(DOCTOR_EXAMPLES);
+    let ui_examples = examples_help(UI_EXAMPLES);
+    let selftest_sizes_examples = examples_help(SELFTEST_SIZES_EXAMPLES);
+    let backup_examples = examples_help(BACKUP_EXAMPLES);
+    let restore_examples = examples_help(RESTORE_EXAMPLES);
+    let inspect_examples = examples_help(INSPECT_EXAMPLES);
+    let remove_examples = examples_help(REMOVE_EXAMPLES);
+    let verify_examples = examples_help(VERIFY_EXAMPLES);
 
     let list_dirs = Arg::new("list-dirs")
         .short('l')
         .long("list-dirs")
         .help("List all found directory paths");
 
+    let estimate = Arg::new("estimate").long("estimate").help(
+        "Quickly estimate the size of the cache by only looking at a sample of each directory",
+    );
+
     let remove_dir = Arg::new("remove-dir").short('r').long("remove-dir")
-        .help("Remove directories, accepted values: all,git-db,git-repos,\nregistry-sources,registry-crate-cache,registry-index,registry")
+        .help("Remove directories, accepted values: all,git-db,git-repos,\nregistry-sources,registry-crate-cache,registry-index,registry-index-sparse,registry,\na single registry: registry-crate-cache:<registry>")
         .takes_value(true)
         .value_name("dir1,dir2,dir3");
 
@@ -234,6 +1518,10 @@ pub(crate) fn gen_clap() -> ArgMatches {
             "Print information cache directories, what they are for and what can be safely deleted",
         );
 
+    let delete_empty_registries = Arg::new("delete-empty-registries")
+        .long("delete-empty-registries")
+        .help("Remove the index and the empty directories of registries without crate archives and sources");
+
     let keep_duplicate_crates = Arg::new("keep-duplicate-crates")
         .short('k')
         .long("keep-duplicate-crates")
@@ -241,6 +1529,14 @@ pub(crate) fn gen_clap() -> ArgMatches {
         .takes_value(true)
         .value_name("N");
 
+    let keep_by = Arg::new("keep-by")
+        .long("keep-by")
+        .help("Which versions --keep-duplicate-crates keeps: the highest versions or the most recently downloaded ones, default: version")
+        .takes_value(true)
+        .possible_values(["version", "mtime"])
+        .requires("keep-duplicate-crates")
+        .value_name("ORDER");
+
     let dry_run = Arg::new("dry-run")
         .short('n')
         .long("dry-run")
@@ -281,6 +1577,177 @@ pub(crate) fn gen_clap() -> ArgMatches {
         .takes_value(true)
         .value_name("date");
 
+    let allow_cross_device = Arg::new("allow-cross-device")
+        .long("allow-cross-device")
+        .help(
+            "Allow removing directories that are located on a different device than the cargo home",
+        );
+
+    let use_system_trash = Arg::new("use-system-trash")
+        .long("use-system-trash")
+        .help("Move removed items to the trash or recycle bin of the desktop instead of deleting them, \
+        the space is only freed once the trash is emptied. Without a desktop, items are deleted")
+        .global(true);
+
+    let keep_going = Arg::new("keep-going")
+        .long("keep-going")
+        .help("Remove everything that can be removed even if some entries can not be removed by the current user");
+
+    let i_know_what_i_am_doing = Arg::new("i-know-what-i-am-doing")
+        .long("i-know-what-i-am-doing")
+        .help("Remove things even if the cargo home is \"/\", the home directory or does not look like a cargo home");
+
+    let removal_stats = Arg::new("stats")
+        .long("stats")
+        .help("Print how many files and bytes per second were removed, slow removals hint at a virus scanner or network file system");
+
+    let quiet = Arg::new("quiet")
+        .short('q')
+        .long("quiet")
+        .help("Do not show the progress of --gc, trim and verify");
+
+    let yes = Arg::new("yes")
+        .long("yes")
+        .help("Do not ask for confirmation before --autoclean removes a lot of data");
+
+    let offline = Arg::new("offline")
+        .long("offline")
+        .help("Never access the network, even if a subcommand is asked to (also CARGO_NET_OFFLINE and net.offline)");
+
+    let preserve_modified = Arg::new("preserve-modified")
+        .long("preserve-modified")
+        .help("Keep crate sources that were modified after they were extracted with --autoclean");
+
+    let keep_days = Arg::new("keep-days")
+        .long("keep-days")
+        .help("Keep crate sources and git checkouts that were used in the last N days with --autoclean, default: 0")
+        .takes_value(true)
+        .value_name("N");
+
+    let keep = Arg::new("keep")
+        .long("keep")
+        .help("Never remove the archives and sources of these crates with --autoclean, trim, --keep-duplicate-crates and --remove-if-*-than")
+        .takes_value(true)
+        .value_name("crate1,crate2");
+
+    let only_registry = Arg::new("only-registry")
+        .long("only-registry")
+        .help("Only remove items of these registries with --autoclean and trim, for example: crates.io or a name from [registries] in the cargo config")
+        .takes_value(true)
+        .value_name("reg1,reg2");
+
+    let exclude_registry = Arg::new("exclude-registry")
+        .long("exclude-registry")
+        .help("Do not remove items of these registries with --autoclean and trim")
+        .takes_value(true)
+        .value_name("reg1,reg2");
+
+    let components = Arg::new("components")
+        .long("components")
+        .help("Only scan and operate on these components, accepted values: all,git-db,git-repos,\nregistry-sources,registry-crate-cache,registry-index,registry")
+        .takes_value(true)
+        .value_name("comp1,comp2");
+
+    let no_default_scan = Arg::new("no-default-scan")
+        .long("no-default-scan")
+        .help("Do not scan the whole cache, only the components given via --components");
+
+    let no_size_cache = Arg::new("no-size-cache")
+        .long("no-size-cache")
+        .help("Scan all crate sources and git checkouts instead of reusing the sizes of unchanged ones from the last run")
+        .conflicts_with("refresh-size-cache");
+
+    let refresh_size_cache = Arg::new("refresh-size-cache")
+        .long("refresh-size-cache")
+        .help(
+            "Scan all crate sources and git checkouts and remember their sizes for the next runs",
+        );
+
+    let no_wait = Arg::new("no-wait")
+        .long("no-wait")
+        .help("Fail instead of waiting if another cargo-cache is modifying the cache")
+        .conflicts_with("wait");
+
+    let wait = Arg::new("wait")
+        .long("wait")
+        .help("Wait at most this many seconds for another cargo-cache that is modifying the cache")
+        .takes_value(true)
+        .value_name("SECONDS");
+
+    let retries = Arg::new("retries")
+        .long("retries")
+        .help("Retry filesystem operations that fail with transient errors (network filesystems) N times, default: 3")
+        .takes_value(true)
+        .value_name("N");
+
+    let retry_backoff = Arg::new("retry-backoff")
+        .long("retry-backoff")
+        .help("Wait this many milliseconds before the first retry, doubled for every further retry, default: 100")
+        .takes_value(true)
+        .value_name("MILLISECONDS");
+
+    let max_scan_depth = Arg::new("max-scan-depth")
+        .long("max-scan-depth")
+        .help("Warn about and skip directories nested deeper than N levels, default: 100")
+        .takes_value(true)
+        .value_name("N");
+
+    let cargo_home = Arg::new("cargo-home")
+        .long("cargo-home")
+        .visible_alias("cache-dir")
+        .alias("root")
+        .help("Use this directory as the cargo home instead of $CARGO_HOME, for example a mounted CI cache, \
+        works with all subcommands. Pass it several times or as a quoted glob (\"/srv/cargo/*\") to work on several cargo homes")
+        .takes_value(true)
+        .value_name("PATH")
+        .multiple_occurrences(true)
+        .global(true);
+
+    let approve_with = Arg::new("approve-with")
+        .long("approve-with")
+        .help("Pass the items trim, clean-unref and --remove-if-older/younger-than would remove as json to this command \
+        and only remove the ones it approves")
+        .takes_value(true)
+        .value_name("COMMAND");
+
+    let hyperlinks = Arg::new("hyperlinks")
+        .long("hyperlinks")
+        .help("Print paths as clickable links in terminals that support it, default: auto")
+        .takes_value(true)
+        .possible_values(["on", "off", "auto"])
+        .value_name("WHEN");
+
+    let assume_tty = Arg::new("assume-tty")
+        .long("assume-tty")
+        .help("Behave as if running in a terminal: show progress, ask for confirmation and print hyperlinks (--hyperlinks auto)")
+        .conflicts_with("no-tty");
+
+    let no_tty = Arg::new("no-tty")
+        .long("no-tty")
+        .help("Behave as if not running in a terminal: no progress, no confirmation prompts and no hyperlinks (--hyperlinks auto)");
+
+    let lang = Arg::new("lang")
+        .long("lang")
+        .help("Print the summary and --info in this language, needs a message catalog <LANG>.messages")
+        .takes_value(true)
+        .value_name("LANG")
+        .env(env::LANG);
+
+    let output_format = Arg::new("output-format")
+        .long("output-format")
+        .help("Print the summary, --info, registry and --top-cache-items as json for scripts, default: table")
+        .takes_value(true)
+        .possible_values(["table", "json"])
+        .value_name("FORMAT");
+
+    let output_schema = Arg::new("output-schema")
+        .long("output-schema")
+        .help("Print the JSON Schema of everything --output-format json and --json-errors print");
+
+    let json_errors = Arg::new("json-errors")
+        .long("json-errors")
+        .help("Print errors as json with a stable error code for scripts, implied by --output-format json");
+
     let debug = Arg::new("debug")
         .long("debug")
         .help("print some debug stats")
@@ -307,43 +1774,213 @@ pub(crate) fn gen_clap() -> ArgMatches {
         .long("human-readable")
         .help("print sizes in human readable format");
 
+    // the patterns, an item is listed if it matches any of them
+    let query_patterns = Arg::new("QUERY")
+        .help("regular expressions to match the names of the cache items against")
+        .multiple_values(true);
+
+    // arg of query sbcmd
+    let union = Arg::new("union")
+        .long("union")
+        .help("list items matching any of the patterns, the default")
+        .conflicts_with("intersect");
+
+    // arg of query sbcmd
+    let intersect = Arg::new("intersect")
+        .long("intersect")
+        .help("list items matching all of the patterns");
+
+    // arg of query sbcmd
+    let not = Arg::new("not")
+        .long("not")
+        .help("never list items matching this pattern, can be passed several times")
+        .takes_value(true)
+        .multiple_occurrences(true)
+        .value_name("PATTERN");
+
+    // arg of query sbcmd
+    let larger_than = Arg::new("larger-than")
+        .long("larger-than")
+        .help("only list items bigger than this, for example 10M")
+        .takes_value(true)
+        .value_name("SIZE");
+
+    // arg of query sbcmd
+    let older_than = Arg::new("older-than")
+        .long("older-than")
+        .help("only list items last used before this date (YYYY.MM.DD) or this long ago (30d, 6w, 3m, 1y)")
+        .takes_value(true)
+        .value_name("AGE");
+
+    // arg of query sbcmd
+    let delete = Arg::new("delete")
+        .long("delete")
+        .help("remove the listed items");
+
+    // arg of query and remove sbcmds
+    let also_sccache = Arg::new("also-sccache")
+        .long("also-sccache")
+        .help("also remove the compiled objects of the crates from the sccache cache");
+
     // query subcommand to allow querying
     let query = App::new("query")
         .about("run a query")
-        .arg(Arg::new("QUERY"))
+        .after_help(&*query_examples)
+        .arg(&query_patterns)
+        .arg(&union)
+        .arg(&intersect)
+        .arg(&not)
+        .arg(&larger_than)
+        .arg(&older_than)
         .arg(&query_order)
-        .arg(&human_readable);
+        .arg(&human_readable)
+        .arg(&delete)
+        .arg(also_sccache.clone().requires("delete"))
+        .arg(&dry_run)
+        .arg(&hyperlinks);
 
     // short q (shorter query sbcmd)
     let query_short = App::new("q")
         .about("run a query")
-        .arg(Arg::new("QUERY"))
+        .after_help(&*query_examples)
+        .arg(&query_patterns)
+        .arg(&union)
+        .arg(&intersect)
+        .arg(&not)
+        .arg(&larger_than)
+        .arg(&older_than)
         .arg(&query_order)
-        .arg(&human_readable);
+        .arg(&human_readable)
+        .arg(&delete)
+        .arg(also_sccache.clone().requires("delete"))
+        .arg(&dry_run)
+        .arg(&hyperlinks);
     // </query>
 
     //<local>
     // local subcommand
-    let local = App::new("local").about("check local build cache (target) of a rust project");
+    let clean_build_dir = Arg::new("clean-build-dir")
+        .long("clean-build-dir")
+        .help("remove intermediate build files but keep final artifacts");
+
+    let clean_stale_deps = Arg::new("clean-stale-deps")
+        .long("clean-stale-deps")
+        .help("remove the build output of dependencies that are no longer in the Cargo.lock");
+
+    let clean_stale_build_scripts = Arg::new("clean-stale-build-scripts")
+        .long("clean-stale-build-scripts")
+        .help("only remove the build script output (build/) of dependencies that are no longer in the Cargo.lock");
+
+    let recursive = Arg::new("recursive")
+        .long("recursive")
+        .help("list the target dir sizes of all projects found below this directory")
+        .takes_value(true)
+        .value_name("DIR")
+        .conflicts_with_all(&[
+            "clean-build-dir",
+            "clean-stale-deps",
+            "clean-stale-build-scripts",
+        ]);
+
+    let clean_target = Arg::new("clean")
+        .long("clean")
+        .help("remove the target dir of the project, or of all projects with --recursive")
+        .conflicts_with_all(&[
+            "clean-build-dir",
+            "clean-stale-deps",
+            "clean-stale-build-scripts",
+        ]);
+
+    let clean_release_only = Arg::new("clean-release-only")
+        .long("clean-release-only")
+        .help("like --clean, but only remove the release build output")
+        .conflicts_with_all(&[
+            "clean-build-dir",
+            "clean-stale-deps",
+            "clean-stale-build-scripts",
+        ]);
+
+    let keep_recent = Arg::new("keep-recent")
+        .long("keep-recent")
+        .help("keep the target dirs of the N most recently built projects with --clean")
+        .takes_value(true)
+        .value_name("N")
+        .requires("clean-targets");
+
+    // --keep-recent needs one of these
+    let clean_targets = ArgGroup::new("clean-targets")
+        .args(&["clean", "clean-release-only"])
+        .multiple(true);
+
+    let local = App::new("local")
+        .about("check local build cache (target) of a rust project")
+        .after_help(&*local_examples)
+        .arg(&clean_build_dir)
+        .arg(&clean_stale_deps)
+        .arg(&clean_stale_build_scripts)
+        .arg(&recursive)
+        .arg(&clean_target)
+        .arg(&clean_release_only)
+        .arg(&keep_recent)
+        .group(&clean_targets)
+        .arg(&dry_run);
     // shorter local subcommand (l)
-    let local_short = App::new("l").about("check local build cache (target) of a rust project");
+    let local_short = App::new("l")
+        .about("check local build cache (target) of a rust project")
+        .after_help(&*local_examples)
+        .arg(&clean_build_dir)
+        .arg(&clean_stale_deps)
+        .arg(&clean_stale_build_scripts)
+        .arg(&recursive)
+        .arg(&clean_target)
+        .arg(&clean_release_only)
+        .arg(&keep_recent)
+        .group(&clean_targets)
+        .arg(&dry_run);
     //</local>
 
     // <registry>
     // registry subcommand
-    let registry = App::new("registry").about("query each package registry separately");
-    let registry_short = App::new("r").about("query each package registry separately");
+    let registry = App::new("registry")
+        .about("query each package registry separately")
+        .after_help(&*registry_examples)
+        .arg(&output_format);
+    let registry_short = App::new("r")
+        .about("query each package registry separately")
+        .after_help(&*registry_examples)
+        .arg(&output_format);
     // hidden, but have "cargo cache registries" work too
     let registries_hidden = App::new("registries")
         .about("query each package registry separately")
-        .setting(AppSettings::Hidden);
+        .setting(AppSettings::Hidden)
+        .arg(&output_format);
     //</registry>
 
     //<sccache>
+    let sccache_clean = Arg::new("clean")
+        .long("clean")
+        .help("remove the least recently used files until the cache is smaller than its configured maximum size (${SCCACHE_CACHE_SIZE}, 10G by default)");
+
+    let sccache_trim_limit = Arg::new("trim-limit")
+        .long("trim-limit")
+        .help("like --clean, but shrink the cache to this size, for example: '500M' or '5G'")
+        .takes_value(true)
+        .value_name("LIMIT");
+
     // local subcommand
-    let sccache = App::new("sccache").about("gather stats on a local sccache cache");
+    let sccache = App::new("sccache")
+        .about("gather stats on a local sccache cache")
+        .after_help(&*sccache_examples)
+        .arg(&sccache_clean)
+        .arg(&sccache_trim_limit)
+        .arg(&dry_run);
     // shorter local subcommand (l)
-    let sccache_short = App::new("sc").about("gather stats on a local sccache cache");
+    let sccache_short = App::new("sc")
+        .about("gather stats on a local sccache cache")
+        .after_help(&*sccache_examples)
+        .arg(&sccache_clean)
+        .arg(&sccache_trim_limit)
+        .arg(&dry_run);
     //</sccache>
 
     //<clean-unref>
@@ -360,10 +1997,23 @@ pub(crate) fn gen_clap() -> ArgMatches {
         .takes_value(true)
         .value_name("PATH");
 
+    let allow_net = Arg::new("allow-net").long("allow-net").help(
+        "Let cargo access the network to resolve the dependencies, Cargo.lock is never modified",
+    );
+
+    let all_projects = Arg::new("all-projects")
+        .long("all-projects")
+        .help("Keep what any of the projects added with \"cargo cache projects add\" references")
+        .conflicts_with("manifest-path");
+
     let clean_unref = App::new("clean-unref")
         .about("remove crates that are not referenced in a Cargo.toml from the cache")
+        .after_help(&*clean_unref_examples)
         .arg(&manifest_path)
-        .arg(&dry_run);
+        .arg(&all_projects)
+        .arg(&allow_net)
+        .arg(&dry_run)
+        .arg(&removal_stats);
     //</clean-unref>
 
     //<trim>
@@ -373,15 +2023,458 @@ pub(crate) fn gen_clap() -> ArgMatches {
         .help("size that the cache will be reduced to, for example: '6B', '1K', '4M', '5G' or '1T'")
         .takes_value(true)
         .value_name("LIMIT")
-        .required(true);
+        .env(env::TRIM_LIMIT);
+
+    let keep_newer_than = Arg::new("keep-newer-than")
+        .long("keep-newer-than")
+        .help("remove items that were not used within this time, for example '30d', '6w', '3m' (months) or '1y', or since a date: YYYY.MM.DD")
+        .takes_value(true)
+        .value_name("AGE");
+
+    let plan = Arg::new("plan").long("plan").help(
+        "Only list the items that would be removed, least recently used first, with the cumulative size",
+    );
 
     let trim = App::new("trim")
         .about("trim old items from the cache until maximum cache size limit is reached")
+        .after_help(&*trim_examples)
         .arg(&size_limit)
-        .arg(&dry_run);
+        .arg(&keep_newer_than)
+        .arg(&dry_run)
+        .arg(&plan)
+        .arg(&output_format)
+        .arg(&only_registry)
+        .arg(&exclude_registry)
+        .arg(&keep)
+        .arg(&removal_stats)
+        .arg(&quiet);
 
     // </trim>
-    let toolchain = App::new("toolchain").about("print stats on installed toolchains");
+    let breakdown = Arg::new("breakdown")
+        .long("breakdown")
+        .help("show the size of each component of the toolchains");
+
+    let remove_toolchain = Arg::new("remove-toolchain")
+        .long("remove-toolchain")
+        .help("uninstall a toolchain, through rustup if it is installed")
+        .takes_value(true)
+        .value_name("TOOLCHAIN");
+
+    let remove_docs = Arg::new("remove-docs")
+        .long("remove-docs")
+        .help("remove the offline documentation (rust-docs) of all toolchains, through rustup if it is installed");
+
+    let toolchain_yes = Arg::new("yes")
+        .long("yes")
+        .help("Do not ask for confirmation before removing a toolchain or the docs");
+
+    let toolchain = App::new("toolchain")
+        .about("print stats on installed toolchains")
+        .after_help(&*toolchain_examples)
+        .arg(&breakdown)
+        .arg(&remove_toolchain)
+        .arg(&remove_docs)
+        .arg(&toolchain_yes)
+        .arg(&dry_run);
+
+    // <preheat-install>
+    let preheat_install = App::new("preheat-install")
+        .about("check if a crate can be installed offline with what is in the cache")
+        .after_help(&*preheat_install_examples)
+        .arg(Arg::new("CRATE").required(true))
+        .arg(
+            Arg::new("crate-version")
+                .long("version")
+                .help("version (requirement) of the crate to check")
+                .takes_value(true)
+                .value_name("VERSION"),
+        );
+    // </preheat-install>
+
+    // <compare>
+    let compare = App::new("compare")
+        .about("compare the cache with the one of another cargo home")
+        .after_help(&*compare_examples)
+        .arg(
+            Arg::new("OTHER_CARGO_HOME")
+                .required(true)
+                .help("path of the other cargo home"),
+        )
+        .arg(
+            Arg::new("merge-from")
+                .long("merge-from")
+                .help("copy crate archives and git repos that only exist in the other cargo home"),
+        )
+        .arg(&dry_run);
+    // </compare>
+
+    // <stats>
+    let stats = App::new("stats")
+        .about("print statistics on the cache")
+        .after_help(&*stats_examples)
+        .arg(
+            Arg::new("value")
+                .long("value")
+                .help("estimate the time the cache saves compared to downloading and extracting everything again"),
+        )
+        .arg(
+            Arg::new("bandwidth")
+                .long("bandwidth")
+                .help("download speed in bytes per second to assume for --value, for example '500K' or '10M'")
+                .takes_value(true)
+                .value_name("BANDWIDTH")
+                .requires("value"),
+        )
+        .arg(
+            Arg::new("history")
+                .long("history")
+                .help("show how the size of the cache changed over the last runs, needs \"snapshots\" in the [stats] table of the config file"),
+        )
+        .arg(
+            Arg::new("last")
+                .long("last")
+                .help("number of runs --history shows, 10 by default")
+                .takes_value(true)
+                .value_name("N")
+                .requires("history"),
+        )
+        .arg(
+            Arg::new("days")
+                .long("days")
+                .help("only show the runs of the last N days with --history")
+                .takes_value(true)
+                .value_name("N")
+                .requires("history"),
+        )
+        .arg(
+            Arg::new("sparkline")
+                .long("sparkline")
+                .help("also draw the total size of the runs --history shows as a sparkline")
+                .requires("history"),
+        )
+        .group(
+            ArgGroup::new("view")
+                .args(&["value", "history"])
+                .required(true),
+        );
+    // </stats>
+
+    // <check>
+    let check = App::new("check")
+        .about("fail if the cache is bigger than a limit, without removing anything")
+        .after_help(&*check_examples)
+        .arg(
+            Arg::new("fail-if-above")
+                .long("fail-if-above")
+                .help("exit with 1 if a component is bigger than its limit, for example 'registry=5G,total=20G',\ncomponents: total,bin,registry,registry-index,registry-crate-cache,registry-sources,\ngit,git-db,git-repos")
+                .takes_value(true)
+                .value_name("LIMITS")
+                .env(env::FAIL_IF_ABOVE)
+                .required(true),
+        );
+    // </check>
+
+    // <metrics>
+    let metrics = App::new("metrics")
+        .about("print or push anonymized sizes and counts of the cache")
+        .after_help(&*metrics_examples)
+        .arg(
+            Arg::new("push")
+                .long("push")
+                .help("post the metrics to this http url (or the one configured) instead of printing them")
+                .takes_value(true)
+                .min_values(0)
+                .max_values(1)
+                .multiple_values(false)
+                .value_name("URL"),
+        )
+        .arg(
+            Arg::new("label")
+                .long("label")
+                .help("identify the machine in the metrics, for example 'ci-runner-linux'")
+                .takes_value(true)
+                .value_name("LABEL")
+                .env(env::METRICS_LABEL),
+        )
+        .arg(
+            Arg::new("dry-run")
+                .short('n')
+                .long("dry-run")
+                .help("with --push, print what would be pushed and where instead"),
+        );
+    // </metrics>
+
+    // <migrate-registry>
+    let migrate_registry = App::new("migrate-registry")
+        .about("remove registry directories left behind by older cargo versions")
+        .after_help(&*migrate_registry_examples)
+        .arg(&dry_run);
+    // </migrate-registry>
+
+    // <dedupe>
+    let dedupe = App::new("dedupe")
+        .about("replace identical files of the crate sources with links to one of them")
+        .after_help(&*dedupe_examples)
+        .arg(
+            Arg::new("reflink")
+                .long("reflink")
+                .help("Use reflinks instead of hardlinks: copies that share their data until one of them is modified. Only Btrfs, XFS and APFS support them"),
+        )
+        .arg(&dry_run);
+    // </dedupe>
+
+    // <maintain>
+    let maintain = App::new("maintain")
+        .about("clean and gc the cache when a policy says so, cheap enough for cron")
+        .after_help(&*maintain_examples)
+        .arg(
+            Arg::new("autoclean-above")
+                .long("autoclean-above")
+                .help("run --autoclean if the cache is bigger than this, for example '10G'")
+                .takes_value(true)
+                .value_name("SIZE"),
+        )
+        .arg(
+            Arg::new("gc-every-days")
+                .long("gc-every-days")
+                .help("run --gc if the last one was at least this many days ago")
+                .takes_value(true)
+                .value_name("DAYS"),
+        )
+        .arg(
+            Arg::new("check-every-hours")
+                .long("check-every-hours")
+                .help("only look at the size of the cache this often (default: 24)")
+                .takes_value(true)
+                .value_name("HOURS"),
+        )
+        .arg(
+            Arg::new("force")
+                .long("force")
+                .help("ignore when things were last done, check and gc now"),
+        )
+        .arg(&allow_cross_device)
+        .arg(&dry_run);
+    // </maintain>
+
+    // <savings>
+    let savings = App::new("savings")
+        .about("show how much space cargo-cache has freed so far")
+        .after_help(&*savings_examples);
+    // </savings>
+
+    // <log>
+    let log = App::new("log")
+        .about("list what the past runs removed from the cache")
+        .after_help(&*log_examples)
+        .arg(
+            Arg::new("last")
+                .long("last")
+                .help("number of runs to show, 10 by default")
+                .takes_value(true)
+                .value_name("N"),
+        )
+        .arg(
+            Arg::new("paths")
+                .long("paths")
+                .help("list every removed item with its size"),
+        );
+    // </log>
+
+    // <projects>
+    let project_path = Arg::new("PATH")
+        .help("the project directory, its Cargo.toml or its Cargo.lock")
+        .required(true);
+
+    let projects = App::new("projects")
+        .about("register projects whose crates clean-unref --all-projects keeps")
+        .after_help(&*projects_examples)
+        .subcommand(
+            App::new("add")
+                .about("register a project")
+                .after_help(&*projects_examples)
+                .arg(&project_path),
+        )
+        .subcommand(
+            App::new("remove")
+                .about("forget a registered project")
+                .after_help(&*projects_examples)
+                .arg(&project_path),
+        );
+    // </projects>
+
+    // <self>
+    let self_subcmd = App::new("self")
+        .about("check for a newer release of cargo-cache")
+        .after_help(&*self_examples)
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            App::new("check-update")
+                .about("compare the installed version with the latest release on crates.io")
+                .after_help(&*self_examples)
+                .arg(
+                    Arg::new("online")
+                        .long("online")
+                        .help("ask crates.io instead of only checking the local registry index"),
+                ),
+        );
+    // </self>
+
+    // <doctor>
+    let doctor = App::new("doctor")
+        .about("find entries of the cache that are not where cargo expects them")
+        .after_help(&*doctor_examples)
+        .arg(
+            Arg::new("quarantine")
+                .long("quarantine")
+                .help("move misplaced entries and the crate sources and git checkouts with problems into cargo-cache-quarantine inside the cargo home")
+                .conflicts_with("remove"),
+        )
+        .arg(
+            Arg::new("remove")
+                .long("remove")
+                .help("remove misplaced entries and the crate sources and git checkouts with problems, cargo extracts those again"),
+        )
+        .arg(&dry_run);
+    // </doctor>
+
+    // <ui>
+    let ui = App::new("ui")
+        .about("browse the cache interactively and remove single crates")
+        .after_help(&*ui_examples)
+        .arg(&dry_run);
+    // </ui>
+
+    // <selftest-sizes>
+    let selftest_sizes = App::new("selftest-sizes")
+        .about("cross-check the calculated sizes with a full scan of the cache")
+        .after_help(&*selftest_sizes_examples);
+    // </selftest-sizes>
+
+    // <backup>
+    let backup = App::new("backup")
+        .about("archive components of the cache into a .tar.gz, .tar.zst or .tar file")
+        .after_help(&*backup_examples)
+        .arg(
+            Arg::new("output")
+                .long("output")
+                .short('o')
+                .takes_value(true)
+                .value_name("FILE")
+                .required(true)
+                .help("the archive to write, .tar.gz, .tgz, .tar.zst, .tzst or .tar"),
+        )
+        .arg(
+            Arg::new("components")
+                .long("components")
+                .takes_value(true)
+                .value_name("comp1,comp2")
+                .help("the components to archive, default: registry-crate-cache,registry-index,git-db"),
+        );
+    // </backup>
+
+    // <restore>
+    let restore = App::new("restore")
+        .about("restore an archive written by backup, existing files are kept")
+        .after_help(&*restore_examples)
+        .arg(
+            Arg::new("ARCHIVE")
+                .required(true)
+                .help("the archive to restore"),
+        )
+        .arg(&dry_run);
+    // </restore>
+
+    // <git>
+    let git = App::new("git")
+        .about("work with the cached git repos")
+        .after_help(&*git_examples)
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            App::new("export")
+                .about("clone a cached git repo into a directory, without network access")
+                .after_help(&*git_examples)
+                .arg(
+                    Arg::new("REPO")
+                        .help("name of the repo in git/db, with or without the hash")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("DIR")
+                        .help("directory to clone into, must not exist or be empty")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("rev")
+                        .long("rev")
+                        .help("check out this branch, tag or commit instead of the newest commit")
+                        .takes_value(true)
+                        .value_name("REV"),
+                ),
+        )
+        .subcommand(
+            App::new("list")
+                .about("list the checked out revisions of a cached git repo")
+                .after_help(&*git_examples)
+                .arg(
+                    Arg::new("REPO")
+                        .help("name of the repo in git/checkouts, with or without the hash")
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            App::new("rm-checkout")
+                .about("remove the checkout of one revision of a cached git repo")
+                .after_help(&*git_examples)
+                .arg(
+                    Arg::new("REPO")
+                        .help("name of the repo in git/checkouts, with or without the hash")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("REV")
+                        .help("the revision to remove, a prefix is enough if it is unique")
+                        .required(true),
+                )
+                .arg(&dry_run),
+        );
+    // </git>
+
+    // <inspect>
+    let inspect = App::new("inspect")
+        .about("list the largest files of a cached crate")
+        .after_help(&*inspect_examples)
+        .arg(Arg::new("CRATE").required(true))
+        .arg(Arg::new("VERSION").help("version of the crate, the newest cached one if omitted"))
+        .arg(
+            Arg::new("limit")
+                .long("limit")
+                .help("number of files to list, 20 by default")
+                .takes_value(true)
+                .value_name("N"),
+        );
+    // </inspect>
+
+    // <remove>
+    let remove =
+        App::new("remove")
+            .about("remove all cached archives and sources of a crate")
+            .after_help(&*remove_examples)
+            .arg(
+                Arg::new("CRATE").required(true).help(
+                    "<crate> or <crate>@<version>, all cached versions if no version is given",
+                ),
+            )
+            .arg(Arg::new("git").long("git").help(
+                "also remove the bare repos and checkouts of git dependencies with this name",
+            ))
+            .arg(&also_sccache)
+            .arg(&dry_run);
+    // </remove>
+
+    // <examples>
+    let examples = App::new("examples").about("print example invocations of all subcommands");
+    // </examples>
 
     // <verify>
 
@@ -390,10 +2483,30 @@ pub(crate) fn gen_clap() -> ArgMatches {
         .short('c')
         .help("automatically remove corrupted cache entries");
 
+    let report = Arg::new("report")
+        .long("report")
+        .help("write the mismatching files of each crate to this file as json")
+        .takes_value(true)
+        .value_name("FILE");
+
+    let content = Arg::new("content")
+        .long("content")
+        .help("compare the contents of the files with the archive, not only their sizes (slow)");
+
+    let checksums = Arg::new("checksums")
+        .long("checksums")
+        .conflicts_with("content")
+        .help("check the .crate archives against the checksums of the registry index instead");
+
     let verify = App::new("verify")
         .about("verify crate sources")
+        .after_help(&*verify_examples)
         .arg(&dry_run)
-        .arg(&clean_corrupted);
+        .arg(&clean_corrupted)
+        .arg(&report)
+        .arg(&content)
+        .arg(&checksums)
+        .arg(&quiet);
 
     // </verify>
 
@@ -420,20 +2533,75 @@ pub(crate) fn gen_clap() -> ArgMatches {
         .subcommand(sccache_short.clone())
         .subcommand(clean_unref.clone())
         .subcommand(toolchain.clone())
+        .subcommand(preheat_install.clone())
+        .subcommand(compare.clone())
+        .subcommand(examples.clone())
+        .subcommand(stats.clone())
+        .subcommand(check.clone())
+        .subcommand(metrics.clone())
+        .subcommand(migrate_registry.clone())
+        .subcommand(dedupe.clone())
+        .subcommand(maintain.clone())
+        .subcommand(savings.clone())
+        .subcommand(log.clone())
+        .subcommand(projects.clone())
+        .subcommand(self_subcmd.clone())
+        .subcommand(git.clone())
+        .subcommand(doctor.clone())
+        .subcommand(selftest_sizes.clone())
+        .subcommand(backup.clone())
+        .subcommand(restore.clone())
+        .subcommand(inspect.clone())
+        .subcommand(remove.clone())
         .subcommand(trim.clone())
+        .subcommand(ui.clone())
         .subcommand(verify.clone())
         .arg(&list_dirs)
+        .arg(&estimate)
         .arg(&remove_dir)
         .arg(&gc_repos)
         .arg(&fsck_repos)
         .arg(&info)
         .arg(&keep_duplicate_crates)
+        .arg(&delete_empty_registries)
+        .arg(&keep_by)
         .arg(&dry_run)
         .arg(&autoclean)
         .arg(&autoclean_expensive)
         .arg(&list_top_cache_items)
         .arg(&remove_if_younger)
         .arg(&remove_if_older)
+        .arg(&allow_cross_device)
+        .arg(&use_system_trash)
+        .arg(&keep_going)
+        .arg(&i_know_what_i_am_doing)
+        .arg(&removal_stats)
+        .arg(&quiet)
+        .arg(&yes)
+        .arg(&preserve_modified)
+        .arg(&keep_days)
+        .arg(&keep)
+        .arg(&offline)
+        .arg(&only_registry)
+        .arg(&exclude_registry)
+        .arg(&components)
+        .arg(&no_default_scan)
+        .arg(&no_size_cache)
+        .arg(&refresh_size_cache)
+        .arg(&no_wait)
+        .arg(&wait)
+        .arg(&retries)
+        .arg(&retry_backoff)
+        .arg(&max_scan_depth)
+        .arg(&cargo_home)
+        .arg(&approve_with)
+        .arg(&hyperlinks)
+        .arg(&assume_tty)
+        .arg(&no_tty)
+        .arg(&output_format)
+        .arg(&output_schema)
+        .arg(&lang)
+        .arg(&json_errors)
         .arg(&debug)
         .setting(AppSettings::Hidden);
 
@@ -455,20 +2623,75 @@ pub(crate) fn gen_clap() -> ArgMatches {
         .subcommand(sccache_short)
         .subcommand(clean_unref)
         .subcommand(toolchain)
+        .subcommand(preheat_install)
+        .subcommand(compare)
+        .subcommand(examples)
+        .subcommand(stats)
+        .subcommand(check)
+        .subcommand(metrics)
+        .subcommand(migrate_registry)
+        .subcommand(dedupe)
+        .subcommand(maintain)
+        .subcommand(savings)
+        .subcommand(log)
+        .subcommand(projects)
+        .subcommand(self_subcmd)
+        .subcommand(git)
+        .subcommand(doctor)
+        .subcommand(selftest_sizes)
+        .subcommand(backup)
+        .subcommand(restore)
+        .subcommand(inspect)
+        .subcommand(remove)
         .subcommand(trim)
+        .subcommand(ui)
         .subcommand(verify)
         .arg(&list_dirs)
+        .arg(&estimate)
         .arg(&remove_dir)
         .arg(&gc_repos)
         .arg(&fsck_repos)
         .arg(&info)
         .arg(&keep_duplicate_crates)
+        .arg(&delete_empty_registries)
+        .arg(&keep_by)
         .arg(&dry_run)
         .arg(&autoclean)
         .arg(&autoclean_expensive)
         .arg(&list_top_cache_items)
         .arg(&remove_if_younger)
         .arg(&remove_if_older)
+        .arg(&allow_cross_device)
+        .arg(&use_system_trash)
+        .arg(&keep_going)
+        .arg(&i_know_what_i_am_doing)
+        .arg(&removal_stats)
+        .arg(&quiet)
+        .arg(&yes)
+        .arg(&preserve_modified)
+        .arg(&keep_days)
+        .arg(&keep)
+        .arg(&offline)
+        .arg(&only_registry)
+        .arg(&exclude_registry)
+        .arg(&components)
+        .arg(&no_default_scan)
+        .arg(&no_size_cache)
+        .arg(&refresh_size_cache)
+        .arg(&no_wait)
+        .arg(&wait)
+        .arg(&retries)
+        .arg(&retry_backoff)
+        .arg(&max_scan_depth)
+        .arg(&cargo_home)
+        .arg(&approve_with)
+        .arg(&hyperlinks)
+        .arg(&assume_tty)
+        .arg(&no_tty)
+        .arg(&output_format)
+        .arg(&output_schema)
+        .arg(&lang)
+        .arg(&json_errors)
         .arg(&debug)
         .get_matches()
 }
@@ -490,7 +2713,8 @@ mod clitests {
 
         let mut help_desired = rustc_tools_util::get_version_info!().to_string();
         help_desired.push_str(
-            "\nmatthiaskrgr
+            "
+matthiaskrgr
 Manage cargo cache
 
 USAGE:
@@ -500,9 +2724,39 @@ OPTIONS:
     -a, --autoclean
             Removes crate source checkouts and git repo checkouts
 
+        --allow-cross-device
+            Allow removing directories that are located on a different device than the cargo home
+
+        --approve-with <COMMAND>
+            Pass the items trim, clean-unref and --remove-if-older/younger-than would remove as json
+            to this command and only remove the ones it approves
+
+        --assume-tty
+            Behave as if running in a terminal: show progress, ask for confirmation and print
+            hyperlinks (--hyperlinks auto)
+
+        --cargo-home <PATH>
+            Use this directory as the cargo home instead of $CARGO_HOME, for example a mounted CI
+            cache, works with all subcommands. Pass it several times or as a quoted glob
+            (\"/srv/cargo/*\") to work on several cargo homes [aliases: cache-dir]
+
+        --components <comp1,comp2>
+            Only scan and operate on these components, accepted values: all,git-db,git-repos,
+            registry-sources,registry-crate-cache,registry-index,registry
+
+        --delete-empty-registries
+            Remove the index and the empty directories of registries without crate archives and
+            sources
+
     -e, --autoclean-expensive
             As --autoclean, but also recompresses git repositories
 
+        --estimate
+            Quickly estimate the size of the cache by only looking at a sample of each directory
+
+        --exclude-registry <reg1,reg2>
+            Do not remove items of these registries with --autoclean and trim
+
     -f, --fsck
             Fsck git repositories
 
@@ -512,48 +2766,165 @@ OPTIONS:
     -h, --help
             Print help information
 
+        --hyperlinks <WHEN>
+            Print paths as clickable links in terminals that support it, default: auto [possible
+            values: on, off, auto]
+
+        --i-know-what-i-am-doing
+            Remove things even if the cargo home is \"/\", the home directory or does not look like a
+            cargo home
+
     -i, --info
             Print information cache directories, what they are for and what can be safely deleted
 
+        --json-errors
+            Print errors as json with a stable error code for scripts, implied by --output-format
+            json
+
     -k, --keep-duplicate-crates <N>
             Remove all but N versions of crate in the source archives directory
 
+        --keep <crate1,crate2>
+            Never remove the archives and sources of these crates with --autoclean, trim,
+            --keep-duplicate-crates and --remove-if-*-than
+
+        --keep-by <ORDER>
+            Which versions --keep-duplicate-crates keeps: the highest versions or the most recently
+            downloaded ones, default: version [possible values: version, mtime]
+
+        --keep-days <N>
+            Keep crate sources and git checkouts that were used in the last N days with --autoclean,
+            default: 0
+
+        --keep-going
+            Remove everything that can be removed even if some entries can not be removed by the
+            current user
+
     -l, --list-dirs
             List all found directory paths
 
+        --lang <LANG>
+            Print the summary and --info in this language, needs a message catalog <LANG>.messages
+            [env: CARGO_CACHE_LANG=]
+
+        --max-scan-depth <N>
+            Warn about and skip directories nested deeper than N levels, default: 100
+
     -n, --dry-run
             Don't remove anything, just pretend
 
+        --no-default-scan
+            Do not scan the whole cache, only the components given via --components
+
+        --no-size-cache
+            Scan all crate sources and git checkouts instead of reusing the sizes of unchanged ones
+            from the last run
+
+        --no-tty
+            Behave as if not running in a terminal: no progress, no confirmation prompts and no
+            hyperlinks (--hyperlinks auto)
+
+        --no-wait
+            Fail instead of waiting if another cargo-cache is modifying the cache
+
     -o, --remove-if-older-than <date>
             Removes items older than specified date: YYYY.MM.DD or HH:MM:SS
 
+        --offline
+            Never access the network, even if a subcommand is asked to (also CARGO_NET_OFFLINE and
+            net.offline)
+
+        --only-registry <reg1,reg2>
+            Only remove items of these registries with --autoclean and trim, for example: crates.io
+            or a name from [registries] in the cargo config
+
+        --output-format <FORMAT>
+            Print the summary, --info, registry and --top-cache-items as json for scripts, default:
+            table [possible values: table, json]
+
+        --output-schema
+            Print the JSON Schema of everything --output-format json and --json-errors print
+
+        --preserve-modified
+            Keep crate sources that were modified after they were extracted with --autoclean
+
+    -q, --quiet
+            Do not show the progress of --gc, trim and verify
+
     -r, --remove-dir <dir1,dir2,dir3>
             Remove directories, accepted values: all,git-db,git-repos,
-            registry-sources,registry-crate-cache,registry-index,registry
+            registry-sources,registry-crate-cache,registry-index,registry-index-sparse,registry,
+            a single registry: registry-crate-cache:<registry>
+
+        --refresh-size-cache
+            Scan all crate sources and git checkouts and remember their sizes for the next runs
+
+        --retries <N>
+            Retry filesystem operations that fail with transient errors (network filesystems) N
+            times, default: 3
+
+        --retry-backoff <MILLISECONDS>
+            Wait this many milliseconds before the first retry, doubled for every further retry,
+            default: 100
+
+        --stats
+            Print how many files and bytes per second were removed, slow removals hint at a virus
+            scanner or network file system
 
     -t, --top-cache-items <N>
             List the top N items taking most space in the cache
 
+        --use-system-trash
+            Move removed items to the trash or recycle bin of the desktop instead of deleting them,
+            the space is only freed once the trash is emptied. Without a desktop, items are deleted
+
     -V, --version
             Print version information
 
+        --wait <SECONDS>
+            Wait at most this many seconds for another cargo-cache that is modifying the cache
+
     -y, --remove-if-younger-than <date>
             Removes items younger than the specified date: YYYY.MM.DD or HH:MM:SS
 
+        --yes
+            Do not ask for confirmation before --autoclean removes a lot of data
+
 SUBCOMMANDS:
-    clean-unref    remove crates that are not referenced in a Cargo.toml from the cache
-    help           Print this message or the help of the given subcommand(s)
-    l              check local build cache (target) of a rust project
-    local          check local build cache (target) of a rust project
-    q              run a query
-    query          run a query
-    r              query each package registry separately
-    registry       query each package registry separately
-    sc             gather stats on a local sccache cache
-    sccache        gather stats on a local sccache cache
-    toolchain      print stats on installed toolchains
-    trim           trim old items from the cache until maximum cache size limit is reached
-    verify         verify crate sources\n",
+    backup              archive components of the cache into a .tar.gz, .tar.zst or .tar file
+    check               fail if the cache is bigger than a limit, without removing anything
+    clean-unref         remove crates that are not referenced in a Cargo.toml from the cache
+    compare             compare the cache with the one of another cargo home
+    dedupe              replace identical files of the crate sources with links to one of them
+    doctor              find entries of the cache that are not where cargo expects them
+    examples            print example invocations of all subcommands
+    git                 work with the cached git repos
+    help                Print this message or the help of the given subcommand(s)
+    inspect             list the largest files of a cached crate
+    l                   check local build cache (target) of a rust project
+    local               check local build cache (target) of a rust project
+    log                 list what the past runs removed from the cache
+    maintain            clean and gc the cache when a policy says so, cheap enough for cron
+    metrics             print or push anonymized sizes and counts of the cache
+    migrate-registry    remove registry directories left behind by older cargo versions
+    preheat-install     check if a crate can be installed offline with what is in the cache
+    projects            register projects whose crates clean-unref --all-projects keeps
+    q                   run a query
+    query               run a query
+    r                   query each package registry separately
+    registry            query each package registry separately
+    remove              remove all cached archives and sources of a crate
+    restore             restore an archive written by backup, existing files are kept
+    savings             show how much space cargo-cache has freed so far
+    sc                  gather stats on a local sccache cache
+    sccache             gather stats on a local sccache cache
+    self                check for a newer release of cargo-cache
+    selftest-sizes      cross-check the calculated sizes with a full scan of the cache
+    stats               print statistics on the cache
+    toolchain           print stats on installed toolchains
+    trim                trim old items from the cache until maximum cache size limit is reached
+    ui                  browse the cache interactively and remove single crates
+    verify              verify crate sources\n",
         );
         assert_eq!(help_desired, help_real);
     }
@@ -566,7 +2937,8 @@ SUBCOMMANDS:
 
         let mut help_desired = rustc_tools_util::get_version_info!().to_string();
         help_desired.push_str(
-            "\nmatthiaskrgr
+            "
+matthiaskrgr
 Manage cargo cache
 
 USAGE:
@@ -576,9 +2948,39 @@ OPTIONS:
     -a, --autoclean
             Removes crate source checkouts and git repo checkouts
 
+        --allow-cross-device
+            Allow removing directories that are located on a different device than the cargo home
+
+        --approve-with <COMMAND>
+            Pass the items trim, clean-unref and --remove-if-older/younger-than would remove as json
+            to this command and only remove the ones it approves
+
+        --assume-tty
+            Behave as if running in a terminal: show progress, ask for confirmation and print
+            hyperlinks (--hyperlinks auto)
+
+        --cargo-home <PATH>
+            Use this directory as the cargo home instead of $CARGO_HOME, for example a mounted CI
+            cache, works with all subcommands. Pass it several times or as a quoted glob
+            (\"/srv/cargo/*\") to work on several cargo homes [aliases: cache-dir]
+
+        --components <comp1,comp2>
+            Only scan and operate on these components, accepted values: all,git-db,git-repos,
+            registry-sources,registry-crate-cache,registry-index,registry
+
+        --delete-empty-registries
+            Remove the index and the empty directories of registries without crate archives and
+            sources
+
     -e, --autoclean-expensive
             As --autoclean, but also recompresses git repositories
 
+        --estimate
+            Quickly estimate the size of the cache by only looking at a sample of each directory
+
+        --exclude-registry <reg1,reg2>
+            Do not remove items of these registries with --autoclean and trim
+
     -f, --fsck
             Fsck git repositories
 
@@ -588,48 +2990,165 @@ OPTIONS:
     -h, --help
             Print help information
 
+        --hyperlinks <WHEN>
+            Print paths as clickable links in terminals that support it, default: auto [possible
+            values: on, off, auto]
+
+        --i-know-what-i-am-doing
+            Remove things even if the cargo home is \"/\", the home directory or does not look like a
+            cargo home
+
     -i, --info
             Print information cache directories, what they are for and what can be safely deleted
 
+        --json-errors
+            Print errors as json with a stable error code for scripts, implied by --output-format
+            json
+
     -k, --keep-duplicate-crates <N>
             Remove all but N versions of crate in the source archives directory
 
+        --keep <crate1,crate2>
+            Never remove the archives and sources of these crates with --autoclean, trim,
+            --keep-duplicate-crates and --remove-if-*-than
+
+        --keep-by <ORDER>
+            Which versions --keep-duplicate-crates keeps: the highest versions or the most recently
+            downloaded ones, default: version [possible values: version, mtime]
+
+        --keep-days <N>
+            Keep crate sources and git checkouts that were used in the last N days with --autoclean,
+            default: 0
+
+        --keep-going
+            Remove everything that can be removed even if some entries can not be removed by the
+            current user
+
     -l, --list-dirs
             List all found directory paths
 
+        --lang <LANG>
+            Print the summary and --info in this language, needs a message catalog <LANG>.messages
+            [env: CARGO_CACHE_LANG=]
+
+        --max-scan-depth <N>
+            Warn about and skip directories nested deeper than N levels, default: 100
+
     -n, --dry-run
             Don't remove anything, just pretend
 
+        --no-default-scan
+            Do not scan the whole cache, only the components given via --components
+
+        --no-size-cache
+            Scan all crate sources and git checkouts instead of reusing the sizes of unchanged ones
+            from the last run
+
+        --no-tty
+            Behave as if not running in a terminal: no progress, no confirmation prompts and no
+            hyperlinks (--hyperlinks auto)
+
+        --no-wait
+            Fail instead of waiting if another cargo-cache is modifying the cache
+
     -o, --remove-if-older-than <date>
             Removes items older than specified date: YYYY.MM.DD or HH:MM:SS
 
+        --offline
+            Never access the network, even if a subcommand is asked to (also CARGO_NET_OFFLINE and
+            net.offline)
+
+        --only-registry <reg1,reg2>
+            Only remove items of these registries with --autoclean and trim, for example: crates.io
+            or a name from [registries] in the cargo config
+
+        --output-format <FORMAT>
+            Print the summary, --info, registry and --top-cache-items as json for scripts, default:
+            table [possible values: table, json]
+
+        --output-schema
+            Print the JSON Schema of everything --output-format json and --json-errors print
+
+        --preserve-modified
+            Keep crate sources that were modified after they were extracted with --autoclean
+
+    -q, --quiet
+            Do not show the progress of --gc, trim and verify
+
     -r, --remove-dir <dir1,dir2,dir3>
             Remove directories, accepted values: all,git-db,git-repos,
-            registry-sources,registry-crate-cache,registry-index,registry
+            registry-sources,registry-crate-cache,registry-index,registry-index-sparse,registry,
+            a single registry: registry-crate-cache:<registry>
+
+        --refresh-size-cache
+            Scan all crate sources and git checkouts and remember their sizes for the next runs
+
+        --retries <N>
+            Retry filesystem operations that fail with transient errors (network filesystems) N
+            times, default: 3
+
+        --retry-backoff <MILLISECONDS>
+            Wait this many milliseconds before the first retry, doubled for every further retry,
+            default: 100
+
+        --stats
+            Print how many files and bytes per second were removed, slow removals hint at a virus
+            scanner or network file system
 
     -t, --top-cache-items <N>
             List the top N items taking most space in the cache
 
+        --use-system-trash
+            Move removed items to the trash or recycle bin of the desktop instead of deleting them,
+            the space is only freed once the trash is emptied. Without a desktop, items are deleted
+
     -V, --version
             Print version information
 
+        --wait <SECONDS>
+            Wait at most this many seconds for another cargo-cache that is modifying the cache
+
     -y, --remove-if-younger-than <date>
             Removes items younger than the specified date: YYYY.MM.DD or HH:MM:SS
 
+        --yes
+            Do not ask for confirmation before --autoclean removes a lot of data
+
 SUBCOMMANDS:
-    clean-unref    remove crates that are not referenced in a Cargo.toml from the cache
-    help           Print this message or the help of the given subcommand(s)
-    l              check local build cache (target) of a rust project
-    local          check local build cache (target) of a rust project
-    q              run a query
-    query          run a query
-    r              query each package registry separately
-    registry       query each package registry separately
-    sc             gather stats on a local sccache cache
-    sccache        gather stats on a local sccache cache
-    toolchain      print stats on installed toolchains
-    trim           trim old items from the cache until maximum cache size limit is reached
-    verify         verify crate sources\n",
+    backup              archive components of the cache into a .tar.gz, .tar.zst or .tar file
+    check               fail if the cache is bigger than a limit, without removing anything
+    clean-unref         remove crates that are not referenced in a Cargo.toml from the cache
+    compare             compare the cache with the one of another cargo home
+    dedupe              replace identical files of the crate sources with links to one of them
+    doctor              find entries of the cache that are not where cargo expects them
+    examples            print example invocations of all subcommands
+    git                 work with the cached git repos
+    help                Print this message or the help of the given subcommand(s)
+    inspect             list the largest files of a cached crate
+    l                   check local build cache (target) of a rust project
+    local               check local build cache (target) of a rust project
+    log                 list what the past runs removed from the cache
+    maintain            clean and gc the cache when a policy says so, cheap enough for cron
+    metrics             print or push anonymized sizes and counts of the cache
+    migrate-registry    remove registry directories left behind by older cargo versions
+    preheat-install     check if a crate can be installed offline with what is in the cache
+    projects            register projects whose crates clean-unref --all-projects keeps
+    q                   run a query
+    query               run a query
+    r                   query each package registry separately
+    registry            query each package registry separately
+    remove              remove all cached archives and sources of a crate
+    restore             restore an archive written by backup, existing files are kept
+    savings             show how much space cargo-cache has freed so far
+    sc                  gather stats on a local sccache cache
+    sccache             gather stats on a local sccache cache
+    self                check for a newer release of cargo-cache
+    selftest-sizes      cross-check the calculated sizes with a full scan of the cache
+    stats               print statistics on the cache
+    toolchain           print stats on installed toolchains
+    trim                trim old items from the cache until maximum cache size limit is reached
+    ui                  browse the cache interactively and remove single crates
+    verify              verify crate sources\n",
         );
 
         assert_eq!(help_desired, help_real);
@@ -652,24 +3171,158 @@ SUBCOMMANDS:
 
         let mut help_desired = String::new();
         help_desired.push_str(
-"cargo-cache-query 
+            "cargo-cache-query 
 run a query
 
 USAGE:
-    cargo cache query [OPTIONS] [QUERY]
+    cargo cache query [OPTIONS] [QUERY]...
 
 ARGS:
-    <QUERY>    
+    <QUERY>...    regular expressions to match the names of the cache items against
 
 OPTIONS:
-    -h, --help              Print help information
-        --human-readable    print sizes in human readable format
-    -s, --sort-by <sort>    sort files alphabetically or by file size [possible values: size, name]\n"
+        --also-sccache          also remove the compiled objects of the crates from the sccache
+                                cache
+        --cargo-home <PATH>     Use this directory as the cargo home instead of $CARGO_HOME, for
+                                example a mounted CI cache, works with all subcommands. Pass it
+                                several times or as a quoted glob (\"/srv/cargo/*\") to work on
+                                several cargo homes [aliases: cache-dir]
+        --delete                remove the listed items
+    -h, --help                  Print help information
+        --human-readable        print sizes in human readable format
+        --hyperlinks <WHEN>     Print paths as clickable links in terminals that support it,
+                                default: auto [possible values: on, off, auto]
+        --intersect             list items matching all of the patterns
+        --larger-than <SIZE>    only list items bigger than this, for example 10M
+    -n, --dry-run               Don't remove anything, just pretend
+        --not <PATTERN>         never list items matching this pattern, can be passed several times
+        --older-than <AGE>      only list items last used before this date (YYYY.MM.DD) or this long
+                                ago (30d, 6w, 3m, 1y)
+    -s, --sort-by <sort>        sort files alphabetically or by file size [possible values: size,
+                                name]
+        --union                 list items matching any of the patterns, the default
+        --use-system-trash      Move removed items to the trash or recycle bin of the desktop
+                                instead of deleting them, the space is only freed once the trash is
+                                emptied. Without a desktop, items are deleted
+
+EXAMPLES:
+    cargo cache query serde
+        list all cache items whose names contain \"serde\"
+    cargo cache query '^tokio-' --sort-by size --human-readable
+        list all tokio-* items, largest first, with human readable sizes
+    cargo cache query '^serde' '^tokio' --not json
+        list all serde* and tokio* items except the json ones
+    cargo cache query tokio serde --intersect
+        list the items whose names contain both \"tokio\" and \"serde\"
+    cargo cache query 'serde.*' --larger-than 10M --older-than 2023.01.01 --delete
+        remove all serde items bigger than 10 MB that were not used since 2023\n",
         );
 
         assert_eq!(help_desired, help_real);
     }
 
+    #[test]
+    fn conflicting_operations() {
+        use super::check_conflicts;
+        use crate::library::Error;
+
+        assert!(check_conflicts(&[], None).is_ok());
+        assert!(check_conflicts(&[], Some("trim")).is_ok());
+        assert!(check_conflicts(&["autoclean"], None).is_ok());
+        // documented compositions
+        assert!(check_conflicts(&["gc-repos", "autoclean"], None).is_ok());
+        assert!(check_conflicts(&["remove-dir", "remove-if-older-than"], None).is_ok());
+        assert!(check_conflicts(
+            &[
+                "remove-dir",
+                "remove-if-older-than",
+                "remove-if-younger-than"
+            ],
+            None
+        )
+        .is_ok());
+
+        match check_conflicts(&["remove-dir", "autoclean", "top-cache-items"], None) {
+            Err(Error::ConflictingOperations(operations)) => assert_eq!(
+                operations,
+                vec!["--autoclean", "--remove-dir", "--top-cache-items"]
+            ),
+            other => panic!("expected a conflict, got {other:?}"),
+        }
+        match check_conflicts(&["autoclean"], Some("trim")) {
+            Err(Error::ConflictingOperations(operations)) => {
+                assert_eq!(operations, vec!["--autoclean", "the \"trim\" subcommand"]);
+            }
+            other => panic!("expected a conflict, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn output_formats() {
+        use super::CargoCacheCommands;
+        use crate::library::{Error, OutputFormat};
+
+        assert_eq!(OutputFormat::new(None), OutputFormat::Table);
+        assert_eq!(OutputFormat::new(Some("table")), OutputFormat::Table);
+        assert_eq!(OutputFormat::new(Some("json")), OutputFormat::Json);
+
+        // tables work everywhere
+        assert!(CargoCacheCommands::Version
+            .check_output_format(OutputFormat::Table)
+            .is_ok());
+        if cfg!(feature = "json") {
+            assert!(CargoCacheCommands::DefaultSummary
+                .check_output_format(OutputFormat::Json)
+                .is_ok());
+            assert!(CargoCacheCommands::Info
+                .check_output_format(OutputFormat::Json)
+                .is_ok());
+            match CargoCacheCommands::Version.check_output_format(OutputFormat::Json) {
+                Err(Error::OutputFormatUnsupported(command)) => assert_eq!(command, "version"),
+                other => panic!("expected an unsupported output format, got {other:?}"),
+            }
+        } else {
+            assert!(CargoCacheCommands::DefaultSummary
+                .check_output_format(OutputFormat::Json)
+                .is_err());
+        }
+    }
+
+    #[test]
+    fn every_subcommand_has_examples() {
+        let examples = Command::new(bin_path())
+            .arg("cache")
+            .arg("examples")
+            .output()
+            .unwrap()
+            .stdout;
+        let examples = String::from_utf8_lossy(&examples).into_owned();
+
+        for (subcommand, subcommand_examples) in super::EXAMPLES {
+            assert!(
+                !subcommand_examples.is_empty(),
+                "{subcommand} has no examples"
+            );
+            assert!(examples.contains(&format!("cargo cache {subcommand}:\n")));
+
+            let help = Command::new(bin_path())
+                .arg("cache")
+                .arg(subcommand)
+                .arg("--help")
+                .output()
+                .unwrap()
+                .stdout;
+            let help = String::from_utf8_lossy(&help).into_owned();
+            assert!(
+                help.contains(&format!(
+                    "EXAMPLES:\n    {}",
+                    subcommand_examples[0].command
+                )),
+                "no examples in the help of {subcommand}"
+            );
+        }
+    }
+
     #[test]
     fn all_versions_are_equal() {
         let v1 = Command::new(bin_path()).arg("-V").output().unwrap().stdout;
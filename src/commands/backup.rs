@@ -0,0 +1,426 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// "cargo cache backup" and "cargo cache restore" commands
+// Put components of the cache into a single .tar.gz (or .tar.zst or plain .tar) to seed a CI runner or move a
+// warm cache to another machine, and unpack such an archive into a cargo home again.
+// Paths inside the archive are relative to the cargo home ("registry/cache/<registry>/foo.crate"),
+// so an archive can be restored into a cargo home at any location. The first entry is a small
+// marker file, archives that do not start with it were not written by us and are refused.
+// Restoring never overwrites anything that already exists in the cargo home.
+
+use std::fs;
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{self, Path, PathBuf};
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use humansize::{FormatSize, DECIMAL};
+
+use crate::library::{CargoCachePaths, Component, ComponentSelection, Error};
+use crate::remove::component_path;
+use crate::walk::walk;
+
+/// the first entry of every archive
+const MARKER: &str = "cargo-cache-backup";
+
+/// backed up if --components is not passed: everything that needs to be downloaded again,
+/// cargo recreates extracted sources and checkouts from these by itself
+pub(crate) const DEFAULT_BACKUP_COMPONENTS: &str = "registry-crate-cache,registry-index,git-db";
+
+const ALL_COMPONENTS: [Component; 5] = [
+    Component::RegistryIndex,
+    Component::RegistryCrateCache,
+    Component::RegistrySources,
+    Component::GitDB,
+    Component::GitRepos,
+];
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ArchiveFormat {
+    TarGz,
+    TarZst,
+    Tar,
+}
+
+/// the format of the archive to write is picked by its file extension
+// the name is lowercased already
+#[allow(clippy::case_sensitive_file_extension_comparisons)]
+fn format_of_output(output: &Path) -> Result<ArchiveFormat, Error> {
+    let name = output
+        .file_name()
+        .map(|name| name.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        Ok(ArchiveFormat::TarGz)
+    } else if name.ends_with(".tar.zst") || name.ends_with(".tzst") {
+        Ok(ArchiveFormat::TarZst)
+    } else if name.ends_with(".tar") {
+        Ok(ArchiveFormat::Tar)
+    } else {
+        Err(Error::UnsupportedArchiveFormat(output.to_path_buf()))
+    }
+}
+
+/// the contents of the marker file
+fn marker_contents(components: &[Component]) -> String {
+    format!(
+        "cargo-cache {}\ncomponents: {}\n",
+        env!("CARGO_PKG_VERSION"),
+        components
+            .iter()
+            .map(Component::name)
+            .collect::<Vec<&str>>()
+            .join(",")
+    )
+}
+
+/// number and size of the files that were put into the archive
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct Written {
+    files: u64,
+    size: u64,
+}
+
+/// add the marker and the files and directories of the components to the archive
+fn write_archive<W: Write>(
+    ccp: &CargoCachePaths,
+    components: &[Component],
+    writer: W,
+) -> Result<(W, Written), Error> {
+    let mut builder = tar::Builder::new(writer);
+    // a symlink stays a symlink, we never archive things outside of the cargo home
+    builder.follow_symlinks(false);
+
+    let marker = marker_contents(components);
+    let mut header = tar::Header::new_gnu();
+    header.set_size(marker.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder
+        .append_data(&mut header, MARKER, marker.as_bytes())
+        .map_err(|error| Error::BackupFailed(PathBuf::from(MARKER), error))?;
+
+    let mut written = Written::default();
+    for component in components {
+        let dir = component_path(component, ccp);
+        if !dir.is_dir() {
+            continue;
+        }
+        for entry in walk(dir) {
+            let name = match entry.path().strip_prefix(&ccp.cargo_home) {
+                Ok(name) => name,
+                Err(_) => continue,
+            };
+            builder
+                .append_path_with_name(entry.path(), name)
+                .map_err(|error| Error::BackupFailed(entry.path().to_path_buf(), error))?;
+            if !entry.file_type().is_dir() {
+                written.files += 1;
+                written.size += entry.metadata().map_or(0, |metadata| metadata.len());
+            }
+        }
+    }
+
+    let inner = builder
+        .into_inner()
+        .map_err(|error| Error::BackupFailed(PathBuf::from(MARKER), error))?;
+    Ok((inner, written))
+}
+
+/// write the selected components (--remove-dir style names) into an archive
+pub(crate) fn backup(
+    ccp: &CargoCachePaths,
+    output: &Path,
+    components: Option<&str>,
+) -> Result<(), Error> {
+    let format = format_of_output(output)?;
+    let selection =
+        ComponentSelection::new(Some(components.unwrap_or(DEFAULT_BACKUP_COMPONENTS)), false)?;
+    let selected = ALL_COMPONENTS
+        .iter()
+        .filter(|component| selection.contains(component))
+        .cloned()
+        .collect::<Vec<Component>>();
+
+    let failed = |error: io::Error| Error::BackupFailed(output.to_path_buf(), error);
+    let file = BufWriter::new(fs::File::create(output).map_err(failed)?);
+    let written = match format {
+        ArchiveFormat::Tar => {
+            let (mut file, written) = write_archive(ccp, &selected, file)?;
+            file.flush().map_err(failed)?;
+            written
+        }
+        ArchiveFormat::TarGz => {
+            let encoder = GzEncoder::new(file, Compression::default());
+            let (encoder, written) = write_archive(ccp, &selected, encoder)?;
+            encoder.finish().map_err(failed)?.flush().map_err(failed)?;
+            written
+        }
+        ArchiveFormat::TarZst => {
+            let encoder =
+                zstd::Encoder::new(file, zstd::DEFAULT_COMPRESSION_LEVEL).map_err(failed)?;
+            let (encoder, written) = write_archive(ccp, &selected, encoder)?;
+            encoder.finish().map_err(failed)?.flush().map_err(failed)?;
+            written
+        }
+    };
+
+    let archive_size = fs::metadata(output).map_or(0, |metadata| metadata.len());
+    println!(
+        "Backed up {} files ({}) of {} to \"{}\" ({}).",
+        written.files,
+        written.size.format_size(DECIMAL),
+        selected
+            .iter()
+            .map(Component::name)
+            .collect::<Vec<&str>>()
+            .join(", "),
+        output.display(),
+        archive_size.format_size(DECIMAL)
+    );
+    Ok(())
+}
+
+/// what was (or would be) restored from an archive
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct Restored {
+    files: u64,
+    size: u64,
+    /// files that already exist in the cargo home and were kept
+    existing: u64,
+    /// entries that are not inside of a component directory
+    rejected: u64,
+}
+
+/// whether an entry of an archive may be unpacked into the cargo home
+/// only relative paths inside of the directories of the components are accepted
+fn is_restorable(path: &Path, roots: &[PathBuf]) -> bool {
+    path.components()
+        .all(|component| matches!(component, path::Component::Normal(_)))
+        && roots.iter().any(|root| path.starts_with(root))
+}
+
+/// unpack the entries of an archive after the marker into the cargo home
+fn unpack<R: Read>(
+    ccp: &CargoCachePaths,
+    archive_path: &Path,
+    reader: R,
+    dry_run: bool,
+) -> Result<Restored, Error> {
+    let failed = |error: io::Error| Error::RestoreFailed(archive_path.to_path_buf(), error);
+    let mut archive = tar::Archive::new(reader);
+    let mut entries = archive.entries().map_err(failed)?;
+
+    let is_backup = match entries.next() {
+        Some(Ok(marker)) => marker
+            .path()
+            .map_or(false, |path| path == Path::new(MARKER)),
+        _ => false,
+    };
+    if !is_backup {
+        return Err(Error::NotABackup(archive_path.to_path_buf()));
+    }
+
+    let roots = ALL_COMPONENTS
+        .iter()
+        .filter_map(|component| {
+            component_path(component, ccp)
+                .strip_prefix(&ccp.cargo_home)
+                .ok()
+                .map(Path::to_path_buf)
+        })
+        .collect::<Vec<PathBuf>>();
+
+    let mut restored = Restored::default();
+    for entry in entries {
+        let mut entry = entry.map_err(failed)?;
+        let path = entry.path().map_err(failed)?.into_owned();
+        if !is_restorable(&path, &roots) {
+            restored.rejected += 1;
+            continue;
+        }
+        let is_dir = entry.header().entry_type().is_dir();
+        if !is_dir {
+            if ccp.cargo_home.join(&path).symlink_metadata().is_ok() {
+                restored.existing += 1;
+                continue;
+            }
+            restored.files += 1;
+            restored.size += entry.size();
+        }
+        if !dry_run {
+            let _ = entry.unpack_in(&ccp.cargo_home).map_err(failed)?;
+        }
+    }
+    Ok(restored)
+}
+
+/// unpack an archive written by "cargo cache backup" into the cargo home
+pub(crate) fn restore(ccp: &CargoCachePaths, archive: &Path, dry_run: bool) -> Result<(), Error> {
+    let failed = |error: io::Error| Error::RestoreFailed(archive.to_path_buf(), error);
+    let mut file = fs::File::open(archive).map_err(failed)?;
+    let mut magic = Vec::new();
+    let _ = (&mut file)
+        .take(4)
+        .read_to_end(&mut magic)
+        .map_err(failed)?;
+    let _ = file.seek(SeekFrom::Start(0)).map_err(failed)?;
+
+    let reader = BufReader::new(file);
+    let restored = if magic.starts_with(&GZIP_MAGIC) {
+        unpack(ccp, archive, GzDecoder::new(reader), dry_run)?
+    } else if magic == ZSTD_MAGIC {
+        let decoder = zstd::Decoder::with_buffer(reader).map_err(failed)?;
+        unpack(ccp, archive, decoder, dry_run)?
+    } else {
+        unpack(ccp, archive, reader, dry_run)?
+    };
+
+    if dry_run {
+        println!(
+            "dry-run: would restore {} files ({}) into \"{}\".",
+            restored.files,
+            restored.size.format_size(DECIMAL),
+            ccp.cargo_home.display()
+        );
+    } else {
+        println!(
+            "Restored {} files ({}) into \"{}\".",
+            restored.files,
+            restored.size.format_size(DECIMAL),
+            ccp.cargo_home.display()
+        );
+    }
+    if restored.existing > 0 {
+        println!(
+            "Kept {} files that already exist in the cargo home.",
+            restored.existing
+        );
+    }
+    if restored.rejected > 0 {
+        println!(
+            "Skipped {} entries that are not part of the cache.",
+            restored.rejected
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod backup_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn output_formats() {
+        assert_eq!(
+            format_of_output(Path::new("cache.tar.gz")).unwrap(),
+            ArchiveFormat::TarGz
+        );
+        assert_eq!(
+            format_of_output(Path::new("dir/cache.TGZ")).unwrap(),
+            ArchiveFormat::TarGz
+        );
+        assert_eq!(
+            format_of_output(Path::new("cache.tar")).unwrap(),
+            ArchiveFormat::Tar
+        );
+        assert_eq!(
+            format_of_output(Path::new("cache.tar.zst")).unwrap(),
+            ArchiveFormat::TarZst
+        );
+        assert!(matches!(
+            format_of_output(Path::new("cache.zip")),
+            Err(Error::UnsupportedArchiveFormat(_))
+        ));
+    }
+
+    #[test]
+    fn restorable_paths() {
+        let roots = vec![PathBuf::from("registry/cache"), PathBuf::from("git/db")];
+        assert!(is_restorable(
+            Path::new("registry/cache/index.crates.io-6f17d22bba15001f/serde-1.0.0.crate"),
+            &roots
+        ));
+        assert!(is_restorable(Path::new("git/db"), &roots));
+        assert!(!is_restorable(Path::new("registry/src/foo"), &roots));
+        assert!(!is_restorable(Path::new("bin/cargo-cache"), &roots));
+        assert!(!is_restorable(
+            Path::new("registry/cache/../../bin/evil"),
+            &roots
+        ));
+        assert!(!is_restorable(Path::new("/registry/cache/evil"), &roots));
+    }
+
+    #[test]
+    fn backup_and_restore() {
+        let tmp = tempfile::tempdir().unwrap();
+        fs::create_dir(tmp.path().join("home")).unwrap();
+        let ccp = CargoCachePaths::new(tmp.path().join("home")).unwrap();
+        let registry = ccp
+            .registry_pkg_cache
+            .join("index.crates.io-6f17d22bba15001f");
+        fs::create_dir_all(&registry).unwrap();
+        fs::write(registry.join("serde-1.0.0.crate"), "serde").unwrap();
+        fs::create_dir_all(ccp.git_repos_bare.join("repo-1234/objects")).unwrap();
+        fs::write(ccp.git_repos_bare.join("repo-1234/HEAD"), "ref").unwrap();
+        // not backed up by default
+        fs::create_dir_all(ccp.registry_sources.join("foo")).unwrap();
+        fs::write(ccp.registry_sources.join("foo/lib.rs"), "").unwrap();
+
+        for name in ["cache.tar.gz", "cache.tar.zst", "cache.tar"] {
+            let archive = tmp.path().join(name);
+            backup(&ccp, &archive, None).unwrap();
+
+            let other_home = tmp.path().join(format!("other-{name}"));
+            fs::create_dir(&other_home).unwrap();
+            let other = CargoCachePaths::new(other_home).unwrap();
+            restore(&other, &archive, true).unwrap();
+            assert!(!other.registry_pkg_cache.exists());
+
+            restore(&other, &archive, false).unwrap();
+            let restored = other
+                .registry_pkg_cache
+                .join("index.crates.io-6f17d22bba15001f/serde-1.0.0.crate");
+            assert_eq!(fs::read_to_string(&restored).unwrap(), "serde");
+            assert!(other.git_repos_bare.join("repo-1234/objects").is_dir());
+            assert!(!other.registry_sources.exists());
+
+            // existing files are kept
+            fs::write(&restored, "changed").unwrap();
+            restore(&other, &archive, false).unwrap();
+            assert_eq!(fs::read_to_string(&restored).unwrap(), "changed");
+        }
+    }
+
+    #[test]
+    fn foreign_archives_are_refused() {
+        let tmp = tempfile::tempdir().unwrap();
+        let ccp = CargoCachePaths::new(tmp.path().to_path_buf()).unwrap();
+        let archive = tmp.path().join("foreign.tar");
+        let mut builder = tar::Builder::new(fs::File::create(&archive).unwrap());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(0);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "registry/cache/foo.crate", io::empty())
+            .unwrap();
+        builder.finish().unwrap();
+
+        assert!(matches!(
+            restore(&ccp, &archive, false),
+            Err(Error::NotABackup(_))
+        ));
+    }
+}
@@ -0,0 +1,140 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// "cargo cache check --fail-if-above registry=5G,total=20G" command
+// compare the size of the cache (or some of its components) with a limit and exit with 1 if any
+// of them is bigger, so that CI or build machines can notice a growing cache without deleting anything.
+
+use humansize::{FormatSize, DECIMAL};
+
+use crate::args::parse::{self, SizeUnits};
+use crate::dirsizes::DirSizes;
+use crate::library::Error;
+use crate::tables::{two_row_table, TableLine};
+
+/// the parts of the cache a limit can be set for, names are the same as in the summary / --remove-dir
+const CHECKABLE: &[&str] = &[
+    "total",
+    "bin",
+    "registry",
+    "registry-index",
+    "registry-crate-cache",
+    "registry-sources",
+    "git",
+    "git-db",
+    "git-repos",
+];
+
+/// a component of the cache and the size it must not exceed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct SizeLimit {
+    component: String,
+    limit: u64,
+}
+
+/// parse "registry=5G,total=20G", a size without component ("20G") limits the total size
+pub(crate) fn parse_size_limits(limits: &str) -> Result<Vec<SizeLimit>, Error> {
+    limits
+        .split(',')
+        .map(|limit| {
+            let (component, size) = limit.split_once('=').unwrap_or(("total", limit));
+            let component = component.trim();
+            match parse::size(size.trim(), SizeUnits::Decimal) {
+                Ok(size) if CHECKABLE.contains(&component) => Ok(SizeLimit {
+                    component: component.to_string(),
+                    limit: size,
+                }),
+                _ => Err(Error::InvalidSizeLimit(limit.to_string())),
+            }
+        })
+        .collect()
+}
+
+/// the current size of a component of the cache
+fn component_size(dir_sizes: &DirSizes<'_>, component: &str) -> u64 {
+    match component {
+        "total" => dir_sizes.total_size(),
+        "bin" => dir_sizes.total_bin_size(),
+        "registry" => dir_sizes.total_reg_size(),
+        "registry-index" => dir_sizes.total_reg_index_size(),
+        "registry-crate-cache" => dir_sizes.total_reg_cache_size(),
+        "registry-sources" => dir_sizes.total_reg_src_size(),
+        "git" => dir_sizes.total_git_db_size(),
+        "git-db" => dir_sizes.total_git_repos_bare_size(),
+        "git-repos" => dir_sizes.total_git_chk_size(),
+        _ => unreachable!("size limit of unknown component \"{}\"", component),
+    }
+}
+
+/// print the size of each component next to its limit and fail if any of them is exceeded
+pub(crate) fn check_size_limits(dir_sizes: &DirSizes<'_>, limits: &str) -> Result<(), Error> {
+    let limits = parse_size_limits(limits)?;
+
+    let mut table = Vec::new();
+    let mut exceeded = Vec::new();
+    for SizeLimit { component, limit } in limits {
+        let size = component_size(dir_sizes, &component);
+        table.push(TableLine::new(
+            0,
+            &format!("{component} (limit {}): ", limit.format_size(DECIMAL)),
+            &size.format_size(DECIMAL),
+        ));
+        if size > limit {
+            exceeded.push(format!(
+                "{component} ({} > {})",
+                size.format_size(DECIMAL),
+                limit.format_size(DECIMAL)
+            ));
+        }
+    }
+
+    println!(
+        "Size limits of cargo cache '{}':\n",
+        dir_sizes.root_path().display()
+    );
+    print!("{}", two_row_table(2, table, true));
+
+    if exceeded.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::SizeLimitExceeded(exceeded))
+    }
+}
+
+#[cfg(test)]
+mod check_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn limits() {
+        assert_eq!(
+            parse_size_limits("registry=5G,total=20G").unwrap(),
+            vec![
+                SizeLimit {
+                    component: String::from("registry"),
+                    limit: 5_000_000_000
+                },
+                SizeLimit {
+                    component: String::from("total"),
+                    limit: 20_000_000_000
+                }
+            ]
+        );
+        assert_eq!(
+            parse_size_limits("1G").unwrap(),
+            vec![SizeLimit {
+                component: String::from("total"),
+                limit: 1_000_000_000
+            }]
+        );
+        assert!(parse_size_limits("registry=5G,foo=1G").is_err());
+        assert!(parse_size_limits("registry=").is_err());
+    }
+}
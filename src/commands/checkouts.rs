@@ -0,0 +1,266 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// "cargo cache git list <repo>" and "cargo cache git rm-checkout <repo> <rev>" commands
+// cargo checks out every revision of a git dependency it builds into git/checkouts/<repo>-<hash>/<rev>,
+// a big repo that was updated a few times ends up with several full copies of the source.
+// These commands list the checkouts of one repo and remove single ones, the bare repo in git/db
+// and the other checkouts are left alone.
+
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use humansize::{FormatSize, DECIMAL};
+
+use crate::cache::caches::{get_cache_name, Cache};
+use crate::cache::git_checkouts::GitCheckoutCache;
+use crate::library::{size_of_path, CargoCachePaths, Error};
+use crate::remove::remove_and_record_freed;
+use crate::retry;
+use crate::tables::format_table;
+
+/// a checked out revision of a cached git repo
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Checkout {
+    /// the directory name, the abbreviated commit hash
+    rev: String,
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+/// the name of a directory as String
+fn dir_name(path: &Path) -> String {
+    path.file_name().unwrap().to_string_lossy().to_string()
+}
+
+/// find the repo in git/checkouts by its directory name or by its name without the hash
+fn find_repo(repos: &[PathBuf], repo: &str) -> Result<PathBuf, Error> {
+    if let Some(exact) = repos.iter().find(|path| dir_name(path) == repo) {
+        return Ok(exact.clone());
+    }
+
+    let mut matching = repos
+        .iter()
+        .filter(|path| get_cache_name(path) == repo)
+        .cloned()
+        .collect::<Vec<PathBuf>>();
+    match matching.len() {
+        0 => Err(Error::GitCheckoutRepoNotFound(repo.to_string())),
+        1 => Ok(matching.remove(0)),
+        _ => Err(Error::GitCheckoutAmbiguousRepo(
+            repo.to_string(),
+            matching.iter().map(|path| dir_name(path)).collect(),
+        )),
+    }
+}
+
+/// the checkouts of all repos, grouped by repo and sorted by the time they were last modified
+/// the oldest checkout comes first
+fn checkouts_by_repo(checkouts_cache: &mut GitCheckoutCache) -> Vec<(PathBuf, Vec<Checkout>)> {
+    let mut repos: Vec<(PathBuf, Vec<Checkout>)> = Vec::new();
+    for item in checkouts_cache.items_sorted() {
+        let repo = item.parent().unwrap().to_path_buf();
+        let checkout = Checkout {
+            rev: dir_name(item),
+            path: item.clone(),
+            modified: retry::metadata(item)
+                .and_then(|metadata| metadata.modified())
+                .ok(),
+        };
+        match repos.last_mut() {
+            Some((last_repo, checkouts)) if *last_repo == repo => checkouts.push(checkout),
+            _ => repos.push((repo, vec![checkout])),
+        }
+    }
+    for (_repo, checkouts) in &mut repos {
+        checkouts.sort_by(|a, b| (a.modified, &a.rev).cmp(&(b.modified, &b.rev)));
+    }
+    repos
+}
+
+/// the checkouts of `repo`
+fn checkouts_of(ccp: &CargoCachePaths, repo: &str) -> Result<(PathBuf, Vec<Checkout>), Error> {
+    let mut checkouts_cache = GitCheckoutCache::new(ccp.git_checkouts.clone());
+    let mut repos = checkouts_by_repo(&mut checkouts_cache);
+    let repo_path = find_repo(
+        &repos
+            .iter()
+            .map(|(path, _)| path.clone())
+            .collect::<Vec<_>>(),
+        repo,
+    )?;
+    let position = repos
+        .iter()
+        .position(|(path, _)| *path == repo_path)
+        .unwrap();
+    Ok(repos.swap_remove(position))
+}
+
+/// the checkout whose revision starts with `rev`, a full commit hash matches its abbreviation
+fn find_checkout<'a>(
+    checkouts: &'a [Checkout],
+    repo: &str,
+    rev: &str,
+) -> Result<&'a Checkout, Error> {
+    if let Some(exact) = checkouts.iter().find(|checkout| checkout.rev == rev) {
+        return Ok(exact);
+    }
+
+    let matching = checkouts
+        .iter()
+        .filter(|checkout| checkout.rev.starts_with(rev) || rev.starts_with(&checkout.rev))
+        .collect::<Vec<&Checkout>>();
+    match matching.as_slice() {
+        [] => Err(Error::GitCheckoutRevNotFound(
+            repo.to_string(),
+            rev.to_string(),
+            checkouts
+                .iter()
+                .map(|checkout| checkout.rev.clone())
+                .collect(),
+        )),
+        [checkout] => Ok(checkout),
+        _ => Err(Error::GitCheckoutAmbiguousRev(
+            rev.to_string(),
+            matching
+                .iter()
+                .map(|checkout| checkout.rev.clone())
+                .collect(),
+        )),
+    }
+}
+
+/// the table of the checkouts with their sizes
+fn checkouts_table(checkouts: &[Checkout]) -> String {
+    let mut table = vec![vec![String::from("Revision"), String::from("Size")]];
+    table.extend(checkouts.iter().map(|checkout| {
+        vec![
+            checkout.rev.clone(),
+            size_of_path(&checkout.path).format_size(DECIMAL),
+        ]
+    }));
+    format_table(&table, 0)
+}
+
+/// print the checked out revisions of the cached git repo `repo`
+pub(crate) fn list(ccp: &CargoCachePaths, repo: &str) -> Result<(), Error> {
+    let (repo_path, checkouts) = checkouts_of(ccp, repo)?;
+    println!(
+        "Checkouts of \"{}\", the least recently modified first:\n",
+        dir_name(&repo_path)
+    );
+    print!("{}", checkouts_table(&checkouts));
+    Ok(())
+}
+
+/// remove the checkout of the revision `rev` of the cached git repo `repo`
+pub(crate) fn rm_checkout(
+    ccp: &CargoCachePaths,
+    repo: &str,
+    rev: &str,
+    dry_run: bool,
+) -> Result<(), Error> {
+    let (repo_path, checkouts) = checkouts_of(ccp, repo)?;
+    let checkout = find_checkout(&checkouts, &dir_name(&repo_path), rev)?;
+
+    remove_and_record_freed(&[&checkout.path], dry_run);
+    Ok(())
+}
+
+#[cfg(test)]
+mod checkouts_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::fs;
+
+    fn checkout(rev: &str) -> Checkout {
+        Checkout {
+            rev: rev.to_string(),
+            path: PathBuf::from(rev),
+            modified: None,
+        }
+    }
+
+    #[test]
+    fn find_repos() {
+        let repos = [
+            "git/checkouts/bar-1234",
+            "git/checkouts/bar-5678",
+            "git/checkouts/foo-1234",
+        ]
+        .iter()
+        .map(PathBuf::from)
+        .collect::<Vec<_>>();
+
+        assert_eq!(find_repo(&repos, "foo").unwrap(), repos[2]);
+        assert_eq!(find_repo(&repos, "bar-5678").unwrap(), repos[1]);
+        assert!(matches!(
+            find_repo(&repos, "bar"),
+            Err(Error::GitCheckoutAmbiguousRepo(_, names)) if names == ["bar-1234", "bar-5678"]
+        ));
+        assert!(matches!(
+            find_repo(&repos, "baz"),
+            Err(Error::GitCheckoutRepoNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn find_revs() {
+        let checkouts = vec![
+            checkout("1ecc629"),
+            checkout("1eff000"),
+            checkout("abcdef0"),
+        ];
+
+        assert_eq!(
+            find_checkout(&checkouts, "foo", "ab").unwrap().rev,
+            "abcdef0"
+        );
+        // a full hash matches its abbreviation
+        assert_eq!(
+            find_checkout(&checkouts, "foo", "1ecc6299db9ec823abc")
+                .unwrap()
+                .rev,
+            "1ecc629"
+        );
+        assert!(matches!(
+            find_checkout(&checkouts, "foo", "1e"),
+            Err(Error::GitCheckoutAmbiguousRev(_, revs)) if revs == ["1ecc629", "1eff000"]
+        ));
+        assert!(matches!(
+            find_checkout(&checkouts, "foo", "ff"),
+            Err(Error::GitCheckoutRevNotFound(_, _, revs)) if revs.len() == 3
+        ));
+    }
+
+    #[test]
+    fn remove_one_checkout() {
+        let tmp = tempfile::tempdir().unwrap();
+        let cargo_home = tmp.path();
+        let repo = cargo_home.join("git/checkouts/foo-1234");
+        for rev in ["1ecc629", "abcdef0"] {
+            fs::create_dir_all(repo.join(rev)).unwrap();
+            fs::write(repo.join(rev).join("lib.rs"), "fn foo() {}").unwrap();
+        }
+        fs::create_dir_all(cargo_home.join("git/db/foo-1234")).unwrap();
+        let ccp = CargoCachePaths::new(cargo_home.to_path_buf()).unwrap();
+
+        let (path, checkouts) = checkouts_of(&ccp, "foo").unwrap();
+        assert_eq!(path, repo);
+        assert_eq!(checkouts.len(), 2);
+
+        rm_checkout(&ccp, "foo", "1ecc", true).unwrap();
+        assert!(repo.join("1ecc629").is_dir());
+
+        rm_checkout(&ccp, "foo", "1ecc", false).unwrap();
+        assert!(!repo.join("1ecc629").exists());
+        assert!(repo.join("abcdef0").is_dir());
+        assert!(cargo_home.join("git/db/foo-1234").is_dir());
+    }
+}
@@ -0,0 +1,466 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// "cargo cache compare <other-cargo-home>" command
+// compare the local cargo home with another one (a mounted backup, a snapshot of a colleague...):
+// which crate archives, bare git repos and git checkout revisions exist only on one side.
+// With --merge-from, crate archives and bare git repos that only exist in the other cargo home are
+// copied into the local one so that cargo can use them (the sources and checkouts are extracted
+// from these by cargo when needed).
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use humansize::{FormatSize, DECIMAL};
+use walkdir::WalkDir;
+
+use crate::cancel::{self, block_on, is_cancelled, CancelToken, ScanTask};
+use crate::ignore::is_ignored;
+use crate::library::{size_of_path, CargoCachePaths, Error};
+use crate::tables::{two_row_table, TableLine};
+
+/// the kinds of items we compare
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Kind {
+    /// registry/cache/<registry>/<crate>-<version>.crate
+    CrateArchive,
+    /// git/db/<repo>
+    BareGitRepo,
+    /// git/checkouts/<repo>/<revision>
+    GitRevision,
+}
+
+impl Kind {
+    fn name(self) -> &'static str {
+        match self {
+            Self::CrateArchive => "crate archives",
+            Self::BareGitRepo => "bare git repos",
+            Self::GitRevision => "git checkout revisions",
+        }
+    }
+}
+
+/// the items of a cargo home that can be compared
+/// maps the (kind, path relative to the cargo home) to the absolute path
+#[derive(Debug, Default)]
+struct Inventory {
+    items: BTreeMap<(Kind, PathBuf), PathBuf>,
+}
+
+impl Inventory {
+    /// take stock of a cargo home
+    fn new(ccp: &CargoCachePaths) -> Self {
+        let mut inventory = Self::default();
+
+        for registry in subdirs(&ccp.registry_pkg_cache)
+            .into_iter()
+            .take_while(|_| !is_cancelled())
+        {
+            let archives = fs::read_dir(&registry)
+                .map(|entries| {
+                    entries
+                        .filter_map(Result::ok)
+                        .map(|entry| entry.path())
+                        .filter(|path| {
+                            path.is_file()
+                                && path.extension().map_or(false, |ext| ext == "crate")
+                                && !is_ignored(path)
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .unwrap_or_default();
+            inventory.add(ccp, Kind::CrateArchive, archives);
+        }
+
+        inventory.add(ccp, Kind::BareGitRepo, subdirs(&ccp.git_repos_bare));
+
+        for repo in subdirs(&ccp.git_checkouts)
+            .into_iter()
+            .take_while(|_| !is_cancelled())
+        {
+            inventory.add(ccp, Kind::GitRevision, subdirs(&repo));
+        }
+
+        inventory
+    }
+
+    fn add(&mut self, ccp: &CargoCachePaths, kind: Kind, paths: Vec<PathBuf>) {
+        for path in paths {
+            if let Ok(relative) = path.strip_prefix(&ccp.cargo_home) {
+                let _ = self.items.insert((kind, relative.to_path_buf()), path);
+            }
+        }
+    }
+
+    /// items of this inventory that the other one does not have
+    fn missing_from<'a>(&'a self, other: &'a Self) -> Vec<(&'a (Kind, PathBuf), &'a PathBuf)> {
+        self.items
+            .iter()
+            .filter(|(key, _)| !other.items.contains_key(*key))
+            .collect()
+    }
+
+    /// number of items of each kind that both inventories have
+    fn common_with(&self, other: &Self, kind: Kind) -> usize {
+        self.items
+            .keys()
+            .filter(|key| key.0 == kind && other.items.contains_key(*key))
+            .count()
+    }
+}
+
+/// the directories inside a directory (not following symlinks), sorted and without ignored ones
+fn subdirs(dir: &Path) -> Vec<PathBuf> {
+    let mut dirs = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .filter(|entry| entry.file_type().map_or(false, |ft| ft.is_dir()))
+                .map(|entry| entry.path())
+                .filter(|path| !is_ignored(path))
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    dirs.sort();
+    dirs
+}
+
+/// lines summarizing the items that only exist on one side
+fn only_in_lines(cargo_home: &Path, items: &[(&(Kind, PathBuf), &PathBuf)]) -> Vec<TableLine> {
+    let mut lines = vec![TableLine::new(
+        0,
+        &format!("Only in '{}': ", cargo_home.display()),
+        &String::new(),
+    )];
+    for kind in [Kind::CrateArchive, Kind::BareGitRepo, Kind::GitRevision] {
+        let of_kind = items
+            .iter()
+            .filter(|((item_kind, _), _)| *item_kind == kind)
+            .collect::<Vec<_>>();
+        let size: u64 = of_kind.iter().map(|(_, path)| size_of_path(path)).sum();
+        lines.push(TableLine::new(
+            1,
+            &format!("{} {}: ", of_kind.len(), kind.name()),
+            &size.format_size(DECIMAL),
+        ));
+    }
+    lines
+}
+
+/// list the items that only exist on one side
+fn only_in_list(cargo_home: &Path, items: &[(&(Kind, PathBuf), &PathBuf)]) -> String {
+    if items.is_empty() {
+        return String::new();
+    }
+    let mut list = format!("\nOnly in '{}':\n", cargo_home.display());
+    for ((_, relative), _) in items {
+        list.push_str("  ");
+        list.push_str(&relative.display().to_string());
+        list.push('\n');
+    }
+    list
+}
+
+/// copy a file or a directory to `destination`
+/// everything is copied next to the destination first and then renamed so that cargo never
+/// sees half-copied crate archives or git repos
+fn copy_item(source: &Path, destination: &Path) -> io::Result<()> {
+    let mut temporary = destination.as_os_str().to_owned();
+    temporary.push(".cargo-cache-partial");
+    let temporary = PathBuf::from(temporary);
+
+    if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let copied = if source.is_dir() {
+        copy_dir(source, &temporary)
+    } else {
+        fs::copy(source, &temporary).map(|_| ())
+    };
+
+    match copied.and_then(|()| fs::rename(&temporary, destination)) {
+        Ok(()) => Ok(()),
+        Err(error) => {
+            // clean up after ourselves
+            let _ = if temporary.is_dir() {
+                fs::remove_dir_all(&temporary)
+            } else {
+                fs::remove_file(&temporary)
+            };
+            Err(error)
+        }
+    }
+}
+
+/// recursively copy a directory, symlinks are skipped
+fn copy_dir(source: &Path, destination: &Path) -> io::Result<()> {
+    for entry in WalkDir::new(source) {
+        let entry = entry.map_err(io::Error::from)?;
+        let target = destination.join(entry.path().strip_prefix(source).unwrap());
+        if entry.file_type().is_dir() {
+            fs::create_dir_all(&target)?;
+        } else if entry.file_type().is_file() {
+            let _ = fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+/// copy the crate archives and bare git repos that only exist in the other cargo home into ours
+fn merge(
+    local: &CargoCachePaths,
+    missing_locally: &[(&(Kind, PathBuf), &PathBuf)],
+    dry_run: bool,
+) -> Result<(), Error> {
+    // sources and checkouts are extracted from the archives and repos by cargo
+    let to_copy = missing_locally
+        .iter()
+        .filter(|((kind, _), _)| *kind != Kind::GitRevision)
+        .map(|((_, relative), source)| (*source, local.cargo_home.join(relative)))
+        .filter(|(_, destination)| !is_ignored(destination))
+        .collect::<Vec<_>>();
+
+    println!();
+    let mut copied_items = 0;
+    let mut copied_size = 0;
+    // stop before the next item once we were cancelled, items are never copied halfway
+    for (source, destination) in to_copy.iter().take_while(|_| !is_cancelled()) {
+        let size = size_of_path(source);
+        if dry_run {
+            println!(
+                "dry-run: would copy '{}' to '{}' ({})",
+                source.display(),
+                destination.display(),
+                size.format_size(DECIMAL)
+            );
+        } else {
+            println!("copying: '{}'", destination.display());
+            copy_item(source, destination)
+                .map_err(|error| Error::CopyFailed((*source).clone(), error))?;
+        }
+        copied_items += 1;
+        copied_size += size;
+    }
+
+    println!(
+        "{}{} items ({})",
+        if dry_run {
+            "dry-run: would copy "
+        } else {
+            "Copied "
+        },
+        copied_items,
+        copied_size.format_size(DECIMAL)
+    );
+    cancel::exit_if_cancelled("not all missing items were copied");
+    Ok(())
+}
+
+/// compare the local cargo home with another one and optionally copy what we are missing
+pub(crate) fn compare(
+    local: &CargoCachePaths,
+    other_cargo_home: &str,
+    merge_from: bool,
+    dry_run: bool,
+) -> Result<(), Error> {
+    let other = CargoCachePaths::new(PathBuf::from(other_cargo_home))?;
+    if fs::canonicalize(&other.cargo_home).ok() == fs::canonicalize(&local.cargo_home).ok() {
+        return Err(Error::CompareSameCargoHome(other.cargo_home));
+    }
+
+    // scan both cargo homes at the same time, the other one may well be on a slow (network) drive
+    // only Ctrl-C stops them
+    let token = CancelToken::new();
+    let local_scan = ScanTask::spawn(token.clone(), {
+        let local = local.clone();
+        move || Inventory::new(&local)
+    });
+    let other_scan = ScanTask::spawn(token, {
+        let other = other.clone();
+        move || Inventory::new(&other)
+    });
+    let (local_inventory, other_inventory) =
+        block_on(async { (local_scan.await, other_scan.await) });
+    cancel::exit_if_cancelled("the scan did not finish, nothing was compared");
+
+    let only_local = local_inventory.missing_from(&other_inventory);
+    let only_other = other_inventory.missing_from(&local_inventory);
+
+    let mut table = vec![
+        TableLine::new(
+            0,
+            &format!(
+                "Comparing cargo cache '{}' with '{}':\n\n",
+                local.cargo_home.display(),
+                other.cargo_home.display()
+            ),
+            &String::new(),
+        ),
+        TableLine::new(0, &"In both: ".to_string(), &String::new()),
+    ];
+    for kind in [Kind::CrateArchive, Kind::BareGitRepo, Kind::GitRevision] {
+        table.push(TableLine::new(
+            1,
+            &format!(
+                "{} {} ",
+                local_inventory.common_with(&other_inventory, kind),
+                kind.name()
+            ),
+            &String::new(),
+        ));
+    }
+    table.extend(only_in_lines(&local.cargo_home, &only_local));
+    table.extend(only_in_lines(&other.cargo_home, &only_other));
+
+    print!("{}", two_row_table(2, table, false));
+    print!("{}", only_in_list(&local.cargo_home, &only_local));
+    print!("{}", only_in_list(&other.cargo_home, &only_other));
+
+    if merge_from {
+        merge(local, &only_other, dry_run)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod compare_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn write(path: &Path, content: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    fn relative_paths(items: &[(&(Kind, PathBuf), &PathBuf)]) -> Vec<String> {
+        items
+            .iter()
+            .map(|((_, relative), _)| relative.display().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn inventories() {
+        let local_dir = tempfile::tempdir().unwrap();
+        let other_dir = tempfile::tempdir().unwrap();
+        let local = CargoCachePaths::new(local_dir.path().to_path_buf()).unwrap();
+        let other = CargoCachePaths::new(other_dir.path().to_path_buf()).unwrap();
+
+        let registry = "index.crates.io-6f17d22bba15001f";
+        for ccp in [&local, &other] {
+            write(
+                &ccp.registry_pkg_cache
+                    .join(registry)
+                    .join("serde-1.0.0.crate"),
+                "serde",
+            );
+            write(&ccp.git_repos_bare.join("foo-1234").join("HEAD"), "head");
+        }
+        write(
+            &local
+                .registry_pkg_cache
+                .join(registry)
+                .join("local-0.1.0.crate"),
+            "l",
+        );
+        write(
+            &other
+                .registry_pkg_cache
+                .join(registry)
+                .join("other-0.1.0.crate"),
+            "o",
+        );
+        // not a crate archive
+        write(
+            &other
+                .registry_pkg_cache
+                .join(registry)
+                .join(".package-cache"),
+            "",
+        );
+        write(&other.git_repos_bare.join("bar-5678").join("HEAD"), "head");
+        write(
+            &other
+                .git_checkouts
+                .join("bar-5678")
+                .join("abcdef0")
+                .join("lib.rs"),
+            "x",
+        );
+
+        let local_inventory = Inventory::new(&local);
+        let other_inventory = Inventory::new(&other);
+
+        assert_eq!(
+            relative_paths(&local_inventory.missing_from(&other_inventory)),
+            vec![format!("registry/cache/{registry}/local-0.1.0.crate")]
+        );
+        assert_eq!(
+            relative_paths(&other_inventory.missing_from(&local_inventory)),
+            vec![
+                format!("registry/cache/{registry}/other-0.1.0.crate"),
+                String::from("git/db/bar-5678"),
+                String::from("git/checkouts/bar-5678/abcdef0"),
+            ]
+        );
+        assert_eq!(
+            local_inventory.common_with(&other_inventory, Kind::CrateArchive),
+            1
+        );
+        assert_eq!(
+            local_inventory.common_with(&other_inventory, Kind::BareGitRepo),
+            1
+        );
+        assert_eq!(
+            local_inventory.common_with(&other_inventory, Kind::GitRevision),
+            0
+        );
+
+        // dry run does not copy anything
+        let only_other = other_inventory.missing_from(&local_inventory);
+        merge(&local, &only_other, true).unwrap();
+        assert_eq!(
+            local_inventory.missing_from(&Inventory::new(&local)).len(),
+            0
+        );
+        assert_eq!(Inventory::new(&local).items.len(), 3);
+
+        merge(&local, &only_other, false).unwrap();
+        let merged = Inventory::new(&local);
+        // archives and bare repos are copied, checkouts are not
+        assert_eq!(
+            relative_paths(&other_inventory.missing_from(&merged)),
+            vec![String::from("git/checkouts/bar-5678/abcdef0")]
+        );
+        assert_eq!(
+            fs::read_to_string(
+                local
+                    .registry_pkg_cache
+                    .join(registry)
+                    .join("other-0.1.0.crate")
+            )
+            .unwrap(),
+            "o"
+        );
+        assert!(local.git_repos_bare.join("bar-5678").join("HEAD").is_file());
+    }
+
+    #[test]
+    fn same_cargo_home() {
+        let dir = tempfile::tempdir().unwrap();
+        let ccp = CargoCachePaths::new(dir.path().to_path_buf()).unwrap();
+        assert!(matches!(
+            compare(&ccp, &dir.path().display().to_string(), false, false),
+            Err(Error::CompareSameCargoHome(_))
+        ));
+    }
+}
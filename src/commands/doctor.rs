@@ -0,0 +1,560 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// "cargo cache doctor" command
+// check that the cache components only contain the kind of entries cargo puts there.
+// Interrupted downloads or extractions and manual copying sometimes leave .crate archives inside
+// registry/src or files where cargo expects directories. The caches of cargo-cache assume the
+// layout is right and would count or remove such entries as if they were crate sources or repos.
+// With --quarantine the misplaced entries are moved out of the way, with --remove they are removed.
+//
+// It also looks for crate sources and git checkouts that can not be extracted correctly everywhere:
+// files whose names only differ by case overwrite each other on case-insensitive file systems
+// (macOS, Windows) and paths longer than 260 characters fail on Windows without long path support.
+// Such a cache may have been copied from a case-sensitive system, or the extraction silently lost
+// files which leads to cryptic build errors later on. Removing the sources makes cargo extract
+// them again, --quarantine and --remove handle them like misplaced entries.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use humansize::{FormatSize, DECIMAL};
+
+use crate::ignore::is_ignored;
+use crate::library::{size_of_path, CargoCachePaths, Error};
+use crate::remove::{classify_path, remove_file, DryRunMessage, PathKind};
+use crate::walk::walk;
+
+/// name of the directory inside the cargo home that --quarantine moves misplaced entries to
+const QUARANTINE_DIR_NAME: &str = "cargo-cache-quarantine";
+
+/// windows refuses longer paths unless long path support is enabled (`MAX_PATH`)
+const MAX_PATH_LENGTH: usize = 260;
+
+/// what to do with misplaced entries
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DoctorFix {
+    /// only report them
+    Report,
+    /// move them into the quarantine directory
+    Quarantine,
+    /// remove them
+    Remove,
+}
+
+/// the kind of entry cargo puts at a level of a cache component
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Expected {
+    Directory,
+    CrateArchive,
+}
+
+impl Expected {
+    fn matches(self, path: &Path, kind: PathKind) -> bool {
+        match self {
+            Self::Directory => kind == PathKind::Directory,
+            Self::CrateArchive => {
+                kind == PathKind::File && path.extension().map_or(false, |ext| ext == "crate")
+            }
+        }
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            Self::Directory => "a directory",
+            Self::CrateArchive => "a .crate archive",
+        }
+    }
+}
+
+/// an entry of a cache component that is not of the kind cargo puts there
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Misplaced {
+    path: PathBuf,
+    expected: Expected,
+    found: PathKind,
+    size: u64,
+}
+
+impl Misplaced {
+    fn details(&self, cargo_home: &Path) -> String {
+        let found = match self.found {
+            PathKind::Directory => "a directory",
+            PathKind::File => "a file",
+            PathKind::Link | PathKind::Missing => unreachable!("links are never misplaced"),
+        };
+        format!(
+            "{}: expected {}, found {found} ({})",
+            self.path
+                .strip_prefix(cargo_home)
+                .unwrap_or(&self.path)
+                .display(),
+            self.expected.description(),
+            self.size.format_size(DECIMAL)
+        )
+    }
+}
+
+/// the components and what cargo puts into them, level by level
+fn layout(ccp: &CargoCachePaths) -> [(&Path, &'static [Expected]); 5] {
+    [
+        // registry/index/<registry>
+        (&ccp.registry_index, &[Expected::Directory]),
+        // registry/cache/<registry>/<crate>-<version>.crate
+        (
+            &ccp.registry_pkg_cache,
+            &[Expected::Directory, Expected::CrateArchive],
+        ),
+        // registry/src/<registry>/<crate>-<version>
+        (
+            &ccp.registry_sources,
+            &[Expected::Directory, Expected::Directory],
+        ),
+        // git/db/<repo>
+        (&ccp.git_repos_bare, &[Expected::Directory]),
+        // git/checkouts/<repo>/<revision>
+        (
+            &ccp.git_checkouts,
+            &[Expected::Directory, Expected::Directory],
+        ),
+    ]
+}
+
+/// files that cargo and other tools put between the entries on purpose
+fn is_expected_file(path: &Path) -> bool {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    name == "CACHEDIR.TAG" || name.starts_with('.')
+}
+
+/// collect the entries of `dir` that do not match the expected layout
+fn find_misplaced(dir: &Path, levels: &[Expected], misplaced: &mut Vec<Misplaced>) {
+    let (expected, deeper) = match levels.split_first() {
+        Some(level) => level,
+        None => return,
+    };
+
+    let mut entries = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .map(|entry| entry.path())
+                .collect::<Vec<PathBuf>>()
+        })
+        .unwrap_or_default();
+    entries.sort();
+
+    for path in entries {
+        if is_ignored(&path) || is_expected_file(&path) {
+            continue;
+        }
+        let kind = classify_path(&path);
+        match kind {
+            // links are never followed, whatever is behind them is not ours to judge
+            PathKind::Link | PathKind::Missing => {}
+            _ if expected.matches(&path, kind) => {
+                if kind == PathKind::Directory {
+                    find_misplaced(&path, deeper, misplaced);
+                }
+            }
+            _ => misplaced.push(Misplaced {
+                size: size_of_path(&path),
+                path,
+                expected: *expected,
+                found: kind,
+            }),
+        }
+    }
+}
+
+/// all the misplaced entries of the cache components
+fn all_misplaced(ccp: &CargoCachePaths) -> Vec<Misplaced> {
+    let mut misplaced = Vec::new();
+    for (dir, levels) in layout(ccp) {
+        find_misplaced(dir, levels, &mut misplaced);
+    }
+    misplaced
+}
+
+/// why a crate source or git checkout can not be extracted correctly on every file system
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Problem {
+    /// two entries of a directory whose names only differ by case, relative to the source
+    CaseCollision(PathBuf, PathBuf),
+    /// the longest path inside and its length in characters
+    PathTooLong(PathBuf, usize),
+}
+
+/// a crate source or git checkout with problems, cargo extracts it again if it is removed
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Unportable {
+    path: PathBuf,
+    problems: Vec<Problem>,
+    size: u64,
+}
+
+impl Unportable {
+    fn details(&self, cargo_home: &Path) -> String {
+        let problems = self
+            .problems
+            .iter()
+            .map(|problem| match problem {
+                Problem::CaseCollision(a, b) => format!(
+                    "\"{}\" and \"{}\" only differ by case",
+                    a.display(),
+                    b.display()
+                ),
+                Problem::PathTooLong(path, length) => format!(
+                    "\"{}\" is {length} characters long, more than {MAX_PATH_LENGTH}",
+                    path.display()
+                ),
+            })
+            .collect::<Vec<String>>()
+            .join(", ");
+        format!(
+            "{}: {problems} ({})",
+            self.path
+                .strip_prefix(cargo_home)
+                .unwrap_or(&self.path)
+                .display(),
+            self.size.format_size(DECIMAL)
+        )
+    }
+}
+
+/// the directories inside `dir` (no links)
+fn subdirs(dir: &Path) -> Vec<PathBuf> {
+    let mut dirs = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .map(|entry| entry.path())
+                .filter(|path| !is_ignored(path) && classify_path(path) == PathKind::Directory)
+                .collect::<Vec<PathBuf>>()
+        })
+        .unwrap_or_default();
+    dirs.sort();
+    dirs
+}
+
+/// check a crate source or git checkout for names that clash when case is ignored and paths
+/// that are too long for windows
+fn find_problems(item: &Path) -> Vec<Problem> {
+    // (parent, lowercase name) => the first path with that name
+    let mut names: BTreeMap<(PathBuf, String), PathBuf> = BTreeMap::new();
+    let mut collisions = Vec::new();
+    let mut longest: Option<(PathBuf, usize)> = None;
+
+    for entry in walk(item).skip(1) {
+        let path = entry.path();
+        let relative = path.strip_prefix(item).unwrap_or(path).to_path_buf();
+        let key = (
+            relative.parent().map(Path::to_path_buf).unwrap_or_default(),
+            entry.file_name().to_string_lossy().to_lowercase(),
+        );
+        match names.get(&key) {
+            Some(first) if *first < relative => {
+                collisions.push(Problem::CaseCollision(first.clone(), relative));
+            }
+            Some(first) => collisions.push(Problem::CaseCollision(relative, first.clone())),
+            None => {
+                let _ = names.insert(key, relative);
+            }
+        }
+
+        let length = path.to_string_lossy().chars().count();
+        if length > MAX_PATH_LENGTH && longest.as_ref().map_or(true, |(_, l)| length > *l) {
+            longest = Some((path.to_path_buf(), length));
+        }
+    }
+
+    collisions.sort();
+    if let Some((path, length)) = longest {
+        collisions.push(Problem::PathTooLong(path, length));
+    }
+    collisions
+}
+
+/// the crate sources and git checkouts that can not be extracted correctly everywhere
+fn all_unportable(ccp: &CargoCachePaths) -> Vec<Unportable> {
+    [&ccp.registry_sources, &ccp.git_checkouts]
+        .iter()
+        // registry/src/<registry>/<crate>-<version> and git/checkouts/<repo>/<revision>
+        .flat_map(|component| subdirs(component))
+        .flat_map(|dir| subdirs(&dir))
+        .filter_map(|item| {
+            let problems = find_problems(&item);
+            if problems.is_empty() {
+                None
+            } else {
+                Some(Unportable {
+                    size: size_of_path(&item),
+                    path: item,
+                    problems,
+                })
+            }
+        })
+        .collect()
+}
+
+/// move a misplaced entry into the quarantine, keeping its path relative to the cargo home
+fn quarantine(path: &Path, cargo_home: &Path, quarantine_dir: &Path) -> Result<PathBuf, Error> {
+    let relative = path.strip_prefix(cargo_home).unwrap_or(path);
+    let target = quarantine_dir.join(relative);
+    let failed = |error| Error::QuarantineFailed(path.to_path_buf(), error);
+
+    fs::create_dir_all(target.parent().unwrap()).map_err(failed)?;
+    fs::rename(path, &target).map_err(failed)?;
+    Ok(target)
+}
+
+/// check the layout of the cache components and the crate sources and git checkouts,
+/// report, quarantine or remove the entries with problems
+pub(crate) fn doctor(ccp: &CargoCachePaths, fix: DoctorFix, dry_run: bool) -> Result<(), Error> {
+    println!("Checking the layout of the cache...\n");
+
+    let misplaced = all_misplaced(ccp);
+    if misplaced.is_empty() {
+        println!("No misplaced entries found.");
+    } else {
+        for entry in &misplaced {
+            println!("{}", entry.details(&ccp.cargo_home));
+        }
+        let total_size: u64 = misplaced.iter().map(|entry| entry.size).sum();
+        println!(
+            "\nFound {} misplaced entries ({}).",
+            misplaced.len(),
+            total_size.format_size(DECIMAL)
+        );
+    }
+
+    println!("\nChecking crate sources and git checkouts for names that only differ by case and long paths...\n");
+
+    let unportable = all_unportable(ccp);
+    if unportable.is_empty() {
+        println!("No problems found.");
+    } else {
+        for entry in &unportable {
+            println!("{}", entry.details(&ccp.cargo_home));
+        }
+        let total_size: u64 = unportable.iter().map(|entry| entry.size).sum();
+        println!(
+            "\nFound {} crate sources and git checkouts ({}) that are not extracted correctly on case-insensitive \
+            file systems (macOS, Windows) or without long path support (Windows). \
+            Remove them so that cargo extracts them again.",
+            unportable.len(),
+            total_size.format_size(DECIMAL)
+        );
+    }
+
+    let entries = misplaced
+        .iter()
+        .map(|entry| (&entry.path, entry.size))
+        .chain(unportable.iter().map(|entry| (&entry.path, entry.size)))
+        .collect::<Vec<(&PathBuf, u64)>>();
+    if entries.is_empty() {
+        return Ok(());
+    }
+
+    // one directory per run so that entries of different runs never collide
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    let quarantine_dir = ccp
+        .cargo_home
+        .join(QUARANTINE_DIR_NAME)
+        .join(seconds.to_string());
+
+    match fix {
+        DoctorFix::Report => println!(
+            "Run \"cargo cache doctor --quarantine\" to move them to \"{}\" or \"cargo cache doctor --remove\" to remove them.",
+            ccp.cargo_home.join(QUARANTINE_DIR_NAME).display()
+        ),
+        DoctorFix::Quarantine => {
+            println!();
+            for (path, _) in &entries {
+                if dry_run {
+                    println!(
+                        "dry-run: would move '{}' to '{}'",
+                        path.display(),
+                        quarantine_dir.display()
+                    );
+                } else {
+                    let target = quarantine(path, &ccp.cargo_home, &quarantine_dir)?;
+                    println!("moved '{}' to '{}'", path.display(), target.display());
+                }
+            }
+        }
+        DoctorFix::Remove => {
+            println!();
+            let mut size_changed = false;
+            for (path, size) in &entries {
+                remove_file(
+                    path,
+                    dry_run,
+                    &mut size_changed,
+                    Some(format!("removing: '{}'", path.display())),
+                    &DryRunMessage::Default,
+                    Some(*size),
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod doctor_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn misplaced_entries() {
+        let tmp = tempfile::tempdir().unwrap();
+        let cargo_home = tmp.path();
+        let ccp = CargoCachePaths::new(cargo_home.to_path_buf()).unwrap();
+        let registry = "index.crates.io-6f17d22bba15001f";
+
+        // everything where it belongs
+        fs::create_dir_all(ccp.registry_index.join(registry)).unwrap();
+        fs::create_dir_all(ccp.registry_pkg_cache.join(registry)).unwrap();
+        fs::write(
+            ccp.registry_pkg_cache
+                .join(registry)
+                .join("foo-1.0.0.crate"),
+            "archive",
+        )
+        .unwrap();
+        fs::create_dir_all(ccp.registry_sources.join(registry).join("foo-1.0.0")).unwrap();
+        fs::create_dir_all(ccp.git_repos_bare.join("bar-1234")).unwrap();
+        fs::create_dir_all(ccp.git_checkouts.join("bar-1234").join("abcdef")).unwrap();
+        fs::write(ccp.registry_sources.join("CACHEDIR.TAG"), "tag").unwrap();
+        fs::write(
+            ccp.registry_sources.join(registry).join(".package-cache"),
+            "",
+        )
+        .unwrap();
+
+        assert_eq!(all_misplaced(&ccp), vec![]);
+
+        // an archive in the sources and a source in the archives
+        let archive_in_src = ccp.registry_sources.join(registry).join("foo-1.0.0.crate");
+        fs::write(&archive_in_src, "archive").unwrap();
+        let src_in_cache = ccp.registry_pkg_cache.join(registry).join("foo-1.0.0");
+        fs::create_dir_all(&src_in_cache).unwrap();
+        let file_in_db = ccp.git_repos_bare.join("bar-1234.tar");
+        fs::write(&file_in_db, "tar").unwrap();
+
+        let misplaced = all_misplaced(&ccp);
+        assert_eq!(
+            misplaced,
+            vec![
+                Misplaced {
+                    size: size_of_path(&src_in_cache),
+                    path: src_in_cache,
+                    expected: Expected::CrateArchive,
+                    found: PathKind::Directory,
+                },
+                Misplaced {
+                    path: archive_in_src.clone(),
+                    expected: Expected::Directory,
+                    found: PathKind::File,
+                    size: 7,
+                },
+                Misplaced {
+                    path: file_in_db,
+                    expected: Expected::Directory,
+                    found: PathKind::File,
+                    size: 3,
+                },
+            ]
+        );
+        assert_eq!(
+            misplaced[1].details(cargo_home),
+            format!(
+                "registry/src/{registry}/foo-1.0.0.crate: expected a directory, found a file (7 B)"
+            )
+        );
+
+        // quarantine keeps the path relative to the cargo home
+        let quarantine_dir = cargo_home.join(QUARANTINE_DIR_NAME).join("1");
+        let target = quarantine(&archive_in_src, cargo_home, &quarantine_dir).unwrap();
+        assert_eq!(
+            target,
+            quarantine_dir
+                .join("registry/src")
+                .join(registry)
+                .join("foo-1.0.0.crate")
+        );
+        assert!(target.is_file());
+        assert!(!archive_in_src.exists());
+    }
+
+    #[test]
+    fn unportable_sources() {
+        let tmp = tempfile::tempdir().unwrap();
+        let cargo_home = tmp.path();
+        let ccp = CargoCachePaths::new(cargo_home.to_path_buf()).unwrap();
+        let registry = "index.crates.io-6f17d22bba15001f";
+
+        let fine = ccp.registry_sources.join(registry).join("fine-1.0.0");
+        fs::create_dir_all(fine.join("src")).unwrap();
+        fs::write(fine.join("src/lib.rs"), "").unwrap();
+        fs::write(fine.join("Cargo.toml"), "").unwrap();
+        assert_eq!(all_unportable(&ccp), vec![]);
+
+        // the file system of the test may be case-insensitive itself
+        let cased = ccp.registry_sources.join(registry).join("cased-1.0.0");
+        fs::create_dir_all(cased.join("src")).unwrap();
+        fs::write(cased.join("src/Foo.rs"), "a").unwrap();
+        fs::write(cased.join("src/foo.rs"), "b").unwrap();
+        let case_sensitive = fs::read_dir(cased.join("src")).unwrap().count() == 2;
+
+        let long = ccp.git_checkouts.join("long-1234").join("abcdef");
+        let long_dir = long.join("a".repeat(200)).join("b".repeat(100));
+        fs::create_dir_all(&long_dir).unwrap();
+        let long_file = long_dir.join("lib.rs");
+        fs::write(&long_file, "").unwrap();
+
+        let mut expected = Vec::new();
+        if case_sensitive {
+            expected.push(Unportable {
+                path: cased.clone(),
+                problems: vec![Problem::CaseCollision(
+                    PathBuf::from("src/Foo.rs"),
+                    PathBuf::from("src/foo.rs"),
+                )],
+                size: size_of_path(&cased),
+            });
+        }
+        let length = long_file.to_string_lossy().chars().count();
+        expected.push(Unportable {
+            size: size_of_path(&long),
+            path: long,
+            problems: vec![Problem::PathTooLong(long_file, length)],
+        });
+        assert_eq!(all_unportable(&ccp), expected);
+
+        assert_eq!(
+            Unportable {
+                path: cased,
+                problems: vec![Problem::CaseCollision(
+                    PathBuf::from("src/Foo.rs"),
+                    PathBuf::from("src/foo.rs"),
+                )],
+                size: 2,
+            }
+            .details(cargo_home),
+            format!(
+                "registry/src/{registry}/cased-1.0.0: \"src/Foo.rs\" and \"src/foo.rs\" only differ by case (2 B)"
+            )
+        );
+    }
+}
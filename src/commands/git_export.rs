@@ -0,0 +1,246 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// "cargo cache git export <repo> <dir>" command
+// clone a bare repo that cargo cached in git/db into a directory to inspect or patch the source of
+// a git dependency without downloading it again.
+// Cargo does not keep the upstream url and stores what it fetched as refs/remotes/origin/<branch>
+// (branches) and refs/commit/<sha> (pinned revisions), so a plain clone would miss most of it.
+// We fetch all of these from the cached repo and check out the newest commit unless --rev is given.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use git2::build::CheckoutBuilder;
+use git2::{Commit, Repository};
+
+use crate::git::is_git_repo;
+use crate::library::{CargoCachePaths, Error};
+
+/// what we fetch from the cached repo, this keeps the ref names cargo uses
+const REFSPECS: &[&str] = &[
+    "+refs/heads/*:refs/remotes/origin/*",
+    "+refs/remotes/origin/*:refs/remotes/origin/*",
+    "+refs/commit/*:refs/commit/*",
+    "+refs/tags/*:refs/tags/*",
+];
+
+/// the name of a repo in git/db without the hash: "serde-1ecc6299db9ec823" => "serde"
+fn repo_name(dir_name: &str) -> &str {
+    dir_name
+        .rsplit_once('-')
+        .map_or(dir_name, |(name, _hash)| name)
+}
+
+/// find the cached bare repo by its directory name or by its name without the hash
+fn find_repo(git_db: &Path, repo: &str) -> Result<PathBuf, Error> {
+    let mut repos: Vec<PathBuf> = fs::read_dir(git_db)
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .map(|entry| entry.path())
+                .filter(|path| is_git_repo(path))
+                .collect()
+        })
+        .unwrap_or_default();
+    repos.sort();
+
+    let dir_name = |path: &PathBuf| path.file_name().unwrap().to_string_lossy().to_string();
+
+    if let Some(exact) = repos.iter().find(|path| dir_name(path) == repo) {
+        return Ok(exact.clone());
+    }
+
+    let mut matching = repos
+        .into_iter()
+        .filter(|path| repo_name(&dir_name(path)) == repo)
+        .collect::<Vec<PathBuf>>();
+    match matching.len() {
+        0 => Err(Error::GitExportRepoNotFound(repo.to_string())),
+        1 => Ok(matching.remove(0)),
+        _ => Err(Error::GitExportAmbiguousRepo(
+            repo.to_string(),
+            matching.iter().map(dir_name).collect(),
+        )),
+    }
+}
+
+/// the most recent commit any of the refs of the repo points to
+fn newest_commit(repo: &Repository) -> Result<Option<(Commit<'_>, String)>, git2::Error> {
+    let mut newest: Option<(Commit<'_>, String)> = None;
+    for reference in repo.references()? {
+        let reference = reference?;
+        let commit = match reference.peel_to_commit() {
+            Ok(commit) => commit,
+            // refs to blobs or trees
+            Err(_) => continue,
+        };
+        let is_newer = newest
+            .as_ref()
+            .map_or(true, |(newest, _)| commit.time() > newest.time());
+        if is_newer {
+            let name = reference.shorthand().unwrap_or_default().to_string();
+            newest = Some((commit, name));
+        }
+    }
+    Ok(newest)
+}
+
+/// clone the bare repo into `dest` and check out `rev` or the newest commit
+/// returns a description of what was checked out
+fn export(source: &Path, dest: &Path, rev: Option<&str>) -> Result<String, git2::Error> {
+    let repo = Repository::init(dest)?;
+    let url = source
+        .to_str()
+        .ok_or_else(|| git2::Error::from_str("the path of the cached repo is not valid utf-8"))?;
+    let mut remote = repo.remote("origin", url)?;
+    remote.fetch(REFSPECS, None, None)?;
+
+    let (commit, description) = match rev {
+        Some(rev) => {
+            let commit = repo.revparse_single(rev)?.peel_to_commit()?;
+            (commit, rev.to_string())
+        }
+        None => newest_commit(&repo)?
+            .ok_or_else(|| git2::Error::from_str("the cached repo does not contain any commits"))?,
+    };
+
+    repo.checkout_tree(commit.as_object(), Some(CheckoutBuilder::new().force()))?;
+    repo.set_head_detached(commit.id())?;
+
+    let id = commit.id().to_string();
+    Ok(format!("{} ({description})", &id[..id.len().min(10)]))
+}
+
+/// export the cached git repo `repo` into the directory `dest`
+pub(crate) fn git_export(
+    ccp: &CargoCachePaths,
+    repo: &str,
+    dest: &str,
+    rev: Option<&str>,
+) -> Result<(), Error> {
+    let source = find_repo(&ccp.git_repos_bare, repo)?;
+    let dest = PathBuf::from(dest);
+
+    // never clone over something that is already there
+    let is_empty_dir = fs::read_dir(&dest).map_or(false, |mut entries| entries.next().is_none());
+    if dest.exists() && !is_empty_dir {
+        return Err(Error::GitExportFailed(
+            dest,
+            String::from("the destination already exists and is not an empty directory"),
+        ));
+    }
+
+    let checked_out = export(&source, &dest, rev)
+        .map_err(|error| Error::GitExportFailed(dest.clone(), error.message().to_string()))?;
+
+    println!(
+        "Exported \"{}\" to \"{}\", checked out {checked_out}.",
+        source.display(),
+        dest.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod git_export_tests {
+    use super::*;
+    use git2::{Oid, Signature};
+    use pretty_assertions::assert_eq;
+
+    /// a bare repo that looks like cargo fetched the "master" branch and one pinned revision
+    fn cached_repo(path: &Path) -> (Oid, Oid) {
+        let repo = Repository::init_bare(path).unwrap();
+        let commit = |content: &str, time: i64, parents: &[&Commit<'_>]| {
+            let blob = repo.blob(content.as_bytes()).unwrap();
+            let mut tree = repo.treebuilder(None).unwrap();
+            let _ = tree.insert("lib.rs", blob, 0o100_644).unwrap();
+            let tree = repo.find_tree(tree.write().unwrap()).unwrap();
+            let signature = Signature::new("a", "a@b.c", &git2::Time::new(time, 0)).unwrap();
+            repo.commit(None, &signature, &signature, content, &tree, parents)
+                .unwrap()
+        };
+
+        let old = commit("fn old() {}", 1_000_000, &[]);
+        let new = commit("fn new() {}", 2_000_000, &[&repo.find_commit(old).unwrap()]);
+        let _ = repo
+            .reference(&format!("refs/commit/{old}"), old, false, "")
+            .unwrap();
+        let _ = repo
+            .reference("refs/remotes/origin/master", new, false, "")
+            .unwrap();
+        (old, new)
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(repo_name("serde-1ecc6299db9ec823"), "serde");
+        assert_eq!(repo_name("cargo-cache-1ecc6299db9ec823"), "cargo-cache");
+        assert_eq!(repo_name("nohash"), "nohash");
+    }
+
+    #[test]
+    fn find() {
+        let tmp = tempfile::tempdir().unwrap();
+        let git_db = tmp.path();
+        let _ = Repository::init_bare(git_db.join("foo-1234")).unwrap();
+        let _ = Repository::init_bare(git_db.join("bar-1234")).unwrap();
+        let _ = Repository::init_bare(git_db.join("bar-5678")).unwrap();
+        fs::create_dir(git_db.join("baz-1234")).unwrap();
+
+        assert_eq!(find_repo(git_db, "foo").unwrap(), git_db.join("foo-1234"));
+        assert_eq!(
+            find_repo(git_db, "bar-5678").unwrap(),
+            git_db.join("bar-5678")
+        );
+        assert!(matches!(
+            find_repo(git_db, "bar"),
+            Err(Error::GitExportAmbiguousRepo(_, repos)) if repos == ["bar-1234", "bar-5678"]
+        ));
+        // not a git repo
+        assert!(matches!(
+            find_repo(git_db, "baz"),
+            Err(Error::GitExportRepoNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn export_newest_and_rev() {
+        let tmp = tempfile::tempdir().unwrap();
+        let source = tmp.path().join("git/db/foo-1234");
+        let (old, new) = cached_repo(&source);
+
+        let newest = tmp.path().join("newest");
+        let checked_out = export(&source, &newest, None).unwrap();
+        assert_eq!(
+            checked_out,
+            format!("{} (origin/master)", &new.to_string()[..10])
+        );
+        assert_eq!(
+            fs::read_to_string(newest.join("lib.rs")).unwrap(),
+            "fn new() {}"
+        );
+
+        let pinned = tmp.path().join("pinned");
+        let _ = export(&source, &pinned, Some(&old.to_string())).unwrap();
+        assert_eq!(
+            fs::read_to_string(pinned.join("lib.rs")).unwrap(),
+            "fn old() {}"
+        );
+        assert_eq!(
+            Repository::open(&pinned)
+                .unwrap()
+                .head()
+                .unwrap()
+                .target()
+                .unwrap(),
+            old
+        );
+    }
+}
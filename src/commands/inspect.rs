@@ -0,0 +1,367 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// "cargo cache inspect <crate> [version]" command
+// list the largest files of a cached crate to find out why it is so big (bundled test data,
+// vendored C sources...).
+// If the crate is extracted in registry/src we look at the files on disk, otherwise we list the
+// contents of the .crate archive in registry/cache (this needs the "verify" feature for tar.gz
+// support).
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use humansize::{FormatSize, DECIMAL};
+use walkdir::WalkDir;
+
+use crate::library::{CargoCachePaths, Error};
+use crate::tables::{two_row_table, TableLine};
+use crate::walk::guarded;
+
+/// a cached version of a crate, extracted and / or as archive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct CachedCrate {
+    /// the registry directory name, "index.crates.io-6f17d22bba15001f"
+    pub(crate) registry: String,
+    pub(crate) version: String,
+    pub(crate) archive: Option<PathBuf>,
+    pub(crate) source: Option<PathBuf>,
+}
+
+/// the version part of "serde-1.0.188" or "serde-1.0.188.crate" if it is a version of `name`
+/// "serde_json-1.0.0" and "serde-json-1.0.0" are not versions of "serde"
+fn version_of<'a>(entry: &'a str, name: &str) -> Option<&'a str> {
+    let version = entry.strip_prefix(name)?.strip_prefix('-')?;
+    let version = version.strip_suffix(".crate").unwrap_or(version);
+    version
+        .starts_with(|c: char| c.is_ascii_digit())
+        .then_some(version)
+}
+
+/// sort key so that "1.10.0" comes after "1.9.0" and "1.0.0-beta" before "1.0.0"
+fn version_key(version: &str) -> (Vec<u64>, bool, String) {
+    let (release, pre) = version.split_once('-').map_or((version, ""), |split| split);
+    let release = release
+        .split('.')
+        .map(|part| part.parse().unwrap_or_default())
+        .collect();
+    (release, pre.is_empty(), pre.to_string())
+}
+
+/// all cached versions of the crate in all registries, oldest first
+pub(crate) fn find_cached_versions(ccp: &CargoCachePaths, name: &str) -> Vec<CachedCrate> {
+    let entries = |dir: &Path| -> Vec<PathBuf> {
+        fs::read_dir(dir)
+            .map(|entries| {
+                entries
+                    .filter_map(Result::ok)
+                    .map(|entry| entry.path())
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+
+    let mut registries = entries(&ccp.registry_pkg_cache);
+    registries.extend(entries(&ccp.registry_sources));
+    let mut registries = registries
+        .iter()
+        .filter(|path| path.is_dir())
+        .map(|path| path.file_name().unwrap().to_os_string())
+        .collect::<Vec<_>>();
+    registries.sort();
+    registries.dedup();
+
+    let mut versions: Vec<CachedCrate> = Vec::new();
+    for registry in registries {
+        let mut found = entries(&ccp.registry_pkg_cache.join(&registry));
+        found.extend(entries(&ccp.registry_sources.join(&registry)));
+
+        for path in found {
+            let file_name = path.file_name().unwrap().to_string_lossy().to_string();
+            let version = match version_of(&file_name, name) {
+                Some(version) => version.to_string(),
+                None => continue,
+            };
+            let registry = registry.to_string_lossy().to_string();
+            let position = versions
+                .iter()
+                .position(|krate| krate.registry == registry && krate.version == version);
+            let krate = if let Some(position) = position {
+                &mut versions[position]
+            } else {
+                versions.push(CachedCrate {
+                    registry,
+                    version,
+                    archive: None,
+                    source: None,
+                });
+                versions.last_mut().unwrap()
+            };
+            if path.is_dir() {
+                krate.source = Some(path);
+            } else {
+                krate.archive = Some(path);
+            }
+        }
+    }
+
+    versions.sort_by_cached_key(|krate| (version_key(&krate.version), krate.registry.clone()));
+    versions
+}
+
+/// the files of the extracted crate source and their sizes, relative to the source directory
+fn files_of_source(source: &Path) -> Vec<(PathBuf, u64)> {
+    guarded(WalkDir::new(source), |_| true)
+        .filter(|entry| entry.file_type().is_file() && entry.file_name() != ".cargo-ok")
+        .map(|entry| {
+            let size = entry.metadata().map_or(0, |metadata| metadata.len());
+            let path = entry.path().strip_prefix(source).unwrap().to_path_buf();
+            (path, size)
+        })
+        .collect()
+}
+
+/// the files inside the .crate archive and their (uncompressed) sizes
+#[cfg(feature = "verify")]
+fn files_of_archive(archive: &Path) -> Result<Vec<(PathBuf, u64)>, Error> {
+    use flate2::read::GzDecoder;
+    use tar::Archive;
+
+    let failed = |error: std::io::Error| Error::InspectFailed(archive.to_path_buf(), error);
+    let mut tar = Archive::new(GzDecoder::new(fs::File::open(archive).map_err(failed)?));
+
+    let mut files = Vec::new();
+    for entry in tar.entries().map_err(failed)? {
+        let entry = entry.map_err(failed)?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        // paths inside the archive start with "<crate>-<version>/"
+        let path = entry.path().map_err(failed)?;
+        let path = path.iter().skip(1).collect::<PathBuf>();
+        files.push((path, entry.size()));
+    }
+    Ok(files)
+}
+
+#[cfg(not(feature = "verify"))]
+fn files_of_archive(_archive: &Path) -> Result<Vec<(PathBuf, u64)>, Error> {
+    Err(Error::FeatureDisabled(
+        String::from("inspect of crates that are not extracted"),
+        "verify",
+    ))
+}
+
+/// the table of the `limit` largest files, the rest is summed up
+fn largest_files_table(mut files: Vec<(PathBuf, u64)>, limit: usize) -> Vec<TableLine> {
+    // largest first, same sizes by path
+    files.sort_by(|(path_a, size_a), (path_b, size_b)| {
+        size_b.cmp(size_a).then_with(|| path_a.cmp(path_b))
+    });
+
+    let mut lines = files
+        .iter()
+        .take(limit)
+        .map(|(path, size)| {
+            TableLine::new(
+                1,
+                &format!("{}: ", path.display()),
+                &size.format_size(DECIMAL),
+            )
+        })
+        .collect::<Vec<TableLine>>();
+
+    if files.len() > limit {
+        let rest = &files[limit..];
+        let rest_size: u64 = rest.iter().map(|(_, size)| size).sum();
+        lines.push(TableLine::new(
+            1,
+            &format!("{} more files: ", rest.len()),
+            &rest_size.format_size(DECIMAL),
+        ));
+    }
+    lines
+}
+
+/// list the largest files of a cached crate
+pub(crate) fn inspect(
+    ccp: &CargoCachePaths,
+    name: &str,
+    version: Option<&str>,
+    limit: usize,
+) -> Result<(), Error> {
+    let versions = find_cached_versions(ccp, name);
+    let krate = match version {
+        Some(version) => versions.iter().find(|krate| krate.version == version),
+        // the newest one
+        None => versions.last(),
+    }
+    .ok_or_else(|| {
+        let spec = version.map_or(name.to_string(), |version| format!("{name}-{version}"));
+        Error::InspectCrateNotFound(spec)
+    })?;
+
+    // the same version can be cached for several registries
+    let mut all = versions
+        .iter()
+        .map(|cached| cached.version.as_str())
+        .collect::<Vec<&str>>();
+    all.dedup();
+    if version.is_none() && all.len() > 1 {
+        println!(
+            "Found {} cached versions of {name}: {}\nInspecting the newest one, pass a version to inspect another one.\n",
+            all.len(),
+            all.join(", ")
+        );
+    }
+
+    // prefer the files on disk, these are exact
+    let (files, origin) = match (&krate.source, &krate.archive) {
+        (Some(source), _) => (files_of_source(source), "extracted source"),
+        (None, Some(archive)) => (files_of_archive(archive)?, "archive"),
+        (None, None) => unreachable!("a cached crate is either extracted or an archive"),
+    };
+
+    let total_size: u64 = files.iter().map(|(_, size)| size).sum();
+    let mut lines = vec![TableLine::new(
+        0,
+        &format!(
+            "{name}-{} ({}), {} files from the {origin}:\n",
+            krate.version,
+            krate.registry,
+            files.len()
+        ),
+        &String::new(),
+    )];
+    lines.push(TableLine::new(
+        0,
+        &"Total: ",
+        &total_size.format_size(DECIMAL),
+    ));
+    if let Some(archive) = &krate.archive {
+        let archive_size = fs::metadata(archive).map_or(0, |metadata| metadata.len());
+        lines.push(TableLine::new(
+            0,
+            &"Archive (compressed): ",
+            &archive_size.format_size(DECIMAL),
+        ));
+    }
+    lines.push(TableLine::new(0, &"Largest files:", &String::new()));
+    lines.extend(largest_files_table(files, limit));
+
+    println!("{}", two_row_table(2, lines, false));
+    Ok(())
+}
+
+#[cfg(test)]
+mod inspect_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn versions() {
+        assert_eq!(version_of("serde-1.0.188", "serde"), Some("1.0.188"));
+        assert_eq!(version_of("serde-1.0.188.crate", "serde"), Some("1.0.188"));
+        assert_eq!(
+            version_of("serde-1.0.0-beta.1.crate", "serde"),
+            Some("1.0.0-beta.1")
+        );
+        assert_eq!(version_of("serde_json-1.0.0", "serde"), None);
+        assert_eq!(version_of("serde-json-1.0.0", "serde"), None);
+        assert_eq!(version_of("serde", "serde"), None);
+
+        let mut versions = vec!["1.10.0", "1.9.0", "1.0.0", "1.0.0-beta"];
+        versions.sort_by_key(|version| version_key(version));
+        assert_eq!(versions, vec!["1.0.0-beta", "1.0.0", "1.9.0", "1.10.0"]);
+    }
+
+    #[test]
+    fn cached_versions() {
+        let tmp = tempfile::tempdir().unwrap();
+        let ccp = CargoCachePaths::new(tmp.path().to_path_buf()).unwrap();
+        let registry = "index.crates.io-6f17d22bba15001f";
+        let archives = ccp.registry_pkg_cache.join(registry);
+        let sources = ccp.registry_sources.join(registry);
+        fs::create_dir_all(&archives).unwrap();
+        fs::create_dir_all(sources.join("foo-1.10.0").join("src")).unwrap();
+        fs::write(archives.join("foo-1.10.0.crate"), "archive").unwrap();
+        fs::write(archives.join("foo-1.9.0.crate"), "archive").unwrap();
+        fs::write(archives.join("foo-bar-1.0.0.crate"), "archive").unwrap();
+        fs::write(sources.join("foo-1.10.0").join("src").join("lib.rs"), "x").unwrap();
+        fs::write(sources.join("foo-1.10.0").join(".cargo-ok"), "ok").unwrap();
+
+        let versions = find_cached_versions(&ccp, "foo");
+        assert_eq!(
+            versions,
+            vec![
+                CachedCrate {
+                    registry: registry.to_string(),
+                    version: String::from("1.9.0"),
+                    archive: Some(archives.join("foo-1.9.0.crate")),
+                    source: None,
+                },
+                CachedCrate {
+                    registry: registry.to_string(),
+                    version: String::from("1.10.0"),
+                    archive: Some(archives.join("foo-1.10.0.crate")),
+                    source: Some(sources.join("foo-1.10.0")),
+                },
+            ]
+        );
+        assert_eq!(
+            files_of_source(&sources.join("foo-1.10.0")),
+            vec![(PathBuf::from("src").join("lib.rs"), 1)]
+        );
+    }
+
+    #[test]
+    fn largest_files() {
+        let files = vec![
+            (PathBuf::from("src/lib.rs"), 100),
+            (PathBuf::from("tests/data.bin"), 5123),
+            (PathBuf::from("Cargo.toml"), 100),
+            (PathBuf::from("README.md"), 10),
+        ];
+        let table = two_row_table(2, largest_files_table(files, 3), true);
+        assert_eq!(
+            table,
+            "  tests/data.bin:   5.12 kB
+  Cargo.toml:        100  B
+  src/lib.rs:        100  B
+  1 more files:       10  B
+"
+        );
+    }
+
+    #[cfg(feature = "verify")]
+    #[test]
+    fn archive() {
+        use flate2::write::GzEncoder;
+        use flate2::Compression;
+
+        let tmp = tempfile::tempdir().unwrap();
+        let archive = tmp.path().join("foo-1.0.0.crate");
+        let mut builder = tar::Builder::new(GzEncoder::new(
+            fs::File::create(&archive).unwrap(),
+            Compression::default(),
+        ));
+        let mut header = tar::Header::new_gnu();
+        header.set_size(5);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "foo-1.0.0/src/lib.rs", &b"12345"[..])
+            .unwrap();
+        let _ = builder.into_inner().unwrap().finish().unwrap();
+
+        assert_eq!(
+            files_of_archive(&archive).unwrap(),
+            vec![(PathBuf::from("src").join("lib.rs"), 5)]
+        );
+    }
+}
@@ -14,7 +14,7 @@
 /// target directory sizes which are "local" to the project
 /// We print the total size of each subdirectory that we know to be rust-related (so debug/release/package etc)
 /// and sum up the rest under "other:"; the output can look like this:
-/// ````
+/// ````text
 /// Project "/home/matthias/vcs/github/cargo-cache"
 /// Target dir: /home/matthias/vcs/github/cargo-cache/target
 ///
@@ -23,19 +23,54 @@
 /// release:          224.26 MB
 /// other:            360.57 MB
 /// ````
+/// If cargo is configured to put intermediate build files into a separate build directory
+/// (`build.build-dir`), that directory is reported separately.
+/// `--clean-build-dir` removes the intermediate build files but keeps the final artifacts.
+/// `--clean-stale-deps` removes the build output of dependencies that are no longer in the Cargo.lock,
+/// for example the old versions left behind after a `cargo update`.
+/// `--clean-stale-build-scripts` only removes the `build/<package>-<hash>` dirs of these: the compiled
+/// build scripts and their output (often compiled C libraries), usually the biggest part of them.
+/// For workspaces with several members, the size of the build output of each member is listed as well.
+/// `--recursive <dir>` looks for all projects below a directory and lists the sizes of their target dirs.
+/// `--clean` removes the target dirs (of the project or of all projects found with `--recursive`),
+/// `--clean-release-only` only their release build output, `--keep-recent N` spares the N most recently built projects.
 use std::env;
 use std::ffi::OsStr;
 use std::fmt::Write as _;
-use std::fs::read_dir;
+use std::fs::{self, read_dir};
 use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
-use cargo_metadata::MetadataCommand;
+use cargo_metadata::{Metadata, MetadataCommand};
 use humansize::{FormatSize, DECIMAL};
+use rayon::prelude::*;
 use walkdir::WalkDir;
 
 use crate::library;
 use crate::library::Error;
+use crate::remove::remove_with_default_message;
 use crate::tables::*;
+use crate::walk::guarded;
+
+/// directories inside a profile dir (`target/debug`) which only hold intermediate build files
+const INTERMEDIATE_DIRS: [&str; 4] = [".fingerprint", "build", "deps", "incremental"];
+
+/// the directories cargo writes build output of a project to
+struct BuildDirs {
+    /// final artifacts (binaries, libraries, docs...) are placed here, this is `target/` by default
+    artifact_dir: PathBuf,
+    /// intermediate build files are placed here, same as `artifact_dir` unless `build.build-dir` is set
+    build_dir: PathBuf,
+}
+
+/// what `--clean` and `--clean-release-only` remove
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct CleanTargets {
+    /// only the release build output (`release/`, `<target triple>/release/`), not the whole target dir
+    pub(crate) release_only: bool,
+    /// the target dirs of this many of the most recently built projects are not touched
+    pub(crate) keep_recent: usize,
+}
 
 /// Checks if a cargo manifest named "Cargo.toml" is found in the current directory.
 /// If yes, return a path to it, if not, return None
@@ -75,30 +110,378 @@ pub(crate) fn get_manifest() -> Result<PathBuf, Error> {
     }
 }
 
+/// run `cargo metadata` on the manifest and extract the build directories
+/// the target dir may be configured in the config.toml of the cargo home, so cargo needs ours
+fn build_dirs(manifest: &Path, cargo_home: &Path) -> (Metadata, BuildDirs) {
+    let output = MetadataCommand::new()
+        .manifest_path(manifest)
+        .no_deps()
+        .env("CARGO_HOME", cargo_home)
+        .cargo_command()
+        .output();
+
+    let stdout = match output {
+        Ok(output) if output.status.success() => {
+            String::from_utf8_lossy(&output.stdout).into_owned()
+        }
+        Ok(output) => panic!(
+            "Failed to parse manifest: '{}'\nError: '{}'",
+            &manifest.display(),
+            String::from_utf8_lossy(&output.stderr)
+        ),
+        Err(error) => panic!(
+            "Failed to parse manifest: '{}'\nError: '{:?}'",
+            &manifest.display(),
+            error
+        ),
+    };
+
+    let metadata = MetadataCommand::parse(&stdout).unwrap_or_else(|error| {
+        panic!(
+            "Failed to parse manifest: '{}'\nError: '{:?}'",
+            &manifest.display(),
+            error
+        )
+    });
+
+    let artifact_dir = PathBuf::from(&metadata.target_directory);
+    // "build_directory" is only emitted by newer versions of cargo which can split intermediate
+    // build files from the final artifacts, cargo_metadata does not know about it yet
+    let build_dir = serde_json::from_str::<serde_json::Value>(&stdout)
+        .ok()
+        .and_then(|json| json["build_directory"].as_str().map(PathBuf::from))
+        .unwrap_or_else(|| artifact_dir.clone());
+
+    (
+        metadata,
+        BuildDirs {
+            artifact_dir,
+            build_dir,
+        },
+    )
+}
+
+/// find all directories inside a build dir that only contain intermediate build files
+/// we look for profile directories (`debug`, `release`, `x86_64-unknown-linux-gnu/debug`...)
+/// by checking where cargo put its `.fingerprint` directories
+fn intermediate_dirs(build_dir: &Path) -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = WalkDir::new(build_dir)
+        .min_depth(2)
+        .max_depth(3)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_dir() && entry.file_name() == ".fingerprint")
+        .filter_map(|entry| entry.path().parent().map(Path::to_path_buf))
+        .flat_map(|profile_dir| {
+            INTERMEDIATE_DIRS
+                .iter()
+                .map(move |dir| profile_dir.join(dir))
+        })
+        .filter(|dir| dir.is_dir())
+        .collect();
+    dirs.sort();
+    dirs
+}
+
+/// the names cargo uses for the build output of a package:
+/// the package name (`.fingerprint`, `build`) and the names of its targets with "-" replaced by "_" (`deps`, `incremental`)
+fn output_names(package: &cargo_metadata::Package) -> Vec<String> {
+    let mut names: Vec<String> = package
+        .targets
+        .iter()
+        .map(|target| target.name.replace('-', "_"))
+        .collect();
+    names.push(package.name.clone());
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// check if a file name inside a profile dir belongs to one of the given names:
+/// `<name>-<hash>`, `lib<name>-<hash>.rlib`, `<name>-<hash>.d` or the final artifact `<name>` / `lib<name>.so`...
+fn belongs_to(file_name: &str, names: &[String]) -> bool {
+    let file_name = file_name.strip_prefix("lib").unwrap_or(file_name);
+    // cut off extensions
+    let stem = file_name.split('.').next().unwrap_or(file_name);
+    names.iter().any(|name| {
+        stem == name
+            || stem
+                .strip_prefix(name.as_str())
+                .and_then(|rest| rest.strip_prefix('-'))
+                .map_or(false, |hash| {
+                    !hash.is_empty() && hash.chars().all(|c| c.is_ascii_hexdigit())
+                })
+    })
+}
+
+/// the build output of a unit cargo compiled, found through its `.fingerprint/<package>-<hash>` dir
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Fingerprint {
+    /// the `.fingerprint/<package>-<hash>` dir
+    dir: PathBuf,
+    package: String,
+    /// the metadata hash, the files in `deps/` and the dirs in `build/` of the unit end with it
+    hash: String,
+    /// the version of a registry or git dependency, cargo does not record it for path dependencies
+    version: Option<String>,
+}
+
+/// the packages and versions of a Cargo.lock
+fn locked_packages(lockfile: &str) -> Vec<(String, String)> {
+    let mut packages = Vec::new();
+    let mut name = None;
+    for line in lockfile.lines().map(str::trim) {
+        if line == "[[package]]" {
+            name = None;
+        } else if let Some((key, value)) = line.split_once('=') {
+            let value = value.trim().trim_matches('"').to_string();
+            match key.trim() {
+                "name" => name = Some(value),
+                "version" => {
+                    if let Some(name) = name.take() {
+                        packages.push((name, value));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    packages
+}
+
+/// the version cargo recorded in the fingerprint, it only does that for running build scripts
+/// (`"local":[{"Precalculated":"1.0.188"}]`)
+fn fingerprint_version(dir: &Path) -> Option<String> {
+    read_dir(dir)
+        .ok()?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.extension() == Some(OsStr::new("json")))
+        .filter_map(|path| fs::read_to_string(path).ok())
+        .filter_map(|json| serde_json::from_str::<serde_json::Value>(&json).ok())
+        .find_map(|json| {
+            json["local"].as_array()?.iter().find_map(|local| {
+                local["Precalculated"]
+                    .as_str()
+                    .filter(|version| version.starts_with(|c: char| c.is_ascii_digit()))
+                    .map(str::to_string)
+            })
+        })
+}
+
+/// the version of a registry or git dependency from the sources listed in its dep-info file
+/// (`deps/serde-<hash>.d` or `build/serde-<hash>/build_script_build-<hash>.d`):
+/// `~/.cargo/registry/src/index.crates.io-6f17d22bba15001f/serde-1.0.188/src/lib.rs` => `1.0.188`
+fn dep_info_version(profile_dir: &Path, package: &str, hash: &str) -> Option<String> {
+    let suffix = format!("-{hash}.d");
+    let dep_infos = read_dir(profile_dir.join("deps"))
+        .into_iter()
+        .flatten()
+        .chain(
+            read_dir(profile_dir.join("build").join(format!("{package}-{hash}")))
+                .into_iter()
+                .flatten(),
+        )
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(OsStr::to_str)
+                .map_or(false, |name| name.ends_with(&suffix))
+        });
+    let prefix = format!("{package}-");
+    for dep_info in dep_infos {
+        let content = fs::read_to_string(dep_info).unwrap_or_default();
+        // "<output>: <source> <source>...", the first source is enough
+        let source = content
+            .split_once(": ")
+            .and_then(|(_output, sources)| sources.split_whitespace().next());
+        let version = source.and_then(|source| {
+            Path::new(source).components().find_map(|component| {
+                component
+                    .as_os_str()
+                    .to_str()?
+                    .strip_prefix(&prefix)
+                    .filter(|version| version.starts_with(|c: char| c.is_ascii_digit()))
+                    .map(str::to_string)
+            })
+        });
+        if version.is_some() {
+            return version;
+        }
+    }
+    None
+}
+
+/// the fingerprints inside a `.fingerprint` dir
+fn fingerprints(fingerprint_dir: &Path) -> Vec<Fingerprint> {
+    let profile_dir = fingerprint_dir.parent().unwrap_or(fingerprint_dir);
+    let mut fingerprints: Vec<Fingerprint> = read_dir(fingerprint_dir)
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let dir = entry.path();
+            let file_name = entry.file_name();
+            let (package, hash) = file_name.to_str()?.rsplit_once('-')?;
+            if package.is_empty() || hash.is_empty() || !hash.chars().all(|c| c.is_ascii_hexdigit())
+            {
+                return None;
+            }
+            Some(Fingerprint {
+                package: package.to_string(),
+                hash: hash.to_string(),
+                version: fingerprint_version(&dir)
+                    .or_else(|| dep_info_version(profile_dir, package, hash)),
+                dir,
+            })
+        })
+        .collect();
+    fingerprints.sort_by(|a, b| a.dir.cmp(&b.dir));
+    fingerprints
+}
+
+/// a unit is stale if the Cargo.lock has no package of its name and version anymore,
+/// without a version (path dependencies) only the name can be checked
+fn is_stale(fingerprint: &Fingerprint, locked: &[(String, String)]) -> bool {
+    !locked.iter().any(|(name, version)| {
+        name == &fingerprint.package
+            && fingerprint
+                .version
+                .as_ref()
+                .map_or(true, |fingerprint_version| fingerprint_version == version)
+    })
+}
+
+/// the fingerprint dir of a stale unit and everything in `deps/` and `build/` next to it that ends with its hash:
+/// `deps/libserde-<hash>.rlib`, `deps/serde-<hash>.d`, `build/serde-<hash>/`...
+fn stale_outputs(fingerprint: &Fingerprint) -> Vec<PathBuf> {
+    let mut outputs = vec![fingerprint.dir.clone()];
+    let profile_dir = match fingerprint.dir.parent().and_then(Path::parent) {
+        Some(profile_dir) => profile_dir,
+        None => return outputs,
+    };
+    let suffix = format!("-{}", fingerprint.hash);
+    for dir in ["deps", "build"] {
+        let mut matching: Vec<PathBuf> = read_dir(profile_dir.join(dir))
+            .into_iter()
+            .flatten()
+            .filter_map(Result::ok)
+            .filter(|entry| {
+                entry.file_name().to_str().map_or(false, |name| {
+                    // cut off extensions
+                    name.split('.').next().unwrap_or(name).ends_with(&suffix)
+                })
+            })
+            .map(|entry| entry.path())
+            .collect();
+        matching.sort();
+        outputs.extend(matching);
+    }
+    outputs
+}
+
+/// the build output of all units of the build dir whose package is not in the Cargo.lock anymore
+fn stale_dependencies(build_dir: &Path, locked: &[(String, String)]) -> Vec<PathBuf> {
+    intermediate_dirs(build_dir)
+        .into_iter()
+        .filter(|dir| dir.file_name() == Some(OsStr::new(".fingerprint")))
+        .flat_map(|fingerprint_dir| fingerprints(&fingerprint_dir))
+        .filter(|fingerprint| is_stale(fingerprint, locked))
+        .flat_map(|fingerprint| stale_outputs(&fingerprint))
+        .collect()
+}
+
+/// the `build/<package>-<hash>` dirs of the build dir whose package is not in the Cargo.lock anymore,
+/// and the ones cargo has no fingerprint of anymore, it never uses them again
+fn stale_build_script_outputs(build_dir: &Path, locked: &[(String, String)]) -> Vec<PathBuf> {
+    let mut outputs = Vec::new();
+    for fingerprint_dir in intermediate_dirs(build_dir)
+        .into_iter()
+        .filter(|dir| dir.file_name() == Some(OsStr::new(".fingerprint")))
+    {
+        let build_scripts_dir = match fingerprint_dir.parent() {
+            Some(profile_dir) => profile_dir.join("build"),
+            None => continue,
+        };
+        let fingerprints = fingerprints(&fingerprint_dir);
+        let mut stale: Vec<PathBuf> = read_dir(&build_scripts_dir)
+            .into_iter()
+            .flatten()
+            .filter_map(Result::ok)
+            .filter(|entry| {
+                entry
+                    .file_type()
+                    .map_or(false, |file_type| file_type.is_dir())
+            })
+            .filter(|entry| {
+                let name = entry.file_name();
+                match fingerprints
+                    .iter()
+                    .find(|fingerprint| fingerprint.dir.file_name() == Some(name.as_os_str()))
+                {
+                    Some(fingerprint) => is_stale(fingerprint, locked),
+                    None => true,
+                }
+            })
+            .map(|entry| entry.path())
+            .collect();
+        stale.sort();
+        outputs.extend(stale);
+    }
+    outputs
+}
+
+/// sum up the size of everything inside the profile dirs of the target and build dir that belongs to a package
+fn member_size(names: &[String], target_dir: &Path, build_dir: &Path) -> u64 {
+    // the dirs that contain the output, the intermediate dirs of the build dir and the profile dirs
+    let mut dirs: Vec<PathBuf> = intermediate_dirs(build_dir);
+    dirs.extend(intermediate_dirs(target_dir));
+    // the profile dirs (parents of the intermediate dirs) contain the final artifacts
+    let profile_dirs: Vec<PathBuf> = dirs
+        .iter()
+        .filter_map(|dir| dir.parent().map(Path::to_path_buf))
+        .collect();
+    dirs.extend(profile_dirs);
+    dirs.sort();
+    dirs.dedup();
+
+    dirs.iter()
+        .filter_map(|dir| read_dir(dir).ok())
+        .flatten()
+        .filter_map(Result::ok)
+        .filter(|entry| {
+            entry
+                .file_name()
+                .to_str()
+                .map_or(false, |name| belongs_to(name, names))
+        })
+        .map(|entry| library::size_of_path(&entry.path()))
+        .sum()
+}
+
 /// gather the sizes of subdirs of the `target` directory and prints a formatted table
 /// of the data to stdout
-pub(crate) fn local_subcmd() -> Result<(), Error> {
+#[allow(clippy::fn_params_excessive_bools)] // one per flag of the subcommand
+pub(crate) fn local_subcmd(
+    cargo_home: &Path,
+    clean_build_dir: bool,
+    clean_stale_deps: bool,
+    clean_stale_build_scripts: bool,
+    clean_targets: Option<CleanTargets>,
+    dry_run: bool,
+) -> Result<(), Error> {
     // padding of the final formatting of the table
     const MIN_PADDING: usize = 6;
 
     // find the closest manifest, traverse up if necessary
     let manifest = get_manifest()?;
 
-    // get the cargo metadata for the manifest
-    let metadata = MetadataCommand::new()
-        .manifest_path(&manifest)
-        .no_deps()
-        .exec()
-        .unwrap_or_else(|error| {
-            panic!(
-                "Failed to parse manifest: '{}'\nError: '{:?}'",
-                &manifest.display(),
-                error
-            )
-        });
-
-    // get the project target dir from the metadata
-    let target_dir = PathBuf::from(metadata.target_directory);
+    // get the project target dir (and build dir) from the metadata
+    let (metadata, build_dirs) = build_dirs(&manifest, cargo_home);
+    let target_dir = build_dirs.artifact_dir;
+    let build_dir = build_dirs.build_dir;
+    let separate_build_dir = build_dir != target_dir;
 
     // the target dir might not exist!
     if !target_dir.is_dir() {
@@ -124,7 +507,12 @@ pub(crate) fn local_subcmd() -> Result<(), Error> {
         eprintln!("{stdout}");
     }
 
-    writeln!(stdout, "Target dir: {}\n", target_dir.display()).unwrap();
+    if separate_build_dir {
+        writeln!(stdout, "Target dir: {}", target_dir.display()).unwrap();
+        writeln!(stdout, "Build dir: {}\n", build_dir.display()).unwrap();
+    } else {
+        writeln!(stdout, "Target dir: {}\n", target_dir.display()).unwrap();
+    }
     lines.push(TableLine::new(0, &"Total Size: ", &size_hr));
 
     // we are going to check these directories:
@@ -207,7 +595,7 @@ pub(crate) fn local_subcmd() -> Result<(), Error> {
         })
         .filter(|f| f.exists())
         .map(|f| {
-            std::fs::metadata(&f)
+            fs::metadata(&f)
                 .unwrap_or_else(|_| panic!("Failed to get metadata of file '{}'", &f.display()))
                 .len()
         })
@@ -221,9 +609,646 @@ pub(crate) fn local_subcmd() -> Result<(), Error> {
         ));
     }
 
+    if separate_build_dir {
+        let size_build_dir = library::cumulative_dir_size(&build_dir).dir_size;
+        if size_build_dir > 0 {
+            lines.push(TableLine::new(
+                0,
+                &"build dir: ".to_string(),
+                &size_build_dir.format_size(DECIMAL),
+            ));
+        }
+    }
+
+    // the intermediate build files which can be removed without losing the final artifacts
+    let intermediates: Vec<(PathBuf, u64)> = intermediate_dirs(&build_dir)
+        .into_iter()
+        .map(|dir| {
+            let size = library::cumulative_dir_size(&dir).dir_size;
+            (dir, size)
+        })
+        .collect();
+    let size_intermediates: u64 = intermediates.iter().map(|(_, size)| size).sum();
+    if size_intermediates > 0 {
+        lines.push(TableLine::new(
+            0,
+            &"intermediates: ".to_string(),
+            &size_intermediates.format_size(DECIMAL),
+        ));
+    }
+
+    // the Cargo.lock is needed to tell which build script outputs are stale, without one they are not listed
+    let lockfile = PathBuf::from(&metadata.workspace_root).join("Cargo.lock");
+    let locked = fs::read_to_string(&lockfile)
+        .ok()
+        .map(|content| locked_packages(&content));
+    let stale_build_scripts: Vec<(PathBuf, u64)> = locked
+        .as_ref()
+        .map(|locked| stale_build_script_outputs(&build_dir, locked))
+        .unwrap_or_default()
+        .into_iter()
+        .map(|dir| {
+            let size = library::size_of_path(&dir);
+            (dir, size)
+        })
+        .collect();
+    let size_stale_build_scripts: u64 = stale_build_scripts.iter().map(|(_, size)| size).sum();
+    if size_stale_build_scripts > 0 {
+        lines.push(TableLine::new(
+            0,
+            &"stale build scripts: ".to_string(),
+            &size_stale_build_scripts.format_size(DECIMAL),
+        ));
+    }
+
     // add the formatted table to the output
     stdout.push_str(&two_row_table(MIN_PADDING, lines, true));
+
+    // if we have a workspace with several members, show how much each of them takes
+    if metadata.workspace_members.len() > 1 {
+        let mut member_lines = Vec::new();
+        let mut members: Vec<(String, u64)> = metadata
+            .packages
+            .iter()
+            .filter(|package| metadata.workspace_members.contains(&package.id))
+            .map(|package| {
+                let names = output_names(package);
+                (
+                    package.name.clone(),
+                    member_size(&names, &target_dir, &build_dir),
+                )
+            })
+            .collect();
+        // biggest first
+        members.sort_by(|(name_a, size_a), (name_b, size_b)| {
+            size_b.cmp(size_a).then_with(|| name_a.cmp(name_b))
+        });
+        for (name, size) in members {
+            member_lines.push(TableLine::new(
+                2,
+                &format!("{name}: "),
+                &size.format_size(DECIMAL),
+            ));
+        }
+        stdout.push_str("\n\nWorkspace members:\n");
+        stdout.push_str(&two_row_table(MIN_PADDING, member_lines, true));
+    }
+
     // and finally print it
     println!("{stdout}");
+
+    if let Some(clean) = clean_targets {
+        let mut dirs = vec![target_dir.clone()];
+        if separate_build_dir {
+            dirs.push(build_dir.clone());
+        }
+        let project = (metadata.workspace_root.to_string(), dirs);
+        clean_target_dirs(&[project], clean, dry_run);
+    }
+
+    if clean_build_dir {
+        let mut size_changed = false;
+        println!();
+        for (dir, size) in &intermediates {
+            remove_with_default_message(dir, dry_run, &mut size_changed, Some(*size));
+        }
+        if dry_run {
+            println!(
+                "dry-run: would remove {} of intermediate build files",
+                size_intermediates.format_size(DECIMAL)
+            );
+        } else {
+            println!(
+                "Removed {} of intermediate build files",
+                size_intermediates.format_size(DECIMAL)
+            );
+        }
+    }
+
+    if clean_stale_deps {
+        let locked = locked
+            .as_ref()
+            .ok_or_else(|| Error::LocalNoLockfile(lockfile.clone()))?;
+        let mut size_changed = false;
+        let mut size_stale = 0;
+        println!();
+        for path in stale_dependencies(&build_dir, locked) {
+            let size = library::size_of_path(&path);
+            size_stale += size;
+            remove_with_default_message(&path, dry_run, &mut size_changed, Some(size));
+        }
+        if dry_run {
+            println!(
+                "dry-run: would remove {} of build output of dependencies that are not in the Cargo.lock",
+                size_stale.format_size(DECIMAL)
+            );
+        } else {
+            println!(
+                "Removed {} of build output of dependencies that are not in the Cargo.lock",
+                size_stale.format_size(DECIMAL)
+            );
+        }
+    } else if clean_stale_build_scripts {
+        // --clean-stale-deps already removed these
+        if locked.is_none() {
+            return Err(Error::LocalNoLockfile(lockfile));
+        }
+        let mut size_changed = false;
+        println!();
+        for (dir, size) in &stale_build_scripts {
+            remove_with_default_message(dir, dry_run, &mut size_changed, Some(*size));
+        }
+        if dry_run {
+            println!(
+                "dry-run: would remove {} of build script output of dependencies that are not in the Cargo.lock",
+                size_stale_build_scripts.format_size(DECIMAL)
+            );
+        } else {
+            println!(
+                "Removed {} of build script output of dependencies that are not in the Cargo.lock",
+                size_stale_build_scripts.format_size(DECIMAL)
+            );
+        }
+    }
     Ok(())
 }
+
+/// the projects below `root` that have a target dir: a directory with a Cargo.toml and a target/ next to it
+/// target dirs and hidden directories (.git...) are not searched
+fn projects_below(root: &Path) -> Vec<PathBuf> {
+    guarded(WalkDir::new(root).sort_by_file_name(), |entry| {
+        let is_hidden = entry.depth() > 0
+            && entry
+                .file_name()
+                .to_str()
+                .map_or(false, |name| name.starts_with('.'));
+        let is_target_dir = entry.file_name() == "target"
+            && entry
+                .path()
+                .parent()
+                .map_or(false, |project| project.join("Cargo.toml").is_file());
+        entry.file_type().is_dir() && !is_hidden && !is_target_dir
+    })
+    .map(walkdir::DirEntry::into_path)
+    .filter(|dir| dir.join("Cargo.toml").is_file() && dir.join("target").is_dir())
+    .collect()
+}
+
+/// the release build output of a target dir: `release/` and `<target triple>/release/`
+fn release_dirs(target_dir: &Path) -> Vec<PathBuf> {
+    let mut dirs = vec![target_dir.join("release")];
+    dirs.extend(
+        read_dir(target_dir)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|entry| entry.path().join("release")),
+    );
+    dirs.retain(|dir| dir.is_dir());
+    dirs.sort();
+    dirs
+}
+
+/// when something was last built into the target dirs of a project: the newest modification time of
+/// the target dirs, their profile dirs and what is directly inside those (`deps/`, `.fingerprint/`...)
+fn last_built(target_dirs: &[PathBuf]) -> SystemTime {
+    target_dirs
+        .iter()
+        .flat_map(|dir| WalkDir::new(dir).max_depth(2).into_iter().flatten())
+        .filter_map(|entry| entry.metadata().ok()?.modified().ok())
+        .max()
+        .unwrap_or(SystemTime::UNIX_EPOCH)
+}
+
+/// `--clean`: remove the target dirs of the projects (or only their release build output) and print
+/// how the size of each project changed, `projects` are the names of the projects with their target dirs
+fn clean_target_dirs(projects: &[(String, Vec<PathBuf>)], clean: CleanTargets, dry_run: bool) {
+    const MIN_PADDING: usize = 6;
+
+    // the most recently built projects are kept
+    let mut by_recency = projects.iter().collect::<Vec<_>>();
+    by_recency.sort_by_key(|(_, target_dirs)| std::cmp::Reverse(last_built(target_dirs)));
+    let (kept, cleaned) = by_recency.split_at(clean.keep_recent.min(by_recency.len()));
+
+    let mut size_changed = false;
+    let mut size_removed = 0;
+    let mut lines = Vec::new();
+    println!();
+    for (name, target_dirs) in projects {
+        if !cleaned.iter().any(|(cleaned, _)| cleaned == name) {
+            continue;
+        }
+        let size_before: u64 = target_dirs
+            .iter()
+            .map(|dir| library::cumulative_dir_size(dir).dir_size)
+            .sum();
+        let mut size_project_removed = 0;
+        for target_dir in target_dirs {
+            let dirs = if clean.release_only {
+                release_dirs(target_dir)
+            } else {
+                vec![target_dir.clone()]
+            };
+            for dir in dirs {
+                let size = library::cumulative_dir_size(&dir).dir_size;
+                size_project_removed += size;
+                remove_with_default_message(&dir, dry_run, &mut size_changed, Some(size));
+            }
+        }
+        let size_after = if dry_run {
+            size_before.saturating_sub(size_project_removed)
+        } else {
+            target_dirs
+                .iter()
+                .map(|dir| library::cumulative_dir_size(dir).dir_size)
+                .sum()
+        };
+        size_removed += size_before.saturating_sub(size_after);
+        lines.push(TableLine::new(
+            0,
+            &format!("{name}: "),
+            &library::size_diff_format(size_before, size_after, true),
+        ));
+    }
+
+    if !lines.is_empty() {
+        println!("\n{}", two_row_table(MIN_PADDING, lines, true));
+    }
+    if !kept.is_empty() {
+        println!(
+            "Kept the target dirs of the {} most recently built {}: {}",
+            kept.len(),
+            if kept.len() == 1 {
+                "project"
+            } else {
+                "projects"
+            },
+            kept.iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+    let what = if clean.release_only {
+        "release build output"
+    } else {
+        "target dirs"
+    };
+    if dry_run {
+        println!(
+            "dry-run: would remove {} of {what}",
+            size_removed.format_size(DECIMAL)
+        );
+    } else {
+        println!("Removed {} of {what}", size_removed.format_size(DECIMAL));
+    }
+}
+
+/// `local --recursive <dir>`: the target dir sizes of all projects below `root`, biggest first
+/// with `clean_targets`, the target dirs are removed afterwards
+pub(crate) fn recursive_subcmd(
+    root: &Path,
+    clean_targets: Option<CleanTargets>,
+    dry_run: bool,
+) -> Result<(), Error> {
+    const MIN_PADDING: usize = 6;
+
+    if !root.is_dir() {
+        return Err(Error::LocalNoProjectDir(root.to_path_buf()));
+    }
+
+    let mut projects: Vec<(PathBuf, u64)> = projects_below(root)
+        .into_par_iter()
+        .map(|project| {
+            let size = library::cumulative_dir_size(&project.join("target")).dir_size;
+            (project, size)
+        })
+        .collect();
+    if projects.is_empty() {
+        println!(
+            "No cargo projects with a target dir found in \"{}\"",
+            root.display()
+        );
+        return Ok(());
+    }
+    projects.sort_by(|(path_a, size_a), (path_b, size_b)| {
+        size_b.cmp(size_a).then_with(|| path_a.cmp(path_b))
+    });
+    let total: u64 = projects.iter().map(|(_, size)| size).sum();
+
+    // the projects are listed relative to the root, the root itself is "."
+    let names = projects
+        .iter()
+        .map(|(project, _)| {
+            project
+                .strip_prefix(root)
+                .ok()
+                .filter(|relative| !relative.as_os_str().is_empty())
+                .unwrap_or_else(|| Path::new("."))
+                .display()
+                .to_string()
+        })
+        .collect::<Vec<_>>();
+
+    let mut lines = Vec::new();
+    for (name, (_, size)) in names.iter().zip(&projects) {
+        lines.push(TableLine::new(
+            0,
+            &format!("{name}: "),
+            &size.format_size(DECIMAL),
+        ));
+    }
+    lines.push(TableLine::new(
+        0,
+        &format!("Total ({} projects): ", projects.len()),
+        &total.format_size(DECIMAL),
+    ));
+
+    println!("Target dirs of the projects in \"{}\"\n", root.display());
+    println!("{}", two_row_table(MIN_PADDING, lines, true));
+
+    if let Some(clean) = clean_targets {
+        let projects = names
+            .into_iter()
+            .zip(projects)
+            .map(|(name, (project, _))| (name, vec![project.join("target")]))
+            .collect::<Vec<_>>();
+        clean_target_dirs(&projects, clean, dry_run);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod local_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::fs;
+
+    #[test]
+    fn find_projects() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        let project = |dir: &str, target: bool| {
+            let path = root.join(dir);
+            fs::create_dir_all(&path).unwrap();
+            fs::write(path.join("Cargo.toml"), "").unwrap();
+            if target {
+                fs::create_dir_all(path.join("target/debug")).unwrap();
+            }
+        };
+        project("b", true);
+        project("a", true);
+        // a workspace member builds into the target dir of its workspace
+        project("a/member", false);
+        // a project that was never built
+        project("c", false);
+        project("group/d", true);
+        // a project inside a target dir (cargo package) or a hidden dir is not one of ours
+        project("b/target/package/e", true);
+        project(".hidden/f", true);
+
+        let found = projects_below(root)
+            .iter()
+            .map(|path| path.strip_prefix(root).unwrap().to_path_buf())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            vec![
+                PathBuf::from("a"),
+                PathBuf::from("b"),
+                PathBuf::from("group/d")
+            ]
+        );
+    }
+
+    #[test]
+    fn output_belongs_to_package() {
+        let names = vec!["cargo-cache".to_string(), "cargo_cache".to_string()];
+        assert!(belongs_to("cargo_cache-0123456789abcdef", &names));
+        assert!(belongs_to("cargo_cache-0123456789abcdef.d", &names));
+        assert!(belongs_to("libcargo_cache-0123456789abcdef.rlib", &names));
+        assert!(belongs_to("cargo-cache-0123456789abcdef", &names));
+        assert!(belongs_to("cargo-cache", &names));
+        assert!(belongs_to("cargo-cache.d", &names));
+
+        assert!(!belongs_to("cargo_cache_helper-0123456789abcdef", &names));
+        assert!(!belongs_to("cargo-cache-helper-0123456789abcdef", &names));
+        assert!(!belongs_to("cargo_cache-", &names));
+        assert!(!belongs_to("serde-0123456789abcdef", &names));
+    }
+
+    #[test]
+    fn lockfile_packages() {
+        let lockfile = "\
+            # This file is automatically @generated by Cargo.\n\
+            version = 3\n\
+            \n\
+            [[package]]\n\
+            name = \"cargo-cache\"\n\
+            version = \"0.8.3\"\n\
+            dependencies = [\n \"serde\",\n]\n\
+            \n\
+            [[package]]\n\
+            name = \"serde\"\n\
+            version = \"1.0.188\"\n\
+            source = \"registry+https://github.com/rust-lang/crates.io-index\"\n";
+        assert_eq!(
+            locked_packages(lockfile),
+            vec![
+                (String::from("cargo-cache"), String::from("0.8.3")),
+                (String::from("serde"), String::from("1.0.188")),
+            ]
+        );
+    }
+
+    #[test]
+    fn find_stale_dependencies() {
+        let tmp = tempfile::tempdir().unwrap();
+        let debug = tmp.path().join("debug");
+        let fingerprint = |name: &str, version: Option<&str>| {
+            let dir = debug.join(".fingerprint").join(name);
+            fs::create_dir_all(&dir).unwrap();
+            let local = version.map_or_else(
+                || String::from("{\"CheckDepInfo\":{\"dep_info\":\"x.d\"}}"),
+                |version| format!("{{\"Precalculated\":\"{version}\"}}"),
+            );
+            fs::write(
+                dir.join("lib-x.json"),
+                format!("{{\"rustc\":1,\"local\":[{local}]}}"),
+            )
+            .unwrap();
+        };
+        fingerprint("serde-0000000000000001", Some("1.0.188"));
+        fingerprint("serde-0000000000000002", None);
+        fingerprint("removed-0000000000000003", Some("0.1.0"));
+        fingerprint("cargo-cache-0000000000000004", None);
+        fingerprint("old-member-0000000000000005", None);
+        fs::create_dir_all(debug.join("deps")).unwrap();
+        fs::create_dir_all(debug.join("build").join("serde-0000000000000002")).unwrap();
+        for file in [
+            "libserde-0000000000000001.rlib",
+            "libserde-0000000000000002.rlib",
+            "libserde-0000000000000002.rmeta",
+            "removed-0000000000000003.d",
+        ] {
+            fs::write(debug.join("deps").join(file), "").unwrap();
+        }
+        // the version of serde-0000000000000002 comes from the sources it was built from
+        fs::write(
+            debug.join("deps").join("serde-0000000000000002.d"),
+            "/target/debug/deps/serde-0000000000000002.d: \
+            /cargo/registry/src/index.crates.io-6f17d22bba15001f/serde-1.0.100/src/lib.rs\n",
+        )
+        .unwrap();
+
+        let locked = vec![
+            (String::from("cargo-cache"), String::from("0.8.3")),
+            (String::from("serde"), String::from("1.0.188")),
+        ];
+        let stale: Vec<PathBuf> = stale_dependencies(tmp.path(), &locked)
+            .into_iter()
+            .map(|path| path.strip_prefix(tmp.path()).unwrap().to_path_buf())
+            .collect();
+        let expected: Vec<PathBuf> = [
+            "debug/.fingerprint/old-member-0000000000000005",
+            "debug/.fingerprint/removed-0000000000000003",
+            "debug/deps/removed-0000000000000003.d",
+            "debug/.fingerprint/serde-0000000000000002",
+            "debug/deps/libserde-0000000000000002.rlib",
+            "debug/deps/libserde-0000000000000002.rmeta",
+            "debug/deps/serde-0000000000000002.d",
+            "debug/build/serde-0000000000000002",
+        ]
+        .iter()
+        .map(PathBuf::from)
+        .collect();
+        assert_eq!(stale, expected);
+    }
+
+    #[test]
+    fn find_stale_build_scripts() {
+        let tmp = tempfile::tempdir().unwrap();
+        let debug = tmp.path().join("debug");
+        let run_build_script = |name: &str, version: &str| {
+            let dir = debug.join(".fingerprint").join(name);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(
+                dir.join("run-build-script-build-script-build.json"),
+                format!("{{\"rustc\":1,\"local\":[{{\"Precalculated\":\"{version}\"}}]}}"),
+            )
+            .unwrap();
+            fs::create_dir_all(debug.join("build").join(name).join("out")).unwrap();
+        };
+        run_build_script("libc-0000000000000001", "0.2.150");
+        run_build_script("libc-0000000000000002", "0.2.140");
+        run_build_script("removed-0000000000000003", "1.0.0");
+        // the fingerprint of this one is gone
+        fs::create_dir_all(debug.join("build").join("orphan-0000000000000004")).unwrap();
+        fs::create_dir_all(debug.join("deps")).unwrap();
+        fs::write(debug.join("deps").join("liblibc-0000000000000002.rlib"), "").unwrap();
+
+        let locked = vec![(String::from("libc"), String::from("0.2.150"))];
+        let stale: Vec<PathBuf> = stale_build_script_outputs(tmp.path(), &locked)
+            .into_iter()
+            .map(|path| path.strip_prefix(tmp.path()).unwrap().to_path_buf())
+            .collect();
+        let expected: Vec<PathBuf> = [
+            "debug/build/libc-0000000000000002",
+            "debug/build/orphan-0000000000000004",
+            "debug/build/removed-0000000000000003",
+        ]
+        .iter()
+        .map(PathBuf::from)
+        .collect();
+        assert_eq!(stale, expected);
+    }
+
+    #[test]
+    fn find_intermediate_dirs() {
+        let tmp = tempfile::tempdir().unwrap();
+        let build_dir = tmp.path();
+
+        for dir in &[
+            "debug/.fingerprint",
+            "debug/deps",
+            "debug/build",
+            "debug/examples",
+            "release/.fingerprint",
+            "release/incremental",
+            "x86_64-unknown-linux-gnu/debug/.fingerprint",
+            "x86_64-unknown-linux-gnu/debug/deps",
+            "doc/deps",
+        ] {
+            fs::create_dir_all(build_dir.join(dir)).unwrap();
+        }
+
+        let found: Vec<PathBuf> = intermediate_dirs(build_dir)
+            .into_iter()
+            .map(|dir| dir.strip_prefix(build_dir).unwrap().to_path_buf())
+            .collect();
+
+        let expected: Vec<PathBuf> = [
+            "debug/.fingerprint",
+            "debug/build",
+            "debug/deps",
+            "release/.fingerprint",
+            "release/incremental",
+            "x86_64-unknown-linux-gnu/debug/.fingerprint",
+            "x86_64-unknown-linux-gnu/debug/deps",
+        ]
+        .iter()
+        .map(PathBuf::from)
+        .collect();
+
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn clean_targets() {
+        let tmp = tempfile::tempdir().unwrap();
+        let target_dir = |project: &str| tmp.path().join(project).join("target");
+        for project in ["a", "b"] {
+            for profile in ["debug", "release", "x86_64-unknown-linux-gnu/release"] {
+                let dir = target_dir(project).join(profile);
+                fs::create_dir_all(&dir).unwrap();
+                fs::write(dir.join("lib.rlib"), "x".repeat(100)).unwrap();
+            }
+        }
+        assert_eq!(
+            release_dirs(&target_dir("a")),
+            vec![
+                target_dir("a").join("release"),
+                target_dir("a").join("x86_64-unknown-linux-gnu/release")
+            ]
+        );
+
+        let projects = vec![
+            (String::from("a"), vec![target_dir("a")]),
+            (String::from("b"), vec![target_dir("b")]),
+        ];
+        // a dry run and keeping as many projects as there are remove nothing
+        let release_only = CleanTargets {
+            release_only: true,
+            keep_recent: 0,
+        };
+        clean_target_dirs(&projects, release_only, true);
+        let keep_all = CleanTargets {
+            release_only: false,
+            keep_recent: 2,
+        };
+        clean_target_dirs(&projects, keep_all, false);
+        assert_eq!(release_dirs(&target_dir("b")).len(), 2);
+
+        clean_target_dirs(&projects, release_only, false);
+        assert!(release_dirs(&target_dir("a")).is_empty());
+        assert!(target_dir("a").join("debug/lib.rlib").is_file());
+
+        let everything = CleanTargets {
+            release_only: false,
+            keep_recent: 0,
+        };
+        clean_target_dirs(&projects, everything, false);
+        assert!(!target_dir("a").exists());
+        assert!(!target_dir("b").exists());
+    }
+}
@@ -0,0 +1,193 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// "cargo cache log" command
+// list the destructive runs recorded in the journal of the cargo home: when they ran, which
+// command it was and what it removed. With --paths every removed item is listed.
+
+use std::fmt::Write as _;
+use std::path::Path;
+
+use humansize::{FormatSize, DECIMAL};
+
+use crate::commands::stats::format_time;
+use crate::history;
+use crate::journal::{self, Removal};
+use crate::tables::format_table;
+
+/// the removals of a single run
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Run<'a> {
+    command: &'a str,
+    removals: Vec<&'a Removal>,
+}
+
+impl Run<'_> {
+    /// when the first item was removed
+    fn time(&self) -> u64 {
+        self.removals.first().map_or(0, |removal| removal.time)
+    }
+
+    fn size(&self) -> u64 {
+        self.removals.iter().map(|removal| removal.size).sum()
+    }
+}
+
+/// group the removals by the run that removed them, oldest run first
+/// removals of the same run are next to each other in the journal unless two runs overlapped
+fn runs(removals: &[Removal]) -> Vec<Run<'_>> {
+    let mut runs: Vec<(u64, Run<'_>)> = Vec::new();
+    for removal in removals {
+        match runs.iter_mut().rev().find(|(run, _)| *run == removal.run) {
+            Some((_, run)) => run.removals.push(removal),
+            None => runs.push((
+                removal.run,
+                Run {
+                    command: &removal.command,
+                    removals: vec![removal],
+                },
+            )),
+        }
+    }
+    runs.into_iter().map(|(_, run)| run).collect()
+}
+
+/// generate the "cargo cache log" output for the `last` runs
+fn log_text(cargo_home: &Path, removals: &[Removal], last: usize, paths: bool) -> String {
+    let runs = runs(removals);
+    if runs.is_empty() {
+        return format!(
+            "No removals from '{}' were recorded yet.\n",
+            cargo_home.display()
+        );
+    }
+    let shown = &runs[runs.len().saturating_sub(last)..];
+
+    let mut out = format!(
+        "Removals from '{}', the last {} of {} runs:\n\n",
+        cargo_home.display(),
+        shown.len(),
+        runs.len()
+    );
+    if paths {
+        for run in shown {
+            let _ = writeln!(
+                out,
+                "{}  {}: {} items, {}",
+                format_time(run.time()),
+                run.command,
+                run.removals.len(),
+                run.size().format_size(DECIMAL)
+            );
+            let items = run
+                .removals
+                .iter()
+                .map(|removal| vec![removal.path.clone(), removal.size.format_size(DECIMAL)])
+                .collect::<Vec<_>>();
+            for line in format_table(&items, 1).lines() {
+                let _ = writeln!(out, "  {}", line.trim_end());
+            }
+            out.push('\n');
+        }
+    } else {
+        let mut table = vec![vec![
+            String::from("Time"),
+            String::from("Command"),
+            String::from("Items"),
+            String::from("Removed"),
+        ]];
+        table.extend(shown.iter().map(|run| {
+            vec![
+                format_time(run.time()),
+                run.command.to_string(),
+                run.removals.len().to_string(),
+                run.size().format_size(DECIMAL),
+            ]
+        }));
+        out.push_str(&format_table(&table, 1));
+    }
+    out
+}
+
+/// print what the last `last` destructive runs removed from the cargo home
+pub(crate) fn log(cargo_home: &Path, last: usize, paths: bool) {
+    let removals = journal::read(cargo_home);
+    print!("{}", log_text(cargo_home, &removals, last, paths));
+    // the journal only knows about runs since it exists
+    if let Some(first_removal) = removals.first() {
+        let older_runs = history::read(cargo_home)
+            .iter()
+            .filter(|entry| entry.time < first_removal.time)
+            .count();
+        if older_runs > 0 {
+            println!("\n{older_runs} older runs that freed space are not in the journal, see `cargo cache savings`.");
+        }
+    }
+}
+
+#[cfg(test)]
+mod log_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn removal(run: u64, command: &str, path: &str, size: u64) -> Removal {
+        Removal {
+            time: run / 1000,
+            run,
+            command: command.to_string(),
+            path: path.to_string(),
+            size,
+        }
+    }
+
+    #[test]
+    fn log_of_runs() {
+        let removals = vec![
+            removal(1_000_000_000_000, "--autoclean", "registry/src", 5_000_000),
+            removal(1_000_000_000_000, "--autoclean", "git/checkouts", 1_000_000),
+            removal(
+                1_000_086_400_000,
+                "trim",
+                "registry/cache/foo-0.1.0.crate",
+                2_000,
+            ),
+        ];
+        let cargo_home = Path::new("/home/user/.cargo");
+
+        assert_eq!(
+            log_text(cargo_home, &removals, 10, false),
+            format!(
+                "Removals from '/home/user/.cargo', the last 2 of 2 runs:
+
+Time              Command      Items  Removed
+{}  --autoclean  2      6 MB
+{}  trim         1      2 kB
+",
+                format_time(1_000_000_000),
+                format_time(1_000_086_400)
+            )
+        );
+        assert_eq!(
+            log_text(cargo_home, &removals, 1, true),
+            format!(
+                "Removals from '/home/user/.cargo', the last 1 of 2 runs:
+
+{}  trim: 1 items, 2 kB
+  registry/cache/foo-0.1.0.crate  2 kB
+
+",
+                format_time(1_000_086_400)
+            )
+        );
+        assert_eq!(
+            log_text(cargo_home, &[], 10, false),
+            "No removals from '/home/user/.cargo' were recorded yet.\n"
+        );
+    }
+}
@@ -0,0 +1,342 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// "cargo cache maintain": --autoclean once the cache grew above a size and --gc every few days
+// It is meant to be called from a shell profile or cron job, so most calls must not do anything:
+// a state file remembers when the size of each cargo home was last checked and when its repos
+// were last recompressed, as long as neither is due we exit before scanning the cache.
+// The state lives in cargo-cache/maintain-state inside the local data dir of the platform,
+// one "<last check>\t<last gc>\t<cargo home>" line per cargo home, 0 means never.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use humansize::{FormatSize, DECIMAL};
+
+use crate::commands::trim;
+use crate::library::Error;
+
+/// name of the state file inside the data dir
+const STATE_FILE_NAME: &str = "maintain-state";
+
+const SECONDS_PER_HOUR: u64 = 60 * 60;
+const SECONDS_PER_DAY: u64 = 24 * SECONDS_PER_HOUR;
+
+/// what to do and how often
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Policy {
+    /// run --autoclean if the cache is bigger than this many bytes
+    pub(crate) autoclean_above: Option<u64>,
+    /// run --gc if the last one was at least this many days ago
+    pub(crate) gc_every_days: Option<u64>,
+    /// look at the size of the cache at most this often
+    pub(crate) check_every_hours: u64,
+}
+
+impl Policy {
+    /// the policy of the command line or config file, one of the actions needs to be set
+    pub(crate) fn new(
+        autoclean_above: Option<&str>,
+        gc_every_days: Option<u64>,
+        check_every_hours: u64,
+    ) -> Result<Self, Error> {
+        if autoclean_above.is_none() && gc_every_days.is_none() {
+            return Err(Error::MaintainPolicyMissing);
+        }
+        let autoclean_above = match autoclean_above {
+            Some(limit) => Some(trim::parse_size_limit_to_bytes(Some(limit))?),
+            None => None,
+        };
+        Ok(Self {
+            autoclean_above,
+            gc_every_days,
+            check_every_hours,
+        })
+    }
+
+    /// what needs to be done now, `force` ignores when things were last done
+    pub(crate) fn due(&self, state: State, now: u64, force: bool) -> Due {
+        let elapsed = |since: u64, interval: u64| force || now.saturating_sub(since) >= interval;
+        Due {
+            size_check: self.autoclean_above.is_some()
+                && elapsed(
+                    state.last_check,
+                    self.check_every_hours.saturating_mul(SECONDS_PER_HOUR),
+                ),
+            gc: self.gc_every_days.map_or(false, |days| {
+                elapsed(state.last_gc, days.saturating_mul(SECONDS_PER_DAY))
+            }),
+        }
+    }
+
+    /// whether a cache of `total_size` bytes needs to be cleaned
+    pub(crate) fn needs_autoclean(&self, total_size: u64) -> bool {
+        self.autoclean_above
+            .map_or(false, |limit| total_size > limit)
+    }
+
+    /// "The cache is 12 GB, more than 10 GB: running --autoclean"
+    pub(crate) fn autoclean_reason(&self, total_size: u64) -> String {
+        format!(
+            "The cache is {}, more than {}: running --autoclean",
+            total_size.format_size(DECIMAL),
+            self.autoclean_above
+                .unwrap_or_default()
+                .format_size(DECIMAL)
+        )
+    }
+}
+
+/// when things were last done in a cargo home, seconds since the unix epoch, 0 if never
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct State {
+    pub(crate) last_check: u64,
+    pub(crate) last_gc: u64,
+}
+
+/// what `maintain` has to do in this run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Due {
+    /// scan the cache and --autoclean if it is too big
+    pub(crate) size_check: bool,
+    /// recompress the git repos
+    pub(crate) gc: bool,
+}
+
+impl Due {
+    /// whether we can exit right away
+    pub(crate) fn is_nothing(self) -> bool {
+        !self.size_check && !self.gc
+    }
+}
+
+/// cargo-cache/maintain-state in the local data directory of the platform: `$XDG_DATA_HOME` or
+/// `~/.local/share` on Linux, `~/Library/Application Support` on macOS, `%LOCALAPPDATA%` on Windows
+fn state_file() -> Option<PathBuf> {
+    dirs_next::data_local_dir().map(|data_dir| data_dir.join("cargo-cache").join(STATE_FILE_NAME))
+}
+
+/// parse the state file, lines we do not understand are skipped
+fn parse(content: &str) -> BTreeMap<PathBuf, State> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, '\t');
+            let last_check = fields.next()?.parse().ok()?;
+            let last_gc = fields.next()?.parse().ok()?;
+            let cargo_home = PathBuf::from(fields.next()?);
+            Some((
+                cargo_home,
+                State {
+                    last_check,
+                    last_gc,
+                },
+            ))
+        })
+        .collect()
+}
+
+/// the content of the state file, sorted by cargo home
+fn to_content(states: &BTreeMap<PathBuf, State>) -> String {
+    states
+        .iter()
+        // a path that does not fit on a line could not be read back
+        .filter_map(|(cargo_home, state)| Some((cargo_home.to_str()?, state)))
+        .filter(|(cargo_home, _)| !cargo_home.contains('\n'))
+        .fold(String::new(), |mut content, (cargo_home, state)| {
+            let _ = writeln!(
+                content,
+                "{}\t{}\t{cargo_home}",
+                state.last_check, state.last_gc
+            );
+            content
+        })
+}
+
+fn load_from(file: &Path, cargo_home: &Path) -> State {
+    fs::read_to_string(file)
+        .map(|content| parse(&content))
+        .unwrap_or_default()
+        .get(cargo_home)
+        .copied()
+        .unwrap_or_default()
+}
+
+fn save_to(file: &Path, cargo_home: &Path, state: State) -> std::io::Result<()> {
+    let mut states = fs::read_to_string(file)
+        .map(|content| parse(&content))
+        .unwrap_or_default();
+    let _ = states.insert(cargo_home.to_path_buf(), state);
+
+    if let Some(data_dir) = file.parent() {
+        fs::create_dir_all(data_dir)?;
+    }
+    let temporary = file.with_file_name(format!("{STATE_FILE_NAME}.tmp"));
+    // another cargo-cache may read the state at the same time, replace it in one go
+    fs::write(&temporary, to_content(&states))
+        .and_then(|()| fs::rename(&temporary, file))
+        .map_err(|error| {
+            let _ = fs::remove_file(&temporary);
+            error
+        })
+}
+
+/// the state of the cargo home, never done anything if there is no state file yet
+pub(crate) fn load_state(cargo_home: &Path) -> State {
+    state_file().map_or_else(State::default, |file| load_from(&file, cargo_home))
+}
+
+/// remember the state of the cargo home for the next run
+/// without it every run does everything again, that is worth a warning but not an error
+pub(crate) fn save_state(cargo_home: &Path, state: State) {
+    let file = if let Some(file) = state_file() {
+        file
+    } else {
+        eprintln!("Warning: could not find a directory to remember when maintain last ran");
+        return;
+    };
+    if let Err(error) = save_to(&file, cargo_home, state) {
+        eprintln!(
+            "Warning: failed to remember when maintain last ran in \"{}\": {}",
+            file.display(),
+            error
+        );
+    }
+}
+
+#[cfg(test)]
+mod maintain_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    const NOW: u64 = 1_700_000_000;
+
+    #[test]
+    fn policies() {
+        assert!(matches!(
+            Policy::new(None, None, 24),
+            Err(Error::MaintainPolicyMissing)
+        ));
+        assert!(matches!(
+            Policy::new(Some("5X"), None, 24),
+            Err(Error::TrimLimitUnitParseFailure(_))
+        ));
+        let policy = Policy::new(Some("1K"), Some(7), 24).unwrap();
+        assert_eq!(policy.autoclean_above, Some(1024));
+        assert!(!policy.needs_autoclean(1024));
+        assert!(policy.needs_autoclean(1025));
+        assert_eq!(
+            policy.autoclean_reason(2000),
+            "The cache is 2 kB, more than 1.02 kB: running --autoclean"
+        );
+        assert!(!Policy::new(None, Some(7), 24)
+            .unwrap()
+            .needs_autoclean(u64::MAX));
+    }
+
+    #[test]
+    fn due() {
+        let policy = Policy::new(Some("1G"), Some(7), 24).unwrap();
+        let due = |last_check, last_gc, force| {
+            policy.due(
+                State {
+                    last_check,
+                    last_gc,
+                },
+                NOW,
+                force,
+            )
+        };
+
+        // never ran: everything is due
+        assert_eq!(
+            due(0, 0, false),
+            Due {
+                size_check: true,
+                gc: true
+            }
+        );
+        // checked an hour ago, recompressed yesterday: nothing to do
+        let recently = due(NOW - SECONDS_PER_HOUR, NOW - SECONDS_PER_DAY, false);
+        assert!(recently.is_nothing());
+        // unless forced
+        assert_eq!(
+            due(NOW - SECONDS_PER_HOUR, NOW - SECONDS_PER_DAY, true),
+            Due {
+                size_check: true,
+                gc: true
+            }
+        );
+        // checked a day ago, recompressed a week ago
+        assert_eq!(
+            due(NOW - SECONDS_PER_DAY, NOW - 7 * SECONDS_PER_DAY, false),
+            Due {
+                size_check: true,
+                gc: true
+            }
+        );
+        // a clock that went backwards does not make things due
+        assert!(due(NOW + SECONDS_PER_DAY, NOW + SECONDS_PER_DAY, false).is_nothing());
+
+        // actions that are not configured are never due
+        let gc_only = Policy::new(None, Some(1), 24).unwrap();
+        assert_eq!(
+            gc_only.due(State::default(), NOW, true),
+            Due {
+                size_check: false,
+                gc: true
+            }
+        );
+    }
+
+    #[test]
+    fn state_file_lines() {
+        let states = parse(
+            "1700000000\t1690000000\t/home/user/.cargo\n\
+             garbage\n\
+             1\t2\n\
+             5\t0\t/ci/cargo home\n",
+        );
+        assert_eq!(states.len(), 2);
+        assert_eq!(
+            states[Path::new("/ci/cargo home")],
+            State {
+                last_check: 5,
+                last_gc: 0
+            }
+        );
+        assert_eq!(
+            to_content(&states),
+            "5\t0\t/ci/cargo home\n1700000000\t1690000000\t/home/user/.cargo\n"
+        );
+    }
+
+    #[test]
+    fn states_per_cargo_home() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let file = data_dir.path().join("cargo-cache").join(STATE_FILE_NAME);
+        let first = Path::new("/home/user/.cargo");
+        let second = Path::new("/ci/cargo");
+        assert_eq!(load_from(&file, first), State::default());
+
+        let state = State {
+            last_check: NOW,
+            last_gc: NOW - 1,
+        };
+        save_to(&file, first, state).unwrap();
+        save_to(&file, second, State::default()).unwrap();
+        assert_eq!(load_from(&file, first), state);
+        assert_eq!(load_from(&file, second), State::default());
+        assert!(!file
+            .with_file_name(format!("{STATE_FILE_NAME}.tmp"))
+            .exists());
+    }
+}
@@ -0,0 +1,149 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// "cargo cache metrics" command
+// The json summary without anything that identifies the machine or the user: no paths and no file
+// names, only sizes, counts and a label of the user's choice (like "ci-runner-linux").
+// Fleets of build machines can collect these to see how the caches grow.
+// Without --push the metrics are only printed. --push posts them to an http endpoint, this is
+// the only way the metrics leave the machine and it respects the network policy (--offline...).
+
+use serde_json::{json, Value};
+
+use crate::dirsizes::DirSizes;
+use crate::http::{self, Url};
+use crate::library::{print_json, Error};
+use crate::network;
+
+/// remove everything from the json summary that could identify the machine or the user
+fn anonymize(mut summary: Value) -> Value {
+    if let Some(summary) = summary.as_object_mut() {
+        let _ = summary.remove("cargo_home");
+        if let Some(other_files) = summary
+            .get_mut("other_files")
+            .and_then(Value::as_object_mut)
+        {
+            let _ = other_files.remove("files");
+        }
+    }
+    summary
+}
+
+/// the metrics of a summary, as printed and pushed
+fn metrics_payload(summary: Value, label: Option<&str>) -> Value {
+    json!({
+        "label": label,
+        "cargo_cache_version": env!("CARGO_PKG_VERSION"),
+        "summary": anonymize(summary),
+    })
+}
+
+/// the url to push the metrics to
+fn push_url(url: &str) -> Result<Url, Error> {
+    // there is no TLS in our http client, sending the metrics in plain text instead would be worse
+    if url.trim_start().to_lowercase().starts_with("https://") {
+        return Err(Error::MetricsHttpsUnsupported(url.to_string()));
+    }
+    Url::parse(url).ok_or_else(|| Error::MetricsUnsupportedUrl(url.to_string()))
+}
+
+/// print the metrics or post them to `url` if `push` is set
+pub(crate) fn metrics(
+    dir_sizes: &DirSizes<'_>,
+    push: bool,
+    url: Option<&str>,
+    label: Option<&str>,
+    dry_run: bool,
+) -> Result<(), Error> {
+    let payload = metrics_payload(dir_sizes.to_json(), label);
+    if !push {
+        print_json(&payload);
+        return Ok(());
+    }
+
+    let url = url.ok_or(Error::MetricsNoUrl)?;
+    let parsed_url = push_url(url)?;
+    if dry_run {
+        println!("dry-run: would push these metrics to {url}:");
+        print_json(&payload);
+        return Ok(());
+    }
+    let _ = network::allow("metrics --push", true)?;
+
+    let failed = |reason: String| Error::MetricsPushFailed(url.to_string(), reason);
+    match http::post_json(&parsed_url, &payload.to_string()).map_err(failed)? {
+        status @ 200..=299 => {
+            println!("Pushed the metrics to {url} (HTTP {status}).");
+            Ok(())
+        }
+        status => Err(failed(format!("the server responded with HTTP {status}"))),
+    }
+}
+
+#[cfg(test)]
+mod metrics_tests {
+    use super::*;
+    use crate::schema::schema_tests::assert_valid;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn push_urls() {
+        assert!(push_url("http://metrics.internal:9091/cargo-cache").is_ok());
+        assert!(matches!(
+            push_url("https://metrics.example.com/"),
+            Err(Error::MetricsHttpsUnsupported(_))
+        ));
+        assert!(matches!(
+            push_url("HTTPS://metrics.example.com/"),
+            Err(Error::MetricsHttpsUnsupported(_))
+        ));
+        assert!(matches!(
+            push_url("ftp://metrics.example.com/"),
+            Err(Error::MetricsUnsupportedUrl(_))
+        ));
+    }
+
+    #[test]
+    fn no_paths_in_metrics() {
+        let summary = json!({
+            "cargo_home": "/home/alice/.cargo",
+            "total_size": 300,
+            "binaries": { "count": 1, "size": 100 },
+            "other_files": {
+                "count": 2,
+                "size": 200,
+                "files": [{ "name": "alice-notes.txt", "size": 200 }],
+            },
+        });
+        assert_valid(
+            "metrics",
+            &metrics_payload(summary.clone(), Some("ci-runner")),
+        );
+        assert_eq!(
+            metrics_payload(summary, Some("ci-runner")),
+            json!({
+                "label": "ci-runner",
+                "cargo_cache_version": env!("CARGO_PKG_VERSION"),
+                "summary": {
+                    "total_size": 300,
+                    "binaries": { "count": 1, "size": 100 },
+                    "other_files": { "count": 2, "size": 200 },
+                },
+            })
+        );
+        assert_eq!(
+            metrics_payload(json!({ "total_size": 0 }), None),
+            json!({
+                "label": null,
+                "cargo_cache_version": env!("CARGO_PKG_VERSION"),
+                "summary": { "total_size": 0 },
+            })
+        );
+    }
+}
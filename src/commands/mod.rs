@@ -8,8 +8,34 @@
 // except according to those terms.
 
 // code related to subcommands is located here
+#[cfg(feature = "backup")]
+pub(crate) mod backup;
+pub(crate) mod check;
+pub(crate) mod checkouts;
+pub(crate) mod compare;
+pub(crate) mod doctor;
+#[cfg(feature = "git")]
+pub(crate) mod git_export;
+pub(crate) mod inspect;
+#[cfg(feature = "metadata")]
 pub(crate) mod local;
+#[cfg(feature = "json")]
+pub(crate) mod log;
+pub(crate) mod maintain;
+#[cfg(feature = "metrics")]
+pub(crate) mod metrics;
+#[cfg(feature = "metadata")]
+pub(crate) mod preheat_install;
+pub(crate) mod projects;
+#[cfg(feature = "query")]
 pub(crate) mod query;
+pub(crate) mod remove_crate;
+pub(crate) mod savings;
 pub(crate) mod sccache;
+#[cfg(feature = "metadata")]
+pub(crate) mod self_update;
+pub(crate) mod selftest;
+pub(crate) mod stats;
 pub(crate) mod toolchains;
 pub(crate) mod trim;
+pub(crate) mod ui;
@@ -0,0 +1,303 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// "cargo cache preheat-install <crate>" command
+// check if everything that is needed to "cargo install --offline <crate>" is present in the cache.
+// We resolve the dependency graph of the crate using the crate metadata that cargo cached from the
+// registry index and check whether the .crate archives of all the dependencies are downloaded.
+// This is a best-effort approximation of cargos resolver: we always pick the newest matching version,
+// respect default features and features enabled by dependents, but ignore platform specific
+// dependencies (they are all taken into account).
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use cargo_metadata::semver::VersionReq;
+
+use crate::index::{read_index_entries, IndexEntry};
+use crate::library::{CargoCachePaths, Error};
+
+/// the outcome of resolving the dependency graph of a crate
+#[derive(Debug, Default)]
+struct Preheat {
+    /// crates that we resolved, name -> version
+    resolved: BTreeMap<String, IndexEntry>,
+    /// (crate, requirement) that could not be found in the cached index
+    missing_from_index: BTreeSet<(String, String)>,
+}
+
+/// a registry index directory and the matching .crate archive directory
+struct Registry {
+    index: PathBuf,
+    archives: PathBuf,
+}
+
+/// get all registries, crates.io first
+fn registries(ccp: &CargoCachePaths) -> Vec<Registry> {
+    let mut registries: Vec<Registry> = fs::read_dir(&ccp.registry_index)
+        .map(|dirs| {
+            dirs.filter_map(Result::ok)
+                .map(|entry| entry.path())
+                .filter(|path| path.is_dir())
+                .map(|index| Registry {
+                    archives: ccp.registry_pkg_cache.join(index.file_name().unwrap()),
+                    index,
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let is_crates_io = |registry: &Registry| {
+        let name = registry.index.file_name().unwrap().to_string_lossy();
+        name.starts_with("index.crates.io-") || name.starts_with("github.com-")
+    };
+    registries.sort_by_key(|registry| (!is_crates_io(registry), registry.index.clone()));
+    registries
+}
+
+/// find the newest, non-yanked version of a crate that matches the requirement
+fn find_version(registries: &[Registry], name: &str, req: &VersionReq) -> Option<IndexEntry> {
+    registries
+        .iter()
+        .filter_map(|registry| read_index_entries(&registry.index, name))
+        .find_map(|entries| {
+            entries
+                .into_iter()
+                .filter(|entry| !entry.yanked && req.matches(&entry.version))
+                .max_by(|a, b| a.version.cmp(&b.version))
+        })
+}
+
+/// walk the dependency graph of the crate, starting at the given crate and version requirement
+fn resolve(registries: &[Registry], root_name: &str, root_req: &VersionReq) -> Preheat {
+    let mut preheat = Preheat::default();
+    // enabled features per crate
+    let mut enabled_features: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    // crates (and features of these crates) we still need to look at
+    let mut queue: VecDeque<(String, VersionReq, Vec<String>)> = VecDeque::new();
+    queue.push_back((
+        root_name.to_string(),
+        root_req.clone(),
+        vec!["default".to_string()],
+    ));
+
+    while let Some((name, req, features)) = queue.pop_front() {
+        let entry = if let Some(entry) = preheat.resolved.get(&name) {
+            entry.clone()
+        } else if let Some(entry) = find_version(registries, &name, &req) {
+            let _ = preheat.resolved.insert(name.clone(), entry.clone());
+            entry
+        } else {
+            let _ = preheat
+                .missing_from_index
+                .insert((name.clone(), req.to_string()));
+            continue;
+        };
+
+        let first_visit = !enabled_features.contains_key(&name);
+        let enabled_of_crate = enabled_features.entry(name.clone()).or_default();
+
+        // expand the features transitively and collect optional deps and dependency features that get enabled
+        let mut feature_queue: Vec<String> = features;
+        let mut new_features = BTreeSet::new();
+        let mut enabled_deps: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        while let Some(feature) = feature_queue.pop() {
+            if let Some(dep) = feature.strip_prefix("dep:") {
+                let _ = enabled_deps.entry(dep.to_string()).or_default();
+            } else if let Some((dep, dep_feature)) = feature.split_once('/') {
+                // "dep?/feature" only enables the feature if the dep is enabled otherwise
+                if let Some(weak_dep) = dep.strip_suffix('?') {
+                    if enabled_deps.contains_key(weak_dep) {
+                        enabled_deps
+                            .entry(weak_dep.to_string())
+                            .or_default()
+                            .push(dep_feature.to_string());
+                    }
+                } else {
+                    enabled_deps
+                        .entry(dep.to_string())
+                        .or_default()
+                        .push(dep_feature.to_string());
+                }
+            } else if enabled_of_crate.insert(feature.clone()) {
+                let _ = new_features.insert(feature.clone());
+                match entry.features.get(&feature) {
+                    Some(enables) => feature_queue.extend(enables.iter().cloned()),
+                    // implicit feature of an optional dependency
+                    None => {
+                        let _ = enabled_deps.entry(feature).or_default();
+                    }
+                }
+            }
+        }
+
+        if !first_visit && new_features.is_empty() && enabled_deps.is_empty() {
+            continue;
+        }
+
+        for dep in entry.deps.iter().filter(|dep| dep.kind != "dev") {
+            let dep_enabled_features = enabled_deps.get(&dep.name);
+            if dep.optional && dep_enabled_features.is_none() {
+                continue;
+            }
+            // non-optional deps only need to be queued once
+            if !dep.optional && !first_visit && dep_enabled_features.is_none() {
+                continue;
+            }
+
+            let mut dep_features: Vec<String> = dep.features.clone();
+            if dep.default_features {
+                dep_features.push("default".to_string());
+            }
+            if let Some(enabled_by_dependent) = dep_enabled_features {
+                dep_features.extend(enabled_by_dependent.iter().cloned());
+            }
+            queue.push_back((dep.package.clone(), dep.req.clone(), dep_features));
+        }
+    }
+
+    preheat
+}
+
+/// check if the .crate archive of a crate is present in any registry
+fn archive_is_cached(registries: &[Registry], entry: &IndexEntry) -> bool {
+    let file_name = format!("{}-{}.crate", entry.name, entry.version);
+    registries
+        .iter()
+        .any(|registry| Path::new(&registry.archives).join(&file_name).is_file())
+}
+
+/// check whether all dependencies of a crate are cached so that it can be installed offline
+pub(crate) fn preheat_install(
+    ccp: &CargoCachePaths,
+    crate_name: &str,
+    version: Option<&str>,
+) -> Result<(), Error> {
+    let req = match version {
+        // cargo install --version 1.2.3 means =1.2.3
+        Some(version) if version.chars().next().map_or(false, char::is_numeric) => {
+            VersionReq::parse(&format!("={version}"))
+        }
+        Some(version) => VersionReq::parse(version),
+        None => Ok(VersionReq::STAR),
+    }
+    .map_err(|_| Error::InvalidVersionRequirement(version.unwrap_or_default().to_string()))?;
+
+    let registries = registries(ccp);
+    let preheat = resolve(&registries, crate_name, &req);
+
+    let root = match preheat.resolved.get(crate_name) {
+        Some(root) => root,
+        None => {
+            return Err(Error::CrateNotInIndex(
+                crate_name.to_string(),
+                req.to_string(),
+            ))
+        }
+    };
+
+    println!(
+        "Checking {} crates needed to install {} {}",
+        preheat.resolved.len(),
+        root.name,
+        root.version
+    );
+
+    let missing_archives: Vec<&IndexEntry> = preheat
+        .resolved
+        .values()
+        .filter(|entry| !archive_is_cached(&registries, entry))
+        .collect();
+
+    if !preheat.missing_from_index.is_empty() {
+        println!("\nNot found in the cached registry index:");
+        for (name, requirement) in &preheat.missing_from_index {
+            println!("  {name} {requirement}");
+        }
+    }
+
+    if !missing_archives.is_empty() {
+        println!("\nCrate archives that have not been downloaded:");
+        for entry in &missing_archives {
+            println!("  {} {}", entry.name, entry.version);
+        }
+    }
+
+    if preheat.missing_from_index.is_empty() && missing_archives.is_empty() {
+        println!(
+            "\nEverything is cached, `cargo install --offline {} --version {}` should work.",
+            root.name, root.version
+        );
+        Ok(())
+    } else {
+        Err(Error::PreheatIncomplete(
+            preheat.missing_from_index.len() + missing_archives.len(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod preheat_tests {
+    use super::*;
+    use crate::index::index_cache_path;
+    use pretty_assertions::assert_eq;
+
+    fn write_index(index: &Path, name: &str, entries: &[&str]) {
+        let path = index_cache_path(index, name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let mut data: Vec<u8> = vec![3, 2, 0, 0, 0];
+        data.extend(b"etag\0");
+        for entry in entries {
+            data.extend(b"0.0.0\0");
+            data.extend(entry.as_bytes());
+            data.push(0);
+        }
+        fs::write(path, data).unwrap();
+    }
+
+    #[test]
+    fn resolve_deps_and_features() {
+        let tmp = tempfile::tempdir().unwrap();
+        let registry = Registry {
+            index: tmp.path().join("index"),
+            archives: tmp.path().join("cache"),
+        };
+
+        write_index(
+            &registry.index,
+            "foo",
+            &[
+                r#"{"name":"foo","vers":"1.0.0","deps":[],"features":{},"yanked":false}"#,
+                r#"{"name":"foo","vers":"1.1.0","deps":[{"name":"bar","req":"^0.2","features":[],"optional":false,"default_features":true,"kind":"normal"},{"name":"baz","req":"^1","features":[],"optional":true,"default_features":true,"kind":"normal"},{"name":"unused","req":"^1","features":[],"optional":true,"default_features":true,"kind":"normal"},{"name":"testing","req":"^1","features":[],"optional":false,"default_features":true,"kind":"dev"}],"features":{"default":["baz"]},"yanked":false}"#,
+                r#"{"name":"foo","vers":"1.2.0","deps":[],"features":{},"yanked":true}"#,
+            ],
+        );
+        write_index(
+            &registry.index,
+            "bar",
+            &[r#"{"name":"bar","vers":"0.2.5","deps":[],"features":{},"yanked":false}"#],
+        );
+
+        let preheat = resolve(&[registry], "foo", &VersionReq::STAR);
+
+        let resolved: Vec<String> = preheat
+            .resolved
+            .values()
+            .map(|entry| format!("{} {}", entry.name, entry.version))
+            .collect();
+        // the yanked version is skipped
+        assert_eq!(resolved, vec!["bar 0.2.5", "foo 1.1.0"]);
+
+        // the optional dependency enabled by the default feature is not in the index,
+        // the disabled optional dependency and the dev-dependency are not needed
+        let missing: Vec<(String, String)> = preheat.missing_from_index.into_iter().collect();
+        assert_eq!(missing, vec![("baz".to_string(), "^1".to_string())]);
+    }
+}
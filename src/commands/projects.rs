@@ -0,0 +1,89 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// "cargo cache projects" command
+// list, add and remove the projects "clean-unref --all-projects" keeps the dependencies of.
+
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use crate::library::Error;
+use crate::projects;
+
+/// what "cargo cache projects" was asked to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ProjectsAction<'a> {
+    /// list the registered projects
+    List,
+    /// register the project at the path
+    Add(&'a str),
+    /// forget the project at the path
+    Remove(&'a str),
+}
+
+/// the registered projects, the ones that are gone are marked
+fn list_text(cargo_home: &Path, manifests: &[PathBuf]) -> String {
+    if manifests.is_empty() {
+        return format!(
+            "No projects are registered in '{}', add one with \"cargo cache projects add <path>\".\n",
+            cargo_home.display()
+        );
+    }
+    let mut text = format!("Projects registered in '{}':\n", cargo_home.display());
+    for manifest in manifests {
+        let _ = writeln!(
+            text,
+            "{}{}",
+            manifest.display(),
+            if manifest.is_file() { "" } else { " (missing)" }
+        );
+    }
+    text
+}
+
+pub(crate) fn projects(cargo_home: &Path, action: ProjectsAction<'_>) -> Result<(), Error> {
+    match action {
+        ProjectsAction::List => print!("{}", list_text(cargo_home, &projects::read(cargo_home))),
+        ProjectsAction::Add(path) => {
+            let (manifest, added) = projects::add(cargo_home, Path::new(path))?;
+            if added {
+                println!("Registered project '{}'", manifest.display());
+            } else {
+                println!("'{}' is already registered", manifest.display());
+            }
+        }
+        ProjectsAction::Remove(path) => {
+            let manifest = projects::remove(cargo_home, Path::new(path))?;
+            println!("Removed project '{}'", manifest.display());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod projects_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn list() {
+        let cargo_home = Path::new("/home/user/.cargo");
+        assert_eq!(
+            list_text(cargo_home, &[]),
+            "No projects are registered in '/home/user/.cargo', add one with \"cargo cache projects add <path>\".\n"
+        );
+        assert_eq!(
+            list_text(
+                cargo_home,
+                &[PathBuf::from("/this/project/does/not/exist/Cargo.toml")]
+            ),
+            "Projects registered in '/home/user/.cargo':\n/this/project/does/not/exist/Cargo.toml (missing)\n"
+        );
+    }
+}
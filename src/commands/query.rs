@@ -10,23 +10,58 @@
 use std::fmt::Write as _;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
+use crate::args::parse::{self, SizeUnits};
 use crate::cache::caches::{Cache, RegistrySuperCache};
 use crate::cache::*;
+use crate::cancel::is_cancelled;
+use crate::commands::sccache;
+use crate::hyperlink;
+use crate::last_used::{format_last_used, LastUsed};
 use crate::library::Error;
+use crate::remove::{parse_version, remove_and_record_freed};
+use crate::walk::walk;
 
 use clap::ArgMatches;
 use humansize::{FormatSize, DECIMAL};
 use rayon::prelude::*;
-use regex::Regex;
-use walkdir::WalkDir;
+use regex::{Regex, RegexSet};
+use walkdir::DirEntry;
 
 #[derive(Debug)]
 struct File<'a> {
-    #[allow(unused)]
     path: &'a Path,
     name: String,
     size: u64,
+    /// filled in once the item matched the patterns
+    last_used: Option<SystemTime>,
+}
+
+impl File<'_> {
+    fn with_last_used(self, last_used: &LastUsed) -> Self {
+        Self {
+            last_used: last_used.get(self.path),
+            ..self
+        }
+    }
+
+    /// "\t<name>: <size> (last used <date>)"
+    fn line(&self, hr_size: bool) -> String {
+        let size = if hr_size {
+            self.size.format_size(DECIMAL)
+        } else {
+            self.size.to_string()
+        };
+        let last_used = match self.last_used {
+            Some(_) => format!(" (last used {})", format_last_used(self.last_used)),
+            None => String::new(),
+        };
+        format!(
+            "\t{}: {size}{last_used}",
+            hyperlink::link(self.path, &self.name)
+        )
+    }
 }
 
 #[inline]
@@ -54,6 +89,7 @@ fn binary_to_file(path: &Path) -> File<'_> {
         size: fs::metadata(path)
             .unwrap_or_else(|_| panic!("Failed to get metadata of file '{}'", &path.display()))
             .len(),
+        last_used: None,
     }
 }
 
@@ -61,18 +97,19 @@ fn git_checkout_to_file(path: &Path) -> File<'_> {
     File {
         path,
         name: path_to_name_unstemmed(path),
-        size: WalkDir::new(path.display().to_string())
-            .into_iter()
-            .map(|d| d.unwrap().into_path())
+        size: walk(path)
+            .take_while(|_| !is_cancelled())
+            .map(DirEntry::into_path)
             .filter(|f| f.exists())
             .collect::<Vec<PathBuf>>()
             .par_iter()
             .map(|f| {
                 fs::metadata(f)
-                    .unwrap_or_else(|_| panic!("Failed to read size of file: '{f:?}'"))
+                    .unwrap_or_else(|_| panic!("Failed to read size of file: '{}'", f.display()))
                     .len()
             })
             .sum(),
+        last_used: None,
     }
 }
 
@@ -80,18 +117,19 @@ fn bare_repo_to_file(path: &Path) -> File<'_> {
     File {
         path,
         name: path_to_name_unstemmed(path),
-        size: WalkDir::new(path.display().to_string())
-            .into_iter()
-            .map(|d| d.unwrap().into_path())
+        size: walk(path)
+            .take_while(|_| !is_cancelled())
+            .map(DirEntry::into_path)
             .filter(|f| f.exists())
             .collect::<Vec<PathBuf>>()
             .par_iter()
             .map(|f| {
                 fs::metadata(f)
-                    .unwrap_or_else(|_| panic!("Failed to read size of file: '{f:?}'"))
+                    .unwrap_or_else(|_| panic!("Failed to read size of file: '{}'", f.display()))
                     .len()
             })
             .sum(),
+        last_used: None,
     }
 }
 
@@ -100,18 +138,19 @@ fn registry_pkg_cache_to_file(path: &Path) -> File<'_> {
         // todo: sum up the versions
         path,
         name: path_to_name_stemmed(path),
-        size: WalkDir::new(path.display().to_string())
-            .into_iter()
-            .map(|d| d.unwrap().into_path())
+        size: walk(path)
+            .take_while(|_| !is_cancelled())
+            .map(DirEntry::into_path)
             .filter(|f| f.exists())
             .collect::<Vec<PathBuf>>()
             .par_iter()
             .map(|f| {
                 fs::metadata(f)
-                    .unwrap_or_else(|_| panic!("Failed to read size of file: '{f:?}'"))
+                    .unwrap_or_else(|_| panic!("Failed to read size of file: '{}'", f.display()))
                     .len()
             })
             .sum(),
+        last_used: None,
     }
 }
 
@@ -120,84 +159,188 @@ fn registry_source_cache_to_file(path: &Path) -> File<'_> {
         // todo: sum up the versions
         path,
         name: path_to_name_unstemmed(path),
-        size: WalkDir::new(path.display().to_string())
-            .into_iter()
-            .map(|d| d.unwrap().into_path())
+        size: walk(path)
+            .take_while(|_| !is_cancelled())
+            .map(DirEntry::into_path)
             .filter(|f| f.exists())
             .collect::<Vec<PathBuf>>()
             .par_iter()
             .map(|f| {
                 fs::metadata(f)
-                    .unwrap_or_else(|_| panic!("Failed to read size of file: '{f:?}'"))
+                    .unwrap_or_else(|_| panic!("Failed to read size of file: '{}'", f.display()))
                     .len()
             })
             .sum(),
+        last_used: None,
+    }
+}
+
+/// the patterns of a query, compiled once and matched against every item of the cache
+#[derive(Debug)]
+struct Patterns {
+    /// the positional patterns
+    include: RegexSet,
+    /// the --not patterns, an item matching any of these is never listed
+    exclude: RegexSet,
+    /// --intersect: an item must match all of the patterns, not just one of them
+    intersect: bool,
+}
+
+impl Patterns {
+    fn new(include: &[&str], exclude: &[&str], intersect: bool) -> Result<Self, Error> {
+        // a query without patterns lists everything
+        let include = if include.is_empty() { &[""] } else { include };
+        Ok(Self {
+            include: compile(include)?,
+            exclude: compile(exclude)?,
+            intersect,
+        })
+    }
+
+    fn is_match(&self, name: &str) -> bool {
+        let matches = self.include.matches(name);
+        let included = if self.intersect {
+            matches.iter().count() == self.include.len()
+        } else {
+            matches.matched_any()
+        };
+        included && !self.exclude.is_match(name)
+    }
+}
+
+/// compile a set of patterns, reporting the first pattern that is not a valid regex
+fn compile(patterns: &[&str]) -> Result<RegexSet, Error> {
+    RegexSet::new(patterns).map_err(|_| {
+        let invalid = patterns
+            .iter()
+            .find(|pattern| Regex::new(pattern).is_err())
+            .unwrap_or(&"");
+        Error::QueryRegexFailedParsing((*invalid).to_string())
+    })
+}
+
+/// the --larger-than and --older-than filters, an item has to pass them after matching the patterns
+#[derive(Debug, Default)]
+struct Filters {
+    /// only items bigger than this many bytes
+    larger_than: Option<u64>,
+    /// only items that were last used before this point in time
+    older_than: Option<SystemTime>,
+}
+
+impl Filters {
+    fn new(larger_than: Option<&str>, older_than: Option<&str>) -> Result<Self, Error> {
+        Ok(Self {
+            // decimal units like the sizes we print
+            larger_than: larger_than
+                .map(|size| parse::size(size, SizeUnits::Decimal))
+                .transpose()?,
+            older_than: older_than.map(parse::age_cutoff).transpose()?,
+        })
+    }
+
+    fn is_restricted(&self) -> bool {
+        self.larger_than.is_some() || self.older_than.is_some()
+    }
+
+    fn keeps(&self, file: &File<'_>) -> bool {
+        // an item we can't tell the age of is not old, --delete must not remove it
+        self.larger_than.map_or(true, |size| file.size > size)
+            && self.older_than.map_or(true, |cutoff| {
+                file.last_used.map_or(false, |last_used| last_used < cutoff)
+            })
     }
 }
 
+// the same crate can be in several registries, equal names and sizes are ordered by path so that
+// the output does not depend on the order of the file system
 fn sort_files_by_name(v: &mut [File<'_>]) {
-    v.sort_by_key(|f| f.name.clone() /* @TODO: don't clone*/);
+    v.sort_by(|a, b| (&a.name, a.path).cmp(&(&b.name, b.path)));
 }
 
 fn sort_files_by_size(v: &mut [File<'_>]) {
-    v.sort_by_key(|f| f.size);
+    v.sort_by(|a, b| (a.size, &a.name, a.path).cmp(&(b.size, &b.name, b.path)));
 }
 
+/// `dry_run` is --dry-run of cargo-cache or of the subcommand, nothing is deleted then
+#[allow(clippy::too_many_arguments)]
 pub(crate) fn run_query(
     query_config: &ArgMatches,
+    dry_run: bool,
     bin_cache: &mut bin::BinaryCache,
     checkouts_cache: &mut git_checkouts::GitCheckoutCache,
     bare_repos_cache: &mut git_bare_repos::GitRepoCache,
     registry_pkg_cache: &mut registry_pkg_cache::RegistryPkgCaches,
     registry_sources_caches: &mut registry_sources::RegistrySourceCaches,
+    cargo_home: &Path,
 ) -> Result<(), Error> {
     let sorting = query_config.value_of("sort");
-    let query = query_config.value_of("QUERY").unwrap_or("" /* default */);
     let hr_size = query_config.is_present("hr");
+    let include = query_config
+        .values_of("QUERY")
+        .map(Iterator::collect::<Vec<&str>>)
+        .unwrap_or_default();
+    let exclude = query_config
+        .values_of("not")
+        .map(Iterator::collect::<Vec<&str>>)
+        .unwrap_or_default();
+    let patterns = Patterns::new(&include, &exclude, query_config.is_present("intersect"))?;
+    let filters = Filters::new(
+        query_config.value_of("larger-than"),
+        query_config.value_of("older-than"),
+    )?;
+    let delete = query_config.is_present("delete");
+    // a query without patterns or filters matches the whole cache
+    if delete && include.is_empty() && !filters.is_restricted() {
+        return Err(Error::QueryDeleteEverything);
+    }
 
     let mut output = String::new();
-
-    // make the regex
-    let re = match Regex::new(query) {
-        Ok(re) => re,
-        Err(_e) => {
-            return Err(Error::QueryRegexFailedParsing(query.to_string()));
-        }
-    };
+    let last_used = LastUsed::load(cargo_home);
 
     let mut binary_matches: Vec<File<'_>> = bin_cache
         .files()
         .iter()
         .map(|path| binary_to_file(path)) // convert the path into a file struct
-        .filter(|f| re.is_match(f.name.as_str())) // filter by regex
+        .filter(|f| patterns.is_match(f.name.as_str())) // filter by the patterns
+        .map(|f| f.with_last_used(&last_used))
+        .filter(|f| filters.keeps(f)) // then by size and age
         .collect::<Vec<_>>();
 
     let mut git_checkout_matches: Vec<_> = checkouts_cache
         .items()
         .iter()
         .map(|path| git_checkout_to_file(path))
-        .filter(|f| re.is_match(f.name.as_str())) // filter by regex
+        .filter(|f| patterns.is_match(f.name.as_str())) // filter by the patterns
+        .map(|f| f.with_last_used(&last_used))
+        .filter(|f| filters.keeps(f)) // then by size and age
         .collect::<Vec<_>>();
 
     let mut bare_repos_matches: Vec<_> = bare_repos_cache
         .items()
         .iter()
         .map(|path| bare_repo_to_file(path))
-        .filter(|f| re.is_match(f.name.as_str())) // filter by regex
+        .filter(|f| patterns.is_match(f.name.as_str())) // filter by the patterns
+        .map(|f| f.with_last_used(&last_used))
+        .filter(|f| filters.keeps(f)) // then by size and age
         .collect::<Vec<_>>();
 
     let files = registry_pkg_cache.files(); //@TODO fixme?
     let mut registry_pkg_cache_matches: Vec<_> = files
         .iter()
         .map(|path| registry_pkg_cache_to_file(path))
-        .filter(|f| re.is_match(f.name.as_str())) // filter by regex
+        .filter(|f| patterns.is_match(f.name.as_str())) // filter by the patterns
+        .map(|f| f.with_last_used(&last_used))
+        .filter(|f| filters.keeps(f)) // then by size and age
         .collect::<Vec<_>>();
 
     let mut registry_source_caches_matches: Vec<_> = registry_sources_caches
         .items()
         .iter()
         .map(|path| registry_source_cache_to_file(path))
-        .filter(|f| re.is_match(f.name.as_str())) // filter by regex
+        .filter(|f| patterns.is_match(f.name.as_str())) // filter by the patterns
+        .map(|f| f.with_last_used(&last_used))
+        .filter(|f| filters.keeps(f)) // then by size and age
         .collect::<Vec<_>>();
 
     match sorting {
@@ -208,12 +351,7 @@ pub(crate) fn run_query(
                 sort_files_by_name(&mut binary_matches);
                 output.push_str("Binaries sorted by name:\n");
                 binary_matches.iter().for_each(|b| {
-                    let size = if hr_size {
-                        b.size.format_size(DECIMAL)
-                    } else {
-                        b.size.to_string()
-                    };
-                    writeln!(output, "\t{}: {}", b.name, size).unwrap();
+                    writeln!(output, "{}", b.line(hr_size)).unwrap();
                 });
             }
 
@@ -222,12 +360,7 @@ pub(crate) fn run_query(
                 sort_files_by_name(&mut git_checkout_matches);
                 output.push_str("\nGit checkouts sorted by name:\n");
                 git_checkout_matches.iter().for_each(|b| {
-                    let size = if hr_size {
-                        b.size.format_size(DECIMAL)
-                    } else {
-                        b.size.to_string()
-                    };
-                    writeln!(output, "\t{}: {}", b.name, size).unwrap();
+                    writeln!(output, "{}", b.line(hr_size)).unwrap();
                 });
             }
             // bare git repos
@@ -235,12 +368,7 @@ pub(crate) fn run_query(
                 sort_files_by_name(&mut bare_repos_matches);
                 output.push_str("\nBare git repos sorted by name:\n");
                 bare_repos_matches.iter().for_each(|b| {
-                    let size = if hr_size {
-                        b.size.format_size(DECIMAL)
-                    } else {
-                        b.size.to_string()
-                    };
-                    writeln!(output, "\t{}: {}", b.name, size).unwrap();
+                    writeln!(output, "{}", b.line(hr_size)).unwrap();
                 });
             }
 
@@ -249,12 +377,7 @@ pub(crate) fn run_query(
                 sort_files_by_name(&mut registry_pkg_cache_matches);
                 output.push_str("\nRegistry cache sorted by name:\n");
                 registry_pkg_cache_matches.iter().for_each(|b| {
-                    let size = if hr_size {
-                        b.size.format_size(DECIMAL)
-                    } else {
-                        b.size.to_string()
-                    };
-                    writeln!(output, "\t{}: {}", b.name, size).unwrap();
+                    writeln!(output, "{}", b.line(hr_size)).unwrap();
                 });
             }
 
@@ -263,12 +386,7 @@ pub(crate) fn run_query(
                 sort_files_by_name(&mut registry_source_caches_matches);
                 output.push_str("\nRegistry source cache sorted by name:\n");
                 registry_source_caches_matches.iter().for_each(|b| {
-                    let size = if hr_size {
-                        b.size.format_size(DECIMAL)
-                    } else {
-                        b.size.to_string()
-                    };
-                    writeln!(output, "\t{}: {}", b.name, size).unwrap();
+                    writeln!(output, "{}", b.line(hr_size)).unwrap();
                 });
             }
         }
@@ -279,12 +397,7 @@ pub(crate) fn run_query(
                 sort_files_by_size(&mut binary_matches);
                 output.push_str("\nBinaries sorted by size:\n");
                 binary_matches.iter().for_each(|b| {
-                    let size = if hr_size {
-                        b.size.format_size(DECIMAL)
-                    } else {
-                        b.size.to_string()
-                    };
-                    writeln!(output, "\t{}: {}", b.name, size).unwrap();
+                    writeln!(output, "{}", b.line(hr_size)).unwrap();
                 });
             }
 
@@ -293,12 +406,7 @@ pub(crate) fn run_query(
                 sort_files_by_size(&mut git_checkout_matches);
                 output.push_str("\nGit checkouts sorted by size:\n");
                 git_checkout_matches.iter().for_each(|b| {
-                    let size = if hr_size {
-                        b.size.format_size(DECIMAL)
-                    } else {
-                        b.size.to_string()
-                    };
-                    writeln!(output, "\t{}: {}", b.name, size).unwrap();
+                    writeln!(output, "{}", b.line(hr_size)).unwrap();
                 });
             }
 
@@ -307,12 +415,7 @@ pub(crate) fn run_query(
                 sort_files_by_size(&mut bare_repos_matches);
                 output.push_str("\nBare git repos sorted by size:\n");
                 bare_repos_matches.iter().for_each(|b| {
-                    let size = if hr_size {
-                        b.size.format_size(DECIMAL)
-                    } else {
-                        b.size.to_string()
-                    };
-                    writeln!(output, "\t{}: {}", b.name, size).unwrap();
+                    writeln!(output, "{}", b.line(hr_size)).unwrap();
                 });
             }
 
@@ -321,12 +424,7 @@ pub(crate) fn run_query(
                 sort_files_by_size(&mut registry_pkg_cache_matches);
                 output.push_str("\nRegistry cache sorted by size:\n");
                 registry_pkg_cache_matches.iter().for_each(|b| {
-                    let size = if hr_size {
-                        b.size.format_size(DECIMAL)
-                    } else {
-                        b.size.to_string()
-                    };
-                    writeln!(output, "\t{}: {}", b.name, size).unwrap();
+                    writeln!(output, "{}", b.line(hr_size)).unwrap();
                 });
             }
 
@@ -335,12 +433,7 @@ pub(crate) fn run_query(
                 sort_files_by_size(&mut registry_source_caches_matches);
                 output.push_str("\nRegistry source cache sorted by size:\n");
                 registry_source_caches_matches.iter().for_each(|b| {
-                    let size = if hr_size {
-                        b.size.format_size(DECIMAL)
-                    } else {
-                        b.size.to_string()
-                    };
-                    writeln!(output, "\t{}: {}", b.name, size).unwrap();
+                    writeln!(output, "{}", b.line(hr_size)).unwrap();
                 });
             }
         }
@@ -350,18 +443,130 @@ pub(crate) fn run_query(
         }
     }
 
+    last_used.save();
     let trimmed = output.trim();
     if !trimmed.is_empty() {
         println!("{trimmed}");
     }
+
+    if delete {
+        // only the registry items tell the name of the crate
+        let sccache_entries = if query_config.is_present("also-sccache") {
+            let mut names = registry_pkg_cache_matches
+                .iter()
+                .chain(&registry_source_caches_matches)
+                .filter_map(|f| parse_version(f.path).ok())
+                .map(|(name, _)| name)
+                .collect::<Vec<String>>();
+            names.sort();
+            names.dedup();
+            sccache::entries_of_crates(&names.iter().map(String::as_str).collect::<Vec<&str>>())?
+        } else {
+            Vec::new()
+        };
+        let paths = binary_matches
+            .iter()
+            .chain(&git_checkout_matches)
+            .chain(&bare_repos_matches)
+            .chain(&registry_pkg_cache_matches)
+            .chain(&registry_source_caches_matches)
+            .map(|f| f.path)
+            .chain(sccache_entries.iter().map(PathBuf::as_path))
+            .collect::<Vec<&Path>>();
+        remove_and_record_freed(&paths, dry_run);
+    }
     Ok(())
 }
 
 #[cfg(test)]
 mod query_tests {
+    use super::*;
     use crate::test_helpers::bin_path;
+    use pretty_assertions::assert_eq;
     use std::process::Command;
 
+    #[test]
+    fn pattern_sets() {
+        let names = [
+            "serde-1.0.0",
+            "serde_json-1.0.0",
+            "tokio-1.0.0",
+            "tokio-serde-0.8.0",
+        ];
+        let matching = |patterns: &Patterns| {
+            names
+                .iter()
+                .filter(|name| patterns.is_match(name))
+                .copied()
+                .collect::<Vec<&str>>()
+        };
+
+        assert_eq!(matching(&Patterns::new(&[], &[], false).unwrap()), names);
+        assert_eq!(
+            matching(&Patterns::new(&["^serde", "^tokio"], &[], false).unwrap()),
+            names
+        );
+        assert_eq!(
+            matching(&Patterns::new(&["tokio", "serde"], &[], true).unwrap()),
+            vec!["tokio-serde-0.8.0"]
+        );
+        assert_eq!(
+            matching(&Patterns::new(&["serde"], &["json", "^tokio"], false).unwrap()),
+            vec!["serde-1.0.0"]
+        );
+        assert_eq!(
+            matching(&Patterns::new(&[], &["serde"], false).unwrap()),
+            vec!["tokio-1.0.0"]
+        );
+
+        match Patterns::new(&["serde", "tokio("], &[], false) {
+            Err(Error::QueryRegexFailedParsing(pattern)) => assert_eq!(pattern, "tokio("),
+            other => panic!("expected a regex error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn size_and_age_filters() {
+        let tmp = tempfile::tempdir().unwrap();
+        let crate_archive = tmp.path().join("serde-1.0.0.crate");
+        fs::write(&crate_archive, "archive").unwrap();
+        let file = |size: u64| {
+            File {
+                path: &crate_archive,
+                name: String::from("serde-1.0.0"),
+                size,
+                last_used: None,
+            }
+            .with_last_used(&LastUsed::load(tmp.path()))
+        };
+
+        let no_filters = Filters::new(None, None).unwrap();
+        assert!(!no_filters.is_restricted());
+        assert!(no_filters.keeps(&file(0)));
+
+        let larger_than = Filters::new(Some("10M"), None).unwrap();
+        assert!(larger_than.is_restricted());
+        assert!(!larger_than.keeps(&file(10_000_000)));
+        assert!(larger_than.keeps(&file(10_000_001)));
+
+        // the archive was just accessed
+        assert!(!Filters::new(None, Some("2000.01.01"))
+            .unwrap()
+            .keeps(&file(0)));
+        assert!(Filters::new(Some("1K"), Some("2999.01.01"))
+            .unwrap()
+            .keeps(&file(2000)));
+
+        assert!(matches!(
+            Filters::new(Some("lots"), None),
+            Err(Error::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            Filters::new(None, Some("yesterday")),
+            Err(Error::InvalidArgument(_))
+        ));
+    }
+
     #[test]
     fn query_subcmd_long() {
         let query_cmd = Command::new(bin_path()).arg("query").output();
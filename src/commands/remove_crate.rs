@@ -0,0 +1,160 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// "cargo cache remove <crate>[@version]" command
+// remove everything the cache has of a single crate: its .crate archives in registry/cache and its
+// extracted sources in registry/src, of all registries. Without a version all cached versions are
+// removed. With --git the bare repos in git/db and the checkouts in git/checkouts of a git
+// dependency of the same name are removed as well, these have no version.
+// With --also-sccache the objects sccache compiled of the crate go too, see sccache.rs.
+// cargo downloads and extracts whatever it needs again on the next build.
+
+use std::path::PathBuf;
+
+use crate::cache::caches::{get_cache_name, Cache};
+use crate::cache::git_bare_repos::GitRepoCache;
+use crate::cache::git_checkouts::GitCheckoutCache;
+use crate::commands::inspect::find_cached_versions;
+use crate::commands::sccache;
+use crate::library::{CargoCachePaths, Error};
+use crate::remove::remove_and_record_freed;
+
+/// split "serde@1.0.188" into the name and the version
+fn parse_spec(spec: &str) -> Result<(&str, Option<&str>), Error> {
+    let (name, version) = match spec.split_once('@') {
+        Some((name, version)) => (name, Some(version)),
+        None => (spec, None),
+    };
+    if name.is_empty() || version.map_or(false, str::is_empty) {
+        return Err(Error::InvalidCrateSpec(spec.to_string()));
+    }
+    Ok((name, version))
+}
+
+/// the paths of all cache entries of the crate
+fn paths_of_crate(
+    ccp: &CargoCachePaths,
+    name: &str,
+    version: Option<&str>,
+    git: bool,
+) -> Vec<PathBuf> {
+    let mut paths = find_cached_versions(ccp, name)
+        .into_iter()
+        .filter(|krate| version.map_or(true, |version| krate.version == version))
+        .flat_map(|krate| krate.archive.into_iter().chain(krate.source))
+        .collect::<Vec<PathBuf>>();
+
+    if git {
+        let mut bare_repos = GitRepoCache::new(ccp.git_repos_bare.clone());
+        let mut checkouts = GitCheckoutCache::new(ccp.git_checkouts.clone());
+        // the checkouts of a repo are all inside git/checkouts/<repo>-<hash>, remove that directory
+        let mut checkout_repos = checkouts
+            .items()
+            .iter()
+            .filter_map(|checkout| checkout.parent())
+            .map(PathBuf::from)
+            .collect::<Vec<PathBuf>>();
+        checkout_repos.sort();
+        checkout_repos.dedup();
+        paths.extend(
+            bare_repos
+                .items()
+                .iter()
+                .cloned()
+                .chain(checkout_repos)
+                .filter(|repo| get_cache_name(repo) == name),
+        );
+    }
+    paths
+}
+
+/// remove all cached files of a crate
+pub(crate) fn remove_crate(
+    ccp: &CargoCachePaths,
+    spec: &str,
+    git: bool,
+    also_sccache: bool,
+    dry_run: bool,
+) -> Result<(), Error> {
+    let (name, version) = parse_spec(spec)?;
+    let mut paths = paths_of_crate(ccp, name, version, git);
+    if paths.is_empty() {
+        return Err(Error::RemoveCrateNotFound(spec.to_string(), git));
+    }
+    if also_sccache {
+        paths.extend(sccache::entries_of_crates(&[name])?);
+    }
+
+    remove_and_record_freed(&paths, dry_run);
+    Ok(())
+}
+
+#[cfg(test)]
+mod remove_crate_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::fs;
+
+    #[test]
+    fn specs() {
+        assert_eq!(parse_spec("serde").unwrap(), ("serde", None));
+        assert_eq!(
+            parse_spec("serde@1.0.188").unwrap(),
+            ("serde", Some("1.0.188"))
+        );
+        assert!(matches!(
+            parse_spec("@1.0.0"),
+            Err(Error::InvalidCrateSpec(_))
+        ));
+        assert!(matches!(
+            parse_spec("serde@"),
+            Err(Error::InvalidCrateSpec(_))
+        ));
+    }
+
+    #[test]
+    fn paths() {
+        let tmp = tempfile::tempdir().unwrap();
+        let ccp = CargoCachePaths::new(tmp.path().to_path_buf()).unwrap();
+        let registry = "index.crates.io-6f17d22bba15001f";
+        let archives = ccp.registry_pkg_cache.join(registry);
+        let sources = ccp.registry_sources.join(registry);
+        let bare_repo = ccp.git_repos_bare.join("foo-0123456789abcdef");
+        let checkout = ccp
+            .git_checkouts
+            .join("foo-0123456789abcdef")
+            .join("a1b2c3d");
+        for dir in [&sources.join("foo-1.0.0"), &bare_repo, &checkout] {
+            fs::create_dir_all(dir).unwrap();
+        }
+        fs::create_dir_all(ccp.git_repos_bare.join("foo-bar-0123456789abcdef")).unwrap();
+        fs::create_dir_all(&archives).unwrap();
+        for archive in ["foo-1.0.0.crate", "foo-2.0.0.crate", "foo-bar-1.0.0.crate"] {
+            fs::write(archives.join(archive), "archive").unwrap();
+        }
+
+        assert_eq!(
+            paths_of_crate(&ccp, "foo", None, false),
+            vec![
+                archives.join("foo-1.0.0.crate"),
+                sources.join("foo-1.0.0"),
+                archives.join("foo-2.0.0.crate"),
+            ]
+        );
+        assert_eq!(
+            paths_of_crate(&ccp, "foo", Some("2.0.0"), true),
+            vec![
+                archives.join("foo-2.0.0.crate"),
+                bare_repo,
+                ccp.git_checkouts.join("foo-0123456789abcdef"),
+            ]
+        );
+        assert!(paths_of_crate(&ccp, "foo", Some("3.0.0"), false).is_empty());
+    }
+}
@@ -0,0 +1,143 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// "cargo cache savings" command
+// sum up how much space cargo-cache freed in this cargo home over the last week, month and
+// in total, and by which operation.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use humansize::{FormatSize, DECIMAL};
+
+use crate::history::{self, Entry};
+use crate::library::CargoCachePaths;
+use crate::tables::{two_row_table, TableLine};
+
+const DAY: u64 = 24 * 60 * 60;
+
+/// sum of the space freed by the entries that are younger than `max_age` seconds
+fn freed_since(entries: &[Entry], now: u64, max_age: u64) -> u64 {
+    entries
+        .iter()
+        .filter(|entry| now.saturating_sub(entry.time) < max_age)
+        .map(|entry| entry.freed)
+        .sum()
+}
+
+/// generate the "cargo cache savings" table
+fn savings_table(cargo_home: &Path, entries: &[Entry], now: u64) -> String {
+    if entries.is_empty() {
+        return format!(
+            "cargo-cache has not freed any space in '{}' yet.\n",
+            cargo_home.display()
+        );
+    }
+
+    // number of runs and bytes freed of each operation
+    let mut per_command: BTreeMap<&str, (usize, u64)> = BTreeMap::new();
+    for entry in entries {
+        let (runs, freed) = per_command.entry(&entry.command).or_default();
+        *runs += 1;
+        *freed += entry.freed;
+    }
+    let mut per_command = per_command.into_iter().collect::<Vec<_>>();
+    // most space freed first
+    per_command.sort_by(|(_, (_, a)), (_, (_, b))| b.cmp(a));
+
+    let mut table = vec![
+        TableLine::new(
+            0,
+            &format!(
+                "Space freed by cargo-cache in '{}':\n\n",
+                cargo_home.display()
+            ),
+            &String::new(),
+        ),
+        TableLine::new(
+            0,
+            &"Last 7 days: ".to_string(),
+            &freed_since(entries, now, 7 * DAY).format_size(DECIMAL),
+        ),
+        TableLine::new(
+            0,
+            &"Last 30 days: ".to_string(),
+            &freed_since(entries, now, 30 * DAY).format_size(DECIMAL),
+        ),
+        TableLine::new(
+            0,
+            &"All time: ".to_string(),
+            &entries
+                .iter()
+                .map(|entry| entry.freed)
+                .sum::<u64>()
+                .format_size(DECIMAL),
+        ),
+    ];
+    table.extend(per_command.into_iter().map(|(command, (runs, freed))| {
+        TableLine::new(
+            1,
+            &format!("{command} ({runs} runs): "),
+            &freed.format_size(DECIMAL),
+        )
+    }));
+
+    two_row_table(2, table, false)
+}
+
+/// print how much space cargo-cache freed in the cargo home
+pub(crate) fn savings(ccp: &CargoCachePaths) {
+    let entries = history::read(&ccp.cargo_home);
+    print!(
+        "{}",
+        savings_table(&ccp.cargo_home, &entries, history::now())
+    );
+}
+
+#[cfg(test)]
+mod savings_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn table() {
+        let now = 100 * DAY;
+        let entry = |days_ago: u64, command: &str, freed: u64| Entry {
+            time: now - days_ago * DAY,
+            command: command.to_string(),
+            freed,
+        };
+        let entries = vec![
+            entry(60, "--autoclean", 5_000_000),
+            entry(20, "trim", 1_000_000),
+            entry(2, "--autoclean", 2_000_000),
+        ];
+
+        assert_eq!(freed_since(&entries, now, 7 * DAY), 2_000_000);
+        assert_eq!(freed_since(&entries, now, 30 * DAY), 3_000_000);
+
+        let table = savings_table(Path::new("/home/user/.cargo"), &entries, now);
+        assert_eq!(
+            table,
+            "Space freed by cargo-cache in '/home/user/.cargo':
+
+Last 7 days:              2 MB
+Last 30 days:             3 MB
+All time:                 8 MB
+  --autoclean (2 runs):   7 MB
+  trim (1 runs):          1 MB
+"
+        );
+
+        assert_eq!(
+            savings_table(Path::new("/home/user/.cargo"), &[], now),
+            "cargo-cache has not freed any space in '/home/user/.cargo' yet.\n"
+        );
+    }
+}
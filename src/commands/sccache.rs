@@ -8,18 +8,32 @@
 // except according to those terms.
 
 // find ~/.cache/sccache -type f -printf "\n%AD %AT %p"  | cut -d' ' -f1 | sort -n | uniq -c
+// "sccache --clean" removes the least recently used files like "trim" does with the cargo cache
+// "remove <crate> --also-sccache" and "query --delete --also-sccache" remove the compiled objects of
+// the removed crates as well: sccache names its entries after a hash of the compilation, but every
+// entry is a zip archive of the output files and rustc names these after the crate
+// ("libserde-<hash>.rlib", "serde-<hash>.d"), so we look at the file names in the archives.
+// The version is not part of these names, the objects of all versions of a crate are removed.
 
+use std::cmp::Reverse;
 use std::env;
 use std::fs;
-use std::path::PathBuf;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
 
 use chrono::prelude::*;
-use humansize::{FormatSize, DECIMAL};
+use humansize::{FormatSize, BINARY, DECIMAL};
+use rayon::prelude::*;
 use walkdir::WalkDir;
 
+use crate::commands::trim::{self, EvictionItem, EvictionKind};
 use crate::library;
+use crate::remove::{remove_file, DryRunMessage};
 use crate::tables::format_table;
 
+/// the cache size sccache uses if `${SCCACHE_CACHE_SIZE}` is not set
+const DEFAULT_SCCACHE_CACHE_SIZE: &str = "10G";
+
 #[derive(Debug, Clone)]
 struct File {
     path: PathBuf,
@@ -36,25 +50,220 @@ pub(crate) fn percentage_of_as_string(fraction: u64, total: u64) -> String {
 }
 
 /// get the location of a local sccache path
+///
+/// this mirrors what sccache itself does: `${SCCACHE_DIR}` takes precedence, otherwise
+/// we use the platform specific cache directory
 fn sccache_dir() -> Result<PathBuf, library::Error> {
-    env::var_os("SCCACHE_DIR")
+    let sccache_path = env::var_os("SCCACHE_DIR")
         .map(PathBuf::from)
         .or_else(|| {
-            const CACHE_DIR_NAME: &str = if cfg!(target_os = "macos") {
-                "Mozilla.sccache"
+            if cfg!(target_os = "macos") {
+                // ~/Library/Caches/Mozilla.sccache
+                Some(dirs_next::cache_dir()?.join("Mozilla.sccache"))
             } else if cfg!(target_os = "windows") {
-                "Mozilla\\sccache"
+                // %LOCALAPPDATA%\Mozilla\sccache\cache
+                Some(
+                    dirs_next::data_local_dir()?
+                        .join("Mozilla")
+                        .join("sccache")
+                        .join("cache"),
+                )
             } else {
-                "sccache"
-            };
+                // ${XDG_CACHE_HOME}/sccache or ~/.cache/sccache
+                Some(dirs_next::cache_dir()?.join("sccache"))
+            }
+        })
+        .ok_or(library::Error::NoSccacheDir(None))?;
+
+    if sccache_path.is_dir() {
+        Ok(sccache_path)
+    } else {
+        Err(library::Error::NoSccacheDir(Some(sccache_path)))
+    }
+}
+
+/// parse a size as understood by sccache: a number optionally followed by K, M, G or T,
+/// sccache uses binary units (1K = 1024 bytes)
+fn parse_sccache_size(size: &str) -> Option<u64> {
+    let multiplier: u64 = match size.chars().last() {
+        Some('K') => 1024,
+        Some('M') => 1024 * 1024,
+        Some('G') => 1024 * 1024 * 1024,
+        Some('T') => 1024 * 1024 * 1024 * 1024,
+        _ => 1,
+    };
+    let number = if multiplier > 1 {
+        &size[..size.len() - 1]
+    } else {
+        size
+    };
+    number
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+}
+
+/// get the maximum size the sccache cache may grow to, sccache defaults to 10G
+fn sccache_max_size() -> Result<u64, library::Error> {
+    match env::var("SCCACHE_CACHE_SIZE") {
+        Ok(size) => {
+            parse_sccache_size(&size).ok_or(library::Error::SccacheCacheSizeParseFailure(size))
+        }
+        Err(_) => Ok(parse_sccache_size(DEFAULT_SCCACHE_CACHE_SIZE).unwrap()),
+    }
+}
+
+/// the files of the sccache cache that have to go so that it does not exceed `size_limit` bytes,
+/// least recently used first
+fn entries_to_trim(sccache_path: &Path, size_limit: u64) -> Vec<EvictionItem> {
+    let mut entries: Vec<EvictionItem> = WalkDir::new(sccache_path)
+        .into_iter()
+        .flatten()
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| EvictionItem::new(entry.path(), EvictionKind::SccacheEntry))
+        .collect();
+    // youngest first, like the items of the cargo cache
+    entries.sort_by_key(|entry| Reverse(entry.last_access));
+    let mut to_remove = trim::select_for_removal(entries, Some(size_limit), None);
+    to_remove.reverse();
+    to_remove
+}
+
+/// `sccache --clean` and `--trim-limit <size>`: remove the least recently used files of the sccache cache
+/// until it is smaller than the limit, the maximum size sccache is configured to if there is none
+pub(crate) fn sccache_trim(trim_limit: Option<&str>, dry_run: bool) -> Result<(), library::Error> {
+    let sccache_path: PathBuf = sccache_dir()?;
+    let size_limit = match trim_limit {
+        Some(limit) => trim::parse_size_limit_to_bytes(Some(limit))?,
+        None => sccache_max_size()?,
+    };
+
+    let entries = entries_to_trim(&sccache_path, size_limit);
+    if entries.is_empty() {
+        println!(
+            "The sccache cache is smaller than {}, nothing to remove.\n",
+            size_limit.format_size(DECIMAL)
+        );
+        return Ok(());
+    }
+
+    let mut size_changed = false;
+    let mut removed_size: u64 = 0;
+    let mut removed_kinds = Vec::new();
+    for entry in entries {
+        remove_file(
+            &entry.path,
+            dry_run,
+            &mut size_changed,
+            None,
+            &DryRunMessage::Default,
+            Some(entry.size),
+        );
+        removed_size += entry.size;
+        removed_kinds.push(entry.kind);
+    }
+    println!("{}\n", trim::removal_summary(&removed_kinds, removed_size));
+    Ok(())
+}
+
+/// the little-endian number of `N` bytes at `at`
+fn number_at<const N: usize>(bytes: &[u8], at: usize) -> Option<u64> {
+    let bytes = bytes.get(at..at.checked_add(N)?)?;
+    Some(
+        bytes
+            .iter()
+            .rev()
+            .fold(0, |number, byte| number << 8 | u64::from(*byte)),
+    )
+}
 
-            Some(dirs_next::cache_dir()?.join(CACHE_DIR_NAME))
+/// the names of the files inside a zip archive, read from the central directory at its end
+/// None if the file is not a zip archive
+fn zip_file_names(path: &Path) -> Option<Vec<String>> {
+    let mut file = fs::File::open(path).ok()?;
+    let len = file.metadata().ok()?.len();
+    // the end of central directory record: 22 bytes and a comment of at most 64 KiB
+    let tail_len = len.min(22 + 0xFFFF);
+    let _ = file.seek(SeekFrom::Start(len - tail_len)).ok()?;
+    let mut tail = vec![0; usize::try_from(tail_len).ok()?];
+    file.read_exact(&mut tail).ok()?;
+    let end = (0..=tail.len().checked_sub(22)?)
+        .rev()
+        .find(|&at| tail[at..].starts_with(b"PK\x05\x06"))?;
+    let directory_size = number_at::<4>(&tail, end + 12)?;
+    let directory_offset = number_at::<4>(&tail, end + 16)?;
+    // this also skips zip64 archives, their offsets do not fit into these fields
+    if directory_offset.checked_add(directory_size)? > len {
+        return None;
+    }
+
+    let _ = file.seek(SeekFrom::Start(directory_offset)).ok()?;
+    let mut directory = vec![0; usize::try_from(directory_size).ok()?];
+    file.read_exact(&mut directory).ok()?;
+    let mut names = Vec::new();
+    let mut at = 0;
+    while directory
+        .get(at..)
+        .map_or(false, |entry| entry.starts_with(b"PK\x01\x02"))
+    {
+        let name_len = usize::try_from(number_at::<2>(&directory, at + 28)?).ok()?;
+        let extra_len = usize::try_from(number_at::<2>(&directory, at + 30)?).ok()?;
+        let comment_len = usize::try_from(number_at::<2>(&directory, at + 32)?).ok()?;
+        let name = directory.get(at + 46..at + 46 + name_len)?;
+        names.push(String::from_utf8_lossy(name).into_owned());
+        at += 46 + name_len + extra_len + comment_len;
+    }
+    Some(names)
+}
+
+/// whether rustc named the output file after the crate: "lib<crate>-<hash>.rlib", "<crate>-<hash>.d"..
+/// cargo passes the 16 hex digits of the hash as `-C extra-filename`
+/// `crate_name` with underscores, as rustc uses it
+fn is_output_of(file_name: &str, crate_name: &str) -> bool {
+    [Some(file_name), file_name.strip_prefix("lib")]
+        .into_iter()
+        .flatten()
+        .filter_map(|name| name.strip_prefix(crate_name)?.strip_prefix('-'))
+        .filter_map(|rest| rest.split_once('.'))
+        .any(|(hash, _)| hash.len() == 16 && hash.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// the entries of the sccache cache in `sccache_path` that hold outputs of any of the crates
+fn entries_of_crates_in(sccache_path: &Path, names: &[&str]) -> Vec<PathBuf> {
+    let crate_names = names
+        .iter()
+        .map(|name| name.replace('-', "_"))
+        .collect::<Vec<String>>();
+    let files = WalkDir::new(sccache_path)
+        .into_iter()
+        .flatten()
+        .filter(|entry| entry.file_type().is_file())
+        .map(walkdir::DirEntry::into_path)
+        .collect::<Vec<PathBuf>>();
+    let mut entries = files
+        .into_par_iter()
+        .filter(|path| {
+            zip_file_names(path).map_or(false, |file_names| {
+                file_names.iter().any(|file_name| {
+                    crate_names
+                        .iter()
+                        .any(|crate_name| is_output_of(file_name, crate_name))
+                })
+            })
         })
-        .ok_or(library::Error::NoSccacheDir)
+        .collect::<Vec<PathBuf>>();
+    entries.sort();
+    entries
+}
+
+/// --also-sccache: the entries of the sccache cache that hold compiled objects of the crates
+pub(crate) fn entries_of_crates(names: &[&str]) -> Result<Vec<PathBuf>, library::Error> {
+    Ok(entries_of_crates_in(&sccache_dir()?, names))
 }
 
 pub(crate) fn sccache_stats() -> Result<(), library::Error> {
     let sccache_path: PathBuf = sccache_dir()?;
+    let max_size: u64 = sccache_max_size()?;
 
     // of all the files inside the sccache cache, gather last access time and path
     let files = WalkDir::new(sccache_path.display().to_string())
@@ -65,14 +274,13 @@ pub(crate) fn sccache_stats() -> Result<(), library::Error> {
                 if path.is_file() {
                     if let Ok(metadata) = fs::metadata(&path) {
                         if let Ok(access_time) = metadata.accessed() {
-                            let access_time =
-                                chrono::DateTime::<Local>::from(access_time).naive_local();
+                            let access_time = DateTime::<Local>::from(access_time).naive_local();
                             let access_date = access_time.date();
                             return Some(File { path, access_date });
                         }
                     }
                 }
-            };
+            }
 
             None
         });
@@ -176,5 +384,135 @@ pub(crate) fn sccache_stats() -> Result<(), library::Error> {
     let table = format_table(&table_vec, 1); // need so strip whitespaces added by the padding
     let table_trimmed = table.trim();
     println!("{table_trimmed}");
+
+    println!(
+        "\nsccache directory: {}\nConfigured maximum size: {}, used: {} ({})",
+        sccache_path.display(),
+        max_size.format_size(BINARY),
+        total_size.format_size(BINARY),
+        percentage_of_as_string(total_size, max_size)
+    );
     Ok(())
 }
+
+#[cfg(test)]
+mod sccache_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn parse_sizes() {
+        assert_eq!(parse_sccache_size("0"), Some(0));
+        assert_eq!(parse_sccache_size("1337"), Some(1337));
+        assert_eq!(parse_sccache_size("1K"), Some(1024));
+        assert_eq!(parse_sccache_size("2M"), Some(2 * 1024 * 1024));
+        assert_eq!(parse_sccache_size("10G"), Some(10 * 1024 * 1024 * 1024));
+        assert_eq!(parse_sccache_size("1T"), Some(1024 * 1024 * 1024 * 1024));
+
+        assert_eq!(parse_sccache_size(""), None);
+        assert_eq!(parse_sccache_size("G"), None);
+        assert_eq!(parse_sccache_size("1.5G"), None);
+        assert_eq!(parse_sccache_size("10g"), None);
+        // does not fit into an u64
+        assert_eq!(parse_sccache_size("99999999999T"), None);
+    }
+
+    /// a zip archive with only the central directory, that is all we read
+    fn zip(file_names: &[&str]) -> Vec<u8> {
+        let mut zip = b"PK\x03\x04 the compressed files".to_vec();
+        let directory_offset = zip.len();
+        for name in file_names {
+            zip.extend(b"PK\x01\x02");
+            zip.extend([0; 24]);
+            zip.extend(u16::try_from(name.len()).unwrap().to_le_bytes());
+            zip.extend([0; 16]);
+            zip.extend(name.as_bytes());
+        }
+        let directory_size = zip.len() - directory_offset;
+        zip.extend(b"PK\x05\x06");
+        zip.extend([0; 4]);
+        zip.extend(u16::try_from(file_names.len()).unwrap().to_le_bytes());
+        zip.extend(u16::try_from(file_names.len()).unwrap().to_le_bytes());
+        zip.extend(u32::try_from(directory_size).unwrap().to_le_bytes());
+        zip.extend(u32::try_from(directory_offset).unwrap().to_le_bytes());
+        zip.extend([0; 2]);
+        zip
+    }
+
+    #[test]
+    fn output_names() {
+        assert!(is_output_of("libserde-8e9fbd3fa1c5a2b1.rlib", "serde"));
+        assert!(is_output_of("libserde-8e9fbd3fa1c5a2b1.rmeta", "serde"));
+        assert!(is_output_of("serde-8e9fbd3fa1c5a2b1.d", "serde"));
+        assert!(is_output_of(
+            "libserde_derive-0123abcd0123abcd.so",
+            "serde_derive"
+        ));
+
+        assert!(!is_output_of(
+            "libserde_json-8e9fbd3fa1c5a2b1.rlib",
+            "serde"
+        ));
+        assert!(!is_output_of("libserde-.rlib", "serde"));
+        assert!(!is_output_of("libserde-1.0.188.rlib", "serde"));
+        assert!(!is_output_of("libserde-8e9fbd3f.rlib", "serde"));
+        assert!(!is_output_of("stdout", "serde"));
+    }
+
+    #[test]
+    fn entries_of_crates() {
+        let tmp = tempfile::tempdir().unwrap();
+        let entry = |dir: &str, file_names: &[&str]| {
+            let dir = tmp.path().join(dir);
+            fs::create_dir_all(&dir).unwrap();
+            let path = dir.join("0123456789abcdef");
+            fs::write(&path, zip(file_names)).unwrap();
+            path
+        };
+        let serde = entry(
+            "a/b",
+            &["libserde-8e9fbd3fa1c5a2b1.rlib", "stdout", "stderr"],
+        );
+        let derive = entry(
+            "c/d",
+            &[
+                "libserde_derive-0123abcd0123abcd.so",
+                "serde_derive-0123abcd0123abcd.d",
+            ],
+        );
+        let _json = entry("e/f", &["libserde_json-4567cdef4567cdef.rmeta"]);
+        let not_a_zip = tmp.path().join("a").join("not-a-zip");
+        fs::write(&not_a_zip, "libserde-8e9fbd3fa1c5a2b1.rlib").unwrap();
+
+        assert_eq!(zip_file_names(&serde).unwrap().len(), 3);
+        assert_eq!(zip_file_names(&not_a_zip), None);
+        assert_eq!(
+            entries_of_crates_in(tmp.path(), &["serde", "serde-derive"]),
+            vec![serde, derive]
+        );
+        assert!(entries_of_crates_in(tmp.path(), &["rand"]).is_empty());
+    }
+
+    #[test]
+    fn trim_entries() {
+        let tmp = tempfile::tempdir().unwrap();
+        for (dir, size) in [("a/b", 100), ("c/d", 200), ("e", 300)] {
+            let dir = tmp.path().join(dir);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("entry"), "x".repeat(size)).unwrap();
+        }
+        let removed_size = |limit| {
+            entries_to_trim(tmp.path(), limit)
+                .iter()
+                .map(|entry| entry.size)
+                .sum::<u64>()
+        };
+        assert_eq!(removed_size(600), 0);
+        assert_eq!(removed_size(0), 600);
+        // only files count, whole entries are removed until the rest fits
+        assert!(removed_size(550) >= 50);
+        assert!(entries_to_trim(tmp.path(), 550)
+            .iter()
+            .all(|entry| entry.kind == EvictionKind::SccacheEntry && entry.path.is_file()));
+    }
+}
@@ -0,0 +1,175 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// "cargo cache self check-update" command
+// compare the version of the running cargo-cache with the latest release on crates.io.
+// We first look into the crates.io index that cargo cached in the cargo home, this does not need
+// network access but may be out of date. With --online we ask crates.io via "cargo search".
+
+use std::ffi::OsString;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+use cargo_metadata::semver::Version;
+
+use crate::index::read_index_entries;
+use crate::library::{CargoCachePaths, Error};
+use crate::network;
+
+const CRATE_NAME: &str = "cargo-cache";
+
+/// the crates.io index directories inside the cargo home (sparse and git)
+fn crates_io_indices(ccp: &CargoCachePaths) -> Vec<PathBuf> {
+    fs::read_dir(&ccp.registry_index)
+        .map(|dirs| {
+            dirs.filter_map(Result::ok)
+                .map(|entry| entry.path())
+                .filter(|path| {
+                    let name = path.file_name().unwrap().to_string_lossy();
+                    name.starts_with("index.crates.io-") || name.starts_with("github.com-")
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// the latest release (not yanked, no pre-release) of cargo-cache in the local crates.io index
+fn latest_in_local_index(ccp: &CargoCachePaths) -> Option<Version> {
+    crates_io_indices(ccp)
+        .iter()
+        .filter_map(|index| read_index_entries(index, CRATE_NAME))
+        .flatten()
+        .filter(|entry| !entry.yanked && entry.version.pre.is_empty())
+        .map(|entry| entry.version)
+        .max()
+}
+
+/// find `cargo-cache = "0.8.3"    # Manage cargo cache...` in the output of "cargo search"
+fn parse_search_output(output: &str) -> Option<Version> {
+    output.lines().find_map(|line| {
+        let (name, rest) = line.split_once('=')?;
+        if name.trim() != CRATE_NAME {
+            return None;
+        }
+        let version = rest.trim_start().strip_prefix('"')?.split('"').next()?;
+        Version::parse(version).ok()
+    })
+}
+
+/// ask crates.io for the latest release of cargo-cache
+fn latest_on_crates_io() -> Result<Version, Error> {
+    // cargo tells its subcommands where it is located
+    let cargo = std::env::var_os("CARGO").unwrap_or_else(|| OsString::from("cargo"));
+    let output = Command::new(cargo)
+        .args(["search", "--limit", "1", CRATE_NAME])
+        .output()
+        .map_err(|error| Error::CheckUpdateFailed(error.to_string()))?;
+    if !output.status.success() {
+        return Err(Error::CheckUpdateFailed(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+
+    parse_search_output(&String::from_utf8_lossy(&output.stdout)).ok_or_else(|| {
+        Error::CheckUpdateFailed(format!("\"cargo search\" did not find {CRATE_NAME}"))
+    })
+}
+
+/// tell the user whether an update is available and how to install it
+fn update_message(current: &Version, latest: Option<&Version>, online: bool) -> String {
+    let source = if online {
+        "crates.io"
+    } else {
+        "the local crates.io index"
+    };
+
+    match latest {
+        None => format!(
+            "cargo-cache {current} is installed, {CRATE_NAME} was not found in {source}.\n\
+            Run \"cargo cache self check-update --online\" to ask crates.io.\n"
+        ),
+        Some(latest) if latest > current => format!(
+            "cargo-cache {current} is installed, {latest} is available according to {source}.\n\n\
+            Update with:\n    cargo install {CRATE_NAME}\n\
+            If cargo-cache was installed via a package manager, use that to update it instead.\n"
+        ),
+        Some(latest) => {
+            let mut message = format!(
+                "cargo-cache {current} is up to date, the latest release according to {source} is {latest}.\n"
+            );
+            if !online {
+                message.push_str(
+                    "The local index may be out of date, run \"cargo cache self check-update --online\" to ask crates.io.\n",
+                );
+            }
+            message
+        }
+    }
+}
+
+/// print whether a newer release of cargo-cache is available
+pub(crate) fn check_update(ccp: &CargoCachePaths, online: bool) -> Result<(), Error> {
+    let current = Version::parse(env!("CARGO_PKG_VERSION")).unwrap();
+    let online = network::allow("self check-update --online", online)?;
+    let latest = if online {
+        Some(latest_on_crates_io()?)
+    } else {
+        latest_in_local_index(ccp)
+    };
+
+    print!("{}", update_message(&current, latest.as_ref(), online));
+    Ok(())
+}
+
+#[cfg(test)]
+mod self_update_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn search_output() {
+        let output = r#"cargo-cache = "0.8.3"    # Manage cargo cache (${CARGO_HOME}, ~/.cargo/), print sizes of dirs and remove dirs selectively
+... and 12 crates more (use --limit N to see more)
+"#;
+        assert_eq!(parse_search_output(output), Some(Version::new(0, 8, 3)));
+        assert_eq!(
+            parse_search_output(r#"cargo-cache-foo = "1.0.0"    # something else"#),
+            None
+        );
+        assert_eq!(parse_search_output(""), None);
+    }
+
+    #[test]
+    fn messages() {
+        let current = Version::new(0, 8, 3);
+
+        assert_eq!(
+            update_message(&current, Some(&Version::new(0, 9, 0)), true),
+            "cargo-cache 0.8.3 is installed, 0.9.0 is available according to crates.io.
+
+Update with:
+    cargo install cargo-cache
+If cargo-cache was installed via a package manager, use that to update it instead.
+"
+        );
+        assert_eq!(
+            update_message(&current, Some(&Version::new(0, 8, 3)), false),
+            "cargo-cache 0.8.3 is up to date, the latest release according to the local crates.io index is 0.8.3.
+The local index may be out of date, run \"cargo cache self check-update --online\" to ask crates.io.
+"
+        );
+        assert_eq!(
+            update_message(&current, None, false),
+            "cargo-cache 0.8.3 is installed, cargo-cache was not found in the local crates.io index.
+Run \"cargo cache self check-update --online\" to ask crates.io.
+"
+        );
+    }
+}
@@ -0,0 +1,455 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// "cargo cache selftest-sizes"
+// Cross-checks the sizes cargo-cache calculates for each component with a plain recursive scan,
+// pretty much what "du --apparent-size" does. The two don't have to agree byte for byte:
+// cargo-cache counts a hard linked file once per link, follows symlinks, skips whatever the
+// .cargocacheignore lists and counts the directory entries of the git checkouts as well.
+// The scan keeps track of all of these, a difference that remains is an accounting bug.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use humansize::{FormatSize, DECIMAL};
+use walkdir::WalkDir;
+
+use crate::cache::caches::{Cache, RegistrySuperCache};
+use crate::cache::*;
+use crate::ignore::{is_ignored, IGNORE_FILE_NAME};
+use crate::library::{CargoCachePaths, Component, ComponentSelection, Error};
+use crate::remove::component_path;
+use crate::tables::format_table;
+use crate::walk::guarded;
+
+/// how cargo-cache looks at the directory of a component
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Layout {
+    /// files below this depth are not counted, the bin dir is not looked at recursively
+    max_depth: Option<usize>,
+    /// the size of the directory entries is counted as well, but only if something is checked out
+    counts_directories: bool,
+}
+
+/// what the full scan of a component found
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Scan {
+    /// apparent size of all regular files, hard linked files are counted once
+    total: u64,
+    /// size of the blocks allocated for these files
+    on_disk: u64,
+    /// size of the additional hard links to files that were already counted
+    hardlinked: u64,
+    /// size of what symlinks point to
+    symlinked: u64,
+    /// size of the directory entries
+    directories: u64,
+    /// number of directories two levels down (the git checkouts)
+    checkouts: usize,
+    /// size of the files in paths listed in the .cargocacheignore
+    ignored: u64,
+    /// size of the files below the depth cargo-cache looks at
+    nested: u64,
+    /// the part of the apparent size of sparse files that is not allocated
+    sparse: u64,
+}
+
+/// the device and inode of a file with several hard links
+#[cfg(unix)]
+fn hardlinked_inode(metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    (metadata.nlink() > 1).then(|| (metadata.dev(), metadata.ino()))
+}
+
+/// hard links are not detected on windows
+#[cfg(not(unix))]
+fn hardlinked_inode(_metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+/// the size of the blocks allocated for a file
+#[cfg(unix)]
+fn allocated(metadata: &fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    metadata.blocks() * 512
+}
+
+/// sparse files are not detected on windows
+#[cfg(not(unix))]
+fn allocated(metadata: &fs::Metadata) -> u64 {
+    metadata.len()
+}
+
+/// walk the whole directory of a component without following symlinks
+fn scan(path: &Path, layout: Layout) -> Scan {
+    let mut scan = Scan::default();
+    if !path.is_dir() {
+        return scan;
+    }
+    let mut seen_inodes = HashSet::new();
+    let mut ignored_dirs: Vec<PathBuf> = Vec::new();
+
+    // unreadable entries are reported by the walk
+    for entry in guarded(WalkDir::new(path), |_| true) {
+        let metadata = match entry.metadata() {
+            Ok(metadata) => metadata,
+            // removed in the meantime
+            Err(_) => continue,
+        };
+        let ignored = ignored_dirs.iter().any(|dir| entry.path().starts_with(dir))
+            || is_ignored(entry.path());
+        let counted = !ignored && layout.max_depth.map_or(true, |max| entry.depth() <= max);
+        let file_type = entry.file_type();
+
+        if file_type.is_dir() {
+            if ignored {
+                ignored_dirs.push(entry.into_path());
+            } else if counted && layout.counts_directories {
+                scan.directories += metadata.len();
+                if entry.depth() == 2 {
+                    scan.checkouts += 1;
+                }
+            }
+        } else if file_type.is_symlink() {
+            // dangling symlinks are not counted by anyone
+            if let Ok(target) = fs::metadata(entry.path()) {
+                if counted && (target.is_file() || layout.counts_directories) {
+                    scan.symlinked += target.len();
+                }
+            }
+        } else if file_type.is_file() {
+            let size = metadata.len();
+            let first_link =
+                hardlinked_inode(&metadata).map_or(true, |inode| seen_inodes.insert(inode));
+            if first_link {
+                let allocated = allocated(&metadata);
+                scan.total += size;
+                scan.on_disk += allocated;
+                scan.sparse += size.saturating_sub(allocated);
+                if ignored {
+                    scan.ignored += size;
+                } else if !counted {
+                    scan.nested += size;
+                }
+            } else if counted {
+                scan.hardlinked += size;
+            }
+        }
+    }
+    scan
+}
+
+/// the size of a component as cargo-cache calculates it, None are the installed binaries
+fn cargo_cache_size(component: Option<&Component>, ccp: &CargoCachePaths) -> u64 {
+    match component {
+        None => bin::BinaryCache::new(ccp.bin_dir.clone()).total_size(),
+        Some(Component::GitDB) => {
+            git_bare_repos::GitRepoCache::new(ccp.git_repos_bare.clone()).total_size()
+        }
+        Some(Component::GitRepos) => {
+            git_checkouts::GitCheckoutCache::new(ccp.git_checkouts.clone()).total_size()
+        }
+        Some(Component::RegistrySources) => {
+            registry_sources::RegistrySourceCaches::new(ccp.registry_sources.clone()).total_size()
+        }
+        Some(Component::RegistryCrateCache) => {
+            registry_pkg_cache::RegistryPkgCaches::new(ccp.registry_pkg_cache.clone()).total_size()
+        }
+        Some(Component::RegistryIndex) => {
+            registry_index::RegistryIndicesCache::new(ccp.registry_index.clone()).total_size()
+        }
+    }
+}
+
+/// a size with a sign
+fn signed(difference: i128) -> String {
+    let size = u64::try_from(difference.unsigned_abs())
+        .unwrap_or(u64::MAX)
+        .format_size(DECIMAL);
+    match difference {
+        0 => size,
+        d if d > 0 => format!("+{size}"),
+        _ => format!("-{size}"),
+    }
+}
+
+/// the sizes of a component according to cargo-cache and to the full scan
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Check {
+    name: &'static str,
+    /// the size cargo-cache calculated before and after the full scan
+    cargo_cache: (u64, u64),
+    scan: Scan,
+    layout: Layout,
+}
+
+impl Check {
+    /// something was added or removed while we were scanning, the sizes can't be compared
+    fn changed_during_scan(&self) -> bool {
+        self.cargo_cache.0 != self.cargo_cache.1
+    }
+
+    fn difference(&self) -> i128 {
+        i128::from(self.cargo_cache.1) - i128::from(self.scan.total)
+    }
+
+    /// the part of the difference the scan found a reason for
+    fn explained(&self) -> i128 {
+        let directories = if self.layout.counts_directories && self.scan.checkouts > 0 {
+            self.scan.directories
+        } else {
+            0
+        };
+        i128::from(self.scan.hardlinked) + i128::from(self.scan.symlinked) + i128::from(directories)
+            - i128::from(self.scan.ignored)
+            - i128::from(self.scan.nested)
+    }
+
+    /// whether the difference is an accounting bug
+    fn mismatch(&self) -> bool {
+        !self.changed_during_scan() && self.difference() != self.explained()
+    }
+
+    /// why the sizes differ, one line each
+    fn causes(&self) -> Vec<String> {
+        let scan = &self.scan;
+        let mut causes = Vec::new();
+        let mut cause = |size: u64, what: &str| {
+            if size > 0 {
+                causes.push(format!(
+                    "{}: {} {what}",
+                    self.name,
+                    size.format_size(DECIMAL)
+                ));
+            }
+        };
+        cause(
+            scan.hardlinked,
+            "of additional hard links to files that are already counted, cargo-cache counts every link",
+        );
+        cause(
+            scan.symlinked,
+            "that symlinks point to, cargo-cache follows them",
+        );
+        if self.layout.counts_directories && scan.checkouts > 0 {
+            cause(
+                scan.directories,
+                "of directory entries, cargo-cache counts them as well",
+            );
+        }
+        cause(
+            scan.ignored,
+            &format!("in paths listed in the {IGNORE_FILE_NAME}, cargo-cache skips them"),
+        );
+        cause(
+            scan.nested,
+            "in subdirectories, cargo-cache does not look at them",
+        );
+        cause(
+            scan.sparse,
+            "of sparse files that are not allocated, the size on disk is smaller",
+        );
+        if self.changed_during_scan() {
+            causes.push(format!(
+                "{}: changed by {} during the scan (is cargo running?), run the selftest again",
+                self.name,
+                signed(i128::from(self.cargo_cache.1) - i128::from(self.cargo_cache.0))
+            ));
+        } else if self.mismatch() {
+            causes.push(format!(
+                "{}: {} of the difference are unexplained",
+                self.name,
+                signed(self.difference() - self.explained())
+            ));
+        }
+        causes
+    }
+}
+
+/// compare the size of a component with the full scan of its directory
+fn check(name: &'static str, component: Option<&Component>, ccp: &CargoCachePaths) -> Check {
+    let (path, layout) = match component {
+        None => (
+            ccp.bin_dir.as_path(),
+            Layout {
+                max_depth: Some(1),
+                ..Layout::default()
+            },
+        ),
+        Some(component) => (
+            component_path(component, ccp),
+            Layout {
+                counts_directories: *component == Component::GitRepos,
+                ..Layout::default()
+            },
+        ),
+    };
+
+    let before = cargo_cache_size(component, ccp);
+    let scan = scan(path, layout);
+    let after = cargo_cache_size(component, ccp);
+    Check {
+        name,
+        cargo_cache: (before, after),
+        scan,
+        layout,
+    }
+}
+
+/// the table of the sizes of all components
+fn checks_table(checks: &[Check]) -> String {
+    let mut table = vec![vec![
+        String::from("Component"),
+        String::from("cargo-cache"),
+        String::from("Full scan"),
+        String::from("On disk"),
+        String::from("Difference"),
+    ]];
+    table.extend(checks.iter().map(|check| {
+        vec![
+            check.name.to_string(),
+            check.cargo_cache.1.format_size(DECIMAL),
+            check.scan.total.format_size(DECIMAL),
+            check.scan.on_disk.format_size(DECIMAL),
+            signed(check.difference()),
+        ]
+    }));
+    format_table(&table, 0)
+}
+
+/// compare the sizes of the selected components with a full scan of their directories
+pub(crate) fn selftest_sizes(
+    ccp: &CargoCachePaths,
+    selection: &ComponentSelection,
+) -> Result<(), Error> {
+    let mut checks = Vec::new();
+    if selection.includes_binaries() {
+        checks.push(check("binaries", None, ccp));
+    }
+    for component in [
+        Component::GitDB,
+        Component::GitRepos,
+        Component::RegistrySources,
+        Component::RegistryCrateCache,
+        Component::RegistryIndex,
+    ] {
+        if selection.contains(&component) {
+            checks.push(check(component.name(), Some(&component), ccp));
+        }
+    }
+
+    print!("{}", checks_table(&checks));
+    let causes = checks
+        .iter()
+        .flat_map(Check::causes)
+        .collect::<Vec<String>>();
+    if !causes.is_empty() {
+        println!("\n{}", causes.join("\n"));
+    }
+
+    let mismatches = checks
+        .iter()
+        .filter(|check| check.mismatch())
+        .map(|check| check.name.to_string())
+        .collect::<Vec<String>>();
+    if mismatches.is_empty() {
+        println!("\nAll sizes match the full scan.");
+        Ok(())
+    } else {
+        Err(Error::SelftestSizesMismatch(mismatches))
+    }
+}
+
+#[cfg(test)]
+mod selftest_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn signed_sizes() {
+        assert_eq!(signed(0), "0 B");
+        assert_eq!(signed(1000), "+1 kB");
+        assert_eq!(signed(-20), "-20 B");
+    }
+
+    #[test]
+    fn nested_binaries() {
+        let tmp = tempfile::tempdir().unwrap();
+        fs::create_dir_all(tmp.path().join("sub")).unwrap();
+        fs::write(tmp.path().join("tool"), vec![1; 100]).unwrap();
+        fs::write(tmp.path().join("sub/helper"), vec![1; 30]).unwrap();
+
+        let binaries = scan(
+            tmp.path(),
+            Layout {
+                max_depth: Some(1),
+                ..Layout::default()
+            },
+        );
+        assert_eq!(binaries.total, 130);
+        assert_eq!(binaries.nested, 30);
+        assert_eq!(binaries.directories, 0);
+    }
+
+    #[test]
+    fn unexplained_difference() {
+        let mut check = Check {
+            name: "registry-sources",
+            cargo_cache: (150, 150),
+            scan: Scan {
+                total: 100,
+                hardlinked: 50,
+                ..Scan::default()
+            },
+            layout: Layout::default(),
+        };
+        assert!(!check.mismatch());
+        assert_eq!(
+            check.causes(),
+            vec!["registry-sources: 50 B of additional hard links to files that are already counted, cargo-cache counts every link"]
+        );
+
+        check.cargo_cache = (170, 170);
+        assert!(check.mismatch());
+        assert_eq!(
+            check.causes()[1],
+            "registry-sources: +20 B of the difference are unexplained"
+        );
+
+        // cargo wrote to the cache in the meantime
+        check.cargo_cache = (150, 170);
+        assert!(!check.mismatch());
+        assert!(check.causes()[1].contains("changed by +20 B during the scan"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn checkouts_with_links() {
+        let tmp = tempfile::tempdir().unwrap();
+        let cargo_home = tmp.path();
+        let checkout = cargo_home.join("git/checkouts/foo-1234/abcdef0");
+        fs::create_dir_all(&checkout).unwrap();
+        fs::create_dir_all(cargo_home.join("git/db")).unwrap();
+        fs::write(checkout.join("lib.rs"), vec![b'a'; 300]).unwrap();
+        fs::hard_link(checkout.join("lib.rs"), checkout.join("main.rs")).unwrap();
+        std::os::unix::fs::symlink(checkout.join("lib.rs"), checkout.join("mod.rs")).unwrap();
+        let ccp = CargoCachePaths::new(cargo_home.to_path_buf()).unwrap();
+
+        let checkouts = check(Component::GitRepos.name(), Some(&Component::GitRepos), &ccp);
+        assert_eq!(checkouts.scan.total, 300);
+        assert_eq!(checkouts.scan.hardlinked, 300);
+        assert_eq!(checkouts.scan.symlinked, 300);
+        assert_eq!(checkouts.scan.checkouts, 1);
+        assert!(!checkouts.mismatch(), "{:?}", checkouts.causes());
+
+        let selection = ComponentSelection::new(Some("git-repos,git-db"), false).unwrap();
+        assert!(selftest_sizes(&ccp, &selection).is_ok());
+    }
+}
@@ -0,0 +1,369 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// "cargo cache stats" command
+// --value estimates what the cache is "worth": how long it would take to download everything that
+// is in the cache again (registry indices, crate archives and git repos) and to extract the crate
+// sources and git checkouts from that.
+// --history shows how the cache grew or shrank, from the snapshots recorded by earlier runs.
+
+use std::fmt::Write as _;
+use std::path::Path;
+
+use chrono::{DateTime, Local};
+use humansize::{FormatSize, DECIMAL};
+
+use crate::args::parse::{self, SizeUnits};
+use crate::dirsizes::DirSizes;
+use crate::history;
+use crate::library::{size_diff_format, Error};
+use crate::snapshots::{self, Snapshot};
+use crate::tables::{format_table, two_row_table, TableLine};
+
+const DAY: u64 = 24 * 60 * 60;
+
+/// the blocks of a sparkline, lowest first
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// bandwidth that is assumed if none is passed via --bandwidth: 10 MB/s
+const DEFAULT_BANDWIDTH: u64 = 10_000_000;
+
+/// rough throughput of unpacking .crate archives / checking out git repos (disk bound): 100 MB/s
+const EXTRACTION_SPEED: u64 = 100_000_000;
+
+/// parse a bandwidth in bytes per second such as "500K", "10M" or "1G"
+/// units are decimal (1K = 1000 bytes) to match how sizes are displayed
+fn parse_bandwidth(bandwidth: &str) -> Result<u64, Error> {
+    match parse::size(bandwidth, SizeUnits::Decimal) {
+        Ok(bytes_per_second) if bytes_per_second > 0 => Ok(bytes_per_second),
+        _ => Err(Error::InvalidBandwidth(bandwidth.to_string())),
+    }
+}
+
+/// seconds needed to process `size` bytes at `bytes_per_second`, rounded up
+fn seconds_needed(size: u64, bytes_per_second: u64) -> u64 {
+    (size + bytes_per_second - 1) / bytes_per_second
+}
+
+/// format a number of seconds as "1h 2m 3s"
+fn format_duration(seconds: u64) -> String {
+    let (hours, minutes, seconds) = (seconds / 3600, (seconds % 3600) / 60, seconds % 60);
+    if hours > 0 {
+        format!("{hours}h {minutes}m {seconds}s")
+    } else if minutes > 0 {
+        format!("{minutes}m {seconds}s")
+    } else {
+        format!("{seconds}s")
+    }
+}
+
+/// generate the "cargo cache stats --value" table
+fn value_table(dir_sizes: &DirSizes<'_>, bandwidth: u64) -> String {
+    let downloads = [
+        ("Registry index: ", dir_sizes.total_reg_index_size()),
+        ("Crate archives: ", dir_sizes.total_reg_cache_size()),
+        ("Bare git repos: ", dir_sizes.total_git_repos_bare_size()),
+    ];
+    let extractions = [
+        ("Crate source checkouts: ", dir_sizes.total_reg_src_size()),
+        ("Git repo checkouts: ", dir_sizes.total_git_chk_size()),
+    ];
+
+    let size_and_time = |size: u64, seconds: u64| {
+        format!(
+            "{}, {}",
+            size.format_size(DECIMAL),
+            format_duration(seconds)
+        )
+    };
+
+    let mut download_lines = Vec::new();
+    let (mut download_size, mut download_seconds) = (0, 0);
+    for (name, size) in downloads {
+        let seconds = seconds_needed(size, bandwidth);
+        download_size += size;
+        download_seconds += seconds;
+        download_lines.push(TableLine::new(
+            2,
+            &name.to_string(),
+            &size_and_time(size, seconds),
+        ));
+    }
+
+    let mut extraction_lines = Vec::new();
+    let (mut extraction_size, mut extraction_seconds) = (0, 0);
+    for (name, size) in extractions {
+        let seconds = seconds_needed(size, EXTRACTION_SPEED);
+        extraction_size += size;
+        extraction_seconds += seconds;
+        extraction_lines.push(TableLine::new(
+            2,
+            &name.to_string(),
+            &size_and_time(size, seconds),
+        ));
+    }
+
+    let mut table = vec![
+        TableLine::new(
+            0,
+            &format!(
+                "Estimated value of cargo cache '{}':\n\n",
+                dir_sizes.root_path().display()
+            ),
+            &String::new(),
+        ),
+        TableLine::new(
+            0,
+            &"Time saved: ".to_string(),
+            &size_and_time(
+                download_size + extraction_size,
+                download_seconds + extraction_seconds,
+            ),
+        ),
+        TableLine::new(
+            1,
+            &format!("Re-downloading at {}/s: ", bandwidth.format_size(DECIMAL)),
+            &size_and_time(download_size, download_seconds),
+        ),
+    ];
+    table.extend(download_lines);
+    table.push(TableLine::new(
+        1,
+        &format!(
+            "Re-extracting at {}/s: ",
+            EXTRACTION_SPEED.format_size(DECIMAL)
+        ),
+        &size_and_time(extraction_size, extraction_seconds),
+    ));
+    table.extend(extraction_lines);
+
+    two_row_table(2, table, false)
+}
+
+/// print an estimate of how much time the cache saves compared to downloading and extracting everything again
+pub(crate) fn value_stats(dir_sizes: &DirSizes<'_>, bandwidth: Option<&str>) -> Result<(), Error> {
+    let bandwidth = match bandwidth {
+        Some(bandwidth) => parse_bandwidth(bandwidth)?,
+        None => DEFAULT_BANDWIDTH,
+    };
+
+    print!("{}", value_table(dir_sizes, bandwidth));
+    Ok(())
+}
+
+/// the snapshots younger than `days` (if given), at most the `last` newest ones
+fn select_snapshots(
+    snapshots: &[Snapshot],
+    now: u64,
+    last: usize,
+    days: Option<u64>,
+) -> &[Snapshot] {
+    let first_young = days.map_or(0, |days| {
+        snapshots
+            .iter()
+            .position(|snapshot| now.saturating_sub(snapshot.time) < days * DAY)
+            .unwrap_or(snapshots.len())
+    });
+    let snapshots = &snapshots[first_young..];
+    &snapshots[snapshots.len().saturating_sub(last)..]
+}
+
+/// "▁▃▅█", one block per size, scaled between the smallest and the largest size
+fn sparkline(sizes: &[u64]) -> String {
+    let min = sizes.iter().copied().min().unwrap_or(0);
+    let max = sizes.iter().copied().max().unwrap_or(0);
+    sizes
+        .iter()
+        .map(|&size| {
+            let level = if max == min {
+                0
+            } else {
+                // the sizes are far below 2^53, no precision is lost
+                #[allow(
+                    clippy::cast_precision_loss,
+                    clippy::cast_possible_truncation,
+                    clippy::cast_sign_loss
+                )]
+                let level = ((size - min) as f64 / (max - min) as f64 * 7.0).round() as usize;
+                level
+            };
+            SPARKS[level]
+        })
+        .collect()
+}
+
+/// the time of a snapshot in the local time zone
+pub(crate) fn format_time(time: u64) -> String {
+    DateTime::<Local>::from(std::time::UNIX_EPOCH + std::time::Duration::from_secs(time))
+        .format("%Y-%m-%d %H:%M")
+        .to_string()
+}
+
+/// generate the "cargo cache stats --history" table
+fn history_table(cargo_home: &Path, snapshots: &[Snapshot], sparkline_too: bool) -> String {
+    let (first, last) = match (snapshots.first(), snapshots.last()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => {
+            return format!(
+                "No sizes of '{}' were recorded yet, set \"snapshots = <N>\" in the [stats] table of the config file to record them.\n",
+                cargo_home.display()
+            )
+        }
+    };
+
+    let mut rows = vec![vec![
+        String::from("Time"),
+        String::from("Total"),
+        String::from("Registry"),
+        String::from("Git"),
+        String::from("Binaries"),
+    ]];
+    let mut previous_total = None;
+    for snapshot in snapshots {
+        rows.push(vec![
+            format_time(snapshot.time),
+            previous_total.map_or_else(
+                || snapshot.total.format_size(DECIMAL),
+                |previous| size_diff_format(previous, snapshot.total, false),
+            ),
+            snapshot.registry().format_size(DECIMAL),
+            snapshot.git().format_size(DECIMAL),
+            snapshot.binaries.format_size(DECIMAL),
+        ]);
+        previous_total = Some(snapshot.total);
+    }
+
+    let mut table = format!(
+        "Size of cargo cache '{}' over the last {} runs:\n\n{}",
+        cargo_home.display(),
+        snapshots.len(),
+        format_table(&rows, 1)
+    );
+    if sparkline_too {
+        let totals = snapshots
+            .iter()
+            .map(|snapshot| snapshot.total)
+            .collect::<Vec<_>>();
+        let _ = write!(table, "\nTotal: {}\n", sparkline(&totals));
+    }
+    if snapshots.len() > 1 {
+        let _ = write!(
+            table,
+            "\nSize changed {}\n",
+            size_diff_format(first.total, last.total, true)
+        );
+    }
+    table
+}
+
+/// print how the size of the cache changed over the last runs that recorded a snapshot
+pub(crate) fn history_stats(cargo_home: &Path, last: usize, days: Option<u64>, sparkline: bool) {
+    let snapshots = snapshots::read(cargo_home);
+    let selected = select_snapshots(&snapshots, history::now(), last, days);
+    print!("{}", history_table(cargo_home, selected, sparkline));
+}
+
+#[cfg(test)]
+mod stats_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn bandwidth() {
+        assert_eq!(parse_bandwidth("100").unwrap(), 100);
+        assert_eq!(parse_bandwidth("2K").unwrap(), 2000);
+        assert_eq!(parse_bandwidth("1.5m").unwrap(), 1_500_000);
+        assert_eq!(parse_bandwidth("1G").unwrap(), 1_000_000_000);
+        assert!(parse_bandwidth("0M").is_err());
+        assert!(parse_bandwidth("fast").is_err());
+        assert!(parse_bandwidth("").is_err());
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(seconds_needed(0, 10), 0);
+        assert_eq!(seconds_needed(11, 10), 2);
+        assert_eq!(format_duration(59), "59s");
+        assert_eq!(format_duration(61), "1m 1s");
+        assert_eq!(format_duration(3600 + 120 + 3), "1h 2m 3s");
+    }
+
+    fn snapshot(time: u64, total: u64) -> Snapshot {
+        Snapshot {
+            time,
+            total,
+            binaries: 1_000_000,
+            registry_index: 0,
+            crate_archives: total / 2,
+            crate_sources: 0,
+            git_db: total - total / 2 - 1_000_000,
+            git_checkouts: 0,
+        }
+    }
+
+    #[test]
+    fn selection() {
+        let now = 100 * DAY;
+        let snapshots = (0..10)
+            .map(|days_ago| snapshot(now - (9 - days_ago) * DAY, 10_000_000))
+            .collect::<Vec<_>>();
+        assert_eq!(select_snapshots(&snapshots, now, 20, None).len(), 10);
+        assert_eq!(select_snapshots(&snapshots, now, 3, None), &snapshots[7..]);
+        assert_eq!(
+            select_snapshots(&snapshots, now, 20, Some(2)),
+            &snapshots[8..]
+        );
+        assert_eq!(
+            select_snapshots(&snapshots, now, 1, Some(2)),
+            &snapshots[9..]
+        );
+        assert!(select_snapshots(&snapshots, now + 30 * DAY, 20, Some(7)).is_empty());
+    }
+
+    #[test]
+    fn sparklines() {
+        assert_eq!(sparkline(&[1, 5, 8, 3]), "▁▅█▃");
+        assert_eq!(sparkline(&[7, 7]), "▁▁");
+        assert_eq!(sparkline(&[]), "");
+    }
+
+    #[test]
+    fn history() {
+        let snapshots = [
+            snapshot(1_000_000_000, 10_000_000),
+            snapshot(1_000_086_400, 30_000_000),
+            snapshot(1_000_172_800, 20_000_000),
+        ];
+        let table = history_table(Path::new("/home/user/.cargo"), &snapshots, true);
+        assert_eq!(
+            table,
+            format!(
+                "Size of cargo cache '/home/user/.cargo' over the last 3 runs:
+
+Time              Total                    Registry  Git    Binaries
+{}  10 MB                    5 MB      4 MB   1 MB
+{}  30 MB (+20 MB, 200%)     15 MB     14 MB  1 MB
+{}  20 MB (-10 MB, -33.33%)  10 MB     9 MB   1 MB
+
+Total: ▁█▅
+
+Size changed 10 MB => 20 MB (+10 MB, 100%)
+",
+                format_time(1_000_000_000),
+                format_time(1_000_086_400),
+                format_time(1_000_172_800)
+            )
+        );
+
+        assert_eq!(
+            history_table(Path::new("/home/user/.cargo"), &[], false),
+            "No sizes of '/home/user/.cargo' were recorded yet, set \"snapshots = <N>\" in the [stats] table of the config file to record them.\n"
+        );
+    }
+}
@@ -7,35 +7,37 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use std::path::PathBuf;
+// "cargo cache toolchain": the toolchains installed via rustup and how big they are
+// --breakdown splits each toolchain up into the components rustup installed (rustc, cargo, rust-docs,
+// the rust-std of each target...), using the lists of files rustup keeps in lib/rustlib/manifest-<component>.
+// --remove-toolchain and --remove-docs leave the work to rustup if it is installed, without rustup we
+// remove the same files rustup would remove.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
 
-use chrono::prelude::*;
 use humansize::{FormatSize, DECIMAL};
 use walkdir::WalkDir;
 
+use crate::confirm::confirm_removal;
 use crate::library;
 use crate::sccache::percentage_of_as_string;
 use crate::tables::format_table;
 
-#[derive(Debug, Clone)]
-struct File {
-    #[allow(unused)]
-    path: PathBuf,
-    #[allow(unused)]
-    access_date: NaiveDate,
+/// the directory rustup installs the toolchains to
+fn toolchain_root() -> Result<PathBuf, library::Error> {
+    // intentionally map the Err to our own type
+    #[allow(clippy::map_err_ignore)]
+    let mut p = home::rustup_home().map_err(|_| library::Error::NoRustupHome)?;
+    p.push("toolchains");
+    Ok(p)
 }
 
 /// return a list of toolchains (subdirs in the toolchain directory)
-fn toolchains() -> Result<std::fs::ReadDir, library::Error> {
-    let toolchain_root = {
-        // intentionally map the Err to our own type
-        #[allow(clippy::map_err_ignore)]
-        let mut p = home::rustup_home().map_err(|_| library::Error::NoRustupHome)?;
-        p.push("toolchains");
-        p
-    };
-
-    match std::fs::read_dir(toolchain_root) {
+fn toolchains() -> Result<fs::ReadDir, library::Error> {
+    match fs::read_dir(toolchain_root()?) {
         Ok(readdir) => Ok(readdir),
         // we might be on a system that has rust installed purley via package manager and not via rustup! (#121)
         _ => Err(library::Error::NoRustupHome),
@@ -45,7 +47,6 @@ fn toolchains() -> Result<std::fs::ReadDir, library::Error> {
 #[derive(Clone, Debug)]
 struct Toolchain {
     name: String,
-    #[allow(unused)]
     path: PathBuf,
     number_files: usize,
     size: u64,
@@ -55,17 +56,7 @@ impl Toolchain {
     fn new(path: PathBuf) -> Self {
         let name = path.file_name().unwrap().to_owned().into_string().unwrap();
         let number_files = WalkDir::new(&path).into_iter().count();
-        #[allow(clippy::manual_filter_map)]
-        let size: u64 = WalkDir::new(&path)
-            .into_iter()
-            .map(|f| {
-                let x = f.unwrap();
-                let z = x.path().to_owned();
-                z
-            })
-            .filter(|f| f.is_file())
-            .map(|f| std::fs::metadata(f).unwrap().len())
-            .sum();
+        let size = files_size(&path);
 
         Toolchain {
             name,
@@ -76,25 +67,104 @@ impl Toolchain {
     }
 }
 
-pub(crate) fn toolchain_stats() {
+/// the summed up size of all files at or below `path`, directories themselves are not counted
+/// so that the sizes of components add up to the size of the toolchain
+fn files_size(path: &Path) -> u64 {
+    WalkDir::new(path)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| entry.metadata().ok())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+/// the installed toolchains, biggest first, toolchains of the same size by name
+fn installed_toolchains() -> Result<Vec<Toolchain>, library::Error> {
+    let mut tcs = toolchains()?
+        .map(|dir| dir.unwrap().path())
+        .map(Toolchain::new)
+        .collect::<Vec<_>>();
+    tcs.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.name.cmp(&b.name)));
+    Ok(tcs)
+}
+
+/// the components rustup installed into a toolchain and their files, from the
+/// `lib/rustlib/manifest-<component>` files which list one "file:<path>" or "dir:<path>" per line
+fn component_paths(toolchain: &Path) -> Vec<(String, Vec<PathBuf>)> {
+    let mut components = fs::read_dir(toolchain.join("lib").join("rustlib"))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry
+                .file_name()
+                .to_str()?
+                .strip_prefix("manifest-")?
+                .to_string();
+            let manifest = fs::read_to_string(entry.path()).ok()?;
+            let mut paths = manifest
+                .lines()
+                .filter_map(|line| {
+                    line.strip_prefix("file:")
+                        .or_else(|| line.strip_prefix("dir:"))
+                })
+                .map(|path| toolchain.join(path))
+                .collect::<Vec<_>>();
+            // the list itself is part of the component
+            paths.push(entry.path());
+            Some((name, paths))
+        })
+        .collect::<Vec<_>>();
+    components.sort();
+    components
+}
+
+/// the size of the files of a component that are still there, missing files count as empty
+fn component_size(paths: &[PathBuf]) -> u64 {
+    paths.iter().map(|path| files_size(path)).sum()
+}
+
+/// the components of a toolchain with their sizes, biggest first, and what belongs to none of them
+fn breakdown_table(toolchain: &Toolchain) -> String {
+    let mut components = component_paths(&toolchain.path)
+        .into_iter()
+        .map(|(name, paths)| (name, component_size(&paths)))
+        .collect::<Vec<_>>();
+    components.sort_by(|(name_a, size_a), (name_b, size_b)| {
+        size_b.cmp(size_a).then_with(|| name_a.cmp(name_b))
+    });
+    let other = toolchain
+        .size
+        .saturating_sub(components.iter().map(|(_, size)| size).sum());
+    if other > 0 {
+        components.push((String::from("other"), other));
+    }
+
+    let mut table_vec = vec![vec![
+        toolchain.name.clone(),
+        "Size".to_string(),
+        "Percentage".to_string(),
+    ]];
+    table_vec.extend(components.into_iter().map(|(name, size)| {
+        vec![
+            name,
+            size.format_size(DECIMAL),
+            percentage_of_as_string(size, toolchain.size),
+        ]
+    }));
+    format_table(&table_vec, 1)
+}
+
+pub(crate) fn toolchain_stats(breakdown: bool) {
     // get a list of toolchains, sorted by size
-    let toolchains = {
-        let toolchain_readdir = match toolchains() {
-            Ok(readdir) => readdir,
-            Err(library::Error::NoRustupHome) => {
-                eprintln!("Could not find any toolchains installed via rustup!");
-                std::process::exit(0);
-            }
-            Err(e) => unreachable!("encountered unexpected error: '{:?}'", e),
-        };
-
-        let mut tcs = toolchain_readdir
-            .map(|dir| dir.unwrap().path())
-            .map(Toolchain::new)
-            .collect::<Vec<_>>();
-        tcs.sort_by_key(|tc| tc.size);
-        tcs.reverse();
-        tcs
+    let toolchains = match installed_toolchains() {
+        Ok(toolchains) => toolchains,
+        Err(library::Error::NoRustupHome) => {
+            eprintln!("Could not find any toolchains installed via rustup!");
+            std::process::exit(0);
+        }
+        Err(e) => unreachable!("encountered unexpected error: '{:?}'", e),
     };
 
     // get the size
@@ -151,4 +221,202 @@ pub(crate) fn toolchain_stats() {
     let table = format_table(&table_vec, 1); // need so strip whitespaces added by the padding
     let table_trimmed = table.trim();
     println!("{table_trimmed}");
+
+    if breakdown {
+        for toolchain in &toolchains {
+            println!("\n{}", breakdown_table(toolchain).trim_end());
+        }
+    }
+}
+
+/// whether rustup is installed, then we let it remove things the way it would
+fn rustup_installed() -> bool {
+    Command::new("rustup")
+        .arg("--version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map_or(false, |status| status.success())
+}
+
+/// run rustup, `what` is what it removes
+fn run_rustup(mut rustup: Command, what: &str) -> Result<(), library::Error> {
+    let output = rustup
+        .output()
+        .map_err(|error| library::Error::ToolchainRemovalFailed(what.into(), error.to_string()))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(library::Error::ToolchainRemovalFailed(
+            what.into(),
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ))
+    }
+}
+
+/// remove a file or directory of a toolchain ourselves
+fn remove_path(path: &Path, what: &str) -> Result<(), library::Error> {
+    let removed = if path.is_dir() {
+        fs::remove_dir_all(path)
+    } else if path.exists() {
+        fs::remove_file(path)
+    } else {
+        Ok(())
+    };
+    removed.map_err(|error: io::Error| {
+        library::Error::ToolchainRemovalFailed(what.into(), error.to_string())
+    })
+}
+
+/// the toolchain `name` refers to: its full name ("nightly-2023-01-01-x86_64-unknown-linux-gnu")
+/// or the name without the host ("nightly-2023-01-01") if that is unambiguous
+fn find_toolchain(toolchains: Vec<Toolchain>, name: &str) -> Result<Toolchain, library::Error> {
+    let names = toolchains.iter().map(|tc| tc.name.clone()).collect();
+    let mut matching = toolchains
+        .into_iter()
+        .filter(|tc| {
+            tc.name == name
+                || tc
+                    .name
+                    .strip_prefix(name)
+                    .map_or(false, |host| host.starts_with('-'))
+        })
+        .collect::<Vec<_>>();
+    if let Some(exact) = matching.iter().position(|tc| tc.name == name) {
+        return Ok(matching.swap_remove(exact));
+    }
+    match matching.len() {
+        1 => Ok(matching.remove(0)),
+        _ => Err(library::Error::ToolchainNotInstalled(name.into(), names)),
+    }
+}
+
+/// `toolchain --remove-toolchain <name>`: uninstall a toolchain
+pub(crate) fn remove_toolchain(
+    name: &str,
+    assume_yes: bool,
+    dry_run: bool,
+) -> Result<(), library::Error> {
+    let toolchain = find_toolchain(installed_toolchains()?, name)?;
+    let what = format!("toolchain \"{}\"", toolchain.name);
+    let size = toolchain.size.format_size(DECIMAL);
+    if dry_run {
+        println!("dry-run: would remove {what} ({size})");
+        return Ok(());
+    }
+    confirm_removal(&format!("{what} ({size})"), assume_yes)?;
+
+    if rustup_installed() {
+        let mut rustup = Command::new("rustup");
+        let _ = rustup.args(["toolchain", "uninstall", &toolchain.name]);
+        run_rustup(rustup, &what)?;
+    } else {
+        remove_path(&toolchain.path, &what)?;
+        // rustup remembers the hash of the manifest the toolchain was installed from
+        if let Some(rustup_home) = toolchain.path.parent().and_then(Path::parent) {
+            remove_path(
+                &rustup_home.join("update-hashes").join(&toolchain.name),
+                &what,
+            )?;
+        }
+    }
+    println!("Removed {what}, freed {size}");
+    Ok(())
+}
+
+/// remove the files of a component from a toolchain and the component from its list of components
+fn remove_component_files(
+    toolchain: &Path,
+    component: &str,
+    paths: &[PathBuf],
+    what: &str,
+) -> Result<(), library::Error> {
+    for path in paths {
+        remove_path(path, what)?;
+    }
+    let components_file = toolchain.join("lib").join("rustlib").join("components");
+    if let Ok(components) = fs::read_to_string(&components_file) {
+        let kept = components
+            .lines()
+            .filter(|line| *line != component)
+            .flat_map(|line| [line, "\n"])
+            .collect::<String>();
+        fs::write(&components_file, kept).map_err(|error| {
+            library::Error::ToolchainRemovalFailed(what.into(), error.to_string())
+        })?;
+    }
+    Ok(())
+}
+
+/// `toolchain --remove-docs`: remove the offline documentation (rust-docs) of all toolchains
+pub(crate) fn remove_docs(assume_yes: bool, dry_run: bool) -> Result<(), library::Error> {
+    // the rust-docs component of each target the docs were installed for
+    let docs = installed_toolchains()?
+        .into_iter()
+        .flat_map(|toolchain| {
+            component_paths(&toolchain.path)
+                .into_iter()
+                .filter(|(component, _)| component.starts_with("rust-docs-"))
+                .map(move |(component, paths)| {
+                    let size = component_size(&paths);
+                    (toolchain.clone(), component, paths, size)
+                })
+        })
+        .collect::<Vec<_>>();
+    if docs.is_empty() {
+        println!("None of the toolchains has docs installed.");
+        return Ok(());
+    }
+    let total_size: u64 = docs.iter().map(|(.., size)| size).sum();
+    if dry_run {
+        for (toolchain, component, _, size) in &docs {
+            println!(
+                "dry-run: would remove {component} from \"{}\" ({})",
+                toolchain.name,
+                size.format_size(DECIMAL)
+            );
+        }
+        println!(
+            "dry-run: would remove {} of docs",
+            total_size.format_size(DECIMAL)
+        );
+        return Ok(());
+    }
+    let mut toolchain_names = docs
+        .iter()
+        .map(|(toolchain, ..)| toolchain.name.as_str())
+        .collect::<Vec<_>>();
+    toolchain_names.dedup();
+    confirm_removal(
+        &format!(
+            "the docs of {} toolchains ({})",
+            toolchain_names.len(),
+            total_size.format_size(DECIMAL)
+        ),
+        assume_yes,
+    )?;
+
+    let use_rustup = rustup_installed();
+    for (toolchain, component, paths, _) in &docs {
+        let what = format!("{component} from \"{}\"", toolchain.name);
+        println!("removing: {what}");
+        if use_rustup {
+            let target = component.trim_start_matches("rust-docs-");
+            let mut rustup = Command::new("rustup");
+            let _ = rustup.args([
+                "component",
+                "remove",
+                "rust-docs",
+                "--target",
+                target,
+                "--toolchain",
+                &toolchain.name,
+            ]);
+            run_rustup(rustup, &what)?;
+        } else {
+            remove_component_files(&toolchain.path, component, paths, &what)?;
+        }
+    }
+    println!("Removed {} of docs", total_size.format_size(DECIMAL));
+    Ok(())
 }
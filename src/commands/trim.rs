@@ -8,171 +8,401 @@
 // except according to those terms.
 
 // "cargo cache trim" command
-// trim the size of the cargo cache down to a certain limit.
+// trim the size of the cargo cache down to a certain limit and/or remove what was not used for some time.
 // note that this does not take account the registry indices and the installed binaries in calculations
+// "trim --plan" only prints the items that would be removed, in the order they would be removed
 
+use std::fs;
 use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
+use crate::approve;
+use crate::args::parse::{self, SizeUnits};
 use crate::cache::caches::*;
 use crate::cache::*;
+use crate::cancel::is_cancelled;
+use crate::keep::KeepList;
 use crate::library::*;
+use crate::progress::Progress;
 use crate::remove::*;
+use crate::retry;
+use crate::tables::format_table;
+use crate::walk::walk;
 
+use chrono::{DateTime, Local};
 use humansize::{FormatSize, DECIMAL};
-use walkdir::WalkDir;
 
-fn get_last_access_of_item(path: &Path) -> std::time::SystemTime {
+/// the last access of a file or, for a directory, the most recent access of any of its files
+/// None if the item vanished in the meantime or the scan was cancelled
+pub(crate) fn get_last_access_of_item(path: &Path) -> Option<SystemTime> {
+    let accessed = |file: &Path| fs::metadata(file).and_then(|m| m.accessed()).ok();
     if path.is_file() {
         // if we have a file, simply get the access time
-        std::fs::metadata(path).unwrap().accessed().unwrap()
+        accessed(path)
     } else {
         // if we have a directory, get the latest access of all files of that directory
         // get the max time / the file with the youngest access date / most recently accessed
-        WalkDir::new(path)
-            .into_iter()
-            .map(|e| e.unwrap().path().to_owned())
-            .map(|filepath| std::fs::metadata(filepath).unwrap().accessed().unwrap()) //@TODO make this an reusable function/method to simplify code
-            .max()
-            .unwrap()
+        let last_access = walk(path)
+            .take_while(|_| !is_cancelled())
+            .filter_map(|entry| accessed(entry.path()))
+            .max();
+        if is_cancelled() {
+            None
+        } else {
+            last_access
+        }
+    }
+}
+
+/// the kinds of cache entries trim removes, each of them is only ever removed as a whole
+/// so that no half-removed crate or checkout is left behind
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum EvictionKind {
+    /// registry/cache/<registry>/<crate>-<version>.crate
+    CrateArchive,
+    /// registry/src/<registry>/<crate>-<version>/
+    CrateSource,
+    /// git/db/<repo>/
+    GitRepo,
+    /// git/checkouts/<repo>/<revision>/
+    GitCheckout,
+    /// a file of the sccache cache, only trimmed by `sccache --clean`
+    SccacheEntry,
+}
+
+/// an entry of the cache that trim may remove
+#[derive(Debug, Clone)]
+pub(crate) struct EvictionItem {
+    pub(crate) path: PathBuf,
+    pub(crate) kind: EvictionKind,
+    /// size of the file or all files of the directory
+    pub(crate) size: u64,
+    /// the most recent access of the file or any file of the directory
+    pub(crate) last_access: SystemTime,
+}
+
+impl EvictionKind {
+    /// all kinds, in the order of the cache summary
+    const ALL: [Self; 5] = [
+        Self::CrateArchive,
+        Self::CrateSource,
+        Self::GitRepo,
+        Self::GitCheckout,
+        Self::SccacheEntry,
+    ];
+
+    /// how the items are called in the cache summary
+    fn name(self) -> &'static str {
+        match self {
+            Self::CrateArchive => "crate archives",
+            Self::CrateSource => "crate source checkouts",
+            Self::GitRepo => "bare git repos",
+            Self::GitCheckout => "git repo checkouts",
+            Self::SccacheEntry => "sccache entries",
+        }
+    }
+
+    /// the kind of a single item in the --plan
+    fn key(self) -> &'static str {
+        match self {
+            Self::CrateArchive => "crate-archive",
+            Self::CrateSource => "crate-source",
+            Self::GitRepo => "git-repo",
+            Self::GitCheckout => "git-checkout",
+            Self::SccacheEntry => "sccache-entry",
+        }
+    }
+}
+
+impl EvictionItem {
+    /// None if the item vanished in the meantime or its last access can not be determined,
+    /// we leave such items alone
+    pub(crate) fn new(path: &Path, kind: EvictionKind) -> Option<Self> {
+        let last_access = get_last_access_of_item(path)?;
+        let size = if path.is_dir() {
+            cumulative_dir_size(path).dir_size
+        } else {
+            retry::metadata(path).ok()?.len()
+        };
+        Some(Self {
+            path: path.to_path_buf(),
+            kind,
+            size,
+            last_access,
+        })
     }
 }
 
 // get a list of all cache items, sorted by file access time (young to old)
-pub(crate) fn gather_all_cache_items<'a>(
-    git_checkouts_cache: &'a mut git_checkouts::GitCheckoutCache,
-    bare_repos_cache: &'a mut git_bare_repos::GitRepoCache,
-    registry_pkg_cache: &'a mut registry_pkg_cache::RegistryPkgCaches,
-    registry_sources_cache: &'a mut registry_sources::RegistrySourceCaches,
-) -> Vec<&'a PathBuf> {
-    let mut all_items: Vec<&PathBuf> = Vec::new();
-    all_items.extend(git_checkouts_cache.items());
-    all_items.extend(bare_repos_cache.items());
-    all_items.extend(registry_pkg_cache.items());
-    all_items.extend(registry_sources_cache.items());
-
-    // calculating the last access for each path ever time is not cheap, so use caching
+// items of registries that are not included are left out, git repos and checkouts belong to no registry
+pub(crate) fn gather_all_cache_items(
+    git_checkouts_cache: &mut git_checkouts::GitCheckoutCache,
+    bare_repos_cache: &mut git_bare_repos::GitRepoCache,
+    registry_pkg_cache: &mut registry_pkg_cache::RegistryPkgCaches,
+    registry_sources_cache: &mut registry_sources::RegistrySourceCaches,
+    registries: &RegistryFilter,
+) -> Vec<EvictionItem> {
+    let mut all_items: Vec<EvictionItem> = Vec::new();
+    if registries.includes_git() {
+        all_items.extend(
+            git_checkouts_cache
+                .items()
+                .iter()
+                .filter_map(|path| EvictionItem::new(path, EvictionKind::GitCheckout)),
+        );
+        all_items.extend(
+            bare_repos_cache
+                .items()
+                .iter()
+                .filter_map(|path| EvictionItem::new(path, EvictionKind::GitRepo)),
+        );
+    }
+    all_items.extend(
+        registry_pkg_cache
+            .caches()
+            .iter_mut()
+            .filter(|cache| registries.includes_registry_path(cache.path()))
+            .flat_map(|cache| cache.items().iter())
+            // leftovers of interrupted downloads and other stray files are no crates
+            .filter(|path| path.extension().map_or(false, |ext| ext == "crate"))
+            .filter_map(|path| EvictionItem::new(path, EvictionKind::CrateArchive)),
+    );
+    all_items.extend(
+        registry_sources_cache
+            .caches()
+            .iter_mut()
+            .filter(|cache| registries.includes_registry_path(cache.path()))
+            .flat_map(|cache| cache.items().iter())
+            .filter_map(|path| EvictionItem::new(path, EvictionKind::CrateSource)),
+    );
+
     // sort from youngest to oldest
-    all_items.sort_by_cached_key(|path| get_last_access_of_item(path));
-    // reverse the vec so that youngest access dates come first
     // [2020, 2019, 2018, ....]
-    all_items.reverse();
+    // items used at the same time (file systems mounted with noatime) are ordered by path, so
+    // that the same cache is always trimmed the same way
+    all_items.sort_by(|a, b| {
+        b.last_access
+            .cmp(&a.last_access)
+            .then_with(|| a.path.cmp(&b.path))
+    });
 
     all_items
 }
 
 /// figure out how big the cache should remain after trimming
-fn parse_size_limit_to_bytes(limit: Option<&str>) -> Result<u64, Error> {
+/// units are binary (1K = 1024 bytes), trim --limit has always worked like that
+pub(crate) fn parse_size_limit_to_bytes(limit: Option<&str>) -> Result<u64, Error> {
     match limit {
         None => unreachable!("No trim --limit was supplied although clap should enforce that!"),
-        Some(limit) => {
-            // figure out the unit
-            let unit_multiplicator: Result<u64, Error> = match limit.chars().last() {
-                // we have no limit
-                None => Ok(0),
-                // we expect a unit such as B, K, M, G, T...
-                Some(c) => {
-                    if c.is_alphabetic() {
-                        match c {
-                            'b' | 'B' => Ok(1),
-                            'k' | 'K' => Ok(1024),
-                            'm' | 'M' => Ok(1024 * 1024),
-                            'g' | 'G' => Ok(1024 * 1024 * 1024),
-                            't' | 'T' => Ok(1024 * 1024 * 1024 * 1024),
-                            _ => Err(Error::TrimLimitUnitParseFailure(limit.to_string())),
-                        }
-                    } else {
-                        Err(Error::TrimLimitUnitParseFailure(limit.to_string()))
-                    }
-                }
-            };
-
-            let value: f64 = match limit[0..(limit.len() - 1)].parse() {
-                Ok(val) => val,
-                Err(_) => {
-                    return Err(Error::TrimLimitUnitParseFailure(limit.to_string()));
-                }
-            };
-            if value == 0.0 {
-                return Ok(0);
-            }
-            // we may truncate the value here but that's ok
-            #[allow(clippy::cast_lossless)]
-            #[allow(clippy::cast_sign_loss)]
-            #[allow(clippy::cast_possible_truncation)]
-            #[allow(clippy::cast_precision_loss)]
-            Ok((value * unit_multiplicator? as f64) as u64)
-        }
+        Some(limit) => parse::size(limit, SizeUnits::Binary)
+            .map_err(|_| Error::TrimLimitUnitParseFailure(limit.to_string())),
     }
 }
 
-/// trim the cache to a certain limit and invalidate caches
-pub(crate) fn trim_cache(
-    unparsed_size_limit: Option<&str>,
+/// "Removed 3 items totalling 2 MB: 2 crate archives, 1 crate source checkouts"
+pub(crate) fn removal_summary(removed_kinds: &[EvictionKind], removed_size: u64) -> String {
+    let mut summary = format!(
+        "Removed {} items totalling {}",
+        removed_kinds.len(),
+        removed_size.format_size(DECIMAL)
+    );
+    let per_kind = EvictionKind::ALL
+        .iter()
+        .map(|kind| (kind, removed_kinds.iter().filter(|k| k == &kind).count()))
+        .filter(|(_, count)| *count > 0)
+        .map(|(kind, count)| format!("{count} {}", kind.name()))
+        .collect::<Vec<String>>();
+    if !per_kind.is_empty() {
+        summary.push_str(": ");
+        summary.push_str(&per_kind.join(", "));
+    }
+    summary
+}
+
+/// whether the cache is already smaller than `size_limit` bytes, then we do not have to delete anything
+/// (only if all registries are trimmed, the size of the others does not count towards the limit)
+fn fits_limit(
+    size_limit: u64,
     git_checkouts_cache: &mut git_checkouts::GitCheckoutCache,
     bare_repos_cache: &mut git_bare_repos::GitRepoCache,
     registry_pkg_cache: &mut registry_pkg_cache::RegistryPkgCaches,
     registry_sources_cache: &mut registry_sources::RegistrySourceCaches,
-    dry_run: bool,
-    size_changed: &mut bool,
-) -> Result<(), Error> {
-    // the cache should not exceed this limit
-    let size_limit = parse_size_limit_to_bytes(unparsed_size_limit)?;
-
-    // fast path:
-    // if the  limit is bigger than the cache size, we can return
-    // because we know we won't have to delete anything
+    registries: &RegistryFilter,
+) -> bool {
+    if registries.is_restricted() {
+        return false;
+    }
     let total_cache_size: u64 = git_checkouts_cache.total_size()
         + bare_repos_cache.total_size()
         + registry_pkg_cache.total_size()
         + registry_sources_cache.total_size();
 
-    if size_limit > total_cache_size {
-        //println!("trim: limit exceeds cache-limit, doing nothing");
-        return Ok(());
+    size_limit > total_cache_size
+}
+
+/// the items that have to be removed so that the cache does not exceed `size_limit` bytes,
+/// the least recently used ones, and all items that were last used before `cutoff`
+/// kept crates are never removed, but their size still counts towards the limit
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn items_to_trim(
+    size_limit: Option<u64>,
+    cutoff: Option<SystemTime>,
+    git_checkouts_cache: &mut git_checkouts::GitCheckoutCache,
+    bare_repos_cache: &mut git_bare_repos::GitRepoCache,
+    registry_pkg_cache: &mut registry_pkg_cache::RegistryPkgCaches,
+    registry_sources_cache: &mut registry_sources::RegistrySourceCaches,
+    registries: &RegistryFilter,
+    keep: &KeepList,
+) -> Vec<EvictionItem> {
+    if let (Some(size_limit), None) = (size_limit, cutoff) {
+        if fits_limit(
+            size_limit,
+            git_checkouts_cache,
+            bare_repos_cache,
+            registry_pkg_cache,
+            registry_sources_cache,
+            registries,
+        ) {
+            return Vec::new();
+        }
     }
 
     // get all the items of the cache
-    let all_cache_items: Vec<&PathBuf> = gather_all_cache_items(
+    let all_cache_items: Vec<EvictionItem> = gather_all_cache_items(
         git_checkouts_cache,
         bare_repos_cache,
         registry_pkg_cache,
         registry_sources_cache,
+        registries,
     );
+    let (kept, removable): (Vec<EvictionItem>, Vec<EvictionItem>) = all_cache_items
+        .into_iter()
+        .partition(|item| keep.keeps(&item.path));
+    let kept_size: u64 = kept.iter().map(|item| item.size).sum();
 
-    // delete everything that is unneeded
-    let mut cache_size = 0;
-    let mut removed_size: u64 = 0;
-    let mut removed_item_count = 0;
+    select_for_removal(
+        removable,
+        size_limit.map(|size_limit| size_limit.saturating_sub(kept_size)),
+        cutoff,
+    )
+}
 
+/// the items older than the cutoff and the items that do not fit into the size limit anymore,
+/// `all_cache_items` are sorted youngest first
+pub(crate) fn select_for_removal(
+    all_cache_items: Vec<EvictionItem>,
+    size_limit: Option<u64>,
+    cutoff: Option<SystemTime>,
+) -> Vec<EvictionItem> {
     // walk the items and collect items until we have reached the size limit
+    let mut cache_size = 0;
     all_cache_items
         // walk through the files, youngest item comes first, oldest item comes last
-        .iter()
-        .filter(|path| {
-            //@TODO query cache for size!
-            let item_size = size_of_path(path);
+        .into_iter()
+        .filter(|item| {
+            // too old, no matter how much space is left
+            if cutoff.map_or(false, |cutoff| item.last_access < cutoff) {
+                return true;
+            }
             // add the item size to the cache size
-            cache_size += item_size;
+            cache_size += item.size;
             // keep all items (for deletion) once we have exceeded the cache size
-            let keep_file = cache_size > size_limit;
-            if keep_file {
-                removed_size += item_size;
-                removed_item_count += 1;
-            }
-            keep_file
+            size_limit.map_or(false, |size_limit| cache_size > size_limit)
         })
-        // .for_each(|path| println!("{}", path.display().to_string()));
-        // for debugging: the smaller the size limit is, the more items we keep for deletion
-        .for_each(|path| {
-            remove_file(
-                path,
-                dry_run,
-                size_changed,
-                None,
-                &DryRunMessage::Default,
-                None,
-            );
-        });
+        .collect()
+}
+
+/// the parsed --limit and --keep-newer-than
+fn parse_policy(
+    unparsed_size_limit: Option<&str>,
+    unparsed_keep_newer_than: Option<&str>,
+) -> Result<(Option<u64>, Option<SystemTime>), Error> {
+    let size_limit = match unparsed_size_limit {
+        Some(limit) => Some(parse_size_limit_to_bytes(Some(limit))?),
+        None => None,
+    };
+    let cutoff = match unparsed_keep_newer_than {
+        Some(age) => Some(parse::age_cutoff(age)?),
+        None => None,
+    };
+    Ok((size_limit, cutoff))
+}
+
+/// "Trimming to 5G and everything older than 30d"
+fn policy_description(
+    unparsed_size_limit: Option<&str>,
+    unparsed_keep_newer_than: Option<&str>,
+) -> String {
+    match (unparsed_size_limit, unparsed_keep_newer_than) {
+        (Some(limit), None) => format!("Trimming to {limit}"),
+        (None, Some(age)) => format!("Trimming everything older than {age}"),
+        (Some(limit), Some(age)) => {
+            format!("Trimming to {limit} and everything older than {age}")
+        }
+        (None, None) => unreachable!("clap requires --limit or --keep-newer-than for trim"),
+    }
+}
+
+/// trim the cache to a certain limit and invalidate caches
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn trim_cache(
+    unparsed_size_limit: Option<&str>,
+    unparsed_keep_newer_than: Option<&str>,
+    git_checkouts_cache: &mut git_checkouts::GitCheckoutCache,
+    bare_repos_cache: &mut git_bare_repos::GitRepoCache,
+    registry_pkg_cache: &mut registry_pkg_cache::RegistryPkgCaches,
+    registry_sources_cache: &mut registry_sources::RegistrySourceCaches,
+    registries: &RegistryFilter,
+    keep: &KeepList,
+    dry_run: bool,
+    size_changed: &mut bool,
+) -> Result<(), Error> {
+    // the cache should not exceed this limit and/or not contain items older than the cutoff
+    let (size_limit, cutoff) = parse_policy(unparsed_size_limit, unparsed_keep_newer_than)?;
+
+    let items_to_remove = items_to_trim(
+        size_limit,
+        cutoff,
+        git_checkouts_cache,
+        bare_repos_cache,
+        registry_pkg_cache,
+        registry_sources_cache,
+        registries,
+        keep,
+    );
+    // fast path: nothing to remove
+    if items_to_remove.is_empty() {
+        return Ok(());
+    }
+
+    // the --approve-with command may keep some of them
+    let items_to_remove = approve::approved("trim", dry_run, items_to_remove, |item| {
+        (item.path.clone(), item.size)
+    })?;
+
+    // the smaller the size limit is, the more items we keep for deletion
+    let mut removed_size: u64 = 0;
+    let mut removed_kinds: Vec<EvictionKind> = Vec::new();
+    // a dry run prints every item anyway
+    let progress = if dry_run {
+        Progress::hidden("Trimming", items_to_remove.len())
+    } else {
+        Progress::new("Trimming", items_to_remove.len())
+    };
+    for item in items_to_remove {
+        remove_file(
+            &item.path,
+            dry_run,
+            size_changed,
+            None,
+            &DryRunMessage::Default,
+            Some(item.size),
+        );
+        removed_size += item.size;
+        removed_kinds.push(item.kind);
+        progress.inc(item.size);
+    }
+    progress.finish();
 
     // invalidate caches that we might have touched
     git_checkouts_cache.invalidate();
@@ -180,10 +410,132 @@ pub(crate) fn trim_cache(
     registry_pkg_cache.invalidate();
     registry_sources_cache.invalidate();
 
-    println!(
-        "Removed {} items totalling {}",
-        removed_item_count,
-        removed_size.format_size(DECIMAL)
+    println!("{}", removal_summary(&removed_kinds, removed_size));
+    Ok(())
+}
+
+/// the items that would be trimmed in the order they would be removed, least recently used first,
+/// each with the size freed up to and including it
+fn eviction_plan(mut items_to_remove: Vec<EvictionItem>) -> Vec<(EvictionItem, u64)> {
+    // items_to_trim() returns the youngest item first
+    items_to_remove.reverse();
+    let mut cumulative_size = 0;
+    items_to_remove
+        .into_iter()
+        .map(|item| {
+            cumulative_size += item.size;
+            (item, cumulative_size)
+        })
+        .collect()
+}
+
+/// "trim --plan": the items in removal order, with their last access and the cumulative size
+fn plan_table(description: &str, plan: &[(EvictionItem, u64)]) -> String {
+    let total_size = match plan.last() {
+        Some((_, total_size)) => total_size,
+        None => return format!("{description} would remove nothing\n"),
+    };
+    let mut table = vec![vec![
+        String::from("#"),
+        String::from("Last access"),
+        String::from("Size"),
+        String::from("Cumulative"),
+        String::from("Kind"),
+        String::from("Path"),
+    ]];
+    table.extend(
+        plan.iter()
+            .enumerate()
+            .map(|(idx, (item, cumulative_size))| {
+                vec![
+                    (idx + 1).to_string(),
+                    DateTime::<Local>::from(item.last_access)
+                        .format("%Y-%m-%d %H:%M")
+                        .to_string(),
+                    item.size.format_size(DECIMAL),
+                    cumulative_size.format_size(DECIMAL),
+                    item.kind.key().to_string(),
+                    item.path.display().to_string(),
+                ]
+            }),
+    );
+    format!(
+        "{description} would remove {} items totalling {}, least recently used first:\n\n{}",
+        plan.len(),
+        total_size.format_size(DECIMAL),
+        format_table(&table, 0)
+    )
+}
+
+/// "trim --plan --output-format json", sizes in bytes and access times in seconds since the epoch
+#[cfg(feature = "json")]
+fn plan_json(
+    size_limit: Option<u64>,
+    cutoff: Option<SystemTime>,
+    plan: &[(EvictionItem, u64)],
+) -> serde_json::Value {
+    let seconds_since_epoch = |time: SystemTime| {
+        time.duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |since_epoch| since_epoch.as_secs())
+    };
+    let items = plan
+        .iter()
+        .map(|(item, cumulative_size)| {
+            serde_json::json!({
+                "path": item.path.display().to_string(),
+                "kind": item.kind.key(),
+                "size": item.size,
+                "cumulative_size": cumulative_size,
+                "last_access": seconds_since_epoch(item.last_access),
+            })
+        })
+        .collect::<Vec<_>>();
+    serde_json::json!({
+        "limit": size_limit,
+        "keep_newer_than": cutoff.map(seconds_since_epoch),
+        "total_size": plan.last().map_or(0, |(_, cumulative_size)| *cumulative_size),
+        "items": items,
+    })
+}
+
+/// print what trim would remove, nothing is removed
+// without the json feature, check_output_format() only lets tables through
+#[cfg_attr(not(feature = "json"), allow(unused_variables))]
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn trim_plan(
+    unparsed_size_limit: Option<&str>,
+    unparsed_keep_newer_than: Option<&str>,
+    git_checkouts_cache: &mut git_checkouts::GitCheckoutCache,
+    bare_repos_cache: &mut git_bare_repos::GitRepoCache,
+    registry_pkg_cache: &mut registry_pkg_cache::RegistryPkgCaches,
+    registry_sources_cache: &mut registry_sources::RegistrySourceCaches,
+    registries: &RegistryFilter,
+    keep: &KeepList,
+    output_format: OutputFormat,
+) -> Result<(), Error> {
+    let (size_limit, cutoff) = parse_policy(unparsed_size_limit, unparsed_keep_newer_than)?;
+    let plan = eviction_plan(items_to_trim(
+        size_limit,
+        cutoff,
+        git_checkouts_cache,
+        bare_repos_cache,
+        registry_pkg_cache,
+        registry_sources_cache,
+        registries,
+        keep,
+    ));
+
+    #[cfg(feature = "json")]
+    if output_format == OutputFormat::Json {
+        print_json(&plan_json(size_limit, cutoff, &plan));
+        return Ok(());
+    }
+    print!(
+        "{}",
+        plan_table(
+            &policy_description(unparsed_size_limit, unparsed_keep_newer_than),
+            &plan
+        )
     );
     Ok(())
 }
@@ -235,3 +587,187 @@ mod parse_size_limit {
         let _ignore = parse_size_limit_to_bytes(None);
     }
 }
+
+#[cfg(test)]
+mod trim_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::fs;
+
+    #[test]
+    fn eviction_items() {
+        let cargo_home = tempfile::tempdir().unwrap();
+        let root = cargo_home.path();
+        let write = |path: PathBuf, size: usize| {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, vec![b'x'; size]).unwrap();
+        };
+
+        let registry_cache = root.join("registry").join("cache").join("reg-1234");
+        write(registry_cache.join("foo-1.0.0.crate"), 10);
+        // an interrupted download is no crate archive
+        write(registry_cache.join("foo-1.0.1.crate.part"), 5);
+        let registry_src = root.join("registry").join("src").join("reg-1234");
+        write(
+            registry_src.join("foo-1.0.0").join("src").join("lib.rs"),
+            20,
+        );
+        write(registry_src.join("foo-1.0.0").join("Cargo.toml"), 3);
+        write(root.join("git").join("db").join("bar-5678").join("HEAD"), 7);
+        let checkout = root.join("git").join("checkouts").join("bar-5678");
+        write(checkout.join("abcdef").join("lib.rs"), 4);
+
+        let mut items = gather_all_cache_items(
+            &mut git_checkouts::GitCheckoutCache::new(root.join("git").join("checkouts")),
+            &mut git_bare_repos::GitRepoCache::new(root.join("git").join("db")),
+            &mut registry_pkg_cache::RegistryPkgCaches::new(root.join("registry").join("cache")),
+            &mut registry_sources::RegistrySourceCaches::new(root.join("registry").join("src")),
+            &RegistryFilter::new(None, None),
+        );
+
+        // youngest first
+        assert!(items
+            .windows(2)
+            .all(|pair| pair[0].last_access >= pair[1].last_access));
+
+        items.sort_by(|a, b| a.path.cmp(&b.path));
+        // directory sizes depend on the file system, the files add up to at least this much
+        assert!(items.iter().map(|item| item.size).sum::<u64>() >= 4 + 7 + 10 + 23);
+        assert_eq!(items[2].size, 10);
+        let items = items
+            .into_iter()
+            .map(|item| (item.path, item.kind))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            items,
+            vec![
+                (checkout.join("abcdef"), EvictionKind::GitCheckout),
+                (
+                    root.join("git").join("db").join("bar-5678"),
+                    EvictionKind::GitRepo
+                ),
+                (
+                    registry_cache.join("foo-1.0.0.crate"),
+                    EvictionKind::CrateArchive
+                ),
+                (registry_src.join("foo-1.0.0"), EvictionKind::CrateSource),
+            ]
+        );
+    }
+
+    #[test]
+    fn vanished_items_are_skipped() {
+        let cargo_home = tempfile::tempdir().unwrap();
+        let gone = cargo_home.path().join("foo-1.0.0.crate");
+        assert_eq!(get_last_access_of_item(&gone), None);
+        assert!(EvictionItem::new(&gone, EvictionKind::CrateArchive).is_none());
+
+        fs::write(&gone, "x").unwrap();
+        let item = EvictionItem::new(&gone, EvictionKind::CrateArchive).unwrap();
+        assert_eq!(item.size, 1);
+    }
+
+    #[test]
+    fn kept_crates_are_not_trimmed() {
+        let cargo_home = tempfile::tempdir().unwrap();
+        let root = cargo_home.path();
+        let registry_cache = root.join("registry").join("cache").join("reg-1234");
+        fs::create_dir_all(&registry_cache).unwrap();
+        for name in ["foo-1.0.0.crate", "bar-1.0.0.crate"] {
+            fs::write(registry_cache.join(name), vec![b'x'; 1000]).unwrap();
+        }
+
+        // the kept crate still takes up space, the other one has to make room for it
+        let items = items_to_trim(
+            Some(1500),
+            None,
+            &mut git_checkouts::GitCheckoutCache::new(root.join("git").join("checkouts")),
+            &mut git_bare_repos::GitRepoCache::new(root.join("git").join("db")),
+            &mut registry_pkg_cache::RegistryPkgCaches::new(root.join("registry").join("cache")),
+            &mut registry_sources::RegistrySourceCaches::new(root.join("registry").join("src")),
+            &RegistryFilter::new(None, None),
+            &KeepList::new(Some("foo"), &[]),
+        );
+        assert_eq!(
+            items.into_iter().map(|item| item.path).collect::<Vec<_>>(),
+            vec![registry_cache.join("bar-1.0.0.crate")]
+        );
+    }
+
+    #[test]
+    fn plan_is_ordered_and_cumulative() {
+        let now = SystemTime::now();
+        let item = |name: &str, size: u64, age: u64| EvictionItem {
+            path: PathBuf::from(name),
+            kind: EvictionKind::CrateArchive,
+            size,
+            last_access: now - std::time::Duration::from_secs(age),
+        };
+        // items_to_trim() returns the youngest item first
+        let plan = eviction_plan(vec![item("young", 5, 10), item("old", 3, 100)]);
+        let plan = plan
+            .iter()
+            .map(|(planned, cumulative_size)| (planned.path.to_str().unwrap(), *cumulative_size))
+            .collect::<Vec<_>>();
+        assert_eq!(plan, vec![("old", 3), ("young", 8)]);
+
+        assert_eq!(
+            plan_table(&policy_description(Some("5G"), None), &[]),
+            "Trimming to 5G would remove nothing\n"
+        );
+        let table = plan_table(
+            &policy_description(Some("1K"), None),
+            &eviction_plan(vec![item("foo-1.0.0.crate", 2_000, 10)]),
+        );
+        assert!(table.starts_with(
+            "Trimming to 1K would remove 1 items totalling 2 kB, least recently used first:\n\n# "
+        ));
+        assert!(table.ends_with("2 kB 2 kB       crate-archive foo-1.0.0.crate\n"));
+    }
+
+    #[test]
+    fn size_and_age_policies() {
+        let now = SystemTime::now();
+        let days_ago = |days: u64| now - std::time::Duration::from_secs(days * 24 * 60 * 60);
+        // youngest first, as gather_all_cache_items() returns them
+        let items = ["a", "b", "c", "d"]
+            .iter()
+            .zip([1, 10, 40, 100])
+            .map(|(name, age)| EvictionItem {
+                path: PathBuf::from(name),
+                kind: EvictionKind::CrateSource,
+                size: 10,
+                last_access: days_ago(age),
+            })
+            .collect::<Vec<_>>();
+        let removed = |size_limit: Option<u64>, cutoff: Option<SystemTime>| {
+            select_for_removal(items.clone(), size_limit, cutoff)
+                .into_iter()
+                .map(|removed| removed.path.to_str().unwrap().to_string())
+                .collect::<Vec<String>>()
+        };
+
+        assert_eq!(removed(Some(25), None), vec!["c", "d"]);
+        assert_eq!(removed(None, Some(days_ago(30))), vec!["c", "d"]);
+        assert_eq!(removed(None, Some(days_ago(365))), Vec::<String>::new());
+        // the old items do not count towards the limit
+        assert_eq!(removed(Some(15), Some(days_ago(30))), vec!["b", "c", "d"]);
+        assert_eq!(removed(Some(100), Some(days_ago(5))), vec!["b", "c", "d"]);
+    }
+
+    #[test]
+    fn summary() {
+        assert_eq!(removal_summary(&[], 0), "Removed 0 items totalling 0 B");
+        assert_eq!(
+            removal_summary(
+                &[
+                    EvictionKind::CrateSource,
+                    EvictionKind::CrateArchive,
+                    EvictionKind::CrateArchive
+                ],
+                2_000_000
+            ),
+            "Removed 3 items totalling 2 MB: 2 crate archives, 1 crate source checkouts"
+        );
+    }
+}
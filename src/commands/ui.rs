@@ -0,0 +1,489 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// "cargo cache ui": browse the cache and remove single crates without remembering any flags
+// The interface is line based so that it works in every terminal: the components are listed with
+// their sizes, opening one lists its crates (all versions or checkouts of a crate together), largest
+// first. Crates are marked by their numbers ("3" or "1,4-6"), "p" previews what removing the
+// marked crates would free and "d" removes them after asking once more.
+// With --dry-run "d" only prints what would be removed.
+
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, Write};
+
+use humansize::{FormatSize, DECIMAL};
+
+use crate::cache::caches::{Cache, RegistrySuperCache};
+use crate::cache::git_bare_repos::GitRepoCache;
+use crate::cache::git_checkouts::GitCheckoutCache;
+use crate::cache::registry_pkg_cache::RegistryPkgCaches;
+use crate::cache::registry_sources::RegistrySourceCaches;
+use crate::history;
+use crate::library::{CargoCachePaths, Component, Error};
+use crate::remove::remove_with_default_message;
+use crate::tables::format_table;
+use crate::top_items::common::FileDesc;
+use crate::tty::{self, Stream};
+
+const HELP: &str = "\
+commands:
+    <numbers>    open a component, inside a component mark or unmark crates: 3 or 1,4-6
+    p            preview what removing the marked crates would free
+    d            remove the marked crates
+    b            back to the list of components
+    q            quit
+    ?            show this help";
+
+/// a crate or git repo of a component with all of its versions or checkouts
+#[derive(Debug, Clone)]
+struct Entry {
+    name: String,
+    items: Vec<FileDesc>,
+    size: u64,
+    marked: bool,
+}
+
+/// a component and its entries, the largest entry first
+#[derive(Debug)]
+struct Section {
+    component: Component,
+    entries: Vec<Entry>,
+}
+
+impl Section {
+    fn size(&self) -> u64 {
+        self.entries.iter().map(|entry| entry.size).sum()
+    }
+
+    fn marked(&self) -> impl Iterator<Item = &Entry> {
+        self.entries.iter().filter(|entry| entry.marked)
+    }
+}
+
+/// what the user typed
+#[derive(Debug, PartialEq, Eq)]
+enum Command {
+    /// 1-based numbers of components or entries
+    Select(Vec<usize>),
+    Preview,
+    Delete,
+    Back,
+    Quit,
+    Help,
+    /// an empty line, show the current list again
+    Show,
+}
+
+/// parse "3" or "1,4-6" into 1-based numbers
+fn parse_selection(text: &str) -> Result<Vec<usize>, String> {
+    let number = |digits: &str| {
+        digits
+            .trim()
+            .parse::<usize>()
+            .ok()
+            .filter(|&number| number > 0)
+            .ok_or_else(|| format!("\"{}\" is not a number", digits.trim()))
+    };
+    let mut selection = Vec::new();
+    for part in text.split(',').filter(|part| !part.trim().is_empty()) {
+        match part.split_once('-') {
+            Some((first, last)) => {
+                let (first, last) = (number(first)?, number(last)?);
+                if first > last {
+                    return Err(format!("\"{}\" is an empty range", part.trim()));
+                }
+                selection.extend(first..=last);
+            }
+            None => selection.push(number(part)?),
+        }
+    }
+    Ok(selection)
+}
+
+fn parse_command(line: &str) -> Result<Command, String> {
+    match line.trim() {
+        "" => Ok(Command::Show),
+        "p" | "preview" => Ok(Command::Preview),
+        "d" | "delete" => Ok(Command::Delete),
+        "b" | "back" => Ok(Command::Back),
+        "q" | "quit" | "exit" => Ok(Command::Quit),
+        "?" | "h" | "help" => Ok(Command::Help),
+        selection => parse_selection(selection).map(Command::Select),
+    }
+}
+
+/// group the items of a component by crate, the largest crate first
+fn entries(items: Vec<FileDesc>) -> Vec<Entry> {
+    let mut by_name: BTreeMap<String, Vec<FileDesc>> = BTreeMap::new();
+    for item in items {
+        by_name.entry(item.name.clone()).or_default().push(item);
+    }
+    let mut entries = by_name
+        .into_iter()
+        .map(|(name, versions)| Entry {
+            name,
+            size: versions.iter().map(|item| item.size).sum(),
+            items: versions,
+            marked: false,
+        })
+        .collect::<Vec<Entry>>();
+    // the BTreeMap already sorted by name, keep that order for equal sizes
+    entries.sort_by_key(|entry| Reverse(entry.size));
+    entries
+}
+
+/// the components that can be browsed with their crates and sizes
+fn load(ccp: &CargoCachePaths) -> Vec<Section> {
+    let mut registry_sources = RegistrySourceCaches::new(ccp.registry_sources.clone());
+    let mut registry_pkg_cache = RegistryPkgCaches::new(ccp.registry_pkg_cache.clone());
+    let mut git_checkouts = GitCheckoutCache::new(ccp.git_checkouts.clone());
+    let mut git_repos_bare = GitRepoCache::new(ccp.git_repos_bare.clone());
+
+    vec![
+        Section {
+            component: Component::RegistrySources,
+            entries: entries(
+                registry_sources
+                    .items()
+                    .iter()
+                    .map(|path| FileDesc::new_from_reg_src(path))
+                    .collect(),
+            ),
+        },
+        Section {
+            component: Component::RegistryCrateCache,
+            entries: entries(
+                registry_pkg_cache
+                    .items()
+                    .iter()
+                    .map(|path| FileDesc::new_from_reg_cache(path))
+                    .collect(),
+            ),
+        },
+        Section {
+            component: Component::GitRepos,
+            entries: entries(
+                git_checkouts
+                    .items()
+                    .iter()
+                    .map(|path| FileDesc::new_from_git_checkouts(path))
+                    .collect(),
+            ),
+        },
+        Section {
+            component: Component::GitDB,
+            entries: entries(
+                git_repos_bare
+                    .items()
+                    .iter()
+                    .map(|path| FileDesc::new_from_git_bare(path))
+                    .collect(),
+            ),
+        },
+    ]
+}
+
+/// the state of the ui: the sections and the one that is currently open
+struct Ui {
+    sections: Vec<Section>,
+    open: Option<usize>,
+}
+
+impl Ui {
+    fn marked(&self) -> impl Iterator<Item = &Entry> {
+        self.sections.iter().flat_map(Section::marked)
+    }
+
+    fn marked_size(&self) -> u64 {
+        self.marked().map(|entry| entry.size).sum()
+    }
+
+    /// the line below every list
+    fn status(&self) -> String {
+        format!(
+            "{} crates marked ({}), ? for help",
+            self.marked().count(),
+            self.marked_size().format_size(DECIMAL)
+        )
+    }
+
+    /// the list of components
+    fn overview(&self) -> String {
+        let table = self
+            .sections
+            .iter()
+            .enumerate()
+            .map(|(i, section)| {
+                vec![
+                    format!("{}", i + 1),
+                    section.component.name().to_string(),
+                    format!("{} crates", section.entries.len()),
+                    section.size().format_size(DECIMAL),
+                    format!("{} marked", section.marked().count()),
+                ]
+            })
+            .collect::<Vec<_>>();
+        format!("{}\n{}", format_table(&table, 1), self.status())
+    }
+
+    /// the list of entries of a section
+    fn listing(&self, section: &Section) -> String {
+        let mut table = vec![vec![
+            String::new(),
+            String::new(),
+            section.component.name().to_string(),
+            String::new(),
+            section.size().format_size(DECIMAL),
+        ]];
+        table.extend(section.entries.iter().enumerate().map(|(i, entry)| {
+            vec![
+                String::from(if entry.marked { "[x]" } else { "[ ]" }),
+                format!("{}", i + 1),
+                entry.name.clone(),
+                format!("{} items", entry.items.len()),
+                entry.size.format_size(DECIMAL),
+            ]
+        }));
+        format!("{}\n{}", format_table(&table, 1), self.status())
+    }
+
+    /// the list that is currently shown
+    fn screen(&self) -> String {
+        match self.open {
+            Some(open) => self.listing(&self.sections[open]),
+            None => self.overview(),
+        }
+    }
+
+    /// open a component or toggle the marks of entries of the open component
+    fn select(&mut self, selection: &[usize]) -> Result<(), String> {
+        let count = match self.open {
+            Some(open) => self.sections[open].entries.len(),
+            None => self.sections.len(),
+        };
+        if let Some(number) = selection.iter().find(|&&number| number > count) {
+            return Err(format!(
+                "there is no number {number}, the last one is {count}"
+            ));
+        }
+        match (self.open, selection) {
+            (Some(open), _) => {
+                for number in selection {
+                    let entry = &mut self.sections[open].entries[number - 1];
+                    entry.marked = !entry.marked;
+                }
+            }
+            (None, [number]) => self.open = Some(number - 1),
+            (None, _) => return Err(String::from("open one component at a time")),
+        }
+        Ok(())
+    }
+
+    /// remove the marked entries (or only print what would be removed), returns the freed bytes
+    /// removed entries are dropped from their section
+    fn remove_marked(&mut self, dry_run: bool) -> u64 {
+        let mut freed = 0;
+        for section in &mut self.sections {
+            for entry in section.entries.iter().filter(|entry| entry.marked) {
+                for item in &entry.items {
+                    let mut size_changed = false;
+                    remove_with_default_message(
+                        &item.path,
+                        dry_run,
+                        &mut size_changed,
+                        Some(item.size),
+                    );
+                    if size_changed {
+                        freed += item.size;
+                    }
+                }
+            }
+            if !dry_run {
+                section.entries.retain(|entry| {
+                    !entry.marked || entry.items.iter().any(|item| item.path.exists())
+                });
+            }
+        }
+        freed
+    }
+}
+
+/// ask a yes/no question, everything but yes is no
+fn ask(lines: &mut impl Iterator<Item = io::Result<String>>, question: &str) -> bool {
+    print!("{question} [y/N] ");
+    let _ = io::stdout().flush();
+    matches!(
+        lines.next().and_then(Result::ok).as_deref().map(str::trim),
+        Some("y" | "yes")
+    )
+}
+
+/// browse the cache and remove the crates the user marks
+pub(crate) fn ui(ccp: &CargoCachePaths, dry_run: bool) -> Result<(), Error> {
+    if !tty::is_tty(Stream::Stdin) {
+        return Err(Error::UiNeedsTerminal);
+    }
+    println!("Calculating the sizes of the cache...");
+    let mut ui = Ui {
+        sections: load(ccp),
+        open: None,
+    };
+
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    println!("{}", ui.screen());
+    loop {
+        print!("> ");
+        let _ = io::stdout().flush();
+        // end of input quits as well
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            _ => break,
+        };
+        match parse_command(&line) {
+            Ok(Command::Select(selection)) => match ui.select(&selection) {
+                Ok(()) => println!("{}", ui.screen()),
+                Err(error) => println!("{error}"),
+            },
+            Ok(Command::Preview) => {
+                let _ = ui.remove_marked(true);
+                println!(
+                    "Removing the marked crates frees {}.",
+                    ui.marked_size().format_size(DECIMAL)
+                );
+            }
+            Ok(Command::Delete) => {
+                let count = ui.marked().count();
+                if count == 0 {
+                    println!("Nothing is marked, mark crates by their numbers first.");
+                    continue;
+                }
+                let question = format!(
+                    "Remove {count} crates ({})?",
+                    ui.marked_size().format_size(DECIMAL)
+                );
+                if !ask(&mut lines, &question) {
+                    println!("Nothing was removed.");
+                    continue;
+                }
+                let freed = ui.remove_marked(dry_run);
+                if freed > 0 {
+                    history::record_freed(freed);
+                    println!("Freed {}.", freed.format_size(DECIMAL));
+                }
+                println!("{}", ui.screen());
+            }
+            Ok(Command::Back) => {
+                ui.open = None;
+                println!("{}", ui.screen());
+            }
+            Ok(Command::Show) => println!("{}", ui.screen()),
+            Ok(Command::Help) => println!("{HELP}"),
+            Ok(Command::Quit) => break,
+            Err(error) => println!("{error}, ? shows the commands"),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod ui_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    fn file_desc(name: &str, path: &Path, size: u64) -> FileDesc {
+        FileDesc {
+            path: path.to_path_buf(),
+            name: name.to_string(),
+            size,
+        }
+    }
+
+    #[test]
+    fn selections() {
+        assert_eq!(parse_selection("3"), Ok(vec![3]));
+        assert_eq!(parse_selection("1, 4-6,"), Ok(vec![1, 4, 5, 6]));
+        assert!(parse_selection("0").is_err());
+        assert!(parse_selection("5-4").is_err());
+        assert!(parse_selection("serde").is_err());
+
+        assert_eq!(parse_command(" p "), Ok(Command::Preview));
+        assert_eq!(parse_command("q"), Ok(Command::Quit));
+        assert_eq!(parse_command(""), Ok(Command::Show));
+        assert_eq!(parse_command("2-3"), Ok(Command::Select(vec![2, 3])));
+    }
+
+    #[test]
+    fn entries_by_crate() {
+        let entries = entries(vec![
+            file_desc("serde", Path::new("serde-1.0.1"), 10),
+            file_desc("anyhow", Path::new("anyhow-1.0.0"), 30),
+            file_desc("serde", Path::new("serde-1.0.2"), 20),
+            file_desc("cc", Path::new("cc-1.0.0"), 30),
+        ]);
+        let names_and_sizes = entries
+            .iter()
+            .map(|entry| (entry.name.as_str(), entry.items.len(), entry.size))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names_and_sizes,
+            vec![("anyhow", 1, 30), ("cc", 1, 30), ("serde", 2, 30)]
+        );
+    }
+
+    #[test]
+    fn mark_and_remove() {
+        let tmp = tempfile::tempdir().unwrap();
+        let paths = ["serde-1.0.1", "serde-1.0.2", "anyhow-1.0.0"]
+            .iter()
+            .map(|name| {
+                let path = tmp.path().join(name);
+                fs::write(&path, "x").unwrap();
+                path
+            })
+            .collect::<Vec<PathBuf>>();
+        let mut ui = Ui {
+            sections: vec![Section {
+                component: Component::RegistrySources,
+                entries: entries(vec![
+                    file_desc("serde", &paths[0], 10),
+                    file_desc("serde", &paths[1], 20),
+                    file_desc("anyhow", &paths[2], 5),
+                ]),
+            }],
+            open: None,
+        };
+
+        assert!(ui.select(&[2]).is_err());
+        ui.select(&[1]).unwrap();
+        assert_eq!(ui.open, Some(0));
+        assert!(ui.select(&[3]).is_err());
+        // mark both, then unmark anyhow again
+        ui.select(&[1, 2]).unwrap();
+        ui.select(&[2]).unwrap();
+        assert_eq!(ui.marked_size(), 30);
+
+        // the preview does not remove anything
+        assert_eq!(ui.remove_marked(true), 0);
+        assert!(paths.iter().all(|path| path.exists()));
+
+        assert_eq!(ui.remove_marked(false), 30);
+        assert!(!paths[0].exists() && !paths[1].exists() && paths[2].exists());
+        let names = ui.sections[0]
+            .entries
+            .iter()
+            .map(|entry| entry.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["anyhow"]);
+        assert_eq!(ui.marked_size(), 0);
+    }
+}
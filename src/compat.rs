@@ -0,0 +1,210 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// compatibility with newer cargo versions
+// With several rustup toolchains installed, a cargo that is much newer than cargo-cache may have
+// written to the cargo home. Before doing anything we take a quick look at the places where cargo
+// changed the layout before: new directories next to registry/{index,cache,src} and git/{db,checkouts}
+// and the version of the ".cargo-ok" marker of extracted sources and checkouts.
+// Unknown directories are never touched anyway, we only warn about them. Components whose markers
+// we don't understand are left out of the run so we neither miscount nor remove them.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::library::{CargoCachePaths, Component};
+
+/// the newest version of the ".cargo-ok" marker we know, newer cargos write {"v":1}
+const CARGO_OK_VERSION: u64 = 1;
+
+/// what cargo puts into registry/
+const KNOWN_REGISTRY_ENTRIES: &[&str] = &["index", "cache", "src", "CACHEDIR.TAG"];
+
+/// what cargo puts into git/
+const KNOWN_GIT_ENTRIES: &[&str] = &["db", "checkouts", "CACHEDIR.TAG"];
+
+/// how many extracted sources or checkouts per registry or repo we look at
+const SAMPLES: usize = 3;
+
+/// something in the cargo home that cargo-cache does not understand
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Unknown {
+    path: PathBuf,
+    /// the component we can't handle, None if only an unknown directory or file was found
+    component: Option<Component>,
+    /// the ".cargo-ok" content we don't understand
+    marker: Option<String>,
+}
+
+/// the result of the compatibility probe of a cargo home
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct Compatibility {
+    unknown: Vec<Unknown>,
+}
+
+impl Compatibility {
+    /// the warnings to print, one per unknown entry
+    pub(crate) fn warnings(&self) -> Vec<String> {
+        self.unknown
+            .iter()
+            .map(|unknown| match (&unknown.component, &unknown.marker) {
+                (Some(component), Some(marker)) => format!(
+                    "\"{}\" has a .cargo-ok marker cargo-cache does not know ({marker}), it was \
+                    probably written by a newer cargo. Leaving out \"{}\".",
+                    unknown.path.display(),
+                    component.name()
+                ),
+                _ => format!(
+                    "\"{}\" is not known to cargo-cache, it was probably created by a newer cargo. \
+                    It is left alone.",
+                    unknown.path.display()
+                ),
+            })
+            .collect()
+    }
+
+    /// the components cargo-cache must not scan or remove
+    pub(crate) fn unrecognized_components(&self) -> Vec<Component> {
+        let mut components = self
+            .unknown
+            .iter()
+            .filter_map(|unknown| unknown.component.clone())
+            .collect::<Vec<Component>>();
+        components.sort();
+        components.dedup();
+        components
+    }
+}
+
+/// the sorted entries of a directory, empty if it can't be read
+fn entries(dir: &Path) -> Vec<PathBuf> {
+    let mut entries = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .map(|entry| entry.path())
+                .collect::<Vec<PathBuf>>()
+        })
+        .unwrap_or_default();
+    entries.sort();
+    entries
+}
+
+/// the entries of `dir` that are not in `known`, hidden files are fine
+fn unknown_entries(dir: &Path, known: &[&str]) -> Vec<Unknown> {
+    entries(dir)
+        .into_iter()
+        .filter(|path| {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            !name.starts_with('.') && !known.contains(&name.as_ref())
+        })
+        .map(|path| Unknown {
+            path,
+            component: None,
+            marker: None,
+        })
+        .collect()
+}
+
+/// the version of a ".cargo-ok" marker: empty or "ok" for old cargos, {"v":N} for newer ones
+fn cargo_ok_version(content: &str) -> Option<u64> {
+    match content.trim() {
+        "" | "ok" => Some(0),
+        json => json
+            .strip_prefix('{')?
+            .strip_suffix('}')?
+            .trim()
+            .strip_prefix("\"v\"")?
+            .trim()
+            .strip_prefix(':')?
+            .trim()
+            .parse()
+            .ok(),
+    }
+}
+
+/// look at the ".cargo-ok" markers of a few directories of each group (registry or repo)
+fn unknown_markers(component_dir: &Path, component: &Component) -> Option<Unknown> {
+    entries(component_dir)
+        .iter()
+        .flat_map(|group| entries(group).into_iter().take(SAMPLES))
+        .find_map(|dir| {
+            // sources that are still being extracted don't have a marker yet
+            let content = fs::read_to_string(dir.join(".cargo-ok")).ok()?;
+            match cargo_ok_version(&content) {
+                Some(version) if version <= CARGO_OK_VERSION => None,
+                _ => Some(Unknown {
+                    path: dir.join(".cargo-ok"),
+                    component: Some(component.clone()),
+                    marker: Some(content.trim().to_string()),
+                }),
+            }
+        })
+}
+
+/// take a quick look at the cargo home for things that a newer cargo may have written
+pub(crate) fn probe(ccp: &CargoCachePaths) -> Compatibility {
+    let mut unknown = unknown_entries(&ccp.registry, KNOWN_REGISTRY_ENTRIES);
+    unknown.extend(unknown_entries(
+        &ccp.cargo_home.join("git"),
+        KNOWN_GIT_ENTRIES,
+    ));
+    unknown.extend(unknown_markers(
+        &ccp.registry_sources,
+        &Component::RegistrySources,
+    ));
+    unknown.extend(unknown_markers(&ccp.git_checkouts, &Component::GitRepos));
+    Compatibility { unknown }
+}
+
+#[cfg(test)]
+mod compat_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn marker_versions() {
+        assert_eq!(cargo_ok_version(""), Some(0));
+        assert_eq!(cargo_ok_version("ok"), Some(0));
+        assert_eq!(cargo_ok_version("{\"v\":1}"), Some(1));
+        assert_eq!(cargo_ok_version("{ \"v\": 2 }\n"), Some(2));
+        assert_eq!(cargo_ok_version("{\"version\":\"1\"}"), None);
+    }
+
+    #[test]
+    fn probe_cargo_home() {
+        let tmp = tempfile::tempdir().unwrap();
+        let ccp = CargoCachePaths::new(tmp.path().to_path_buf()).unwrap();
+        let source = ccp
+            .registry_sources
+            .join("index.crates.io-1234/serde-1.0.0");
+        let checkout = ccp.git_checkouts.join("foo-1234/abcdef0");
+        fs::create_dir_all(&source).unwrap();
+        fs::create_dir_all(&checkout).unwrap();
+        fs::create_dir_all(ccp.registry.join(".hidden")).unwrap();
+        fs::write(ccp.registry.join("CACHEDIR.TAG"), "").unwrap();
+        fs::write(source.join(".cargo-ok"), "{\"v\":1}").unwrap();
+        fs::write(checkout.join(".cargo-ok"), "").unwrap();
+
+        // everything as we know it
+        assert_eq!(probe(&ccp), Compatibility::default());
+
+        fs::create_dir_all(ccp.registry.join("new-thing")).unwrap();
+        fs::write(source.join(".cargo-ok"), "{\"v\":2}").unwrap();
+        let compat = probe(&ccp);
+        assert_eq!(
+            compat.unrecognized_components(),
+            vec![Component::RegistrySources]
+        );
+        let warnings = compat.warnings();
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].contains("new-thing"));
+        assert!(warnings[1].ends_with("Leaving out \"registry-sources\"."));
+    }
+}
@@ -0,0 +1,327 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// defaults from a config file, ~/.config/cargo-cache/config.toml (or $CARGO_CACHE_CONFIG):
+//
+//   output_format = "json"        # default of --output-format, for the commands that support it
+//   exclude = ["my-toolchain/"]   # globs that are never removed, like in the .cargocacheignore
+//   keep = ["windows-sys"]        # crates that are never removed, like --keep
+//
+//   [autoclean]
+//   keep_days = 7                 # default of --keep-days
+//
+//   [trim]
+//   limit = "5G"                  # default of trim --limit
+//
+//   [metrics]
+//   url = "http://host:9091/"     # where metrics --push posts the metrics
+//   label = "ci-runner-linux"     # default of metrics --label
+//
+//   [stats]
+//   snapshots = 100               # record the sizes of the last 100 runs for stats --history
+//
+//   [maintain]
+//   autoclean_above = "10G"       # default of maintain --autoclean-above
+//   gc_every_days = 7             # default of maintain --gc-every-days
+//   check_every_hours = 24        # default of maintain --check-every-hours
+//
+// Command line options and environment variables always win over the config file.
+// Like the cargo config in network.rs, the file is read line by line: only strings, integers
+// and arrays of strings that fit on a single line are supported. Unknown keys are errors so
+// that a typo does not silently fall back to the built-in default.
+
+use std::fs;
+use std::path::PathBuf;
+
+use crate::commands::trim;
+use crate::env;
+use crate::library::{Error, OutputFormat};
+
+/// the keys of the config file
+const KEYS: &[&str] = &[
+    "output_format",
+    "exclude",
+    "keep",
+    "autoclean.keep_days",
+    "trim.limit",
+    "metrics.url",
+    "metrics.label",
+    "stats.snapshots",
+    "maintain.autoclean_above",
+    "maintain.gc_every_days",
+    "maintain.check_every_hours",
+];
+
+/// the defaults of the config file, everything is unset if there is no config file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct Config {
+    /// `autoclean.keep_days`
+    pub(crate) autoclean_keep_days: Option<u64>,
+    /// `trim.limit`
+    pub(crate) trim_limit: Option<String>,
+    /// `output_format`
+    pub(crate) output_format: Option<OutputFormat>,
+    /// `exclude`
+    pub(crate) exclude: Vec<String>,
+    /// `keep`
+    pub(crate) keep: Vec<String>,
+    /// `metrics.url`
+    pub(crate) metrics_url: Option<String>,
+    /// `metrics.label`
+    pub(crate) metrics_label: Option<String>,
+    /// `stats.snapshots`
+    pub(crate) stats_snapshots: Option<u64>,
+    /// `maintain.autoclean_above`
+    pub(crate) maintain_autoclean_above: Option<String>,
+    /// `maintain.gc_every_days`
+    pub(crate) maintain_gc_every_days: Option<u64>,
+    /// `maintain.check_every_hours`
+    pub(crate) maintain_check_every_hours: Option<u64>,
+}
+
+/// a value in the config file
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Value {
+    String(String),
+    Integer(u64),
+    Array(Vec<String>),
+}
+
+/// remove a trailing '#' comment, a '#' inside a string is kept
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    for (i, c) in line.char_indices() {
+        match (c, quote) {
+            ('"' | '\'', None) => quote = Some(c),
+            (c, Some(q)) if c == q => quote = None,
+            ('#', None) => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// "abc" or 'abc'
+fn parse_string(text: &str) -> Option<String> {
+    ['"', '\''].iter().find_map(|&quote| {
+        text.strip_prefix(quote)?
+            .strip_suffix(quote)
+            .filter(|inner| !inner.contains(quote))
+            .map(str::to_string)
+    })
+}
+
+fn parse_value(text: &str) -> Option<Value> {
+    if let Some(items) = text.strip_prefix('[') {
+        let items = items.strip_suffix(']')?.trim();
+        return items
+            .split(',')
+            .map(str::trim)
+            // allow a trailing comma
+            .filter(|item| !item.is_empty())
+            .map(parse_string)
+            .collect::<Option<Vec<String>>>()
+            .map(Value::Array);
+    }
+    parse_string(text)
+        .map(Value::String)
+        .or_else(|| text.parse().ok().map(Value::Integer))
+}
+
+/// parse the content of a config file, errors contain the line number
+pub(crate) fn parse(content: &str) -> Result<Config, String> {
+    let mut config = Config::default();
+    let mut table = String::new();
+
+    for (number, line) in content.lines().enumerate() {
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        let error = |message: String| format!("line {}: {message}", number + 1);
+
+        if let Some(header) = line.strip_prefix('[') {
+            table = header.trim_end_matches(']').trim().to_string();
+            continue;
+        }
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| error(format!("expected \"key = value\", got \"{line}\"")))?;
+        let key = if table.is_empty() {
+            key.trim().to_string()
+        } else {
+            format!("{table}.{}", key.trim())
+        };
+        if !KEYS.contains(&key.as_str()) {
+            return Err(error(format!(
+                "unknown key \"{key}\", known keys: {}",
+                KEYS.join(", ")
+            )));
+        }
+        let invalid = || error(format!("invalid value of \"{key}\": {}", value.trim()));
+
+        match (key.as_str(), parse_value(value.trim()).ok_or_else(invalid)?) {
+            ("output_format", Value::String(format)) if format == "table" || format == "json" => {
+                config.output_format = Some(OutputFormat::new(Some(&format)));
+            }
+            ("exclude", Value::Array(globs)) => config.exclude = globs,
+            ("keep", Value::Array(crates)) => config.keep = crates,
+            ("autoclean.keep_days", Value::Integer(days)) => {
+                config.autoclean_keep_days = Some(days);
+            }
+            ("trim.limit", Value::String(limit)) => {
+                let _ = trim::parse_size_limit_to_bytes(Some(&limit))
+                    .map_err(|e| error(e.to_string()))?;
+                config.trim_limit = Some(limit);
+            }
+            ("metrics.url", Value::String(url)) => config.metrics_url = Some(url),
+            ("metrics.label", Value::String(label)) => config.metrics_label = Some(label),
+            ("stats.snapshots", Value::Integer(snapshots)) => {
+                config.stats_snapshots = Some(snapshots);
+            }
+            ("maintain.autoclean_above", Value::String(limit)) => {
+                let _ = trim::parse_size_limit_to_bytes(Some(&limit))
+                    .map_err(|e| error(e.to_string()))?;
+                config.maintain_autoclean_above = Some(limit);
+            }
+            ("maintain.gc_every_days", Value::Integer(days)) => {
+                config.maintain_gc_every_days = Some(days);
+            }
+            ("maintain.check_every_hours", Value::Integer(hours)) => {
+                config.maintain_check_every_hours = Some(hours);
+            }
+            _ => return Err(invalid()),
+        }
+    }
+    Ok(config)
+}
+
+/// cargo-cache/config.toml in the config directory of the platform: `$XDG_CONFIG_HOME` or
+/// `~/.config` on linux, `~/Library/Application Support` on macos and `%APPDATA%` on windows
+fn default_config_path() -> Option<PathBuf> {
+    dirs_next::config_dir().map(|config_dir| config_dir.join("cargo-cache").join("config.toml"))
+}
+
+/// `$CARGO_CACHE_CONFIG` or the default config file
+pub(crate) fn config_path() -> Option<PathBuf> {
+    env::value(env::CONFIG)
+        .map(PathBuf::from)
+        .or_else(default_config_path)
+}
+
+/// read the config file, a missing config file sets no defaults
+/// unless it was explicitly asked for via `$CARGO_CACHE_CONFIG`
+pub(crate) fn load() -> Result<Config, Error> {
+    let path = match config_path() {
+        Some(path) => path,
+        None => return Ok(Config::default()),
+    };
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(error)
+            if error.kind() == std::io::ErrorKind::NotFound
+                && env::value(env::CONFIG).is_none() =>
+        {
+            return Ok(Config::default());
+        }
+        Err(error) => return Err(Error::InvalidConfig(path, error.to_string())),
+    };
+    parse(&content).map_err(|message| Error::InvalidConfig(path, message))
+}
+
+#[cfg(test)]
+mod config_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn config_file() {
+        let config = parse(
+            "# shared defaults\n\
+            output_format = \"json\"\n\
+            exclude = [\"my-toolchain/\", '/registry/src/*/serde-1.*', ] # keep these\n\
+            keep = [\"windows-sys\"]\n\
+            \n\
+            [autoclean]\n\
+            keep_days = 7\n\
+            \n\
+            [trim]\n\
+            limit = \"5G\"\n\
+            \n\
+            [metrics]\n\
+            url = \"http://localhost:9091/\"\n\
+            label = 'ci-runner'\n\
+            \n\
+            [stats]\n\
+            snapshots = 100\n\
+            \n\
+            [maintain]\n\
+            autoclean_above = \"10G\"\n\
+            gc_every_days = 7\n\
+            check_every_hours = 12\n",
+        )
+        .unwrap();
+        assert_eq!(
+            config,
+            Config {
+                autoclean_keep_days: Some(7),
+                trim_limit: Some(String::from("5G")),
+                output_format: Some(OutputFormat::Json),
+                exclude: vec![
+                    String::from("my-toolchain/"),
+                    String::from("/registry/src/*/serde-1.*")
+                ],
+                keep: vec![String::from("windows-sys")],
+                metrics_url: Some(String::from("http://localhost:9091/")),
+                metrics_label: Some(String::from("ci-runner")),
+                stats_snapshots: Some(100),
+                maintain_autoclean_above: Some(String::from("10G")),
+                maintain_gc_every_days: Some(7),
+                maintain_check_every_hours: Some(12),
+            }
+        );
+
+        // dotted keys work as well, an empty file sets nothing
+        assert_eq!(
+            parse("trim.limit = '1G'").unwrap().trim_limit,
+            Some(String::from("1G"))
+        );
+        assert_eq!(parse("").unwrap(), Config::default());
+    }
+
+    #[test]
+    fn invalid_config_files() {
+        assert_eq!(
+            parse("[trim]\nlimt = \"5G\"").unwrap_err(),
+            "line 2: unknown key \"trim.limt\", known keys: output_format, exclude, keep, autoclean.keep_days, trim.limit, metrics.url, metrics.label, stats.snapshots, maintain.autoclean_above, maintain.gc_every_days, maintain.check_every_hours"
+        );
+        assert_eq!(
+            parse("output_format = \"yaml\"").unwrap_err(),
+            "line 1: invalid value of \"output_format\": \"yaml\""
+        );
+        assert_eq!(
+            parse("[autoclean]\n\nkeep_days = \"7\"").unwrap_err(),
+            "line 3: invalid value of \"autoclean.keep_days\": \"7\""
+        );
+        assert_eq!(
+            parse("exclude = [\"a\", b]").unwrap_err(),
+            "line 1: invalid value of \"exclude\": [\"a\", b]"
+        );
+        assert!(parse("[trim]\nlimit = \"5X\"").is_err());
+        assert!(parse("[maintain]\nautoclean_above = \"5X\"").is_err());
+        assert!(parse("output_format").is_err());
+    }
+
+    #[test]
+    fn comments() {
+        assert_eq!(strip_comment("a = 1 # one"), "a = 1 ");
+        assert_eq!(strip_comment("a = \"#1\" # one"), "a = \"#1\" ");
+        assert_eq!(strip_comment("# all comment"), "");
+    }
+}
@@ -0,0 +1,225 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// confirmation before --autoclean removes a lot of data
+// New users may not expect --autoclean to remove several gigabytes. If a lot is about to be removed,
+// cargo-cache is run interactively and the user did not set up a cargo-cache config file yet, we
+// show the largest items that would be removed and ask before removing anything.
+// --yes skips the question, scripts (no TTY) are never asked.
+// "toolchain --remove-toolchain" and "--remove-docs" ask the same way, no matter how much is removed.
+
+use std::fmt::Write as _;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use humansize::{FormatSize, DECIMAL};
+
+use crate::cache::caches::{Cache, RegistrySubCache, RegistrySuperCache};
+use crate::cache::git_checkouts::GitCheckoutCache;
+use crate::cache::registry_sources::RegistrySourceCaches;
+use crate::config;
+use crate::library::{cumulative_dir_size, CargoCachePaths, Error, RegistryFilter};
+use crate::rebuild_cost::RebuildCosts;
+use crate::tables::format_table;
+use crate::tty::{self, Stream};
+
+/// ask before removing more than this many bytes
+const CONFIRM_THRESHOLD: u64 = 5_000_000_000;
+
+/// number of items shown in the preview
+const PREVIEW_ITEMS: usize = 10;
+
+/// whether we need to ask before removing `size` bytes
+fn needs_confirmation(size: u64, assume_yes: bool, dry_run: bool) -> bool {
+    !assume_yes
+        && !dry_run
+        && size > CONFIRM_THRESHOLD
+        && !config::config_path().map_or(false, |config| config.is_file())
+        && tty::is_tty(Stream::Stdin)
+}
+
+/// the largest items and their sizes, largest first
+fn largest_items(items: &[PathBuf], limit: usize) -> Vec<(&Path, u64)> {
+    let mut sized = items
+        .iter()
+        .map(|item| (item.as_path(), cumulative_dir_size(item).dir_size))
+        .collect::<Vec<_>>();
+    // largest first, by path if the size is the same
+    sized.sort_by(|(a_path, a_size), (b_path, b_size)| {
+        b_size.cmp(a_size).then_with(|| a_path.cmp(b_path))
+    });
+    sized.truncate(limit);
+    sized
+}
+
+/// the preview of what is going to be removed and what it takes to get it back
+fn preview(ccp: &CargoCachePaths, items: &[PathBuf], size: u64) -> String {
+    let sized = largest_items(items, items.len());
+    // only the sources and checkouts are removed, their archives and bare repos stay
+    let mut rebuild_costs = RebuildCosts::new(ccp, items.iter().cloned());
+    let costs = sized
+        .iter()
+        .map(|(path, item_size)| rebuild_costs.add(path, *item_size))
+        .collect::<Vec<_>>();
+    let rows = sized
+        .iter()
+        .zip(costs)
+        .take(PREVIEW_ITEMS)
+        .map(|((path, item_size), cost)| {
+            vec![
+                path.display().to_string(),
+                item_size.format_size(DECIMAL),
+                cost.map(|cost| cost.to_string()).unwrap_or_default(),
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    let largest = format_table(&rows, 0)
+        .lines()
+        .fold(String::new(), |mut largest, line| {
+            let _ = writeln!(largest, "  {line}");
+            largest
+        });
+
+    format!(
+        "--autoclean is about to remove {} in {} items, the largest ones are:\n\n{largest}\n{}",
+        size.format_size(DECIMAL),
+        items.len(),
+        rebuild_costs.table()
+    )
+}
+
+/// ask the user for confirmation if --autoclean would remove a lot of data
+/// the caches only contain the selected components, so does the preview
+/// registries that are not cleaned are not part of the preview either
+pub(crate) fn confirm_autoclean(
+    ccp: &CargoCachePaths,
+    registry_sources_caches: &mut RegistrySourceCaches,
+    checkouts_cache: &mut GitCheckoutCache,
+    registries: &RegistryFilter,
+    assume_yes: bool,
+    dry_run: bool,
+) -> Result<(), Error> {
+    let mut size = 0;
+    let mut items = Vec::new();
+    for cache in registry_sources_caches
+        .caches()
+        .iter_mut()
+        .filter(|cache| registries.includes_registry_path(cache.path()))
+    {
+        size += cache.total_size();
+        items.extend(cache.items().iter().cloned());
+    }
+    if registries.includes_git() {
+        size += checkouts_cache.total_size();
+        items.extend(checkouts_cache.items().iter().cloned());
+    }
+
+    if !needs_confirmation(size, assume_yes, dry_run) {
+        return Ok(());
+    }
+
+    println!("{}", preview(ccp, &items, size));
+    if ask("Continue?") {
+        Ok(())
+    } else {
+        Err(Error::AutocleanAborted)
+    }
+}
+
+/// ask a yes/no question, no is the default
+fn ask(question: &str) -> bool {
+    print!("{question} (pass --yes to skip this question) [y/N] ");
+    let _ = io::stdout().flush();
+
+    let mut answer = String::new();
+    let _ = io::stdin().lock().read_line(&mut answer);
+
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+/// ask before a toolchain (or a part of one) is removed, scripts (no TTY) are never asked
+pub(crate) fn confirm_removal(what: &str, assume_yes: bool) -> Result<(), Error> {
+    if assume_yes || !tty::is_tty(Stream::Stdin) || ask(&format!("Remove {what}?")) {
+        Ok(())
+    } else {
+        Err(Error::ToolchainRemovalAborted)
+    }
+}
+
+#[cfg(test)]
+mod confirm_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::fs;
+
+    #[test]
+    fn largest_items_first() {
+        let tmp = tempfile::tempdir().unwrap();
+        let items = ["small", "large", "medium", "also_medium"]
+            .iter()
+            .zip([1, 300, 20, 20])
+            .map(|(name, size)| {
+                let dir = tmp.path().join(name);
+                fs::create_dir(&dir).unwrap();
+                fs::write(dir.join("file"), "x".repeat(size)).unwrap();
+                dir
+            })
+            .collect::<Vec<_>>();
+
+        let largest = largest_items(&items, 3)
+            .into_iter()
+            .map(|(path, _)| path.file_name().unwrap().to_str().unwrap())
+            .collect::<Vec<_>>();
+        // the size of the directory entries depends on the filesystem, only check the order
+        assert_eq!(largest, vec!["large", "also_medium", "medium"]);
+    }
+
+    #[test]
+    fn preview_rebuild_costs() {
+        let tmp = tempfile::tempdir().unwrap();
+        let ccp = CargoCachePaths::new(tmp.path().to_path_buf()).unwrap();
+        let registry = "index.crates.io-6f17d22bba15001f";
+        let source = ccp.registry_sources.join(registry).join("foo-1.0.0");
+        let checkout = ccp
+            .git_checkouts
+            .join("repo-0123456789abcdef")
+            .join("a1b2c3d");
+        fs::create_dir_all(ccp.registry_pkg_cache.join(registry)).unwrap();
+        fs::write(
+            ccp.registry_pkg_cache
+                .join(registry)
+                .join("foo-1.0.0.crate"),
+            "x",
+        )
+        .unwrap();
+        for dir in [&source, &checkout] {
+            fs::create_dir_all(dir).unwrap();
+            fs::write(dir.join("lib.rs"), "x".repeat(100)).unwrap();
+        }
+
+        let preview = preview(&ccp, &[source, checkout], 200);
+        assert!(preview.starts_with("--autoclean is about to remove 200 B in 2 items"));
+        // the archive stays, the bare repo is gone
+        let lines = preview.lines().collect::<Vec<_>>();
+        let line_of = |item: &str| *lines.iter().find(|line| line.contains(item)).unwrap();
+        assert!(line_of("foo-1.0.0").starts_with("  "));
+        assert!(line_of("foo-1.0.0").ends_with("local re-extraction"));
+        assert!(line_of("a1b2c3d").ends_with("git re-clone"));
+        assert!(line_of("Rebuild cost").ends_with("To download"));
+    }
+
+    #[test]
+    fn no_confirmation_needed() {
+        let too_much = CONFIRM_THRESHOLD + 1;
+        assert!(!needs_confirmation(CONFIRM_THRESHOLD, false, false));
+        assert!(!needs_confirmation(too_much, true, false));
+        assert!(!needs_confirmation(too_much, false, true));
+    }
+}
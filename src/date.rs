@@ -7,13 +7,16 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+use crate::approve;
+use crate::args::parse;
 use crate::cache::caches::{Cache, RegistrySuperCache};
 use crate::cache::*;
+use crate::keep::KeepList;
 use crate::library::*;
 use crate::remove::*;
+use crate::would_free;
 
 use chrono::{prelude::*, NaiveDateTime};
-use regex::Regex;
 
 // remove cache items that are older than X or younger than Y (or between X and Y)
 
@@ -29,74 +32,6 @@ enum AgeRelation<'a> {
     // OlderOrYounger(&'a str, &'a str),
 }
 
-fn parse_date(date: &str) -> Result<NaiveDateTime, Error> {
-    // @TODO handle yyyyy.mm.dd hh:mm:ss
-    // @TODO  handle dd.mm.yy if yy is yy and not yyyy
-    let date_to_compare: NaiveDateTime = {
-        // xxxx.xx.xx => yyyy.mm.dd
-        // we only have a date but no time
-        if Regex::new(r"^\d{4}.\d{2}.\d{2}$").unwrap().is_match(date) {
-            // most likely a date
-            let now = Local::now();
-            let split: Result<Vec<u32>, _> = date.split('.').map(str::parse).collect();
-            let split = match split {
-                Ok(result) => result,
-                Err(a) => return Err(Error::DateParseFailure(a.to_string(), "u32".into())),
-            };
-            #[allow(clippy::cast_possible_wrap)]
-            let nd =
-                if let Some(date2) = NaiveDate::from_ymd_opt(split[0] as i32, split[1], split[2]) {
-                    date2
-                } else {
-                    return Err(Error::DateParseFailure(
-                        format!("{}.{}.{}", split[0], split[1], split[2]),
-                        "date".into(),
-                    ));
-                };
-
-            match nd.and_hms_opt(now.hour(), now.minute(), now.second()) {
-                Some(nd) => nd,
-                None => return Err(Error::DateParseFailure(format!("{now:?}"), "date".into())),
-            }
-
-        // xx:xx:xx => hh::mm::ss
-        } else if Regex::new(r"^\d{2}:\d{2}:\d{2}$").unwrap().is_match(date) {
-            // probably a time
-            let today = Local::now();
-
-            let split: Result<Vec<u32>, _> = date.split(':').map(str::parse).collect();
-            let split = match split {
-                Ok(result) => result,
-                Err(a) => return Err(Error::DateParseFailure(a.to_string(), "u32".into())),
-            };
-
-            let nd = if let Some(date2) =
-                NaiveDate::from_ymd_opt(today.year(), today.month(), today.day())
-            {
-                date2
-            } else {
-                return Err(Error::DateParseFailure(
-                    format!("{}:{}:{}", today.year(), today.month(), today.day()),
-                    "date".into(),
-                ));
-            };
-
-            match nd.and_hms_opt(split[0], split[1], split[2]) {
-                Some(nd) => nd,
-                None => {
-                    return Err(Error::DateParseFailure(
-                        format!("{}:{}:{}", split[0], split[1], split[2]),
-                        "date".into(),
-                    ))
-                }
-            }
-        } else {
-            return Err(Error::DateParseFailure(date.into(), String::new()));
-        }
-    };
-    Ok(date_to_compare)
-}
-
 #[derive(Debug, Clone)]
 struct FileWithDate {
     file: std::path::PathBuf,
@@ -113,7 +48,7 @@ fn filter_files_by_date<'a>(
         }
         AgeRelation::FileYoungerThanDate(younger_date) => {
             // file is younger than date if file.date > date_param
-            let date_parameter = parse_date(younger_date)?;
+            let date_parameter = parse::date(younger_date)?;
             Ok(files
                 .iter()
                 .filter(|file| file.access_date > date_parameter)
@@ -121,7 +56,7 @@ fn filter_files_by_date<'a>(
         }
         AgeRelation::FileOlderThanDate(older_date) => {
             // file is older than date if file.date < date_param
-            let date_parameter = parse_date(older_date)?;
+            let date_parameter = parse::date(older_date)?;
             Ok(files
                 .iter()
                 .filter(|file| file.access_date < date_parameter)
@@ -151,6 +86,7 @@ pub(crate) fn remove_files_by_dates(
     arg_older: Option<&str>,
     dry_run: bool,
     dirs: Option<&str>,
+    keep: &KeepList,
     size_changed: &mut bool,
 ) -> Result<(), Error> {
     if dirs.is_none() {
@@ -197,9 +133,10 @@ pub(crate) fn remove_files_by_dates(
     // for each file, get the access time
     let mut dates: Vec<FileWithDate> = files_of_components
         .into_iter()
+        .filter(|path| !keep.keeps(path))
         .map(|path| {
             let access_time = path.metadata().unwrap().accessed().unwrap();
-            let naive_datetime = chrono::DateTime::<Local>::from(access_time).naive_local();
+            let naive_datetime = DateTime::<Local>::from(access_time).naive_local();
             FileWithDate {
                 file: path,
                 access_date: naive_datetime,
@@ -212,7 +149,16 @@ pub(crate) fn remove_files_by_dates(
     // filter the files by comparing the given date and the files access time
     let filtered_files: Vec<&FileWithDate> = filter_files_by_date(&date_comp, &dates)?;
 
+    // the --approve-with command may keep some of them
+    let filtered_files = approve::approved("remove-by-date", dry_run, filtered_files, |fwd| {
+        (fwd.file.clone(), size_of_path(&fwd.file))
+    })?;
+
     if dry_run {
+        // the end of the run shows what this would free
+        for fwd in &filtered_files {
+            would_free::record(&fwd.file, None);
+        }
         // if we dry run, we won't have to invalidate caches
         println!(
             "dry-run: would delete {} items that are {}...",
@@ -277,73 +223,38 @@ pub(crate) fn remove_files_by_dates(
 }
 
 #[cfg(test)]
-mod libtests {
+mod date_tests {
     use super::*;
-
     use pretty_assertions::assert_eq;
+    use std::fs;
 
     #[test]
-    fn parse_dates() {
-        assert!(parse_date("").is_err());
-        assert!(parse_date(&String::from("a")).is_err());
-
-        assert!(parse_date(&String::from("01.01:2002")).is_err());
-        assert!(parse_date(&String::from("01.01.2002")).is_err()); // need yyyy.mm.dd
-        assert!(parse_date(&String::from("2002.30.30")).is_err());
-
-        assert_eq!(
-            parse_date(&String::from("2002.01.01"))
-                .unwrap()
-                .format("%Y.%m.%d")
-                .to_string(),
-            String::from("2002.01.01")
-        );
-
-        assert_eq!(
-            parse_date(&String::from("1234.12.08"))
-                .unwrap()
-                .format("%Y.%m.%d")
-                .to_string(),
-            String::from("1234.12.08")
-        );
-
-        assert_eq!(
-            parse_date(&String::from("1990.12.08"))
-                .unwrap()
-                .format("%Y.%m.%d")
-                .to_string(),
-            String::from("1990.12.08")
-        );
-
-        assert_eq!(
-            parse_date(&String::from("12:00:00"))
-                .unwrap()
-                .format("%H:%M:%S")
-                .to_string(),
-            String::from("12:00:00")
-        );
-
-        assert_eq!(
-            parse_date(&String::from("00:00:00"))
-                .unwrap()
-                .format("%H:%M:%S")
-                .to_string(),
-            String::from("00:00:00")
-        );
-    }
-
-    #[test]
-    fn parse_dates_panic1() {
-        assert!(parse_date(&String::from("24:00:00")).is_err());
-    }
-
-    #[test]
-    fn parse_dates_panic2() {
-        assert!(parse_date(&String::from("24:30:24")).is_err());
-    }
-
-    #[test]
-    fn parse_dates_panic3() {
-        assert!(parse_date(&String::from("30:30:24")).is_err());
+    fn dry_run_records_what_it_would_free() {
+        let tmp = tempfile::tempdir().unwrap();
+        let ccp = CargoCachePaths::new(tmp.path().to_path_buf()).unwrap();
+        let registry = ccp
+            .registry_pkg_cache
+            .join("index.crates.io-6f17d22bba15001f");
+        fs::create_dir_all(&registry).unwrap();
+        let archive = registry.join("foo-1.0.0.crate");
+        fs::write(&archive, "archive").unwrap();
+
+        let mut size_changed = false;
+        remove_files_by_dates(
+            &mut git_checkouts::GitCheckoutCache::new(ccp.git_checkouts.clone()),
+            &mut git_bare_repos::GitRepoCache::new(ccp.git_repos_bare.clone()),
+            &mut registry_pkg_cache::RegistryPkgCaches::new(ccp.registry_pkg_cache.clone()),
+            &mut registry_sources::RegistrySourceCaches::new(ccp.registry_sources),
+            Some("2000.01.01"),
+            None,
+            true,
+            Some("registry-crate-cache"),
+            &KeepList::default(),
+            &mut size_changed,
+        )
+        .unwrap();
+
+        assert!(archive.is_file());
+        assert_eq!(would_free::recorded(&archive), Some(7));
     }
 }
@@ -0,0 +1,433 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// "cargo cache dedupe": identical files of the extracted crate sources share their data on disk
+// Every version of a crate (of every registry) is extracted into its own directory of registry/src,
+// adjacent versions usually contain many identical files: licenses, unchanged modules, test data...
+// We group the files by size and permissions, hash the groups with more than one member and replace
+// all but the first file of each set of identical files with a hardlink to it or, with --reflink, with
+// a clone the file system shares the data of (Btrfs and XFS on Linux, APFS on macOS, ReFS on Windows).
+// Clones are made with the FICLONE ioctl or clonefile(), which fail instead of copying the data, so
+// a file that could not be cloned is kept and never counted as saved.
+// A hardlink is the same file under another name: editing one of the crate sources edits all of its
+// copies. Cargo never writes to a crate source after extracting it, except for .cargo-ok which we
+// leave alone. The link is created next to the duplicate under a temporary name and renamed over it,
+// an interrupted run never leaves a file missing.
+// The sizes in the summary count every name of a hardlinked file, they do not shrink.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use humansize::{FormatSize, DECIMAL};
+use rayon::prelude::*;
+
+use crate::cancel::{exit_if_cancelled, is_cancelled};
+use crate::library::{sha256_of_file, CargoCachePaths, Error};
+use crate::progress::Progress;
+use crate::walk::walk;
+
+/// how duplicates are replaced
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum LinkKind {
+    Hardlink,
+    Reflink,
+}
+
+impl LinkKind {
+    fn plural(self) -> &'static str {
+        match self {
+            Self::Hardlink => "hardlinks",
+            Self::Reflink => "reflinks",
+        }
+    }
+}
+
+/// files can only be linked if they have the same size and permissions, a hardlink shares them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct FileKey {
+    size: u64,
+    mode: u32,
+}
+
+/// a file of the crate sources that may have identical copies
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Candidate {
+    path: PathBuf,
+    key: FileKey,
+    /// device and inode, files that are already hardlinked to each other have the same
+    id: Option<(u64, u64)>,
+}
+
+/// a set of identical files, the duplicates are replaced by links to `keep`
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct IdenticalFiles {
+    keep: PathBuf,
+    duplicates: Vec<PathBuf>,
+    /// the space that is freed once all duplicates are links to `keep`
+    freed: u64,
+}
+
+#[cfg(unix)]
+fn file_key(metadata: &fs::Metadata) -> FileKey {
+    use std::os::unix::fs::PermissionsExt;
+    FileKey {
+        size: metadata.len(),
+        mode: metadata.permissions().mode() & 0o7777,
+    }
+}
+
+#[cfg(not(unix))]
+fn file_key(metadata: &fs::Metadata) -> FileKey {
+    FileKey {
+        size: metadata.len(),
+        mode: u32::from(metadata.permissions().readonly()),
+    }
+}
+
+#[cfg(unix)]
+#[allow(clippy::unnecessary_wraps)]
+fn file_id(metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+/// we can not tell without opening the file, count every file as a file of its own
+#[cfg(not(unix))]
+fn file_id(_metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+/// the files of the crate sources that are worth linking: no empty files, no symlinks and no .cargo-ok
+fn candidates(registry_sources: &Path) -> Vec<Candidate> {
+    walk(registry_sources)
+        .filter(|_| !is_cancelled())
+        .filter(|entry| entry.file_type().is_file())
+        .filter(|entry| entry.file_name() != ".cargo-ok")
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            (metadata.len() > 0).then(|| Candidate {
+                path: entry.into_path(),
+                key: file_key(&metadata),
+                id: file_id(&metadata),
+            })
+        })
+        .collect()
+}
+
+/// the number of different files among `files`, files we can't identify are all different
+fn distinct_files<'a>(files: impl Iterator<Item = &'a Candidate>) -> usize {
+    let mut ids = BTreeSet::new();
+    files
+        .filter(|file| file.id.map_or(true, |id| ids.insert(id)))
+        .count()
+}
+
+/// group the candidates into sets of identical files, files that can't be read are skipped
+fn identical_files(candidates: Vec<Candidate>) -> Vec<IdenticalFiles> {
+    let mut by_key: BTreeMap<FileKey, Vec<Candidate>> = BTreeMap::new();
+    for candidate in candidates {
+        by_key.entry(candidate.key).or_default().push(candidate);
+    }
+    // only files that may have a copy that is not linked to them yet need to be hashed
+    let to_hash = by_key
+        .into_values()
+        .filter(|files| distinct_files(files.iter()) > 1)
+        .flatten()
+        .collect::<Vec<_>>();
+
+    let progress = Progress::new("Hashing files", to_hash.len());
+    let hashed = to_hash
+        .into_par_iter()
+        .filter(|_| !is_cancelled())
+        .filter_map(|candidate| {
+            let hash = sha256_of_file(&candidate.path);
+            progress.inc(0);
+            match hash {
+                Ok(hash) => Some((hash, candidate)),
+                Err(error) => {
+                    progress.suspend(|| {
+                        eprintln!(
+                            "Warning: failed to read '{}', skipping it: {}",
+                            candidate.path.display(),
+                            error
+                        );
+                    });
+                    None
+                }
+            }
+        })
+        .collect::<Vec<_>>();
+    progress.finish();
+
+    let mut by_content: BTreeMap<(FileKey, String), Vec<Candidate>> = BTreeMap::new();
+    for (hash, candidate) in hashed {
+        by_content
+            .entry((candidate.key, hash))
+            .or_default()
+            .push(candidate);
+    }
+    by_content
+        .into_iter()
+        .filter_map(|((key, _), mut files)| {
+            files.sort();
+            let keep = files.remove(0);
+            let duplicates = files
+                .into_iter()
+                .filter(|file| file.id.is_none() || file.id != keep.id)
+                .collect::<Vec<_>>();
+            let freed = key.size * distinct_files(duplicates.iter()) as u64;
+            (!duplicates.is_empty()).then(|| IdenticalFiles {
+                keep: keep.path,
+                duplicates: duplicates.into_iter().map(|file| file.path).collect(),
+                freed,
+            })
+        })
+        .collect()
+}
+
+/// whether files in `dir` can be cloned, we clone a small file to find out: the type of the file
+/// system is not enough, XFS for example only supports it if it was created with reflink=1
+fn supports_reflinks(dir: &Path) -> bool {
+    let probe = dir.join(".cargo-cache-reflink-probe");
+    let clone = dir.join(".cargo-cache-reflink-probe-clone");
+    let supported = fs::write(&probe, "probe")
+        .and_then(|()| reflink_copy::reflink(&probe, &clone))
+        .is_ok();
+    let _ = fs::remove_file(&clone);
+    let _ = fs::remove_file(&probe);
+    supported
+}
+
+/// run `change` with `dir` writable, cargo extracts directories that are read-only in the crate
+/// archive as read-only and we can not create the link in there otherwise
+fn with_writable_dir<T>(dir: &Path, change: impl FnOnce() -> io::Result<T>) -> io::Result<T> {
+    let permissions = fs::metadata(dir)?.permissions();
+    if !permissions.readonly() {
+        return change();
+    }
+    let mut writable = permissions.clone();
+    #[allow(clippy::permissions_set_readonly_false)]
+    writable.set_readonly(false);
+    fs::set_permissions(dir, writable)?;
+    let changed = change();
+    fs::set_permissions(dir, permissions)?;
+    changed
+}
+
+/// replace `duplicate` with a link to `keep`
+fn replace_with_link(keep: &Path, duplicate: &Path, kind: LinkKind) -> io::Result<()> {
+    let dir = duplicate.parent().unwrap_or_else(|| Path::new("."));
+    let mut temporary_name = duplicate.file_name().unwrap_or_default().to_os_string();
+    temporary_name.push(".cargo-cache-dedupe");
+    let temporary = dir.join(temporary_name);
+
+    with_writable_dir(dir, || {
+        match kind {
+            LinkKind::Hardlink => fs::hard_link(keep, &temporary)?,
+            LinkKind::Reflink => reflink_copy::reflink(keep, &temporary)?,
+        }
+        // windows does not replace read-only files
+        #[cfg(windows)]
+        {
+            let mut permissions = fs::metadata(duplicate)?.permissions();
+            #[allow(clippy::permissions_set_readonly_false)]
+            permissions.set_readonly(false);
+            fs::set_permissions(duplicate, permissions)?;
+        }
+        fs::rename(&temporary, duplicate).map_err(|error| {
+            let _ = fs::remove_file(&temporary);
+            error
+        })
+    })
+}
+
+/// `cargo cache dedupe`: replace identical files of the crate sources by links to one of them
+pub(crate) fn dedupe(ccp: &CargoCachePaths, kind: LinkKind, dry_run: bool) -> Result<(), Error> {
+    let registry_sources = &ccp.registry_sources;
+    if kind == LinkKind::Reflink && !supports_reflinks(registry_sources) {
+        return Err(Error::ReflinksUnsupported(registry_sources.clone()));
+    }
+
+    let candidates = candidates(registry_sources);
+    let scanned = candidates.len();
+    let sets = identical_files(candidates);
+    if sets.is_empty() {
+        println!("None of the {scanned} files of the crate sources has a copy that is not linked to it yet.");
+        return Ok(());
+    }
+    let duplicates: usize = sets.iter().map(|set| set.duplicates.len()).sum();
+    let freed: u64 = sets.iter().map(|set| set.freed).sum();
+    println!(
+        "Found {} duplicates of {} files among the {} files of the crate sources.",
+        duplicates,
+        sets.len(),
+        scanned
+    );
+    if dry_run {
+        println!(
+            "dry-run: would replace them with {}, saving {}",
+            kind.plural(),
+            freed.format_size(DECIMAL)
+        );
+        return Ok(());
+    }
+
+    let progress = Progress::new("Linking duplicates", duplicates);
+    let mut failed = 0;
+    for set in &sets {
+        for duplicate in &set.duplicates {
+            if is_cancelled() {
+                break;
+            }
+            if let Err(error) = replace_with_link(&set.keep, duplicate, kind) {
+                failed += 1;
+                progress.suspend(|| {
+                    eprintln!(
+                        "Warning: failed to replace '{}' with a link to '{}': {}",
+                        duplicate.display(),
+                        set.keep.display(),
+                        error
+                    );
+                });
+            }
+            progress.inc(0);
+        }
+    }
+    progress.finish();
+    exit_if_cancelled("only some of the duplicates were replaced");
+
+    if failed == 0 {
+        println!(
+            "Replaced {} duplicates with {}, saved {}",
+            duplicates,
+            kind.plural(),
+            freed.format_size(DECIMAL)
+        );
+    } else {
+        println!(
+            "Replaced {} of {} duplicates with {}, {} could not be replaced",
+            duplicates - failed,
+            duplicates,
+            kind.plural(),
+            failed
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod dedupe_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn crate_source(registry_sources: &Path, name: &str, files: &[(&str, &str)]) {
+        let dir = registry_sources
+            .join("index.crates.io-6f17d22bba15001f")
+            .join(name);
+        for (file, content) in files {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+    }
+
+    #[test]
+    fn finds_identical_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let sources = dir.path();
+        let license = "MIT license text";
+        crate_source(
+            sources,
+            "foo-0.1.0",
+            &[
+                ("LICENSE", license),
+                ("src/lib.rs", "fn a() {}"),
+                (".cargo-ok", "ok"),
+            ],
+        );
+        crate_source(
+            sources,
+            "foo-0.1.1",
+            &[
+                ("LICENSE", license),
+                ("src/lib.rs", "fn b() {}"),
+                (".cargo-ok", "ok"),
+            ],
+        );
+        crate_source(sources, "bar-1.0.0", &[("LICENSE", license), ("empty", "")]);
+        crate_source(sources, "baz-1.0.0", &[("empty", "")]);
+
+        let registry = sources.join("index.crates.io-6f17d22bba15001f");
+        let sets = identical_files(candidates(sources));
+        assert_eq!(
+            sets,
+            vec![IdenticalFiles {
+                keep: registry.join("bar-1.0.0/LICENSE"),
+                duplicates: vec![
+                    registry.join("foo-0.1.0/LICENSE"),
+                    registry.join("foo-0.1.1/LICENSE")
+                ],
+                freed: 2 * license.len() as u64,
+            }]
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn links_duplicates() {
+        use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+        let dir = tempfile::tempdir().unwrap();
+        let sources = dir.path();
+        crate_source(sources, "foo-0.1.0", &[("LICENSE", "MIT")]);
+        crate_source(sources, "foo-0.1.1", &[("LICENSE", "MIT")]);
+        let registry = sources.join("index.crates.io-6f17d22bba15001f");
+        let read_only = registry.join("foo-0.1.1");
+        fs::set_permissions(&read_only, fs::Permissions::from_mode(0o555)).unwrap();
+
+        for set in identical_files(candidates(sources)) {
+            for duplicate in &set.duplicates {
+                replace_with_link(&set.keep, duplicate, LinkKind::Hardlink).unwrap();
+            }
+        }
+
+        let metadata = fs::metadata(registry.join("foo-0.1.1/LICENSE")).unwrap();
+        assert_eq!(metadata.nlink(), 2);
+        assert_eq!(
+            fs::metadata(&read_only).unwrap().permissions().mode() & 0o777,
+            0o555
+        );
+        assert_eq!(fs::read_dir(&read_only).unwrap().count(), 1);
+        // already linked files are not found again
+        assert_eq!(identical_files(candidates(sources)), vec![]);
+        fs::set_permissions(&read_only, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    #[test]
+    fn reflinks_are_never_copies() {
+        let dir = tempfile::tempdir().unwrap();
+        let keep = dir.path().join("keep");
+        let duplicate = dir.path().join("duplicate");
+        fs::write(&keep, "same").unwrap();
+        fs::write(&duplicate, "same").unwrap();
+
+        let supported = supports_reflinks(dir.path());
+        // the probe leaves nothing behind
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+        // without support the duplicate stays as it is instead of being replaced by a full copy
+        assert_eq!(
+            replace_with_link(&keep, &duplicate, LinkKind::Reflink).is_ok(),
+            supported
+        );
+        assert_eq!(fs::read_to_string(&duplicate).unwrap(), "same");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+}
@@ -9,7 +9,7 @@
 
 /// This file provides the `DirSize` struct which holds information on the sizes and the number of files of the cargo cache.
 /// When constructing the struct, the caches from the cache modules are used.
-/// The new() method does parallel processing to a bit of time
+/// The `new()` method does parallel processing to a bit of time
 use std::fmt;
 
 use crate::cache::caches::Cache;
@@ -17,7 +17,15 @@ use crate::cache::caches::RegistrySubCache;
 use crate::cache::caches::RegistrySuperCache;
 
 use crate::cache::*;
+use crate::cancel;
+use crate::history;
+use crate::hyperlink;
 use crate::library::*;
+use crate::messages::{self, Message};
+use crate::names;
+use crate::other_files::OtherFiles;
+use crate::registry_names;
+use crate::size_cache;
 use crate::tables::*;
 
 use humansize::{FormatSize, DECIMAL};
@@ -52,17 +60,29 @@ pub(crate) struct DirSizes<'a> {
     total_reg_index_size: u64,
     /// total number of registry indices
     total_reg_index_num: u64,
+    /// number of sparse registry indices, these have no git repo
+    // only part of the --output-format json summary
+    #[cfg_attr(not(feature = "json"), allow(dead_code))]
+    numb_reg_index_sparse: usize,
     /// number of source archives (.crates) // @TODO clarify
     numb_reg_cache_entries: usize,
     /// number of registry source checkouts// @TODO clarify
     numb_reg_src_checkouts: usize,
+    /// number of distinct crates among the source archives
+    numb_reg_cache_crates: usize,
+    /// number of distinct crates among the source checkouts
+    numb_reg_src_crates: usize,
     /// root path of the cache
     root_path: &'a std::path::PathBuf,
+    /// the components that were scanned, the others are left out of the summary
+    selection: ComponentSelection,
+    /// files of the cargo home that do not belong to any component (config, credentials...)
+    other_files: OtherFiles,
 }
 
 impl<'a> DirSizes<'a> {
     /// create a new `DirSize` object by querying the caches for their data, done in parallel
-
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn new(
         bin_cache: &mut bin::BinaryCache,
         checkouts_cache: &mut git_checkouts::GitCheckoutCache,
@@ -71,6 +91,7 @@ impl<'a> DirSizes<'a> {
         registry_index_caches: &mut registry_index::RegistryIndicesCache,
         registry_sources_caches: &mut registry_sources::RegistrySourceCaches,
         ccd: &'a CargoCachePaths,
+        selection: &ComponentSelection,
     ) -> Self {
         let mut reg_index_size: Option<u64> = None;
         let mut bin_dir_size: Option<u64> = None;
@@ -83,6 +104,9 @@ impl<'a> DirSizes<'a> {
         let mut total_reg_cache_entries: Option<usize> = None;
         let mut total_reg_src_size: Option<u64> = None;
         let mut numb_reg_src_checkouts: Option<usize> = None;
+        let mut numb_reg_cache_crates: Option<usize> = None;
+        let mut numb_reg_src_crates: Option<usize> = None;
+        let mut other_files = OtherFiles::default();
 
         rayon::scope(|s| {
             // spawn one thread per cache
@@ -106,14 +130,24 @@ impl<'a> DirSizes<'a> {
             s.spawn(|_| {
                 total_reg_cache_size = Some(registry_pkg_cache.total_size());
                 total_reg_cache_entries = Some(registry_pkg_cache.total_number_of_files());
+                numb_reg_cache_crates = Some(registry_pkg_cache.number_of_unique_crates());
             });
 
             s.spawn(|_| {
                 total_reg_src_size = Some(registry_sources_caches.total_size());
                 numb_reg_src_checkouts = Some(registry_sources_caches.number_of_items());
+                numb_reg_src_crates = Some(registry_sources_caches.number_of_unique_crates());
             });
+
+            // other files are only of interest if we look at the entire cache
+            if !selection.is_restricted() {
+                s.spawn(|_| other_files = OtherFiles::new(ccd));
+            }
         });
 
+        // the next run only needs to scan what changed until then
+        size_cache::save();
+
         let root_path = &ccd.cargo_home;
         let total_reg_size =
             total_reg_cache_size.unwrap() + total_reg_src_size.unwrap() + reg_index_size.unwrap();
@@ -121,7 +155,8 @@ impl<'a> DirSizes<'a> {
 
         let total_bin_size = bin_dir_size.unwrap();
 
-        let total_size = total_reg_size + total_git_db_size + total_bin_size;
+        let total_size =
+            total_reg_size + total_git_db_size + total_bin_size + other_files.total_size();
         Self {
             total_size,                    // total size of cargo root dir
             numb_bins: numb_bins.unwrap(), // number of binaries found
@@ -136,9 +171,14 @@ impl<'a> DirSizes<'a> {
             total_reg_src_size: total_reg_src_size.unwrap(), // registry sources size
             total_reg_index_size: reg_index_size.unwrap(), // registry index size
             total_reg_index_num: registry_index_caches.number_of_subcaches() as u64, // number  of indices //@TODO parallelize like the rest
+            numb_reg_index_sparse: registry_index_caches.sparse_indices().len(), // number of sparse indices
             numb_reg_cache_entries: total_reg_cache_entries.unwrap(), // number of source archives
             numb_reg_src_checkouts: numb_reg_src_checkouts.unwrap(),  // number of source checkouts
+            numb_reg_cache_crates: numb_reg_cache_crates.unwrap(), // number of crates in the archives
+            numb_reg_src_crates: numb_reg_src_crates.unwrap(), // number of crates in the checkouts
             root_path,
+            selection: selection.clone(),
+            other_files,
         }
     }
 
@@ -187,209 +227,397 @@ impl<'a> DirSizes<'a> {
     pub(crate) fn numb_reg_src_checkouts(&self) -> usize {
         self.numb_reg_src_checkouts
     }
+    pub(crate) fn numb_reg_cache_crates(&self) -> usize {
+        self.numb_reg_cache_crates
+    }
+    pub(crate) fn numb_reg_src_crates(&self) -> usize {
+        self.numb_reg_src_crates
+    }
     pub(crate) fn root_path(&self) -> &'a std::path::PathBuf {
         self.root_path
     }
+    pub(crate) fn selection(&self) -> &ComponentSelection {
+        &self.selection
+    }
+    pub(crate) fn other_files(&self) -> &OtherFiles {
+        &self.other_files
+    }
 }
 
-impl<'a> DirSizes<'a> {
+/// the sizes of one registry, the parts that were not found are None
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct RegistrySizes {
+    /// "crates.io", without the hash
+    name: String,
+    /// "index.crates.io-6f17d22bba15001f"
+    dir_name: String,
+    /// the name of the registry in the cargo config ("company-internal"), if we can tell
+    configured_name: Option<&'static str>,
+    index_size: Option<u64>,
+    /// number and size of the .crate archives
+    archives: Option<(usize, u64)>,
+    /// number and size of the extracted sources
+    sources: Option<(usize, u64)>,
+    /// neither crate archives nor sources are left
+    empty: bool,
+}
+
+impl RegistrySizes {
+    fn total_size(&self) -> u64 {
+        self.index_size.unwrap_or_default()
+            + self.archives.map_or(0, |(_, size)| size)
+            + self.sources.map_or(0, |(_, size)| size)
+    }
+}
+
+impl DirSizes<'_> {
     /// returns the header of the summary which contains the path to the cache and its total size
     fn header(&self) -> Vec<TableLine> {
         vec![
+            TableLine::new(0, &format!("{}\n\n", self.header_line()), &String::new()),
             TableLine::new(
                 0,
-                &format!("Cargo cache '{}':\n\n", &self.root_path().display()),
-                &String::new(),
-            ),
-            TableLine::new(
-                0,
-                &"Total: ".to_string(),
+                &label(Message::SummaryTotal, &[]),
                 &self.total_size().format_size(DECIMAL),
             ),
         ]
     }
 
+    /// "Cargo cache '/home/user/.cargo':"
+    fn header_line(&self) -> String {
+        messages::fill(
+            Message::SummaryHeader,
+            &[
+                ("path", &hyperlink::path(self.root_path())),
+                ("components", &self.selection_note()),
+            ],
+        )
+    }
+
+    /// mentions the selected components if not everything was scanned
+    fn selection_note(&self) -> String {
+        if self.selection.is_restricted() {
+            format!(
+                " {}",
+                messages::fill(
+                    Message::SummaryComponents,
+                    &[("components", &self.selection)]
+                )
+            )
+        } else {
+            String::new()
+        }
+    }
+
+    /// which of the lines of `git()` belong to selected components
+    fn git_lines_selected(&self) -> [bool; 3] {
+        let bare_repos = self.selection.contains(&Component::GitDB);
+        let checkouts = self.selection.contains(&Component::GitRepos);
+        [bare_repos || checkouts, bare_repos, checkouts]
+    }
+
+    /// which of the lines of `registries_summary()` belong to selected components
+    /// the number of distinct crates is only shown if there are any
+    fn registry_lines_selected(&self) -> [bool; 6] {
+        let index = self.selection.contains(&Component::RegistryIndex);
+        let archives = self.selection.contains(&Component::RegistryCrateCache);
+        let sources = self.selection.contains(&Component::RegistrySources);
+        [
+            index || archives || sources,
+            index,
+            archives,
+            archives && self.numb_reg_cache_crates() > 0,
+            sources,
+            sources && self.numb_reg_src_crates() > 0,
+        ]
+    }
+
     /// returns amount and size of installed crate binaries
     fn bin(&self) -> Vec<TableLine> {
+        if !self.selection.includes_binaries() {
+            return Vec::new();
+        }
         vec![TableLine::new(
             1,
-            &format!("{} installed binaries: ", self.numb_bins()),
+            &label(
+                Message::SummaryInstalledBinaries,
+                &[("count", &self.numb_bins())],
+            ),
             &self.total_bin_size().format_size(DECIMAL),
         )]
     }
 
     /// returns amount and size of bare git repos and git repo checkouts
     fn git(&self) -> Vec<TableLine> {
-        vec![
+        let lines = vec![
             TableLine::new(
                 1,
-                &"Git db: ".to_string(),
+                &label(Message::SummaryGitDb, &[]),
                 &self.total_git_db_size().format_size(DECIMAL),
             ),
             TableLine::new(
                 2,
-                &format!("{} bare git repos: ", self.numb_git_repos_bare_repos()),
+                &label(
+                    Message::SummaryBareGitRepos,
+                    &[("count", &self.numb_git_repos_bare_repos())],
+                ),
                 &self.total_git_repos_bare_size().format_size(DECIMAL),
             ),
             TableLine::new(
                 2,
-                &format!("{} git repo checkouts: ", self.numb_git_checkouts()),
+                &label(
+                    Message::SummaryGitCheckouts,
+                    &[("count", &self.numb_git_checkouts())],
+                ),
                 &self.total_git_chk_size().format_size(DECIMAL),
             ),
-        ]
+        ];
+        select_lines(lines, &self.git_lines_selected())
+    }
+
+    /// returns amount and size of files that are not part of any cache component
+    /// lists the files of the cargo home by name (never their content), files in subdirectories are summed up
+    fn other(&self) -> Vec<TableLine> {
+        let other_files = self.other_files();
+        if other_files.number_of_files() == 0 {
+            return Vec::new();
+        }
+
+        let mut lines = vec![TableLine::new(
+            1,
+            &label(
+                Message::SummaryOtherFiles,
+                &[("count", &other_files.number_of_files())],
+            ),
+            &other_files.total_size().format_size(DECIMAL),
+        )];
+        lines.extend(other_files.top_level().iter().map(|(name, size)| {
+            TableLine::new(2, &format!("{name}: "), &size.format_size(DECIMAL))
+        }));
+        if other_files.numb_nested() > 0 {
+            lines.push(TableLine::new(
+                2,
+                &label(
+                    Message::SummaryOtherDirectories,
+                    &[("count", &other_files.numb_nested())],
+                ),
+                &other_files.nested_size().format_size(DECIMAL),
+            ));
+        }
+        lines
     }
 
     /// returns summary of sizes of registry indices and registries (both, .crate archives and the extracted sources)
     fn registries_summary(&self) -> Vec<TableLine> {
         let tl1 = TableLine::new(
             1,
-            &"Registry: ".to_string(),
+            &label(Message::SummaryRegistry, &[]),
             &self.total_reg_size().format_size(DECIMAL),
         );
 
         let left = if let 1 = self.total_reg_index_num {
-            String::from("Registry index: ")
+            label(Message::SummaryRegistryIndex, &[])
         } else {
-            format!("{} registry indices: ", &self.total_reg_index_num())
+            label(
+                Message::SummaryRegistryIndices,
+                &[("count", &self.total_reg_index_num())],
+            )
         };
         let tl2 = TableLine::new(2, &left, &self.total_reg_index_size().format_size(DECIMAL));
 
         let tl3 = TableLine::new(
             2,
-            &format!("{} crate archives: ", self.numb_reg_cache_entries()),
+            &label(
+                Message::SummaryCrateArchives,
+                &[("count", &self.numb_reg_cache_entries())],
+            ),
             &self.total_reg_cache_size().format_size(DECIMAL),
         );
 
+        let crates_and_versions = TableLine::new(
+            3,
+            &messages::fill(
+                Message::SummaryCratesAndVersions,
+                &[
+                    ("crates", &self.numb_reg_cache_crates()),
+                    ("versions", &self.numb_reg_cache_entries()),
+                ],
+            ),
+            &String::new(),
+        );
+
         let tl4 = TableLine::new(
             2,
-            &format!("{} crate source checkouts: ", self.numb_reg_src_checkouts()),
+            &label(
+                Message::SummaryCrateSources,
+                &[("count", &self.numb_reg_src_checkouts())],
+            ),
             &self.total_reg_src_size().format_size(DECIMAL),
         );
 
-        vec![tl1, tl2, tl3, tl4]
+        let src_crates_and_versions = TableLine::new(
+            3,
+            &messages::fill(
+                Message::SummaryCratesAndVersions,
+                &[
+                    ("crates", &self.numb_reg_src_crates()),
+                    ("versions", &self.numb_reg_src_checkouts()),
+                ],
+            ),
+            &String::new(),
+        );
+
+        select_lines(
+            vec![
+                tl1,
+                tl2,
+                tl3,
+                crates_and_versions,
+                tl4,
+                src_crates_and_versions,
+            ],
+            &self.registry_lines_selected(),
+        )
     }
 
-    /// returns more detailed summary about each registry
-    fn registries_seperate(
+    /// the sizes of each registry, matched across the index, the crate archives and the sources
+    fn registry_sizes(
         &self,
         index_caches: &mut registry_index::RegistryIndicesCache,
         registry_sources: &mut registry_sources::RegistrySourceCaches,
         pkg_caches: &mut registry_pkg_cache::RegistryPkgCaches,
-    ) -> Vec<TableLine> {
-        let mut v: Vec<TableLine> = vec![];
+    ) -> Vec<RegistrySizes> {
+        let dir_name = |path: &std::path::Path| -> String { names::file_name(path).into_owned() };
 
         // we need to match the separate registries together somehow
         // do this by folder names
         let mut registries: Vec<String> = vec![];
         index_caches.caches().iter().for_each(|registry| {
-            registries.push(
-                registry
-                    .path()
-                    .file_name()
-                    .unwrap()
-                    .to_str()
-                    .unwrap()
-                    .to_string(),
-            );
+            registries.push(dir_name(registry.path()));
         });
-
         pkg_caches.caches().iter().for_each(|registry| {
-            registries.push(
-                registry
-                    .path()
-                    .file_name()
-                    .unwrap()
-                    .to_str()
-                    .unwrap()
-                    .to_string(),
-            );
+            registries.push(dir_name(registry.path()));
         });
-
         registry_sources.caches().iter().for_each(|registry| {
-            registries.push(
-                registry
-                    .path()
-                    .file_name()
-                    .unwrap()
-                    .to_str()
-                    .unwrap()
-                    .to_string(),
-            );
+            registries.push(dir_name(registry.path()));
         });
         // we now collected all the folder names of the registries and can match a single registry across multiple
         // caches by this
+        registries.sort();
+        registries.dedup();
 
+        registries
+            .into_iter()
+            .map(|registry| {
+                let mut sizes = RegistrySizes::default();
+                let mut registry_name: Option<String> = None;
+                // files of the crate archives and sources, a registry without any is removed by
+                // --delete-empty-registries
+                let mut crate_files = 0;
+
+                if let Some(index) = index_caches
+                    .caches()
+                    .iter_mut()
+                    .find(|r| dir_name(r.path()) == registry)
+                {
+                    sizes.index_size = Some(index.total_size());
+                    registry_name = Some(index.name().into());
+                }
+
+                if let Some(pkg_cache) = pkg_caches
+                    .caches()
+                    .iter_mut()
+                    .find(|p| dir_name(p.path()) == registry)
+                {
+                    sizes.archives = Some((pkg_cache.number_of_files(), pkg_cache.total_size()));
+                    crate_files += pkg_cache.number_of_files();
+                    if registry_name.is_none() {
+                        registry_name = Some(pkg_cache.name().into());
+                    }
+                }
+
+                if let Some(registry_source) = registry_sources
+                    .caches()
+                    .iter_mut()
+                    .find(|s| dir_name(s.path()) == registry)
+                {
+                    sizes.sources = Some((
+                        registry_source.number_of_items(),
+                        registry_source.total_size(),
+                    ));
+                    crate_files += registry_source.number_of_files();
+                    if registry_name.is_none() {
+                        registry_name = Some(registry_source.name().into());
+                    }
+                }
+
+                // we only know that a registry is empty if its archives and sources were scanned
+                sizes.empty = crate_files == 0
+                    && self.selection.contains(&Component::RegistryCrateCache)
+                    && self.selection.contains(&Component::RegistrySources);
+                sizes.name = registry_name.unwrap_or_default();
+                sizes.configured_name = registry_names::name_of(&registry);
+                sizes.dir_name = registry;
+                sizes
+            })
+            .collect()
+    }
+
+    /// returns more detailed summary about each registry
+    fn registries_seperate(
+        &self,
+        index_caches: &mut registry_index::RegistryIndicesCache,
+        registry_sources: &mut registry_sources::RegistrySourceCaches,
+        pkg_caches: &mut registry_pkg_cache::RegistryPkgCaches,
+    ) -> Vec<TableLine> {
         /*
           Registry:                         1.52 GB
             5 registry indices:           250.20 MB
             5399 crate archives:          805.46 MB
             901 crate source checkouts:   460.77 MB
         */
-
-        registries.sort();
-        registries.dedup();
-
-        for registry in &registries {
-            let mut total_size = 0;
-
-            let mut temp_vec: Vec<TableLine> = Vec::new();
-            let mut registry_name: Option<String> = None;
-
-            for index in index_caches.caches().iter_mut().filter(|r| {
-                &r.path().file_name().unwrap().to_str().unwrap().to_string() == registry
-            }) {
-                temp_vec.push(TableLine::new(
+        let mut v: Vec<TableLine> = vec![];
+        for registry in self.registry_sizes(index_caches, registry_sources, pkg_caches) {
+            v.push(TableLine::new(
+                1,
+                &format!(
+                    "{}: {}{}{}",
+                    messages::text(Message::SummaryRegistry),
+                    registry.name,
+                    registry
+                        .configured_name
+                        .map(|name| format!(" ({name})"))
+                        .unwrap_or_default(),
+                    if registry.empty {
+                        format!(" {}", messages::text(Message::SummaryEmptyRegistry))
+                    } else {
+                        String::new()
+                    }
+                ),
+                &registry.total_size().format_size(DECIMAL),
+            ));
+            if let Some(index_size) = registry.index_size {
+                v.push(TableLine::new(
                     2,
-                    &String::from("Registry index:"),
-                    &index.total_size().format_size(DECIMAL),
+                    &format!("{}:", messages::text(Message::SummaryRegistryIndex)),
+                    &index_size.format_size(DECIMAL),
                 ));
-                total_size += index.total_size();
-                if registry_name.is_none() {
-                    registry_name = Some(index.name().into());
-                }
             }
-
-            for pkg_cache in pkg_caches.caches().iter_mut().filter(|p| {
-                &p.path().file_name().unwrap().to_str().unwrap().to_string() == registry
-            }) {
-                temp_vec.push(TableLine::new(
+            if let Some((number, size)) = registry.archives {
+                v.push(TableLine::new(
                     2,
-                    &format!("{} crate archives: ", pkg_cache.number_of_files()),
-                    &pkg_cache.total_size().format_size(DECIMAL),
+                    &label(Message::SummaryCrateArchives, &[("count", &number)]),
+                    &size.format_size(DECIMAL),
                 ));
-                total_size += pkg_cache.total_size();
-                if registry_name.is_none() {
-                    registry_name = Some(pkg_cache.name().into());
-                }
             }
-
-            for registry_source in registry_sources.caches().iter_mut().filter(|s| {
-                &s.path().file_name().unwrap().to_str().unwrap().to_string() == registry
-            }) {
-                temp_vec.push(TableLine::new(
+            if let Some((number, size)) = registry.sources {
+                v.push(TableLine::new(
                     2,
-                    &format!(
-                        "{} crate source checkouts: ",
-                        registry_source.number_of_items()
-                    ),
-                    &registry_source.total_size().format_size(DECIMAL),
+                    &label(Message::SummaryCrateSources, &[("count", &number)]),
+                    &size.format_size(DECIMAL),
                 ));
-                total_size += registry_source.total_size();
-                if registry_name.is_none() {
-                    registry_name = Some(registry_source.name().into());
-                }
             }
-
-            let header_line = TableLine::new(
-                1,
-                &format!("Registry: {}", registry_name.unwrap_or_default()),
-                &total_size.format_size(DECIMAL),
-            );
-
-            v.push(header_line);
-            v.extend(temp_vec);
         }
-
         v
     } // registries separate
 
@@ -407,14 +635,10 @@ impl<'a> DirSizes<'a> {
         // Total:           x Mb => y MB
         fn cmp_total(old: &DirSizes<'_>, new: &DirSizes<'_>) -> Vec<TableLine> {
             vec![
+                TableLine::new(0, &format!("{}\n\n", old.header_line()), &String::new()),
                 TableLine::new(
                     0,
-                    &format!("Cargo cache '{}':\n\n", &old.root_path().display()),
-                    &String::new(),
-                ),
-                TableLine::new(
-                    0,
-                    &"Total: ".to_string(),
+                    &label(Message::SummaryTotal, &[]),
                     &if old.total_size() == new.total_size() {
                         old.total_size().format_size(DECIMAL)
                     } else {
@@ -433,7 +657,7 @@ impl<'a> DirSizes<'a> {
             vec![
                 TableLine::new(
                     1,
-                    &"Git db: ".to_string(),
+                    &label(Message::SummaryGitDb, &[]),
                     &if old.total_git_db_size() == new.total_git_db_size() {
                         new.total_git_db_size().format_size(DECIMAL)
                     } else {
@@ -446,15 +670,16 @@ impl<'a> DirSizes<'a> {
                 ),
                 TableLine::new(
                     2,
-                    &if old.numb_git_repos_bare_repos() == new.numb_git_repos_bare_repos() {
-                        format!("{} bare git repos:", new.numb_git_repos_bare_repos())
-                    } else {
-                        format!(
-                            "{} => {} bare git repos: ",
-                            &old.numb_git_repos_bare_repos(),
-                            &new.numb_git_repos_bare_repos()
-                        )
-                    },
+                    &label(
+                        Message::SummaryBareGitRepos,
+                        &[(
+                            "count",
+                            &count_change(
+                                old.numb_git_repos_bare_repos(),
+                                new.numb_git_repos_bare_repos(),
+                            ),
+                        )],
+                    ),
                     &if old.total_git_repos_bare_size() == new.total_git_repos_bare_size() {
                         new.total_git_repos_bare_size().format_size(DECIMAL)
                     } else {
@@ -467,15 +692,13 @@ impl<'a> DirSizes<'a> {
                 ),
                 TableLine::new(
                     2,
-                    &if old.numb_git_checkouts() == new.numb_git_checkouts() {
-                        format!("{} git repo checkouts: ", new.numb_git_checkouts())
-                    } else {
-                        format!(
-                            "{} => {} git repo checkouts: ",
-                            &old.numb_git_checkouts(),
-                            &new.numb_git_checkouts()
-                        )
-                    },
+                    &label(
+                        Message::SummaryGitCheckouts,
+                        &[(
+                            "count",
+                            &count_change(old.numb_git_checkouts(), new.numb_git_checkouts()),
+                        )],
+                    ),
                     &if old.total_git_chk_size() == new.total_git_chk_size() {
                         new.total_git_chk_size().format_size(DECIMAL)
                     } else {
@@ -492,7 +715,7 @@ impl<'a> DirSizes<'a> {
         fn regs(old: &DirSizes<'_>, new: &DirSizes<'_>) -> Vec<TableLine> {
             let tl1 = TableLine::new(
                 1,
-                &"Registry: ".to_string(),
+                &label(Message::SummaryRegistry, &[]),
                 &if old.total_reg_size() == new.total_reg_size() {
                     new.total_reg_size().format_size(DECIMAL)
                 } else {
@@ -507,9 +730,12 @@ impl<'a> DirSizes<'a> {
             let tl2 = TableLine::new(
                 2,
                 &if let 1 = &old.total_reg_index_num {
-                    String::from("Registry index: ")
+                    label(Message::SummaryRegistryIndex, &[])
                 } else {
-                    format!("{} registry indices: ", &old.total_reg_index_num())
+                    label(
+                        Message::SummaryRegistryIndices,
+                        &[("count", &old.total_reg_index_num())],
+                    )
                 },
                 &if old.total_reg_index_size() == new.total_reg_index_size() {
                     old.total_reg_index_size().format_size(DECIMAL)
@@ -524,15 +750,13 @@ impl<'a> DirSizes<'a> {
 
             let tl3 = TableLine::new(
                 2,
-                &if old.numb_reg_cache_entries() == new.numb_reg_cache_entries() {
-                    format!("{} crate archives: ", new.numb_reg_cache_entries())
-                } else {
-                    format!(
-                        "{} => {} crate archives: ",
-                        &old.numb_reg_cache_entries(),
-                        &new.numb_reg_cache_entries()
-                    )
-                },
+                &label(
+                    Message::SummaryCrateArchives,
+                    &[(
+                        "count",
+                        &count_change(old.numb_reg_cache_entries(), new.numb_reg_cache_entries()),
+                    )],
+                ),
                 &if old.total_reg_cache_size() == new.total_reg_cache_size() {
                     new.total_reg_cache_size().format_size(DECIMAL)
                 } else {
@@ -546,15 +770,13 @@ impl<'a> DirSizes<'a> {
 
             let tl4 = TableLine::new(
                 2,
-                &if old.numb_reg_src_checkouts() == new.numb_reg_src_checkouts() {
-                    format!("{} crate source checkouts: ", new.numb_reg_src_checkouts())
-                } else {
-                    format!(
-                        "{} => {} crate source checkouts: ",
-                        &old.numb_reg_src_checkouts(),
-                        &new.numb_reg_src_checkouts()
-                    )
-                },
+                &label(
+                    Message::SummaryCrateSources,
+                    &[(
+                        "count",
+                        &count_change(old.numb_reg_src_checkouts(), new.numb_reg_src_checkouts()),
+                    )],
+                ),
                 &if old.total_reg_src_size() == new.total_reg_src_size() {
                     old.total_reg_src_size().format_size(DECIMAL)
                 } else {
@@ -569,6 +791,9 @@ impl<'a> DirSizes<'a> {
             vec![tl1, tl2, tl3, tl4]
         } // fn regs()
 
+        // a scan of the cache would stop right away, we can not tell how much was removed
+        cancel::exit_if_cancelled("stopped removing, not everything was removed");
+
         // and requery it to let it do its thing
         let cache_sizes_new = DirSizes::new(
             bin_cache,
@@ -578,18 +803,26 @@ impl<'a> DirSizes<'a> {
             registry_index_caches,
             registry_sources_caches,
             cargo_cache,
+            cache_sizes_old.selection(),
         );
 
         let mut v = Vec::new();
         v.extend(cmp_total(cache_sizes_old, &cache_sizes_new));
         v.extend(cache_sizes_new.bin());
-        v.extend(regs(cache_sizes_old, &cache_sizes_new));
-        v.extend(git(cache_sizes_old, &cache_sizes_new));
+        v.extend(select_lines(
+            regs(cache_sizes_old, &cache_sizes_new),
+            &cache_sizes_new.registry_lines_selected(),
+        ));
+        v.extend(select_lines(
+            git(cache_sizes_old, &cache_sizes_new),
+            &cache_sizes_new.git_lines_selected(),
+        ));
 
         let mut summary = two_row_table(3, v, false);
 
         let total_size_old = cache_sizes_old.total_size();
         let total_size_new = cache_sizes_new.total_size();
+        history::record_freed(total_size_old.saturating_sub(total_size_new));
 
         // only show final summary if something changed
         if total_size_old != total_size_new {
@@ -607,7 +840,31 @@ impl<'a> DirSizes<'a> {
     }
 } // print_size_difference()
 
-impl<'a> fmt::Display for DirSizes<'a> {
+/// the left column of a summary line: "12 installed binaries: "
+fn label(message: Message, values: &[(&str, &dyn fmt::Display)]) -> String {
+    format!("{}: ", messages::fill(message, values))
+}
+
+/// "5" if the number did not change, "5 => 3" if it did
+fn count_change(old: usize, new: usize) -> String {
+    if old == new {
+        new.to_string()
+    } else {
+        format!("{old} => {new}")
+    }
+}
+
+/// only keep the lines that are marked as selected
+fn select_lines(lines: Vec<TableLine>, selected: &[bool]) -> Vec<TableLine> {
+    lines
+        .into_iter()
+        .zip(selected)
+        .filter(|(_, selected)| **selected)
+        .map(|(line, _)| line)
+        .collect()
+}
+
+impl fmt::Display for DirSizes<'_> {
     /// returns the default summary of cargo-cache (cmd: "cargo cache")
     fn fmt(&self, f: &'_ mut fmt::Formatter<'_>) -> fmt::Result {
         let mut table: Vec<TableLine> = vec![];
@@ -615,6 +872,7 @@ impl<'a> fmt::Display for DirSizes<'a> {
         table.extend(self.bin());
         table.extend(self.registries_summary());
         table.extend(self.git());
+        table.extend(self.other());
 
         let string: String = two_row_table(2, table, false);
 
@@ -623,6 +881,148 @@ impl<'a> fmt::Display for DirSizes<'a> {
     }
 }
 
+#[cfg(feature = "json")]
+impl DirSizes<'_> {
+    /// the default summary as json, only the selected components are included and sizes are in bytes
+    pub(crate) fn to_json(&self) -> serde_json::Value {
+        use serde_json::{json, Map, Value};
+
+        let mut summary = Map::new();
+        let _ = summary.insert(
+            "cargo_home".into(),
+            json!(self.root_path().display().to_string()),
+        );
+        if self.selection.is_restricted() {
+            let _ = summary.insert("components".into(), json!(self.selection.to_string()));
+        }
+        let _ = summary.insert("total_size".into(), json!(self.total_size()));
+
+        if self.selection.includes_binaries() {
+            let _ = summary.insert(
+                "binaries".into(),
+                json!({ "count": self.numb_bins(), "size": self.total_bin_size() }),
+            );
+        }
+
+        let [registry_selected, index, archives, _, sources, _] = self.registry_lines_selected();
+        if registry_selected {
+            let mut registry = Map::new();
+            let _ = registry.insert("size".into(), json!(self.total_reg_size()));
+            if index {
+                let _ = registry.insert(
+                    "indices".into(),
+                    json!({
+                        "count": self.total_reg_index_num(),
+                        "sparse": self.numb_reg_index_sparse,
+                        "size": self.total_reg_index_size(),
+                    }),
+                );
+            }
+            if archives {
+                let _ = registry.insert(
+                    "crate_archives".into(),
+                    json!({
+                        "count": self.numb_reg_cache_entries(),
+                        "crates": self.numb_reg_cache_crates(),
+                        "size": self.total_reg_cache_size(),
+                    }),
+                );
+            }
+            if sources {
+                let _ = registry.insert(
+                    "crate_sources".into(),
+                    json!({
+                        "count": self.numb_reg_src_checkouts(),
+                        "crates": self.numb_reg_src_crates(),
+                        "size": self.total_reg_src_size(),
+                    }),
+                );
+            }
+            let _ = summary.insert("registry".into(), Value::Object(registry));
+        }
+
+        let [git_selected, bare_repos, checkouts] = self.git_lines_selected();
+        if git_selected {
+            let mut git = Map::new();
+            let _ = git.insert("size".into(), json!(self.total_git_db_size()));
+            if bare_repos {
+                let _ = git.insert(
+                    "bare_repos".into(),
+                    json!({
+                        "count": self.numb_git_repos_bare_repos(),
+                        "size": self.total_git_repos_bare_size(),
+                    }),
+                );
+            }
+            if checkouts {
+                let _ = git.insert(
+                    "checkouts".into(),
+                    json!({
+                        "count": self.numb_git_checkouts(),
+                        "size": self.total_git_chk_size(),
+                    }),
+                );
+            }
+            let _ = summary.insert("git".into(), Value::Object(git));
+        }
+
+        let other_files = self.other_files();
+        if other_files.number_of_files() > 0 {
+            let _ = summary.insert(
+                "other_files".into(),
+                json!({
+                    "count": other_files.number_of_files(),
+                    "size": other_files.total_size(),
+                    "files": other_files
+                        .top_level()
+                        .iter()
+                        .map(|(name, size)| json!({ "name": name, "size": size }))
+                        .collect::<Vec<Value>>(),
+                }),
+            );
+        }
+
+        Value::Object(summary)
+    }
+}
+
+/// the summary with details on each registry as json (cmd: "cargo cache registry --output-format json")
+#[cfg(feature = "json")]
+pub(crate) fn per_registry_json(
+    dir_size: &DirSizes<'_>,
+    index_caches: &mut registry_index::RegistryIndicesCache,
+    pkg_caches: &mut registry_sources::RegistrySourceCaches,
+    registry_sources: &mut registry_pkg_cache::RegistryPkgCaches,
+) -> serde_json::Value {
+    use serde_json::{json, Value};
+
+    let registries = dir_size
+        .registry_sizes(index_caches, pkg_caches, registry_sources)
+        .into_iter()
+        .map(|registry| {
+            let count_and_size = |part: Option<(usize, u64)>| {
+                part.map(|(count, size)| json!({ "count": count, "size": size }))
+            };
+            json!({
+                "name": registry.name,
+                "configured_name": registry.configured_name,
+                "directory": registry.dir_name,
+                "size": registry.total_size(),
+                "empty": registry.empty,
+                "index_size": registry.index_size,
+                "crate_archives": count_and_size(registry.archives),
+                "crate_sources": count_and_size(registry.sources),
+            })
+        })
+        .collect::<Vec<Value>>();
+
+    let mut summary = dir_size.to_json();
+    if let Value::Object(map) = &mut summary {
+        let _ = map.insert("registries".into(), Value::Array(registries));
+    }
+    summary
+}
+
 /// returns a summary with details on each registry (cmd: "cargo cache registry")
 pub(crate) fn per_registry_summary(
     dir_size: &DirSizes<'_>,
@@ -635,6 +1035,7 @@ pub(crate) fn per_registry_summary(
     table.extend(dir_size.bin());
     table.extend(dir_size.registries_seperate(index_caches, pkg_caches, registry_sources));
     table.extend(dir_size.git());
+    table.extend(dir_size.other());
 
     two_row_table(2, table, false)
 }
@@ -687,10 +1088,15 @@ mod libtests {
 
                 total_reg_src_size: reg_src.dir_size,
                 numb_reg_src_checkouts: reg_src.file_number as usize,
+                numb_reg_cache_crates: 0,
+                numb_reg_src_crates: 0,
 
                 total_reg_index_size: reg_index.dir_size,
                 total_reg_index_num: 1,
+                numb_reg_index_sparse: 0,
                 root_path: path,
+                selection: ComponentSelection::default(),
+                other_files: OtherFiles::default(),
             }
         }
     }
@@ -929,6 +1335,131 @@ Total:                          0  B
 
         assert_eq!(output_is, output_should);
     }
+
+    #[allow(non_snake_case)]
+    #[test]
+    fn test_DirSizes_other_files() {
+        let dir_info = |dir_size, file_number| DirInfo {
+            dir_size,
+            file_number,
+        };
+        let pb = PathBuf::from("/home/user/.cargo");
+
+        let mut dirSizes = DirSizes::new_manually(
+            &dir_info(0, 0),
+            &dir_info(0, 0),
+            &dir_info(0, 0),
+            &dir_info(0, 0),
+            &dir_info(0, 0),
+            &dir_info(1000, 1),
+            &pb,
+        );
+        dirSizes.other_files = OtherFiles::new_manually(
+            vec![
+                (String::from("config.toml"), 300),
+                (String::from("credentials.toml"), 100),
+            ],
+            2,
+            600,
+        );
+        dirSizes.total_size += dirSizes.other_files.total_size();
+
+        let output_is = format!("{}", &dirSizes);
+
+        let output_should = "Cargo cache '/home/user/.cargo':
+
+Total:                            2 kB
+  0 installed binaries:           0  B
+  Registry:                       1 kB
+    Registry index:               1 kB
+    0 crate archives:             0  B
+    0 crate source checkouts:     0  B
+  Git db:                         0  B
+    0 bare git repos:             0  B
+    0 git repo checkouts:         0  B
+  4 other files:                  1 kB
+    config.toml:                300  B
+    credentials.toml:           100  B
+    2 in other directories:     600  B\n";
+
+        assert_eq!(output_is, output_should);
+    }
+
+    #[allow(non_snake_case)]
+    #[test]
+    fn test_DirSizes_selected_components() {
+        let dir_info = |dir_size, file_number| DirInfo {
+            dir_size,
+            file_number,
+        };
+        let pb = PathBuf::from("/home/user/.cargo");
+
+        // caches of components that were not selected are empty
+        let mut dirSizes = DirSizes::new_manually(
+            &dir_info(0, 0),
+            &dir_info(0, 0),
+            &dir_info(4000, 2),
+            &dir_info(0, 0),
+            &dir_info(0, 0),
+            &dir_info(1000, 1),
+            &pb,
+        );
+        dirSizes.selection =
+            ComponentSelection::from(vec![Component::GitRepos, Component::RegistryIndex]);
+
+        let output_is = format!("{}", &dirSizes);
+
+        let output_should =
+            "Cargo cache '/home/user/.cargo' (components: git-repos,registry-index):
+
+Total:                      5 kB
+  Registry:                 1 kB
+    Registry index:         1 kB
+  Git db:                   4 kB
+    2 git repo checkouts:   4 kB\n";
+
+        assert_eq!(output_is, output_should);
+    }
+
+    #[allow(non_snake_case)]
+    #[test]
+    fn test_DirSizes_unique_crates() {
+        let dir_info = |dir_size, file_number| DirInfo {
+            dir_size,
+            file_number,
+        };
+        let pb = PathBuf::from("/home/user/.cargo");
+
+        let mut dirSizes = DirSizes::new_manually(
+            &dir_info(0, 0),
+            &dir_info(0, 0),
+            &dir_info(0, 0),
+            &dir_info(3000, 30),
+            &dir_info(2000, 12),
+            &dir_info(1000, 1),
+            &pb,
+        );
+        dirSizes.numb_reg_cache_crates = 10;
+        dirSizes.numb_reg_src_crates = 9;
+
+        let output_is = format!("{}", &dirSizes);
+
+        let output_should = "Cargo cache '/home/user/.cargo':
+
+Total:                           6 kB
+  0 installed binaries:          0  B
+  Registry:                      6 kB
+    Registry index:              1 kB
+    30 crate archives:           3 kB
+      10 crates, 30 versions
+    12 crate source checkouts:   2 kB
+      9 crates, 12 versions
+  Git db:                        0  B
+    0 bare git repos:            0  B
+    0 git repo checkouts:        0  B\n";
+
+        assert_eq!(output_is, output_should);
+    }
 }
 
 #[cfg(all(test, feature = "bench"))]
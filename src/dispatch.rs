@@ -0,0 +1,996 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// the commands that work on the scanned cache
+// main() parses the arguments, sets up the state of the run (lock, .cargocacheignore, network...)
+// and scans the cache, everything after that happens here: each command has a handler that gets a
+// `Context` with the paths, the caches, where the output goes, the --dry-run flag and the report
+// of the run, and tells main() whether the run goes on with the size difference and the summary or
+// is over. Handlers never exit the process themselves, so the tests below drive them in-process
+// against temporary cargo homes.
+// The helpers the handlers call (removals, git, cargo metadata...) still print to stdout.
+
+use std::fmt;
+use std::io::Write;
+#[cfg(any(feature = "metadata", feature = "verify"))]
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use crate::args::parse;
+use crate::cache::caches::{Cache, RegistrySuperCache};
+use crate::cache::*;
+#[cfg(feature = "metadata")]
+use crate::clean_unref::clean_unref;
+use crate::cli::CargoCacheCommands;
+#[cfg(feature = "metadata")]
+use crate::commands::local;
+#[cfg(feature = "metrics")]
+use crate::commands::metrics;
+#[cfg(feature = "query")]
+use crate::commands::query;
+use crate::commands::{check, maintain, stats, trim};
+use crate::config;
+use crate::confirm::confirm_autoclean;
+use crate::date;
+use crate::dedupe::{dedupe, LinkKind};
+use crate::dirsizes::{self, DirSizes};
+use crate::empty_registries;
+use crate::env;
+#[cfg(feature = "git")]
+use crate::git::{git_fsck_everything, git_gc_everything};
+use crate::history;
+use crate::incremental;
+use crate::keep::{protect_kept_sources, KeepList};
+use crate::library::*;
+use crate::modified;
+use crate::preflight::preflight;
+use crate::rebuild_cost;
+use crate::registry_migration;
+use crate::remove::*;
+use crate::report::RunReport;
+use crate::suggestions;
+use crate::top_items_summary::*;
+#[cfg(feature = "verify")]
+use crate::verify;
+
+/// the caches of all components, components that were not selected get empty caches
+pub(crate) struct Caches {
+    pub(crate) bin: bin::BinaryCache,
+    pub(crate) checkouts: git_checkouts::GitCheckoutCache,
+    pub(crate) bare_repos: git_bare_repos::GitRepoCache,
+    pub(crate) registry_pkgs: registry_pkg_cache::RegistryPkgCaches,
+    pub(crate) registry_index: registry_index::RegistryIndicesCache,
+    pub(crate) registry_sources: registry_sources::RegistrySourceCaches,
+}
+
+impl Caches {
+    /// the caches of the selected components, nothing is read from disk until they are used
+    pub(crate) fn new(paths: &CargoCachePaths, selection: &ComponentSelection) -> Self {
+        Self {
+            bin: bin::BinaryCache::new(if selection.includes_binaries() {
+                paths.bin_dir.clone()
+            } else {
+                PathBuf::new()
+            }),
+            checkouts: git_checkouts::GitCheckoutCache::new(
+                selection.cache_path(&Component::GitRepos, &paths.git_checkouts),
+            ),
+            bare_repos: git_bare_repos::GitRepoCache::new(
+                selection.cache_path(&Component::GitDB, &paths.git_repos_bare),
+            ),
+            registry_pkgs: registry_pkg_cache::RegistryPkgCaches::new(
+                selection.cache_path(&Component::RegistryCrateCache, &paths.registry_pkg_cache),
+            ),
+            registry_index: registry_index::RegistryIndicesCache::new(
+                selection.cache_path(&Component::RegistryIndex, &paths.registry_index),
+            ),
+            registry_sources: registry_sources::RegistrySourceCaches::new(
+                selection.cache_path(&Component::RegistrySources, &paths.registry_sources),
+            ),
+        }
+    }
+
+    /// scan the whole cache and sum up the sizes of the components
+    pub(crate) fn dir_sizes<'a>(
+        &mut self,
+        paths: &'a CargoCachePaths,
+        selection: &ComponentSelection,
+    ) -> DirSizes<'a> {
+        DirSizes::new(
+            &mut self.bin,
+            &mut self.checkouts,
+            &mut self.bare_repos,
+            &mut self.registry_pkgs,
+            &mut self.registry_index,
+            &mut self.registry_sources,
+            paths,
+            selection,
+        )
+    }
+}
+
+/// everything a command needs to run
+pub(crate) struct Context<'a> {
+    pub(crate) paths: &'a CargoCachePaths,
+    pub(crate) selection: &'a ComponentSelection,
+    /// the crates that are never removed, --keep
+    pub(crate) keep: &'a KeepList,
+    pub(crate) caches: Caches,
+    /// the defaults of the config file
+    pub(crate) defaults: &'a config::Config,
+    /// where the handlers print to, stdout unless we are testing
+    pub(crate) out: &'a mut dyn Write,
+    /// --dry-run was passed to cargo-cache itself, the subcommands have their own flag
+    pub(crate) dry_run: bool,
+    pub(crate) output_format: OutputFormat,
+    /// what the commands did, printed at the end of the run
+    pub(crate) report: RunReport,
+}
+
+impl Context<'_> {
+    /// print to the output of the context, like `print!()` this panics if stdout went away
+    fn print(&mut self, text: impl fmt::Display) {
+        write!(self.out, "{text}").expect("failed printing to the output");
+    }
+
+    #[cfg(feature = "json")]
+    fn print_json(&mut self, value: &serde_json::Value) {
+        let json = serde_json::to_string_pretty(value).unwrap();
+        self.print(format_args!("{json}\n"));
+    }
+}
+
+/// how the run goes on after a command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Flow {
+    /// print the size difference and the summary
+    Continue,
+    /// the command printed everything, exit with this code
+    Exit(i32),
+}
+
+/// "maintain" decides what is due before the lock is taken and the cache is scanned
+pub(crate) type Maintenance = (maintain::Policy, maintain::State, maintain::Due);
+
+/// run the command on the scanned cache, `dir_sizes` are the sizes before the command ran
+pub(crate) fn run(
+    ctx: &mut Context<'_>,
+    command: &CargoCacheCommands<'_>,
+    dir_sizes: &DirSizes<'_>,
+    maintenance: Option<Maintenance>,
+) -> Result<Flow, Error> {
+    match *command {
+        CargoCacheCommands::Trim {
+            dry_run,
+            plan,
+            trim_limit,
+            keep_newer_than,
+            ref registries,
+        } => trim(ctx, dry_run, plan, trim_limit, keep_newer_than, registries),
+        #[cfg(feature = "metadata")]
+        CargoCacheCommands::CleanUnref {
+            dry_run,
+            manifest_path,
+            all_projects,
+            allow_net,
+        } => {
+            let res = clean_unref(
+                ctx.paths,
+                manifest_path,
+                all_projects,
+                allow_net,
+                &mut ctx.caches.checkouts,
+                &mut ctx.caches.bare_repos,
+                &mut ctx.caches.registry_pkgs,
+                &mut ctx.caches.registry_sources,
+                dry_run,
+                &mut ctx.report.size_changed,
+                ctx.selection,
+            );
+            ctx.report.record(res);
+            Ok(Flow::Continue)
+        }
+        CargoCacheCommands::TopCacheItems { limit } => Ok(top_cache_items(ctx, limit)),
+        #[cfg(feature = "query")]
+        CargoCacheCommands::Query {
+            query_config,
+            dry_run,
+        } => {
+            query::run_query(
+                query_config,
+                dry_run,
+                &mut ctx.caches.bin,
+                &mut ctx.caches.checkouts,
+                &mut ctx.caches.bare_repos,
+                &mut ctx.caches.registry_pkgs,
+                &mut ctx.caches.registry_sources,
+                &ctx.paths.cargo_home,
+            )?;
+            Ok(Flow::Exit(0))
+        }
+        #[cfg(feature = "metadata")]
+        CargoCacheCommands::Local {
+            clean_build_dir,
+            clean_stale_deps,
+            clean_stale_build_scripts,
+            dry_run,
+            recursive,
+            clean,
+            clean_release_only,
+            keep_recent,
+        } => {
+            let clean_targets = (clean || clean_release_only).then_some(local::CleanTargets {
+                release_only: clean_release_only,
+                keep_recent,
+            });
+            if let Some(root) = recursive {
+                local::recursive_subcmd(Path::new(root), clean_targets, dry_run)?;
+            } else {
+                local::local_subcmd(
+                    &ctx.paths.cargo_home,
+                    clean_build_dir,
+                    clean_stale_deps,
+                    clean_stale_build_scripts,
+                    clean_targets,
+                    dry_run,
+                )?;
+            }
+            Ok(Flow::Exit(0))
+        }
+        CargoCacheCommands::RemoveIfDate {
+            dry_run,
+            arg_younger,
+            arg_older,
+            dirs,
+        } => {
+            let res = date::remove_files_by_dates(
+                &mut ctx.caches.checkouts,
+                &mut ctx.caches.bare_repos,
+                &mut ctx.caches.registry_pkgs,
+                &mut ctx.caches.registry_sources,
+                arg_younger,
+                arg_older,
+                dry_run,
+                dirs,
+                ctx.keep,
+                &mut ctx.report.size_changed,
+            );
+            ctx.report.record(res);
+            Ok(Flow::Continue)
+        }
+        CargoCacheCommands::Stats { bandwidth, .. } => {
+            stats::value_stats(dir_sizes, bandwidth)?;
+            Ok(Flow::Exit(0))
+        }
+        CargoCacheCommands::Check { fail_if_above } => {
+            check::check_size_limits(dir_sizes, fail_if_above)?;
+            Ok(Flow::Exit(0))
+        }
+        #[cfg(feature = "metrics")]
+        CargoCacheCommands::Metrics {
+            push,
+            ref url,
+            label,
+            dry_run,
+        } => {
+            metrics::metrics(dir_sizes, push, url.as_deref(), label, dry_run)?;
+            Ok(Flow::Exit(0))
+        }
+        CargoCacheCommands::Info => {
+            info(ctx, dir_sizes);
+            Ok(Flow::Exit(0))
+        }
+        CargoCacheCommands::RemoveDir {
+            dry_run,
+            allow_cross_device,
+            keep_going,
+            dirs,
+        } => {
+            remove_dir(ctx, dirs, dry_run, allow_cross_device, keep_going);
+            Ok(Flow::Continue)
+        }
+        #[cfg(feature = "git")]
+        CargoCacheCommands::FSCKRepos => {
+            git_fsck_everything(
+                &ctx.paths.git_repos_bare,
+                &ctx.paths.registry_pkg_cache,
+                ctx.selection,
+            )?;
+            Ok(Flow::Exit(0))
+        }
+        #[cfg(feature = "git")]
+        CargoCacheCommands::GitGCRepos { dry_run } => {
+            gc(ctx, dry_run)?;
+            Ok(Flow::Continue)
+        }
+        CargoCacheCommands::AutoClean {
+            dry_run,
+            allow_cross_device,
+            keep_going,
+            yes,
+            preserve_modified,
+            keep_days,
+            ref registries,
+        } => {
+            let options = AutocleanOptions {
+                dry_run,
+                allow_cross_device,
+                keep_going,
+                yes,
+                preserve_modified,
+                keep_days,
+                registries,
+            };
+            autoclean(ctx, &options, false)?;
+            Ok(Flow::Continue)
+        }
+        #[cfg(feature = "git")]
+        CargoCacheCommands::AutoCleanExpensive {
+            dry_run,
+            allow_cross_device,
+            keep_going,
+            yes,
+            preserve_modified,
+            keep_days,
+            ref registries,
+        } => {
+            let options = AutocleanOptions {
+                dry_run,
+                allow_cross_device,
+                keep_going,
+                yes,
+                preserve_modified,
+                keep_days,
+                registries,
+            };
+            autoclean(ctx, &options, true)?;
+            Ok(Flow::Continue)
+        }
+        CargoCacheCommands::KeepDuplicateCrates {
+            dry_run,
+            limit,
+            keep_by,
+        } if ctx.selection.contains(&Component::RegistryCrateCache) => {
+            keep_duplicate_crates(ctx, dry_run, limit, keep_by);
+            Ok(Flow::Continue)
+        }
+        CargoCacheCommands::DeleteEmptyRegistries { dry_run } => {
+            empty_registries::delete_empty_registries(
+                ctx.paths,
+                ctx.selection,
+                dry_run,
+                &mut ctx.report.size_changed,
+                &mut ctx.caches.registry_index,
+                &mut ctx.caches.registry_pkgs,
+                &mut ctx.caches.registry_sources,
+            );
+            ctx.report.request_summary();
+            Ok(Flow::Continue)
+        }
+        CargoCacheCommands::MigrateRegistry { dry_run } => {
+            registry_migration::migrate_registries(
+                ctx.paths,
+                dry_run,
+                &mut ctx.report.size_changed,
+                &mut ctx.caches.registry_index,
+                &mut ctx.caches.registry_pkgs,
+                &mut ctx.caches.registry_sources,
+            );
+            ctx.report.request_summary();
+            Ok(Flow::Continue)
+        }
+        CargoCacheCommands::Dedupe { reflink, dry_run } => {
+            let kind = if reflink {
+                LinkKind::Reflink
+            } else {
+                LinkKind::Hardlink
+            };
+            dedupe(ctx.paths, kind, dry_run)?;
+            Ok(Flow::Continue)
+        }
+        CargoCacheCommands::Maintain {
+            dry_run,
+            allow_cross_device,
+            ..
+        } => {
+            // main() exits early if nothing is due
+            let maintenance = maintenance.expect("maintain runs without a maintenance policy");
+            maintain(ctx, dir_sizes, maintenance, dry_run, allow_cross_device)?;
+            Ok(Flow::Continue)
+        }
+        CargoCacheCommands::OnlyDryRun if !ctx.report.size_changed => {
+            eprintln!("Warning: there is nothing to be dry run!");
+            Ok(Flow::Continue)
+        }
+        #[cfg(feature = "verify")]
+        CargoCacheCommands::Verify {
+            clean_corrupted,
+            dry_run,
+            report,
+            content,
+            checksums,
+        } => verify(ctx, clean_corrupted, dry_run, report, content, checksums),
+        _ => Ok(Flow::Continue),
+    }
+}
+
+/// the end of a run: the size difference, the --stats and the summary the command asked for
+/// exits if one of the operations failed
+pub(crate) fn finish(
+    mut ctx: Context<'_>,
+    command: &CargoCacheCommands<'_>,
+    dir_sizes: &DirSizes<'_>,
+) -> Flow {
+    // the only place that prints how the size has changed
+    let report = std::mem::take(&mut ctx.report);
+    report.finish(
+        ctx.dry_run,
+        dir_sizes,
+        ctx.paths,
+        &mut ctx.caches.bin,
+        &mut ctx.caches.checkouts,
+        &mut ctx.caches.bare_repos,
+        &mut ctx.caches.registry_pkgs,
+        &mut ctx.caches.registry_index,
+        &mut ctx.caches.registry_sources,
+    );
+
+    // print the default summary
+    #[cfg(feature = "json")]
+    if ctx.output_format == OutputFormat::Json {
+        let json = if matches!(command, CargoCacheCommands::Registries) {
+            dirsizes::per_registry_json(
+                dir_sizes,
+                &mut ctx.caches.registry_index,
+                &mut ctx.caches.registry_sources,
+                &mut ctx.caches.registry_pkgs,
+            )
+        } else {
+            dir_sizes.to_json()
+        };
+        ctx.print_json(&json);
+        return Flow::Exit(0);
+    }
+    if matches!(command, CargoCacheCommands::Registries) {
+        // print per-registry summary
+        let output = dirsizes::per_registry_summary(
+            dir_sizes,
+            &mut ctx.caches.registry_index,
+            &mut ctx.caches.registry_sources,
+            &mut ctx.caches.registry_pkgs,
+        );
+        ctx.print(output);
+    } else if matches!(command, CargoCacheCommands::DefaultSummary) {
+        ctx.print(dir_sizes);
+        // registries cached in several directories by different cargo versions
+        if let Some(note) = registry_migration::duplicate_registries_note(ctx.paths) {
+            ctx.print(note);
+        }
+        if let Some(suggestions) =
+            suggestions::suggestions(dir_sizes, &mut ctx.caches.registry_pkgs)
+        {
+            ctx.print(suggestions);
+        }
+    }
+    Flow::Continue
+}
+
+fn trim(
+    ctx: &mut Context<'_>,
+    dry_run: bool,
+    plan: bool,
+    trim_limit: Option<&str>,
+    keep_newer_than: Option<&str>,
+    registries: &RegistryFilter,
+) -> Result<Flow, Error> {
+    if plan {
+        trim::trim_plan(
+            trim_limit,
+            keep_newer_than,
+            &mut ctx.caches.checkouts,
+            &mut ctx.caches.bare_repos,
+            &mut ctx.caches.registry_pkgs,
+            &mut ctx.caches.registry_sources,
+            registries,
+            ctx.keep,
+            ctx.output_format,
+        )?;
+        return Ok(Flow::Exit(0));
+    }
+    let res = trim::trim_cache(
+        trim_limit,
+        keep_newer_than,
+        &mut ctx.caches.checkouts,
+        &mut ctx.caches.bare_repos,
+        &mut ctx.caches.registry_pkgs,
+        &mut ctx.caches.registry_sources,
+        registries,
+        ctx.keep,
+        dry_run,
+        &mut ctx.report.size_changed,
+    );
+    ctx.report.record(res);
+    Ok(Flow::Continue)
+}
+
+fn top_cache_items(ctx: &mut Context<'_>, limit: u32) -> Flow {
+    #[cfg(feature = "json")]
+    if ctx.output_format == OutputFormat::Json {
+        let json = get_top_crates_json(
+            limit,
+            ctx.paths,
+            &mut ctx.caches.bin,
+            &mut ctx.caches.checkouts,
+            &mut ctx.caches.bare_repos,
+            &mut ctx.caches.registry_pkgs,
+            &mut ctx.caches.registry_index,
+            &mut ctx.caches.registry_sources,
+            ctx.selection,
+        );
+        ctx.print_json(&json);
+        return Flow::Exit(0);
+    }
+    if limit > 0 {
+        let top_crates = get_top_crates(
+            limit,
+            ctx.paths,
+            &mut ctx.caches.bin,
+            &mut ctx.caches.checkouts,
+            &mut ctx.caches.bare_repos,
+            &mut ctx.caches.registry_pkgs,
+            &mut ctx.caches.registry_index,
+            &mut ctx.caches.registry_sources,
+            ctx.selection,
+        );
+        ctx.print(format_args!("{top_crates}\n"));
+    }
+    Flow::Exit(0)
+}
+
+fn info(ctx: &mut Context<'_>, dir_sizes: &DirSizes<'_>) {
+    #[cfg(feature = "json")]
+    if ctx.output_format == OutputFormat::Json {
+        ctx.print_json(&get_info_json(ctx.paths, dir_sizes));
+        return;
+    }
+    let info = get_info(ctx.paths, dir_sizes);
+    ctx.print(format_args!("{info}\n"));
+}
+
+fn remove_dir(
+    ctx: &mut Context<'_>,
+    dirs: Option<&str>,
+    dry_run: bool,
+    allow_cross_device: bool,
+    keep_going: bool,
+) {
+    // CARGO_CACHE_MAX_AGE: only remove the items that were last used before that date
+    let max_age = env::value(env::MAX_AGE)
+        .map(|max_age| {
+            parse::date(&max_age)
+                .map_err(|error| Error::InvalidEnvVar(env::MAX_AGE, Box::new(error.into())))
+        })
+        .transpose();
+    let res = max_age.and_then(|max_age| {
+        remove_dir_via_cmdline(
+            dirs,
+            dry_run,
+            allow_cross_device,
+            keep_going,
+            ctx.paths,
+            &mut ctx.report.size_changed,
+            &mut ctx.caches.checkouts,
+            &mut ctx.caches.bare_repos,
+            &mut ctx.caches.registry_index,
+            &mut ctx.caches.registry_pkgs,
+            &mut ctx.caches.registry_sources,
+            ctx.selection,
+            max_age,
+            ctx.keep,
+        )
+    });
+    ctx.report.record(res);
+}
+
+/// --gc, also part of --autoclean-expensive and maintain
+#[cfg(feature = "git")]
+fn gc(ctx: &mut Context<'_>, dry_run: bool) -> Result<(), Error> {
+    let res = git_gc_everything(
+        &ctx.paths.git_repos_bare,
+        &ctx.paths.registry_pkg_cache,
+        dry_run,
+        ctx.selection,
+    );
+    if !dry_run {
+        ctx.caches.bare_repos.invalidate();
+        ctx.caches.registry_index.invalidate();
+        ctx.report.size_changed = true;
+    }
+    res
+}
+
+/// the flags of --autoclean and --autoclean-expensive
+#[allow(clippy::struct_excessive_bools)] // one per flag of the subcommand
+struct AutocleanOptions<'a> {
+    dry_run: bool,
+    allow_cross_device: bool,
+    keep_going: bool,
+    yes: bool,
+    preserve_modified: bool,
+    keep_days: u64,
+    registries: &'a RegistryFilter,
+}
+
+/// --autoclean, --autoclean-expensive recompresses the git repos first
+fn autoclean(
+    ctx: &mut Context<'_>,
+    options: &AutocleanOptions<'_>,
+    expensive: bool,
+) -> Result<(), Error> {
+    let dry_run = options.dry_run;
+    // clean the registry sources and git checkouts
+    let dirs = autoclean_dirs(
+        ctx.paths,
+        ctx.selection,
+        options.registries,
+        &mut ctx.caches.registry_sources,
+    );
+    let dirs = modified::protect_modified_sources(
+        dirs,
+        &mut ctx.caches.registry_sources,
+        options.preserve_modified,
+    );
+    let dirs = protect_recently_used(
+        dirs,
+        &mut ctx.caches.registry_sources,
+        &mut ctx.caches.checkouts,
+        options.keep_days,
+    );
+    let dirs = protect_kept_sources(dirs, &mut ctx.caches.registry_sources, ctx.keep);
+
+    if !options.allow_cross_device {
+        for dir in &dirs {
+            check_same_device(dir)?;
+        }
+    }
+    preflight(
+        &dirs.iter().map(PathBuf::as_path).collect::<Vec<_>>(),
+        dry_run,
+        options.keep_going,
+    )?;
+    confirm_autoclean(
+        ctx.paths,
+        &mut ctx.caches.registry_sources,
+        &mut ctx.caches.checkouts,
+        options.registries,
+        options.yes,
+        dry_run,
+    )?;
+
+    #[cfg(feature = "git")]
+    if expensive {
+        gc(ctx, dry_run)?;
+        ctx.report.size_changed = true;
+    }
+    #[cfg(not(feature = "git"))]
+    let _ = expensive;
+
+    // depending on the size of the cache and the system (SSD, HDD...) this can take a few seconds.
+    ctx.print("Clearing cache...\n\n");
+    remove_autoclean_dirs(ctx, &dirs, dry_run);
+    ctx.report.request_summary();
+    Ok(())
+}
+
+/// remove what --autoclean removes, in a dry run, show what it takes to get it back
+fn remove_autoclean_dirs(ctx: &mut Context<'_>, dirs: &[PathBuf], dry_run: bool) {
+    let started = SystemTime::now();
+    for dir in dirs {
+        let size = cumulative_dir_size(dir);
+        if dir.is_dir() {
+            remove_file(
+                dir,
+                dry_run,
+                &mut ctx.report.size_changed,
+                None,
+                &DryRunMessage::Default,
+                Some(size.dir_size),
+            );
+        }
+    }
+    if dry_run {
+        let mut items = ctx.caches.registry_sources.items().to_vec();
+        items.extend(ctx.caches.checkouts.items().iter().cloned());
+        rebuild_cost::removed_items(ctx.paths, dirs, &items).print_table();
+    }
+    ctx.caches.registry_sources.invalidate();
+    ctx.caches.checkouts.invalidate();
+    if !dry_run {
+        incremental::record_run(&ctx.paths.cargo_home, started);
+    }
+}
+
+fn keep_duplicate_crates(ctx: &mut Context<'_>, dry_run: bool, limit: u64, keep_by: KeepBy) {
+    let res = rm_old_crates(
+        limit,
+        keep_by,
+        dry_run,
+        &ctx.paths.registry_pkg_cache,
+        ctx.keep,
+        &mut ctx.report.size_changed,
+    );
+    ctx.caches.registry_pkgs.invalidate();
+    ctx.caches.registry_sources.invalidate();
+    ctx.report.request_summary();
+
+    if let Err(error) = res {
+        match error {
+            Error::MalformedPackageName(_) => {
+                // force a stacktrace here
+                panic!("{}", error);
+            }
+            _ => unreachable!(),
+        };
+    }
+}
+
+fn maintain(
+    ctx: &mut Context<'_>,
+    dir_sizes: &DirSizes<'_>,
+    (policy, mut state, due): Maintenance,
+    dry_run: bool,
+    allow_cross_device: bool,
+) -> Result<(), Error> {
+    let now = history::now();
+    if due.size_check {
+        state.last_check = now;
+        let total_size = dir_sizes.total_size();
+        if policy.needs_autoclean(total_size) {
+            ctx.print(format_args!("{}\n", policy.autoclean_reason(total_size)));
+            let dirs = autoclean_dirs(
+                ctx.paths,
+                ctx.selection,
+                &RegistryFilter::new(None, None),
+                &mut ctx.caches.registry_sources,
+            );
+            let dirs = protect_recently_used(
+                dirs,
+                &mut ctx.caches.registry_sources,
+                &mut ctx.caches.checkouts,
+                ctx.defaults.autoclean_keep_days.unwrap_or(0),
+            );
+            let dirs = protect_kept_sources(dirs, &mut ctx.caches.registry_sources, ctx.keep);
+            if !allow_cross_device {
+                for dir in &dirs {
+                    check_same_device(dir)?;
+                }
+            }
+            preflight(
+                &dirs.iter().map(PathBuf::as_path).collect::<Vec<_>>(),
+                dry_run,
+                false,
+            )?;
+            remove_autoclean_dirs(ctx, &dirs, dry_run);
+        }
+    }
+    #[cfg(feature = "git")]
+    if due.gc {
+        state.last_gc = now;
+        ctx.print("Recompressing the git repos: running --gc\n");
+        gc(ctx, dry_run)?;
+    }
+    if !dry_run {
+        maintain::save_state(&ctx.paths.cargo_home, state);
+    }
+    ctx.report.request_summary();
+    Ok(())
+}
+
+#[cfg(feature = "verify")]
+#[allow(clippy::fn_params_excessive_bools)] // one per flag of the subcommand
+fn verify(
+    ctx: &mut Context<'_>,
+    clean_corrupted: bool,
+    dry_run: bool,
+    report: Option<&str>,
+    content: bool,
+    checksums: bool,
+) -> Result<Flow, Error> {
+    ctx.print("Verifying cache, this may take some time...\n\n");
+    let verification = if checksums {
+        verify::verify_checksums(&mut ctx.caches.registry_pkgs, &ctx.paths.registry_index)
+    } else {
+        verify::verify_crates(&mut ctx.caches.registry_sources, content)
+    };
+    if let Some(report) = report {
+        let failed_verifications = verification.as_ref().err().map_or(&[][..], Vec::as_slice);
+        verify::write_report(Path::new(report), failed_verifications)?;
+    }
+    let failed_verifications = match verification {
+        Ok(()) => return Ok(Flow::Exit(0)),
+        Err(failed_verifications) => failed_verifications,
+    };
+
+    eprintln!("\n");
+    for diff in &failed_verifications {
+        ctx.print(format_args!("{}\n", diff.details()));
+    }
+    eprintln!(
+        "\nFound {} possible corrupted {}.",
+        failed_verifications.len(),
+        if checksums { "archives" } else { "sources" }
+    );
+
+    if clean_corrupted && checksums {
+        verify::clean_corrupted_archives(
+            &mut ctx.caches.registry_pkgs,
+            &failed_verifications,
+            dry_run,
+        );
+    } else if clean_corrupted {
+        verify::clean_corrupted(
+            &mut ctx.caches.registry_sources,
+            &failed_verifications,
+            dry_run,
+        );
+    } else {
+        ctx.print(format_args!(
+            "Hint: use `cargo cache verify{} --clean-corrupted` to remove them.\n",
+            if checksums { " --checksums" } else { "" }
+        ));
+    }
+    Ok(Flow::Exit(1))
+}
+
+#[cfg(test)]
+mod dispatch_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::fs;
+    use std::path::Path;
+
+    /// a cargo home with a crate archive, its extracted sources and a git checkout
+    fn fake_cargo_home() -> tempfile::TempDir {
+        let tmp = tempfile::tempdir().unwrap();
+        let registry = "index.crates.io-6f17d22bba15001f";
+        let archive = tmp
+            .path()
+            .join("registry/cache")
+            .join(registry)
+            .join("foo-1.0.0.crate");
+        fs::create_dir_all(archive.parent().unwrap()).unwrap();
+        fs::write(&archive, "x".repeat(1000)).unwrap();
+        let source = tmp
+            .path()
+            .join("registry/src")
+            .join(registry)
+            .join("foo-1.0.0");
+        fs::create_dir_all(&source).unwrap();
+        fs::write(source.join("lib.rs"), "x".repeat(2000)).unwrap();
+        let checkout = tmp
+            .path()
+            .join("git/checkouts/repo-0123456789abcdef/a1b2c3d");
+        fs::create_dir_all(&checkout).unwrap();
+        fs::write(checkout.join("lib.rs"), "x".repeat(3000)).unwrap();
+        fs::create_dir_all(tmp.path().join("git/db/repo-0123456789abcdef")).unwrap();
+        tmp
+    }
+
+    /// run `command` in-process, return what it printed and how the run goes on
+    fn run_command(cargo_home: &Path, command: &CargoCacheCommands<'_>) -> (String, Flow) {
+        run_command_keeping(cargo_home, command, &KeepList::default())
+    }
+
+    /// like `run_command()`, but never remove the crates of `keep`
+    fn run_command_keeping(
+        cargo_home: &Path,
+        command: &CargoCacheCommands<'_>,
+        keep: &KeepList,
+    ) -> (String, Flow) {
+        let paths = CargoCachePaths::from_root(cargo_home.to_path_buf()).unwrap();
+        let selection = ComponentSelection::new(None, false).unwrap();
+        let defaults = config::Config::default();
+        let mut out = Vec::new();
+        let mut ctx = Context {
+            paths: &paths,
+            selection: &selection,
+            keep,
+            caches: Caches::new(&paths, &selection),
+            defaults: &defaults,
+            out: &mut out,
+            dry_run: false,
+            output_format: OutputFormat::Table,
+            report: RunReport::new(),
+        };
+        let dir_sizes = ctx.caches.dir_sizes(&paths, &selection);
+        let mut flow = run(&mut ctx, command, &dir_sizes, None).unwrap();
+        if flow == Flow::Continue {
+            flow = finish(ctx, command, &dir_sizes);
+        }
+        (String::from_utf8(out).unwrap(), flow)
+    }
+
+    #[test]
+    fn summary() {
+        let cargo_home = fake_cargo_home();
+        let (output, flow) = run_command(cargo_home.path(), &CargoCacheCommands::DefaultSummary);
+        assert_eq!(flow, Flow::Continue);
+        assert!(output.contains("1 crate archives:"), "{output}");
+        assert!(output.contains("1 git repo checkouts:"), "{output}");
+    }
+
+    #[test]
+    fn info() {
+        let cargo_home = fake_cargo_home();
+        let (output, flow) = run_command(cargo_home.path(), &CargoCacheCommands::Info);
+        assert_eq!(flow, Flow::Exit(0));
+        assert!(
+            output.contains(&cargo_home.path().display().to_string()),
+            "{output}"
+        );
+    }
+
+    #[test]
+    fn autoclean() {
+        let cargo_home = fake_cargo_home();
+        let autoclean = CargoCacheCommands::AutoClean {
+            dry_run: false,
+            allow_cross_device: false,
+            keep_going: false,
+            yes: true,
+            preserve_modified: false,
+            keep_days: 0,
+            registries: RegistryFilter::new(None, None),
+        };
+        let (output, flow) = run_command(cargo_home.path(), &autoclean);
+        assert_eq!(flow, Flow::Continue);
+        assert!(output.starts_with("Clearing cache..."), "{output}");
+        // the sources and checkouts are gone, the archive and the bare repo stay
+        assert!(!cargo_home.path().join("registry/src").exists());
+        assert!(!cargo_home.path().join("git/checkouts").exists());
+        assert!(cargo_home
+            .path()
+            .join("registry/cache/index.crates.io-6f17d22bba15001f/foo-1.0.0.crate")
+            .is_file());
+        assert!(cargo_home
+            .path()
+            .join("git/db/repo-0123456789abcdef")
+            .is_dir());
+    }
+
+    #[test]
+    fn autoclean_keeps_crates() {
+        let cargo_home = fake_cargo_home();
+        let sources = cargo_home
+            .path()
+            .join("registry/src/index.crates.io-6f17d22bba15001f");
+        fs::create_dir_all(sources.join("bar-1.0.0")).unwrap();
+        fs::write(sources.join("bar-1.0.0/lib.rs"), "bar").unwrap();
+        let autoclean = CargoCacheCommands::AutoClean {
+            dry_run: false,
+            allow_cross_device: false,
+            keep_going: false,
+            yes: true,
+            preserve_modified: false,
+            keep_days: 0,
+            registries: RegistryFilter::new(None, None),
+        };
+        let (output, flow) = run_command_keeping(
+            cargo_home.path(),
+            &autoclean,
+            &KeepList::new(Some("foo"), &[]),
+        );
+        assert_eq!(flow, Flow::Continue);
+        // only the sources of the kept crate are left
+        assert!(sources.join("foo-1.0.0/lib.rs").is_file(), "{output}");
+        assert!(!sources.join("bar-1.0.0").exists());
+        assert!(!cargo_home.path().join("git/checkouts").exists());
+    }
+}
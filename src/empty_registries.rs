@@ -0,0 +1,198 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// --delete-empty-registries
+// After removing crates of a registry (trim, clean-unref, --remove-if-older-than...) it may be left
+// with nothing but its index and a few empty directories in registry/cache and registry/src.
+// Such registries are removed entirely, cargo sets them up again once it needs them.
+// Whether a registry is empty is decided by looking at the disk and not at the caches: the caches
+// of components that were not selected via --components are empty even if the directories are not.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use walkdir::WalkDir;
+
+use crate::cache::caches::{get_cache_name, RegistrySuperCache};
+use crate::cache::*;
+use crate::ignore::is_ignored;
+use crate::library::{CargoCachePaths, Component, ComponentSelection};
+use crate::remove::{component_path, remove_with_default_message};
+use crate::walk::guarded;
+
+/// a registry that has no crate archives and no extracted sources left
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct EmptyRegistry {
+    /// the name without the hash, "crates.io" or "index.crates.io"
+    pub(crate) name: String,
+    /// the directories of the registry in registry/index, registry/cache and registry/src
+    dirs: Vec<(Component, PathBuf)>,
+}
+
+/// the components a registry consists of
+pub(crate) const REGISTRY_COMPONENTS: [Component; 3] = [
+    Component::RegistryIndex,
+    Component::RegistryCrateCache,
+    Component::RegistrySources,
+];
+
+/// whether there is no file (not even an ignored one) below a directory
+fn has_no_files(dir: &Path) -> bool {
+    !guarded(WalkDir::new(dir), |_| true).any(|entry| !entry.file_type().is_dir())
+}
+
+/// the names of the directories of all registries, "github.com-1ecc6299db9ec823" and such
+pub(crate) fn registry_dir_names(ccp: &CargoCachePaths) -> BTreeSet<String> {
+    REGISTRY_COMPONENTS
+        .iter()
+        .flat_map(|component| {
+            fs::read_dir(component_path(component, ccp))
+                .map(|entries| {
+                    entries
+                        .filter_map(Result::ok)
+                        .filter(|entry| entry.path().is_dir())
+                        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                        .collect::<Vec<String>>()
+                })
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+/// the registries that only consist of their index and empty directories
+pub(crate) fn find_empty_registries(ccp: &CargoCachePaths) -> Vec<EmptyRegistry> {
+    registry_dir_names(ccp)
+        .into_iter()
+        .filter_map(|dir_name| {
+            let dirs = REGISTRY_COMPONENTS
+                .iter()
+                .map(|component| {
+                    (
+                        component.clone(),
+                        component_path(component, ccp).join(&dir_name),
+                    )
+                })
+                .filter(|(_, dir)| dir.is_dir())
+                .collect::<Vec<(Component, PathBuf)>>();
+
+            let empty = dirs.iter().all(|(component, dir)| {
+                !is_ignored(dir) && (*component == Component::RegistryIndex || has_no_files(dir))
+            });
+            empty.then(|| EmptyRegistry {
+                name: get_cache_name(&dirs[0].1),
+                dirs,
+            })
+        })
+        .collect()
+}
+
+/// remove the remaining directories of registries without crate archives and sources
+/// only the directories of the selected components are removed
+pub(crate) fn delete_empty_registries(
+    ccp: &CargoCachePaths,
+    selection: &ComponentSelection,
+    dry_run: bool,
+    size_changed: &mut bool,
+    registry_index_caches: &mut registry_index::RegistryIndicesCache,
+    registry_pkgs_cache: &mut registry_pkg_cache::RegistryPkgCaches,
+    registry_sources_caches: &mut registry_sources::RegistrySourceCaches,
+) {
+    let empty_registries = find_empty_registries(ccp);
+    if empty_registries.is_empty() {
+        println!("No empty registries found.");
+        return;
+    }
+
+    for registry in &empty_registries {
+        println!(
+            "Registry \"{}\" has no crate archives or sources left.",
+            registry.name
+        );
+        for (_, dir) in registry
+            .dirs
+            .iter()
+            .filter(|(component, _)| selection.contains(component))
+        {
+            remove_with_default_message(dir, dry_run, size_changed, None);
+        }
+    }
+
+    if !dry_run {
+        registry_index_caches.invalidate();
+        registry_pkgs_cache.invalidate();
+        registry_sources_caches.invalidate();
+    }
+}
+
+#[cfg(test)]
+mod empty_registries_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn find_and_delete() {
+        let tmp = tempfile::tempdir().unwrap();
+        let ccp = CargoCachePaths::new(tmp.path().to_path_buf()).unwrap();
+        // only the index and empty directories are left
+        let empty = "my-registry.com-1234";
+        fs::create_dir_all(ccp.registry_index.join(empty).join(".cache")).unwrap();
+        fs::write(ccp.registry_index.join(empty).join(".cache/serde"), "{}").unwrap();
+        fs::create_dir_all(ccp.registry_pkg_cache.join(empty)).unwrap();
+        fs::create_dir_all(ccp.registry_sources.join(empty).join("serde-1.0.0")).unwrap();
+        // still has a crate archive
+        let used = "index.crates.io-6f17d22bba15001f";
+        fs::create_dir_all(ccp.registry_index.join(used)).unwrap();
+        fs::create_dir_all(ccp.registry_pkg_cache.join(used)).unwrap();
+        fs::write(
+            ccp.registry_pkg_cache.join(used).join("serde-1.0.0.crate"),
+            "",
+        )
+        .unwrap();
+
+        let found = find_empty_registries(&ccp);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].name, "my-registry.com");
+        assert_eq!(found[0].dirs.len(), 3);
+
+        let mut index = registry_index::RegistryIndicesCache::new(ccp.registry_index.clone());
+        let mut pkgs = registry_pkg_cache::RegistryPkgCaches::new(ccp.registry_pkg_cache.clone());
+        let mut sources = registry_sources::RegistrySourceCaches::new(ccp.registry_sources.clone());
+        let mut size_changed = false;
+
+        // only remove the index, --components registry-index
+        let selection = ComponentSelection::new(Some("registry-index"), false).unwrap();
+        delete_empty_registries(
+            &ccp,
+            &selection,
+            false,
+            &mut size_changed,
+            &mut index,
+            &mut pkgs,
+            &mut sources,
+        );
+        assert!(size_changed);
+        assert!(!ccp.registry_index.join(empty).exists());
+        assert!(ccp.registry_pkg_cache.join(empty).exists());
+
+        delete_empty_registries(
+            &ccp,
+            &ComponentSelection::default(),
+            false,
+            &mut size_changed,
+            &mut index,
+            &mut pkgs,
+            &mut sources,
+        );
+        assert!(!ccp.registry_pkg_cache.join(empty).exists());
+        assert!(!ccp.registry_sources.join(empty).exists());
+        assert!(ccp.registry_index.join(used).exists());
+        assert!(find_empty_registries(&ccp).is_empty());
+    }
+}
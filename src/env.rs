@@ -0,0 +1,140 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// options that can also be set through environment variables
+// Container images and CI templates can configure cargo-cache without changing the command lines,
+// a value passed on the command line always wins over the environment.
+// Values from the environment are checked before anything runs and errors name the variable,
+// otherwise a typo in a CI template would look like a typo on the command line that is not there.
+
+use clap::{ArgMatches, ValueSource};
+
+use crate::args::parse;
+use crate::commands::{check, trim};
+use crate::library::Error;
+
+/// the size "trim" reduces the cache to, if --limit is not passed
+pub(crate) const TRIM_LIMIT: &str = "CARGO_CACHE_TRIM_LIMIT";
+/// the limits of "check", if --fail-if-above is not passed
+pub(crate) const FAIL_IF_ABOVE: &str = "CARGO_CACHE_FAIL_IF_ABOVE";
+/// "--remove-dir" only removes items that were last used before this date
+pub(crate) const MAX_AGE: &str = "CARGO_CACHE_MAX_AGE";
+/// the config file, instead of ~/.config/cargo-cache/config.toml
+pub(crate) const CONFIG: &str = "CARGO_CACHE_CONFIG";
+/// the language of the summary and --info, if --lang is not passed
+pub(crate) const LANG: &str = "CARGO_CACHE_LANG";
+/// where "metrics --push" posts the metrics, if no url is passed
+pub(crate) const METRICS_URL: &str = "CARGO_CACHE_METRICS_URL";
+/// the label of the machine in the metrics, if --label is not passed
+pub(crate) const METRICS_LABEL: &str = "CARGO_CACHE_METRICS_LABEL";
+
+/// the value of the environment variable, None if it is not set or empty
+pub(crate) fn value(var: &str) -> Option<String> {
+    std::env::var(var).ok().filter(|value| !value.is_empty())
+}
+
+/// whether clap took the value of `arg` from its environment variable
+fn from_env(config: &ArgMatches, arg: &str) -> bool {
+    config.value_source(arg) == Some(ValueSource::EnvVariable)
+}
+
+/// whether --remove-dir falls back to `CARGO_CACHE_MAX_AGE` because no date was passed
+pub(crate) fn max_age_applies(config: &ArgMatches) -> bool {
+    config.is_present("remove-dir")
+        && !config.is_present("remove-if-older-than")
+        && !config.is_present("remove-if-younger-than")
+}
+
+/// parse the values that were taken from the environment the same way the command will
+pub(crate) fn check_values(config: &ArgMatches) -> Result<(), Error> {
+    let invalid =
+        |var: &'static str| move |error: Error| Error::InvalidEnvVar(var, Box::new(error));
+
+    if let Some(trim_config) = config.subcommand_matches("trim") {
+        if from_env(trim_config, "trim_limit") {
+            let _ = trim::parse_size_limit_to_bytes(trim_config.value_of("trim_limit"))
+                .map_err(invalid(TRIM_LIMIT))?;
+        }
+    }
+    if let Some(check_config) = config.subcommand_matches("check") {
+        if from_env(check_config, "fail-if-above") {
+            // clap makes sure this is present
+            let _ = check::parse_size_limits(check_config.value_of("fail-if-above").unwrap())
+                .map_err(invalid(FAIL_IF_ABOVE))?;
+        }
+    }
+    if max_age_applies(config) {
+        if let Some(max_age) = value(MAX_AGE) {
+            let _ = parse::date(&max_age)
+                .map_err(Error::from)
+                .map_err(invalid(MAX_AGE))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod env_tests {
+    use super::*;
+    use clap::{App, Arg};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn values_from_the_environment_are_checked() {
+        // a variable of its own, tests run in parallel
+        let var = "CARGO_CACHE_ENV_TESTS_TRIM_LIMIT";
+        // clap reads the variable when the argument is created
+        let app = || {
+            App::new("cargo-cache")
+                .args(
+                    [
+                        "remove-dir",
+                        "remove-if-older-than",
+                        "remove-if-younger-than",
+                    ]
+                    .map(|name| Arg::new(name).long(name).takes_value(true)),
+                )
+                .subcommand(
+                    App::new("trim").arg(
+                        Arg::new("trim_limit")
+                            .long("limit")
+                            .takes_value(true)
+                            .env(var),
+                    ),
+                )
+                .subcommand(
+                    App::new("check").arg(
+                        Arg::new("fail-if-above")
+                            .long("fail-if-above")
+                            .takes_value(true),
+                    ),
+                )
+        };
+
+        std::env::set_var(var, "");
+        assert_eq!(value(var), None);
+
+        std::env::set_var(var, "5x");
+        assert_eq!(value(var), Some(String::from("5x")));
+        match check_values(&app().get_matches_from(["cargo-cache", "trim"])) {
+            Err(error @ Error::InvalidEnvVar(TRIM_LIMIT, _)) => assert!(error
+                .to_string()
+                .starts_with("Invalid value of the environment variable CARGO_CACHE_TRIM_LIMIT: ")),
+            other => panic!("expected an invalid CARGO_CACHE_TRIM_LIMIT, got {other:?}"),
+        }
+        // the command line wins
+        assert!(
+            check_values(&app().get_matches_from(["cargo-cache", "trim", "--limit", "5G"])).is_ok()
+        );
+
+        std::env::set_var(var, "5G");
+        assert!(check_values(&app().get_matches_from(["cargo-cache", "trim"])).is_ok());
+        std::env::remove_var(var);
+    }
+}
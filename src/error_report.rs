@@ -0,0 +1,146 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// how fatal errors are printed
+// Errors are printed to stderr as a sentence. With --json-errors (implied by --output-format json)
+// they are printed as a single line of json instead, so that scripts can branch on the "code"
+// instead of matching the message, which may change between releases:
+// {"code":"root-not-directory","message":"...","path":"/tmp/x","component":null,"io_error":null}
+// "component" is the part of the cache the error is about (same names as --remove-dir) and
+// "io_error" the kind of the underlying io error ("permission-denied", "not-found", ...), if any.
+
+use std::fmt;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::redact::redact;
+
+/// set if errors should be printed as json
+static JSON_ERRORS: AtomicBool = AtomicBool::new(false);
+
+/// print all following errors as json, needs to be called before the first error can happen
+pub(crate) fn init(json_errors: bool) {
+    JSON_ERRORS.store(json_errors, Ordering::SeqCst);
+}
+
+/// what we can tell scripts about an error besides its message
+#[cfg_attr(not(feature = "json"), allow(dead_code))]
+pub(crate) trait ErrorDetails: fmt::Display {
+    /// stable identifier of the kind of error, kebab-case
+    fn code(&self) -> &'static str {
+        "error"
+    }
+
+    /// the file or directory the error is about
+    fn path(&self) -> Option<&Path> {
+        None
+    }
+
+    /// the part of the cache the error is about, named like the --remove-dir values
+    fn component(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// the io error that caused the error
+    fn io_error(&self) -> Option<&std::io::Error> {
+        None
+    }
+}
+
+// ad-hoc errors that did not get a variant in library::Error
+impl ErrorDetails for &str {}
+
+/// print an error to stderr, credentials in it are redacted
+pub(crate) fn print(error: &impl ErrorDetails) {
+    let message = redact(&error.to_string());
+    #[cfg(feature = "json")]
+    if JSON_ERRORS.load(Ordering::SeqCst) {
+        eprintln!("{}", json(error, &message));
+        return;
+    }
+    eprintln!("{message}");
+}
+
+/// the error as a json object
+#[cfg(feature = "json")]
+fn json(error: &impl ErrorDetails, message: &str) -> serde_json::Value {
+    serde_json::json!({
+        "code": error.code(),
+        "message": message,
+        "path": error.path().map(|path| redact(&path.display().to_string())),
+        "component": error.component(),
+        "io_error": error.io_error().map(|io_error| kebab_case(&format!("{:?}", io_error.kind()))),
+    })
+}
+
+/// `PermissionDenied` => `permission-denied`
+#[cfg(feature = "json")]
+fn kebab_case(camel_case: &str) -> String {
+    let mut kebab_case = String::with_capacity(camel_case.len() + 4);
+    for (i, c) in camel_case.chars().enumerate() {
+        if c.is_ascii_uppercase() && i > 0 {
+            kebab_case.push('-');
+        }
+        kebab_case.push(c.to_ascii_lowercase());
+    }
+    kebab_case
+}
+
+#[cfg(all(test, feature = "json"))]
+mod error_report_tests {
+    use super::*;
+    use crate::library::Error;
+    use pretty_assertions::assert_eq;
+    use std::io;
+    use std::path::PathBuf;
+
+    #[test]
+    fn json_errors() {
+        let error = Error::RootNotDirectory(PathBuf::from("/tmp/nothing"));
+        assert_eq!(
+            json(&error, &error.to_string()),
+            serde_json::json!({
+                "code": "root-not-directory",
+                "message": error.to_string(),
+                "path": "/tmp/nothing",
+                "component": null,
+                "io_error": null,
+            })
+        );
+
+        let io_error = Error::QuarantineFailed(
+            PathBuf::from("/home/user/.cargo/registry/src/stray"),
+            io::Error::from(io::ErrorKind::PermissionDenied),
+        );
+        let report = json(&io_error, &io_error.to_string());
+        assert_eq!(report["code"], "quarantine-failed");
+        assert_eq!(report["component"], "registry-sources");
+        assert_eq!(report["io_error"], "permission-denied");
+
+        let env_error = Error::InvalidEnvVar(
+            "CARGO_CACHE_TRIM_LIMIT",
+            Box::new(Error::TrimLimitUnitParseFailure(String::from("5x"))),
+        );
+        assert_eq!(
+            json(&env_error, &env_error.to_string())["code"],
+            "invalid-env-var"
+        );
+
+        let ad_hoc = json(&"Error: something went wrong", "message");
+        assert_eq!(ad_hoc["code"], "error");
+        assert_eq!(ad_hoc["path"], serde_json::Value::Null);
+    }
+
+    #[test]
+    fn kebab_cases() {
+        assert_eq!(kebab_case("NotFound"), "not-found");
+        assert_eq!(kebab_case("PermissionDenied"), "permission-denied");
+        assert_eq!(kebab_case("Other"), "other");
+    }
+}
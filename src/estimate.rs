@@ -0,0 +1,274 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// "cargo cache --estimate": a quick summary for huge caches or slow (network) file systems
+// Instead of looking at every file, we list the entries of a directory but only look at a sample
+// of them (spread evenly, not just the first ones since the entries are sorted by name) and
+// extrapolate. The sampled subdirectories are estimated the same way with a share of the budget,
+// so the number of entries we look at per component is bounded no matter how big the cache is.
+// Every estimate comes with a 95% confidence interval (two-stage sampling); components that were
+// small enough to be looked at completely are exact.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use humansize::{FormatSize, DECIMAL};
+
+use crate::cache::caches::Cache;
+use crate::cache::*;
+use crate::cancel::is_cancelled;
+use crate::hyperlink;
+use crate::library::CargoCachePaths;
+use crate::tables::{two_row_table, TableLine};
+
+/// how many entries we look at per component at most (roughly)
+const BUDGET_PER_COMPONENT: usize = 5000;
+
+/// z-score of a 95% confidence interval
+const Z_95: f64 = 1.96;
+
+/// an estimated size in bytes and the variance of the estimate
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct Estimate {
+    size: f64,
+    variance: f64,
+}
+
+impl Estimate {
+    #[allow(clippy::cast_precision_loss)]
+    fn exact(size: u64) -> Self {
+        Self {
+            size: size as f64,
+            variance: 0.0,
+        }
+    }
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            size: self.size + other.size,
+            variance: self.variance + other.variance,
+        }
+    }
+
+    fn is_exact(self) -> bool {
+        self.variance <= 0.0
+    }
+
+    #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
+    fn bytes(value: f64) -> u64 {
+        value.max(0.0).round() as u64
+    }
+
+    /// "1.20 GB" if exact, "~1.20 GB ± 50.00 MB" otherwise
+    fn format(self) -> String {
+        let size = Self::bytes(self.size).format_size(DECIMAL);
+        if self.is_exact() {
+            size
+        } else {
+            let error = Self::bytes(Z_95 * self.variance.sqrt()).format_size(DECIMAL);
+            format!("~{size} ± {error}")
+        }
+    }
+}
+
+/// indices of `m` out of `n` entries, spread evenly
+fn spread_sample(n: usize, m: usize) -> impl Iterator<Item = usize> {
+    (0..m).map(move |i| i * n / m)
+}
+
+/// extrapolate the estimates of the sampled entries to all `n` entries of the directory
+#[allow(clippy::cast_precision_loss)]
+fn extrapolate(samples: &[Estimate], n: usize) -> Estimate {
+    let sum = samples
+        .iter()
+        .fold(Estimate::default(), |sum, sample| sum.add(*sample));
+    let m = samples.len();
+    if m == 0 || m == n {
+        return sum;
+    }
+
+    let (n, m) = (n as f64, m as f64);
+    let mean = sum.size / m;
+    // with a single sample we can not tell how much the entries vary, assume a lot
+    let spread = if samples.len() > 1 {
+        samples
+            .iter()
+            .map(|sample| (sample.size - mean).powi(2))
+            .sum::<f64>()
+            / (m - 1.0)
+    } else {
+        mean * mean
+    };
+
+    Estimate {
+        size: n * mean,
+        // variance between the entries plus the variance of the estimates of the entries themselves
+        variance: n * n * (1.0 - m / n) * spread / m + n / m * sum.variance,
+    }
+}
+
+/// estimate the size of a file or directory, looking at about `budget` entries
+fn estimate_path(path: &Path, budget: usize) -> Estimate {
+    // links are not followed, just like when calculating the real sizes
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => estimate_dir(path, budget),
+        Ok(metadata) => Estimate::exact(metadata.len()),
+        Err(_) => Estimate::default(),
+    }
+}
+
+/// estimate the size of everything inside `dir`, looking at about `budget` entries
+#[allow(
+    clippy::cast_precision_loss,
+    clippy::cast_sign_loss,
+    clippy::cast_possible_truncation
+)]
+fn estimate_dir(dir: &Path, budget: usize) -> Estimate {
+    // listing a directory tells us which entries are directories without looking at each of them
+    let mut entries = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .map(|entry| {
+                    let is_dir = entry.file_type().map_or(false, |kind| kind.is_dir());
+                    (entry.path(), is_dir)
+                })
+                .collect::<Vec<(PathBuf, bool)>>()
+        })
+        .unwrap_or_default();
+    entries.sort();
+    let n = entries.len();
+    if n == 0 {
+        return Estimate::default();
+    }
+
+    // look at least at one entry per directory, if there are subdirectories,
+    // spend the budget on both the number of samples and the depth
+    let has_subdirs = entries.iter().any(|(_, is_dir)| *is_dir);
+    let max_samples = if has_subdirs {
+        (budget as f64).sqrt().ceil() as usize
+    } else {
+        budget
+    };
+    let m = n.min(max_samples).max(1);
+    let budget_per_sample = budget.saturating_sub(m) / m;
+
+    let samples = spread_sample(n, m)
+        .take_while(|_| !is_cancelled())
+        .map(|i| estimate_path(&entries[i].0, budget_per_sample))
+        .collect::<Vec<Estimate>>();
+    extrapolate(&samples, n)
+}
+
+/// print the estimated sizes of the cache components
+pub(crate) fn print_estimate(ccp: &CargoCachePaths) {
+    // there are only a few binaries, these are always exact
+    let binaries = Estimate::exact(bin::BinaryCache::new(ccp.bin_dir.clone()).total_size());
+    let components = [
+        ("Registry index: ", &ccp.registry_index),
+        ("Crate archives: ", &ccp.registry_pkg_cache),
+        ("Crate source checkouts: ", &ccp.registry_sources),
+        ("Bare git repos: ", &ccp.git_repos_bare),
+        ("Git repo checkouts: ", &ccp.git_checkouts),
+    ];
+    let mut estimates = vec![("Installed binaries: ", binaries)];
+    estimates.extend(
+        components
+            .iter()
+            .map(|(name, dir)| (*name, estimate_path(dir, BUDGET_PER_COMPONENT))),
+    );
+    let total = estimates
+        .iter()
+        .fold(Estimate::default(), |total, (_, estimate)| {
+            total.add(*estimate)
+        });
+
+    let mut lines = vec![
+        TableLine::new(
+            0,
+            &format!(
+                "Estimated cargo cache '{}' (sampled, ± is the 95% confidence interval):\n\n",
+                hyperlink::path(&ccp.cargo_home)
+            ),
+            &String::new(),
+        ),
+        TableLine::new(0, &"Total: ", &total.format()),
+    ];
+    lines.extend(
+        estimates
+            .iter()
+            .map(|(name, estimate)| TableLine::new(1, name, &estimate.format())),
+    );
+    println!("{}", two_row_table(2, lines, false));
+}
+
+#[cfg(test)]
+mod estimate_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn sample_indices() {
+        assert_eq!(spread_sample(10, 3).collect::<Vec<_>>(), vec![0, 3, 6]);
+        assert_eq!(spread_sample(3, 3).collect::<Vec<_>>(), vec![0, 1, 2]);
+        assert_eq!(spread_sample(100, 1).collect::<Vec<_>>(), vec![0]);
+    }
+
+    #[test]
+    fn extrapolation() {
+        // everything was looked at
+        let all = [Estimate::exact(10), Estimate::exact(20)];
+        assert_eq!(extrapolate(&all, 2), Estimate::exact(30));
+
+        // 2 out of 4 entries
+        let estimate = extrapolate(&all, 4);
+        // spread: ((10-15)² + (20-15)²) / 1 = 50, variance: 16 * 0.5 * 50 / 2 = 200
+        assert_eq!(
+            estimate,
+            Estimate {
+                size: 60.0,
+                variance: 200.0
+            }
+        );
+        assert!(!estimate.is_exact());
+
+        assert_eq!(extrapolate(&[], 0), Estimate::default());
+    }
+
+    #[test]
+    fn small_dirs_are_exact() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        fs::create_dir_all(dir.join("a/b")).unwrap();
+        fs::write(dir.join("a/b/file"), "12345").unwrap();
+        fs::write(dir.join("a/file"), "123").unwrap();
+        fs::write(dir.join("file"), "1").unwrap();
+
+        let estimate = estimate_dir(dir, BUDGET_PER_COMPONENT);
+        assert_eq!(estimate, Estimate::exact(9));
+        assert_eq!(estimate.format(), "9 B");
+    }
+
+    #[test]
+    fn big_dirs_are_sampled() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        for i in 0..100 {
+            fs::write(dir.join(format!("{i:03}")), "x".repeat(100 + i)).unwrap();
+        }
+
+        // looks at 10 of the 100 files
+        let estimate = estimate_dir(dir, 10);
+        assert!(!estimate.is_exact());
+        // the real size is 14950
+        let error = Z_95 * estimate.variance.sqrt();
+        assert!((estimate.size - 14950.0).abs() <= error);
+        assert!(estimate.format().starts_with('~'));
+    }
+}
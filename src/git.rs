@@ -9,24 +9,67 @@
 
 use std::fs;
 use std::io::{stdout, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::time::SystemTime;
 
 use humansize::{FormatSize, DECIMAL};
 
+use crate::cancel::is_cancelled;
+use crate::ignore::is_ignored;
 use crate::library::Error;
 use crate::library::*;
+use crate::progress::Progress;
+
+/// check if a directory looks like a git repository without opening it
+/// bare repos (git/db) have HEAD and objects at the top level, registry indices have a .git dir
+/// sparse registry indices and stray files or directories are no git repositories
+pub(crate) fn is_git_repo(path: &Path) -> bool {
+    let is_git_dir = |dir: &Path| dir.join("HEAD").is_file() && dir.join("objects").is_dir();
+    path.is_dir() && (is_git_dir(path) || is_git_dir(&path.join(".git")))
+}
+
+/// split the entries of a directory into git repos and the rest
+/// everything that is not a git repo is reported as skipped
+fn find_git_repos(path: &Path) -> Vec<PathBuf> {
+    let mut entries: Vec<_> = fs::read_dir(path)
+        .unwrap()
+        .map(|x| x.unwrap().path())
+        .filter(|path| !is_ignored(path))
+        .collect();
+    // sort git repos in alphabetical order
+    entries.sort();
+
+    let (git_repos, skipped): (Vec<PathBuf>, Vec<PathBuf>) =
+        entries.into_iter().partition(|entry| is_git_repo(entry));
+
+    for entry in skipped {
+        let name = entry.file_name().unwrap_or_default().to_string_lossy();
+        if entry.join(".cache").is_dir() {
+            println!("Skipping '{name}', sparse registry index");
+        } else if entry.is_dir() {
+            println!("Skipping '{name}', not a git repository");
+        } else {
+            println!("Skipping '{name}', not a directory");
+        }
+    }
+    git_repos
+}
 
 fn gc_repo(path: &Path, dry_run: bool) -> Result<(u64, u64), Error> {
     let start_time = SystemTime::now();
 
     // get name of the repo (last item of path)
-    let repo_name = match path.iter().last() {
-        Some(name) => name.to_str().unwrap().to_string(),
+    let repo_name = match path.iter().next_back() {
+        Some(name) => name.to_string_lossy().into_owned(),
         None => "<unknown>".to_string(),
     };
-    debug_assert_ne!(repo_name, "<unknown>", "unknown repo name: '{:?}'", &path);
+    debug_assert_ne!(
+        repo_name,
+        "<unknown>",
+        "unknown repo name: '{}'",
+        path.display()
+    );
 
     print!("Recompressing '{}': ", &repo_name);
     // if something went wrong and this is not actually a directory, return an error
@@ -124,10 +167,11 @@ pub(crate) fn git_gc_everything(
     git_repos_bare_dir: &Path,
     registry_pkg_cache_dir: &Path,
     dry_run: bool,
+    selection: &ComponentSelection,
 ) -> Result<(), Error> {
     // gc repos and registries inside cargo cache
 
-    fn gc_subdirs(path: &Path, dry_run: bool) -> Result<(u64, u64), Error> {
+    fn gc_subdirs(path: &Path, dry_run: bool, label: &'static str) -> Result<(u64, u64), Error> {
         if path.is_file() {
             return Err(Error::GitGCFile(path.to_path_buf()));
         } else if !path.is_dir() {
@@ -138,16 +182,13 @@ pub(crate) fn git_gc_everything(
         let mut size_sum_before: u64 = 0;
         let mut size_sum_after: u64 = 0;
 
-        let mut git_repos: Vec<_> = fs::read_dir(path)
-            .unwrap()
-            .map(|x| x.unwrap().path())
-            .collect();
-        // sort git repos in alphabetical order
-        git_repos.sort();
-
-        for repo in git_repos {
+        // sparse registry indices or stray files can not be gc'd/fsck'd
+        let repos = find_git_repos(path);
+        let progress = Progress::new(label, repos.len());
+        // stop after the current repo once we were cancelled
+        for repo in repos.into_iter().take_while(|_| !is_cancelled()) {
             // compress
-            let (size_before, size_after) = match gc_repo(&repo, dry_run) {
+            let (size_before, size_after) = match progress.suspend(|| gc_repo(&repo, dry_run)) {
                 // run gc
                 Ok((before, after)) => (before, after),
                 Err(error) => match error {
@@ -156,6 +197,7 @@ pub(crate) fn git_gc_everything(
                     | Error::GitRepoDirNotFound(_)
                     | Error::GitRepoNotOpened(_) => {
                         eprintln!("{error}");
+                        progress.inc(0);
                         continue;
                     }
 
@@ -164,7 +206,9 @@ pub(crate) fn git_gc_everything(
             };
             size_sum_before += size_before;
             size_sum_after += size_after;
+            progress.inc(size_before.saturating_sub(size_after));
         }
+        progress.finish();
         Ok((size_sum_before, size_sum_after))
     } // fn gc_subdirs
 
@@ -177,21 +221,27 @@ pub(crate) fn git_gc_everything(
     let mut total_size_before: u64 = 0;
     let mut total_size_after: u64 = 0;
 
-    println!("\nRecompressing repositories. This may take some time...");
-    // gc git repos of crates
-    let (repos_before, repos_after) = gc_subdirs(git_repos_bare_dir, dry_run)?;
-    total_size_before += repos_before;
-    total_size_after += repos_after;
-
-    println!("\nRecompressing registries. This may take some time...");
-    let mut repo_index = registry_pkg_cache_dir.to_path_buf();
-    // cd "../index"
-    let _ = repo_index.pop();
-    repo_index.push("index");
-    // gc registries
-    let (regs_before, regs_after) = gc_subdirs(&repo_index, dry_run)?;
-    total_size_before += regs_before;
-    total_size_after += regs_after;
+    if selection.contains(&Component::GitDB) {
+        println!("\nRecompressing repositories. This may take some time...");
+        // gc git repos of crates
+        let (repos_before, repos_after) =
+            gc_subdirs(git_repos_bare_dir, dry_run, "Recompressing repositories")?;
+        total_size_before += repos_before;
+        total_size_after += repos_after;
+    }
+
+    if selection.contains(&Component::RegistryIndex) {
+        println!("\nRecompressing registries. This may take some time...");
+        let mut repo_index = registry_pkg_cache_dir.to_path_buf();
+        // cd "../index"
+        let _ = repo_index.pop();
+        repo_index.push("index");
+        // gc registries
+        let (regs_before, regs_after) =
+            gc_subdirs(&repo_index, dry_run, "Recompressing registries")?;
+        total_size_before += regs_before;
+        total_size_after += regs_after;
+    }
 
     println!(
         "\nCompressed {} to {}",
@@ -203,11 +253,16 @@ pub(crate) fn git_gc_everything(
 
 fn fsck_repo(path: &Path) -> Result<(), Error> {
     // get name of the repo (last item of path)
-    let repo_name = match path.iter().last() {
-        Some(name) => name.to_str().unwrap().to_string(),
+    let repo_name = match path.iter().next_back() {
+        Some(name) => name.to_string_lossy().into_owned(),
         None => "<unknown>".to_string(),
     };
-    debug_assert_ne!(repo_name, "<unknown>", "unknown repo name: '{:?}'", &path);
+    debug_assert_ne!(
+        repo_name,
+        "<unknown>",
+        "unknown repo name: '{}'",
+        path.display()
+    );
 
     println!("Fscking '{}'", &repo_name);
 
@@ -239,6 +294,7 @@ fn fsck_repo(path: &Path) -> Result<(), Error> {
 pub(crate) fn git_fsck_everything(
     git_repos_bare_dir: &Path,
     registry_pkg_cache_dir: &Path,
+    selection: &ComponentSelection,
 ) -> Result<(), Error> {
     // gc repos and registries inside cargo cache
 
@@ -252,29 +308,26 @@ pub(crate) fn git_fsck_everything(
             return;
         }
 
-        let mut git_repos: Vec<_> = fs::read_dir(path)
-            .unwrap()
-            .map(|x| x.unwrap().path())
-            .collect();
-        // sort git repos in alphabetical order
-        git_repos.sort();
-
-        for repo in git_repos {
+        // sparse registry indices or stray files can not be gc'd/fsck'd
+        // stop after the current repo once we were cancelled
+        for repo in find_git_repos(path)
+            .into_iter()
+            .take_while(|_| !is_cancelled())
+        {
             // compress
             match fsck_repo(&repo) {
                 // run gc
-                Ok(_) => {}
+                Ok(()) => {}
                 Err(error) => match error {
                     Error::GitFsckFailed(_, _)
                     | Error::GitRepoDirNotFound(_)
                     | Error::GitRepoNotOpened(_) => {
                         eprintln!("{error}");
-                        continue;
                     }
 
                     _ => unreachable!(),
                 },
-            };
+            }
         }
     } // fn fsck_subdirs
 
@@ -283,17 +336,21 @@ pub(crate) fn git_fsck_everything(
         return Err(Error::GitNotInstalled);
     }
 
-    println!("\nFscking repositories. This may take some time...");
-    // fsck git repos of crates
-    fsck_subdirs(git_repos_bare_dir);
-
-    println!("\nFscking registries. This may take some time...");
-    let mut repo_index = registry_pkg_cache_dir.to_path_buf();
-    // cd "../index"
-    let _ = repo_index.pop();
-    repo_index.push("index");
-    // fsck registries
-    fsck_subdirs(&repo_index);
+    if selection.contains(&Component::GitDB) {
+        println!("\nFscking repositories. This may take some time...");
+        // fsck git repos of crates
+        fsck_subdirs(git_repos_bare_dir);
+    }
+
+    if selection.contains(&Component::RegistryIndex) {
+        println!("\nFscking registries. This may take some time...");
+        let mut repo_index = registry_pkg_cache_dir.to_path_buf();
+        // cd "../index"
+        let _ = repo_index.pop();
+        repo_index.push("index");
+        // fsck registries
+        fsck_subdirs(&repo_index);
+    }
     Ok(())
 }
 
@@ -301,7 +358,6 @@ pub(crate) fn git_fsck_everything(
 mod gittest {
     use super::*;
     use std::fs::File;
-    use std::path::PathBuf;
     use std::process::Command;
 
     #[test]
@@ -441,4 +497,34 @@ mod gittest {
         let res = fsck_repo(&PathBuf::from("target/gitrepo_fsck/"));
         assert!(res.is_ok(), "Failed to fsck git repo: {res:?}");
     }
+
+    #[test]
+    fn test_is_git_repo() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+
+        // bare repo as found in git/db
+        let bare = root.join("bare");
+        let _ = git2::Repository::init_bare(&bare).unwrap();
+        assert!(is_git_repo(&bare));
+
+        // git registry index
+        let index = root.join("github.com-1ecc6299db9ec823");
+        let _ = git2::Repository::init(&index).unwrap();
+        assert!(is_git_repo(&index));
+
+        // sparse registry index, no git repo
+        let sparse = root.join("index.crates.io-6f17d22bba15001f");
+        fs::create_dir_all(sparse.join(".cache").join("se").join("rd")).unwrap();
+        assert!(!is_git_repo(&sparse));
+
+        // stray file
+        let file = root.join("CACHEDIR.TAG");
+        let _ = File::create(&file).unwrap();
+        assert!(!is_git_repo(&file));
+
+        assert!(!is_git_repo(&root.join("does_not_exist")));
+
+        assert_eq!(find_git_repos(root), vec![bare, index]);
+    }
 }
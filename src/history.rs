@@ -0,0 +1,170 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// history of removals
+// Every run that frees space appends a line to a file in the state dir of the cargo home: when it
+// ran, what operation it was and how many bytes it freed. "cargo cache savings" sums these up.
+// Dry runs and read-only commands never write to the history.
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use once_cell::sync::OnceCell;
+
+use crate::state_dir;
+
+/// name of the history file inside the state dir
+pub(crate) const HISTORY_FILE_NAME: &str = "history";
+
+/// where and as what the current run is recorded, set up once in `main()`
+static RECORDER: OnceCell<Recorder> = OnceCell::new();
+
+#[derive(Debug)]
+struct Recorder {
+    /// the history file
+    path: PathBuf,
+    /// the operation of the current run
+    command: &'static str,
+    /// the size difference may be printed several times, but the run must only be recorded once
+    recorded: AtomicBool,
+}
+
+/// a run of cargo-cache that freed some space
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Entry {
+    /// seconds since the unix epoch
+    pub(crate) time: u64,
+    /// the operation, for example "--autoclean" or "trim"
+    pub(crate) command: String,
+    /// number of bytes that were freed
+    pub(crate) freed: u64,
+}
+
+impl Entry {
+    /// "<time>\t<command>\t<freed>"
+    fn to_line(&self) -> String {
+        format!("{}\t{}\t{}", self.time, self.command, self.freed)
+    }
+
+    /// parse a line of the history file, lines we do not understand are skipped
+    fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.split('\t');
+        let entry = Self {
+            time: fields.next()?.parse().ok()?,
+            command: fields.next()?.to_string(),
+            freed: fields.next()?.parse().ok()?,
+        };
+        if fields.next().is_none() {
+            Some(entry)
+        } else {
+            None
+        }
+    }
+}
+
+/// seconds since the unix epoch
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+/// record the space freed by the current run in the history of this cargo home
+/// only called for runs that may remove something
+pub(crate) fn init(cargo_home: &Path, command: &'static str) {
+    let _ = RECORDER.set(Recorder {
+        path: state_dir::state_file(cargo_home, HISTORY_FILE_NAME),
+        command,
+        recorded: AtomicBool::new(false),
+    });
+}
+
+/// add the space freed by the current run to the history
+/// does nothing if the run is not recorded (read-only commands, dry runs) or nothing was freed
+pub(crate) fn record_freed(bytes: u64) {
+    let recorder = match RECORDER.get() {
+        Some(recorder) => recorder,
+        None => return,
+    };
+    if bytes == 0 || recorder.recorded.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    let entry = Entry {
+        time: now(),
+        command: recorder.command.to_string(),
+        freed: bytes,
+    };
+    let appended = recorder
+        .path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|()| {
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&recorder.path)
+        })
+        .and_then(|mut file| writeln!(file, "{}", entry.to_line()));
+
+    if let Err(error) = appended {
+        eprintln!(
+            "Warning: failed to add this run to the history \"{}\": {}",
+            recorder.path.display(),
+            error
+        );
+    }
+}
+
+/// all recorded runs of the cargo home, oldest first
+pub(crate) fn read(cargo_home: &Path) -> Vec<Entry> {
+    fs::read_to_string(state_dir::state_file(cargo_home, HISTORY_FILE_NAME))
+        .map(|history| history.lines().filter_map(Entry::parse).collect())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod history_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn entries() {
+        let entry = Entry {
+            time: 1_690_000_000,
+            command: String::from("--autoclean"),
+            freed: 123_456,
+        };
+        assert_eq!(entry.to_line(), "1690000000\t--autoclean\t123456");
+        assert_eq!(Entry::parse(&entry.to_line()), Some(entry));
+
+        assert_eq!(Entry::parse(""), None);
+        assert_eq!(Entry::parse("yesterday\ttrim\t5"), None);
+        assert_eq!(Entry::parse("1\ttrim"), None);
+        assert_eq!(Entry::parse("1\ttrim\t5\t6"), None);
+    }
+
+    #[test]
+    fn read_history() {
+        let cargo_home = tempfile::tempdir().unwrap();
+        assert!(read(cargo_home.path()).is_empty());
+
+        let path = state_dir::state_file(cargo_home.path(), HISTORY_FILE_NAME);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, "1\ttrim\t5\ngarbage\n2\t--autoclean\t10\n").unwrap();
+        let commands = read(cargo_home.path())
+            .into_iter()
+            .map(|entry| entry.command)
+            .collect::<Vec<_>>();
+        assert_eq!(commands, vec!["trim", "--autoclean"]);
+    }
+}
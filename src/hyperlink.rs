@@ -0,0 +1,135 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// clickable paths
+// Many terminals support OSC 8 escape sequences which turn text into hyperlinks. If enabled via
+// --hyperlinks (by default only if stdout is a terminal), the paths printed by the summary,
+// --top-cache-items and query link to their file:// urls so they can be opened from the terminal.
+
+use std::env;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use once_cell::sync::OnceCell;
+
+use crate::tty::{self, Stream};
+
+/// whether paths are printed as hyperlinks, set up once in `main()`
+static HYPERLINKS: OnceCell<bool> = OnceCell::new();
+
+/// --hyperlinks on|off|auto
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum HyperlinkMode {
+    On,
+    Off,
+    /// only if stdout is a terminal
+    Auto,
+}
+
+impl HyperlinkMode {
+    /// clap makes sure that only "on", "off" or "auto" are passed
+    pub(crate) fn new(mode: Option<&str>) -> Self {
+        match mode {
+            Some("on") => Self::On,
+            Some("off") => Self::Off,
+            Some("auto") | None => Self::Auto,
+            Some(other) => unreachable!("invalid --hyperlinks mode \"{}\"", other),
+        }
+    }
+
+    /// whether we print hyperlinks
+    fn enabled(self) -> bool {
+        match self {
+            Self::On => true,
+            Self::Off => false,
+            Self::Auto => tty::can_draw(Stream::Stdout),
+        }
+    }
+}
+
+/// decide whether paths are printed as hyperlinks, needs to be called before anything is printed
+pub(crate) fn init(mode: HyperlinkMode) {
+    let _ = HYPERLINKS.set(mode.enabled());
+}
+
+/// the file:// url of a path
+fn file_url(path: &Path) -> String {
+    let absolute: PathBuf = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        env::current_dir().map_or_else(|_| path.to_path_buf(), |cwd| cwd.join(path))
+    };
+    let mut url_path = absolute.to_string_lossy().replace('\\', "/");
+    // C:/foo => /C:/foo
+    if !url_path.starts_with('/') {
+        url_path.insert(0, '/');
+    }
+
+    let mut url = String::from("file://");
+    for byte in url_path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' | b':' => {
+                url.push(char::from(byte));
+            }
+            _ => write!(url, "%{byte:02X}").unwrap(),
+        }
+    }
+    url
+}
+
+/// `text` as a hyperlink to `path`
+fn hyperlink(path: &Path, text: &str) -> String {
+    format!("\x1b]8;;{}\x1b\\{}\x1b]8;;\x1b\\", file_url(path), text)
+}
+
+/// `text` which links to `path` if hyperlinks are enabled
+pub(crate) fn link(path: &Path, text: &str) -> String {
+    if HYPERLINKS.get().copied().unwrap_or(false) {
+        hyperlink(path, text)
+    } else {
+        text.to_string()
+    }
+}
+
+/// the displayed path, linking to itself if hyperlinks are enabled
+pub(crate) fn path(path: &Path) -> String {
+    link(path, &path.display().to_string())
+}
+
+#[cfg(test)]
+mod hyperlink_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn modes() {
+        assert_eq!(HyperlinkMode::new(None), HyperlinkMode::Auto);
+        assert_eq!(HyperlinkMode::new(Some("on")), HyperlinkMode::On);
+        assert_eq!(HyperlinkMode::new(Some("off")), HyperlinkMode::Off);
+        assert!(HyperlinkMode::On.enabled());
+        assert!(!HyperlinkMode::Off.enabled());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn urls() {
+        assert_eq!(
+            file_url(Path::new("/home/user/.cargo/registry")),
+            "file:///home/user/.cargo/registry"
+        );
+        assert_eq!(
+            file_url(Path::new("/tmp/my cache/ä")),
+            "file:///tmp/my%20cache/%C3%A4"
+        );
+        assert_eq!(
+            hyperlink(Path::new("/tmp/foo"), "foo"),
+            "\x1b]8;;file:///tmp/foo\x1b\\foo\x1b]8;;\x1b\\"
+        );
+    }
+}
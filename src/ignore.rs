@@ -0,0 +1,330 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// support for a ".cargocacheignore" file inside the cargo home.
+// It lists globs (one per line, '#' starts a comment) of paths that cargo-cache must never
+// look into or remove, for example a custom toolchain that lives inside the cargo home.
+// Globs containing a '/' are matched against the path relative to the cargo home,
+// globs without a '/' are matched against the name of every file or directory.
+// '*' and '?' do not match '/', "**" matches across directories.
+// Everything below an ignored directory is ignored as well.
+// The "exclude" globs of the config file (see config.rs) are added to the ones of every cargo home.
+// The binary sets up the list of its cargo home once. The library (see ops.rs) can work on several
+// cargo homes at the same time, so every operation runs with the list of its own home through
+// `with_list()`: it takes precedence over the global list on the threads of that operation.
+
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use once_cell::sync::OnceCell;
+
+use crate::cancel;
+
+/// name of the ignore file inside the cargo home
+pub(crate) const IGNORE_FILE_NAME: &str = ".cargocacheignore";
+
+/// the ignore list of the cargo home we are working on, set up once in `main()`
+static IGNORE_LIST: OnceCell<IgnoreList> = OnceCell::new();
+
+thread_local! {
+    /// the ignore list of the cargo home the current thread works on, see `with_list()`
+    static THREAD_LIST: RefCell<Option<Arc<IgnoreList>>> = const { RefCell::new(None) };
+}
+
+#[derive(Debug)]
+struct Pattern {
+    /// the glob as written in the ignore file
+    glob: String,
+    /// the parsed glob
+    tokens: Vec<GlobToken>,
+    /// whether the glob is matched against the relative path or just the file name
+    anchored: bool,
+}
+
+/// the globs of a .cargocacheignore file
+#[derive(Debug)]
+pub(crate) struct IgnoreList {
+    /// the cargo home, globs are relative to this
+    root: PathBuf,
+    patterns: Vec<Pattern>,
+    /// print ignored paths (once each) when running with --debug
+    debug: bool,
+    /// ignored paths that were already reported
+    reported: Mutex<BTreeSet<PathBuf>>,
+}
+
+/// a piece of a glob
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum GlobToken {
+    /// a character that has to match exactly
+    Literal(char),
+    /// '?', any character but '/'
+    AnyChar,
+    /// '*', any number of characters but '/'
+    AnyName,
+    /// "**", any number of characters
+    AnyPath,
+    /// "**/", zero or more directories
+    AnyDirs,
+}
+
+/// split a glob into its tokens
+fn parse_glob(glob: &str) -> Vec<GlobToken> {
+    let mut tokens = Vec::new();
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        let token = match c {
+            '*' if chars.peek() == Some(&'*') => {
+                let _ = chars.next();
+                if chars.peek() == Some(&'/') {
+                    let _ = chars.next();
+                    GlobToken::AnyDirs
+                } else {
+                    GlobToken::AnyPath
+                }
+            }
+            '*' => GlobToken::AnyName,
+            '?' => GlobToken::AnyChar,
+            other => GlobToken::Literal(other),
+        };
+        tokens.push(token);
+    }
+    tokens
+}
+
+/// check if the whole text matches the glob
+fn glob_matches(tokens: &[GlobToken], text: &[char]) -> bool {
+    match tokens.split_first() {
+        None => text.is_empty(),
+        Some((GlobToken::Literal(c), rest)) => {
+            text.first() == Some(c) && glob_matches(rest, &text[1..])
+        }
+        Some((GlobToken::AnyChar, rest)) => {
+            matches!(text.first(), Some(c) if *c != '/') && glob_matches(rest, &text[1..])
+        }
+        Some((GlobToken::AnyName, rest)) => (0..=text.len())
+            .take_while(|&skip| skip == 0 || text[skip - 1] != '/')
+            .any(|skip| glob_matches(rest, &text[skip..])),
+        Some((GlobToken::AnyPath, rest)) => {
+            (0..=text.len()).any(|skip| glob_matches(rest, &text[skip..]))
+        }
+        Some((GlobToken::AnyDirs, rest)) => {
+            glob_matches(rest, text)
+                || (1..=text.len())
+                    .filter(|&skip| text[skip - 1] == '/')
+                    .any(|skip| glob_matches(rest, &text[skip..]))
+        }
+    }
+}
+
+/// check if the whole text matches the glob, "--cargo-home /srv/cargo/*" uses this as well
+pub(crate) fn glob_match(glob: &str, text: &str) -> bool {
+    glob_matches(&parse_glob(glob), &text.chars().collect::<Vec<char>>())
+}
+
+impl Pattern {
+    fn matches(&self, text: &str) -> bool {
+        glob_matches(&self.tokens, &text.chars().collect::<Vec<char>>())
+    }
+}
+
+impl IgnoreList {
+    /// parse the content of an ignore file
+    pub(crate) fn parse(root: &Path, content: &str, debug: bool) -> Self {
+        let patterns = content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| Pattern {
+                glob: line.to_string(),
+                tokens: parse_glob(line.trim_start_matches('/').trim_end_matches('/')),
+                // a leading slash anchors the glob as well
+                anchored: line.trim_end_matches('/').contains('/'),
+            })
+            .collect();
+
+        Self {
+            root: root.to_path_buf(),
+            patterns,
+            debug,
+            reported: Mutex::new(BTreeSet::new()),
+        }
+    }
+
+    /// read the ignore file of a cargo home, a missing file means that nothing is ignored
+    /// `extra` are more globs, the "exclude" of the config file
+    pub(crate) fn load(cargo_home: &Path, extra: &[String], debug: bool) -> Self {
+        let mut content = fs::read_to_string(cargo_home.join(IGNORE_FILE_NAME)).unwrap_or_default();
+        for glob in extra {
+            content.push('\n');
+            content.push_str(glob);
+        }
+        Self::parse(cargo_home, &content, debug)
+    }
+
+    /// the glob that matches the path or one of its parent directories
+    pub(crate) fn matching_glob(&self, path: &Path) -> Option<&str> {
+        if self.patterns.is_empty() {
+            return None;
+        }
+        let relative = path.strip_prefix(&self.root).ok()?;
+
+        let mut prefix = String::new();
+        for component in relative.components() {
+            let name = match component {
+                Component::Normal(name) => name.to_string_lossy(),
+                _ => continue,
+            };
+            if !prefix.is_empty() {
+                prefix.push('/');
+            }
+            prefix.push_str(&name);
+
+            let matching = self.patterns.iter().find(|pattern| {
+                if pattern.anchored {
+                    pattern.matches(&prefix)
+                } else {
+                    pattern.matches(&name)
+                }
+            });
+            if let Some(pattern) = matching {
+                return Some(&pattern.glob);
+            }
+        }
+        None
+    }
+
+    /// check if the path is ignored, report it if we are in debug mode
+    pub(crate) fn is_ignored(&self, path: &Path) -> bool {
+        match self.matching_glob(path) {
+            Some(glob) => {
+                if self.debug && self.reported.lock().unwrap().insert(path.to_path_buf()) {
+                    eprintln!(
+                        "debug: skipping \"{}\", it matches \"{glob}\" in {IGNORE_FILE_NAME} or the config file",
+                        path.display()
+                    );
+                }
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// load the .cargocacheignore of the cargo home, needs to be called before any scanning happens
+pub(crate) fn init(cargo_home: &Path, extra: &[String], debug: bool) {
+    let _ = IGNORE_LIST.set(IgnoreList::load(cargo_home, extra, debug));
+}
+
+/// run `operation` with the ignore list of its own cargo home instead of the global one,
+/// the parallel iterators of the operation run on a rayon pool of their own that uses it as well
+/// the pool keeps the cancel token of the current thread
+#[allow(dead_code)] // only used by the library, see ops.rs
+pub(crate) fn with_list<T: Send>(
+    list: &Arc<IgnoreList>,
+    operation: impl FnOnce() -> T + Send,
+) -> T {
+    let set_list = |new: Option<Arc<IgnoreList>>| THREAD_LIST.with(|current| current.replace(new));
+    let previous = set_list(Some(Arc::clone(list)));
+    let pool_list = Arc::clone(list);
+    let token = cancel::current_token();
+    let pool = rayon::ThreadPoolBuilder::new()
+        .start_handler(move |_| {
+            let _ = set_list(Some(Arc::clone(&pool_list)));
+            cancel::set_token(token.clone());
+        })
+        .build();
+    let result = match pool {
+        Ok(pool) => pool.install(operation),
+        // the parallel parts use the global list then, which ignores nothing in the library
+        Err(_) => operation(),
+    };
+    let _ = set_list(previous);
+    result
+}
+
+/// the ignore list of the current thread or the global one
+fn current_list<T>(f: impl FnOnce(Option<&IgnoreList>) -> T) -> T {
+    THREAD_LIST.with(|list| match &*list.borrow() {
+        Some(list) => f(Some(list)),
+        None => f(IGNORE_LIST.get()),
+    })
+}
+
+/// check whether cargo-cache must not touch a path
+/// if the ignore list was not set up (for example in tests), nothing is ignored
+pub(crate) fn is_ignored(path: &Path) -> bool {
+    current_list(|list| list.map_or(false, |ignore_list| ignore_list.is_ignored(path)))
+}
+
+/// whether there is anything to ignore at all
+pub(crate) fn ignores_anything() -> bool {
+    current_list(|list| list.map_or(false, |ignore_list| !ignore_list.patterns.is_empty()))
+}
+
+#[cfg(test)]
+mod ignore_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn globs() {
+        let root = Path::new("/home/user/.cargo");
+        let ignore_list = IgnoreList::parse(
+            root,
+            "# custom toolchain\n\
+            my-toolchain/\n\
+            \n\
+            /registry/src/*/serde-1.*\n\
+            git/**/keep-me\n\
+            *.important\n",
+            false,
+        );
+        let glob = |path: &str| ignore_list.matching_glob(&root.join(path));
+
+        assert_eq!(glob("my-toolchain"), Some("my-toolchain/"));
+        // everything below an ignored directory is ignored too
+        assert_eq!(glob("my-toolchain/bin/rustc"), Some("my-toolchain/"));
+        assert_eq!(glob("bin/my-toolchain"), Some("my-toolchain/"));
+
+        assert_eq!(
+            glob("registry/src/github.com-1ecc6299db9ec823/serde-1.0.100/src/lib.rs"),
+            Some("/registry/src/*/serde-1.*")
+        );
+        assert_eq!(glob("registry/src/a/b/serde-1.0.0"), None);
+        assert_eq!(
+            glob("registry/src/github.com-1ecc6299db9ec823/serde_json-1.0.0"),
+            None
+        );
+
+        assert_eq!(glob("git/keep-me"), Some("git/**/keep-me"));
+        assert_eq!(glob("git/checkouts/a/b/keep-me"), Some("git/**/keep-me"));
+        assert_eq!(glob("git/checkouts/a/b/keep-me-not"), None);
+
+        assert_eq!(glob("registry/cache/foo.important"), Some("*.important"));
+        assert_eq!(glob("registry"), None);
+
+        // paths outside of the cargo home are never ignored
+        assert_eq!(
+            ignore_list.matching_glob(Path::new("/tmp/my-toolchain")),
+            None
+        );
+    }
+
+    #[test]
+    fn empty_list() {
+        let root = Path::new("/home/user/.cargo");
+        let ignore_list = IgnoreList::parse(root, "# nothing\n\n", false);
+        assert!(ignore_list.patterns.is_empty());
+        assert!(!ignore_list.is_ignored(&root.join("bin")));
+    }
+}
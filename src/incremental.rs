@@ -0,0 +1,126 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// incremental --autoclean
+// --autoclean removes the crate source and git checkout directories, cargo recreates them as
+// soon as it extracts something again which gives them a new mtime.
+// We remember when the last --autoclean ran in the state dir of the cargo home. If all the
+// directories --autoclean would remove are gone, or are empty and older than the last run,
+// there is nothing to do and we can skip scanning the whole cache.
+
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::state_dir;
+
+/// name of the file inside the state dir that holds the time of the last --autoclean
+pub(crate) const STATE_FILE_NAME: &str = "autoclean";
+
+/// when --autoclean last ran in this cargo home, if it ever did
+pub(crate) fn last_run(cargo_home: &Path) -> Option<SystemTime> {
+    let seconds: u64 = fs::read_to_string(state_dir::state_file(cargo_home, STATE_FILE_NAME))
+        .ok()?
+        .trim()
+        .parse()
+        .ok()?;
+    Some(UNIX_EPOCH + Duration::from_secs(seconds))
+}
+
+/// remember that --autoclean ran, `started` is the time before anything was removed
+pub(crate) fn record_run(cargo_home: &Path, started: SystemTime) {
+    let seconds = started
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    let path = state_dir::state_file(cargo_home, STATE_FILE_NAME);
+    let written = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|()| fs::write(&path, format!("{seconds}\n")));
+    if let Err(error) = written {
+        eprintln!(
+            "Warning: failed to remember this --autoclean in \"{}\": {}",
+            path.display(),
+            error
+        );
+    }
+}
+
+/// whether the directory was not touched since the last run: gone, or empty and not modified since
+fn untouched_since(dir: &Path, last_run: SystemTime) -> bool {
+    let metadata = match fs::symlink_metadata(dir) {
+        Ok(metadata) => metadata,
+        Err(_) => return true,
+    };
+    let modified_before = metadata
+        .modified()
+        .map_or(false, |modified| modified <= last_run);
+    let is_empty = fs::read_dir(dir).map_or(false, |mut entries| entries.next().is_none());
+
+    metadata.is_dir() && modified_before && is_empty
+}
+
+/// the time of the last --autoclean if none of the directories changed since then
+pub(crate) fn unchanged_since_last_run(cargo_home: &Path, dirs: &[&Path]) -> Option<SystemTime> {
+    let last_run = last_run(cargo_home)?;
+    if dirs.iter().all(|dir| untouched_since(dir, last_run)) {
+        Some(last_run)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod incremental_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn state_file() {
+        let cargo_home = tempfile::tempdir().unwrap();
+        let cargo_home = cargo_home.path();
+        assert_eq!(last_run(cargo_home), None);
+
+        let time = UNIX_EPOCH + Duration::from_secs(1_690_000_000);
+        record_run(cargo_home, time);
+        assert!(cargo_home.join(".cargo-cache").join("autoclean").is_file());
+        assert_eq!(last_run(cargo_home), Some(time));
+    }
+
+    #[test]
+    fn unchanged_dirs() {
+        let cargo_home = tempfile::tempdir().unwrap();
+        let cargo_home = cargo_home.path();
+        let sources = cargo_home.join("registry").join("src");
+        let checkouts = cargo_home.join("git").join("checkouts");
+        let dirs = [sources.as_path(), checkouts.as_path()];
+
+        // never ran
+        assert_eq!(unchanged_since_last_run(cargo_home, &dirs), None);
+
+        // the dirs were removed by the last run
+        let later = SystemTime::now() + Duration::from_secs(60);
+        record_run(cargo_home, later);
+        let last = last_run(cargo_home);
+        assert_eq!(unchanged_since_last_run(cargo_home, &dirs), last);
+
+        // an empty directory that is older than the last run
+        fs::create_dir_all(&sources).unwrap();
+        assert_eq!(unchanged_since_last_run(cargo_home, &dirs), last);
+
+        // cargo extracted something
+        fs::create_dir_all(sources.join("index.crates.io-6f17d22bba15001f")).unwrap();
+        assert_eq!(unchanged_since_last_run(cargo_home, &dirs), None);
+
+        // the directory was modified after the last run
+        fs::remove_dir(sources.join("index.crates.io-6f17d22bba15001f")).unwrap();
+        record_run(cargo_home, UNIX_EPOCH + Duration::from_secs(1));
+        assert_eq!(unchanged_since_last_run(cargo_home, &dirs), None);
+    }
+}
@@ -0,0 +1,298 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// This file provides read access to the crate metadata that cargo caches inside of a registry index.
+// Cargo keeps a cache of the index entries of every crate it has looked at in
+// registry/index/<registry>/.cache/<prefix>/<crate name>, both for git and sparse indices.
+// The format of these files is:
+// 1 byte cache version, 4 bytes index format version, the index version (etag or commit hash)
+// and then pairs of <crate version>\0<json blob>\0
+// The dependencies and features are needed by clean-unref, preheat-install and self check-update
+// (feature "metadata"), the checksums of the crate archives by verify --checksums (feature "verify").
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "metadata")]
+use cargo_metadata::semver::{Version, VersionReq};
+use serde_json::Value;
+
+/// a dependency of a crate as listed in the index
+#[cfg(feature = "metadata")]
+#[derive(Debug, Clone)]
+pub(crate) struct IndexDependency {
+    /// the name of the dependency as it is used in the crate (may be renamed)
+    pub(crate) name: String,
+    /// the actual name of the dependency package
+    pub(crate) package: String,
+    /// the version requirement
+    pub(crate) req: VersionReq,
+    /// features of the dependency that are enabled
+    pub(crate) features: Vec<String>,
+    /// whether the dependency is optional
+    pub(crate) optional: bool,
+    /// whether the default features of the dependency are enabled
+    pub(crate) default_features: bool,
+    /// "normal", "build" or "dev"
+    pub(crate) kind: String,
+}
+
+/// a single version of a crate as listed in the index
+#[cfg(feature = "metadata")]
+#[derive(Debug, Clone)]
+pub(crate) struct IndexEntry {
+    /// name of the crate
+    pub(crate) name: String,
+    /// version of this entry
+    pub(crate) version: Version,
+    /// the dependencies of this version
+    pub(crate) deps: Vec<IndexDependency>,
+    /// features and what they enable
+    pub(crate) features: BTreeMap<String, Vec<String>>,
+    /// whether this version was yanked
+    pub(crate) yanked: bool,
+}
+
+/// get the path to the cached index file of a crate inside a registry index directory
+pub(crate) fn index_cache_path(index_dir: &Path, crate_name: &str) -> PathBuf {
+    let name = crate_name.to_lowercase();
+    let mut path = index_dir.join(".cache");
+    match name.len() {
+        1 => path.push("1"),
+        2 => path.push("2"),
+        3 => {
+            path.push("3");
+            path.push(&name[0..1]);
+        }
+        _ => {
+            path.push(&name[0..2]);
+            path.push(&name[2..4]);
+        }
+    }
+    path.push(name);
+    path
+}
+
+#[cfg(feature = "metadata")]
+fn parse_dependency(json: &Value) -> Option<IndexDependency> {
+    let name = json["name"].as_str()?.to_string();
+    // renamed dependencies have the actual package name in the "package" field
+    let package = json["package"].as_str().unwrap_or(&name).to_string();
+    Some(IndexDependency {
+        req: VersionReq::parse(json["req"].as_str()?).ok()?,
+        features: json["features"]
+            .as_array()
+            .map(|features| {
+                features
+                    .iter()
+                    .filter_map(|f| f.as_str().map(ToString::to_string))
+                    .collect()
+            })
+            .unwrap_or_default(),
+        optional: json["optional"].as_bool().unwrap_or(false),
+        default_features: json["default_features"].as_bool().unwrap_or(true),
+        kind: json["kind"].as_str().unwrap_or("normal").to_string(),
+        name,
+        package,
+    })
+}
+
+/// parse a single json line of the index
+#[cfg(feature = "metadata")]
+pub(crate) fn parse_index_entry(line: &str) -> Option<IndexEntry> {
+    let json: Value = serde_json::from_str(line).ok()?;
+
+    let mut features: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    // "features2" holds features using the newer "dep:" / "?" syntax
+    for key in ["features", "features2"] {
+        if let Some(map) = json[key].as_object() {
+            for (feature, enables) in map {
+                let enables = enables
+                    .as_array()
+                    .map(|list| {
+                        list.iter()
+                            .filter_map(|f| f.as_str().map(ToString::to_string))
+                            .collect::<Vec<String>>()
+                    })
+                    .unwrap_or_default();
+                features.entry(feature.clone()).or_default().extend(enables);
+            }
+        }
+    }
+
+    Some(IndexEntry {
+        name: json["name"].as_str()?.to_string(),
+        version: Version::parse(json["vers"].as_str()?).ok()?,
+        deps: json["deps"]
+            .as_array()
+            .map(|deps| deps.iter().filter_map(parse_dependency).collect())
+            .unwrap_or_default(),
+        features,
+        yanked: json["yanked"].as_bool().unwrap_or(false),
+    })
+}
+
+/// the json blobs of all versions in the contents of an index cache file
+fn json_records(data: &[u8]) -> Vec<&str> {
+    // skip the cache version and index format version
+    if data.len() < 5 {
+        return Vec::new();
+    }
+    let mut fields = data[5..].split(|byte| *byte == 0);
+    // skip the index version
+    let _ = fields.next();
+
+    let mut records = Vec::new();
+    // the remaining fields come in pairs of version and json
+    while let (Some(_version), Some(json)) = (fields.next(), fields.next()) {
+        if let Ok(json) = std::str::from_utf8(json) {
+            records.push(json);
+        }
+    }
+    records
+}
+
+/// parse the contents of an index cache file
+#[cfg(feature = "metadata")]
+pub(crate) fn parse_index_cache(data: &[u8]) -> Vec<IndexEntry> {
+    json_records(data)
+        .into_iter()
+        .filter_map(parse_index_entry)
+        .collect()
+}
+
+/// the sha256 checksums of the .crate archives of all versions in the contents of an index cache
+/// file, by version
+#[cfg(feature = "verify")]
+fn parse_checksums(data: &[u8]) -> BTreeMap<String, String> {
+    json_records(data)
+        .into_iter()
+        .filter_map(|json| serde_json::from_str::<Value>(json).ok())
+        .filter_map(|json| {
+            let version = json["vers"].as_str()?.to_string();
+            let checksum = json["cksum"].as_str()?.to_lowercase();
+            Some((version, checksum))
+        })
+        .collect()
+}
+
+/// the checksums of the .crate archives of a crate that are known to the index cache of a registry
+/// index, by version
+/// returns None if cargo has not cached anything about this crate
+#[cfg(feature = "verify")]
+pub(crate) fn read_checksums(
+    index_dir: &Path,
+    crate_name: &str,
+) -> Option<BTreeMap<String, String>> {
+    let data = fs::read(index_cache_path(index_dir, crate_name)).ok()?;
+    Some(parse_checksums(&data))
+}
+
+/// get all the versions of a crate that are known to the index cache of a registry index
+/// returns None if cargo has not cached anything about this crate
+#[cfg(feature = "metadata")]
+pub(crate) fn read_index_entries(index_dir: &Path, crate_name: &str) -> Option<Vec<IndexEntry>> {
+    let data = fs::read(index_cache_path(index_dir, crate_name)).ok()?;
+    Some(parse_index_cache(&data))
+}
+
+#[cfg(test)]
+mod index_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn cache_paths() {
+        let root = Path::new("index");
+        let p = |name| {
+            index_cache_path(root, name)
+                .strip_prefix(root)
+                .unwrap()
+                .to_path_buf()
+        };
+        assert_eq!(p("a"), PathBuf::from(".cache/1/a"));
+        assert_eq!(p("ab"), PathBuf::from(".cache/2/ab"));
+        assert_eq!(p("abc"), PathBuf::from(".cache/3/a/abc"));
+        assert_eq!(p("serde"), PathBuf::from(".cache/se/rd/serde"));
+        assert_eq!(p("Inflector"), PathBuf::from(".cache/in/fl/inflector"));
+    }
+
+    #[cfg(feature = "metadata")]
+    #[test]
+    fn parse_cache_file() {
+        let mut data: Vec<u8> = vec![3, 2, 0, 0, 0];
+        data.extend(b"etag\0");
+        data.extend(b"1.0.0\0");
+        data.extend(
+            br#"{"name":"foo","vers":"1.0.0","deps":[{"name":"bar","req":"^0.2","features":["std"],"optional":false,"default_features":true,"target":null,"kind":"normal"},{"name":"baz2","package":"baz","req":"=1.2.3","features":[],"optional":true,"default_features":false,"target":null,"kind":"dev"}],"cksum":"abc","features":{"default":["baz2"]},"yanked":false}"#,
+        );
+        data.push(0);
+        data.extend(b"1.1.0\0");
+        data.extend(br#"{"name":"foo","vers":"1.1.0","deps":[],"cksum":"def","features":{},"features2":{"x":["dep:bar"]},"yanked":true}"#);
+        data.push(0);
+
+        let entries = parse_index_cache(&data);
+        assert_eq!(entries.len(), 2);
+
+        let first = &entries[0];
+        assert_eq!(first.name, "foo");
+        assert_eq!(first.version, Version::new(1, 0, 0));
+        assert!(!first.yanked);
+        assert_eq!(first.deps.len(), 2);
+        assert_eq!(first.deps[0].package, "bar");
+        assert_eq!(first.deps[0].features, vec!["std".to_string()]);
+        assert_eq!(first.deps[1].name, "baz2");
+        assert_eq!(first.deps[1].package, "baz");
+        assert_eq!(first.deps[1].kind, "dev");
+        assert!(first.deps[1].optional);
+        assert!(!first.deps[1].default_features);
+        assert_eq!(first.features["default"], vec!["baz2".to_string()]);
+
+        let second = &entries[1];
+        assert!(second.yanked);
+        assert_eq!(second.features["x"], vec!["dep:bar".to_string()]);
+    }
+
+    #[cfg(feature = "metadata")]
+    #[test]
+    fn parse_garbage() {
+        assert!(parse_index_cache(&[]).is_empty());
+        assert!(
+            parse_index_cache(b"\x03\x02\x00\x00\x00etag\x001.0.0\x00{not json\x00").is_empty()
+        );
+    }
+
+    #[cfg(feature = "verify")]
+    #[test]
+    fn checksums() {
+        let mut data: Vec<u8> = vec![3, 2, 0, 0, 0];
+        data.extend(b"etag\0");
+        data.extend(b"1.0.0\0");
+        data.extend(br#"{"name":"foo","vers":"1.0.0","deps":[],"cksum":"ABC123","features":{}}"#);
+        data.push(0);
+        data.extend(b"1.1.0\0");
+        data.extend(br#"{"name":"foo","vers":"1.1.0","deps":[],"features":{}}"#);
+        data.push(0);
+        data.extend(b"2.0.0-rc.1\0");
+        data.extend(br#"{"name":"foo","vers":"2.0.0-rc.1","deps":[],"cksum":"def","features":{}}"#);
+        data.push(0);
+
+        let checksums = parse_checksums(&data);
+        // 1.1.0 has no checksum
+        assert_eq!(
+            checksums.into_iter().collect::<Vec<(String, String)>>(),
+            vec![
+                (String::from("1.0.0"), String::from("abc123")),
+                (String::from("2.0.0-rc.1"), String::from("def")),
+            ]
+        );
+        assert!(parse_checksums(b"\x03\x02\x00\x00\x00etag\x001.0.0\x00{not json\x00").is_empty());
+    }
+}
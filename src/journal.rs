@@ -0,0 +1,251 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// journal of removals: what each destructive run removed from the cargo home
+// Every item a run removes is appended to $CARGO_HOME/.cargo-cache/journal.json right away:
+// when, by which command, which path and how big it was. "cargo cache log" lists the runs, which
+// helps to find out why cargo suddenly downloads everything again when several people or CI jobs
+// share a cargo home. Nothing can be restored from the journal.
+// The journal has one JSON object per line, so recording an item never rewrites the file and a run
+// that is interrupted or exits early still leaves complete lines behind. Once the journal is bigger
+// than MAX_JOURNAL_SIZE, the older half of it is dropped at the start of the next destructive run.
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use once_cell::sync::OnceCell;
+use serde_json::{json, Value};
+
+use crate::history::now;
+use crate::state_dir;
+use crate::walk::walk;
+
+/// name of the journal inside the state dir, JSON lines
+pub(crate) const JOURNAL_FILE_NAME: &str = "journal.json";
+/// a few 10000 removals, the journal is read completely by "cargo cache log"
+const MAX_JOURNAL_SIZE: u64 = 4_000_000;
+
+/// where and as what the removals of the current run are recorded, set up once in `main()`
+static JOURNAL: OnceCell<Journal> = OnceCell::new();
+
+#[derive(Debug)]
+struct Journal {
+    /// the journal file
+    path: PathBuf,
+    /// removed paths are recorded relative to the cargo home
+    cargo_home: PathBuf,
+    /// the operation of the current run
+    command: &'static str,
+    /// identifies the removals of the current run: the time it started, in milliseconds
+    run: u64,
+    /// only warn once if the journal can not be written
+    warned: AtomicBool,
+}
+
+/// an item that was removed from the cargo home
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Removal {
+    /// seconds since the unix epoch
+    pub(crate) time: u64,
+    /// the run that removed the item, all removals of a run have the same
+    pub(crate) run: u64,
+    /// the operation, for example "--autoclean" or "trim"
+    pub(crate) command: String,
+    /// relative to the cargo home, absolute if it was outside of it
+    pub(crate) path: String,
+    /// number of bytes that were removed
+    pub(crate) size: u64,
+}
+
+impl Removal {
+    fn to_json(&self) -> Value {
+        json!({
+            "time": self.time,
+            "run": self.run,
+            "command": self.command,
+            "path": self.path,
+            "size": self.size,
+        })
+    }
+
+    /// parse a line of the journal, lines we do not understand are skipped
+    fn parse(line: &str) -> Option<Self> {
+        let value: Value = serde_json::from_str(line).ok()?;
+        Some(Self {
+            time: value.get("time")?.as_u64()?,
+            run: value.get("run")?.as_u64()?,
+            command: value.get("command")?.as_str()?.to_string(),
+            path: value.get("path")?.as_str()?.to_string(),
+            size: value.get("size")?.as_u64()?,
+        })
+    }
+}
+
+/// the path of the journal of a cargo home
+pub(crate) fn journal_path(cargo_home: &Path) -> PathBuf {
+    state_dir::state_file(cargo_home, JOURNAL_FILE_NAME)
+}
+
+/// keep only the newer half of a journal that grew too big
+fn truncate(path: &Path) {
+    if fs::metadata(path).map_or(true, |metadata| metadata.len() <= MAX_JOURNAL_SIZE) {
+        return;
+    }
+    let journal = match fs::read_to_string(path) {
+        Ok(journal) => journal,
+        Err(_) => return,
+    };
+    let lines = journal.lines().collect::<Vec<_>>();
+    let newer = lines[lines.len() / 2..]
+        .iter()
+        .flat_map(|line| [*line, "\n"])
+        .collect::<String>();
+    if let Err(error) = fs::write(path, newer) {
+        eprintln!(
+            "Warning: failed to shorten the journal \"{}\": {}",
+            path.display(),
+            error
+        );
+    }
+}
+
+/// record the removals of the current run in the journal of this cargo home
+/// only called for runs that may remove something
+pub(crate) fn init(cargo_home: &Path, command: &'static str) {
+    let path = journal_path(cargo_home);
+    truncate(&path);
+    let run = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| {
+            u64::try_from(duration.as_millis()).unwrap_or(u64::MAX)
+        });
+    let _ = JOURNAL.set(Journal {
+        path,
+        cargo_home: cargo_home.to_path_buf(),
+        command,
+        run,
+        warned: AtomicBool::new(false),
+    });
+}
+
+/// whether removals of this run are recorded, the size of a removed item is only worth
+/// calculating if they are
+pub(crate) fn enabled() -> bool {
+    JOURNAL.get().is_some()
+}
+
+/// the size of an item about to be removed, like the sizes of the summary only files count
+pub(crate) fn size_of(path: &Path) -> u64 {
+    walk(path)
+        .filter(|entry| !entry.file_type().is_dir())
+        .filter_map(|entry| entry.metadata().ok())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+/// add a removed item to the journal
+/// does nothing if the run is not recorded (read-only commands, dry runs)
+pub(crate) fn record_removal(path: &Path, size: u64) {
+    let journal = match JOURNAL.get() {
+        Some(journal) => journal,
+        None => return,
+    };
+    let removal = Removal {
+        time: now(),
+        run: journal.run,
+        command: journal.command.to_string(),
+        path: path
+            .strip_prefix(&journal.cargo_home)
+            .unwrap_or(path)
+            .display()
+            .to_string(),
+        size,
+    };
+    let appended = fs::create_dir_all(journal.path.parent().unwrap_or(&journal.cargo_home))
+        .and_then(|()| {
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&journal.path)
+        })
+        .and_then(|mut file| writeln!(file, "{}", removal.to_json()));
+
+    if let Err(error) = appended {
+        if !journal.warned.swap(true, Ordering::SeqCst) {
+            eprintln!(
+                "Warning: failed to record removals in the journal \"{}\": {}",
+                journal.path.display(),
+                error
+            );
+        }
+    }
+}
+
+/// all recorded removals of the cargo home, oldest first
+pub(crate) fn read(cargo_home: &Path) -> Vec<Removal> {
+    fs::read_to_string(journal_path(cargo_home))
+        .map(|journal| journal.lines().filter_map(Removal::parse).collect())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod journal_tests {
+    use super::*;
+    use crate::schema::schema_tests::assert_valid;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn removals() {
+        let removal = Removal {
+            time: 1_690_000_000,
+            run: 1_690_000_000_123,
+            command: String::from("--autoclean"),
+            path: String::from("registry/src/index.crates.io-6f17d22bba15001f"),
+            size: 123_456,
+        };
+        let line = removal.to_json().to_string();
+        assert_eq!(
+            line,
+            r#"{"command":"--autoclean","path":"registry/src/index.crates.io-6f17d22bba15001f","run":1690000000123,"size":123456,"time":1690000000}"#
+        );
+        assert_valid("journal_record", &removal.to_json());
+        assert_eq!(Removal::parse(&line), Some(removal));
+
+        assert_eq!(Removal::parse(""), None);
+        assert_eq!(Removal::parse("{}"), None);
+        assert_eq!(Removal::parse(r#"{"command":"trim","path":"x"}"#), None);
+    }
+
+    #[test]
+    fn truncate_old_half() {
+        let cargo_home = tempfile::tempdir().unwrap();
+        let path = journal_path(cargo_home.path());
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+
+        fs::write(&path, "1\n2\n").unwrap();
+        truncate(&path);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "1\n2\n");
+
+        let line = "x".repeat(1000);
+        let mut lines = String::new();
+        for number in 0..5000 {
+            lines.push_str(&number.to_string());
+            lines.push_str(&line);
+            lines.push('\n');
+        }
+        fs::write(&path, lines).unwrap();
+        truncate(&path);
+        let kept = fs::read_to_string(&path).unwrap();
+        assert_eq!(kept.lines().count(), 2500);
+        assert!(kept.starts_with(&format!("2500{line}\n")));
+    }
+}
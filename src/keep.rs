@@ -0,0 +1,148 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// crates that are never removed
+// "--keep windows-sys,my-sdk" (or "keep" in the config file) protects the crate archives and the
+// extracted sources of these crates, all versions and from every registry, from --autoclean, trim,
+// --keep-duplicate-crates and --remove-if-older-than / --remove-if-younger-than.
+// Unlike the globs of the .cargocacheignore, kept crates are still scanned and counted,
+// trim --limit just has to make room elsewhere.
+
+use std::path::{Path, PathBuf};
+
+use crate::cache::caches::RegistrySuperCache;
+use crate::cache::registry_sources;
+use crate::modified::without_modified;
+use crate::names;
+
+/// the crates that --keep protects
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub(crate) struct KeepList {
+    /// crate names, with '-' instead of '_'
+    crates: Vec<String>,
+}
+
+/// crates.io does not distinguish `foo_bar` and `foo-bar`, neither do we
+fn normalize(name: &str) -> String {
+    name.replace('_', "-")
+}
+
+/// the name of the crate a path inside registry/cache/<registry> or registry/src/<registry>
+/// belongs to, for example "serde" for "registry/src/<registry>/serde-1.0.0/src/lib.rs"
+fn crate_of(path: &Path) -> Option<&str> {
+    let names: Vec<_> = path.iter().collect();
+    names
+        .windows(4)
+        .find_map(|window| match (window[0].to_str()?, window[1].to_str()?) {
+            ("registry", "cache" | "src") => Some(names::split_name_version(window[3].to_str()?).0),
+            _ => None,
+        })
+}
+
+impl KeepList {
+    /// the comma separated crates of --keep, added to the `keep` crates of the config file
+    pub(crate) fn new(list: Option<&str>, config: &[String]) -> Self {
+        let mut crates = list
+            .unwrap_or_default()
+            .split(',')
+            .chain(config.iter().map(String::as_str))
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(normalize)
+            .collect::<Vec<String>>();
+        crates.sort();
+        crates.dedup();
+        Self { crates }
+    }
+
+    /// whether no crate is kept
+    pub(crate) fn is_empty(&self) -> bool {
+        self.crates.is_empty()
+    }
+
+    /// whether the path is (or is inside of) a crate archive or source of a kept crate
+    pub(crate) fn keeps(&self, path: &Path) -> bool {
+        !self.is_empty()
+            && crate_of(path).map_or(false, |name| self.crates.contains(&normalize(name)))
+    }
+}
+
+/// replace the directories --autoclean removes that contain sources of kept crates by the
+/// other crate sources inside them
+pub(crate) fn protect_kept_sources(
+    dirs: Vec<PathBuf>,
+    registry_sources_caches: &mut registry_sources::RegistrySourceCaches,
+    keep: &KeepList,
+) -> Vec<PathBuf> {
+    if keep.is_empty() {
+        return dirs;
+    }
+    let sources = registry_sources_caches.items().to_vec();
+    let kept = sources
+        .iter()
+        .filter(|source| dirs.iter().any(|dir| source.starts_with(dir)))
+        .filter(|source| keep.keeps(source))
+        .cloned()
+        .collect::<Vec<PathBuf>>();
+    if kept.is_empty() {
+        return dirs;
+    }
+
+    println!("Keeping {} crate sources of --keep crates\n", kept.len());
+    without_modified(dirs, &sources, &kept)
+}
+
+#[cfg(test)]
+mod keep_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn kept_crates() {
+        let keep = KeepList::new(Some("windows-sys, my_sdk,,"), &[String::from("serde")]);
+        assert_eq!(
+            keep.crates,
+            vec![
+                String::from("my-sdk"),
+                String::from("serde"),
+                String::from("windows-sys")
+            ]
+        );
+        let registry = Path::new("/cargo/registry");
+        let index = "index.crates.io-6f17d22bba15001f";
+        assert!(keep.keeps(
+            &registry
+                .join("cache")
+                .join(index)
+                .join("windows-sys-0.48.0.crate")
+        ));
+        assert!(keep.keeps(&registry.join("src").join(index).join("my-sdk-1.0.0")));
+        assert!(keep.keeps(
+            &registry
+                .join("src")
+                .join(index)
+                .join("serde-1.0.0")
+                .join("src/lib.rs")
+        ));
+        // other crates, the registry directories and git repos are not kept
+        assert!(!keep.keeps(
+            &registry
+                .join("cache")
+                .join(index)
+                .join("serde_json-1.0.0.crate")
+        ));
+        assert!(!keep.keeps(&registry.join("src").join(index)));
+        assert!(!keep.keeps(Path::new("/cargo/git/checkouts/serde-1234/abcdef")));
+
+        assert!(KeepList::new(None, &[]).is_empty());
+        assert!(
+            !KeepList::new(None, &[]).keeps(&registry.join("src").join(index).join("serde-1.0.0"))
+        );
+    }
+}
@@ -0,0 +1,267 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// when the items of the cache were last used
+// cargo does not tell us when a build used a crate, so we look at the file system: a build reads
+// the sources (access time) and cargo writes archives, sources and checkouts when it fetches them
+// (modification time). The access time alone is not reliable: noatime mounts never update it,
+// relatime only about once a day and copying or restoring a cache resets it.
+// So the newest time we ever saw for an item is kept in a small database in the state dir of the
+// cargo home (".cargo-cache/last-used", one "<seconds>\t<path>" line per item) and an item is
+// never reported as used earlier than that. --top-cache-items and query show it, it is meant to
+// make trimming smarter than looking at modification times alone.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use chrono::{DateTime, Local};
+
+use crate::cancel::is_cancelled;
+use crate::state_dir;
+use crate::walk::walk;
+
+/// name of the database file inside the state dir
+pub(crate) const DB_FILE_NAME: &str = "last-used";
+
+/// the last use of the items of one cargo home
+#[derive(Debug)]
+pub(crate) struct LastUsed {
+    cargo_home: PathBuf,
+    /// what the database knows, seconds since the unix epoch by path relative to the cargo home
+    recorded: BTreeMap<PathBuf, u64>,
+    /// what we saw during this run, only kept if it is newer than the database
+    observed: Mutex<BTreeMap<PathBuf, u64>>,
+}
+
+fn to_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+fn from_seconds(seconds: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(seconds)
+}
+
+/// parse the database, lines we do not understand are skipped
+fn parse(content: &str) -> BTreeMap<PathBuf, u64> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let (seconds, path) = line.split_once('\t')?;
+            Some((PathBuf::from(path), seconds.parse().ok()?))
+        })
+        .collect()
+}
+
+/// the content of the database, sorted by path
+fn to_content(records: &BTreeMap<PathBuf, u64>) -> String {
+    records
+        .iter()
+        // a path that does not fit on a line could not be read back
+        .filter_map(|(path, seconds)| Some((path.to_str()?, seconds)))
+        .filter(|(path, _)| !path.contains('\n'))
+        .fold(String::new(), |mut content, (path, seconds)| {
+            let _ = writeln!(content, "{seconds}\t{path}");
+            content
+        })
+}
+
+/// the newest access or modification of the file or of any file of the directory
+pub(crate) fn observe(path: &Path) -> Option<SystemTime> {
+    let newest = |metadata: fs::Metadata| {
+        let accessed = metadata.accessed().ok();
+        let modified = metadata.modified().ok();
+        accessed.max(modified)
+    };
+    if path.is_file() {
+        return fs::metadata(path).ok().and_then(newest);
+    }
+    walk(path)
+        .take_while(|_| !is_cancelled())
+        // the item may be removed while we look at it
+        .filter_map(|entry| entry.metadata().ok())
+        .filter_map(newest)
+        .max()
+}
+
+impl LastUsed {
+    /// read the database of the cargo home, it is fine if there is none yet
+    pub(crate) fn load(cargo_home: &Path) -> Self {
+        let recorded = fs::read_to_string(state_dir::state_file(cargo_home, DB_FILE_NAME))
+            .map(|content| parse(&content))
+            .unwrap_or_default();
+        Self {
+            cargo_home: cargo_home.to_path_buf(),
+            recorded,
+            observed: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// when the file or directory was last used, None if we can't tell
+    pub(crate) fn get(&self, path: &Path) -> Option<SystemTime> {
+        let key = path.strip_prefix(&self.cargo_home).ok();
+        let recorded = key.and_then(|key| self.recorded.get(key)).copied();
+        let observed = observe(path).map(to_seconds);
+
+        if let (Some(key), Some(seconds)) = (key, observed) {
+            if recorded.map_or(true, |recorded| seconds > recorded) {
+                let _ = self
+                    .observed
+                    .lock()
+                    .unwrap()
+                    .insert(key.to_path_buf(), seconds);
+            }
+        }
+        recorded.max(observed).map(from_seconds)
+    }
+
+    /// the most recent use of any of the paths, for example all versions of a crate
+    pub(crate) fn newest<'p>(
+        &self,
+        paths: impl IntoIterator<Item = &'p Path>,
+    ) -> Option<SystemTime> {
+        paths.into_iter().filter_map(|path| self.get(path)).max()
+    }
+
+    /// remember what we saw in the database and forget items that are gone
+    /// the database only helps, a cargo home we can't write to still works without it
+    pub(crate) fn save(&self) {
+        let mut records = self.recorded.clone();
+        let observed = self.observed.lock().unwrap();
+        records.extend(
+            observed
+                .iter()
+                .map(|(path, seconds)| (path.clone(), *seconds)),
+        );
+        records.retain(|path, _| fs::symlink_metadata(self.cargo_home.join(path)).is_ok());
+        if records == self.recorded {
+            return;
+        }
+
+        let database = state_dir::state_file(&self.cargo_home, DB_FILE_NAME);
+        let temporary = database.with_extension("tmp");
+        // another cargo-cache may read the database at the same time, replace it in one go
+        let written = database
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| fs::write(&temporary, to_content(&records)));
+        if written.is_ok() && fs::rename(&temporary, &database).is_err() {
+            let _ = fs::remove_file(&temporary);
+        }
+    }
+}
+
+/// the day of the last use for the tables, "-" if we can't tell
+pub(crate) fn format_last_used(time: Option<SystemTime>) -> String {
+    time.map_or_else(
+        || String::from("-"),
+        |time| DateTime::<Local>::from(time).format("%Y-%m-%d").to_string(),
+    )
+}
+
+/// the last use for the json output, seconds since the unix epoch
+#[cfg(feature = "json")]
+pub(crate) fn json_last_used(time: Option<SystemTime>) -> serde_json::Value {
+    time.map_or(serde_json::Value::Null, |time| to_seconds(time).into())
+}
+
+#[cfg(test)]
+mod last_used_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn database_lines() {
+        let content =
+            "1700000000\tregistry/cache/index.crates.io-6f17d22bba15001f/serde-1.0.0.crate\n\
+                       garbage\n\
+                       1600000000\tgit/checkouts/foo-1234/abcdef\n";
+        let records = parse(content);
+        assert_eq!(records.len(), 2);
+        assert_eq!(
+            records[Path::new("git/checkouts/foo-1234/abcdef")],
+            1_600_000_000
+        );
+        // written sorted by path, without the garbage
+        assert_eq!(
+            to_content(&records),
+            "1600000000\tgit/checkouts/foo-1234/abcdef\n\
+             1700000000\tregistry/cache/index.crates.io-6f17d22bba15001f/serde-1.0.0.crate\n"
+        );
+    }
+
+    #[test]
+    fn database_wins_over_older_file_times() {
+        let cargo_home = tempfile::tempdir().unwrap();
+        let archive = cargo_home.path().join("foo-1.0.0.crate");
+        fs::write(&archive, "archive").unwrap();
+
+        // the database knows of a use in the future, the file system can't beat that
+        let future = to_seconds(SystemTime::now()) + 3600;
+        let database = state_dir::state_file(cargo_home.path(), DB_FILE_NAME);
+        fs::create_dir_all(database.parent().unwrap()).unwrap();
+        fs::write(
+            &database,
+            format!("{future}\tfoo-1.0.0.crate\n1\tgone-1.0.0.crate\n"),
+        )
+        .unwrap();
+        let last_used = LastUsed::load(cargo_home.path());
+        assert_eq!(last_used.get(&archive), Some(from_seconds(future)));
+
+        // nothing newer was seen, the item that is gone is forgotten
+        last_used.save();
+        assert_eq!(
+            fs::read_to_string(&database).unwrap(),
+            format!("{future}\tfoo-1.0.0.crate\n")
+        );
+    }
+
+    #[test]
+    fn newer_file_times_are_recorded() {
+        let cargo_home = tempfile::tempdir().unwrap();
+        let checkout = cargo_home.path().join("checkouts").join("foo-1234");
+        fs::create_dir_all(&checkout).unwrap();
+        let before = to_seconds(SystemTime::now());
+        fs::write(checkout.join("lib.rs"), "").unwrap();
+
+        let last_used = LastUsed::load(cargo_home.path());
+        let seen = last_used.get(&checkout).unwrap();
+        assert!(to_seconds(seen) >= before);
+        assert_eq!(
+            last_used.newest([checkout.as_path(), Path::new("/nonexistent")]),
+            Some(seen)
+        );
+        last_used.save();
+
+        // the next run knows about it even if the files are gone
+        let database = state_dir::state_file(cargo_home.path(), DB_FILE_NAME);
+        let records = parse(&fs::read_to_string(database).unwrap());
+        assert_eq!(
+            records.get(Path::new("checkouts/foo-1234")),
+            Some(&to_seconds(seen))
+        );
+        assert_eq!(
+            LastUsed::load(cargo_home.path()).get(Path::new("/nonexistent")),
+            None
+        );
+    }
+
+    #[test]
+    fn formatting() {
+        assert_eq!(format_last_used(None), "-");
+        assert_eq!(
+            format_last_used(Some(SystemTime::now())).len(),
+            "2023-01-01".len()
+        );
+    }
+}
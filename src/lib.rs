@@ -0,0 +1,96 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! cargo-cache as a library
+//!
+//! The [`ops`] module lets other tools (build orchestrators, IDEs, other cargo subcommands) run the
+//! cleanups of cargo-cache with their own user interface.
+//! The modules below are the ones of the cargo-cache binary, the library only exposes [`ops`]
+//! and, with the `testing` feature, the parsers of cache item names and option values together
+//! with proptest strategies to fuzz them (`testing`).
+
+#![deny(unsafe_code, clippy::unimplemented)]
+#![warn(
+    ellipsis_inclusive_range_patterns,
+    trivial_casts,
+    trivial_numeric_casts,
+    unused,
+    unused_qualifications,
+    unused_results,
+    rust_2018_idioms,
+    missing_docs
+)]
+#![warn(clippy::all, clippy::pedantic)]
+#![allow(clippy::too_many_lines, clippy::unused_self)]
+#![allow(clippy::wildcard_imports)]
+#![allow(clippy::option_if_let_else)]
+#![allow(clippy::upper_case_acronyms)]
+#![allow(clippy::needless_for_each)]
+#![allow(clippy::let_underscore_untyped)]
+// most of the code of the binary is not reachable from ops
+#![allow(dead_code)]
+
+// the "ci-autoclean" build only has the binary, "testing" needs the parsers of the library though
+cfg_if::cfg_if! {
+    if #[cfg(any(not(feature = "ci-autoclean"), feature = "testing"))] {
+        mod approve;
+        mod args;
+        mod cache;
+        mod cancel;
+        #[cfg(feature = "metadata")]
+        mod clean_unref;
+        mod compat;
+        mod commands {
+            #[cfg(feature = "metadata")]
+            pub(crate) mod local;
+            pub(crate) mod trim;
+        }
+        mod date;
+        mod dirsizes;
+        mod error_report;
+        mod history;
+        mod hyperlink;
+        mod ignore;
+        mod incremental;
+        #[cfg(feature = "json")]
+        mod journal;
+        mod keep;
+        mod library;
+        mod lock;
+        mod messages;
+        mod modified;
+        mod names;
+        mod network;
+        mod other_files;
+        mod preflight;
+        mod progress;
+        mod projects;
+        #[cfg(feature = "metadata")]
+        mod rebuild_cost;
+        mod redact;
+        mod registry_names;
+        mod remove;
+        mod retry;
+        mod size_cache;
+        mod state_dir;
+        mod system_trash;
+        mod tables;
+        mod throughput;
+        mod tty;
+        mod walk;
+        mod would_free;
+
+        pub mod ops;
+        #[cfg(feature = "testing")]
+        pub mod testing;
+
+        #[cfg(feature = "metadata")]
+        use crate::commands::local;
+    }
+}
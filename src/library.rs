@@ -7,34 +7,42 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-/// This file provides core logic of the crate
 use std::fmt;
 use std::fmt::Write as _;
+/// This file provides core logic of the crate
 use std::fs;
 use std::path::{Path, PathBuf};
 
-use crate::cache::caches::{Cache, RegistrySuperCache};
-use crate::cache::*;
+use crate::args::parse::ParseError;
+use crate::cancel::is_cancelled;
 use crate::dirsizes::DirSizes;
+use crate::error_report::{self, ErrorDetails};
+use crate::messages::{self, Message};
+use crate::registry_names;
+use crate::retry;
+use crate::walk::walk;
 
 use humansize::{FormatSize, FormatSizeI, DECIMAL};
 use rayon::iter::*;
-use walkdir::WalkDir;
+use sha2::{Digest, Sha256};
+use walkdir::{DirEntry, WalkDir};
 
 // lets us call let z =  None.unwrap_oe_exit_with_error();
 pub(crate) type CargoCacheResult<T, E> = Result<T, E>;
-pub(crate) trait ErrorHandling<T, E: std::fmt::Display> {
+pub(crate) trait ErrorHandling<T, E: ErrorDetails> {
     fn unwrap_or_fatal_error(self) -> T;
     fn exit_or_fatal_error(self);
 }
 
-impl<T, E: std::fmt::Display> ErrorHandling<T, E> for CargoCacheResult<T, E> {
+impl<T, E: ErrorDetails> ErrorHandling<T, E> for CargoCacheResult<T, E> {
     /// return the wrapped value or print the wrapped error and terminate cargo-cache
+    /// errors may contain the output of cargo or git, credentials in there are redacted
+    /// with --json-errors, the error is printed as json
     fn unwrap_or_fatal_error(self) -> T {
         match self {
             Ok(t) => t,
             Err(e) => {
-                eprintln!("{e}");
+                error_report::print(&e);
                 std::process::exit(1);
             }
         }
@@ -47,7 +55,7 @@ impl<T, E: std::fmt::Display> ErrorHandling<T, E> for CargoCacheResult<T, E> {
                 std::process::exit(0);
             }
             Err(e) => {
-                eprintln!("{e}");
+                error_report::print(&e);
                 std::process::exit(1);
             }
         }
@@ -65,11 +73,24 @@ pub(crate) struct DirInfo {
     #[allow(unused)] // used in tests iirc
     pub(crate) file_number: u64,
 }
+/// how the cargo home was found, --info tells the user
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CargoHomeSource {
+    /// passed via --cargo-home or by a user of the library
+    Explicit,
+    /// the `CARGO_HOME` env var
+    Env,
+    /// ~/.cargo
+    Default,
+}
+
 /// `CargoCachePaths` contains paths to all the subcomponents of the cargo cache
 #[derive(Debug, Clone)]
 pub(crate) struct CargoCachePaths {
     /// the root path to the cargo home
     pub(crate) cargo_home: PathBuf,
+    /// how the cargo home was found
+    pub(crate) source: CargoHomeSource,
     /// the directory where installed (cargo install..) binaries are located
     pub(crate) bin_dir: PathBuf,
     /// path where registries are stored
@@ -88,6 +109,17 @@ pub(crate) struct CargoCachePaths {
 
 /// possible errors the crate may encounter, most of them unrecoverable
 #[derive(Debug)]
+// errors of commands that need a feature are not constructed if cargo-cache is built without it
+#[cfg_attr(
+    not(all(
+        feature = "git",
+        feature = "metadata",
+        feature = "query",
+        feature = "verify",
+        feature = "metrics"
+    )),
+    allow(dead_code)
+)]
 pub(crate) enum Error {
     /// git-rs failed to open a git repo
     GitRepoNotOpened(PathBuf),
@@ -115,6 +147,8 @@ pub(crate) enum Error {
     InvalidDeletableDirs(String),
     /// --remove-dir didn't get any args passed
     RemoveDirNoArg,
+    /// --remove-dir named a registry there are no directories of
+    RemoveDirUnknownRegistry(String),
     /// failed to find current working directory
     NoCWD,
     /// failed to find Cargo.toml manifest
@@ -125,22 +159,176 @@ pub(crate) enum Error {
     GitGCFile(PathBuf),
     // local tried to open a target dir that does not exist
     LocalNoTargetDir(PathBuf),
-    // failed to parse date given to younger or older
-    DateParseFailure(String, String),
+    // local --clean-stale-deps needs the Cargo.lock of the project
+    LocalNoLockfile(PathBuf),
+    // local --recursive was given something that is not a directory
+    LocalNoProjectDir(PathBuf),
+    // an argument that should be a size, duration or date could not be parsed
+    InvalidArgument(ParseError),
     // cargo metadata failed to parse a cargo manifest
+    #[cfg(feature = "metadata")]
     UnparsableManifest(PathBuf, cargo_metadata::Error),
-    // could not find sccache cache dir
-    NoSccacheDir,
+    // clean-unref: resolving the dependencies of the manifest needs network access but --allow-net was not passed
+    MetadataNeedsNetwork(PathBuf),
+    // clean-unref: the Cargo.lock of the manifest is missing or out of date, we never update it
+    LockfileNeedsUpdate(PathBuf),
+    // could not find sccache cache dir, contains the path we looked at (if any)
+    NoSccacheDir(Option<PathBuf>),
+    // failed to parse ${SCCACHE_CACHE_SIZE}
+    SccacheCacheSizeParseFailure(String),
+    // a directory that was supposed to be removed is located on a different device than its parent
+    CrossDeviceDeletion(PathBuf),
+    // failed to parse a version requirement
+    InvalidVersionRequirement(String),
+    // crate (name, version requirement) not found in any cached registry index
+    CrateNotInIndex(String, String),
+    // preheat-install found this many crates missing from the cache
+    PreheatIncomplete(usize),
+    // one of the parameters of --components was not recognized
+    InvalidComponents(String),
+    // failed to parse the --bandwidth of "stats --value"
+    InvalidBandwidth(String),
+    // another cargo-cache holds the lock of the cargo home and we were told not to wait
+    CacheLocked(PathBuf),
+    // waited this many seconds for the lock of the cargo home without getting it
+    LockTimeout(PathBuf, u64),
+    // failed to create or lock the lock file
+    LockFailed(PathBuf, std::io::Error),
+    // failed to parse the number of seconds passed via --wait
+    InvalidWaitTime(String),
+    // failed to parse --retries or --retry-backoff
+    InvalidRetryPolicy(String),
+    // failed to parse --max-scan-depth
+    InvalidMaxScanDepth(String),
+    // the pre-flight found this many entries that we would not be able to remove
+    PreflightFailed(usize),
+    // several operations that can not be combined were passed
+    ConflictingOperations(Vec<String>),
+    // the user did not confirm the removal of a large --autoclean
+    AutocleanAborted,
+    // "cargo cache compare" was given the local cargo home
+    CompareSameCargoHome(PathBuf),
+    // failed to copy a crate archive or git repo from another cargo home
+    CopyFailed(PathBuf, std::io::Error),
+    // failed to write the "verify --report" file
+    WriteReportFailed(PathBuf, std::io::Error),
     // could not get rustup home
     NoRustupHome,
     // trim failed to parse the given unit
     TrimLimitUnitParseFailure(String),
+    // the directory passed via --cargo-home does not exist
+    RootNotDirectory(PathBuf),
+    // failed to parse one of the "component=size" limits of "check --fail-if-above"
+    InvalidSizeLimit(String),
+    // "check --fail-if-above" found these components to be bigger than their limit
+    SizeLimitExceeded(Vec<String>),
+    // the --approve-with command failed or did not print a valid list of approved paths
+    ApproveCommandFailed(String, String),
+    // "self check-update --online" failed to get the latest release from crates.io
+    CheckUpdateFailed(String),
+    // the operation needs a cargo feature that cargo-cache was built without
+    FeatureDisabled(String, &'static str),
+    // "git export" found no cached git repo with this name
+    GitExportRepoNotFound(String),
+    // "git export" found several cached git repos with this name
+    GitExportAmbiguousRepo(String, Vec<String>),
+    // "git export" failed to clone the cached repo into the directory
+    GitExportFailed(PathBuf, String),
+    // "git list" or "git rm-checkout" found no repo with this name in git/checkouts
+    GitCheckoutRepoNotFound(String),
+    // "git list" or "git rm-checkout" found several repos with this name in git/checkouts
+    GitCheckoutAmbiguousRepo(String, Vec<String>),
+    // "git rm-checkout" found no checkout of this revision, the revisions that are checked out
+    GitCheckoutRevNotFound(String, String, Vec<String>),
+    // "git rm-checkout" found several checkouts whose revision starts with the prefix
+    GitCheckoutAmbiguousRev(String, Vec<String>),
+    // "doctor --quarantine" failed to move a misplaced entry out of the way
+    QuarantineFailed(PathBuf, std::io::Error),
+    // "inspect" found no cached version of the crate
+    InspectCrateNotFound(String),
+    // "inspect" failed to read the .crate archive
+    InspectFailed(PathBuf, std::io::Error),
+    // the operation needs network access but --offline, CARGO_NET_OFFLINE or net.offline forbid it
+    NetworkDisabled(String, String),
+    // "selftest-sizes" found differences it can't explain, the names of the components
+    SelftestSizesMismatch(Vec<String>),
+    // "backup" failed to add a file to the archive or to write the archive
+    BackupFailed(PathBuf, std::io::Error),
+    // "restore" failed to read the archive or to unpack an entry
+    RestoreFailed(PathBuf, std::io::Error),
+    // "backup" / "restore" only support .tar.gz and .tar archives
+    UnsupportedArchiveFormat(PathBuf),
+    // "restore" was passed an archive that was not written by "backup"
+    NotABackup(PathBuf),
+    // "--output-format json" was passed to a command that only prints tables
+    OutputFormatUnsupported(String),
+    // the value of an environment variable such as CARGO_CACHE_TRIM_LIMIT is invalid
+    InvalidEnvVar(&'static str, Box<Error>),
+    // the config file could not be read or contains an invalid line
+    InvalidConfig(PathBuf, String),
+    // trim got neither --limit nor --keep-newer-than and the config file sets no trim.limit
+    TrimPolicyMissing,
+    // maintain got neither --autoclean-above nor --gc-every-days and the config file sets neither
+    MaintainPolicyMissing,
+    // "cargo cache ui" was run without a terminal to ask the user
+    UiNeedsTerminal,
+    // "cargo cache remove" got something else than <crate> or <crate>@<version>
+    InvalidCrateSpec(String),
+    // "cargo cache remove" found nothing of the crate, whether git repos were searched as well
+    RemoveCrateNotFound(String, bool),
+    // "query --delete" without patterns, --larger-than or --older-than would remove the whole cache
+    QueryDeleteEverything,
+    // the cargo home is "/", the home directory or does not look like a cargo home at all
+    UnsafeCargoHome(PathBuf, &'static str),
+    // "--lang" names a language no message catalog was found for
+    UnknownLanguage(String),
+    // a message catalog contains a line that is not "key = text"
+    InvalidMessageCatalog(PathBuf, String),
+    // "metrics --push" got no url and the config file sets no metrics.url
+    MetricsNoUrl,
+    // "metrics --push" only supports http://host[:port][/path]
+    MetricsUnsupportedUrl(String),
+    // "metrics --push" was given an https url, we can not speak TLS
+    MetricsHttpsUnsupported(String),
+    // "metrics --push" could not reach the endpoint or the endpoint refused the metrics
+    MetricsPushFailed(String, String),
+    // a --cargo-home glob such as "/srv/cargo/*" matched no directory
+    CargoHomeGlobNoMatch(String),
+    // the command was given several cargo homes but only works on one
+    SingleCargoHomeOnly(&'static str),
+    // the command failed for some of several cargo homes
+    CargoHomesFailed(Vec<PathBuf>),
+    // "toolchain --remove-toolchain" was given a toolchain that is not installed (or a prefix of several)
+    ToolchainNotInstalled(String, Vec<String>),
+    // the user did not confirm the removal of a toolchain or its docs
+    ToolchainRemovalAborted,
+    // rustup or we failed to remove a toolchain or a component of one
+    ToolchainRemovalFailed(String, String),
+    // "dedupe --reflink" on a file system that does not share the data of copies
+    ReflinksUnsupported(PathBuf),
+    // "projects add" was given a path that is neither a project directory, nor its Cargo.toml or Cargo.lock
+    ProjectNoManifest(PathBuf),
+    // "projects remove" was given a project that is not registered
+    ProjectNotRegistered(PathBuf),
+    // failed to update the list of registered projects
+    ProjectsWriteFailed(PathBuf, std::io::Error),
+    // "clean-unref --all-projects" without any registered projects
+    NoProjects(PathBuf),
+    // the manifest of a registered project is gone, "clean-unref --all-projects" would remove what it needs
+    RegisteredProjectMissing(PathBuf),
+}
+
+impl From<ParseError> for Error {
+    fn from(error: ParseError) -> Self {
+        Self::InvalidArgument(error)
+    }
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let valid_deletable_dirs =
-            "git-db,git-repos,registry-sources,registry-crate-cache,registry-index,registry,all";
+            "git-db,git-repos,registry-sources,registry-crate-cache,registry-index,registry-index-sparse,registry,all \
+            (the registry groups may name a single registry: registry-crate-cache:<registry>)";
 
         match &self {
             Self::GitRepoNotOpened(path) => {
@@ -186,7 +374,7 @@ impl fmt::Display for Error {
                 error
             ),
 
-            Self::GitNotInstalled => write!(f, "Could not find 'git' binary. Is 'git' installed?",),
+            Self::GitNotInstalled => write!(f, "Could not find 'git' binary. Is 'git' installed?"),
 
             Self::MalformedPackageName(pkgname) => {
                 write!(f, "Error:  \"{pkgname}\" is not a valid package name")
@@ -209,7 +397,11 @@ impl fmt::Display for Error {
                 f,
                 "No argument passed to \"--remove-dir\"! Chose one or several from {valid_deletable_dirs}"
             ),
-            Self::NoCWD => write!(f, "Failed to find current working directory!",),
+            Self::RemoveDirUnknownRegistry(registry) => write!(
+                f,
+                "\"--remove-dir\": there is no registry \"{registry}\" in the cache!"
+            ),
+            Self::NoCWD => write!(f, "Failed to find current working directory!"),
             Self::NoCargoManifest(dir) => write!(
                 f,
                 "Failed to find Cargo.toml manifest in {} or downwards.",
@@ -229,12 +421,18 @@ impl fmt::Display for Error {
                 directory that does not exist: \"{}\"",
                 path.display()
             ),
-            Self::DateParseFailure(date, error) => {
-                write!(
-                    f,
-                    "ERROR failed to parse '{date}' as date {error}, expected YYYY.MM.DD or HH:MM:SS "
-                )
-            }
+            Self::LocalNoLockfile(path) => write!(
+                f,
+                "Failed to read \"{}\", it is needed to find the dependencies that are no longer used",
+                path.display()
+            ),
+            Self::LocalNoProjectDir(path) => write!(
+                f,
+                "\"local --recursive\" needs a directory to look for projects in, \"{}\" is none",
+                path.display()
+            ),
+            Self::InvalidArgument(error) => write!(f, "{error}"),
+            #[cfg(feature = "metadata")]
             Self::UnparsableManifest(path, error) => write!(
                 f,
                 "Failed to parse Cargo.toml at '{}': '{:?}'",
@@ -242,9 +440,109 @@ impl fmt::Display for Error {
                 error
             ),
 
-            Self::NoSccacheDir => {
+            Self::MetadataNeedsNetwork(path) => write!(
+                f,
+                "Failed to resolve the dependencies of \"{}\" without network access since some of them are not in the cache. \
+                Pass --allow-net to let cargo access the network.",
+                path.display()
+            ),
+            Self::LockfileNeedsUpdate(path) => write!(
+                f,
+                "The Cargo.lock of \"{}\" is missing or out of date, clean-unref never modifies it. \
+                Run \"cargo generate-lockfile\" or \"cargo update\" first.",
+                path.display()
+            ),
+
+            Self::NoSccacheDir(None) => {
                 write!(f,
-                "Could not find sccache cache directory at ~/.cache/sccache or ${{SCCACHE_DIR}}")
+                "Could not determine sccache cache directory, try setting ${{SCCACHE_DIR}}")
+            }
+            Self::NoSccacheDir(Some(path)) => write!(
+                f,
+                "Could not find sccache cache directory at \"{}\", set ${{SCCACHE_DIR}} if it is located elsewhere",
+                path.display()
+            ),
+            Self::SccacheCacheSizeParseFailure(size) => write!(
+                f,
+                "Failed to parse ${{SCCACHE_CACHE_SIZE}}: \"{size}\". \
+                Should be of the form 123X where X is one of K,M,G or T."
+            ),
+            Self::CrossDeviceDeletion(path) => write!(
+                f,
+                "Refusing to remove \"{}\" since it is located on a different device than its parent directory. \
+                Pass --allow-cross-device to remove it anyway.",
+                path.display()
+            ),
+            Self::InvalidVersionRequirement(version) => {
+                write!(f, "Failed to parse version requirement \"{version}\"")
+            }
+            Self::CrateNotInIndex(name, req) => write!(
+                f,
+                "Could not find \"{name}\" matching \"{req}\" in any cached registry index. \
+                Run \"cargo search {name}\" or \"cargo fetch\" in a project depending on it to update the index."
+            ),
+            Self::PreheatIncomplete(missing) => write!(
+                f,
+                "\n{missing} crates are missing from the cache, an offline install will not work."
+            ),
+            Self::InvalidComponents(components) => write!(
+                f,
+                "\"{components}\" are not valid components! Chose one or several from {valid_deletable_dirs}"
+            ),
+            Self::InvalidBandwidth(bandwidth) => write!(
+                f,
+                "Failed to parse bandwidth: \"{bandwidth}\". \
+                Should be a positive number of bytes per second, optionally followed by one of B,K,M or G."
+            ),
+            Self::CacheLocked(path) => write!(
+                f,
+                "The cargo cache is locked by another cargo-cache process (\"{}\"), not waiting because of --no-wait",
+                path.display()
+            ),
+            Self::LockTimeout(path, seconds) => write!(
+                f,
+                "Timed out after waiting {seconds} seconds for the lock \"{}\" held by another cargo-cache process",
+                path.display()
+            ),
+            Self::LockFailed(path, error) => write!(
+                f,
+                "Failed to lock \"{}\": {error}",
+                path.display()
+            ),
+            Self::InvalidWaitTime(seconds) => write!(
+                f,
+                "Failed to parse --wait \"{seconds}\", expected a number of seconds"
+            ),
+            Self::InvalidRetryPolicy(number) => write!(
+                f,
+                "Failed to parse \"{number}\" passed to --retries or --retry-backoff, expected a positive number"
+            ),
+            Self::InvalidMaxScanDepth(depth) => write!(
+                f,
+                "Failed to parse \"{depth}\" passed to --max-scan-depth, expected a number bigger than 0"
+            ),
+            Self::PreflightFailed(blocked) => write!(
+                f,
+                "Not removing anything since {blocked} entries can not be removed, pass --keep-going to remove everything else anyway"
+            ),
+            Self::ConflictingOperations(operations) => write!(
+                f,
+                "Can not combine {}, pass only one of them. \
+                Only --autoclean and --gc (same as --autoclean-expensive) or --remove-dir and \
+                --remove-if-older-than/--remove-if-younger-than can be combined",
+                operations.join(", ")
+            ),
+            Self::AutocleanAborted => write!(f, "Aborted, nothing was removed"),
+            Self::CompareSameCargoHome(path) => write!(
+                f,
+                "Can not compare \"{}\" with itself, pass the path of another cargo home",
+                path.display()
+            ),
+            Self::CopyFailed(path, error) => {
+                write!(f, "Failed to copy \"{}\": {}", path.display(), error)
+            }
+            Self::WriteReportFailed(path, error) => {
+                write!(f, "Failed to write report \"{}\": {}", path.display(), error)
             }
             Self::NoRustupHome => write!(f, "Failed to determine rustup home directory"),
             Self::TrimLimitUnitParseFailure(limit) => write!(
@@ -252,6 +550,424 @@ impl fmt::Display for Error {
                 "Failed to parse limit: \"{limit}\". \
                 Should be of the form 123X where X is one of B,K,M,G or T."
             ),
+            Self::RootNotDirectory(path) => write!(
+                f,
+                "--cargo-home \"{}\" is not an existing directory",
+                path.display()
+            ),
+            Self::InvalidSizeLimit(limit) => write!(
+                f,
+                "Failed to parse size limit \"{limit}\". Should be of the form component=123X where X is one of B,K,M,G or T \
+                and component is one of total,bin,registry,registry-index,registry-crate-cache,registry-sources,git,git-db,git-repos"
+            ),
+            Self::FeatureDisabled(operation, feature) => write!(
+                f,
+                "\"{operation}\" is not available, cargo-cache was built without the \"{feature}\" feature.\n\
+                Reinstall with: cargo install cargo-cache --features {feature}"
+            ),
+            Self::GitExportRepoNotFound(repo) => write!(
+                f,
+                "No cached git repo named \"{repo}\" found in git/db"
+            ),
+            Self::GitExportAmbiguousRepo(repo, repos) => write!(
+                f,
+                "\"{repo}\" matches several cached git repos, pass one of: {}",
+                repos.join(", ")
+            ),
+            Self::GitExportFailed(dest, reason) => write!(
+                f,
+                "Failed to export git repo to \"{}\": {reason}",
+                dest.display()
+            ),
+            Self::GitCheckoutRepoNotFound(repo) => write!(
+                f,
+                "No git repo named \"{repo}\" is checked out in git/checkouts"
+            ),
+            Self::GitCheckoutAmbiguousRepo(repo, repos) => write!(
+                f,
+                "\"{repo}\" matches several repos in git/checkouts, pass one of: {}",
+                repos.join(", ")
+            ),
+            Self::GitCheckoutRevNotFound(repo, rev, revs) => write!(
+                f,
+                "No checkout of \"{repo}\" matches the revision \"{rev}\", checked out are: {}",
+                revs.join(", ")
+            ),
+            Self::GitCheckoutAmbiguousRev(rev, revs) => write!(
+                f,
+                "The revision \"{rev}\" matches several checkouts, pass one of: {}",
+                revs.join(", ")
+            ),
+            Self::QuarantineFailed(path, error) => write!(
+                f,
+                "Failed to move \"{}\" to the quarantine: {error}",
+                path.display()
+            ),
+            Self::InspectCrateNotFound(krate) => write!(
+                f,
+                "No cached version of \"{krate}\" found in registry/cache or registry/src"
+            ),
+            Self::InspectFailed(archive, error) => write!(
+                f,
+                "Failed to read the crate archive \"{}\": {error}",
+                archive.display()
+            ),
+            Self::NetworkDisabled(operation, reason) => write!(
+                f,
+                "\"{operation}\" needs network access, but cargo-cache is offline: {reason}"
+            ),
+            Self::SelftestSizesMismatch(components) => write!(
+                f,
+                "The sizes of {} do not match the full scan, please report this as a bug of cargo-cache",
+                components.join(", ")
+            ),
+            Self::BackupFailed(path, error) => {
+                write!(f, "Failed to back up \"{}\": {error}", path.display())
+            }
+            Self::RestoreFailed(archive, error) => write!(
+                f,
+                "Failed to restore from \"{}\": {error}",
+                archive.display()
+            ),
+            Self::UnsupportedArchiveFormat(archive) => write!(
+                f,
+                "\"{}\" is not a supported archive, only .tar.gz, .tgz, .tar.zst, .tzst and .tar are supported",
+                archive.display()
+            ),
+            Self::OutputFormatUnsupported(command) => write!(
+                f,
+                "\"--output-format json\" is only supported by the summary, --info, registry, --top-cache-items and trim --plan, not by \"{command}\""
+            ),
+            Self::InvalidEnvVar(var, error) => {
+                write!(f, "Invalid value of the environment variable {var}: {error}")
+            }
+            Self::InvalidConfig(path, error) => {
+                write!(f, "Invalid config file \"{}\": {error}", path.display())
+            }
+            Self::TrimPolicyMissing => write!(
+                f,
+                "trim needs --limit or --keep-newer-than, or a trim.limit in the config file"
+            ),
+            Self::MaintainPolicyMissing => write!(
+                f,
+                "maintain needs --autoclean-above or --gc-every-days, or a [maintain] table in the config file"
+            ),
+            Self::UiNeedsTerminal => write!(
+                f,
+                "\"cargo cache ui\" needs a terminal to ask which items to remove"
+            ),
+            Self::InvalidCrateSpec(spec) => write!(
+                f,
+                "Invalid crate \"{spec}\", expected <crate> or <crate>@<version>"
+            ),
+            Self::QueryDeleteEverything => write!(
+                f,
+                "\"query --delete\" needs a pattern, --larger-than or --older-than, it would remove the whole cache otherwise"
+            ),
+            Self::UnsafeCargoHome(path, reason) => write!(
+                f,
+                "Refusing to remove anything from \"{}\", {reason}. Check $CARGO_HOME and --cargo-home, \
+                or pass --i-know-what-i-am-doing if this really is the cargo home",
+                path.display()
+            ),
+            Self::UnknownLanguage(lang) => write!(
+                f,
+                "No message catalog \"{lang}.messages\" found in $CARGO_CACHE_MESSAGES_DIR, \
+                ~/.config/cargo-cache/messages or /usr/share/cargo-cache/messages"
+            ),
+            Self::InvalidMessageCatalog(path, error) => {
+                write!(f, "Invalid message catalog \"{}\": {error}", path.display())
+            }
+            Self::MetricsNoUrl => write!(
+                f,
+                "\"metrics --push\" needs a url, pass one or set metrics.url in the config file"
+            ),
+            Self::MetricsUnsupportedUrl(url) => write!(
+                f,
+                "Unsupported metrics url \"{url}\", only http://host[:port][/path] is supported"
+            ),
+            Self::MetricsHttpsUnsupported(url) => write!(
+                f,
+                "Can not push the metrics to \"{url}\", https is not supported. Push to an http:// url, \
+                for example a collector inside your network or a local relay that forwards over TLS"
+            ),
+            Self::MetricsPushFailed(url, reason) => {
+                write!(f, "Failed to push the metrics to \"{url}\": {reason}")
+            }
+            Self::CargoHomeGlobNoMatch(glob) => {
+                write!(f, "--cargo-home \"{glob}\" matches no directory")
+            }
+            Self::SingleCargoHomeOnly(command) => write!(
+                f,
+                "\"{command}\" works on one cargo home at a time, pass only one --cargo-home"
+            ),
+            Self::CargoHomesFailed(cargo_homes) => write!(
+                f,
+                "Failed for {} cargo homes: {}",
+                cargo_homes.len(),
+                cargo_homes
+                    .iter()
+                    .map(|cargo_home| format!("\"{}\"", cargo_home.display()))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Self::ToolchainNotInstalled(name, installed) => write!(
+                f,
+                "No single toolchain \"{name}\" is installed, installed toolchains: {}",
+                installed.join(", ")
+            ),
+            Self::ToolchainRemovalAborted => write!(f, "Aborted, nothing was removed"),
+            Self::ToolchainRemovalFailed(what, reason) => {
+                write!(f, "Failed to remove {what}: {reason}")
+            }
+            Self::ReflinksUnsupported(path) => write!(
+                f,
+                "The file system of \"{}\" does not support reflinks, run dedupe without --reflink to use hardlinks",
+                path.display()
+            ),
+            Self::ProjectNoManifest(path) => write!(
+                f,
+                "Failed to find a Cargo.toml for \"{}\", pass a project directory, its Cargo.toml or its Cargo.lock",
+                path.display()
+            ),
+            Self::ProjectNotRegistered(path) => write!(
+                f,
+                "\"{}\" is not a registered project, see \"cargo cache projects\"",
+                path.display()
+            ),
+            Self::ProjectsWriteFailed(path, error) => write!(
+                f,
+                "Failed to update the list of projects \"{}\": {}",
+                path.display(),
+                error
+            ),
+            Self::NoProjects(cargo_home) => write!(
+                f,
+                "No projects are registered in \"{}\", add them with \"cargo cache projects add <path>\"",
+                cargo_home.display()
+            ),
+            Self::RegisteredProjectMissing(manifest) => write!(
+                f,
+                "The registered project \"{}\" does not exist anymore, \
+                 remove it with \"cargo cache projects remove\" if it is not needed",
+                manifest.display()
+            ),
+            Self::RemoveCrateNotFound(spec, git) => write!(
+                f,
+                "Nothing of \"{spec}\" found in {}",
+                if *git {
+                    "registry/cache, registry/src, git/db or git/checkouts"
+                } else {
+                    "registry/cache or registry/src"
+                }
+            ),
+            Self::NotABackup(archive) => write!(
+                f,
+                "\"{}\" was not written by \"cargo cache backup\", refusing to restore it",
+                archive.display()
+            ),
+            Self::CheckUpdateFailed(reason) => write!(
+                f,
+                "Failed to get the latest release of cargo-cache from crates.io: {reason}"
+            ),
+            Self::ApproveCommandFailed(command, reason) => write!(
+                f,
+                "--approve-with \"{command}\" failed, nothing was removed: {reason}"
+            ),
+            Self::SizeLimitExceeded(components) => write!(
+                f,
+                "\nSize limit exceeded: {}",
+                components.join(", ")
+            ),
+        }
+    }
+}
+
+impl ErrorDetails for Error {
+    fn code(&self) -> &'static str {
+        match self {
+            Self::GitRepoNotOpened(..) => "git-repo-not-opened",
+            Self::GitRepoDirNotFound(..) => "git-repo-dir-not-found",
+            Self::GitGCFailed(..) => "git-gc-failed",
+            Self::GitPackRefsFailed(..) => "git-pack-refs-failed",
+            Self::GitReflogFailed(..) => "git-reflog-failed",
+            Self::GitFsckFailed(..) => "git-fsck-failed",
+            Self::GitRepackFailed(..) => "git-repack-failed",
+            Self::GitNotInstalled => "git-not-installed",
+            Self::MalformedPackageName(..) => "malformed-package-name",
+            Self::GetCargoHomeFailed => "get-cargo-home-failed",
+            Self::CargoHomeNotDirectory(..) => "cargo-home-not-directory",
+            Self::InvalidDeletableDirs(..) => "invalid-deletable-dirs",
+            Self::RemoveDirNoArg => "remove-dir-no-arg",
+            Self::RemoveDirUnknownRegistry(..) => "remove-dir-unknown-registry",
+            Self::NoCWD => "no-cwd",
+            Self::NoCargoManifest(..) => "no-cargo-manifest",
+            Self::QueryRegexFailedParsing(..) => "query-regex-failed-parsing",
+            Self::GitGCFile(..) => "git-gc-file",
+            Self::LocalNoTargetDir(..) => "local-no-target-dir",
+            Self::LocalNoLockfile(..) => "local-no-lockfile",
+            Self::LocalNoProjectDir(..) => "local-no-project-dir",
+            Self::InvalidArgument(..) => "invalid-argument",
+            #[cfg(feature = "metadata")]
+            Self::UnparsableManifest(..) => "unparsable-manifest",
+            Self::MetadataNeedsNetwork(..) => "metadata-needs-network",
+            Self::LockfileNeedsUpdate(..) => "lockfile-needs-update",
+            Self::NoSccacheDir(..) => "no-sccache-dir",
+            Self::SccacheCacheSizeParseFailure(..) => "sccache-cache-size-parse-failure",
+            Self::CrossDeviceDeletion(..) => "cross-device-deletion",
+            Self::InvalidVersionRequirement(..) => "invalid-version-requirement",
+            Self::CrateNotInIndex(..) => "crate-not-in-index",
+            Self::PreheatIncomplete(..) => "preheat-incomplete",
+            Self::InvalidComponents(..) => "invalid-components",
+            Self::InvalidBandwidth(..) => "invalid-bandwidth",
+            Self::CacheLocked(..) => "cache-locked",
+            Self::LockTimeout(..) => "lock-timeout",
+            Self::LockFailed(..) => "lock-failed",
+            Self::InvalidWaitTime(..) => "invalid-wait-time",
+            Self::InvalidRetryPolicy(..) => "invalid-retry-policy",
+            Self::InvalidMaxScanDepth(..) => "invalid-max-scan-depth",
+            Self::PreflightFailed(..) => "preflight-failed",
+            Self::ConflictingOperations(..) => "conflicting-operations",
+            Self::AutocleanAborted => "autoclean-aborted",
+            Self::CompareSameCargoHome(..) => "compare-same-cargo-home",
+            Self::CopyFailed(..) => "copy-failed",
+            Self::WriteReportFailed(..) => "write-report-failed",
+            Self::NoRustupHome => "no-rustup-home",
+            Self::TrimLimitUnitParseFailure(..) => "trim-limit-unit-parse-failure",
+            Self::RootNotDirectory(..) => "root-not-directory",
+            Self::InvalidSizeLimit(..) => "invalid-size-limit",
+            Self::SizeLimitExceeded(..) => "size-limit-exceeded",
+            Self::ApproveCommandFailed(..) => "approve-command-failed",
+            Self::CheckUpdateFailed(..) => "check-update-failed",
+            Self::FeatureDisabled(..) => "feature-disabled",
+            Self::GitExportRepoNotFound(..) => "git-export-repo-not-found",
+            Self::GitExportAmbiguousRepo(..) => "git-export-ambiguous-repo",
+            Self::GitExportFailed(..) => "git-export-failed",
+            Self::GitCheckoutRepoNotFound(..) => "git-checkout-repo-not-found",
+            Self::GitCheckoutAmbiguousRepo(..) => "git-checkout-ambiguous-repo",
+            Self::GitCheckoutRevNotFound(..) => "git-checkout-rev-not-found",
+            Self::GitCheckoutAmbiguousRev(..) => "git-checkout-ambiguous-rev",
+            Self::QuarantineFailed(..) => "quarantine-failed",
+            Self::InspectCrateNotFound(..) => "inspect-crate-not-found",
+            Self::InspectFailed(..) => "inspect-failed",
+            Self::NetworkDisabled(..) => "network-disabled",
+            Self::SelftestSizesMismatch(..) => "selftest-sizes-mismatch",
+            Self::BackupFailed(..) => "backup-failed",
+            Self::RestoreFailed(..) => "restore-failed",
+            Self::UnsupportedArchiveFormat(..) => "unsupported-archive-format",
+            Self::NotABackup(..) => "not-a-backup",
+            Self::OutputFormatUnsupported(..) => "output-format-unsupported",
+            Self::InvalidEnvVar(..) => "invalid-env-var",
+            Self::InvalidConfig(..) => "invalid-config",
+            Self::TrimPolicyMissing => "trim-policy-missing",
+            Self::MaintainPolicyMissing => "maintain-policy-missing",
+            Self::UiNeedsTerminal => "ui-needs-terminal",
+            Self::InvalidCrateSpec(..) => "invalid-crate-spec",
+            Self::RemoveCrateNotFound(..) => "remove-crate-not-found",
+            Self::QueryDeleteEverything => "query-delete-everything",
+            Self::UnsafeCargoHome(..) => "unsafe-cargo-home",
+            Self::UnknownLanguage(..) => "unknown-language",
+            Self::InvalidMessageCatalog(..) => "invalid-message-catalog",
+            Self::MetricsNoUrl => "metrics-no-url",
+            Self::MetricsUnsupportedUrl(..) => "metrics-unsupported-url",
+            Self::MetricsHttpsUnsupported(..) => "metrics-https-unsupported",
+            Self::MetricsPushFailed(..) => "metrics-push-failed",
+            Self::CargoHomeGlobNoMatch(..) => "cargo-home-glob-no-match",
+            Self::SingleCargoHomeOnly(..) => "single-cargo-home-only",
+            Self::CargoHomesFailed(..) => "cargo-homes-failed",
+            Self::ToolchainNotInstalled(..) => "toolchain-not-installed",
+            Self::ToolchainRemovalAborted => "toolchain-removal-aborted",
+            Self::ToolchainRemovalFailed(..) => "toolchain-removal-failed",
+            Self::ReflinksUnsupported(..) => "reflinks-unsupported",
+            Self::ProjectNoManifest(..) => "project-no-manifest",
+            Self::ProjectNotRegistered(..) => "project-not-registered",
+            Self::ProjectsWriteFailed(..) => "projects-write-failed",
+            Self::NoProjects(..) => "no-projects",
+            Self::RegisteredProjectMissing(..) => "registered-project-missing",
+        }
+    }
+
+    fn path(&self) -> Option<&Path> {
+        match self {
+            Self::GitRepoNotOpened(path)
+            | Self::GitRepoDirNotFound(path)
+            | Self::GitGCFailed(path, _)
+            | Self::GitPackRefsFailed(path, _)
+            | Self::GitReflogFailed(path, _)
+            | Self::GitFsckFailed(path, _)
+            | Self::GitRepackFailed(path, _)
+            | Self::CargoHomeNotDirectory(path)
+            | Self::ReflinksUnsupported(path)
+            | Self::ProjectNoManifest(path)
+            | Self::ProjectNotRegistered(path)
+            | Self::ProjectsWriteFailed(path, _)
+            | Self::NoProjects(path)
+            | Self::RegisteredProjectMissing(path)
+            | Self::NoCargoManifest(path)
+            | Self::GitGCFile(path)
+            | Self::LocalNoTargetDir(path)
+            | Self::LocalNoLockfile(path)
+            | Self::LocalNoProjectDir(path)
+            | Self::MetadataNeedsNetwork(path)
+            | Self::LockfileNeedsUpdate(path)
+            | Self::CrossDeviceDeletion(path)
+            | Self::CacheLocked(path)
+            | Self::LockTimeout(path, _)
+            | Self::LockFailed(path, _)
+            | Self::CompareSameCargoHome(path)
+            | Self::CopyFailed(path, _)
+            | Self::WriteReportFailed(path, _)
+            | Self::RootNotDirectory(path)
+            | Self::GitExportFailed(path, _)
+            | Self::QuarantineFailed(path, _)
+            | Self::InspectFailed(path, _)
+            | Self::BackupFailed(path, _)
+            | Self::RestoreFailed(path, _)
+            | Self::UnsupportedArchiveFormat(path)
+            | Self::NotABackup(path)
+            | Self::InvalidConfig(path, _)
+            | Self::UnsafeCargoHome(path, _)
+            | Self::InvalidMessageCatalog(path, _) => Some(path),
+            #[cfg(feature = "metadata")]
+            Self::UnparsableManifest(path, _) => Some(path),
+            Self::NoSccacheDir(path) => path.as_deref(),
+            Self::InvalidEnvVar(_, error) => error.path(),
+            _ => None,
+        }
+    }
+
+    fn component(&self) -> Option<&'static str> {
+        match self {
+            Self::CrateNotInIndex(..) => Some(Component::RegistryIndex.name()),
+            Self::InspectCrateNotFound(_) => Some(Component::RegistryCrateCache.name()),
+            Self::GitExportRepoNotFound(_) | Self::GitExportAmbiguousRepo(..) => {
+                Some(Component::GitDB.name())
+            }
+            Self::GitCheckoutRepoNotFound(_)
+            | Self::GitCheckoutAmbiguousRepo(..)
+            | Self::GitCheckoutRevNotFound(..)
+            | Self::GitCheckoutAmbiguousRev(..) => Some(Component::GitRepos.name()),
+            _ => self.path().and_then(Component::of_path).map(|c| c.name()),
+        }
+    }
+
+    fn io_error(&self) -> Option<&std::io::Error> {
+        match self {
+            Self::GitGCFailed(_, error)
+            | Self::GitPackRefsFailed(_, error)
+            | Self::GitReflogFailed(_, error)
+            | Self::GitFsckFailed(_, error)
+            | Self::GitRepackFailed(_, error)
+            | Self::LockFailed(_, error)
+            | Self::CopyFailed(_, error)
+            | Self::WriteReportFailed(_, error)
+            | Self::QuarantineFailed(_, error)
+            | Self::InspectFailed(_, error)
+            | Self::BackupFailed(_, error)
+            | Self::RestoreFailed(_, error)
+            | Self::ProjectsWriteFailed(_, error) => Some(error),
+            Self::InvalidEnvVar(_, error) => error.io_error(),
+            _ => None,
         }
     }
 }
@@ -264,7 +980,19 @@ impl CargoCachePaths {
         } else {
             return Err(Error::GetCargoHomeFailed);
         };
+        // the home crate ignores an empty $CARGO_HOME
+        let source = if std::env::var_os("CARGO_HOME").map_or(false, |var| !var.is_empty()) {
+            CargoHomeSource::Env
+        } else {
+            CargoHomeSource::Default
+        };
+
+        Self::new(cargo_home).map(|paths| Self { source, ..paths })
+    }
 
+    /// returns `CargoCachePaths` of the cargo home at the given path
+    /// this can be any directory that looks like a cargo home, see `--cargo-home`
+    pub(crate) fn new(cargo_home: PathBuf) -> Result<Self, Error> {
         if !cargo_home.is_dir() {
             return Err(Error::CargoHomeNotDirectory(cargo_home));
         }
@@ -279,6 +1007,7 @@ impl CargoCachePaths {
 
         Ok(Self {
             cargo_home,
+            source: CargoHomeSource::Explicit,
             bin_dir: bin,
             registry,
             registry_index,
@@ -288,10 +1017,73 @@ impl CargoCachePaths {
             git_checkouts,
         })
     }
+
+    /// returns `CargoCachePaths` of a directory passed via --cargo-home, for example a mounted CI cache
+    pub(crate) fn from_root(root: PathBuf) -> Result<Self, Error> {
+        if !root.is_dir() {
+            return Err(Error::RootNotDirectory(root));
+        }
+        Self::new(root)
+    }
+
+    /// ways in which the directory does not look like a cargo home
+    /// we can still scan it but the output might not make much sense
+    pub(crate) fn layout_mismatches(&self) -> Vec<String> {
+        let git = self.cargo_home.join("git");
+        let toplevel = [&self.bin_dir, &self.registry, &git];
+        if !toplevel.iter().any(|dir| dir.exists()) {
+            return vec![format!(
+                "\"{}\" contains neither \"bin\", \"registry\" nor \"git\", it does not look like a cargo home",
+                self.cargo_home.display()
+            )];
+        }
+
+        toplevel
+            .into_iter()
+            .chain([
+                &self.registry_index,
+                &self.registry_pkg_cache,
+                &self.registry_sources,
+                &self.git_repos_bare,
+                &self.git_checkouts,
+            ])
+            .filter(|path| path.exists() && !path.is_dir())
+            .map(|path| format!("\"{}\" is not a directory", path.display()))
+            .collect()
+    }
+
+    /// refuse to remove anything if the cargo home is the root of the file system, the home
+    /// directory or a directory with content but without a cache of cargo, a misconfigured
+    /// `$CARGO_HOME` or `--cargo-home` must not make us remove the wrong things
+    /// an empty cargo home is fine, there is nothing to lose
+    pub(crate) fn check_safe_to_modify(&self, home_dir: Option<&Path>) -> Result<(), Error> {
+        let canonical = |path: &Path| fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        let cargo_home = canonical(&self.cargo_home);
+
+        let reason = if cargo_home.parent().is_none() {
+            "it is the root of the file system"
+        } else if home_dir.map_or(false, |home_dir| canonical(home_dir) == cargo_home) {
+            "it is your home directory"
+        } else if fs::read_dir(&cargo_home).map_or(false, |mut entries| entries.next().is_some())
+            && !CARGO_HOME_ENTRIES
+                .iter()
+                .any(|entry| cargo_home.join(entry).exists())
+        {
+            "it contains neither registry/, git/ nor .crates.toml of cargo"
+        } else {
+            return Ok(());
+        };
+        Err(Error::UnsafeCargoHome(self.cargo_home.clone(), reason))
+    }
 } // impl CargoCachePaths
 
+/// files and directories only cargo creates in its home, a cargo home we may modify contains at
+/// least one of them
+/// a bin/, config or env alone can be in any directory, "~/.local" has a bin/ as well
+const CARGO_HOME_ENTRIES: &[&str] = &["registry", "git", ".crates.toml"];
+
 // this is the output of `cargo cache --list-dirs`
-impl std::fmt::Display for CargoCachePaths {
+impl fmt::Display for CargoCachePaths {
     fn fmt(&self, f: &'_ mut fmt::Formatter<'_>) -> fmt::Result {
         writeln!(
             f,
@@ -343,6 +1135,8 @@ pub(crate) enum RemovableGroup {
     RegistrySources,
     RegistryCrateCache,
     RegistryIndex,
+    // only the sparse indices inside registry/index
+    RegistryIndexSparse,
     Registry,
 }
 
@@ -357,6 +1151,7 @@ impl std::str::FromStr for RemovableGroup {
             "registry-sources" => Ok(RemovableGroup::RegistrySources),
             "registry-crate-cache" => Ok(RemovableGroup::RegistryCrateCache),
             "registry-index" => Ok(RemovableGroup::RegistryIndex),
+            "registry-index-sparse" => Ok(RemovableGroup::RegistryIndexSparse),
             "registry" => Ok(RemovableGroup::Registry),
             other => Err(other.to_string()),
         }
@@ -374,6 +1169,283 @@ pub(crate) enum Component {
     RegistryIndex,      // registry/index
 }
 
+impl Component {
+    /// the name of the component as it is passed on the command line
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Component::GitDB => "git-db",
+            Component::GitRepos => "git-repos",
+            Component::RegistrySources => "registry-sources",
+            Component::RegistryCrateCache => "registry-crate-cache",
+            Component::RegistryIndex => "registry-index",
+        }
+    }
+
+    /// the component a path inside the cargo home belongs to, if any
+    pub(crate) fn of_path(path: &Path) -> Option<Self> {
+        let names: Vec<_> = path.iter().collect();
+        names
+            .windows(2)
+            .find_map(|pair| match (pair[0].to_str()?, pair[1].to_str()?) {
+                ("git", "db") => Some(Component::GitDB),
+                ("git", "checkouts") => Some(Component::GitRepos),
+                ("registry", "src") => Some(Component::RegistrySources),
+                ("registry", "cache") => Some(Component::RegistryCrateCache),
+                ("registry", "index") => Some(Component::RegistryIndex),
+                _ => None,
+            })
+    }
+}
+
+/// the components that are scanned and operated on, set via --components / --no-default-scan
+/// unlike with --remove-dir, naming a component does not pull in components that depend on it
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub(crate) struct ComponentSelection {
+    /// None if everything (including installed binaries) is selected
+    components: Option<Vec<Component>>,
+    /// components that are left out even though everything is selected
+    excluded: Vec<Component>,
+}
+
+impl ComponentSelection {
+    /// parse the --components argument, --no-default-scan without --components selects nothing
+    pub(crate) fn new(input: Option<&str>, no_default_scan: bool) -> Result<Self, Error> {
+        let input = match input {
+            Some(input) => input,
+            None if no_default_scan => return Ok(Self::from(Vec::new())),
+            None => return Ok(Self::default()),
+        };
+
+        let (groups, errors): (Vec<_>, Vec<_>) = input
+            .split(',')
+            .map(str::parse::<RemovableGroup>)
+            .partition(Result::is_ok);
+
+        if !errors.is_empty() {
+            let invalid = errors
+                .into_iter()
+                .map(|e| e.err().unwrap())
+                .collect::<Vec<String>>()
+                .join(" ");
+            return Err(Error::InvalidComponents(invalid.trim().to_string()));
+        }
+
+        let components = groups
+            .into_iter()
+            .flat_map(|group| match group.ok().unwrap() {
+                RemovableGroup::All => vec![
+                    Component::GitDB,
+                    Component::GitRepos,
+                    Component::RegistrySources,
+                    Component::RegistryCrateCache,
+                    Component::RegistryIndex,
+                ],
+                RemovableGroup::GitDB => vec![Component::GitDB],
+                RemovableGroup::GitRepos => vec![Component::GitRepos],
+                RemovableGroup::RegistrySources => vec![Component::RegistrySources],
+                RemovableGroup::RegistryCrateCache => vec![Component::RegistryCrateCache],
+                // the sparse indices are part of the index
+                RemovableGroup::RegistryIndex | RemovableGroup::RegistryIndexSparse => {
+                    vec![Component::RegistryIndex]
+                }
+                RemovableGroup::Registry => vec![
+                    Component::RegistrySources,
+                    Component::RegistryCrateCache,
+                    Component::RegistryIndex,
+                ],
+            })
+            .collect::<Vec<Component>>();
+
+        Ok(Self::from(components))
+    }
+
+    /// leave out these components, the installed binaries are kept if they were selected
+    pub(crate) fn without(mut self, components: &[Component]) -> Self {
+        if let Some(selected) = &mut self.components {
+            selected.retain(|component| !components.contains(component));
+        } else {
+            self.excluded.extend_from_slice(components);
+            self.excluded.sort();
+            self.excluded.dedup();
+        }
+        self
+    }
+
+    /// whether only some of the components were selected
+    pub(crate) fn is_restricted(&self) -> bool {
+        self.components.is_some() || !self.excluded.is_empty()
+    }
+
+    /// whether the component was selected
+    pub(crate) fn contains(&self, component: &Component) -> bool {
+        !self.excluded.contains(component)
+            && self
+                .components
+                .as_ref()
+                .map_or(true, |components| components.contains(component))
+    }
+
+    /// installed binaries are not a component that can be selected, only look at them by default
+    pub(crate) fn includes_binaries(&self) -> bool {
+        self.components.is_none()
+    }
+
+    /// returns the path if the component was selected, an empty (nonexistent) path otherwise.
+    /// a cache created from an empty path is empty and never touches the disk
+    pub(crate) fn cache_path(&self, component: &Component, path: &Path) -> PathBuf {
+        if self.contains(component) {
+            path.to_path_buf()
+        } else {
+            PathBuf::new()
+        }
+    }
+}
+
+impl From<Vec<Component>> for ComponentSelection {
+    fn from(mut components: Vec<Component>) -> Self {
+        components.sort();
+        components.dedup();
+        Self {
+            components: Some(components),
+            excluded: Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for ComponentSelection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.components {
+            None if !self.excluded.is_empty() => write!(
+                f,
+                "all but {}",
+                self.excluded
+                    .iter()
+                    .map(Component::name)
+                    .collect::<Vec<_>>()
+                    .join(",")
+            ),
+            None => write!(f, "all"),
+            Some(components) if components.is_empty() => write!(f, "none"),
+            Some(components) => write!(
+                f,
+                "{}",
+                components
+                    .iter()
+                    .map(Component::name)
+                    .collect::<Vec<_>>()
+                    .join(",")
+            ),
+        }
+    }
+}
+
+/// which versions of a crate --keep-duplicate-crates keeps, set via --keep-by
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub(crate) enum KeepBy {
+    /// the highest versions
+    Version,
+    /// the most recently downloaded or used versions
+    Mtime,
+}
+
+impl KeepBy {
+    /// clap makes sure that only "version" or "mtime" are passed
+    pub(crate) fn new(keep_by: Option<&str>) -> Self {
+        match keep_by {
+            Some("version") | None => Self::Version,
+            Some("mtime") => Self::Mtime,
+            Some(other) => unreachable!("invalid --keep-by \"{}\"", other),
+        }
+    }
+}
+
+/// how the summary, --info, registry and --top-cache-items are printed, set via --output-format
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub(crate) enum OutputFormat {
+    /// the human readable tables
+    Table,
+    /// machine readable, sizes are in bytes
+    Json,
+}
+
+impl OutputFormat {
+    /// clap makes sure that only "table" or "json" are passed
+    pub(crate) fn new(output_format: Option<&str>) -> Self {
+        match output_format {
+            Some("table") | None => Self::Table,
+            Some("json") => Self::Json,
+            Some(other) => unreachable!("invalid --output-format \"{}\"", other),
+        }
+    }
+}
+
+/// the registries that --autoclean and trim may remove things from,
+/// set via --only-registry / --exclude-registry
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub(crate) struct RegistryFilter {
+    /// None if all registries are included
+    only: Option<Vec<String>>,
+    exclude: Vec<String>,
+}
+
+impl RegistryFilter {
+    /// parse the comma separated lists of registries
+    pub(crate) fn new(only: Option<&str>, exclude: Option<&str>) -> Self {
+        let split = |list: &str| {
+            list.split(',')
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(String::from)
+                .collect::<Vec<String>>()
+        };
+        Self {
+            only: only.map(split),
+            exclude: exclude.map(split).unwrap_or_default(),
+        }
+    }
+
+    /// whether not all registries are included
+    pub(crate) fn is_restricted(&self) -> bool {
+        self.only.is_some() || !self.exclude.is_empty()
+    }
+
+    /// whether the directory of a registry (for example "index.crates.io-6f17d22bba15001f") is included
+    pub(crate) fn includes_registry(&self, dir_name: &str) -> bool {
+        let matches = |name: &String| registry_matches(dir_name, name);
+        self.only
+            .as_ref()
+            .map_or(true, |only| only.iter().any(matches))
+            && !self.exclude.iter().any(matches)
+    }
+
+    /// git repos and checkouts do not belong to any registry, --only-registry leaves them alone
+    pub(crate) fn includes_git(&self) -> bool {
+        self.only.is_none()
+    }
+
+    /// whether the root directory of a registry subcache (for example "registry/src/index.crates.io-6f17d22bba15001f")
+    /// is included
+    pub(crate) fn includes_registry_path(&self, path: &Path) -> bool {
+        path.file_name().map_or(false, |dir_name| {
+            self.includes_registry(&dir_name.to_string_lossy())
+        })
+    }
+}
+
+/// whether a registry name passed on the command line means the directory of a registry:
+/// either the full directory name, the name without the hash ("index.crates.io"), "crates.io"
+/// or the name of the registry in the cargo config
+pub(crate) fn registry_matches(dir_name: &str, name: &str) -> bool {
+    let without_hash = dir_name
+        .rsplit_once('-')
+        .map_or(dir_name, |(without_hash, _hash)| without_hash);
+
+    dir_name == name
+        || without_hash == name
+        || (name == "crates.io" && matches!(without_hash, "index.crates.io" | "github.com"))
+        || registry_names::name_of(dir_name) == Some(name)
+}
+
 // map a String to a list of RemovableGroups to actual Components
 // returns either a group of successfully converted Components or a list of unrecognized
 // RemovableGroups as Error
@@ -387,11 +1459,11 @@ pub(crate) fn components_from_groups(input: Option<&str>) -> Result<Vec<Componen
     // sort failed and successful parses
     #[allow(clippy::type_complexity)]
     let (dirs, errors): (
-        Vec<Result<RemovableGroup, String>>,
-        Vec<Result<RemovableGroup, String>>,
+        Vec<Result<(RemovableGroup, Option<&str>), String>>,
+        Vec<Result<(RemovableGroup, Option<&str>), String>>,
     ) = input_string
         .split(',')
-        .map(str::parse)
+        .map(parse_remove_dir_entry)
         .partition(Result::is_ok);
 
     // we got errors, abort
@@ -411,8 +1483,13 @@ pub(crate) fn components_from_groups(input: Option<&str>) -> Result<Vec<Componen
 
     // map the RemovableGroups to Dirs
 
-    // unwrap the Results
-    let dirs = dirs.into_iter().map(|d| d.ok().unwrap());
+    // unwrap the Results, the directories of single registries are removed separately,
+    // see `registry_targets()`
+    let dirs = dirs
+        .into_iter()
+        .map(|d| d.ok().unwrap())
+        .filter(|(_, registry)| registry.is_none())
+        .map(|(group, _)| group);
 
     let mut mapped_dirs = Vec::new();
 
@@ -447,6 +1524,8 @@ pub(crate) fn components_from_groups(input: Option<&str>) -> Result<Vec<Componen
         RemovableGroup::RegistryIndex => {
             mapped_dirs.push(Component::RegistryIndex);
         }
+        // only parts of the index are removed, see `removes_sparse_indices()`
+        RemovableGroup::RegistryIndexSparse => {}
         RemovableGroup::Registry => mapped_dirs.extend(vec![
             Component::RegistrySources,
             Component::RegistryCrateCache,
@@ -460,18 +1539,79 @@ pub(crate) fn components_from_groups(input: Option<&str>) -> Result<Vec<Componen
     Ok(mapped_dirs)
 }
 
+/// parse one entry of --remove-dir, the registry groups may be restricted to a single registry:
+/// "registry-crate-cache:my-registry.example.com"
+fn parse_remove_dir_entry(entry: &str) -> Result<(RemovableGroup, Option<&str>), String> {
+    match entry.split_once(':') {
+        None => entry.parse().map(|group| (group, None)),
+        Some((group, registry)) => match group.parse() {
+            Ok(
+                group @ (RemovableGroup::RegistrySources
+                | RemovableGroup::RegistryCrateCache
+                | RemovableGroup::RegistryIndex
+                | RemovableGroup::Registry),
+            ) if !registry.is_empty() => Ok((group, Some(registry))),
+            _ => Err(entry.to_string()),
+        },
+    }
+}
+
+/// the components --remove-dir only removes the directories of single registries of,
+/// as (component, registry) (the input was already validated by `components_from_groups()`)
+pub(crate) fn registry_targets(input: Option<&str>) -> Vec<(Component, String)> {
+    let mut targets = input
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|entry| parse_remove_dir_entry(entry).ok())
+        .filter_map(|(group, registry)| Some((group, registry?.to_string())))
+        .flat_map(|(group, registry)| {
+            let components = match group {
+                RemovableGroup::RegistrySources => vec![Component::RegistrySources],
+                RemovableGroup::RegistryCrateCache | RemovableGroup::Registry => {
+                    vec![Component::RegistrySources, Component::RegistryCrateCache]
+                }
+                RemovableGroup::RegistryIndex => vec![Component::RegistryIndex],
+                _ => Vec::new(),
+            };
+            components
+                .into_iter()
+                .map(move |component| (component, registry.clone()))
+        })
+        .collect::<Vec<(Component, String)>>();
+    targets.sort();
+    targets.dedup();
+    targets
+}
+
+/// whether --remove-dir asks to remove the sparse registry indices
+/// (the input was already validated by `components_from_groups()`)
+pub(crate) fn removes_sparse_indices(input: Option<&str>) -> bool {
+    input.map_or(false, |input| {
+        input
+            .split(',')
+            .any(|group| group.parse() == Ok(RemovableGroup::RegistryIndexSparse))
+    })
+}
+
 /// get the total size of a directory or a file
 pub(crate) fn size_of_path(path: &Path) -> u64 {
     // if the path is a directory, use cumulative_dir_size
     if path.is_dir() {
         cumulative_dir_size(path).dir_size
     } else {
-        fs::metadata(path)
+        retry::metadata(path)
             .unwrap_or_else(|_| panic!("Failed to get metadata of file '{}'", &path.display()))
             .len()
     }
 }
 
+/// the sha256 of the contents of a file as lowercase hex string
+pub(crate) fn sha256_of_file(path: &Path) -> std::io::Result<String> {
+    let mut hasher = Sha256::new();
+    let _ = std::io::copy(&mut fs::File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
 /// get the total size and number of files of a directory
 pub(crate) fn cumulative_dir_size(dir: &Path) -> DirInfo {
     // Note: using a hashmap to cache dirsizes does apparently not pay out performance-wise
@@ -485,20 +1625,18 @@ pub(crate) fn cumulative_dir_size(dir: &Path) -> DirInfo {
     // traverse recursively and sum filesizes, parallelized by rayon
     let walkdir_start = dir.display().to_string();
 
-    let dir_size = WalkDir::new(&walkdir_start)
-        .into_iter()
-        .map(|e| e.unwrap().path().to_owned())
+    let dir_size = walk(dir)
+        .take_while(|_| !is_cancelled())
+        .map(DirEntry::into_path)
         .filter(|f| f.exists()) // avoid broken symlinks
         .collect::<Vec<_>>() // @TODO perhaps WalkDir will impl ParallelIterator one day
         .par_iter()
         .filter(|f| f.exists()) // check if the file still exists. Since collecting and processing a
         // path, some time may have passed and if we have a "cargo build" operation
-        // running in the directory, a temporary file may be gone already and failing to unwrap() (#43)
-        .map(|f| {
-            fs::metadata(f)
-                .unwrap_or_else(|_| panic!("Failed to get metadata of file '{}'", &f.display()))
-                .len()
-        })
+        // running in the directory, a temporary file may be gone already (#43), files that vanish
+        // after the check or whose metadata can not be read are not counted either
+        .filter_map(|f| retry::metadata(f).ok())
+        .map(|metadata| metadata.len())
         .sum();
 
     // for the file number, we don't want the actual number of files but only the number of
@@ -511,7 +1649,7 @@ pub(crate) fn cumulative_dir_size(dir: &Path) -> DirInfo {
             .into_iter()
             .count()
     } else {
-        fs::read_dir(dir).unwrap().count()
+        retry::read_dir(dir).map_or(0, Iterator::count)
     } as u64;
 
     DirInfo {
@@ -522,122 +1660,146 @@ pub(crate) fn cumulative_dir_size(dir: &Path) -> DirInfo {
 
 /// "cargo cache --info" output
 pub(crate) fn get_info(c: &CargoCachePaths, s: &DirSizes<'_>) -> String {
-    let mut strn = String::with_capacity(1500);
-
-    if let Ok(cache_path) = std::env::var("CARGO_HOME") {
-        writeln!(
-            strn,
-            "${{CARGO_HOME}} env var set to '{cache_path}', using that!"
-        )
-        .unwrap();
-    } else {
-        writeln!(
-            strn,
-            "Default cache dir found: '{}', using that!",
-            c.cargo_home.display()
-        )
-        .unwrap();
+    // a directory of the cargo home, its size, what it holds and what happens if it is removed
+    fn dir(strn: &mut String, path: &Path, size_line: &str, notes: &[Message]) {
+        writeln!(strn, "{}", path.display()).unwrap();
+        writeln!(strn, "\t{size_line}").unwrap();
+        for note in notes {
+            writeln!(strn, "\t{}", messages::text(*note)).unwrap();
+        }
+    }
+    let size = |message: Message, size: u64| {
+        messages::fill(message, &[("size", &size.format_size(DECIMAL))])
     };
 
-    strn.push('\n');
-
-    writeln!(
-        strn,
-        "Total cache size: {}\n",
-        s.total_size().format_size(DECIMAL)
-    )
-    .unwrap();
+    let mut strn = String::with_capacity(1500);
 
-    strn.push_str(&c.bin_dir.display().to_string());
-    strn.push('\n');
+    let cargo_home_message = match c.source {
+        CargoHomeSource::Explicit => Message::InfoCargoHomeFromFlag,
+        CargoHomeSource::Env => Message::InfoCargoHomeFromEnv,
+        CargoHomeSource::Default => Message::InfoCargoHomeDefault,
+    };
     writeln!(
         strn,
-        "\t{} binaries installed in binary directory, total size: {}",
-        s.numb_bins(),
-        s.total_bin_size().format_size(DECIMAL)
+        "{}",
+        messages::fill(cargo_home_message, &[("path", &c.cargo_home.display())])
     )
     .unwrap();
-    strn.push_str("\tThese are the binaries installed via 'cargo install'.\n");
-    strn.push_str("\tUse 'cargo uninstall' to remove binaries if needed.\n");
-    strn.push('\n');
 
-    strn.push_str(&c.registry.display().to_string());
-    strn.push('\n');
-    writeln!(
-        strn,
-        "\tRegistry root dir, size: {}",
-        s.total_reg_size().format_size(DECIMAL)
-    )
-    .unwrap();
-    strn.push_str("\tCrate registries are stored here.\n");
     strn.push('\n');
 
-    strn.push_str(&c.registry_index.display().to_string());
-    strn.push('\n');
-    writeln!(
-        strn,
-        "\tRegistry index, size: {}",
-        s.total_reg_index_size().format_size(DECIMAL)
-    )
-    .unwrap();
-    strn.push_str("\tA git repo holding information on what crates are available.\n");
-    strn.push_str("\tWill be recloned as needed.\n");
+    writeln!(strn, "{}\n", size(Message::InfoTotal, s.total_size())).unwrap();
 
+    dir(
+        &mut strn,
+        &c.bin_dir,
+        &messages::fill(
+            Message::InfoBinaries,
+            &[
+                ("count", &s.numb_bins()),
+                ("size", &s.total_bin_size().format_size(DECIMAL)),
+            ],
+        ),
+        &[Message::InfoBinariesAbout, Message::InfoBinariesHint],
+    );
     strn.push('\n');
 
-    // source archives are extracted here, will be reextracted from the downloaded source if removed
-    strn.push_str(&c.registry_pkg_cache.display().to_string());
+    dir(
+        &mut strn,
+        &c.registry,
+        &size(Message::InfoRegistry, s.total_reg_size()),
+        &[Message::InfoRegistryAbout],
+    );
     strn.push('\n');
-    writeln!(
-        strn,
-        "\tCrate source package archive, size: {}",
-        s.total_reg_cache_size().format_size(DECIMAL)
-    )
-    .unwrap();
 
-    strn.push_str("\tCrates source packages of the registries are downloaded into this folder.\n");
-    strn.push_str("\tThey will be redownloaded as needed.\n");
+    dir(
+        &mut strn,
+        &c.registry_index,
+        &size(Message::InfoRegistryIndex, s.total_reg_index_size()),
+        &[
+            Message::InfoRegistryIndexAbout,
+            Message::InfoRegistryIndexHint,
+        ],
+    );
     strn.push('\n');
 
-    strn.push_str(&c.registry_sources.display().to_string());
+    // source archives are extracted here, will be reextracted from the downloaded source if removed
+    dir(
+        &mut strn,
+        &c.registry_pkg_cache,
+        &size(Message::InfoCrateArchives, s.total_reg_cache_size()),
+        &[
+            Message::InfoCrateArchivesAbout,
+            Message::InfoCrateArchivesHint,
+        ],
+    );
     strn.push('\n');
 
-    writeln!(
-        strn,
-        "\tCrate sources, size: {}",
-        s.total_reg_src_size().format_size(DECIMAL)
-    )
-    .unwrap();
-    strn.push_str("\tSource archives are extracted into this dir.\n");
-    strn.push_str("\tThey will be reextracted from the package archive as needed.\n");
+    dir(
+        &mut strn,
+        &c.registry_sources,
+        &size(Message::InfoCrateSources, s.total_reg_src_size()),
+        &[
+            Message::InfoCrateSourcesAbout,
+            Message::InfoCrateSourcesHint,
+        ],
+    );
     strn.push('\n');
 
-    strn.push_str(&c.git_repos_bare.display().to_string());
-    strn.push('\n');
-    writeln!(
-        strn,
-        "\tGit database, size: {}",
-        s.total_git_repos_bare_size().format_size(DECIMAL)
-    )
-    .unwrap();
-    strn.push_str("\tBare repos of git dependencies are stored here.\n");
-    strn.push_str("\tRemoved git repositories will be recloned as needed.\n");
+    dir(
+        &mut strn,
+        &c.git_repos_bare,
+        &size(Message::InfoGitDb, s.total_git_repos_bare_size()),
+        &[Message::InfoGitDbAbout, Message::InfoGitDbHint],
+    );
     strn.push('\n');
 
-    strn.push_str(&c.git_checkouts.display().to_string());
-    strn.push('\n');
-    writeln!(
-        strn,
-        "\tGit repo checkouts, size: {}",
-        s.total_git_chk_size().format_size(DECIMAL)
-    )
-    .unwrap();
-    strn.push_str("\tSpecific commits of the bare repos will be checked out into here.\n");
-    strn.push_str("\tGit checkouts will be rechecked-out from repo database as needed.");
+    dir(
+        &mut strn,
+        &c.git_checkouts,
+        &size(Message::InfoGitCheckouts, s.total_git_chk_size()),
+        &[
+            Message::InfoGitCheckoutsAbout,
+            Message::InfoGitCheckoutsHint,
+        ],
+    );
+    // the last line has no newline
+    let _ = strn.pop();
     //println!("{}", strn.len());
     strn
 }
 
+/// print the output of --output-format json
+#[cfg(feature = "json")]
+pub(crate) fn print_json(value: &serde_json::Value) {
+    println!("{}", serde_json::to_string_pretty(value).unwrap());
+}
+
+/// "cargo cache --info --output-format json", the directories of the cargo home and their sizes
+#[cfg(feature = "json")]
+pub(crate) fn get_info_json(c: &CargoCachePaths, s: &DirSizes<'_>) -> serde_json::Value {
+    use serde_json::json;
+
+    let dir = |path: &Path, size: u64| json!({ "path": path.display().to_string(), "size": size });
+    json!({
+        "cargo_home": c.cargo_home.display().to_string(),
+        "cargo_home_from_env": c.source == CargoHomeSource::Env,
+        "cargo_home_from_flag": c.source == CargoHomeSource::Explicit,
+        "total_size": s.total_size(),
+        "binaries": {
+            "path": c.bin_dir.display().to_string(),
+            "count": s.numb_bins(),
+            "size": s.total_bin_size(),
+        },
+        "registry": dir(&c.registry, s.total_reg_size()),
+        "registry_index": dir(&c.registry_index, s.total_reg_index_size()),
+        "registry_crate_cache": dir(&c.registry_pkg_cache, s.total_reg_cache_size()),
+        "registry_sources": dir(&c.registry_sources, s.total_reg_src_size()),
+        "git_db": dir(&c.git_repos_bare, s.total_git_repos_bare_size()),
+        "git_checkouts": dir(&c.git_checkouts, s.total_git_chk_size()),
+    })
+}
+
 //@TODO add tests
 /// provides a textual summary of changes (of file sizes)
 pub(crate) fn size_diff_format(
@@ -677,86 +1839,19 @@ pub(crate) fn size_diff_format(
     }
 }
 
-// @TODO make this function obsolete
-#[allow(clippy::too_many_arguments)]
-pub(crate) fn print_size_changed_summary(
-    previous_total_size: u64,
-    cargo_cache: &CargoCachePaths,
-    bin_cache: &mut bin::BinaryCache,
-    checkouts_cache: &mut git_checkouts::GitCheckoutCache,
-    bare_repos_cache: &mut git_bare_repos::GitRepoCache,
-    registry_pkgs_cache: &mut registry_pkg_cache::RegistryPkgCaches,
-    registry_index_caches: &mut registry_index::RegistryIndicesCache,
-    registry_sources_caches: &mut registry_sources::RegistrySourceCaches,
-) {
-    // and invalidate the cache
-    bin_cache.invalidate();
-    checkouts_cache.invalidate();
-    bare_repos_cache.invalidate();
-    registry_pkgs_cache.invalidate();
-    registry_index_caches.invalidate();
-    registry_sources_caches.invalidate();
-
-    // and requery it to let it do its thing
-    let cache_size_new = DirSizes::new(
-        bin_cache,
-        checkouts_cache,
-        bare_repos_cache,
-        registry_pkgs_cache,
-        registry_index_caches,
-        registry_sources_caches,
-        cargo_cache,
-    )
-    .total_size();
-
-    let size_old_human_readable = previous_total_size.format_size(DECIMAL);
-    println!(
-        "\nSize changed from {} to {}",
-        size_old_human_readable,
-        size_diff_format(previous_total_size, cache_size_new, false)
-    );
-}
-
 #[cfg(test)]
 mod libtests {
     use super::*;
 
     use pretty_assertions::assert_eq;
+    #[cfg(feature = "metadata")]
     use regex::Regex;
     use std::env;
+    use std::fs;
 
+    #[cfg(feature = "metadata")]
     use crate::test_helpers::assert_path_end;
 
-    impl CargoCachePaths {
-        pub(crate) fn new(dir: PathBuf) -> Result<Self, Error> {
-            if !dir.is_dir() {
-                return Err(Error::CargoHomeNotDirectory(dir));
-            }
-
-            // get the paths to the relevant directories
-            let cargo_home = dir;
-            let bin = cargo_home.join("bin");
-            let registry = cargo_home.join("registry");
-            let registry_index = registry.join("index");
-            let reg_cache = registry.join("cache");
-            let reg_src = registry.join("src");
-            let git = cargo_home.join("git");
-            let git_repos_bare = git.join("db");
-            let git_checkouts = git.join("checkouts");
-
-            Ok(Self {
-                cargo_home,
-                bin_dir: bin,
-                registry,
-                registry_index,
-                registry_pkg_cache: reg_cache,
-                registry_sources: reg_src,
-                git_repos_bare,
-                git_checkouts,
-            })
-        }
-    }
-
     #[allow(non_snake_case)]
     #[test]
     fn test_DirInfo() {
@@ -776,6 +1871,7 @@ mod libtests {
         assert!(dir_paths.is_ok(), "dir paths: {dir_paths:?}");
     }
 
+    #[cfg(feature = "metadata")]
     #[allow(non_snake_case)]
     #[test]
     fn test_CargoCachePaths_paths() {
@@ -798,11 +1894,11 @@ mod libtests {
         );
 
         // create the directory: cargo-cache/target/cargo_home_cargo_cache_paths
-        if !std::path::PathBuf::from(&CH_string).is_dir() {
-            std::fs::DirBuilder::new().create(&CH_string).unwrap();
+        if !PathBuf::from(&CH_string).is_dir() {
+            fs::DirBuilder::new().create(&CH_string).unwrap();
         }
         assert!(fs::metadata(&CH_string).unwrap().is_dir());
-        assert!(std::path::PathBuf::from(&CH_string).is_dir());
+        assert!(PathBuf::from(&CH_string).is_dir());
 
         let ccp = CargoCachePaths::new(PathBuf::from(CH_string)).unwrap();
 
@@ -839,6 +1935,7 @@ mod libtests {
         );
     }
 
+    #[cfg(feature = "metadata")]
     #[allow(non_snake_case)]
     #[test]
     fn test_CargoCachePaths_print() {
@@ -864,11 +1961,11 @@ mod libtests {
         );
 
         // create the directory
-        if !std::path::PathBuf::from(&CH_string).exists() {
-            std::fs::DirBuilder::new().create(&CH_string).unwrap();
+        if !PathBuf::from(&CH_string).exists() {
+            fs::DirBuilder::new().create(&CH_string).unwrap();
         }
         assert!(fs::metadata(&CH_string).unwrap().is_dir());
-        assert!(std::path::PathBuf::from(&CH_string).is_dir());
+        assert!(PathBuf::from(&CH_string).is_dir());
 
         // set cargo home to this directory
         let ccp = CargoCachePaths::new(PathBuf::from(CH_string)).unwrap();
@@ -956,6 +2053,227 @@ mod libtests {
         let last = iter.next();
         assert!(last.is_none(), "found another directory?!: '{last:?}'");
     }
+
+    #[test]
+    fn component_selection() {
+        let everything = ComponentSelection::new(None, false).unwrap();
+        assert!(!everything.is_restricted());
+        assert!(everything.includes_binaries());
+        assert!(everything.contains(&Component::GitDB));
+        assert_eq!(everything.to_string(), "all");
+
+        let nothing = ComponentSelection::new(None, true).unwrap();
+        assert!(nothing.is_restricted());
+        assert!(!nothing.contains(&Component::RegistryIndex));
+        assert_eq!(nothing.to_string(), "none");
+
+        // unlike --remove-dir, git-db does not pull in git-repos
+        let selection =
+            ComponentSelection::new(Some("registry-crate-cache,git-db"), false).unwrap();
+        assert!(!selection.includes_binaries());
+        assert!(selection.contains(&Component::GitDB));
+        assert!(selection.contains(&Component::RegistryCrateCache));
+        assert!(!selection.contains(&Component::GitRepos));
+        assert!(!selection.contains(&Component::RegistrySources));
+        assert_eq!(selection.to_string(), "git-db,registry-crate-cache");
+        assert_eq!(
+            selection.cache_path(&Component::GitRepos, Path::new("git/checkouts")),
+            PathBuf::new()
+        );
+
+        let registry = ComponentSelection::new(Some("registry,registry-index"), true).unwrap();
+        assert_eq!(
+            registry.to_string(),
+            "registry-sources,registry-crate-cache,registry-index"
+        );
+
+        assert!(matches!(
+            ComponentSelection::new(Some("git-db,foo"), false),
+            Err(Error::InvalidComponents(invalid)) if invalid == "foo"
+        ));
+
+        // leaving out components keeps the binaries unless they were not selected anyway
+        let without = everything.without(&[Component::RegistrySources]);
+        assert!(without.is_restricted());
+        assert!(without.includes_binaries());
+        assert!(!without.contains(&Component::RegistrySources));
+        assert!(without.contains(&Component::RegistryIndex));
+        assert_eq!(without.to_string(), "all but registry-sources");
+        let registry_without = registry.without(&[Component::RegistrySources, Component::GitDB]);
+        assert_eq!(
+            registry_without.to_string(),
+            "registry-crate-cache,registry-index"
+        );
+    }
+
+    #[test]
+    fn remove_sparse_indices() {
+        // the sparse indices are removed one by one, not as a whole component
+        assert_eq!(
+            components_from_groups(Some("registry-index-sparse")).unwrap(),
+            Vec::new()
+        );
+        assert_eq!(
+            components_from_groups(Some("registry-index-sparse,git-repos")).unwrap(),
+            vec![Component::GitRepos]
+        );
+        assert!(removes_sparse_indices(Some(
+            "git-repos,registry-index-sparse"
+        )));
+        assert!(!removes_sparse_indices(Some("registry-index")));
+        assert!(!removes_sparse_indices(None));
+    }
+
+    #[test]
+    fn remove_single_registries() {
+        // qualified groups only remove the directories of that registry, not the components
+        assert_eq!(
+            components_from_groups(Some("registry-crate-cache:my-registry.example.com,git-db"))
+                .unwrap(),
+            vec![Component::GitDB, Component::GitRepos]
+        );
+        assert_eq!(
+            registry_targets(Some(
+                "registry-crate-cache:my-registry.example.com,registry-index:crates.io,git-db"
+            )),
+            vec![
+                (
+                    Component::RegistrySources,
+                    String::from("my-registry.example.com")
+                ),
+                (
+                    Component::RegistryCrateCache,
+                    String::from("my-registry.example.com")
+                ),
+                (Component::RegistryIndex, String::from("crates.io")),
+            ]
+        );
+        assert_eq!(registry_targets(Some("registry,git-db")), Vec::new());
+
+        // only the registry groups belong to a registry
+        assert!(matches!(
+            components_from_groups(Some("git-db:crates.io,registry-index:,registry-index-sparse:crates.io")),
+            Err(Error::InvalidDeletableDirs(invalid))
+                if invalid == "git-db:crates.io registry-index: registry-index-sparse:crates.io"
+        ));
+    }
+
+    #[test]
+    fn layout_mismatches() {
+        let dir = tempfile::tempdir().unwrap();
+        let ccp = CargoCachePaths::new(dir.path().to_path_buf()).unwrap();
+        assert_eq!(ccp.source, CargoHomeSource::Explicit);
+        let mismatches = ccp.layout_mismatches();
+        assert_eq!(mismatches.len(), 1);
+        assert!(mismatches[0].ends_with("it does not look like a cargo home"));
+
+        fs::create_dir_all(&ccp.registry).unwrap();
+        assert!(ccp.layout_mismatches().is_empty());
+
+        fs::write(&ccp.registry_sources, "").unwrap();
+        assert_eq!(
+            ccp.layout_mismatches(),
+            vec![format!(
+                "\"{}\" is not a directory",
+                ccp.registry_sources.display()
+            )]
+        );
+
+        assert!(matches!(
+            CargoCachePaths::from_root(dir.path().join("nonexistent")),
+            Err(Error::RootNotDirectory(_))
+        ));
+    }
+
+    #[test]
+    fn sha256_of_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("abc");
+        fs::write(&file, "abc").unwrap();
+        assert_eq!(
+            sha256_of_file(&file).unwrap(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert!(sha256_of_file(&dir.path().join("missing")).is_err());
+    }
+
+    #[test]
+    fn unsafe_cargo_homes() {
+        let dir = tempfile::tempdir().unwrap();
+        let cargo_home = dir.path().join("cargo-home");
+        fs::create_dir(&cargo_home).unwrap();
+        let ccp = CargoCachePaths::new(cargo_home.clone()).unwrap();
+
+        // empty, nothing to lose
+        assert!(ccp.check_safe_to_modify(None).is_ok());
+        // something that is not a cargo home
+        fs::write(cargo_home.join("thesis.tex"), "").unwrap();
+        assert!(matches!(
+            ccp.check_safe_to_modify(None),
+            Err(Error::UnsafeCargoHome(
+                _,
+                "it contains neither registry/, git/ nor .crates.toml of cargo"
+            ))
+        ));
+        // a bin/ is not enough, "~/.local" has one as well
+        fs::create_dir(cargo_home.join("bin")).unwrap();
+        assert!(matches!(
+            ccp.check_safe_to_modify(None),
+            Err(Error::UnsafeCargoHome(
+                _,
+                "it contains neither registry/, git/ nor .crates.toml of cargo"
+            ))
+        ));
+        fs::create_dir(&ccp.registry).unwrap();
+        assert!(ccp.check_safe_to_modify(None).is_ok());
+        // the home directory, even if it looks like a cargo home
+        assert!(matches!(
+            ccp.check_safe_to_modify(Some(&cargo_home.join("registry").join(".."))),
+            Err(Error::UnsafeCargoHome(_, "it is your home directory"))
+        ));
+
+        let root = CargoCachePaths::new(PathBuf::from("/")).unwrap();
+        assert!(matches!(
+            root.check_safe_to_modify(None),
+            Err(Error::UnsafeCargoHome(
+                _,
+                "it is the root of the file system"
+            ))
+        ));
+    }
+
+    #[test]
+    fn registry_filter() {
+        let crates_io = "index.crates.io-6f17d22bba15001f";
+        let old_crates_io = "github.com-1ecc6299db9ec823";
+        let corporate = "my-registry.example.com-0123456789abcdef";
+
+        let everything = RegistryFilter::new(None, None);
+        assert!(!everything.is_restricted());
+        assert!(everything.includes_git());
+        assert!(everything.includes_registry(corporate));
+
+        let only = RegistryFilter::new(Some("crates.io"), None);
+        assert!(only.is_restricted());
+        assert!(!only.includes_git());
+        assert!(only.includes_registry(crates_io));
+        assert!(only.includes_registry(old_crates_io));
+        assert!(!only.includes_registry(corporate));
+
+        // names with and without the hash, empty entries are ignored
+        let exclude = RegistryFilter::new(None, Some("my-registry.example.com, ,"));
+        assert!(exclude.is_restricted());
+        assert!(exclude.includes_git());
+        assert!(exclude.includes_registry(crates_io));
+        assert!(!exclude.includes_registry(corporate));
+        assert!(!RegistryFilter::new(None, Some(corporate)).includes_registry(corporate));
+
+        let both =
+            RegistryFilter::new(Some("crates.io,my-registry.example.com"), Some("crates.io"));
+        assert!(!both.includes_registry(crates_io));
+        assert!(both.includes_registry_path(&Path::new("registry/src").join(corporate)));
+        assert!(!both.includes_registry_path(&Path::new("registry/src").join(crates_io)));
+    }
 }
 
 #[cfg(all(test, feature = "bench"))]
@@ -0,0 +1,162 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// advisory lock of the cargo home
+// several cargo-cache processes (for example parallel CI jobs that share a cache volume) must not
+// remove things from the same cache at the same time.
+// Operations that modify the cache take an exclusive lock on a file in the state dir of the
+// cargo home, read-only operations never look at the lock.
+// The lock is released by the OS once the process exits, so a crashed cargo-cache can not leave
+// a stale lock behind.
+
+use std::fs::{self, File, OpenOptions};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use fs2::FileExt;
+
+use crate::library::Error;
+use crate::state_dir;
+
+/// name of the lock file inside the state dir
+pub(crate) const LOCK_FILE_NAME: &str = "lock";
+
+/// how often we check if the lock was released while waiting
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// what to do if another process holds the lock
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum WaitPolicy {
+    /// --no-wait: fail right away
+    NoWait,
+    /// --wait <secs>: wait at most this many seconds
+    Timeout(u64),
+    /// default: wait until we get the lock
+    Forever,
+}
+
+impl WaitPolicy {
+    /// get the policy from the --no-wait and --wait <secs> flags
+    pub(crate) fn new(no_wait: bool, wait: Option<&str>) -> Result<Self, Error> {
+        match (no_wait, wait) {
+            (true, _) => Ok(Self::NoWait),
+            (false, Some(seconds)) => seconds
+                .trim()
+                .parse()
+                .map(Self::Timeout)
+                .map_err(|_| Error::InvalidWaitTime(seconds.to_string())),
+            (false, None) => Ok(Self::Forever),
+        }
+    }
+}
+
+/// an exclusive lock of the cargo home, released when dropped
+#[derive(Debug)]
+pub(crate) struct CacheLock {
+    file: File,
+}
+
+impl CacheLock {
+    /// lock the cargo home, waiting for other cargo-cache processes according to the policy
+    pub(crate) fn acquire(cargo_home: &Path, policy: WaitPolicy) -> Result<Self, Error> {
+        let path = state_dir::state_file(cargo_home, LOCK_FILE_NAME);
+        let file = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| {
+                OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .create(true)
+                    .truncate(false)
+                    .open(&path)
+            })
+            .map_err(|error| Error::LockFailed(path.clone(), error))?;
+
+        let started = Instant::now();
+        let mut announced = false;
+
+        loop {
+            match file.try_lock_exclusive() {
+                Ok(()) => return Ok(Self { file }),
+                // someone else holds the lock
+                Err(error) if error.kind() == fs2::lock_contended_error().kind() => {}
+                Err(error) => return Err(Error::LockFailed(path, error)),
+            }
+
+            match policy {
+                WaitPolicy::NoWait => return Err(Error::CacheLocked(path)),
+                WaitPolicy::Timeout(seconds)
+                    if started.elapsed() >= Duration::from_secs(seconds) =>
+                {
+                    return Err(Error::LockTimeout(path, seconds));
+                }
+                WaitPolicy::Timeout(_) | WaitPolicy::Forever => {}
+            }
+
+            if !announced {
+                eprintln!(
+                    "Blocking: waiting for another cargo-cache process to release \"{}\"",
+                    path.display()
+                );
+                announced = true;
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+impl Drop for CacheLock {
+    fn drop(&mut self) {
+        // the lock would also be released when the file is closed, be explicit anyway
+        let _ = FileExt::unlock(&self.file);
+    }
+}
+
+#[cfg(test)]
+mod lock_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn wait_policy() {
+        assert_eq!(WaitPolicy::new(false, None).unwrap(), WaitPolicy::Forever);
+        assert_eq!(WaitPolicy::new(true, None).unwrap(), WaitPolicy::NoWait);
+        assert_eq!(
+            WaitPolicy::new(false, Some("30")).unwrap(),
+            WaitPolicy::Timeout(30)
+        );
+        assert!(matches!(
+            WaitPolicy::new(false, Some("soon")),
+            Err(Error::InvalidWaitTime(_))
+        ));
+    }
+
+    #[test]
+    fn lock_is_exclusive() {
+        let cargo_home = tempfile::tempdir().unwrap();
+
+        let lock = CacheLock::acquire(cargo_home.path(), WaitPolicy::NoWait).unwrap();
+        assert!(state_dir::state_file(cargo_home.path(), LOCK_FILE_NAME).is_file());
+
+        // a second lock can not be taken while the first one is held
+        assert!(matches!(
+            CacheLock::acquire(cargo_home.path(), WaitPolicy::NoWait),
+            Err(Error::CacheLocked(_))
+        ));
+        assert!(matches!(
+            CacheLock::acquire(cargo_home.path(), WaitPolicy::Timeout(0)),
+            Err(Error::LockTimeout(_, 0))
+        ));
+
+        drop(lock);
+        assert!(CacheLock::acquire(cargo_home.path(), WaitPolicy::NoWait).is_ok());
+    }
+}
@@ -36,7 +36,7 @@
     clippy::string_add,
     clippy::string_add_assign,
     clippy::redundant_clone,
-    clippy::empty_enum,
+    clippy::empty_enums,
     clippy::explicit_iter_loop,
     clippy::match_same_arms,
     clippy::needless_borrow,
@@ -67,32 +67,95 @@
 cfg_if::cfg_if! {
     if #[cfg(not(feature = "ci-autoclean"))] {
         // mods
+        mod approve;
+        mod args;
         mod cache;
+        mod cancel;
         mod cli;
         mod commands;
+        mod compat;
+        mod config;
+        mod confirm;
+        mod dedupe;
         mod dirsizes;
+        mod dispatch;
+        mod empty_registries;
+        mod env;
+        mod error_report;
+        mod estimate;
         mod tables;
+        #[cfg(feature = "git")]
         mod git;
+        mod history;
+        #[cfg(feature = "metrics")]
+        mod http;
+        mod hyperlink;
+        mod ignore;
+        mod incremental;
+        #[cfg(feature = "json")]
+        mod journal;
+        mod keep;
+        mod last_used;
+        #[cfg(any(feature = "metadata", feature = "verify"))]
+        mod index;
         mod library;
+        mod messages;
+        mod lock;
+        mod modified;
+        mod multi_cache;
+        mod names;
+        mod network;
+        mod other_files;
+        mod preflight;
+        mod progress;
+        mod projects;
+        mod rebuild_cost;
+        mod redact;
+        mod registry_migration;
+        mod registry_names;
         mod remove;
+        mod throughput;
+        mod report;
+        mod retry;
+        #[cfg(feature = "json")]
+        mod schema;
+        mod size_cache;
+        mod snapshots;
+        mod state_dir;
+        mod suggestions;
+        mod system_trash;
         mod top_items;
         mod top_items_summary;
+        mod tty;
+        mod walk;
+        mod would_free;
         mod date;
+        #[cfg(feature = "metadata")]
         mod clean_unref;
+        #[cfg(feature = "verify")]
         mod verify;
 
         // use
-        use crate::cache::caches::{Cache, RegistrySuperCache};
+        use std::io;
+        use std::path::Path;
+        use std::path::PathBuf;
         use std::process;
         use std::time::SystemTime;
         use walkdir::WalkDir;
-        use crate::cache::*;
-        use crate::commands::{local, query, sccache, trim, toolchains};
-        use crate::git::*;
+        use crate::commands::{checkouts, compare, doctor, inspect, maintain, remove_crate, savings, sccache, selftest, stats, toolchains, ui};
+        #[cfg(feature = "metadata")]
+        use crate::commands::{local, preheat_install, self_update};
+        #[cfg(feature = "git")]
+        use crate::commands::git_export;
+        #[cfg(feature = "backup")]
+        use crate::commands::backup;
+        #[cfg(feature = "json")]
+        use crate::commands::log;
+        use crate::keep::KeepList;
         use crate::library::*;
+        use crate::lock::{CacheLock, WaitPolicy};
         use crate::remove::*;
-        use crate::top_items_summary::*;
-        use crate::clean_unref::*;
+        use crate::report::RunReport;
         use crate::cli::{CargoCacheCommands};
         //use crate::verify;
     }
@@ -114,7 +177,41 @@ fn main() {
     // we need this in case we call "cargo-cache" binary directly
     let config = config.subcommand_matches("cache").unwrap_or(&config);
 
-    let config_enum = cli::clap_to_enum(config);
+    // "--output-format json": machine readable summary, --info, registry, --top-cache-items and trim --plan
+    let explicit_output_format = config.value_of("output-format").or_else(|| {
+        ["registry", "r", "registries", "trim"]
+            .iter()
+            .find_map(|name| config.subcommand_matches(name))
+            .and_then(|subcommand_config| subcommand_config.value_of("output-format"))
+    });
+    let mut output_format = OutputFormat::new(explicit_output_format);
+    // "--json-errors": print errors as json as well, scripts that asked for json get it
+    error_report::init(config.is_present("json-errors") || output_format == OutputFormat::Json);
+    if config.is_present("json-errors") && !cli::feature_enabled("json") {
+        Err::<(), _>(Error::FeatureDisabled(
+            String::from("--json-errors"),
+            "json",
+        ))
+        .unwrap_or_fatal_error();
+    }
+
+    // defaults from ~/.config/cargo-cache/config.toml for everything that was not passed
+    let defaults = config::load().unwrap_or_fatal_error();
+    let config_enum = cli::clap_to_enum(config, &defaults);
+    // cargo-cache may have been built without the dependencies of the command
+    config_enum.check_feature().unwrap_or_fatal_error();
+    // the default output format of the config file only applies to the commands that support it
+    if let (None, Some(default_format)) = (explicit_output_format, defaults.output_format) {
+        if config_enum.check_output_format(default_format).is_ok() {
+            output_format = default_format;
+            error_report::init(output_format == OutputFormat::Json);
+        }
+    }
+    config_enum
+        .check_output_format(output_format)
+        .unwrap_or_fatal_error();
+    // CARGO_CACHE_TRIM_LIMIT and friends, report typos with the name of the variable
+    env::check_values(config).unwrap_or_fatal_error();
 
     // handle hidden "version" subcommand
     if config.is_present("version") || matches!(config_enum, CargoCacheCommands::Version) {
@@ -132,418 +229,438 @@ fn main() {
     };
 
     match &config_enum {
-        CargoCacheCommands::SCCache => sccache::sccache_stats().exit_or_fatal_error(),
-        CargoCacheCommands::Toolchain => {
-            toolchains::toolchain_stats();
+        CargoCacheCommands::SCCache {
+            clean,
+            trim_limit,
+            dry_run,
+        } => {
+            if *clean || trim_limit.is_some() {
+                sccache::sccache_trim(*trim_limit, *dry_run).unwrap_or_fatal_error();
+            }
+            sccache::sccache_stats().exit_or_fatal_error();
+        }
+        CargoCacheCommands::Toolchain {
+            breakdown,
+            remove_toolchain,
+            remove_docs,
+            yes,
+            dry_run,
+        } => {
+            if remove_toolchain.is_some() || *remove_docs {
+                if let Some(name) = remove_toolchain {
+                    toolchains::remove_toolchain(name, *yes, *dry_run).unwrap_or_fatal_error();
+                }
+                if *remove_docs {
+                    toolchains::remove_docs(*yes, *dry_run).unwrap_or_fatal_error();
+                }
+            } else {
+                toolchains::toolchain_stats(*breakdown);
+            }
+            process::exit(0);
+        }
+        CargoCacheCommands::Examples => {
+            println!("{}", cli::all_examples());
+            process::exit(0);
+        }
+        #[cfg(feature = "json")]
+        CargoCacheCommands::OutputSchema => {
+            print_json(&schema::output_schema());
             process::exit(0);
         }
         _ => {}
     }
 
-    // indicates if size changed and whether we should print a before/after size diff
-    let mut size_changed: bool = false;
+    // stop scans and removals gracefully on Ctrl-C
+    cancel::init();
 
-    let cargo_cache = CargoCachePaths::default().unwrap_or_fatal_error();
+    // retry filesystem operations that fail with transient errors (network filesystems)
+    retry::init(
+        retry::RetryPolicy::new(config.value_of("retries"), config.value_of("retry-backoff"))
+            .unwrap_or_fatal_error(),
+    );
 
-    if let CargoCacheCommands::ListDirs = config_enum {
-        // only print the directories and exit, don't calculate anything else
-        println!("{cargo_cache}");
-        process::exit(0);
+    // "--use-system-trash": removed items go to the trash instead of being deleted
+    system_trash::init(config.is_present("use-system-trash"));
+
+    // stop scans at absurdly deep nesting, cycles are always detected
+    walk::init(
+        walk::parse_max_scan_depth(config.value_of("max-scan-depth")).unwrap_or_fatal_error(),
+    );
+
+    // let an external command approve what trim, clean-unref and the date removal remove
+    approve::init(config.value_of("approve-with")).unwrap_or_fatal_error();
+
+    // "--assume-tty"/"--no-tty": override the terminal detection of progress, prompts and links
+    tty::init(tty::TtyMode::new(
+        config.is_present("assume-tty"),
+        config.is_present("no-tty"),
+    ));
+    // print paths as clickable links in terminals that support it
+    hyperlink::init(hyperlink::HyperlinkMode::new(
+        config.value_of("hyperlinks").or_else(|| {
+            config
+                .subcommand_matches("query")
+                .or_else(|| config.subcommand_matches("q"))
+                .and_then(|query_config| query_config.value_of("hyperlinks"))
+        }),
+    ));
+
+    // "--lang": the summary and --info in the language of a message catalog
+    messages::init(
+        config.value_of("lang"),
+        config::config_path().as_deref().and_then(Path::parent),
+    )
+    .unwrap_or_fatal_error();
+
+    // prints the before/after size diff at the end of the run, once
+    let run_report = RunReport::new();
+
+    // "--cargo-home <path>" (any subcommand): treat any directory as the cargo home
+    // several of them (or a glob) run the command once for each cargo home
+    let cargo_homes =
+        multi_cache::cargo_homes(config.values_of("cargo-home").into_iter().flatten())
+            .unwrap_or_fatal_error();
+    if cargo_homes.len() > 1 {
+        multi_cache::run_per_cargo_home(&cargo_homes, &config_enum, output_format);
+    }
+    let cargo_cache = if let Some(cargo_home) = cargo_homes.into_iter().next() {
+        let cargo_cache = CargoCachePaths::from_root(cargo_home).unwrap_or_fatal_error();
+        for mismatch in cargo_cache.layout_mismatches() {
+            eprintln!("Warning: {mismatch}");
+        }
+        cargo_cache
+    } else {
+        CargoCachePaths::default().unwrap_or_fatal_error()
+    };
+    // networked operations stay offline if --offline, CARGO_NET_OFFLINE or net.offline say so
+    network::init(network::NetworkPolicy::new(
+        config.is_present("offline"),
+        &cargo_cache.cargo_home,
+    ));
+    // show the names of the registries from the cargo configs next to their directories
+    registry_names::init(registry_names::RegistryNames::new(&cargo_cache.cargo_home));
+    // paths listed in the .cargocacheignore are never scanned or removed
+    ignore::init(&cargo_cache.cargo_home, &defaults.exclude, debug_mode);
+    // reuse the sizes of the crate sources and git checkouts that did not change since the last run
+    if !config.is_present("no-size-cache") {
+        size_cache::init(
+            &cargo_cache.cargo_home,
+            config.is_present("refresh-size-cache"),
+        );
+    }
+    // a newer cargo may have written things we don't understand, leave those alone
+    let compatibility = compat::probe(&cargo_cache);
+    for warning in compatibility.warnings() {
+        eprintln!("Warning: {warning}");
     }
 
-    // create cache
-    let p = CargoCachePaths::default().unwrap();
+    // "maintain": exit before taking the lock or scanning anything if nothing is due
+    let maintenance = if let CargoCacheCommands::Maintain {
+        autoclean_above,
+        gc_every_days,
+        check_every_hours,
+        force,
+        ..
+    } = config_enum
+    {
+        if gc_every_days.is_some() && !cli::feature_enabled("git") {
+            Err::<(), _>(Error::FeatureDisabled(
+                String::from("maintain --gc-every-days"),
+                "git",
+            ))
+            .unwrap_or_fatal_error();
+        }
+        let policy = maintain::Policy::new(autoclean_above, gc_every_days, check_every_hours)
+            .unwrap_or_fatal_error();
+        let state = maintain::load_state(&cargo_cache.cargo_home);
+        let due = policy.due(state, history::now(), force);
+        if due.is_nothing() {
+            process::exit(0);
+        }
+        Some((policy, state, due))
+    } else {
+        None
+    };
 
-    let mut bin_cache = bin::BinaryCache::new(p.bin_dir);
-    let mut checkouts_cache = git_checkouts::GitCheckoutCache::new(p.git_checkouts);
-    let mut bare_repos_cache = git_bare_repos::GitRepoCache::new(p.git_repos_bare);
+    // a $CARGO_HOME or --cargo-home pointing at "/" or the home directory must not make us remove
+    // everything in there
+    if config_enum.modifies_cache() && !config.is_present("i-know-what-i-am-doing") {
+        cargo_cache
+            .check_safe_to_modify(home::home_dir().as_deref())
+            .unwrap_or_fatal_error();
+    }
+    // make sure no other cargo-cache removes things from the cache while we do,
+    // the lock is held until we exit
+    let _lock = if config_enum.modifies_cache() {
+        let policy = WaitPolicy::new(config.is_present("no-wait"), config.value_of("wait"))
+            .unwrap_or_fatal_error();
+        Some(CacheLock::acquire(&cargo_cache.cargo_home, policy).unwrap_or_fatal_error())
+    } else {
+        None
+    };
+    // remember how much space was freed for "cargo cache savings" and what was removed for "cargo cache log"
+    if config_enum.modifies_cache() {
+        history::init(&cargo_cache.cargo_home, config_enum.name());
+        #[cfg(feature = "json")]
+        journal::init(&cargo_cache.cargo_home, config_enum.name());
+    }
+    // "--stats": measure how fast files are removed
+    throughput::init(
+        config.is_present("stats")
+            || ["trim", "clean-unref"]
+                .iter()
+                .find_map(|name| config.subcommand_matches(name))
+                .map_or(false, |subcommand_config| {
+                    subcommand_config.is_present("stats")
+                }),
+    );
 
-    let mut registry_pkgs_cache =
-        registry_pkg_cache::RegistryPkgCaches::new(p.registry_pkg_cache.clone());
+    // "--quiet": no progress lines for --gc, trim and verify
+    progress::init(
+        config.is_present("quiet")
+            || ["trim", "verify"]
+                .iter()
+                .find_map(|name| config.subcommand_matches(name))
+                .map_or(false, |subcommand_config| {
+                    subcommand_config.is_present("quiet")
+                }),
+    );
 
-    //let mut registry_index_cache = registry_index::RegistryIndexCache::new(p.registry_index);
+    if let CargoCacheCommands::Savings = config_enum {
+        // only reads the history, no need to calculate any sizes
+        savings::savings(&cargo_cache);
+        process::exit(0);
+    }
 
-    let mut registry_sources_caches =
-        registry_sources::RegistrySourceCaches::new(p.registry_sources);
+    #[cfg(feature = "json")]
+    if let CargoCacheCommands::Log { last, paths } = config_enum {
+        // only reads the journal, no need to calculate any sizes
+        log::log(&cargo_cache.cargo_home, last, paths);
+        process::exit(0);
+    }
 
-    let p2 = CargoCachePaths::default().unwrap(); //@TODO remove this
+    if let CargoCacheCommands::Projects { action } = config_enum {
+        // only reads and writes the list of projects
+        commands::projects::projects(&cargo_cache.cargo_home, action).exit_or_fatal_error();
+    }
 
-    let mut registry_index_caches: registry_index::RegistryIndicesCache =
-        registry_index::RegistryIndicesCache::new(p2.registry_index);
+    if let CargoCacheCommands::Stats {
+        history: true,
+        last,
+        days,
+        sparkline,
+        ..
+    } = config_enum
+    {
+        // only reads the recorded snapshots, no need to calculate any sizes
+        stats::history_stats(&cargo_cache.cargo_home, last, days, sparkline);
+        process::exit(0);
+    }
 
-    // this should populate the entire cache, not very happy about this, wen we do this more lazily?
-    let dir_sizes_original = dirsizes::DirSizes::new(
-        &mut bin_cache,
-        &mut checkouts_cache,
-        &mut bare_repos_cache,
-        &mut registry_pkgs_cache,
-        &mut registry_index_caches,
-        &mut registry_sources_caches,
-        &cargo_cache,
-    );
+    #[cfg(feature = "metadata")]
+    if let CargoCacheCommands::SelfCheckUpdate { online } = config_enum {
+        // only needs the registry index, no need to calculate any sizes
+        self_update::check_update(&cargo_cache, online).exit_or_fatal_error();
+    }
 
-    match config_enum {
-        CargoCacheCommands::Trim {
-            dry_run,
-            trim_limit,
-        } => {
-            let trim_result = trim::trim_cache(
-                trim_limit,
-                &mut checkouts_cache,
-                &mut bare_repos_cache,
-                &mut registry_pkgs_cache,
-                &mut registry_sources_caches,
-                dry_run,
-                &mut size_changed,
-            );
-            dirsizes::DirSizes::print_size_difference(
-                &dir_sizes_original,
-                &cargo_cache,
-                &mut bin_cache,
-                &mut checkouts_cache,
-                &mut bare_repos_cache,
-                &mut registry_pkgs_cache,
-                &mut registry_index_caches,
-                &mut registry_sources_caches,
-            );
-            trim_result.exit_or_fatal_error();
-        }
-        CargoCacheCommands::CleanUnref {
-            dry_run,
-            manifest_path,
-        } => {
-            let clean_unref_result = clean_unref(
-                &cargo_cache,
-                manifest_path,
-                &mut bin_cache,
-                &mut checkouts_cache,
-                &mut bare_repos_cache,
-                &mut registry_pkgs_cache,
-                &mut registry_index_caches,
-                &mut registry_sources_caches,
-                dry_run,
-                &mut size_changed,
-            );
-            dirsizes::DirSizes::print_size_difference(
-                &dir_sizes_original,
-                &cargo_cache,
-                &mut bin_cache,
-                &mut checkouts_cache,
-                &mut bare_repos_cache,
-                &mut registry_pkgs_cache,
-                &mut registry_index_caches,
-                &mut registry_sources_caches,
-            );
-            clean_unref_result.exit_or_fatal_error();
-        }
-        CargoCacheCommands::TopCacheItems { limit } => {
-            if limit > 0 {
-                println!(
-                    "{}",
-                    get_top_crates(
-                        limit,
-                        &cargo_cache,
-                        &mut bin_cache,
-                        &mut checkouts_cache,
-                        &mut bare_repos_cache,
-                        &mut registry_pkgs_cache,
-                        /* &mut registry_index_cache, */
-                        &mut registry_sources_caches,
-                    )
-                );
-            }
-            process::exit(0);
-        }
-        CargoCacheCommands::Query { query_config } => {
-            query::run_query(
-                query_config,
-                &mut bin_cache,
-                &mut checkouts_cache,
-                &mut bare_repos_cache,
-                &mut registry_pkgs_cache,
-                &mut registry_sources_caches,
-            )
+    #[cfg(feature = "git")]
+    if let CargoCacheCommands::GitExport { repo, dest, rev } = config_enum {
+        // only needs the cached bare repo, no need to calculate any sizes
+        git_export::git_export(&cargo_cache, repo, dest, rev).exit_or_fatal_error();
+    }
+
+    if let CargoCacheCommands::GitListCheckouts { repo } = config_enum {
+        // only looks at the checkouts of one repo, no need to calculate any sizes
+        checkouts::list(&cargo_cache, repo).exit_or_fatal_error();
+    }
+
+    if let CargoCacheCommands::GitRmCheckout { repo, rev, dry_run } = config_enum {
+        checkouts::rm_checkout(&cargo_cache, repo, rev, dry_run).exit_or_fatal_error();
+    }
+
+    if let CargoCacheCommands::RemoveCrate {
+        spec,
+        git,
+        also_sccache,
+        dry_run,
+    } = config_enum
+    {
+        // only looks at the files of one crate, no need to calculate any sizes
+        remove_crate::remove_crate(&cargo_cache, spec, git, also_sccache, dry_run)
             .exit_or_fatal_error();
-        }
-        CargoCacheCommands::Local => {
-            local::local_subcmd().exit_or_fatal_error();
-        }
-        CargoCacheCommands::RemoveIfDate {
-            dry_run,
-            arg_younger,
-            arg_older,
-            dirs,
-        } => {
-            let res = crate::date::remove_files_by_dates(
-                &mut checkouts_cache,
-                &mut bare_repos_cache,
-                &mut registry_pkgs_cache,
-                /* &mut registry_index_cache, */
-                &mut registry_sources_caches,
-                arg_younger,
-                arg_older,
-                dry_run,
-                dirs,
-                &mut size_changed,
-            );
+    }
 
-            dirsizes::DirSizes::print_size_difference(
-                &dir_sizes_original,
-                &cargo_cache,
-                &mut bin_cache,
-                &mut checkouts_cache,
-                &mut bare_repos_cache,
-                &mut registry_pkgs_cache,
-                &mut registry_index_caches,
-                &mut registry_sources_caches,
-            );
-            // don't run --remove-dir stuff (since we also required that parameter)
+    if let CargoCacheCommands::Inspect {
+        crate_name,
+        version,
+        limit,
+    } = config_enum
+    {
+        // only looks at the files of one crate, no need to calculate any sizes
+        inspect::inspect(&cargo_cache, crate_name, version, limit).exit_or_fatal_error();
+    }
 
-            res.exit_or_fatal_error();
-        }
-        CargoCacheCommands::Info => {
-            println!("{}", get_info(&cargo_cache, &dir_sizes_original));
-            process::exit(0);
-        }
-        // This one must come BEFORE RemoveIfDate because that one also uses --remove dir
-        CargoCacheCommands::RemoveDir { dry_run } => {
-            let res = remove_dir_via_cmdline(
-                config.value_of("remove-dir"),
-                dry_run,
-                &cargo_cache,
-                &mut size_changed,
-                &mut checkouts_cache,
-                &mut bare_repos_cache,
-                &mut registry_index_caches,
-                &mut registry_pkgs_cache,
-                &mut registry_sources_caches,
-            );
+    #[cfg(feature = "metadata")]
+    if let CargoCacheCommands::PreheatInstall {
+        crate_name,
+        version,
+    } = config_enum
+    {
+        // only needs the registry index and the crate archives, no need to calculate any sizes
+        preheat_install::preheat_install(&cargo_cache, crate_name, version).exit_or_fatal_error();
+    }
 
-            dirsizes::DirSizes::print_size_difference(
-                &dir_sizes_original,
-                &cargo_cache,
-                &mut bin_cache,
-                &mut checkouts_cache,
-                &mut bare_repos_cache,
-                &mut registry_pkgs_cache,
-                &mut registry_index_caches,
-                &mut registry_sources_caches,
-            );
-            res.unwrap_or_fatal_error();
-        }
-        CargoCacheCommands::FSCKRepos => {
-            git_fsck_everything(&cargo_cache.git_repos_bare, &cargo_cache.registry_pkg_cache)
-                .exit_or_fatal_error();
-        }
-        CargoCacheCommands::GitGCRepos { dry_run } => {
-            //@TODO deduplicate between autoclean-expensive!
-            let res = git_gc_everything(
-                &cargo_cache.git_repos_bare,
-                &cargo_cache.registry_pkg_cache,
-                dry_run,
-            );
+    if let CargoCacheCommands::Compare {
+        other_cargo_home,
+        merge_from,
+        dry_run,
+    } = config_enum
+    {
+        compare::compare(&cargo_cache, other_cargo_home, merge_from, dry_run).exit_or_fatal_error();
+    }
 
-            if !dry_run {
-                bare_repos_cache.invalidate();
-                registry_index_caches.invalidate();
-                size_changed = true;
-            }
-            // do not terminate cargo cache since gc is part of autoclean-expensive
-            res.unwrap_or_fatal_error();
-        }
+    #[cfg(feature = "backup")]
+    if let CargoCacheCommands::Backup { output, components } = config_enum {
+        // only reads the selected components, no need to calculate any sizes
+        backup::backup(&cargo_cache, Path::new(output), components).exit_or_fatal_error();
+    }
 
-        CargoCacheCommands::AutoClean { dry_run } => {
-            // clean the registry sources and git checkouts
-            let reg_srcs = &cargo_cache.registry_sources;
-            let git_checkouts = &cargo_cache.git_checkouts;
-
-            // depending on the size of the cache and the system (SSD, HDD...) this can take a few seconds.
-            println!("Clearing cache...\n");
-
-            for dir in &[reg_srcs, git_checkouts] {
-                let size = cumulative_dir_size(dir);
-                if dir.is_dir() {
-                    remove_file(
-                        dir,
-                        dry_run,
-                        &mut size_changed,
-                        None,
-                        &DryRunMessage::Default,
-                        Some(size.dir_size),
-                    );
-                }
-            }
-            registry_sources_caches.invalidate();
-            checkouts_cache.invalidate();
-
-            dirsizes::DirSizes::print_size_difference(
-                &dir_sizes_original,
-                &cargo_cache,
-                &mut bin_cache,
-                &mut checkouts_cache,
-                &mut bare_repos_cache,
-                &mut registry_pkgs_cache,
-                &mut registry_index_caches,
-                &mut registry_sources_caches,
-            );
-            std::process::exit(0);
-        }
-        CargoCacheCommands::AutoCleanExpensive { dry_run } => {
-            let res = git_gc_everything(
-                &cargo_cache.git_repos_bare,
-                &cargo_cache.registry_pkg_cache,
-                dry_run,
-            );
+    #[cfg(feature = "backup")]
+    if let CargoCacheCommands::Restore { archive, dry_run } = config_enum {
+        backup::restore(&cargo_cache, Path::new(archive), dry_run).exit_or_fatal_error();
+    }
 
-            if !dry_run {
-                bare_repos_cache.invalidate();
-                registry_index_caches.invalidate();
-            }
-            // do not terminate cargo cache since gc is part of autoclean-expensive
-            res.unwrap_or_fatal_error();
-            size_changed = true;
-
-            // clean the registry sources and git checkouts
-            let reg_srcs = &cargo_cache.registry_sources;
-            let git_checkouts = &cargo_cache.git_checkouts;
-
-            // depending on the size of the cache and the system (SSD, HDD...) this can take a few seconds.
-            println!("Clearing cache...\n");
-
-            for dir in &[reg_srcs, git_checkouts] {
-                let size = cumulative_dir_size(dir);
-                if dir.is_dir() {
-                    remove_file(
-                        dir,
-                        dry_run,
-                        &mut size_changed,
-                        None,
-                        &DryRunMessage::Default,
-                        Some(size.dir_size),
-                    );
-                }
-            }
-            registry_sources_caches.invalidate();
-            checkouts_cache.invalidate();
-
-            dirsizes::DirSizes::print_size_difference(
-                &dir_sizes_original,
-                &cargo_cache,
-                &mut bin_cache,
-                &mut checkouts_cache,
-                &mut bare_repos_cache,
-                &mut registry_pkgs_cache,
-                &mut registry_index_caches,
-                &mut registry_sources_caches,
-            );
-            std::process::exit(0);
-        }
-        CargoCacheCommands::KeepDuplicateCrates { dry_run, limit } => {
-            let res = rm_old_crates(
-                limit,
-                dry_run,
-                &cargo_cache.registry_pkg_cache,
-                &mut size_changed,
-            );
-            registry_pkgs_cache.invalidate();
-            registry_sources_caches.invalidate();
-
-            dirsizes::DirSizes::print_size_difference(
-                &dir_sizes_original,
-                &cargo_cache,
-                &mut bin_cache,
-                &mut checkouts_cache,
-                &mut bare_repos_cache,
-                &mut registry_pkgs_cache,
-                &mut registry_index_caches,
-                &mut registry_sources_caches,
-            );
+    if let CargoCacheCommands::Doctor { fix, dry_run } = config_enum {
+        // only looks at the layout of the components, no need to calculate any sizes
+        doctor::doctor(&cargo_cache, fix, dry_run).exit_or_fatal_error();
+    }
 
-            if let Err(error) = res {
-                match error {
-                    Error::MalformedPackageName(_) => {
-                        // force a stacktrace here
-                        panic!("{}", error);
-                    }
-                    _ => unreachable!(),
-                };
-            }
-        }
-        CargoCacheCommands::OnlyDryRun => {
-            if !size_changed {
-                eprintln!("Warning: there is nothing to be dry run!");
-            }
-        }
-        CargoCacheCommands::Verify {
-            clean_corrupted,
-            dry_run,
-        } => {
-            println!("Verifying cache, this may take some time...\n");
-            if let Err(failed_verifications) = verify::verify_crates(&mut registry_sources_caches) {
-                eprintln!("\n");
-                failed_verifications
-                    .iter()
-                    .for_each(|diff| println!("{}", diff.details()));
-                eprintln!(
-                    "\nFound {} possible corrupted sources.",
-                    failed_verifications.len()
-                );
-
-                if clean_corrupted {
-                    verify::clean_corrupted(
-                        &mut registry_sources_caches,
-                        &failed_verifications,
-                        dry_run,
-                    );
-                } else {
-                    println!("Hint: use `cargo cache verify --clean-corrupted` to remove them.");
-                }
+    if let CargoCacheCommands::Ui { dry_run } = config_enum {
+        // calculates the sizes of the crates itself
+        ui::ui(&cargo_cache, dry_run).exit_or_fatal_error();
+    }
 
-                std::process::exit(1)
-            } else {
-                std::process::exit(0);
-            }
-        }
-        _ => (),
+    if let CargoCacheCommands::ListDirs = config_enum {
+        // only print the directories and exit, don't calculate anything else
+        println!("{cargo_cache}");
+        process::exit(0);
     }
 
-    if size_changed && !config.is_present("dry-run") {
-        // size has changed, print summary of how size has changed
+    if let CargoCacheCommands::Estimate = config_enum {
+        // samples the components itself instead of calculating the exact sizes
+        estimate::print_estimate(&cargo_cache);
+        process::exit(0);
+    }
 
-        dirsizes::DirSizes::print_size_difference(
-            &dir_sizes_original,
+    // "--components" / "--no-default-scan": only look at some of the components
+    let selection = ComponentSelection::new(
+        config.value_of("components"),
+        config.is_present("no-default-scan"),
+    )
+    .unwrap_or_fatal_error()
+    .without(&compatibility.unrecognized_components());
+
+    if let CargoCacheCommands::SelftestSizes = config_enum {
+        // scans the selected components itself, twice
+        selftest::selftest_sizes(&cargo_cache, &selection).exit_or_fatal_error();
+    }
+
+    // create cache
+    // caches of components that were not selected are created empty and never touch the disk
+    let mut caches = dispatch::Caches::new(&cargo_cache, &selection);
+
+    // --autoclean: skip scanning the whole cache if nothing was extracted since the last run
+    // (unless --keep-days keeps recently used items, older ones may have become removable since)
+    if let CargoCacheCommands::AutoClean {
+        ref registries,
+        keep_days: 0,
+        ..
+    } = config_enum
+    {
+        let dirs = autoclean_dirs(
             &cargo_cache,
-            &mut bin_cache,
-            &mut checkouts_cache,
-            &mut bare_repos_cache,
-            &mut registry_pkgs_cache,
-            &mut registry_index_caches,
-            &mut registry_sources_caches,
+            &selection,
+            registries,
+            &mut caches.registry_sources,
         );
+        let dirs = dirs.iter().map(PathBuf::as_path).collect::<Vec<_>>();
+        if let Some(last_run) =
+            incremental::unchanged_since_last_run(&cargo_cache.cargo_home, &dirs)
+        {
+            println!(
+                "Nothing to clean up, no crate sources or git checkouts were extracted since the last --autoclean ({}).",
+                chrono::DateTime::<chrono::Local>::from(last_run).format("%Y-%m-%d %H:%M:%S")
+            );
+            process::exit(0);
+        }
     }
 
-    // no println!() here!
-    // print the default summary
-    if matches!(config_enum, CargoCacheCommands::Registries) {
-        // print per-registry summary
-        let output = dirsizes::per_registry_summary(
-            &dir_sizes_original,
-            &mut registry_index_caches,
-            &mut registry_sources_caches,
-            &mut registry_pkgs_cache,
-        );
-        print!("{output}");
-    } else if matches!(config_enum, CargoCacheCommands::DefaultSummary) {
-        // default summary
-        print!("{dir_sizes_original}");
+    // this should populate the entire cache, not very happy about this, wen we do this more lazily?
+    let dir_sizes_original = caches.dir_sizes(&cargo_cache, &selection);
+
+    if cancel::is_cancelled() {
+        // report what we found until we were cancelled
+        if matches!(config_enum, CargoCacheCommands::DefaultSummary)
+            && output_format == OutputFormat::Table
+        {
+            print!("{dir_sizes_original}");
+            cancel::exit_if_cancelled("the scan did not finish, the sizes above are incomplete");
+        }
+        cancel::exit_if_cancelled("the scan did not finish, nothing was changed");
+    }
+
+    // "snapshots" in the [stats] table of the config file: remember the sizes for "stats --history"
+    if let Some(keep) = defaults.stats_snapshots.filter(|&keep| keep > 0) {
+        if !selection.is_restricted() {
+            snapshots::record(
+                &cargo_cache.cargo_home,
+                snapshots::Snapshot::new(&dir_sizes_original, history::now()),
+                usize::try_from(keep).unwrap_or(usize::MAX),
+            );
+        }
     }
 
+    // run the command, then print the size difference and the summary unless the command is done
+    let mut stdout = io::stdout();
+    // "--keep" (also after "trim") and "keep" of the config file: crates that are never removed
+    let keep = KeepList::new(
+        config.value_of("keep").or_else(|| {
+            config
+                .subcommand_matches("trim")
+                .and_then(|trim| trim.value_of("keep"))
+        }),
+        &defaults.keep,
+    );
+    let mut context = dispatch::Context {
+        paths: &cargo_cache,
+        selection: &selection,
+        keep: &keep,
+        caches,
+        defaults: &defaults,
+        out: &mut stdout,
+        dry_run: config.is_present("dry-run"),
+        output_format,
+        report: run_report,
+    };
+    let flow = match dispatch::run(&mut context, &config_enum, &dir_sizes_original, maintenance)
+        .unwrap_or_fatal_error()
+    {
+        dispatch::Flow::Continue => dispatch::finish(context, &config_enum, &dir_sizes_original),
+        flow @ dispatch::Flow::Exit(_) => flow,
+    };
+    if let dispatch::Flow::Exit(code) = flow {
+        process::exit(code);
+    }
+
+    // for example query or --top-cache-items which print what they found until they were cancelled
+    cancel::exit_if_cancelled("the scan did not finish, the output above is incomplete");
+
     if debug_mode {
         println!("\ndebug:");
 
         let time_elasped = time_started.unwrap().elapsed().unwrap();
 
-        let cache_root = CargoCachePaths::default().unwrap().cargo_home;
+        let cache_root = &cargo_cache.cargo_home;
 
         let wd = WalkDir::new(cache_root.display().to_string());
         let file_count = wd.into_iter().count();
@@ -0,0 +1,473 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// the wording of the summary, --info and the table headers, translatable through message catalogs
+// Every fixed text of that output is a `Message` with a key and its English text. Packagers can
+// ship a catalog "<lang>.messages" which replaces the texts of some or all messages:
+//     # comment
+//     summary.total = Gesamt
+//     summary.installed-binaries = {count} installierte Programme
+// "--lang <lang>" (or CARGO_CACHE_LANG) looks the catalog up in $CARGO_CACHE_MESSAGES_DIR,
+// "messages" next to the config file (~/.config/cargo-cache/messages) and
+// /usr/share/cargo-cache/messages, in that order.
+// Messages a catalog does not have are printed in English, so the catalogs of older versions keep
+// working. messages/en.messages lists every message and is the template for new catalogs.
+// The json output is for scripts and is never translated.
+
+use std::collections::HashMap;
+use std::env;
+use std::fmt::Display;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use once_cell::sync::OnceCell;
+
+use crate::library::Error;
+
+/// the catalog of this run, set up once in `main()`, English if not set up
+static CATALOG: OnceCell<Catalog> = OnceCell::new();
+
+/// the directory searched for catalogs first, before the user and system directories
+pub(crate) const MESSAGES_DIR: &str = "CARGO_CACHE_MESSAGES_DIR";
+
+/// where distributions install catalogs
+const SYSTEM_MESSAGES_DIR: &str = "/usr/share/cargo-cache/messages";
+
+/// a fixed text of the summary, --info or a table header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Message {
+    SummaryHeader,
+    SummaryComponents,
+    SummaryTotal,
+    SummaryInstalledBinaries,
+    SummaryGitDb,
+    SummaryBareGitRepos,
+    SummaryGitCheckouts,
+    SummaryOtherFiles,
+    SummaryOtherDirectories,
+    SummaryRegistry,
+    SummaryEmptyRegistry,
+    SummaryRegistryIndex,
+    SummaryRegistryIndices,
+    SummaryCrateArchives,
+    SummaryCratesAndVersions,
+    SummaryCrateSources,
+    InfoCargoHomeFromFlag,
+    InfoCargoHomeFromEnv,
+    InfoCargoHomeDefault,
+    InfoTotal,
+    InfoBinaries,
+    InfoBinariesAbout,
+    InfoBinariesHint,
+    InfoRegistry,
+    InfoRegistryAbout,
+    InfoRegistryIndex,
+    InfoRegistryIndexAbout,
+    InfoRegistryIndexHint,
+    InfoCrateArchives,
+    InfoCrateArchivesAbout,
+    InfoCrateArchivesHint,
+    InfoCrateSources,
+    InfoCrateSourcesAbout,
+    InfoCrateSourcesHint,
+    InfoGitDb,
+    InfoGitDbAbout,
+    InfoGitDbHint,
+    InfoGitCheckouts,
+    InfoGitCheckoutsAbout,
+    InfoGitCheckoutsHint,
+    TableName,
+    TableCount,
+    TableAverage,
+    TableTotal,
+    TableSize,
+    TableKind,
+    TableCheckout,
+    TableCachedFiles,
+    TableCacheSize,
+    TableLastUsed,
+}
+
+impl Message {
+    /// every message, in the order of messages/en.messages
+    #[cfg(test)]
+    const ALL: &'static [Self] = &[
+        Self::SummaryHeader,
+        Self::SummaryComponents,
+        Self::SummaryTotal,
+        Self::SummaryInstalledBinaries,
+        Self::SummaryGitDb,
+        Self::SummaryBareGitRepos,
+        Self::SummaryGitCheckouts,
+        Self::SummaryOtherFiles,
+        Self::SummaryOtherDirectories,
+        Self::SummaryRegistry,
+        Self::SummaryEmptyRegistry,
+        Self::SummaryRegistryIndex,
+        Self::SummaryRegistryIndices,
+        Self::SummaryCrateArchives,
+        Self::SummaryCratesAndVersions,
+        Self::SummaryCrateSources,
+        Self::InfoCargoHomeFromFlag,
+        Self::InfoCargoHomeFromEnv,
+        Self::InfoCargoHomeDefault,
+        Self::InfoTotal,
+        Self::InfoBinaries,
+        Self::InfoBinariesAbout,
+        Self::InfoBinariesHint,
+        Self::InfoRegistry,
+        Self::InfoRegistryAbout,
+        Self::InfoRegistryIndex,
+        Self::InfoRegistryIndexAbout,
+        Self::InfoRegistryIndexHint,
+        Self::InfoCrateArchives,
+        Self::InfoCrateArchivesAbout,
+        Self::InfoCrateArchivesHint,
+        Self::InfoCrateSources,
+        Self::InfoCrateSourcesAbout,
+        Self::InfoCrateSourcesHint,
+        Self::InfoGitDb,
+        Self::InfoGitDbAbout,
+        Self::InfoGitDbHint,
+        Self::InfoGitCheckouts,
+        Self::InfoGitCheckoutsAbout,
+        Self::InfoGitCheckoutsHint,
+        Self::TableName,
+        Self::TableCount,
+        Self::TableAverage,
+        Self::TableTotal,
+        Self::TableSize,
+        Self::TableKind,
+        Self::TableCheckout,
+        Self::TableCachedFiles,
+        Self::TableCacheSize,
+        Self::TableLastUsed,
+    ];
+
+    /// the key of the message in the catalogs and its English text
+    /// placeholders like {count} are filled in by `fill()`
+    fn entry(self) -> (&'static str, &'static str) {
+        match self {
+            Self::SummaryHeader => ("summary.header", "Cargo cache '{path}'{components}:"),
+            Self::SummaryComponents => ("summary.components", "(components: {components})"),
+            Self::SummaryTotal => ("summary.total", "Total"),
+            Self::SummaryInstalledBinaries => {
+                ("summary.installed-binaries", "{count} installed binaries")
+            }
+            Self::SummaryGitDb => ("summary.git-db", "Git db"),
+            Self::SummaryBareGitRepos => ("summary.bare-git-repos", "{count} bare git repos"),
+            Self::SummaryGitCheckouts => ("summary.git-checkouts", "{count} git repo checkouts"),
+            Self::SummaryOtherFiles => ("summary.other-files", "{count} other files"),
+            Self::SummaryOtherDirectories => {
+                ("summary.other-directories", "{count} in other directories")
+            }
+            Self::SummaryRegistry => ("summary.registry", "Registry"),
+            Self::SummaryEmptyRegistry => ("summary.empty-registry", "(empty)"),
+            Self::SummaryRegistryIndex => ("summary.registry-index", "Registry index"),
+            Self::SummaryRegistryIndices => {
+                ("summary.registry-indices", "{count} registry indices")
+            }
+            Self::SummaryCrateArchives => ("summary.crate-archives", "{count} crate archives"),
+            Self::SummaryCratesAndVersions => (
+                "summary.crates-and-versions",
+                "{crates} crates, {versions} versions",
+            ),
+            Self::SummaryCrateSources => {
+                ("summary.crate-sources", "{count} crate source checkouts")
+            }
+            Self::InfoCargoHomeFromFlag => (
+                "info.cargo-home-from-flag",
+                "--cargo-home set to '{path}', using that!",
+            ),
+            Self::InfoCargoHomeFromEnv => (
+                "info.cargo-home-from-env",
+                "${CARGO_HOME} env var set to '{path}', using that!",
+            ),
+            Self::InfoCargoHomeDefault => (
+                "info.cargo-home-default",
+                "Default cache dir found: '{path}', using that!",
+            ),
+            Self::InfoTotal => ("info.total", "Total cache size: {size}"),
+            Self::InfoBinaries => (
+                "info.binaries",
+                "{count} binaries installed in binary directory, total size: {size}",
+            ),
+            Self::InfoBinariesAbout => (
+                "info.binaries-about",
+                "These are the binaries installed via 'cargo install'.",
+            ),
+            Self::InfoBinariesHint => (
+                "info.binaries-hint",
+                "Use 'cargo uninstall' to remove binaries if needed.",
+            ),
+            Self::InfoRegistry => ("info.registry", "Registry root dir, size: {size}"),
+            Self::InfoRegistryAbout => ("info.registry-about", "Crate registries are stored here."),
+            Self::InfoRegistryIndex => ("info.registry-index", "Registry index, size: {size}"),
+            Self::InfoRegistryIndexAbout => (
+                "info.registry-index-about",
+                "A git repo holding information on what crates are available.",
+            ),
+            Self::InfoRegistryIndexHint => {
+                ("info.registry-index-hint", "Will be recloned as needed.")
+            }
+            Self::InfoCrateArchives => (
+                "info.crate-archives",
+                "Crate source package archive, size: {size}",
+            ),
+            Self::InfoCrateArchivesAbout => (
+                "info.crate-archives-about",
+                "Crates source packages of the registries are downloaded into this folder.",
+            ),
+            Self::InfoCrateArchivesHint => (
+                "info.crate-archives-hint",
+                "They will be redownloaded as needed.",
+            ),
+            Self::InfoCrateSources => ("info.crate-sources", "Crate sources, size: {size}"),
+            Self::InfoCrateSourcesAbout => (
+                "info.crate-sources-about",
+                "Source archives are extracted into this dir.",
+            ),
+            Self::InfoCrateSourcesHint => (
+                "info.crate-sources-hint",
+                "They will be reextracted from the package archive as needed.",
+            ),
+            Self::InfoGitDb => ("info.git-db", "Git database, size: {size}"),
+            Self::InfoGitDbAbout => (
+                "info.git-db-about",
+                "Bare repos of git dependencies are stored here.",
+            ),
+            Self::InfoGitDbHint => (
+                "info.git-db-hint",
+                "Removed git repositories will be recloned as needed.",
+            ),
+            Self::InfoGitCheckouts => ("info.git-checkouts", "Git repo checkouts, size: {size}"),
+            Self::InfoGitCheckoutsAbout => (
+                "info.git-checkouts-about",
+                "Specific commits of the bare repos will be checked out into here.",
+            ),
+            Self::InfoGitCheckoutsHint => (
+                "info.git-checkouts-hint",
+                "Git checkouts will be rechecked-out from repo database as needed.",
+            ),
+            Self::TableName => ("table.name", "Name"),
+            Self::TableCount => ("table.count", "Count"),
+            Self::TableAverage => ("table.average", "Average"),
+            Self::TableTotal => ("table.total", "Total"),
+            Self::TableSize => ("table.size", "Size"),
+            Self::TableKind => ("table.kind", "Kind"),
+            Self::TableCheckout => ("table.checkout", "Checkout"),
+            Self::TableCachedFiles => ("table.cached-files", "Cached files"),
+            Self::TableCacheSize => ("table.cache-size", "Cache size"),
+            Self::TableLastUsed => ("table.last-used", "Last used"),
+        }
+    }
+}
+
+/// the texts of a language, keyed by the keys of the messages
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Catalog {
+    texts: HashMap<String, String>,
+}
+
+impl Catalog {
+    /// parse the lines "key = text" of a catalog, empty lines and lines starting with # are ignored
+    /// returns the number of the first invalid line
+    fn parse(content: &str) -> Result<Self, usize> {
+        let mut texts = HashMap::new();
+        for (number, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match line.split_once('=') {
+                Some((key, text)) if !key.trim().is_empty() => {
+                    let _ = texts.insert(key.trim().to_string(), text.trim().to_string());
+                }
+                _ => return Err(number + 1),
+            }
+        }
+        Ok(Self { texts })
+    }
+
+    /// the text of the message in this language, English if the catalog does not have it
+    fn text(&self, message: Message) -> &str {
+        let (key, english) = message.entry();
+        self.texts.get(key).map_or(english, String::as_str)
+    }
+}
+
+/// the directories that are searched for catalogs, `config_dir` is the directory of the config file
+fn catalog_dirs(config_dir: Option<&Path>) -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if let Some(dir) = env::var_os(MESSAGES_DIR).filter(|dir| !dir.is_empty()) {
+        dirs.push(PathBuf::from(dir));
+    }
+    if let Some(config_dir) = config_dir {
+        dirs.push(config_dir.join("messages"));
+    }
+    dirs.push(PathBuf::from(SYSTEM_MESSAGES_DIR));
+    dirs
+}
+
+/// read the catalog of `lang` from the first of `dirs` that has one, English is always there
+fn load(lang: &str, dirs: &[PathBuf]) -> Result<Catalog, Error> {
+    // "de" or "pt_BR", never a path
+    if lang.is_empty()
+        || !lang
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        return Err(Error::UnknownLanguage(lang.to_string()));
+    }
+    for dir in dirs {
+        let path = dir.join(format!("{lang}.messages"));
+        if let Ok(content) = fs::read_to_string(&path) {
+            return Catalog::parse(&content).map_err(|line| {
+                Error::InvalidMessageCatalog(path, format!("line {line} is not \"key = text\""))
+            });
+        }
+    }
+    if lang == "en" {
+        Ok(Catalog::default())
+    } else {
+        Err(Error::UnknownLanguage(lang.to_string()))
+    }
+}
+
+/// print the summary and --info in `lang`, needs to be called before anything is printed
+pub(crate) fn init(lang: Option<&str>, config_dir: Option<&Path>) -> Result<(), Error> {
+    let catalog = match lang {
+        Some(lang) => load(lang, &catalog_dirs(config_dir))?,
+        None => Catalog::default(),
+    };
+    let _ = CATALOG.set(catalog);
+    Ok(())
+}
+
+/// the text of the message in the language of this run
+pub(crate) fn text(message: Message) -> String {
+    match CATALOG.get() {
+        Some(catalog) => catalog.text(message).to_string(),
+        None => message.entry().1.to_string(),
+    }
+}
+
+/// the text of the message with its placeholders replaced by `values`
+pub(crate) fn fill(message: Message, values: &[(&str, &dyn Display)]) -> String {
+    replace_placeholders(&text(message), values)
+}
+
+/// replace "{name}" by the value of name, placeholders without a value are left alone
+fn replace_placeholders(text: &str, values: &[(&str, &dyn Display)]) -> String {
+    values.iter().fold(text.to_string(), |text, (name, value)| {
+        text.replace(&format!("{{{name}}}"), &value.to_string())
+    })
+}
+
+#[cfg(test)]
+mod messages_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn unique_keys() {
+        let mut keys = Message::ALL
+            .iter()
+            .map(|message| message.entry().0)
+            .collect::<Vec<&str>>();
+        let number_of_keys = keys.len();
+        keys.sort_unstable();
+        keys.dedup();
+        assert_eq!(keys.len(), number_of_keys);
+    }
+
+    #[test]
+    fn template_is_complete() {
+        // messages/en.messages is what translators start from, it must have every message
+        let template = Catalog::parse(include_str!("../messages/en.messages")).unwrap();
+        for message in Message::ALL {
+            let (key, english) = message.entry();
+            assert_eq!(template.texts.get(key).map(String::as_str), Some(english));
+        }
+        assert_eq!(template.texts.len(), Message::ALL.len());
+    }
+
+    #[test]
+    fn parse_catalogs() {
+        let catalog = Catalog::parse(
+            "# German\n\
+            \n\
+            summary.total = Gesamt\n\
+            summary.installed-binaries =   {count} installierte Programme  \n",
+        )
+        .unwrap();
+        assert_eq!(catalog.text(Message::SummaryTotal), "Gesamt");
+        assert_eq!(
+            catalog.text(Message::SummaryInstalledBinaries),
+            "{count} installierte Programme"
+        );
+        // not translated
+        assert_eq!(catalog.text(Message::SummaryGitDb), "Git db");
+
+        assert_eq!(Catalog::parse("summary.total = Gesamt\nGit db\n"), Err(2));
+        assert_eq!(Catalog::parse("= Gesamt"), Err(1));
+    }
+
+    #[test]
+    fn placeholders() {
+        assert_eq!(
+            replace_placeholders(
+                "{crates} crates, {versions} versions",
+                &[("crates", &3), ("versions", &"4 => 5")]
+            ),
+            "3 crates, 4 => 5 versions"
+        );
+        assert_eq!(
+            replace_placeholders("${CARGO_HOME} is '{path}'", &[("path", &"/tmp")]),
+            "${CARGO_HOME} is '/tmp'"
+        );
+    }
+
+    #[test]
+    fn load_catalogs() {
+        let system = tempfile::tempdir().unwrap();
+        let user = tempfile::tempdir().unwrap();
+        fs::write(
+            system.path().join("de.messages"),
+            "summary.total = Gesamt\n",
+        )
+        .unwrap();
+        fs::write(user.path().join("de.messages"), "summary.total = Summe\n").unwrap();
+        fs::write(system.path().join("broken.messages"), "Gesamt\n").unwrap();
+        let dirs = [user.path().to_path_buf(), system.path().to_path_buf()];
+
+        // the first directory wins
+        assert_eq!(
+            load("de", &dirs).unwrap().text(Message::SummaryTotal),
+            "Summe"
+        );
+        assert_eq!(
+            load("de", &dirs[1..]).unwrap().text(Message::SummaryTotal),
+            "Gesamt"
+        );
+        assert_eq!(load("en", &dirs).unwrap(), Catalog::default());
+        assert!(matches!(
+            load("fr", &dirs),
+            Err(Error::UnknownLanguage(lang)) if lang == "fr"
+        ));
+        assert!(matches!(
+            load("../de", &dirs),
+            Err(Error::UnknownLanguage(_))
+        ));
+        assert!(matches!(
+            load("broken", &dirs),
+            Err(Error::InvalidMessageCatalog(..))
+        ));
+    }
+}
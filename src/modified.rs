@@ -0,0 +1,211 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// detect crate sources in registry/src that were modified after cargo extracted them
+// People sometimes patch the sources of a dependency in place while debugging, --autoclean
+// would silently throw these patches away.
+// Cargo writes a ".cargo-ok" file into the source directory once it is done extracting and the
+// files themselves get the (old) mtime that is stored in the .crate archive, so any file that is
+// newer than ".cargo-ok" (or the archive, if there is no ".cargo-ok") was changed afterwards.
+// This only looks at mtimes and is cheap, "cargo cache verify --content" compares the contents.
+// The mtime of the source directory does not change either, so the sizes remembered for the
+// modified sources (see size_cache.rs) are forgotten here.
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use walkdir::WalkDir;
+
+use crate::cache::caches::RegistrySuperCache;
+use crate::cache::*;
+use crate::size_cache;
+use crate::walk::guarded;
+
+/// file systems with coarse timestamps may round, do not count anything within this as modified
+const TIMESTAMP_SLACK: Duration = Duration::from_secs(2);
+
+/// the .crate archive of an extracted crate source
+/// registry/src/<registry>/foo-1.0.0 => registry/cache/<registry>/foo-1.0.0.crate
+fn archive_of_source(source: &Path) -> Option<PathBuf> {
+    let registry_dir = source.parent()?;
+    let registry = registry_dir.parent()?.parent()?;
+    let mut archive_name = source.file_name()?.to_os_string();
+    archive_name.push(".crate");
+    Some(
+        registry
+            .join("cache")
+            .join(registry_dir.file_name()?)
+            .join(archive_name),
+    )
+}
+
+/// when cargo finished extracting the crate source
+fn extraction_time(source: &Path) -> Option<SystemTime> {
+    let metadata = match source.join(".cargo-ok").metadata() {
+        Ok(metadata) => metadata,
+        Err(_) => archive_of_source(source)?.metadata().ok()?,
+    };
+    metadata.modified().ok()
+}
+
+/// whether any file of the crate source was modified after it was extracted
+pub(crate) fn is_modified(source: &Path) -> bool {
+    match extraction_time(source) {
+        Some(extracted) => modified_after(source, extracted + TIMESTAMP_SLACK),
+        // we can't tell
+        None => false,
+    }
+}
+
+/// whether any file of the crate source, apart from ".cargo-ok", is newer than `time`
+fn modified_after(source: &Path, time: SystemTime) -> bool {
+    guarded(WalkDir::new(source).min_depth(1), |_| true)
+        .filter(|entry| entry.file_type().is_file() && entry.file_name() != ".cargo-ok")
+        .filter_map(|entry| entry.metadata().ok()?.modified().ok())
+        .any(|modified| modified > time)
+}
+
+/// the crate sources below the given directories that were modified after extraction
+pub(crate) fn modified_sources(
+    registry_sources_caches: &mut registry_sources::RegistrySourceCaches,
+    dirs: &[PathBuf],
+) -> Vec<PathBuf> {
+    let mut modified = registry_sources_caches
+        .items()
+        .iter()
+        .filter(|source| dirs.iter().any(|dir| source.starts_with(dir)))
+        .filter(|source| is_modified(source))
+        .cloned()
+        .collect::<Vec<PathBuf>>();
+    modified.sort();
+    size_cache::forget(&modified);
+    modified
+}
+
+/// replace the directories that contain modified sources by the unmodified crate sources inside them
+pub(crate) fn without_modified(
+    dirs: Vec<PathBuf>,
+    sources: &[PathBuf],
+    modified: &[PathBuf],
+) -> Vec<PathBuf> {
+    dirs.into_iter()
+        .flat_map(|dir| {
+            if modified.iter().any(|source| source.starts_with(&dir)) {
+                sources
+                    .iter()
+                    .filter(|source| source.starts_with(&dir) && !modified.contains(source))
+                    .cloned()
+                    .collect::<Vec<PathBuf>>()
+            } else {
+                vec![dir]
+            }
+        })
+        .collect()
+}
+
+/// warn about modified crate sources that --autoclean is going to remove,
+/// with --preserve-modified keep them and return the directories that can be removed instead
+pub(crate) fn protect_modified_sources(
+    dirs: Vec<PathBuf>,
+    registry_sources_caches: &mut registry_sources::RegistrySourceCaches,
+    preserve_modified: bool,
+) -> Vec<PathBuf> {
+    let modified = modified_sources(registry_sources_caches, &dirs);
+    if modified.is_empty() {
+        return dirs;
+    }
+
+    let list = modified
+        .iter()
+        .map(|source| format!("  {}", source.display()))
+        .collect::<Vec<String>>()
+        .join("\n");
+    if preserve_modified {
+        println!(
+            "Keeping {} crate sources that were modified after they were extracted:\n{list}\n",
+            modified.len()
+        );
+        without_modified(dirs, registry_sources_caches.items(), &modified)
+    } else {
+        eprintln!(
+            "Warning: {} crate sources were modified after they were extracted and will be removed:\n{list}\n\
+            Pass --preserve-modified to keep them.\n",
+            modified.len()
+        );
+        dirs
+    }
+}
+
+#[cfg(test)]
+mod modified_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::fs;
+
+    #[test]
+    fn archive_path() {
+        assert_eq!(
+            archive_of_source(Path::new(
+                "/cargo/registry/src/github.com-1ecc6299db9ec823/bytes-0.4.12"
+            )),
+            Some(PathBuf::from(
+                "/cargo/registry/cache/github.com-1ecc6299db9ec823/bytes-0.4.12.crate"
+            ))
+        );
+    }
+
+    #[test]
+    fn modified_after_extraction() {
+        let tmp = tempfile::tempdir().unwrap();
+        let source = tmp.path().join("registry/src/reg-1/foo-1.0.0");
+        fs::create_dir_all(source.join("src")).unwrap();
+        fs::write(source.join("src/lib.rs"), "fn foo() {}").unwrap();
+
+        // no .cargo-ok and no archive: we can't tell
+        assert_eq!(extraction_time(&source), None);
+        assert!(!is_modified(&source));
+
+        // extracted just now
+        fs::write(source.join(".cargo-ok"), "{\"v\":1}").unwrap();
+        assert!(extraction_time(&source).is_some());
+        assert!(!is_modified(&source));
+
+        let hour = Duration::from_secs(3600);
+        assert!(modified_after(&source, SystemTime::now() - hour));
+        assert!(!modified_after(&source, SystemTime::now() + hour));
+        // .cargo-ok itself does not count
+        fs::remove_file(source.join("src/lib.rs")).unwrap();
+        assert!(!modified_after(&source, SystemTime::now() - hour));
+    }
+
+    #[test]
+    fn keep_modified() {
+        let src = PathBuf::from("/cargo/registry/src");
+        let checkouts = PathBuf::from("/cargo/git/checkouts");
+        let foo = src.join("reg-1/foo-1.0.0");
+        let bar = src.join("reg-1/bar-1.0.0");
+        let baz = src.join("reg-2/baz-1.0.0");
+        let sources = vec![foo.clone(), bar.clone(), baz.clone()];
+        let modified = vec![foo];
+
+        assert_eq!(
+            without_modified(vec![src.clone(), checkouts.clone()], &sources, &modified),
+            vec![bar, baz, checkouts.clone()]
+        );
+        // registries that do not contain modified sources are removed as a whole
+        assert_eq!(
+            without_modified(
+                vec![src.join("reg-1"), src.join("reg-2"), checkouts.clone()],
+                &sources,
+                &modified
+            ),
+            vec![src.join("reg-1/bar-1.0.0"), src.join("reg-2"), checkouts]
+        );
+    }
+}
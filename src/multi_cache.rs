@@ -0,0 +1,338 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// several cargo homes at once: "--cargo-home /srv/cargo/user1 --cargo-home /srv/cargo/user2" or
+// a quoted glob, "--cargo-home '/srv/cargo/*'".
+// A run keeps a lot of state per cargo home (the lock, the .cargocacheignore, the registry names, the
+// network policy...), so instead of juggling several of those we run ourselves once per cargo home
+// with the same arguments and only that --cargo-home. The summary adds up the sizes of all of them.
+
+use std::collections::HashSet;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+
+#[cfg(feature = "json")]
+use humansize::{FormatSize, DECIMAL};
+
+use crate::cli::CargoCacheCommands;
+use crate::ignore::glob_match;
+use crate::library::{Error, ErrorHandling, OutputFormat};
+#[cfg(feature = "json")]
+use crate::tables::format_table;
+
+/// the options that select the cargo home, the runs per cargo home get exactly one
+const CARGO_HOME_OPTIONS: [&str; 3] = ["--cargo-home", "--cache-dir", "--root"];
+
+fn is_glob(value: &str) -> bool {
+    value.contains(['*', '?'])
+}
+
+/// the directories matching a glob such as "/srv/cargo/*", '*' and '?' do not match across '/'
+fn expand_glob(glob: &str) -> Vec<PathBuf> {
+    let mut matches = vec![PathBuf::new()];
+    for component in Path::new(glob).components() {
+        let name = component.as_os_str().to_string_lossy();
+        if !is_glob(&name) {
+            for path in &mut matches {
+                path.push(component);
+            }
+            continue;
+        }
+        matches = matches
+            .iter()
+            .flat_map(|dir| {
+                let read_from = if dir.as_os_str().is_empty() {
+                    Path::new(".")
+                } else {
+                    dir.as_path()
+                };
+                let mut entries = fs::read_dir(read_from)
+                    .into_iter()
+                    .flatten()
+                    .flatten()
+                    .map(|entry| entry.file_name())
+                    .filter(|entry_name| glob_match(&name, &entry_name.to_string_lossy()))
+                    .map(|entry_name| dir.join(entry_name))
+                    .collect::<Vec<_>>();
+                entries.sort();
+                entries
+            })
+            .collect();
+    }
+    matches.retain(|path| path.is_dir());
+    matches
+}
+
+/// the cargo homes of the --cargo-home values with the globs expanded, each one once
+pub(crate) fn cargo_homes<'a>(
+    values: impl IntoIterator<Item = &'a str>,
+) -> Result<Vec<PathBuf>, Error> {
+    let mut cargo_homes = Vec::new();
+    for value in values {
+        if is_glob(value) {
+            let matches = expand_glob(value);
+            if matches.is_empty() {
+                return Err(Error::CargoHomeGlobNoMatch(value.to_string()));
+            }
+            cargo_homes.extend(matches);
+        } else {
+            cargo_homes.push(PathBuf::from(value));
+        }
+    }
+    let mut seen = HashSet::new();
+    cargo_homes.retain(|cargo_home| seen.insert(cargo_home.clone()));
+    Ok(cargo_homes)
+}
+
+/// the arguments without the `options` and their values, "--option value" and "--option=value"
+fn without_options(args: impl IntoIterator<Item = OsString>, options: &[&str]) -> Vec<OsString> {
+    let mut kept = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let (takes_value, has_value) = {
+            let text = arg.to_string_lossy();
+            (
+                options.contains(&text.as_ref()),
+                options
+                    .iter()
+                    .any(|option| text.starts_with(&format!("{option}="))),
+            )
+        };
+        if takes_value {
+            let _ = args.next();
+        } else if !has_value {
+            kept.push(arg);
+        }
+    }
+    kept
+}
+
+/// cargo-cache with the arguments of this run, but only for `cargo_home`
+fn run_for(cargo_home: &Path, also_without: &[&str], extra_args: &[&str]) -> Command {
+    let exe = env::current_exe().unwrap_or_else(|_| PathBuf::from("cargo-cache"));
+    let options = CARGO_HOME_OPTIONS
+        .iter()
+        .chain(also_without)
+        .copied()
+        .collect::<Vec<_>>();
+    let mut command = Command::new(exe);
+    let _ = command
+        .args(without_options(env::args_os().skip(1), &options))
+        .arg("--cargo-home")
+        .arg(cargo_home)
+        .args(extra_args);
+    command
+}
+
+/// run the command once for each cargo home, with the output of each one under a header
+fn run_each(cargo_homes: &[PathBuf]) -> Vec<PathBuf> {
+    let mut failed = Vec::new();
+    for (index, cargo_home) in cargo_homes.iter().enumerate() {
+        if index > 0 {
+            println!();
+        }
+        println!(
+            "Cargo home {}/{}: '{}'",
+            index + 1,
+            cargo_homes.len(),
+            cargo_home.display()
+        );
+        let success = run_for(cargo_home, &[], &[])
+            .status()
+            .map_or(false, |status| status.success());
+        if !success {
+            failed.push(cargo_home.clone());
+        }
+    }
+    failed
+}
+
+/// the sizes of a summary printed with --output-format json
+#[cfg(feature = "json")]
+fn summary_sizes(summary: &serde_json::Value) -> [u64; 4] {
+    let size = |pointer: &str| summary.pointer(pointer).and_then(serde_json::Value::as_u64);
+    [
+        size("/total_size").unwrap_or_default(),
+        size("/binaries/size").unwrap_or_default(),
+        size("/registry/size").unwrap_or_default(),
+        size("/git/size").unwrap_or_default(),
+    ]
+}
+
+/// the summary of each cargo home and of all of them together
+#[cfg(feature = "json")]
+fn breakdown_table(summaries: &[(PathBuf, serde_json::Value)]) -> String {
+    let mut table = vec![["Cargo home", "Total", "Binaries", "Registry", "Git"]
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()];
+    let mut totals = [0_u64; 4];
+    for (cargo_home, summary) in summaries {
+        let sizes = summary_sizes(summary);
+        for (total, size) in totals.iter_mut().zip(sizes) {
+            *total += size;
+        }
+        let mut row = vec![cargo_home.display().to_string()];
+        row.extend(sizes.iter().map(|size| size.format_size(DECIMAL)));
+        table.push(row);
+    }
+    let mut row = vec![format!("All {} cargo homes", summaries.len())];
+    row.extend(totals.iter().map(|size| size.format_size(DECIMAL)));
+    table.push(row);
+    format_table(&table, 0)
+}
+
+/// run the command once for each cargo home and collect what it prints as json
+#[cfg(feature = "json")]
+fn run_each_json(
+    cargo_homes: &[PathBuf],
+    summary: bool,
+) -> (Vec<(PathBuf, serde_json::Value)>, Vec<PathBuf>) {
+    let mut outputs = Vec::new();
+    let mut failed = Vec::new();
+    for cargo_home in cargo_homes {
+        // the summary of each cargo home is always added up from its json
+        let mut command = if summary {
+            run_for(
+                cargo_home,
+                &["--output-format"],
+                &["--output-format", "json"],
+            )
+        } else {
+            run_for(cargo_home, &[], &[])
+        };
+        match command.stderr(process::Stdio::inherit()).output() {
+            Ok(output) if output.status.success() => {
+                let value = serde_json::from_slice(&output.stdout).unwrap_or_else(|_| {
+                    serde_json::Value::String(String::from_utf8_lossy(&output.stdout).into_owned())
+                });
+                outputs.push((cargo_home.clone(), value));
+            }
+            _ => failed.push(cargo_home.clone()),
+        }
+    }
+    (outputs, failed)
+}
+
+/// run the command for each of several cargo homes and exit, the summary gets a breakdown per
+/// cargo home and the total of all of them
+pub(crate) fn run_per_cargo_home(
+    cargo_homes: &[PathBuf],
+    command: &CargoCacheCommands<'_>,
+    output_format: OutputFormat,
+) -> ! {
+    if !command.works_per_cargo_home() {
+        Err::<(), _>(Error::SingleCargoHomeOnly(command.name())).unwrap_or_fatal_error();
+    }
+    let summary = matches!(command, CargoCacheCommands::DefaultSummary);
+
+    #[cfg(feature = "json")]
+    let failed = if summary || output_format == OutputFormat::Json {
+        let (outputs, failed) = run_each_json(cargo_homes, summary);
+        if output_format == OutputFormat::Json {
+            let mut json = serde_json::json!({
+                "cargo_homes": outputs
+                    .iter()
+                    .map(|(cargo_home, output)| serde_json::json!({
+                        "cargo_home": cargo_home.display().to_string(),
+                        "output": output,
+                    }))
+                    .collect::<Vec<_>>(),
+                "failed": failed
+                    .iter()
+                    .map(|cargo_home| cargo_home.display().to_string())
+                    .collect::<Vec<_>>(),
+            });
+            if summary {
+                json["total_size"] = outputs
+                    .iter()
+                    .map(|(_, output)| summary_sizes(output)[0])
+                    .sum::<u64>()
+                    .into();
+            }
+            crate::library::print_json(&json);
+        } else {
+            print!("{}", breakdown_table(&outputs));
+        }
+        failed
+    } else {
+        run_each(cargo_homes)
+    };
+    #[cfg(not(feature = "json"))]
+    let failed = {
+        let _ = (summary, output_format);
+        run_each(cargo_homes)
+    };
+
+    if failed.is_empty() {
+        process::exit(0);
+    }
+    Err::<(), _>(Error::CargoHomesFailed(failed)).unwrap_or_fatal_error();
+    unreachable!()
+}
+
+#[cfg(test)]
+mod multi_cache_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn globs() {
+        let tmp = tempfile::tempdir().unwrap();
+        for dir in ["user2", "user1", "other/user3"] {
+            fs::create_dir_all(tmp.path().join(dir)).unwrap();
+        }
+        fs::write(tmp.path().join("user.txt"), "").unwrap();
+
+        let glob = tmp.path().join("user*").display().to_string();
+        assert_eq!(
+            cargo_homes([glob.as_str()]).unwrap(),
+            vec![tmp.path().join("user1"), tmp.path().join("user2")]
+        );
+        let nested = tmp.path().join("*").join("user?").display().to_string();
+        assert_eq!(
+            cargo_homes([nested.as_str()]).unwrap(),
+            vec![tmp.path().join("other").join("user3")]
+        );
+        // the same cargo home twice is only used once
+        let user1 = tmp.path().join("user1").display().to_string();
+        assert_eq!(
+            cargo_homes([user1.as_str(), glob.as_str()]).unwrap(),
+            vec![tmp.path().join("user1"), tmp.path().join("user2")]
+        );
+        let nothing = tmp.path().join("nobody*").display().to_string();
+        assert!(matches!(
+            cargo_homes([nothing.as_str()]),
+            Err(Error::CargoHomeGlobNoMatch(_))
+        ));
+    }
+
+    #[test]
+    fn options_are_removed() {
+        let args = [
+            "--cargo-home",
+            "/a",
+            "trim",
+            "--cache-dir=/b",
+            "--limit",
+            "1G",
+            "--root",
+            "/c",
+        ]
+        .iter()
+        .map(OsString::from);
+        assert_eq!(
+            without_options(args, &CARGO_HOME_OPTIONS),
+            vec!["trim", "--limit", "1G"]
+        );
+    }
+}
@@ -0,0 +1,100 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// the names of the items of the cache
+// Crate archives and sources are named "<name>-<version>" ("serde-json-1.0.0.crate"), registries,
+// git repos and checkouts "<name>-<hash>" ("github.com-1ecc6299db9ec823"). Anything else can end
+// up in a cargo home as well: files without a version, names that are not UTF-8, dotfiles...
+// These helpers never panic on such names, the "testing" feature fuzzes them.
+
+use std::borrow::Cow;
+use std::path::Path;
+
+/// the last component of the path, invalid UTF-8 is replaced and ".." or "/" give ""
+pub(crate) fn file_name(path: &Path) -> Cow<'_, str> {
+    path.file_name()
+        .map_or(Cow::Borrowed(""), |name| name.to_string_lossy())
+}
+
+/// split "serde-json-1.0.0.crate" or "serde-json-1.0.0" into name and version
+/// the version starts with the first segment that starts with a digit, it is empty if there is none
+pub(crate) fn split_name_version(file_name: &str) -> (&str, &str) {
+    let stem = file_name.strip_suffix(".crate").unwrap_or(file_name);
+    let mut start = 0;
+    for segment in stem.split('-') {
+        if segment.starts_with(|c: char| c.is_ascii_digit()) {
+            let name = stem[..start].strip_suffix('-').unwrap_or_default();
+            return (name, &stem[start..]);
+        }
+        start += segment.len() + 1;
+    }
+    (stem, "")
+}
+
+/// "github.com-1ecc6299db9ec823" or "yaml-rust-07c50cf5815b3a80" without the hash,
+/// a name without '-' has no hash and nothing is left
+pub(crate) fn strip_hash(dir_name: &str) -> &str {
+    dir_name
+        .rsplit_once('-')
+        .map_or("", |(without_hash, _hash)| without_hash)
+}
+
+#[cfg(test)]
+mod names_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::path::PathBuf;
+
+    #[test]
+    fn names_and_versions() {
+        assert_eq!(
+            split_name_version("serde-json-1.0.0.crate"),
+            ("serde-json", "1.0.0")
+        );
+        assert_eq!(
+            split_name_version("heim-runtime-0.1.0-beta.1"),
+            ("heim-runtime", "0.1.0-beta.1")
+        );
+        assert_eq!(split_name_version("xz2-0.1.4.crate"), ("xz2", "0.1.4"));
+        assert_eq!(split_name_version("no_version"), ("no_version", ""));
+        assert_eq!(split_name_version("1.0.0"), ("", "1.0.0"));
+        assert_eq!(split_name_version("-1"), ("", "1"));
+        assert_eq!(split_name_version("ü-1"), ("ü", "1"));
+        assert_eq!(split_name_version(""), ("", ""));
+    }
+
+    #[test]
+    fn hashes() {
+        assert_eq!(strip_hash("github.com-1ecc6299db9ec823"), "github.com");
+        assert_eq!(strip_hash("yaml-rust-07c50cf5815b3a80"), "yaml-rust");
+        assert_eq!(strip_hash("nohash"), "");
+        assert_eq!(strip_hash("-"), "");
+    }
+
+    #[test]
+    fn file_names() {
+        assert_eq!(
+            file_name(Path::new("a/b/foo-1.0.0.crate")),
+            "foo-1.0.0.crate"
+        );
+        assert_eq!(file_name(Path::new("/")), "");
+        assert_eq!(file_name(Path::new("a/..")), "");
+        assert_eq!(file_name(&PathBuf::new()), "");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_file_names() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+        let path = Path::new(OsStr::from_bytes(b"registry/cache/f\xffo-1.0.0.crate"));
+        assert_eq!(file_name(path), "f\u{fffd}o-1.0.0.crate");
+        assert_eq!(split_name_version(&file_name(path)).1, "1.0.0");
+    }
+}
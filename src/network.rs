@@ -0,0 +1,246 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// the network policy: cargo-cache works offline
+// Everything that may access the network ("self check-update --online", "clean-unref --allow-net",
+// "metrics --push" and whatever comes next) has to be asked for explicitly and has to go through
+// `allow()`.
+// Just like cargo we stay offline if --offline is passed, CARGO_NET_OFFLINE is set or
+// net.offline is set in a cargo config, even if the user asked for network access.
+
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use once_cell::sync::OnceCell;
+
+use crate::library::Error;
+
+/// the network policy of this run, set up once in `main()`
+static NETWORK_POLICY: OnceCell<NetworkPolicy> = OnceCell::new();
+
+/// why network access is forbidden
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Offline {
+    /// --offline was passed
+    Flag,
+    /// `CARGO_NET_OFFLINE=true`
+    Env,
+    /// net.offline = true in this cargo config
+    Config(PathBuf),
+}
+
+impl fmt::Display for Offline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Flag => write!(f, "--offline was passed"),
+            Self::Env => write!(f, "CARGO_NET_OFFLINE is set"),
+            Self::Config(path) => write!(f, "net.offline is set in \"{}\"", path.display()),
+        }
+    }
+}
+
+/// whether network access is forbidden even if an operation asks for it
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct NetworkPolicy {
+    offline: Option<Offline>,
+}
+
+impl NetworkPolicy {
+    /// get the policy from --offline, `${CARGO_NET_OFFLINE}` and the cargo configs
+    pub(crate) fn new(offline_flag: bool, cargo_home: &Path) -> Self {
+        let configs = env::current_dir()
+            .map(|cwd| config_files(&cwd, cargo_home))
+            .unwrap_or_default();
+        Self::from_sources(
+            offline_flag,
+            env::var("CARGO_NET_OFFLINE").ok().as_deref(),
+            &configs,
+        )
+    }
+
+    /// the flag beats the environment which beats the configs, just like in cargo
+    fn from_sources(offline_flag: bool, env_var: Option<&str>, configs: &[PathBuf]) -> Self {
+        let offline = if offline_flag {
+            Some(Offline::Flag)
+        } else {
+            match env_var.map(str::trim) {
+                Some("true") => Some(Offline::Env),
+                Some("false") => None,
+                _ => configs
+                    .iter()
+                    .find_map(|config| {
+                        let content = fs::read_to_string(config).ok()?;
+                        net_offline(&content)
+                            .map(|offline| offline.then(|| Offline::Config(config.clone())))
+                    })
+                    .flatten(),
+            }
+        };
+        Self { offline }
+    }
+
+    /// whether `operation` may access the network, `requested` is whether the user asked for it
+    pub(crate) fn allow(&self, operation: &str, requested: bool) -> Result<bool, Error> {
+        match &self.offline {
+            _ if !requested => Ok(false),
+            Some(offline) => Err(Error::NetworkDisabled(
+                operation.to_string(),
+                offline.to_string(),
+            )),
+            None => Ok(true),
+        }
+    }
+}
+
+/// the cargo configs that apply in `cwd`, the closest one first
+pub(crate) fn config_files(cwd: &Path, cargo_home: &Path) -> Vec<PathBuf> {
+    let mut dirs = cwd
+        .ancestors()
+        .map(|dir| dir.join(".cargo"))
+        .collect::<Vec<PathBuf>>();
+    if !dirs.iter().any(|dir| dir == cargo_home) {
+        dirs.push(cargo_home.to_path_buf());
+    }
+    dirs.iter()
+        .flat_map(|dir| [dir.join("config.toml"), dir.join("config")])
+        .filter(|config| config.is_file())
+        .collect()
+}
+
+/// the value of net.offline in a cargo config, either in a [net] table or as dotted key
+fn net_offline(config: &str) -> Option<bool> {
+    let mut table = String::new();
+    for line in config.lines() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if let Some(header) = line.strip_prefix('[') {
+            table = header.trim_end_matches(']').trim().to_string();
+            continue;
+        }
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) => (key.trim(), value.trim()),
+            None => continue,
+        };
+        let key = if table.is_empty() {
+            key.to_string()
+        } else {
+            format!("{table}.{key}")
+        };
+        if key.replace(' ', "") == "net.offline" {
+            return value.parse().ok();
+        }
+    }
+    None
+}
+
+/// set the network policy, needs to be called before any network access may happen
+pub(crate) fn init(policy: NetworkPolicy) {
+    let _ = NETWORK_POLICY.set(policy);
+}
+
+/// whether `operation` may access the network with the configured policy
+/// `requested` is whether the user asked for it, without that we are always offline
+#[cfg_attr(not(any(feature = "metadata", feature = "metrics")), allow(dead_code))]
+pub(crate) fn allow(operation: &str, requested: bool) -> Result<bool, Error> {
+    NETWORK_POLICY
+        .get()
+        .cloned()
+        .unwrap_or_default()
+        .allow(operation, requested)
+}
+
+#[cfg(test)]
+mod network_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn config_values() {
+        assert_eq!(net_offline("[net]\noffline = true\n"), Some(true));
+        assert_eq!(net_offline("[net]\nretry = 2\noffline=false"), Some(false));
+        assert_eq!(net_offline("net.offline = true # no network"), Some(true));
+        assert_eq!(net_offline("[build]\noffline = true\n"), None);
+        assert_eq!(net_offline("[net]\n# offline = true\n"), None);
+        assert_eq!(net_offline(""), None);
+    }
+
+    #[test]
+    fn precedence() {
+        let tmp = tempfile::tempdir().unwrap();
+        let offline = tmp.path().join("offline.toml");
+        let online = tmp.path().join("online.toml");
+        fs::write(&offline, "[net]\noffline = true\n").unwrap();
+        fs::write(&online, "[net]\noffline = false\n").unwrap();
+
+        // nothing set
+        assert_eq!(
+            NetworkPolicy::from_sources(false, None, &[]),
+            NetworkPolicy::default()
+        );
+        // the closest config wins
+        assert_eq!(
+            NetworkPolicy::from_sources(false, None, &[offline.clone(), online.clone()]).offline,
+            Some(Offline::Config(offline.clone()))
+        );
+        assert_eq!(
+            NetworkPolicy::from_sources(false, None, &[online.clone(), offline.clone()]),
+            NetworkPolicy::default()
+        );
+        // the env beats the configs, the flag beats everything
+        assert_eq!(
+            NetworkPolicy::from_sources(false, Some("true"), std::slice::from_ref(&online)).offline,
+            Some(Offline::Env)
+        );
+        assert_eq!(
+            NetworkPolicy::from_sources(true, Some("false"), &[online]).offline,
+            Some(Offline::Flag)
+        );
+        assert_eq!(
+            NetworkPolicy::from_sources(false, Some("false"), &[offline]),
+            NetworkPolicy::default()
+        );
+    }
+
+    #[test]
+    fn allowed() {
+        let online = NetworkPolicy::default();
+        assert!(!online.allow("op", false).unwrap());
+        assert!(online.allow("op", true).unwrap());
+
+        let offline = NetworkPolicy {
+            offline: Some(Offline::Env),
+        };
+        assert!(!offline.allow("op", false).unwrap());
+        assert!(matches!(
+            offline.allow("op", true),
+            Err(Error::NetworkDisabled(operation, reason))
+                if operation == "op" && reason == "CARGO_NET_OFFLINE is set"
+        ));
+    }
+
+    #[test]
+    fn configs() {
+        let tmp = tempfile::tempdir().unwrap();
+        let project = tmp.path().join("project");
+        let cargo_home = tmp.path().join("cargo_home");
+        fs::create_dir_all(project.join(".cargo")).unwrap();
+        fs::create_dir_all(&cargo_home).unwrap();
+        fs::write(project.join(".cargo/config.toml"), "").unwrap();
+        fs::write(cargo_home.join("config"), "").unwrap();
+
+        assert_eq!(
+            config_files(&project.join("src"), &cargo_home),
+            vec![
+                project.join(".cargo/config.toml"),
+                cargo_home.join("config")
+            ]
+        );
+    }
+}
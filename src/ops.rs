@@ -0,0 +1,491 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! the cleanups of cargo-cache for embedding into other tools
+//!
+//! [`autoclean`], [`trim`] and [`clean_unref`] remove the same things as
+//! `cargo cache --autoclean`, `cargo cache trim --limit` and `cargo cache clean-unref`, but they
+//! never print anything or ask for confirmation. What is removed is reported to a [`Progress`]
+//! and returned as [`Summary`], the user interface is up to the caller.
+//! Paths listed in the `.cargocacheignore` of the cargo home are never removed.
+//!
+//! Unless they are dry runs, the operations refuse to work on a cargo home that looks like the
+//! root of the file system, the home directory or a directory cargo has nothing to do with, and
+//! they wait until no other cargo-cache works on the same cargo home.
+//!
+//! ```no_run
+//! use cargo_cache::ops::{self, AutocleanOptions, CargoHome};
+//!
+//! let cargo_home = CargoHome::detect()?;
+//! let options = AutocleanOptions {
+//!     dry_run: true,
+//!     ..AutocleanOptions::default()
+//! };
+//! let summary = ops::autoclean(&cargo_home, &options, &mut ())?;
+//! println!("--autoclean would free {} bytes", summary.freed());
+//! # Ok::<(), ops::Error>(())
+//! ```
+//!
+//! The operations can also run in the background as a [`ScanTask`]. Its [`CancelToken`] stops it
+//! early, what was removed until then is still reported.
+
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::SystemTime;
+
+use crate::cache::caches::{Cache, RegistrySuperCache};
+use crate::cache::*;
+use crate::cancel;
+#[cfg(feature = "metadata")]
+use crate::clean_unref::unreferenced_items;
+use crate::commands::trim::items_to_trim;
+use crate::compat;
+use crate::ignore::{self, IgnoreList};
+use crate::incremental;
+use crate::keep::KeepList;
+use crate::library::{
+    self, size_of_path, CargoCachePaths, Component, ComponentSelection, RegistryFilter,
+};
+use crate::lock::{CacheLock, WaitPolicy};
+use crate::modified::{modified_sources, without_modified};
+use crate::network::NetworkPolicy;
+use crate::remove::{autoclean_dirs, check_same_device, remove_path};
+
+/// the cargo home whose cache is cleaned
+#[derive(Debug, Clone)]
+pub struct CargoHome {
+    paths: CargoCachePaths,
+    /// the .cargocacheignore of this cargo home
+    ignore: Arc<IgnoreList>,
+    /// `CARGO_NET_OFFLINE` and `net.offline` of the cargo configs that apply to this cargo home
+    #[cfg_attr(not(feature = "metadata"), allow(dead_code))]
+    network: NetworkPolicy,
+}
+
+impl CargoHome {
+    /// the cargo home of the current user: `$CARGO_HOME` or `~/.cargo`
+    ///
+    /// # Errors
+    ///
+    /// if the cargo home can not be found or is not a directory
+    pub fn detect() -> Result<Self, Error> {
+        CargoCachePaths::default().map(Self::new).map_err(Error)
+    }
+
+    /// a cargo home at `path`
+    ///
+    /// # Errors
+    ///
+    /// if `path` is not a directory
+    pub fn at(path: impl Into<PathBuf>) -> Result<Self, Error> {
+        CargoCachePaths::new(path.into())
+            .map(Self::new)
+            .map_err(Error)
+    }
+
+    fn new(paths: CargoCachePaths) -> Self {
+        Self {
+            ignore: Arc::new(IgnoreList::load(&paths.cargo_home, &[], false)),
+            network: NetworkPolicy::new(false, &paths.cargo_home),
+            paths,
+        }
+    }
+
+    /// the path of the cargo home
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.paths.cargo_home
+    }
+}
+
+/// an error of one of the operations, printable for the user
+pub struct Error(library::Error);
+
+impl fmt::Debug for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.0, f)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// callbacks for what an operation is doing, all of them do nothing by default
+///
+/// The operations scan on several threads, so the callbacks must be `Send`.
+pub trait Progress: Send {
+    /// `path` (`size` bytes) is about to be removed, or would be removed in a dry run
+    fn removing(&mut self, _path: &Path, _size: u64) {}
+
+    /// removing `path` failed, the operation continues with the other paths
+    fn failed(&mut self, _path: &Path, _error: &io::Error) {}
+}
+
+/// no progress reporting
+impl Progress for () {}
+
+/// what an operation removed, or would have removed in a dry run
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Summary {
+    /// the removed files and directories and their sizes in bytes
+    pub removed: Vec<(PathBuf, u64)>,
+    /// the paths that could not be removed
+    pub failed: Vec<PathBuf>,
+    /// crate sources that were modified after they were extracted (--autoclean only)
+    pub modified: Vec<PathBuf>,
+}
+
+impl Summary {
+    /// the number of bytes that were freed
+    #[must_use]
+    pub fn freed(&self) -> u64 {
+        self.removed.iter().map(|(_, size)| size).sum()
+    }
+}
+
+/// options of [`autoclean`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AutocleanOptions {
+    /// do not remove anything, only report what would be removed
+    pub dry_run: bool,
+    /// keep crate sources that were modified after they were extracted
+    pub preserve_modified: bool,
+    /// also remove directories that are located on a different device than their parent
+    pub allow_cross_device: bool,
+}
+
+/// options of [`trim`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrimOptions {
+    /// the size in bytes the cache must not exceed afterwards
+    pub limit: u64,
+    /// do not remove anything, only report what would be removed
+    pub dry_run: bool,
+}
+
+/// options of [`clean_unref`]
+#[cfg(feature = "metadata")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CleanUnrefOptions {
+    /// the Cargo.toml of the project whose dependencies are kept
+    pub manifest_path: PathBuf,
+    /// let cargo access the network to resolve the dependencies, unless cargo is configured to be offline
+    pub allow_net: bool,
+    /// do not remove anything, only report what would be removed
+    pub dry_run: bool,
+}
+
+/// everything but the components a newer cargo wrote in a way we don't understand
+fn selection(paths: &CargoCachePaths) -> Result<ComponentSelection, Error> {
+    ComponentSelection::new(None, false)
+        .map(|selection| selection.without(&compat::probe(paths).unrecognized_components()))
+        .map_err(Error)
+}
+
+/// run `operation` on the cargo home with its ignore list
+/// unless it is a dry run, make sure the cargo home is safe to modify and hold its lock meanwhile
+fn run<T: Send>(
+    cargo_home: &CargoHome,
+    dry_run: bool,
+    operation: impl FnOnce() -> Result<T, Error> + Send,
+) -> Result<T, Error> {
+    let _lock = if dry_run {
+        None
+    } else {
+        let paths = &cargo_home.paths;
+        paths
+            .check_safe_to_modify(home::home_dir().as_deref())
+            .map_err(Error)?;
+        Some(CacheLock::acquire(&paths.cargo_home, WaitPolicy::Forever).map_err(Error)?)
+    };
+    ignore::with_list(&cargo_home.ignore, operation)
+}
+
+/// remove the items, skipping ignored ones and going on if a removal fails
+fn remove_items(
+    cargo_home: &CargoHome,
+    items: Vec<(PathBuf, u64)>,
+    dry_run: bool,
+    progress: &mut dyn Progress,
+) -> Summary {
+    let mut summary = Summary::default();
+    // stop before the next item once the task was cancelled
+    for (path, size) in items.into_iter().take_while(|_| !cancel::is_cancelled()) {
+        if cargo_home.ignore.is_ignored(&path) {
+            continue;
+        }
+        progress.removing(&path, size);
+        if dry_run {
+            summary.removed.push((path, size));
+            continue;
+        }
+        match remove_path(&path) {
+            Ok(()) => summary.removed.push((path, size)),
+            Err(error) => {
+                progress.failed(&path, &error);
+                summary.failed.push(path);
+            }
+        }
+    }
+    summary
+}
+
+/// remove the extracted crate sources and git checkouts, like `cargo cache --autoclean`
+///
+/// # Errors
+///
+/// if a directory is located on a different device than its parent and
+/// `allow_cross_device` is not set, nothing is removed in that case
+/// if the cargo home is not safe to modify, see the [module docs](self)
+pub fn autoclean(
+    cargo_home: &CargoHome,
+    options: &AutocleanOptions,
+    progress: &mut dyn Progress,
+) -> Result<Summary, Error> {
+    run(cargo_home, options.dry_run, || {
+        autoclean_locked(cargo_home, options, progress)
+    })
+}
+
+fn autoclean_locked(
+    cargo_home: &CargoHome,
+    options: &AutocleanOptions,
+    progress: &mut dyn Progress,
+) -> Result<Summary, Error> {
+    let paths = &cargo_home.paths;
+    let mut registry_sources_caches =
+        registry_sources::RegistrySourceCaches::new(paths.registry_sources.clone());
+
+    let dirs = autoclean_dirs(
+        paths,
+        &selection(paths)?,
+        &RegistryFilter::new(None, None),
+        &mut registry_sources_caches,
+    );
+    let modified = modified_sources(&mut registry_sources_caches, &dirs);
+    let dirs = if options.preserve_modified && !modified.is_empty() {
+        without_modified(dirs, registry_sources_caches.items(), &modified)
+    } else {
+        dirs
+    };
+
+    if !options.allow_cross_device {
+        for dir in &dirs {
+            check_same_device(dir).map_err(Error)?;
+        }
+    }
+
+    let started = SystemTime::now();
+    let items = dirs
+        .into_iter()
+        .filter(|dir| dir.exists())
+        .map(|dir| {
+            let size = size_of_path(&dir);
+            (dir, size)
+        })
+        .collect();
+    let summary = remove_items(cargo_home, items, options.dry_run, progress);
+    if !options.dry_run {
+        incremental::record_run(&paths.cargo_home, started);
+    }
+
+    Ok(Summary {
+        modified,
+        ..summary
+    })
+}
+
+/// remove the least recently used items until the cache is smaller than the limit,
+/// like `cargo cache trim --limit`
+///
+/// # Errors
+///
+/// if the cargo home is not safe to modify, see the [module docs](self)
+pub fn trim(
+    cargo_home: &CargoHome,
+    options: &TrimOptions,
+    progress: &mut dyn Progress,
+) -> Result<Summary, Error> {
+    run(cargo_home, options.dry_run, || {
+        trim_locked(cargo_home, options, progress)
+    })
+}
+
+fn trim_locked(
+    cargo_home: &CargoHome,
+    options: &TrimOptions,
+    progress: &mut dyn Progress,
+) -> Result<Summary, Error> {
+    let paths = &cargo_home.paths;
+    let selection = selection(paths)?;
+    let items = items_to_trim(
+        Some(options.limit),
+        None,
+        &mut git_checkouts::GitCheckoutCache::new(
+            selection.cache_path(&Component::GitRepos, &paths.git_checkouts),
+        ),
+        &mut git_bare_repos::GitRepoCache::new(
+            selection.cache_path(&Component::GitDB, &paths.git_repos_bare),
+        ),
+        &mut registry_pkg_cache::RegistryPkgCaches::new(
+            selection.cache_path(&Component::RegistryCrateCache, &paths.registry_pkg_cache),
+        ),
+        &mut registry_sources::RegistrySourceCaches::new(
+            selection.cache_path(&Component::RegistrySources, &paths.registry_sources),
+        ),
+        &RegistryFilter::new(None, None),
+        &KeepList::default(),
+    )
+    .into_iter()
+    .map(|item| (item.path, item.size))
+    .collect();
+
+    Ok(remove_items(cargo_home, items, options.dry_run, progress))
+}
+
+/// remove everything that the project of the manifest does not need, like `cargo cache clean-unref`
+///
+/// # Errors
+///
+/// if cargo fails to resolve the dependencies of the project, for example because the
+/// Cargo.lock is out of date or resolving needs network access that is not allowed
+/// if the cargo home is not safe to modify, see the [module docs](self)
+#[cfg(feature = "metadata")]
+pub fn clean_unref(
+    cargo_home: &CargoHome,
+    options: &CleanUnrefOptions,
+    progress: &mut dyn Progress,
+) -> Result<Summary, Error> {
+    run(cargo_home, options.dry_run, || {
+        clean_unref_locked(cargo_home, options, progress)
+    })
+}
+
+#[cfg(feature = "metadata")]
+fn clean_unref_locked(
+    cargo_home: &CargoHome,
+    options: &CleanUnrefOptions,
+    progress: &mut dyn Progress,
+) -> Result<Summary, Error> {
+    let paths = &cargo_home.paths;
+    // honor CARGO_NET_OFFLINE and net.offline just like the binary does
+    let allow_net = cargo_home
+        .network
+        .allow("clean-unref --allow-net", options.allow_net)
+        .map_err(Error)?;
+
+    let items = unreferenced_items(
+        paths,
+        std::slice::from_ref(&options.manifest_path),
+        allow_net,
+        &mut git_checkouts::GitCheckoutCache::new(paths.git_checkouts.clone()),
+        &mut git_bare_repos::GitRepoCache::new(paths.git_repos_bare.clone()),
+        &mut registry_pkg_cache::RegistryPkgCaches::new(paths.registry_pkg_cache.clone()),
+        &mut registry_sources::RegistrySourceCaches::new(paths.registry_sources.clone()),
+        &selection(paths)?,
+    )
+    .map_err(Error)?;
+
+    Ok(remove_items(cargo_home, items, options.dry_run, progress))
+}
+
+/// an operation running on a background thread, a `Future` that resolves to its result
+///
+/// It can be awaited with any executor or with [`block_on`]. Once its [`CancelToken`] is cancelled
+/// the operation stops early and resolves to what it did until then. If the operation panics,
+/// awaiting the task panics as well.
+///
+/// ```no_run
+/// use cargo_cache::ops::{self, CargoHome, ScanTask, TrimOptions};
+///
+/// let cargo_home = CargoHome::detect()?;
+/// let options = TrimOptions {
+///     limit: 1_000_000_000,
+///     dry_run: true,
+/// };
+/// let task = ScanTask::spawn(move || ops::trim(&cargo_home, &options, &mut ()));
+/// let cancel = task.cancel_token();
+/// // cancel.cancel() from anywhere stops this task, other tasks go on
+/// let summary = ops::block_on(task)?;
+/// # Ok::<(), ops::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct ScanTask<T>(cancel::ScanTask<T>);
+
+impl<T: Send + 'static> ScanTask<T> {
+    /// run `operation` on a new thread, with a new [`CancelToken`]
+    pub fn spawn(operation: impl FnOnce() -> T + Send + 'static) -> Self {
+        Self::spawn_with_token(CancelToken::new(), operation)
+    }
+
+    /// run `operation` on a new thread, `token` cancels it
+    ///
+    /// Several tasks can share a token to cancel them together.
+    pub fn spawn_with_token(
+        token: CancelToken,
+        operation: impl FnOnce() -> T + Send + 'static,
+    ) -> Self {
+        Self(cancel::ScanTask::spawn(token.0, operation))
+    }
+}
+
+impl<T> ScanTask<T> {
+    /// the token that cancels this task
+    #[must_use]
+    pub fn cancel_token(&self) -> CancelToken {
+        CancelToken(self.0.token().clone())
+    }
+}
+
+impl<T> Future for ScanTask<T> {
+    type Output = T;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        Pin::new(&mut self.0).poll(cx)
+    }
+}
+
+/// stops the [`ScanTask`]s it was passed to after the item they are working on, like a Ctrl-C
+/// does in cargo-cache
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(cancel::CancelToken);
+
+impl CancelToken {
+    /// a token that is not cancelled yet
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// stop the tasks of this token, this can not be undone
+    pub fn cancel(&self) {
+        self.0.cancel();
+    }
+
+    /// whether [`cancel`](Self::cancel) was called
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.0.is_cancelled()
+    }
+}
+
+/// run a future, for example a [`ScanTask`], to completion on the current thread
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    cancel::block_on(future)
+}
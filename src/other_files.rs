@@ -0,0 +1,196 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// files inside the cargo home that do not belong to any of the cache components:
+// credentials.toml, config.toml, env, .crates.toml, .package-cache and so on.
+// These are never removed, but they take up space and should show up in the total size.
+// Only names and sizes are looked at, we never read the content (credentials!).
+
+use std::path::Path;
+
+use crate::cancel::is_cancelled;
+use crate::ignore::is_ignored;
+use crate::library::CargoCachePaths;
+use crate::walk::guarded;
+
+use walkdir::WalkDir;
+
+/// files of the cargo home that are not part of any cache component
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct OtherFiles {
+    /// name and size of the files directly inside the cargo home, sorted by name
+    top_level: Vec<(String, u64)>,
+    /// number of files in other directories of the cargo home
+    numb_nested: usize,
+    /// size of the files in other directories of the cargo home
+    nested_size: u64,
+}
+
+impl OtherFiles {
+    /// find all the files of the cargo home that none of the caches account for
+    pub(crate) fn new(ccp: &CargoCachePaths) -> Self {
+        let component_dirs = [
+            &ccp.registry_index,
+            &ccp.registry_pkg_cache,
+            &ccp.registry_sources,
+            &ccp.git_repos_bare,
+            &ccp.git_checkouts,
+        ];
+        // the binary cache only looks at the files directly inside the bin dir
+        let is_binary = |path: &Path| path.parent() == Some(&ccp.bin_dir) && path.is_file();
+
+        let mut other_files = Self::default();
+
+        if !ccp.cargo_home.is_dir() {
+            return other_files;
+        }
+
+        guarded(WalkDir::new(&ccp.cargo_home).min_depth(1), |entry| {
+            let path = entry.path();
+            !component_dirs.iter().any(|dir| path == dir.as_path())
+                && !is_binary(path)
+                && !is_ignored(path)
+        })
+        .take_while(|_| !is_cancelled())
+        // directories, symlinks and such are not followed
+        .filter(|entry| entry.file_type().is_file())
+        .for_each(|entry| {
+            // the file may have disappeared in the meantime
+            let size = entry.metadata().map_or(0, |metadata| metadata.len());
+            if entry.depth() == 1 {
+                other_files
+                    .top_level
+                    .push((entry.file_name().to_string_lossy().into_owned(), size));
+            } else {
+                other_files.numb_nested += 1;
+                other_files.nested_size += size;
+            }
+        });
+
+        other_files.top_level.sort();
+        other_files
+    }
+
+    #[cfg(test)]
+    pub(crate) fn new_manually(
+        top_level: Vec<(String, u64)>,
+        numb_nested: usize,
+        nested_size: u64,
+    ) -> Self {
+        Self {
+            top_level,
+            numb_nested,
+            nested_size,
+        }
+    }
+
+    /// name and size of the files directly inside the cargo home
+    pub(crate) fn top_level(&self) -> &[(String, u64)] {
+        &self.top_level
+    }
+
+    /// number of files in other directories of the cargo home
+    pub(crate) fn numb_nested(&self) -> usize {
+        self.numb_nested
+    }
+
+    /// size of the files in other directories of the cargo home
+    pub(crate) fn nested_size(&self) -> u64 {
+        self.nested_size
+    }
+
+    /// number of all other files
+    pub(crate) fn number_of_files(&self) -> usize {
+        self.top_level.len() + self.numb_nested
+    }
+
+    /// size of all other files
+    pub(crate) fn total_size(&self) -> u64 {
+        self.top_level.iter().map(|(_, size)| size).sum::<u64>() + self.nested_size
+    }
+}
+
+#[cfg(test)]
+mod other_files_tests {
+    use super::*;
+    use crate::library::CargoHomeSource;
+    use pretty_assertions::assert_eq;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn paths(cargo_home: &Path) -> CargoCachePaths {
+        let registry = cargo_home.join("registry");
+        CargoCachePaths {
+            cargo_home: cargo_home.to_path_buf(),
+            source: CargoHomeSource::Explicit,
+            bin_dir: cargo_home.join("bin"),
+            registry_pkg_cache: registry.join("cache"),
+            registry_sources: registry.join("src"),
+            registry_index: registry.join("index"),
+            registry,
+            git_repos_bare: cargo_home.join("git").join("db"),
+            git_checkouts: cargo_home.join("git").join("checkouts"),
+        }
+    }
+
+    fn write(path: PathBuf, bytes: usize) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, "x".repeat(bytes)).unwrap();
+    }
+
+    #[test]
+    fn other_files() {
+        let tmp = tempfile::tempdir().unwrap();
+        let cargo_home = tmp.path();
+        let ccp = paths(cargo_home);
+
+        // other files
+        write(cargo_home.join("credentials.toml"), 10);
+        write(cargo_home.join("config.toml"), 20);
+        write(cargo_home.join("env"), 30);
+        write(cargo_home.join("registry").join("CACHEDIR.TAG"), 40);
+        write(cargo_home.join("bin").join("tools").join("helper"), 50);
+        // these are accounted for by the caches
+        write(cargo_home.join("bin").join("cargo-cache"), 1000);
+        write(ccp.registry_index.join("github.com-1234").join("x"), 1000);
+        write(
+            ccp.registry_pkg_cache
+                .join("github.com-1234")
+                .join("a.crate"),
+            1000,
+        );
+        write(ccp.registry_sources.join("github.com-1234").join("a"), 1000);
+        write(ccp.git_repos_bare.join("repo").join("HEAD"), 1000);
+        write(
+            ccp.git_checkouts.join("repo").join("abc").join("lib.rs"),
+            1000,
+        );
+
+        let other_files = OtherFiles::new(&ccp);
+
+        assert_eq!(
+            other_files.top_level(),
+            &[
+                (String::from("config.toml"), 20),
+                (String::from("credentials.toml"), 10),
+                (String::from("env"), 30),
+            ]
+        );
+        assert_eq!(other_files.numb_nested(), 2);
+        assert_eq!(other_files.nested_size(), 90);
+        assert_eq!(other_files.number_of_files(), 5);
+        assert_eq!(other_files.total_size(), 150);
+    }
+
+    #[test]
+    fn no_cargo_home() {
+        let ccp = paths(Path::new("/this/path/does/not/exist"));
+        assert_eq!(OtherFiles::new(&ccp), OtherFiles::default());
+    }
+}
@@ -0,0 +1,253 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// permission pre-flight before removing directories
+// Removing a directory tree fails halfway through if some of the entries belong to another user,
+// are protected by ACLs or are read-only. Before removing anything, we walk (a sample of) the
+// deletion set and check whether the current user can remove each entry, so that the user
+// learns about this up front and can decide to abort or go on with --keep-going.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use walkdir::WalkDir;
+
+use crate::ignore::is_ignored;
+use crate::library::Error;
+
+/// check at most this many entries so that the pre-flight stays fast
+const SAMPLE_LIMIT: usize = 50_000;
+
+/// show at most this many of the entries that can not be removed
+const SHOW_BLOCKED: usize = 5;
+
+/// the result of the pre-flight
+#[derive(Debug, Default)]
+pub(crate) struct PreflightReport {
+    /// number of entries checked
+    checked: usize,
+    /// whether we gave up after `SAMPLE_LIMIT` entries
+    sampled: bool,
+    /// entries that we will not be able to remove
+    blocked: Vec<PathBuf>,
+    /// whether the current user may remove entries from a directory, cached per directory
+    removable_from: HashMap<PathBuf, bool>,
+}
+
+impl PreflightReport {
+    /// check whether the entries of the directories we are going to remove can be removed
+    pub(crate) fn new(dirs: &[&Path]) -> Self {
+        let mut report = Self::default();
+
+        'dirs: for dir in dirs {
+            if dir.symlink_metadata().is_err() {
+                // does not exist, nothing to remove
+                continue;
+            }
+
+            for entry in WalkDir::new(dir)
+                .sort_by_file_name()
+                .into_iter()
+                // ignored paths are not removed anyway
+                .filter_entry(|entry| !is_ignored(entry.path()))
+            {
+                if report.checked >= SAMPLE_LIMIT {
+                    report.sampled = true;
+                    break 'dirs;
+                }
+                report.checked += 1;
+
+                match entry {
+                    Ok(entry) => {
+                        if !report.can_remove(entry.path()) {
+                            report.blocked.push(entry.into_path());
+                        }
+                    }
+                    // we can not even look into a directory, so we can not empty it either
+                    Err(error) => {
+                        if let Some(path) = error.path() {
+                            report.blocked.push(path.to_path_buf());
+                        }
+                    }
+                }
+            }
+        }
+
+        report
+    }
+
+    /// number of entries that can not be removed
+    pub(crate) fn blocked(&self) -> &[PathBuf] {
+        &self.blocked
+    }
+
+    /// whether the current user may remove this entry
+    fn can_remove(&mut self, path: &Path) -> bool {
+        let parent = match path.parent() {
+            Some(parent) => parent,
+            None => return false,
+        };
+        let removable_from = *self
+            .removable_from
+            .entry(parent.to_path_buf())
+            .or_insert_with(|| may_remove_from(parent));
+
+        removable_from && may_remove_entry(parent, path)
+    }
+
+    /// summary of the pre-flight, `None` if everything can be removed
+    pub(crate) fn summary(&self) -> Option<String> {
+        if self.blocked.is_empty() {
+            return None;
+        }
+
+        let mut lines = vec![format!(
+            "Pre-flight: {} of {}{} checked entries can not be removed by the current user (ownership, permissions or read-only attributes)",
+            self.blocked.len(),
+            self.checked,
+            if self.sampled { " sampled" } else { "" }
+        )];
+        lines.extend(
+            self.blocked
+                .iter()
+                .take(SHOW_BLOCKED)
+                .map(|path| format!("  {}", path.display())),
+        );
+        if self.blocked.len() > SHOW_BLOCKED {
+            lines.push(format!(
+                "  ... and {} more",
+                self.blocked.len() - SHOW_BLOCKED
+            ));
+        }
+        let summary = lines.join("\n");
+        Some(summary)
+    }
+}
+
+/// whether the current user may remove entries from a directory (ACLs are taken into account)
+#[cfg(unix)]
+fn may_remove_from(dir: &Path) -> bool {
+    use nix::unistd::{access, AccessFlags};
+    access(dir, AccessFlags::W_OK | AccessFlags::X_OK).is_ok()
+}
+
+/// on windows the permissions of the parent directory do not matter
+#[cfg(not(unix))]
+fn may_remove_from(_dir: &Path) -> bool {
+    true
+}
+
+/// whether an entry of a directory we may remove from can actually be removed
+/// entries of directories with the sticky bit set (like /tmp) can only be removed by their owner
+#[cfg(unix)]
+fn may_remove_entry(parent: &Path, path: &Path) -> bool {
+    use nix::unistd::geteuid;
+    use std::os::unix::fs::MetadataExt;
+
+    const STICKY_BIT: u32 = 0o1000;
+
+    let (parent_metadata, metadata) = match (parent.metadata(), path.symlink_metadata()) {
+        (Ok(parent_metadata), Ok(metadata)) => (parent_metadata, metadata),
+        // the entry disappeared in the meantime
+        _ => return true,
+    };
+    let euid = geteuid().as_raw();
+
+    parent_metadata.mode() & STICKY_BIT == 0
+        || euid == 0
+        || metadata.uid() == euid
+        || parent_metadata.uid() == euid
+}
+
+/// read-only files can not be removed on windows
+#[cfg(not(unix))]
+fn may_remove_entry(_parent: &Path, path: &Path) -> bool {
+    path.symlink_metadata().map_or(true, |metadata| {
+        metadata.is_dir() || !metadata.permissions().readonly()
+    })
+}
+
+/// check the directories before removing them
+/// if something can not be removed, abort before removing anything unless we are told to keep going
+pub(crate) fn preflight(dirs: &[&Path], dry_run: bool, keep_going: bool) -> Result<(), Error> {
+    let report = PreflightReport::new(dirs);
+
+    match report.summary() {
+        None => Ok(()),
+        Some(summary) => {
+            eprintln!("{summary}");
+            if keep_going {
+                eprintln!("Warning: --keep-going passed, removing everything else anyway");
+                Ok(())
+            } else if dry_run {
+                eprintln!("dry-run: would not remove anything unless --keep-going is passed");
+                Ok(())
+            } else {
+                Err(Error::PreflightFailed(report.blocked().len()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod preflight_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::fs;
+
+    #[test]
+    fn everything_removable() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().join("registry").join("src");
+        fs::create_dir_all(dir.join("serde-1.0.0")).unwrap();
+        fs::write(dir.join("serde-1.0.0").join("lib.rs"), "").unwrap();
+
+        let report = PreflightReport::new(&[&dir, &tmp.path().join("does_not_exist")]);
+        assert_eq!(report.checked, 3);
+        assert!(report.blocked().is_empty());
+        assert_eq!(report.summary(), None);
+        assert!(preflight(&[&dir], false, false).is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn read_only_directory() {
+        use std::os::unix::fs::PermissionsExt;
+
+        // root may remove anything, the test makes no sense then
+        if nix::unistd::geteuid().is_root() {
+            return;
+        }
+
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().join("git").join("checkouts");
+        let locked = dir.join("locked");
+        fs::create_dir_all(&locked).unwrap();
+        fs::write(locked.join("a"), "").unwrap();
+        fs::write(locked.join("b"), "").unwrap();
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o555)).unwrap();
+
+        let report = PreflightReport::new(&[&dir]);
+        assert_eq!(report.blocked(), &[locked.join("a"), locked.join("b")]);
+        assert!(report
+            .summary()
+            .unwrap()
+            .starts_with("Pre-flight: 2 of 4 checked entries"));
+
+        assert!(matches!(
+            preflight(&[&dir], false, false),
+            Err(Error::PreflightFailed(2))
+        ));
+        assert!(preflight(&[&dir], false, true).is_ok());
+        assert!(preflight(&[&dir], true, false).is_ok());
+
+        // make sure the tempdir can be cleaned up
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+}
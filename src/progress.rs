@@ -0,0 +1,208 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// progress of long running operations (--gc, trim, verify)
+// A single line on stderr that is redrawn in place:
+// "Recompressing repositories: 12/40 (30%), 1.20 GB freed, ETA 0:42"
+// It is only drawn if stderr is a terminal (see --assume-tty and --no-tty), so pipes and log files never see it, and --quiet
+// turns it off completely. Output of the operation itself (stdout) is printed with the line
+// cleared through `Progress::suspend()` so the two do not get mixed up.
+
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use humansize::{FormatSize, DECIMAL};
+use once_cell::sync::OnceCell;
+
+use crate::tty::{self, Stream};
+
+/// whether progress is shown in this run, set up once in `main()`
+static ENABLED: OnceCell<bool> = OnceCell::new();
+
+/// do not redraw more often than this, drawing is slow on some terminals
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+/// the progress of an operation that works through `total` items
+#[derive(Debug)]
+pub(crate) struct Progress {
+    label: &'static str,
+    total: u64,
+    done: AtomicU64,
+    freed: AtomicU64,
+    started: Instant,
+    enabled: bool,
+    /// whether the line is currently on the screen
+    visible: AtomicBool,
+    /// the last time the line was drawn, also makes sure only one thread draws at a time
+    last_draw: Mutex<Option<Instant>>,
+}
+
+impl Progress {
+    /// start the progress of `total` items, shown unless --quiet was passed or stderr is no terminal
+    pub(crate) fn new(label: &'static str, total: usize) -> Self {
+        Self::with_visibility(label, total, ENABLED.get().copied().unwrap_or(false))
+    }
+
+    /// a progress that is never shown, for example because every item prints a line anyway
+    pub(crate) fn hidden(label: &'static str, total: usize) -> Self {
+        Self::with_visibility(label, total, false)
+    }
+
+    fn with_visibility(label: &'static str, total: usize, enabled: bool) -> Self {
+        Self {
+            label,
+            total: total as u64,
+            done: AtomicU64::new(0),
+            freed: AtomicU64::new(0),
+            started: Instant::now(),
+            enabled,
+            visible: AtomicBool::new(false),
+            last_draw: Mutex::new(None),
+        }
+    }
+
+    /// one more item is done, it freed `freed` bytes
+    pub(crate) fn inc(&self, freed: u64) {
+        let _ = self.done.fetch_add(1, Ordering::SeqCst);
+        let _ = self.freed.fetch_add(freed, Ordering::SeqCst);
+        if !self.enabled {
+            return;
+        }
+        let mut last_draw = match self.last_draw.lock() {
+            Ok(last_draw) => last_draw,
+            Err(_) => return,
+        };
+        let done = self.done.load(Ordering::SeqCst);
+        if done < self.total && last_draw.map_or(false, |last| last.elapsed() < REDRAW_INTERVAL) {
+            return;
+        }
+        let line = progress_line(
+            self.label,
+            done,
+            self.total,
+            self.freed.load(Ordering::SeqCst),
+            self.started.elapsed(),
+        );
+        let mut stderr = io::stderr();
+        let _ = write!(stderr, "\r\x1b[2K{line}");
+        let _ = stderr.flush();
+        self.visible.store(true, Ordering::SeqCst);
+        *last_draw = Some(Instant::now());
+    }
+
+    /// remove the line from the screen, it is drawn again by the next `inc()`
+    pub(crate) fn clear(&self) {
+        if self.visible.swap(false, Ordering::SeqCst) {
+            let mut stderr = io::stderr();
+            let _ = write!(stderr, "\r\x1b[2K");
+            let _ = stderr.flush();
+        }
+    }
+
+    /// print something without the line getting in the way
+    // only --gc and verify print while they make progress
+    #[cfg_attr(not(any(feature = "git", feature = "verify")), allow(dead_code))]
+    pub(crate) fn suspend<T>(&self, print: impl FnOnce() -> T) -> T {
+        self.clear();
+        print()
+    }
+
+    /// the operation is done, remove the line
+    pub(crate) fn finish(&self) {
+        self.clear();
+    }
+}
+
+/// "Recompressing repositories: 12/40 (30%), 1.20 GB freed, ETA 0:42"
+fn progress_line(label: &str, done: u64, total: u64, freed: u64, elapsed: Duration) -> String {
+    // nothing to do is done
+    let percent = (done * 100).checked_div(total).unwrap_or(100);
+    let mut line = format!("{label}: {done}/{total} ({percent}%)");
+    if freed > 0 {
+        let _ = write!(line, ", {} freed", freed.format_size(DECIMAL));
+    }
+    if let Some(eta) = eta(done, total, elapsed) {
+        let seconds = eta.as_secs();
+        let _ = write!(line, ", ETA {}:{:02}", seconds / 60, seconds % 60);
+    }
+    line
+}
+
+/// how long the remaining items will take if they take as long as the ones done so far
+#[allow(clippy::cast_possible_truncation)]
+fn eta(done: u64, total: u64, elapsed: Duration) -> Option<Duration> {
+    if done == 0 || done >= total {
+        return None;
+    }
+    let per_item = elapsed.as_nanos() / u128::from(done);
+    let remaining = per_item.saturating_mul(u128::from(total - done));
+    Some(Duration::from_nanos(
+        remaining.min(u128::from(u64::MAX)) as u64
+    ))
+}
+
+/// show progress unless `quiet`, needs to be called before any operation starts
+pub(crate) fn init(quiet: bool) {
+    let _ = ENABLED.set(!quiet && tty::can_draw(Stream::Stderr));
+}
+
+#[cfg(test)]
+mod progress_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn lines() {
+        assert_eq!(
+            progress_line("Verifying crates", 0, 40, 0, Duration::from_secs(3)),
+            "Verifying crates: 0/40 (0%)"
+        );
+        assert_eq!(
+            progress_line(
+                "Recompressing repositories",
+                12,
+                40,
+                1_200_000_000,
+                Duration::from_secs(18)
+            ),
+            "Recompressing repositories: 12/40 (30%), 1.20 GB freed, ETA 0:42"
+        );
+        assert_eq!(
+            progress_line("Trimming", 40, 40, 0, Duration::from_secs(60)),
+            "Trimming: 40/40 (100%)"
+        );
+        assert_eq!(
+            progress_line("Trimming", 0, 0, 0, Duration::from_secs(0)),
+            "Trimming: 0/0 (100%)"
+        );
+    }
+
+    #[test]
+    fn estimates() {
+        assert_eq!(eta(0, 10, Duration::from_secs(5)), None);
+        assert_eq!(
+            eta(1, 10, Duration::from_secs(5)),
+            Some(Duration::from_secs(45))
+        );
+        assert_eq!(eta(10, 10, Duration::from_secs(5)), None);
+    }
+
+    #[test]
+    fn hidden_progress_counts() {
+        let progress = Progress::hidden("Trimming", 2);
+        progress.inc(10);
+        progress.inc(5);
+        assert_eq!(progress.done.load(Ordering::SeqCst), 2);
+        assert_eq!(progress.freed.load(Ordering::SeqCst), 15);
+        assert!(!progress.visible.load(Ordering::SeqCst));
+    }
+}
@@ -0,0 +1,176 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// projects that use the cargo home
+// "cargo cache projects add <path>" remembers the manifest of a project in a file in the state dir
+// of the cargo home, one absolute path per line. "cargo cache clean-unref --all-projects" keeps everything any
+// of these projects needs (according to their Cargo.lock) and removes the rest, which turns the
+// cache into a store that is garbage collected against the registered projects.
+
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::library::Error;
+use crate::state_dir;
+
+/// name of the project list inside the state dir
+pub(crate) const PROJECTS_FILE_NAME: &str = "projects";
+
+/// the manifests of all registered projects, in the order they were added
+pub(crate) fn read(cargo_home: &Path) -> Vec<PathBuf> {
+    fs::read_to_string(state_dir::state_file(cargo_home, PROJECTS_FILE_NAME))
+        .map(|projects| {
+            projects
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(PathBuf::from)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// replace the project list of the cargo home
+fn write(cargo_home: &Path, manifests: &[PathBuf]) -> Result<(), Error> {
+    let content = manifests
+        .iter()
+        .fold(String::new(), |mut content, manifest| {
+            let _ = writeln!(content, "{}", manifest.display());
+            content
+        });
+
+    let path = state_dir::state_file(cargo_home, PROJECTS_FILE_NAME);
+    let temporary = path.with_extension("tmp");
+    // "clean-unref --all-projects" may read the file at the same time, replace it in one go
+    path.parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|()| fs::write(&temporary, content))
+        .and_then(|()| fs::rename(&temporary, &path))
+        .map_err(|error| {
+            let _ = fs::remove_file(&temporary);
+            Error::ProjectsWriteFailed(path, error)
+        })
+}
+
+/// `path` relative to the current directory
+fn absolute(path: &Path) -> PathBuf {
+    env::current_dir().map_or_else(|_| path.to_path_buf(), |cwd| cwd.join(path))
+}
+
+/// the manifest of a project, `path` may be the project directory, its Cargo.toml or its Cargo.lock
+fn manifest_of(path: &Path) -> Result<PathBuf, Error> {
+    let manifest = if path.is_dir() {
+        path.join("Cargo.toml")
+    } else if path.file_name().map_or(false, |name| name == "Cargo.lock") {
+        path.with_file_name("Cargo.toml")
+    } else {
+        path.to_path_buf()
+    };
+    match fs::canonicalize(&manifest) {
+        Ok(manifest) if manifest.is_file() => Ok(manifest),
+        _ => Err(Error::ProjectNoManifest(absolute(path))),
+    }
+}
+
+/// register the project at `path`, returns its manifest and whether it was not registered yet
+pub(crate) fn add(cargo_home: &Path, path: &Path) -> Result<(PathBuf, bool), Error> {
+    let manifest = manifest_of(path)?;
+    let mut manifests = read(cargo_home);
+    if manifests.contains(&manifest) {
+        return Ok((manifest, false));
+    }
+    manifests.push(manifest.clone());
+    write(cargo_home, &manifests)?;
+    Ok((manifest, true))
+}
+
+/// forget the project at `path` and return its manifest
+/// the project does not need to exist anymore, its registered path is enough
+pub(crate) fn remove(cargo_home: &Path, path: &Path) -> Result<PathBuf, Error> {
+    let absolute_path = absolute(path);
+    let candidates = [
+        manifest_of(path).ok(),
+        Some(absolute_path.join("Cargo.toml")),
+        Some(absolute_path.clone()),
+    ];
+    let mut manifests = read(cargo_home);
+    let position = manifests
+        .iter()
+        .position(|manifest| candidates.iter().flatten().any(|c| c == manifest))
+        .ok_or(Error::ProjectNotRegistered(absolute_path))?;
+    let manifest = manifests.remove(position);
+    write(cargo_home, &manifests)?;
+    Ok(manifest)
+}
+
+/// the manifests "clean-unref --all-projects" resolves the dependencies of
+/// fails if there are none or one of them is gone: everything only that project needs would be
+/// removed, which is not what anyone wants if it was just moved or its disk is not mounted
+#[cfg_attr(not(feature = "metadata"), allow(dead_code))]
+pub(crate) fn manifests(cargo_home: &Path) -> Result<Vec<PathBuf>, Error> {
+    let manifests = read(cargo_home);
+    if manifests.is_empty() {
+        return Err(Error::NoProjects(cargo_home.to_path_buf()));
+    }
+    if let Some(missing) = manifests.iter().find(|manifest| !manifest.is_file()) {
+        return Err(Error::RegisteredProjectMissing(missing.clone()));
+    }
+    Ok(manifests)
+}
+
+#[cfg(test)]
+mod projects_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn add_and_remove() {
+        let cargo_home = tempfile::tempdir().unwrap();
+        let cargo_home = cargo_home.path();
+        let workspace = tempfile::tempdir().unwrap();
+        let project = fs::canonicalize(workspace.path()).unwrap();
+        fs::write(project.join("Cargo.toml"), "").unwrap();
+        let manifest = project.join("Cargo.toml");
+
+        assert_eq!(read(cargo_home), Vec::<PathBuf>::new());
+        assert!(matches!(manifests(cargo_home), Err(Error::NoProjects(_))));
+
+        // the directory, the manifest and the lockfile all mean the same project
+        assert_eq!(add(cargo_home, &project).unwrap(), (manifest.clone(), true));
+        assert_eq!(
+            add(cargo_home, &manifest).unwrap(),
+            (manifest.clone(), false)
+        );
+        assert_eq!(
+            add(cargo_home, &project.join("Cargo.lock")).unwrap(),
+            (manifest.clone(), false)
+        );
+        assert_eq!(read(cargo_home), vec![manifest.clone()]);
+        assert_eq!(manifests(cargo_home).unwrap(), vec![manifest.clone()]);
+
+        assert!(matches!(
+            add(cargo_home, &project.join("src")),
+            Err(Error::ProjectNoManifest(_))
+        ));
+
+        // a project that vanished blocks the sweep, but can still be removed
+        fs::remove_file(&manifest).unwrap();
+        assert!(matches!(
+            manifests(cargo_home),
+            Err(Error::RegisteredProjectMissing(path)) if path == manifest
+        ));
+        assert_eq!(remove(cargo_home, &project).unwrap(), manifest);
+        assert_eq!(read(cargo_home), Vec::<PathBuf>::new());
+        assert!(matches!(
+            remove(cargo_home, &project),
+            Err(Error::ProjectNotRegistered(_))
+        ));
+    }
+}
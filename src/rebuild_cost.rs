@@ -0,0 +1,341 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// the "rebuild cost" of removed cache items, shown by the dry runs of clean-unref and --autoclean
+// and by the --autoclean confirmation: what cargo has to do to get an item back once a build needs it.
+// A crate source is extracted again from its .crate archive and a git checkout from its bare repo,
+// as long as those stay in the cache. Otherwise the archive has to be downloaded or the repo cloned again.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use humansize::{FormatSize, DECIMAL};
+
+use crate::library::{size_of_path, CargoCachePaths};
+use crate::tables::format_table;
+
+/// what it takes to get a removed item back
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum RebuildCost {
+    /// cargo downloads the .crate archive (or the registry index) again, the archive and its size if we know them
+    Download(Option<(PathBuf, u64)>),
+    /// cargo extracts it again from a .crate archive or bare git repo that stays in the cache
+    ReExtract,
+    /// cargo clones the git repo again
+    Reclone,
+}
+
+impl RebuildCost {
+    /// the row of the kind in the totals
+    fn index(&self) -> usize {
+        match self {
+            Self::Download(_) => 0,
+            Self::ReExtract => 1,
+            Self::Reclone => 2,
+        }
+    }
+}
+
+/// the names of the kinds in the totals, in the order of `RebuildCost::index`
+const KINDS: [&str; 3] = ["download", "local re-extraction", "git re-clone"];
+
+impl fmt::Display for RebuildCost {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Download(Some((_, size))) => write!(f, "download {}", size.format_size(DECIMAL)),
+            _ => write!(f, "{}", KINDS[self.index()]),
+        }
+    }
+}
+
+/// classifies the items of one removal and adds up what restoring them takes
+pub(crate) struct RebuildCosts<'a> {
+    ccp: &'a CargoCachePaths,
+    /// everything that is removed in this run, items can only be restored from what stays
+    removed: HashSet<PathBuf>,
+    /// number of items and their size per kind
+    totals: [(usize, u64); 3],
+    /// the archives that need to be downloaded, an archive and its source are only counted once
+    downloads: BTreeMap<PathBuf, u64>,
+    /// downloads whose size we do not know
+    unknown_downloads: usize,
+}
+
+impl<'a> RebuildCosts<'a> {
+    /// `removed` are all the paths that are removed together, including directories
+    pub(crate) fn new(
+        ccp: &'a CargoCachePaths,
+        removed: impl IntoIterator<Item = PathBuf>,
+    ) -> Self {
+        Self {
+            ccp,
+            removed: removed.into_iter().collect(),
+            totals: [(0, 0); 3],
+            downloads: BTreeMap::new(),
+            unknown_downloads: 0,
+        }
+    }
+
+    /// whether the path is still in the cache after the removal
+    fn stays(&self, path: &Path) -> bool {
+        path.exists()
+            && !path
+                .ancestors()
+                .any(|ancestor| self.removed.contains(ancestor))
+    }
+
+    /// what restoring the item at `path` of `size` bytes takes
+    /// None for binaries, files cargo does not know about and directories that contain several items
+    pub(crate) fn cost_of(&self, path: &Path, size: u64) -> Option<RebuildCost> {
+        let ccp = self.ccp;
+        let relative = |dir: &Path| {
+            path.strip_prefix(dir)
+                .ok()
+                .map(|relative| relative.iter().collect::<Vec<_>>())
+        };
+
+        if let Some(relative) = relative(&ccp.registry_pkg_cache) {
+            // registry/cache/<registry>/<crate>.crate
+            return (relative.len() == 2)
+                .then(|| RebuildCost::Download(Some((path.to_path_buf(), size))));
+        }
+        if let Some(relative) = relative(&ccp.registry_sources) {
+            // registry/src/<registry>/<crate> comes from registry/cache/<registry>/<crate>.crate
+            if relative.len() != 2 {
+                return None;
+            }
+            let mut archive_name = relative[1].to_os_string();
+            archive_name.push(".crate");
+            let archive = ccp.registry_pkg_cache.join(relative[0]).join(archive_name);
+            return Some(if self.stays(&archive) {
+                RebuildCost::ReExtract
+            } else {
+                let archive_size = archive.metadata().ok().map(|metadata| metadata.len());
+                RebuildCost::Download(archive_size.map(|archive_size| (archive, archive_size)))
+            });
+        }
+        if let Some(relative) = relative(&ccp.git_checkouts) {
+            // git/checkouts/<repo>-<hash>/<rev> comes from git/db/<repo>-<hash>
+            if relative.is_empty() || relative.len() > 2 {
+                return None;
+            }
+            return Some(if self.stays(&ccp.git_repos_bare.join(relative[0])) {
+                RebuildCost::ReExtract
+            } else {
+                RebuildCost::Reclone
+            });
+        }
+        if let Some(relative) = relative(&ccp.git_repos_bare) {
+            return (relative.len() == 1).then_some(RebuildCost::Reclone);
+        }
+        if let Some(relative) = relative(&ccp.registry_index) {
+            // cargo fetches the index again, we cannot tell how much of it
+            return (relative.len() == 1).then_some(RebuildCost::Download(None));
+        }
+        None
+    }
+
+    /// classify the item and count it in the totals
+    pub(crate) fn add(&mut self, path: &Path, size: u64) -> Option<RebuildCost> {
+        let cost = self.cost_of(path, size)?;
+        let (items, bytes) = &mut self.totals[cost.index()];
+        *items += 1;
+        *bytes += size;
+        match &cost {
+            RebuildCost::Download(Some((archive, archive_size))) => {
+                let _ = self.downloads.insert(archive.clone(), *archive_size);
+            }
+            RebuildCost::Download(None) => self.unknown_downloads += 1,
+            RebuildCost::ReExtract | RebuildCost::Reclone => {}
+        }
+        Some(cost)
+    }
+
+    /// "local re-extraction: 120, download: 3" for a directory that contains several items
+    #[cfg_attr(not(feature = "metadata"), allow(dead_code))]
+    pub(crate) fn summary(&self, items: &[(PathBuf, u64)]) -> String {
+        let mut counts = [0_usize; 3];
+        for (item, size) in items {
+            if let Some(cost) = self.cost_of(item, *size) {
+                counts[cost.index()] += 1;
+            }
+        }
+        KINDS
+            .iter()
+            .zip(counts)
+            .filter(|(_, count)| *count > 0)
+            .map(|(kind, count)| format!("{kind}: {count}"))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// the totals per kind, empty if nothing was counted
+    pub(crate) fn table(&self) -> String {
+        if self.totals.iter().all(|(items, _)| *items == 0) {
+            return String::new();
+        }
+        let mut table = vec![vec![
+            String::from("Rebuild cost"),
+            String::from("Items"),
+            String::from("Size"),
+            String::from("To download"),
+        ]];
+        let known = self.downloads.values().sum::<u64>().format_size(DECIMAL);
+        let downloaded = if self.unknown_downloads > 0 {
+            format!("{known} + {} of unknown size", self.unknown_downloads)
+        } else {
+            known
+        };
+        table.extend(
+            KINDS
+                .iter()
+                .zip(self.totals)
+                .enumerate()
+                .filter(|(_, (_, (items, _)))| *items > 0)
+                .map(|(index, (kind, (items, size)))| {
+                    vec![
+                        (*kind).to_string(),
+                        items.to_string(),
+                        size.format_size(DECIMAL),
+                        if index == 0 {
+                            downloaded.clone()
+                        } else {
+                            String::new()
+                        },
+                    ]
+                }),
+        );
+        format_table(&table, 0)
+    }
+
+    /// print the totals after the "dry-run: would remove" lines
+    pub(crate) fn print_table(&self) {
+        let table = self.table();
+        if !table.is_empty() {
+            println!("\nRestoring what would be removed takes:\n{table}");
+        }
+    }
+}
+
+/// what restoring the cache items inside the removed directories takes, "--autoclean --dry-run"
+pub(crate) fn removed_items<'a>(
+    ccp: &'a CargoCachePaths,
+    removed_dirs: &[PathBuf],
+    items: &[PathBuf],
+) -> RebuildCosts<'a> {
+    let mut costs = RebuildCosts::new(ccp, removed_dirs.iter().cloned());
+    for item in items
+        .iter()
+        .filter(|item| removed_dirs.iter().any(|dir| item.starts_with(dir)))
+    {
+        let _ = costs.add(item, size_of_path(item));
+    }
+    costs
+}
+
+#[cfg(test)]
+mod rebuild_cost_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::fs;
+
+    const REGISTRY: &str = "index.crates.io-6f17d22bba15001f";
+
+    fn write(path: &Path, bytes: usize) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, "x".repeat(bytes)).unwrap();
+    }
+
+    #[test]
+    fn costs_of_items() {
+        let tmp = tempfile::tempdir().unwrap();
+        let ccp = CargoCachePaths::new(tmp.path().to_path_buf()).unwrap();
+        let archive = ccp
+            .registry_pkg_cache
+            .join(REGISTRY)
+            .join("foo-1.0.0.crate");
+        let source = ccp.registry_sources.join(REGISTRY).join("foo-1.0.0");
+        let orphan = ccp.registry_sources.join(REGISTRY).join("bar-1.0.0");
+        let db = ccp.git_repos_bare.join("repo-0123456789abcdef");
+        let checkout = ccp
+            .git_checkouts
+            .join("repo-0123456789abcdef")
+            .join("a1b2c3d");
+        write(&archive, 100);
+        write(&source.join("lib.rs"), 1000);
+        write(&orphan.join("lib.rs"), 1000);
+        fs::create_dir_all(&db).unwrap();
+        fs::create_dir_all(&checkout).unwrap();
+
+        // only the sources and checkouts are removed, like --autoclean does
+        let costs = RebuildCosts::new(
+            &ccp,
+            [ccp.registry_sources.clone(), ccp.git_checkouts.clone()],
+        );
+        assert_eq!(costs.cost_of(&source, 1000), Some(RebuildCost::ReExtract));
+        assert_eq!(
+            costs.cost_of(&orphan, 1000),
+            Some(RebuildCost::Download(None))
+        );
+        assert_eq!(costs.cost_of(&checkout, 0), Some(RebuildCost::ReExtract));
+        assert_eq!(
+            costs.cost_of(&archive, 100),
+            Some(RebuildCost::Download(Some((archive.clone(), 100))))
+        );
+        assert_eq!(costs.cost_of(&db, 0), Some(RebuildCost::Reclone));
+        // directories that contain several items and binaries have no single cost
+        assert_eq!(costs.cost_of(&ccp.registry_sources, 0), None);
+        assert_eq!(costs.cost_of(&ccp.bin_dir.join("cargo-cache"), 0), None);
+
+        // once the archive and the bare repo go as well, the network is needed
+        let network_costs = RebuildCosts::new(&ccp, [archive.clone(), db, source.clone()]);
+        assert_eq!(
+            network_costs.cost_of(&source, 1000),
+            Some(RebuildCost::Download(Some((archive, 100))))
+        );
+        assert_eq!(
+            network_costs.cost_of(&checkout, 0),
+            Some(RebuildCost::Reclone)
+        );
+        assert_eq!(
+            network_costs.summary(&[(source, 1000), (orphan, 1000), (checkout, 0)]),
+            "download: 2, git re-clone: 1"
+        );
+    }
+
+    #[test]
+    fn totals_per_kind() {
+        let tmp = tempfile::tempdir().unwrap();
+        let ccp = CargoCachePaths::new(tmp.path().to_path_buf()).unwrap();
+        let archive = ccp
+            .registry_pkg_cache
+            .join(REGISTRY)
+            .join("foo-1.0.0.crate");
+        let source = ccp.registry_sources.join(REGISTRY).join("foo-1.0.0");
+        write(&archive, 100);
+        write(&source.join("lib.rs"), 1000);
+
+        let mut costs = RebuildCosts::new(&ccp, [archive.clone(), source.clone()]);
+        assert_eq!(costs.table(), "");
+        assert_eq!(
+            costs.add(&archive, 100).unwrap().to_string(),
+            "download 100 B"
+        );
+        let _ = costs.add(&source, 1000);
+        let _ = costs.add(&ccp.git_repos_bare.join("repo-0123456789abcdef"), 5000);
+        // the archive of the source is the one that is removed, it is downloaded once
+        assert_eq!(
+            costs.table(),
+            "Rebuild cost Items Size    To download\n\
+             download     2     1.10 kB 100 B\n\
+             git re-clone 1     5 kB\n"
+        );
+    }
+}
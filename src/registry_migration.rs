@@ -0,0 +1,427 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// registries that are cached in several directories
+// cargo names the directories of a registry after the host of its index and a hash, both changed
+// over time: crates.io moved from "github.com-1ecc6299db9ec823" (git index) to
+// "index.crates.io-6f17d22bba15001f" (sparse index) and later cargo versions hash differently
+// again. A cargo home that was used by several cargo versions keeps all of these directories,
+// often with the same crate archives in each of them.
+// Only the directories crates.io is known to have had are treated as one registry: any registry on
+// the same host can have a directory with a different hash, for example a second registry on
+// "github.com", and we can not tell from the directory name whether it is one of them.
+// The summary points such registries out and "cargo cache migrate-registry" moves the crate
+// archives only the obsolete directories have into the current one and removes the obsolete
+// directories. The current directory is the one cargo wrote to last: downloading a crate or
+// extracting it changes the modification time of the directory of the registry.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+use std::time::SystemTime;
+
+use humansize::{FormatSize, DECIMAL};
+
+use crate::cache::caches::RegistrySuperCache;
+use crate::cache::*;
+use crate::empty_registries::{registry_dir_names, REGISTRY_COMPONENTS};
+use crate::ignore::is_ignored;
+use crate::library::{CargoCachePaths, Component};
+use crate::remove::{component_path, remove_with_default_message};
+
+/// a registry with directories left behind by older cargo versions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct DuplicateRegistry {
+    /// "crates.io", the only registry whose directories we know
+    pub(crate) name: String,
+    /// the directory cargo uses now, "index.crates.io-6f17d22bba15001f"
+    pub(crate) current: String,
+    /// the directories of older cargo versions
+    pub(crate) obsolete: Vec<String>,
+}
+
+/// the directories crates.io had: the git index, the sparse index and the sparse index with the
+/// hash of cargo 1.85 and later
+const CRATES_IO_DIRS: &[&str] = &[
+    "github.com-1ecc6299db9ec823",
+    "index.crates.io-6f17d22bba15001f",
+    "index.crates.io-1949cf8c6b5b557f",
+];
+
+/// the registry a directory belongs to, None if it is not one of the known directories of crates.io
+fn registry_of(dir_name: &str) -> Option<&'static str> {
+    CRATES_IO_DIRS.contains(&dir_name).then_some("crates.io")
+}
+
+/// the last time cargo wrote to any of the directories of a registry
+fn last_written(ccp: &CargoCachePaths, dir_name: &str) -> Option<SystemTime> {
+    REGISTRY_COMPONENTS
+        .iter()
+        .filter_map(|component| fs::metadata(component_path(component, ccp).join(dir_name)).ok())
+        .filter_map(|metadata| metadata.modified().ok())
+        .max()
+}
+
+/// the directory that was written to last, the newer naming scheme if we can't tell
+fn pick_current(candidates: &[(String, Option<SystemTime>)]) -> Option<&str> {
+    candidates
+        .iter()
+        .max_by_key(|(dir_name, written)| (*written, !dir_name.starts_with("github.com-")))
+        .map(|(dir_name, _)| dir_name.as_str())
+}
+
+/// the registries that are cached in more than one directory
+pub(crate) fn find_duplicate_registries(ccp: &CargoCachePaths) -> Vec<DuplicateRegistry> {
+    let mut registries: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for dir_name in registry_dir_names(ccp) {
+        if let Some(registry) = registry_of(&dir_name) {
+            registries
+                .entry(registry.to_string())
+                .or_default()
+                .push(dir_name);
+        }
+    }
+
+    registries
+        .into_iter()
+        .filter(|(_, dir_names)| dir_names.len() > 1)
+        .filter_map(|(name, dir_names)| {
+            let candidates = dir_names
+                .iter()
+                .map(|dir_name| (dir_name.clone(), last_written(ccp, dir_name)))
+                .collect::<Vec<_>>();
+            let current = pick_current(&candidates)?.to_string();
+            let obsolete = dir_names
+                .into_iter()
+                .filter(|dir_name| *dir_name != current)
+                .collect();
+            Some(DuplicateRegistry {
+                name,
+                current,
+                obsolete,
+            })
+        })
+        .collect()
+}
+
+/// the file names of the crate archives in a directory of registry/cache
+fn crate_archives(dir: &Path) -> BTreeSet<String> {
+    fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .filter(|entry| entry.path().is_file())
+                .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+impl DuplicateRegistry {
+    /// the number and size of the crate archives of the obsolete directories that the current one has as well
+    fn duplicated_archives(&self, ccp: &CargoCachePaths) -> (usize, u64) {
+        let current = crate_archives(&ccp.registry_pkg_cache.join(&self.current));
+        self.obsolete
+            .iter()
+            .map(|dir_name| ccp.registry_pkg_cache.join(dir_name))
+            .flat_map(|dir| {
+                crate_archives(&dir)
+                    .into_iter()
+                    .filter(|archive| current.contains(archive))
+                    .map(move |archive| dir.join(archive))
+            })
+            .fold((0, 0), |(count, size), archive| {
+                let archive_size = fs::metadata(archive).map_or(0, |metadata| metadata.len());
+                (count + 1, size + archive_size)
+            })
+    }
+}
+
+/// the note below the summary, None if every registry is cached in a single directory
+pub(crate) fn duplicate_registries_note(ccp: &CargoCachePaths) -> Option<String> {
+    let registries = find_duplicate_registries(ccp);
+    if registries.is_empty() {
+        return None;
+    }
+
+    let mut lines = vec![String::new()];
+    for registry in &registries {
+        lines.push(format!(
+            "{} is cached in {} directories: cargo uses \"{}\", older cargo versions left \"{}\" behind.",
+            registry.name,
+            registry.obsolete.len() + 1,
+            registry.current,
+            registry.obsolete.join("\", \"")
+        ));
+        let (count, size) = registry.duplicated_archives(ccp);
+        if count > 0 {
+            lines.push(format!(
+                "  {} crate archives ({}) are cached twice.",
+                count,
+                size.format_size(DECIMAL)
+            ));
+        }
+    }
+    lines.push(String::from(
+        "Run `cargo cache migrate-registry` to keep only the directories cargo uses.\n",
+    ));
+    Some(lines.join("\n"))
+}
+
+/// move the crate archives that are missing in `to` from `from`
+/// returns false if an archive could not be moved and `from` must be kept
+fn merge_archives(from: &Path, to: &Path, dry_run: bool) -> bool {
+    let existing = crate_archives(to);
+    let mut all_moved = true;
+    for archive in crate_archives(from) {
+        let source = from.join(&archive);
+        if existing.contains(&archive) || is_ignored(&source) {
+            continue;
+        }
+        let target = to.join(&archive);
+        if dry_run {
+            println!(
+                "dry-run: would move '{}' to '{}'",
+                source.display(),
+                target.display()
+            );
+            continue;
+        }
+        println!("moving: '{}' to '{}'", source.display(), target.display());
+        if let Err(error) = fs::create_dir_all(to).and_then(|()| fs::rename(&source, &target)) {
+            eprintln!(
+                "Warning: failed to move '{}' to '{}': {}",
+                source.display(),
+                target.display(),
+                error
+            );
+            all_moved = false;
+        }
+    }
+    all_moved
+}
+
+/// keep only the directories cargo uses of registries that are cached in several directories
+/// crate archives that only the obsolete directories have are moved into the current one
+pub(crate) fn migrate_registries(
+    ccp: &CargoCachePaths,
+    dry_run: bool,
+    size_changed: &mut bool,
+    registry_index_caches: &mut registry_index::RegistryIndicesCache,
+    registry_pkgs_cache: &mut registry_pkg_cache::RegistryPkgCaches,
+    registry_sources_caches: &mut registry_sources::RegistrySourceCaches,
+) {
+    let registries = find_duplicate_registries(ccp);
+    if registries.is_empty() {
+        println!("Every registry is cached in a single directory, nothing to migrate.");
+        return;
+    }
+
+    for registry in &registries {
+        println!(
+            "Registry \"{}\": keeping \"{}\".",
+            registry.name, registry.current
+        );
+        for obsolete in &registry.obsolete {
+            let all_moved = merge_archives(
+                &ccp.registry_pkg_cache.join(obsolete),
+                &ccp.registry_pkg_cache.join(&registry.current),
+                dry_run,
+            );
+            for component in &REGISTRY_COMPONENTS {
+                let dir = component_path(component, ccp).join(obsolete);
+                if !dir.exists() {
+                    continue;
+                }
+                // never remove an archive we failed to move
+                if *component == Component::RegistryCrateCache && !all_moved {
+                    eprintln!(
+                        "Warning: keeping '{}', not all crate archives could be moved.",
+                        dir.display()
+                    );
+                    continue;
+                }
+                remove_with_default_message(&dir, dry_run, size_changed, None);
+            }
+        }
+    }
+
+    if !dry_run {
+        registry_index_caches.invalidate();
+        registry_pkgs_cache.invalidate();
+        registry_sources_caches.invalidate();
+    }
+}
+
+#[cfg(test)]
+mod registry_migration_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    const GIT_INDEX: &str = "github.com-1ecc6299db9ec823";
+    const SPARSE_INDEX: &str = "index.crates.io-6f17d22bba15001f";
+
+    #[test]
+    fn registries_of_dirs() {
+        assert_eq!(registry_of(GIT_INDEX), Some("crates.io"));
+        assert_eq!(registry_of(SPARSE_INDEX), Some("crates.io"));
+        assert_eq!(
+            registry_of("index.crates.io-1949cf8c6b5b557f"),
+            Some("crates.io")
+        );
+        assert_eq!(registry_of("my-registry.com-1234"), None);
+        // another registry on github, not crates.io
+        assert_eq!(registry_of("github.com-0123456789abcdef"), None);
+    }
+
+    #[test]
+    fn registries_on_one_host_are_kept() {
+        let tmp = tempfile::tempdir().unwrap();
+        let ccp = CargoCachePaths::new(tmp.path().to_path_buf()).unwrap();
+        let registries = [
+            "cargo.company.com-0123456789abcdef",
+            "cargo.company.com-fedcba9876543210",
+            "github.com-0123456789abcdef",
+            GIT_INDEX,
+        ];
+        for dir_name in registries {
+            fs::create_dir_all(ccp.registry_index.join(dir_name)).unwrap();
+            fs::create_dir_all(ccp.registry_pkg_cache.join(dir_name)).unwrap();
+            fs::write(
+                ccp.registry_pkg_cache
+                    .join(dir_name)
+                    .join("serde-1.0.0.crate"),
+                dir_name,
+            )
+            .unwrap();
+        }
+
+        // migrate-registry only touches what is found here
+        assert_eq!(find_duplicate_registries(&ccp), Vec::new());
+        assert_eq!(duplicate_registries_note(&ccp), None);
+    }
+
+    #[test]
+    fn current_dir() {
+        let earlier = SystemTime::UNIX_EPOCH;
+        let later = SystemTime::now();
+        let candidates = |git, sparse| {
+            vec![
+                (GIT_INDEX.to_string(), git),
+                (SPARSE_INDEX.to_string(), sparse),
+            ]
+        };
+        assert_eq!(
+            pick_current(&candidates(Some(earlier), Some(later))),
+            Some(SPARSE_INDEX)
+        );
+        // someone still builds with an old cargo
+        assert_eq!(
+            pick_current(&candidates(Some(later), Some(earlier))),
+            Some(GIT_INDEX)
+        );
+        assert_eq!(
+            pick_current(&candidates(Some(later), Some(later))),
+            Some(SPARSE_INDEX)
+        );
+        assert_eq!(pick_current(&[]), None);
+    }
+
+    #[test]
+    fn migrate() {
+        let tmp = tempfile::tempdir().unwrap();
+        let ccp = CargoCachePaths::new(tmp.path().to_path_buf()).unwrap();
+        // the old cargo wrote first
+        fs::create_dir_all(ccp.registry_sources.join(GIT_INDEX).join("libc-0.2.0")).unwrap();
+        for dir_name in [GIT_INDEX, SPARSE_INDEX] {
+            fs::create_dir_all(ccp.registry_index.join(dir_name)).unwrap();
+            fs::create_dir_all(ccp.registry_pkg_cache.join(dir_name)).unwrap();
+            if dir_name == GIT_INDEX {
+                fs::write(
+                    ccp.registry_pkg_cache
+                        .join(dir_name)
+                        .join("libc-0.2.0.crate"),
+                    "libc",
+                )
+                .unwrap();
+            }
+            fs::write(
+                ccp.registry_pkg_cache
+                    .join(dir_name)
+                    .join("serde-1.0.0.crate"),
+                "serde",
+            )
+            .unwrap();
+        }
+        // a single directory is fine
+        fs::create_dir_all(ccp.registry_index.join("my-registry.com-1234")).unwrap();
+
+        let found = find_duplicate_registries(&ccp);
+        assert_eq!(
+            found,
+            vec![DuplicateRegistry {
+                name: String::from("crates.io"),
+                current: SPARSE_INDEX.to_string(),
+                obsolete: vec![GIT_INDEX.to_string()],
+            }]
+        );
+        assert_eq!(found[0].duplicated_archives(&ccp), (1, 5));
+        let note = duplicate_registries_note(&ccp).unwrap();
+        assert!(
+            note.contains("crates.io is cached in 2 directories"),
+            "{note}"
+        );
+        assert!(
+            note.contains("1 crate archives (5 B) are cached twice."),
+            "{note}"
+        );
+
+        let mut index = registry_index::RegistryIndicesCache::new(ccp.registry_index.clone());
+        let mut pkgs = registry_pkg_cache::RegistryPkgCaches::new(ccp.registry_pkg_cache.clone());
+        let mut sources = registry_sources::RegistrySourceCaches::new(ccp.registry_sources.clone());
+        let mut size_changed = false;
+
+        migrate_registries(
+            &ccp,
+            true,
+            &mut size_changed,
+            &mut index,
+            &mut pkgs,
+            &mut sources,
+        );
+        assert!(ccp.registry_pkg_cache.join(GIT_INDEX).exists());
+        assert!(!ccp
+            .registry_pkg_cache
+            .join(SPARSE_INDEX)
+            .join("libc-0.2.0.crate")
+            .exists());
+
+        migrate_registries(
+            &ccp,
+            false,
+            &mut size_changed,
+            &mut index,
+            &mut pkgs,
+            &mut sources,
+        );
+        assert!(size_changed);
+        for component in &REGISTRY_COMPONENTS {
+            assert!(!component_path(component, &ccp).join(GIT_INDEX).exists());
+        }
+        assert_eq!(
+            crate_archives(&ccp.registry_pkg_cache.join(SPARSE_INDEX)),
+            ["libc-0.2.0.crate", "serde-1.0.0.crate"]
+                .iter()
+                .map(|&archive| String::from(archive))
+                .collect()
+        );
+        assert!(find_duplicate_registries(&ccp).is_empty());
+        assert_eq!(duplicate_registries_note(&ccp), None);
+    }
+}
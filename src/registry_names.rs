@@ -0,0 +1,308 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// the names of the registries configured in the cargo configs
+// cargo names the directories of a registry after the host of its index and a hash
+// ("cargo.company.com-0123456789abcdef"), the name the user gave the registry in [registries]
+// ("company-internal") is nowhere to be found in the cargo home. We read the [registries] of the
+// cargo configs (and CARGO_REGISTRIES_<NAME>_INDEX) so that the output can show that name and
+// --only-registry/--exclude-registry accept it.
+// The hash depends on the cargo version, so registries are matched by the host of their index only.
+// If several registries share a host (crates.io counts as well), we can not tell them apart and
+// show no name.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use once_cell::sync::OnceCell;
+
+use crate::network::config_files;
+
+/// the registries of this run, set up once in `main()`
+static REGISTRY_NAMES: OnceCell<RegistryNames> = OnceCell::new();
+
+/// the hosts of the index of crates.io, the old git index and the sparse one
+const CRATES_IO_HOSTS: &[&str] = &["github.com", "index.crates.io"];
+
+/// a registry from a cargo config: its name and the host of its index
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ConfiguredRegistry {
+    name: String,
+    host: String,
+}
+
+/// all configured registries
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct RegistryNames {
+    registries: Vec<ConfiguredRegistry>,
+}
+
+impl RegistryNames {
+    /// read the registries from `CARGO_REGISTRIES_<NAME>_INDEX` and the cargo configs
+    pub(crate) fn new(cargo_home: &Path) -> Self {
+        let configs = env::current_dir()
+            .map(|cwd| config_files(&cwd, cargo_home))
+            .unwrap_or_default();
+        let env_vars = env::vars().collect::<Vec<(String, String)>>();
+        Self::from_sources(&env_vars, &configs)
+    }
+
+    /// the environment beats the configs which beat the configs further away, just like in cargo
+    fn from_sources(env_vars: &[(String, String)], configs: &[PathBuf]) -> Self {
+        let mut indices = env_vars
+            .iter()
+            .filter_map(|(var, value)| {
+                let name = var
+                    .strip_prefix("CARGO_REGISTRIES_")?
+                    .strip_suffix("_INDEX")?;
+                Some((name.to_lowercase().replace('_', "-"), value.clone()))
+            })
+            .collect::<Vec<(String, String)>>();
+        for config in configs {
+            if let Ok(content) = fs::read_to_string(config) {
+                indices.extend(registry_indices(&content));
+            }
+        }
+
+        let mut registries: Vec<ConfiguredRegistry> = Vec::new();
+        for (name, index) in indices {
+            if registries.iter().any(|registry| registry.name == name) {
+                continue;
+            }
+            if let Some(host) = index_host(&index) {
+                registries.push(ConfiguredRegistry { name, host });
+            }
+        }
+        Self { registries }
+    }
+
+    /// the configured name of the registry directory `dir_name` ("cargo.company.com-0123456789abcdef")
+    /// if exactly one registry (including crates.io) uses its host
+    pub(crate) fn name_of(&self, dir_name: &str) -> Option<&str> {
+        let host = dir_name
+            .rsplit_once('-')
+            .map_or(dir_name, |(host, _hash)| host);
+        if CRATES_IO_HOSTS.contains(&host) {
+            // could be crates.io as well
+            return None;
+        }
+        let mut matching = self
+            .registries
+            .iter()
+            .filter(|registry| registry.host == host);
+        match (matching.next(), matching.next()) {
+            (Some(registry), None) => Some(&registry.name),
+            _ => None,
+        }
+    }
+}
+
+/// the registry names and index urls of a cargo config, as [registries.name] table, inline table
+/// in [registries] or dotted keys
+fn registry_indices(config: &str) -> Vec<(String, String)> {
+    let mut indices = Vec::new();
+    let mut table = String::new();
+    for line in config.lines() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if let Some(header) = line.strip_prefix('[') {
+            table = header.trim_end_matches(']').trim().to_string();
+            continue;
+        }
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) => (key.trim(), value.trim()),
+            None => continue,
+        };
+        let key = if table.is_empty() {
+            key.replace(' ', "")
+        } else {
+            format!("{table}.{key}").replace(' ', "")
+        };
+        let registry = match key.strip_prefix("registries.") {
+            Some(registry) => registry,
+            None => continue,
+        };
+        // registries.name.index = "url" or registries.name = { index = "url" }
+        let index = match (registry.strip_suffix(".index"), value.strip_prefix('{')) {
+            (Some(name), _) => unquote(value).map(|url| (name, url)),
+            (None, Some(inline)) => inline
+                .trim_end_matches('}')
+                .split(',')
+                .filter_map(|entry| entry.split_once('='))
+                .find(|(entry_key, _)| entry_key.trim() == "index")
+                .and_then(|(_, url)| unquote(url.trim()))
+                .map(|url| (registry, url)),
+            (None, None) => None,
+        };
+        if let Some((name, url)) = index {
+            indices.push((name.trim_matches('"').to_string(), url));
+        }
+    }
+    indices
+}
+
+/// "abc" or 'abc'
+fn unquote(value: &str) -> Option<String> {
+    ['"', '\''].iter().find_map(|&quote| {
+        value
+            .strip_prefix(quote)?
+            .strip_suffix(quote)
+            .map(str::to_string)
+    })
+}
+
+/// the host of an index url, which cargo names the registry directories after
+/// `sparse+https://cargo.company.com/index/` => `cargo.company.com`
+fn index_host(index: &str) -> Option<String> {
+    let url = index
+        .trim_start_matches("sparse+")
+        .trim_start_matches("registry+");
+    let (_scheme, rest) = url.split_once("://")?;
+    let authority = rest.split('/').next()?;
+    let host_and_port = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_user, host)| host);
+    let host = host_and_port
+        .split_once(':')
+        .map_or(host_and_port, |(host, _port)| host);
+    (!host.is_empty()).then(|| host.to_string())
+}
+
+/// set the configured registries, needs to be called before any output is printed
+pub(crate) fn init(registry_names: RegistryNames) {
+    let _ = REGISTRY_NAMES.set(registry_names);
+}
+
+/// the configured name of the registry directory `dir_name`, if we can tell
+pub(crate) fn name_of(dir_name: &str) -> Option<&'static str> {
+    REGISTRY_NAMES.get()?.name_of(dir_name)
+}
+
+#[cfg(test)]
+mod registry_names_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn names(registries: &[(&str, &str)]) -> RegistryNames {
+        RegistryNames {
+            registries: registries
+                .iter()
+                .map(|&(name, host)| ConfiguredRegistry {
+                    name: name.to_string(),
+                    host: host.to_string(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn config_registries() {
+        let config = "\
+            [registries]\n\
+            company-internal = { index = \"sparse+https://cargo.company.com/index/\" }\n\
+            other = { token = \"x\", index = 'https://git.example.com/index.git' } # comment\n\
+            \n\
+            [registries.tabled]\n\
+            index = \"ssh://git@ssh.example.com:2222/index.git\"\n\
+            token = \"no index\"\n\
+            \n\
+            [net]\n\
+            offline = true\n";
+        assert_eq!(
+            registry_indices(config),
+            vec![
+                (
+                    String::from("company-internal"),
+                    String::from("sparse+https://cargo.company.com/index/")
+                ),
+                (
+                    String::from("other"),
+                    String::from("https://git.example.com/index.git")
+                ),
+                (
+                    String::from("tabled"),
+                    String::from("ssh://git@ssh.example.com:2222/index.git")
+                ),
+            ]
+        );
+        // dotted keys outside of a table
+        assert_eq!(
+            registry_indices("registries.dotted.index = \"https://dotted.example.com/index\""),
+            vec![(
+                String::from("dotted"),
+                String::from("https://dotted.example.com/index")
+            )]
+        );
+    }
+
+    #[test]
+    fn index_hosts() {
+        assert_eq!(
+            index_host("sparse+https://cargo.company.com/index/").as_deref(),
+            Some("cargo.company.com")
+        );
+        assert_eq!(
+            index_host("ssh://git@ssh.example.com:2222/index.git").as_deref(),
+            Some("ssh.example.com")
+        );
+        assert_eq!(index_host("not a url"), None);
+    }
+
+    #[test]
+    fn names_of_directories() {
+        let registries = names(&[
+            ("company-internal", "cargo.company.com"),
+            ("mirror", "github.com"),
+            ("a", "shared.example.com"),
+            ("b", "shared.example.com"),
+        ]);
+        assert_eq!(
+            registries.name_of("cargo.company.com-0123456789abcdef"),
+            Some("company-internal")
+        );
+        // could be crates.io or the mirror, or either of a and b
+        assert_eq!(registries.name_of("github.com-1ecc6299db9ec823"), None);
+        assert_eq!(
+            registries.name_of("shared.example.com-0123456789abcdef"),
+            None
+        );
+        assert_eq!(registries.name_of("index.crates.io-6f17d22bba15001f"), None);
+    }
+
+    #[test]
+    fn precedence() {
+        let tmp = tempfile::tempdir().unwrap();
+        let close = tmp.path().join("close.toml");
+        let far = tmp.path().join("far.toml");
+        fs::write(
+            &close,
+            "[registries.internal]\nindex = \"https://close.example.com/index\"\n",
+        )
+        .unwrap();
+        fs::write(
+            &far,
+            "[registries]\ninternal = { index = \"https://far.example.com/index\" }\n\
+            other = { index = \"https://other.example.com/index\" }\n",
+        )
+        .unwrap();
+        let env_vars = [(
+            String::from("CARGO_REGISTRIES_FROM_ENV_INDEX"),
+            String::from("https://env.example.com/index"),
+        )];
+
+        assert_eq!(
+            RegistryNames::from_sources(&env_vars, &[close, far]),
+            names(&[
+                ("from-env", "env.example.com"),
+                ("internal", "close.example.com"),
+                ("other", "other.example.com"),
+            ])
+        );
+    }
+}
@@ -7,14 +7,34 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+use std::borrow::Cow;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
 
-use crate::cache::caches::{Cache, RegistrySuperCache};
+use crate::cache::caches::{Cache, RegistrySubCache, RegistrySuperCache};
 use crate::cache::*;
+use crate::cancel::is_cancelled;
+use crate::commands::trim::get_last_access_of_item;
+use crate::history;
+use crate::ignore::{ignores_anything, is_ignored};
+#[cfg(feature = "json")]
+use crate::journal;
+use crate::keep::KeepList;
 use crate::library::*;
+use crate::modified::without_modified;
+use crate::names;
+use crate::preflight::preflight;
+use crate::retry;
+use crate::system_trash;
+use crate::tables::format_table;
+use crate::throughput::Measurement;
+use crate::walk::walk;
+use crate::would_free;
 
+use chrono::{DateTime, Local, NaiveDateTime};
 use humansize::{FormatSize, DECIMAL};
+use rayon::prelude::*;
 
 /// dry run message setting
 pub(crate) enum DryRunMessage<'a> {
@@ -24,128 +44,190 @@ pub(crate) enum DryRunMessage<'a> {
     None, // no message
 }
 
-fn parse_version(path: &Path) -> Result<(String, String), Error> {
-    #[allow(clippy::single_match_else)]
-    let filename = match path.file_stem() {
-        Some(name) => name.to_str().unwrap().to_string(),
-        None => {
-            return Err(Error::MalformedPackageName(path.display().to_string()));
-        }
-    };
+/// split the file name of a .crate archive or a source directory into crate name and version
+pub(crate) fn parse_version(path: &Path) -> Result<(String, String), Error> {
+    let file_name = names::file_name(path);
+    if file_name.is_empty() {
+        return Err(Error::MalformedPackageName(path.display().to_string()));
+    }
+    let (name, version) = names::split_name_version(&file_name);
+    Ok((name.to_string(), version.to_string()))
+}
 
-    let mut name = Vec::new();
-    let mut version = Vec::new();
-    let mut found_version = false;
-
-    filename.split('-').for_each(|seg| {
-        let first_char = seg.chars().next();
-        if let Some(char) = first_char {
-            if char.is_numeric() {
-                // the first char of the segment is a number
-                // conclude that this segment is the version string
-                version.push(seg);
-                // if we have found the first version, everything afterwards will be version info
-                found_version = true;
-            } else if found_version {
-                version.push(seg);
-            } else {
-                // if char is not numeric
-                name.push(seg);
+/// a crate archive of a registry together with the metadata pruning needs,
+/// every archive is only statted once, on NFS that is what takes the time
+#[derive(Debug, Clone)]
+struct RegistryCrate {
+    path: PathBuf,
+    size: u64,
+    modified: Option<SystemTime>,
+}
+
+/// stat the crate archives of a registry in parallel
+fn stat_crates(paths: Vec<PathBuf>) -> Vec<RegistryCrate> {
+    paths
+        .into_par_iter()
+        .map(|path| {
+            let metadata = retry::metadata(&path)
+                .unwrap_or_else(|_| panic!("Failed to get metadata of file '{}'", &path.display()));
+            RegistryCrate {
+                size: metadata.len(),
+                modified: metadata.modified().ok(),
+                path,
             }
+        })
+        .collect()
+}
+
+/// sort the crates of a registry so that all versions of a crate are next to each other,
+/// the versions we want to keep most come first
+fn sort_for_pruning(crate_list: &mut [RegistryCrate], keep_by: KeepBy) {
+    match keep_by {
+        KeepBy::Version => {
+            crate_list.sort_by(|a, b| b.path.cmp(&a.path));
+        }
+        KeepBy::Mtime => {
+            // most recently modified (downloaded or touched by cargo) first
+            crate_list.sort_by_cached_key(|krate| {
+                let name = parse_version(&krate.path).map(|(name, _version)| name).ok();
+                (name, std::cmp::Reverse(krate.modified))
+            });
+        }
+    }
+}
+
+/// the crates of a registry that are removed if only `amount_to_keep` versions of each are kept
+/// `crate_list` needs to be sorted with `sort_for_pruning()`
+fn crates_to_prune(
+    crate_list: &[RegistryCrate],
+    amount_to_keep: u64,
+) -> Result<Vec<&RegistryCrate>, Error> {
+    let mut to_prune = Vec::new();
+    let mut versions_of_this_package = 0;
+    let mut last_pkgname = String::new();
+
+    // iterate over all crates and extract name and version
+    for krate in crate_list {
+        let (pkgname, _pkgver) = parse_version(&krate.path)?;
+        if last_pkgname == pkgname {
+            // same package again
+            versions_of_this_package += 1;
+        } else {
+            // we got to a new package, reset counter
+            versions_of_this_package = 1;
+            last_pkgname = pkgname;
+        }
+        // we have seen this package too many times, queue for deletion
+        if versions_of_this_package > amount_to_keep {
+            to_prune.push(krate);
         }
-    });
+    }
+    Ok(to_prune)
+}
 
-    let name = name.join("-");
-    let version = version.join("-");
+/// the crates of a single registry that --keep-duplicate-crates removes
+fn prune_registry(
+    crate_paths: Vec<PathBuf>,
+    amount_to_keep: u64,
+    keep_by: KeepBy,
+) -> Result<Vec<RegistryCrate>, Error> {
+    let mut crate_list = stat_crates(crate_paths);
+    sort_for_pruning(&mut crate_list, keep_by);
+    Ok(crates_to_prune(&crate_list, amount_to_keep)?
+        .into_iter()
+        .cloned()
+        .collect())
+}
 
-    Ok((name, version))
+/// how much --keep-duplicate-crates `amount_to_keep` would free, without removing anything
+pub(crate) fn duplicate_crates_size(
+    registry_pkgs_cache: &mut registry_pkg_cache::RegistryPkgCaches,
+    amount_to_keep: u64,
+    keep_by: KeepBy,
+) -> u64 {
+    let registries = registry_pkgs_cache
+        .caches()
+        .iter_mut()
+        .map(|registry| registry.files().to_vec())
+        .collect::<Vec<Vec<PathBuf>>>();
+    registries
+        .into_par_iter()
+        .map(|crate_paths| {
+            prune_registry(crate_paths, amount_to_keep, keep_by)
+                .unwrap_or_default()
+                .iter()
+                .map(|krate| krate.size)
+                .sum::<u64>()
+        })
+        .sum()
 }
 
 pub(crate) fn rm_old_crates(
     amount_to_keep: u64,
+    keep_by: KeepBy,
     dry_run: bool,
     registry_src_path: &Path,
+    keep: &KeepList,
     size_changed: &mut bool,
 ) -> Result<(), Error> {
     println!();
 
     // remove crate sources from cache
     // src can be completely removed since we can always rebuilt it from cache (by extracting packages)
-    let mut removed_size = 0;
-    // walk registry repos
-    for repo in fs::read_dir(registry_src_path).unwrap() {
-        let mut crate_list = fs::read_dir(repo.unwrap().path())
-            .unwrap()
-            .map(|cratepath| cratepath.unwrap().path())
-            .collect::<Vec<PathBuf>>();
-        crate_list.sort();
-        crate_list.reverse();
-
-        let mut versions_of_this_package = 1;
-        let mut last_pkgname = String::new();
-
-        // iterate over all crates and extract name and version
-        for pkgpath in &crate_list {
-            let (pkgname, pkgver) = parse_version(pkgpath)?;
-
-            if amount_to_keep == 0 {
-                removed_size += fs::metadata(pkgpath)
-                    .unwrap_or_else(|_| {
-                        panic!("Failed to get metadata of file '{}'", &pkgpath.display())
-                    })
-                    .len();
-
-                let dryrun_msg = format!(
-                    "dry run: not actually deleting {} {} at {}",
-                    pkgname,
-                    pkgver,
-                    pkgpath.display()
-                );
-                remove_file(
-                    pkgpath,
-                    dry_run,
-                    size_changed,
-                    None,
-                    &DryRunMessage::Custom(&dryrun_msg),
-                    None,
-                );
+    let registries = retry::read_dir_sorted(registry_src_path).unwrap();
+    // walk and stat all registries in parallel, the sizes are reused for the summary
+    let to_prune = registries
+        .par_iter()
+        .map(|repo| {
+            let crate_paths = retry::read_dir_sorted(repo)
+                .unwrap()
+                .into_iter()
+                // all versions of a kept crate are kept
+                .filter(|path| !is_ignored(path) && !keep.keeps(path))
+                .collect::<Vec<PathBuf>>();
+            prune_registry(crate_paths, amount_to_keep, keep_by)
+        })
+        .collect::<Result<Vec<Vec<RegistryCrate>>, Error>>()?
+        .into_iter()
+        .flatten()
+        .collect::<Vec<RegistryCrate>>();
+    let removed_size: u64 = to_prune.iter().map(|krate| krate.size).sum();
 
-                continue;
-            }
-            // println!("pkgname: {:?}, pkgver: {:?}", pkgname, pkgver);
-            // same package again
-            if last_pkgname == pkgname {
-                versions_of_this_package += 1;
-                if versions_of_this_package > amount_to_keep {
-                    // we have seen this package too many times, queue for deletion
-                    removed_size += fs::metadata(pkgpath)
-                        .unwrap_or_else(|_| {
-                            panic!("Failed to get metadata of file '{}'", &pkgpath.display())
-                        })
-                        .len();
-
-                    let dryrun_msg = format!(
-                        "dry run: not actually deleting {} {} at {}",
-                        pkgname,
-                        pkgver,
-                        pkgpath.display()
-                    );
-                    remove_file(
-                        pkgpath,
-                        dry_run,
-                        size_changed,
-                        None,
-                        &DryRunMessage::Custom(&dryrun_msg),
-                        None,
-                    );
-                }
-            } else {
-                // last_pkgname != pkgname, we got to a new package, reset counter
-                versions_of_this_package = 1;
-                last_pkgname = pkgname;
-            } // if last_pkgname == pkgname
-        } // for pkgpath in &crate_list
+    let remove = |krate: &RegistryCrate| -> Result<bool, Error> {
+        let (pkgname, pkgver) = parse_version(&krate.path)?;
+        let dryrun_msg = format!(
+            "dry run: not actually deleting {} {} at {}",
+            pkgname,
+            pkgver,
+            krate.path.display()
+        );
+        let mut removed = false;
+        remove_file(
+            &krate.path,
+            dry_run,
+            &mut removed,
+            None,
+            &DryRunMessage::Custom(&dryrun_msg),
+            Some(krate.size),
+        );
+        Ok(removed)
+    };
+    // the dry run messages stay in order, nothing is printed when actually removing
+    let removed = if dry_run {
+        to_prune
+            .iter()
+            .map(remove)
+            .collect::<Result<Vec<bool>, Error>>()?
+    } else {
+        to_prune
+            .par_iter()
+            .map(remove)
+            .collect::<Result<Vec<bool>, Error>>()?
+    };
+    if removed.contains(&true) {
+        *size_changed = true;
     }
+
     println!(
         "Removed {} of compressed crate sources.",
         removed_size.format_size(DECIMAL)
@@ -158,6 +240,8 @@ pub(crate) fn rm_old_crates(
 pub(crate) fn remove_dir_via_cmdline(
     directory: Option<&str>,
     dry_run: bool,
+    allow_cross_device: bool,
+    keep_going: bool,
     ccd: &CargoCachePaths,
     size_changed: &mut bool,
     checkouts_cache: &mut git_checkouts::GitCheckoutCache,
@@ -165,51 +249,151 @@ pub(crate) fn remove_dir_via_cmdline(
     registry_index_caches: &mut registry_index::RegistryIndicesCache,
     registry_pkgs_cache: &mut registry_pkg_cache::RegistryPkgCaches,
     registry_sources_caches: &mut registry_sources::RegistrySourceCaches,
+    selection: &ComponentSelection,
+    max_age: Option<NaiveDateTime>,
+    keep: &KeepList,
 ) -> Result<(), Error> {
     // @TODO the passing of the cache is really a mess here... :(
 
-    let dirs_to_remove = components_from_groups(directory)?;
+    let mut dirs_to_remove = components_from_groups(directory)?;
+    // leave components alone that were not selected via --components
+    dirs_to_remove.retain(|component| selection.contains(component));
+    // registry-index-sparse only removes the sparse indices, unless the whole index goes anyway
+    let sparse_indices = if removes_sparse_indices(directory)
+        && selection.contains(&Component::RegistryIndex)
+        && !dirs_to_remove.contains(&Component::RegistryIndex)
+    {
+        registry_index_caches.sparse_sizes()
+    } else {
+        Vec::new()
+    };
+    // "registry-crate-cache:<registry>" and the like only remove the directories of that registry
+    let registry_dirs = registry_dirs(
+        directory,
+        &dirs_to_remove,
+        selection,
+        registry_index_caches,
+        registry_pkgs_cache,
+        registry_sources_caches,
+    )?;
+
+    // refuse to remove anything if one of the directories lives on another device,
+    // check this before removing anything so we don't end up with a half-removed cache
+    if !allow_cross_device {
+        for component in &dirs_to_remove {
+            check_same_device(component_path(component, ccd))?;
+        }
+        if !sparse_indices.is_empty() {
+            check_same_device(&ccd.registry_index)?;
+        }
+        for (component, _, _, _) in &registry_dirs {
+            check_same_device(component_path(component, ccd))?;
+        }
+    }
+
+    // CARGO_CACHE_MAX_AGE: only remove what inside of the directories was last used before that date
+    if let Some(max_age) = max_age {
+        let targets = dirs_to_remove
+            .iter()
+            .map(|component| {
+                (
+                    component.clone(),
+                    component_path(component, ccd).to_path_buf(),
+                )
+            })
+            .chain(
+                sparse_indices
+                    .iter()
+                    .map(|(index, _)| (Component::RegistryIndex, index.clone())),
+            )
+            .chain(
+                registry_dirs
+                    .iter()
+                    .map(|(component, dir, _, _)| (component.clone(), dir.clone())),
+            )
+            .collect::<Vec<_>>();
+        return remove_aged_items(
+            &targets,
+            max_age,
+            dry_run,
+            keep_going,
+            keep,
+            size_changed,
+            checkouts_cache,
+            bare_repos_cache,
+            registry_index_caches,
+            registry_pkgs_cache,
+            registry_sources_caches,
+        );
+    }
 
-    let mut size_removed: u64 = 0;
+    // make sure we can remove everything before we start removing anything
+    let dirs = dirs_to_remove
+        .iter()
+        .map(|component| component_path(component, ccd))
+        .chain(sparse_indices.iter().map(|(index, _)| index.as_path()))
+        .chain(registry_dirs.iter().map(|(_, dir, _, _)| dir.as_path()))
+        .collect::<Vec<&Path>>();
+    preflight(&dirs, dry_run, keep_going)?;
+
+    // what was (or would be) freed per component: (component, bytes, items)
+    let mut freed: Vec<(Component, u64, usize)> = Vec::new();
 
     if dry_run {
         println!(); // newline
     }
 
     for component in dirs_to_remove {
-        match component {
+        // capture the sizes before removing, afterwards the caches are rescanned to see what is left
+        let (size_before, items_before, size_after, items_after) = match component {
             Component::RegistryCrateCache => {
                 let size = registry_pkgs_cache.total_size();
-                size_removed += size;
+                let items = registry_pkgs_cache.number_of_items();
                 remove_with_default_message(
                     &ccd.registry_pkg_cache,
                     dry_run,
                     size_changed,
                     Some(size),
                 );
-                if !dry_run {
+                if dry_run {
+                    (size, items, 0, 0)
+                } else {
                     registry_pkgs_cache.invalidate();
+                    (
+                        size,
+                        items,
+                        registry_pkgs_cache.total_size(),
+                        registry_pkgs_cache.number_of_items(),
+                    )
                 }
             }
 
             Component::RegistrySources => {
                 let size = registry_sources_caches.total_size();
-                size_removed += size;
+                let items = registry_sources_caches.number_of_items();
                 remove_with_default_message(
                     &ccd.registry_sources,
                     dry_run,
                     size_changed,
                     Some(size),
                 );
-                if !dry_run {
+                if dry_run {
+                    (size, items, 0, 0)
+                } else {
                     registry_sources_caches.invalidate();
+                    (
+                        size,
+                        items,
+                        registry_sources_caches.total_size(),
+                        registry_sources_caches.number_of_items(),
+                    )
                 }
             }
             Component::RegistryIndex => {
                 // sum the sizes of the separate indices
                 let size_of_all_indices: u64 = registry_index_caches.total_size();
+                let items = registry_index_caches.number_of_subcaches();
 
-                size_removed += size_of_all_indices;
                 // @TODO only remove specified index
                 remove_with_default_message(
                     &ccd.registry_index,
@@ -217,39 +401,506 @@ pub(crate) fn remove_dir_via_cmdline(
                     size_changed,
                     Some(size_of_all_indices),
                 );
-                if !dry_run {
+                if dry_run {
+                    (size_of_all_indices, items, 0, 0)
+                } else {
                     registry_index_caches.invalidate();
+                    (
+                        size_of_all_indices,
+                        items,
+                        registry_index_caches.total_size(),
+                        registry_index_caches.number_of_subcaches(),
+                    )
                 }
             }
             Component::GitRepos => {
                 let size = checkouts_cache.total_size();
-                size_removed += size;
+                let items = checkouts_cache.number_of_items();
                 remove_with_default_message(&ccd.git_checkouts, dry_run, size_changed, Some(size));
-                if !dry_run {
+                if dry_run {
+                    (size, items, 0, 0)
+                } else {
                     checkouts_cache.invalidate();
+                    (
+                        size,
+                        items,
+                        checkouts_cache.total_size(),
+                        checkouts_cache.number_of_items(),
+                    )
                 }
             }
             Component::GitDB => {
                 let size = bare_repos_cache.total_size();
-                size_removed += size;
+                let items = bare_repos_cache.number_of_items();
                 remove_with_default_message(&ccd.git_repos_bare, dry_run, size_changed, Some(size));
-                if !dry_run {
+                if dry_run {
+                    (size, items, 0, 0)
+                } else {
                     bare_repos_cache.invalidate();
+                    (
+                        size,
+                        items,
+                        bare_repos_cache.total_size(),
+                        bare_repos_cache.number_of_items(),
+                    )
                 }
             }
+        };
+        freed.push((
+            component,
+            size_before.saturating_sub(size_after),
+            items_before.saturating_sub(items_after),
+        ));
+    }
+
+    if !sparse_indices.is_empty() {
+        let size: u64 = sparse_indices.iter().map(|(_, size)| size).sum();
+        let items = sparse_indices.len();
+        for (index, index_size) in &sparse_indices {
+            remove_with_default_message(index, dry_run, size_changed, Some(*index_size));
         }
+        let (size_after, items_after) = if dry_run {
+            (0, 0)
+        } else {
+            registry_index_caches.invalidate();
+            (
+                registry_index_caches.sparse_size(),
+                registry_index_caches.sparse_indices().len(),
+            )
+        };
+        freed.push((
+            Component::RegistryIndex,
+            size.saturating_sub(size_after),
+            items.saturating_sub(items_after),
+        ));
     }
 
-    if dry_run {
-        println!(
-            "dry-run: would remove in total: {}",
-            size_removed.format_size(DECIMAL)
+    for component in [
+        Component::RegistrySources,
+        Component::RegistryCrateCache,
+        Component::RegistryIndex,
+    ] {
+        let registry_component_dirs = registry_dirs
+            .iter()
+            .filter(|(dir_component, _, _, _)| *dir_component == component)
+            .collect::<Vec<_>>();
+        if registry_component_dirs.is_empty() {
+            continue;
+        }
+        let size: u64 = registry_component_dirs
+            .iter()
+            .map(|(_, _, size, _)| size)
+            .sum();
+        let items: usize = registry_component_dirs
+            .iter()
+            .map(|(_, _, _, items)| items)
+            .sum();
+        let (size_before, items_before) = match component {
+            Component::RegistrySources => (
+                registry_sources_caches.total_size(),
+                registry_sources_caches.number_of_items(),
+            ),
+            Component::RegistryCrateCache => (
+                registry_pkgs_cache.total_size(),
+                registry_pkgs_cache.number_of_items(),
+            ),
+            _ => (
+                registry_index_caches.total_size(),
+                registry_index_caches.number_of_subcaches(),
+            ),
+        };
+        for (_, dir, dir_size, _) in &registry_component_dirs {
+            remove_with_default_message(dir, dry_run, size_changed, Some(*dir_size));
+        }
+        // afterwards the caches are rescanned to see what is left
+        let (size_after, items_after) = if dry_run {
+            (
+                size_before.saturating_sub(size),
+                items_before.saturating_sub(items),
+            )
+        } else {
+            match component {
+                Component::RegistrySources => {
+                    registry_sources_caches.invalidate();
+                    (
+                        registry_sources_caches.total_size(),
+                        registry_sources_caches.number_of_items(),
+                    )
+                }
+                Component::RegistryCrateCache => {
+                    registry_pkgs_cache.invalidate();
+                    (
+                        registry_pkgs_cache.total_size(),
+                        registry_pkgs_cache.number_of_items(),
+                    )
+                }
+                _ => {
+                    registry_index_caches.invalidate();
+                    (
+                        registry_index_caches.total_size(),
+                        registry_index_caches.number_of_subcaches(),
+                    )
+                }
+            }
+        };
+        freed.push((
+            component,
+            size_before.saturating_sub(size_after),
+            items_before.saturating_sub(items_after),
+        ));
+    }
+
+    // with several components, show what each of them contributed,
+    // a dry run shows what it would free at the end of the run
+    if freed.len() > 1 && !dry_run {
+        println!("\n{}", freed_per_component(&freed));
+    }
+
+    Ok(())
+}
+
+/// the root directories of the registries "registry-crate-cache:<registry>" and the like remove,
+/// as (component, directory, size, items), unless the whole component is removed anyway
+fn registry_dirs(
+    directory: Option<&str>,
+    whole_components: &[Component],
+    selection: &ComponentSelection,
+    registry_index_caches: &mut registry_index::RegistryIndicesCache,
+    registry_pkgs_cache: &mut registry_pkg_cache::RegistryPkgCaches,
+    registry_sources_caches: &mut registry_sources::RegistrySourceCaches,
+) -> Result<Vec<(Component, PathBuf, u64, usize)>, Error> {
+    fn matches(path: &Path, registry: &str) -> bool {
+        path.file_name().map_or(false, |dir_name| {
+            registry_matches(&dir_name.to_string_lossy(), registry)
+        })
+    }
+
+    let targets = registry_targets(directory);
+    // a typo should not go unnoticed just because nothing is removed
+    for (_, registry) in &targets {
+        let known = registry_sources_caches
+            .caches()
+            .iter()
+            .map(RegistrySubCache::path)
+            .chain(
+                registry_pkgs_cache
+                    .caches()
+                    .iter()
+                    .map(RegistrySubCache::path),
+            )
+            .chain(
+                registry_index_caches
+                    .caches()
+                    .iter()
+                    .map(RegistrySubCache::path),
+            )
+            .any(|path| matches(path, registry));
+        if !known {
+            return Err(Error::RemoveDirUnknownRegistry(registry.clone()));
+        }
+    }
+
+    let mut dirs = Vec::new();
+    for (component, registry) in targets {
+        if whole_components.contains(&component) || !selection.contains(&component) {
+            continue;
+        }
+        match component {
+            Component::RegistrySources => dirs.extend(
+                registry_sources_caches
+                    .caches()
+                    .iter_mut()
+                    .filter(|cache| matches(cache.path(), &registry))
+                    .map(|cache| {
+                        (
+                            Component::RegistrySources,
+                            cache.path().clone(),
+                            cache.total_size(),
+                            cache.number_of_items(),
+                        )
+                    }),
+            ),
+            Component::RegistryCrateCache => dirs.extend(
+                registry_pkgs_cache
+                    .caches()
+                    .iter_mut()
+                    .filter(|cache| matches(cache.path(), &registry))
+                    .map(|cache| {
+                        (
+                            Component::RegistryCrateCache,
+                            cache.path().clone(),
+                            cache.total_size(),
+                            cache.number_of_items(),
+                        )
+                    }),
+            ),
+            Component::RegistryIndex => dirs.extend(
+                registry_index_caches
+                    .caches()
+                    .iter_mut()
+                    .filter(|cache| matches(cache.path(), &registry))
+                    .map(|cache| {
+                        (
+                            Component::RegistryIndex,
+                            cache.path().clone(),
+                            cache.total_size(),
+                            1,
+                        )
+                    }),
+            ),
+            Component::GitDB | Component::GitRepos => {
+                unreachable!("git components do not belong to a registry")
+            }
+        }
+    }
+    // "crates.io" and "index.crates.io" may name the same directory
+    dirs.sort_by(|a, b| (&a.0, &a.1).cmp(&(&b.0, &b.1)));
+    dirs.dedup_by(|a, b| a.0 == b.0 && a.1 == b.1);
+    Ok(dirs)
+}
+
+/// with `CARGO_CACHE_MAX_AGE`, --remove-dir only removes the items inside of the directories
+/// that were last used before `max_age`, instead of the whole directories
+#[allow(clippy::too_many_arguments)]
+fn remove_aged_items(
+    targets: &[(Component, PathBuf)],
+    max_age: NaiveDateTime,
+    dry_run: bool,
+    keep_going: bool,
+    keep: &KeepList,
+    size_changed: &mut bool,
+    checkouts_cache: &mut git_checkouts::GitCheckoutCache,
+    bare_repos_cache: &mut git_bare_repos::GitRepoCache,
+    registry_index_caches: &mut registry_index::RegistryIndicesCache,
+    registry_pkgs_cache: &mut registry_pkg_cache::RegistryPkgCaches,
+    registry_sources_caches: &mut registry_sources::RegistrySourceCaches,
+) -> Result<(), Error> {
+    let used_before_max_age = |item: &Path| {
+        get_last_access_of_item(item).map_or(false, |last_access| {
+            DateTime::<Local>::from(last_access).naive_local() < max_age
+        })
+    };
+
+    let mut aged: Vec<(Component, PathBuf)> = Vec::new();
+    for (component, dir) in targets {
+        let candidates = match component {
+            Component::RegistryCrateCache => registry_pkgs_cache.items().to_vec(),
+            Component::RegistrySources => registry_sources_caches.items().to_vec(),
+            Component::RegistryIndex => registry_index_caches
+                .caches()
+                .iter()
+                .map(|index| index.path().clone())
+                .collect(),
+            Component::GitRepos => checkouts_cache.items().to_vec(),
+            Component::GitDB => bare_repos_cache.items().to_vec(),
+        };
+        aged.extend(
+            candidates
+                .into_iter()
+                .filter(|item| item.starts_with(dir))
+                .filter(|item| !keep.keeps(item) && used_before_max_age(item))
+                .map(|item| (component.clone(), item)),
         );
     }
+    // a registry may have been passed together with its whole component
+    aged.sort();
+    aged.dedup();
 
+    // make sure we can remove everything before we start removing anything
+    let items = aged
+        .iter()
+        .map(|(_, item)| item.as_path())
+        .collect::<Vec<&Path>>();
+    preflight(&items, dry_run, keep_going)?;
+
+    println!(
+        "{} {} items that were last used before {max_age}...",
+        if dry_run {
+            "dry-run: would remove"
+        } else {
+            "Removing"
+        },
+        aged.len()
+    );
+    // what was (or would be) freed per component: (component, bytes, items)
+    let mut freed: Vec<(Component, u64, usize)> = Vec::new();
+    for (component, item) in &aged {
+        let size = size_of_path(item);
+        remove_with_default_message(item, dry_run, size_changed, Some(size));
+        match freed.last_mut() {
+            Some((last, bytes, count)) if last == component => {
+                *bytes += size;
+                *count += 1;
+            }
+            _ => freed.push((component.clone(), size, 1)),
+        }
+    }
+
+    if !dry_run {
+        for (component, _, _) in &freed {
+            match component {
+                Component::RegistryCrateCache => registry_pkgs_cache.invalidate(),
+                Component::RegistrySources => registry_sources_caches.invalidate(),
+                Component::RegistryIndex => registry_index_caches.invalidate(),
+                Component::GitRepos => checkouts_cache.invalidate(),
+                Component::GitDB => bare_repos_cache.invalidate(),
+            }
+        }
+        if freed.len() > 1 {
+            println!("\n{}", freed_per_component(&freed));
+        }
+    }
     Ok(())
 }
 
+/// a table of the bytes and items that were freed per component by --remove-dir
+fn freed_per_component(freed: &[(Component, u64, usize)]) -> String {
+    let mut table = vec![vec![
+        String::from("Component"),
+        String::from("Items"),
+        String::from("Freed"),
+    ]];
+    table.extend(freed.iter().map(|(component, size, items)| {
+        vec![
+            component.name().to_string(),
+            items.to_string(),
+            size.format_size(DECIMAL),
+        ]
+    }));
+    format_table(&table, 0)
+}
+
+/// the directories --autoclean removes (registry sources and git checkouts), if they were selected
+/// if only some registries should be cleaned, the sources of the other registries are kept
+pub(crate) fn autoclean_dirs(
+    ccd: &CargoCachePaths,
+    selection: &ComponentSelection,
+    registries: &RegistryFilter,
+    registry_sources_caches: &mut registry_sources::RegistrySourceCaches,
+) -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if selection.contains(&Component::RegistrySources) {
+        if registries.is_restricted() {
+            dirs.extend(
+                registry_sources_caches
+                    .caches()
+                    .iter()
+                    .map(RegistrySubCache::path)
+                    .filter(|path| registries.includes_registry_path(path))
+                    .cloned(),
+            );
+        } else {
+            dirs.push(ccd.registry_sources.clone());
+        }
+    }
+    if selection.contains(&Component::GitRepos) && registries.includes_git() {
+        dirs.push(ccd.git_checkouts.clone());
+    }
+    dirs
+}
+
+/// with --keep-days (or `autoclean.keep_days` of the config file), keep the crate sources and git
+/// checkouts that were used in the last `keep_days` days: replace the directories that contain
+/// them by the items inside that were not used recently
+pub(crate) fn protect_recently_used(
+    dirs: Vec<PathBuf>,
+    registry_sources_caches: &mut registry_sources::RegistrySourceCaches,
+    checkouts_cache: &mut git_checkouts::GitCheckoutCache,
+    keep_days: u64,
+) -> Vec<PathBuf> {
+    if keep_days == 0 {
+        return dirs;
+    }
+    let cutoff = SystemTime::now()
+        .checked_sub(Duration::from_secs(keep_days.saturating_mul(24 * 60 * 60)))
+        .unwrap_or(SystemTime::UNIX_EPOCH);
+
+    let mut items = registry_sources_caches.items().to_vec();
+    items.extend(checkouts_cache.items().iter().cloned());
+    let recent = items
+        .iter()
+        .filter(|item| dirs.iter().any(|dir| item.starts_with(dir)))
+        .filter(|item| {
+            get_last_access_of_item(item).map_or(false, |last_access| last_access > cutoff)
+        })
+        .cloned()
+        .collect::<Vec<PathBuf>>();
+    if recent.is_empty() {
+        return dirs;
+    }
+
+    println!(
+        "Keeping {} crate sources and git checkouts that were used in the last {keep_days} days\n",
+        recent.len()
+    );
+    without_modified(dirs, &items, &recent)
+}
+
+/// the path of a component inside the cargo home
+pub(crate) fn component_path<'a>(component: &Component, ccd: &'a CargoCachePaths) -> &'a Path {
+    match component {
+        Component::GitDB => &ccd.git_repos_bare,
+        Component::GitRepos => &ccd.git_checkouts,
+        Component::RegistrySources => &ccd.registry_sources,
+        Component::RegistryCrateCache => &ccd.registry_pkg_cache,
+        Component::RegistryIndex => &ccd.registry_index,
+    }
+}
+
+/// make sure that a path we are going to remove is on the same device as its parent directory
+/// if, for example `registry/src` is a symlink to or a mountpoint of another filesystem, we
+/// most likely do not want to delete it
+pub(crate) fn check_same_device(path: &Path) -> Result<(), Error> {
+    let parent = match path.parent() {
+        Some(parent) => parent,
+        None => return Ok(()),
+    };
+    // if either of the paths does not exist, there is nothing we could accidentally delete
+    match (device_id(path), device_id(parent)) {
+        (Some(path_dev), Some(parent_dev)) if path_dev != parent_dev => {
+            Err(Error::CrossDeviceDeletion(path.to_path_buf()))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// refuse to remove a directory if anything below it is on another device, like a mount point
+/// `device_of` is `device_id()` except in tests
+fn check_single_device(dir: &Path, device_of: fn(&Path) -> Option<u64>) -> std::io::Result<()> {
+    let device = match device_of(dir) {
+        Some(device) => device,
+        None => return Ok(()),
+    };
+    let other_device = walk(dir)
+        .filter(|entry| entry.file_type().is_dir())
+        .find(|entry| device_of(entry.path()).map_or(false, |dev| dev != device));
+    match other_device {
+        Some(entry) => Err(std::io::Error::new(
+            std::io::ErrorKind::Other,
+            format!(
+                "\"{}\" is on another device than \"{}\"",
+                entry.path().display(),
+                dir.display()
+            ),
+        )),
+        None => Ok(()),
+    }
+}
+
+/// get the id of the device a path resolves to
+#[cfg(unix)]
+fn device_id(path: &Path) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    // use fs::metadata here so that we follow symlinks and get the device the path resolves to
+    fs::metadata(path).ok().map(|metadata| metadata.dev())
+}
+
+#[cfg(not(unix))]
+fn device_id(_path: &Path) -> Option<u64> {
+    // not supported on this platform
+    None
+}
+
 /// remove a file with a default "removing: {file}" message
 pub(crate) fn remove_with_default_message(
     dir: &Path,
@@ -270,6 +921,29 @@ pub(crate) fn remove_with_default_message(
     );
 }
 
+/// remove the paths with the default message, then print and record in the history how much was
+/// freed, a dry run prints what it would free instead; used by the commands that remove single
+/// items and exit right after, like "remove <crate>", "git rm-checkout" and "query --delete"
+pub(crate) fn remove_and_record_freed<P: AsRef<Path>>(paths: &[P], dry_run: bool) {
+    let mut freed = 0;
+    for path in paths {
+        let path = path.as_ref();
+        let size = size_of_path(path);
+        let mut size_changed = false;
+        remove_with_default_message(path, dry_run, &mut size_changed, Some(size));
+        if size_changed {
+            freed += size;
+        }
+    }
+    if freed > 0 {
+        history::record_freed(freed);
+        println!("Freed {}.", freed.format_size(DECIMAL));
+    }
+    if dry_run {
+        let _ = would_free::print();
+    }
+}
+
 /// remove a file with a custom message
 pub(crate) fn remove_file(
     // path of the file to be deleted
@@ -285,7 +959,15 @@ pub(crate) fn remove_file(
     // size of the file according to cache
     total_size_from_cache: Option<u64>,
 ) {
+    // never touch anything listed in the .cargocacheignore,
+    // once we were cancelled, do not start removing anything else
+    if is_ignored(path) || is_cancelled() {
+        return;
+    }
+
     if dry_run {
+        // the end of the run shows what all of this would free
+        would_free::record(path, total_size_from_cache);
         match dry_run_msg {
             DryRunMessage::Custom(msg) => {
                 println!("{msg}");
@@ -313,24 +995,227 @@ pub(crate) fn remove_file(
             println!("{msg}");
         }
 
-        if path.is_file() && fs::remove_file(path).is_err() {
-            eprintln!("Warning: failed to remove file \"{}\".", path.display());
-        } else {
-            *size_changed = true;
+        let kind = classify_path(path);
+        // --stats: links are removed without looking at what they point to, do not count it
+        let measurement = match kind {
+            PathKind::File | PathKind::Directory => Measurement::start(path),
+            PathKind::Link | PathKind::Missing => None,
+        };
+        // the journal records how much every removal freed, links free nothing
+        #[cfg(feature = "json")]
+        let journal_size = journal::enabled().then(|| match kind {
+            PathKind::File | PathKind::Directory => {
+                total_size_from_cache.unwrap_or_else(|| journal::size_of(path))
+            }
+            PathKind::Link | PathKind::Missing => 0,
+        });
+        match remove_classified(path, kind) {
+            Ok(()) => {
+                if kind != PathKind::Missing {
+                    *size_changed = true;
+                    #[cfg(feature = "json")]
+                    if let Some(size) = journal_size {
+                        journal::record_removal(path, size);
+                    }
+                }
+                if let Some(measurement) = measurement {
+                    measurement.finish();
+                }
+            }
+            Err(error) => match kind {
+                PathKind::Link => {
+                    eprintln!("Warning: failed to remove link \"{}\".", path.display());
+                }
+                PathKind::File => {
+                    eprintln!("Warning: failed to remove file \"{}\".", path.display());
+                }
+                PathKind::Directory | PathKind::Missing => {
+                    eprintln!(
+                        "Warning: failed to recursively remove directory \"{}\".",
+                        path.display()
+                    );
+                    eprintln!("error: {error:?}");
+                }
+            },
         }
+    }
+}
 
-        if path.is_dir() {
-            if let Err(error) = remove_dir_all::remove_dir_all(path) {
-                eprintln!(
-                    "Warning: failed to recursively remove directory \"{}\".",
-                    path.display()
-                );
-                eprintln!("error: {error:?}");
-            } else {
-                *size_changed = true;
+/// remove a file, directory or link without printing anything
+#[allow(dead_code)] // only used by the library, see ops.rs
+pub(crate) fn remove_path(path: &Path) -> std::io::Result<()> {
+    remove_classified(path, classify_path(path))
+}
+
+fn remove_classified(path: &Path, kind: PathKind) -> std::io::Result<()> {
+    // the roots of the components are checked before anything is removed, but a directory
+    // could also be mounted somewhere inside of what we remove
+    if kind == PathKind::Directory {
+        check_single_device(path, device_id)?;
+    }
+    let long_path = extended_length_path(path);
+    match kind {
+        // --use-system-trash: the trash moves links and not what they point to
+        PathKind::Link | PathKind::File if system_trash::enabled() => {
+            retry::removal(|| system_trash::move_to_trash(path))
+        }
+        // never follow links (symlinks or windows junctions), they may point outside of
+        // the cache and we do not want to delete whatever is behind them
+        PathKind::Link => retry::removal(|| remove_link(&long_path)),
+        PathKind::File => retry::removal(|| fs::remove_file(&long_path)),
+        // remove_dir_all does not traverse into links it encounters but deletes the links themselves
+        PathKind::Directory if ignores_anything() => remove_dir_except_ignored(path),
+        PathKind::Directory if system_trash::enabled() => {
+            retry::removal(|| system_trash::move_to_trash(path))
+        }
+        PathKind::Directory => retry::removal(|| remove_dir_all::remove_dir_all(&long_path)),
+        PathKind::Missing => Ok(()),
+    }
+}
+
+/// recursively remove a directory but keep everything that is listed in the .cargocacheignore
+/// directories that still contain ignored paths are kept as well
+fn remove_dir_except_ignored(dir: &Path) -> std::io::Result<()> {
+    let long_dir = extended_length_path(dir);
+    for entry in retry::read_dir(&long_dir)? {
+        // the .cargocacheignore lists the paths the way the user knows them, not as extended-length paths
+        let path = dir.join(entry?.file_name());
+        if is_ignored(&path) {
+            continue;
+        }
+        match classify_path(&path) {
+            PathKind::Directory => remove_dir_except_ignored(&path)?,
+            kind @ (PathKind::Link | PathKind::File) => remove_classified(&path, kind)?,
+            PathKind::Missing => {}
+        }
+    }
+
+    // if something inside was ignored, the directory is not empty and has to stay
+    // an empty directory is not worth a trip to the trash
+    if retry::read_dir(&long_dir)?.next().is_none() {
+        retry::removal(|| fs::remove_dir(&long_dir))?;
+    }
+    Ok(())
+}
+
+/// the kind of a path we are about to delete, links are never followed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PathKind {
+    /// a regular file
+    File,
+    /// a real directory (not a link to one)
+    Directory,
+    /// a symlink or, on windows, any other reparse point such as a junction
+    Link,
+    /// the path does not exist (anymore)
+    Missing,
+}
+
+/// check what kind of filesystem entry a path is, without following links
+pub(crate) fn classify_path(path: &Path) -> PathKind {
+    let metadata = match fs::symlink_metadata(extended_length_path(path)) {
+        Ok(metadata) => metadata,
+        Err(_) => return PathKind::Missing,
+    };
+
+    if metadata.file_type().is_symlink() || is_reparse_point(&metadata) {
+        PathKind::Link
+    } else if metadata.is_dir() {
+        PathKind::Directory
+    } else {
+        PathKind::File
+    }
+}
+
+/// junctions are not reported as symlinks by std, check the reparse point attribute directly
+#[cfg(windows)]
+fn is_reparse_point(metadata: &fs::Metadata) -> bool {
+    use std::os::windows::fs::MetadataExt;
+    const FILE_ATTRIBUTE_REPARSE_POINT: u32 = 0x400;
+    metadata.file_attributes() & FILE_ATTRIBUTE_REPARSE_POINT != 0
+}
+
+#[cfg(not(windows))]
+fn is_reparse_point(_metadata: &fs::Metadata) -> bool {
+    false
+}
+
+/// the path in a form that windows accepts even if it is longer than 260 characters (MAX_PATH):
+/// "\\?\C:\..." or "\\?\UNC\server\share\...", deeply nested git checkouts easily exceed that
+/// windows does not normalize such paths, so "." and ".." are resolved here the way it would
+#[cfg(windows)]
+fn extended_length_path(path: &Path) -> Cow<'_, Path> {
+    use std::ffi::OsString;
+    use std::path::{Component, Prefix};
+
+    let absolute = if path.is_absolute() {
+        Cow::Borrowed(path)
+    } else {
+        match std::env::current_dir() {
+            Ok(cwd) => Cow::Owned(cwd.join(path)),
+            Err(_) => return Cow::Borrowed(path),
+        }
+    };
+    let mut components = absolute.components();
+    let mut extended = match components.next() {
+        Some(Component::Prefix(prefix)) => match prefix.kind() {
+            Prefix::Disk(letter) => PathBuf::from(format!(r"\\?\{}:\", char::from(letter))),
+            Prefix::UNC(server, share) => {
+                let mut unc = OsString::from(r"\\?\UNC\");
+                unc.push(server);
+                unc.push(r"\");
+                unc.push(share);
+                unc.push(r"\");
+                PathBuf::from(unc)
             }
+            // already an extended-length path or a device path
+            _ => return Cow::Borrowed(path),
+        },
+        _ => return Cow::Borrowed(path),
+    };
+    for component in components {
+        match component {
+            Component::Normal(name) => extended.push(name),
+            Component::ParentDir => {
+                let _ = extended.pop();
+            }
+            Component::Prefix(_) | Component::RootDir | Component::CurDir => {}
         }
     }
+    Cow::Owned(extended)
+}
+
+/// other platforms have no such limit
+#[cfg(not(windows))]
+fn extended_length_path(path: &Path) -> Cow<'_, Path> {
+    Cow::Borrowed(path)
+}
+
+/// remove a link itself and not what it points to
+fn remove_link(path: &Path) -> std::io::Result<()> {
+    // on windows, links to directories (and junctions) have to be removed via remove_dir
+    // which removes the link but does not touch the target
+    // ask the link itself, its target may be gone or a file behind a link to a directory
+    if is_directory_link(&fs::symlink_metadata(path)?) {
+        fs::remove_dir(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+/// whether a link (or junction) was created as a link to a directory
+/// `FileTypeExt::is_symlink_dir()` needs rust 1.64, the attribute tells the same
+#[cfg(windows)]
+fn is_directory_link(metadata: &fs::Metadata) -> bool {
+    use std::os::windows::fs::MetadataExt;
+    const FILE_ATTRIBUTE_DIRECTORY: u32 = 0x10;
+    metadata.file_attributes() & FILE_ATTRIBUTE_DIRECTORY != 0
+}
+
+/// elsewhere all links are removed like files
+#[cfg(not(windows))]
+fn is_directory_link(_metadata: &fs::Metadata) -> bool {
+    false
 }
 
 #[cfg(test)]
@@ -353,4 +1238,237 @@ mod libtests {
         assert_eq!(name2, "cargo-cache");
         assert_eq!(version2, "0.4.3");
     }
+
+    #[test]
+    fn prune_order() {
+        let tmp = tempfile::tempdir().unwrap();
+        // the older version was downloaded last
+        let paths = ["foo-1.1.0.crate", "foo-1.0.0.crate", "bar-0.1.0.crate"]
+            .iter()
+            .map(|name| {
+                let path = tmp.path().join(name);
+                fs::write(&path, "").unwrap();
+                std::thread::sleep(Duration::from_millis(20));
+                path
+            })
+            .collect::<Vec<_>>();
+        let crates = stat_crates(paths);
+        let names = |list: &[RegistryCrate]| {
+            list.iter()
+                .map(|krate| {
+                    krate
+                        .path
+                        .file_name()
+                        .unwrap()
+                        .to_str()
+                        .unwrap()
+                        .to_string()
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let mut by_version = crates.clone();
+        sort_for_pruning(&mut by_version, KeepBy::Version);
+        assert_eq!(
+            names(&by_version),
+            vec!["foo-1.1.0.crate", "foo-1.0.0.crate", "bar-0.1.0.crate"]
+        );
+
+        let mut by_mtime = crates;
+        sort_for_pruning(&mut by_mtime, KeepBy::Mtime);
+        assert_eq!(
+            names(&by_mtime),
+            vec!["bar-0.1.0.crate", "foo-1.0.0.crate", "foo-1.1.0.crate"]
+        );
+    }
+
+    #[test]
+    fn prune_keeps_n_versions() {
+        let crates = [
+            "foo-1.2.0.crate",
+            "foo-1.1.0.crate",
+            "foo-1.0.0.crate",
+            "bar-0.1.0.crate",
+        ]
+        .iter()
+        .map(|name| RegistryCrate {
+            path: PathBuf::from(name),
+            size: 1,
+            modified: None,
+        })
+        .collect::<Vec<_>>();
+        let names = |list: Vec<&RegistryCrate>| {
+            list.iter()
+                .map(|krate| krate.path.display().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            names(crates_to_prune(&crates, 1).unwrap()),
+            vec!["foo-1.1.0.crate", "foo-1.0.0.crate"]
+        );
+        assert_eq!(
+            names(crates_to_prune(&crates, 2).unwrap()),
+            vec!["foo-1.0.0.crate"]
+        );
+        assert_eq!(crates_to_prune(&crates, 0).unwrap().len(), 4);
+    }
+
+    #[test]
+    fn freed_table() {
+        let freed = vec![
+            (Component::GitDB, 12_345_678, 3),
+            (Component::RegistrySources, 0, 0),
+        ];
+        assert_eq!(
+            freed_per_component(&freed),
+            "Component        Items Freed\ngit-db           3     12.35 MB\nregistry-sources 0     0 B\n"
+        );
+    }
+
+    #[test]
+    fn same_device() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().join("dir");
+        fs::create_dir(&dir).unwrap();
+
+        assert!(check_same_device(&dir).is_ok());
+        // paths that do not exist can not be on another device
+        assert!(check_same_device(&tmp.path().join("does_not_exist")).is_ok());
+        // neither can the root
+        assert!(check_same_device(Path::new("/")).is_ok());
+    }
+
+    #[test]
+    fn mount_below() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().join("registry").join("src");
+        let mount = dir.join("index.crates.io-6f17d22bba15001f").join("mnt");
+        fs::create_dir_all(mount.join("data")).unwrap();
+        fs::write(mount.join("data").join("file"), "content").unwrap();
+
+        assert!(check_single_device(&dir, device_id).is_ok());
+        // pretend that "mnt" is a mount of another filesystem
+        let mounted = |path: &Path| {
+            Some(if path.components().any(|c| c.as_os_str() == "mnt") {
+                2
+            } else {
+                1
+            })
+        };
+        let error = check_single_device(&dir, mounted).unwrap_err();
+        assert!(error.to_string().contains("mnt\" is on another device"));
+        // the mount itself may go
+        assert!(check_single_device(&mount, mounted).is_ok());
+        // nothing to compare to
+        assert!(check_single_device(&dir, |_| None).is_ok());
+    }
+
+    /// create a link at `link` that points to the directory `target`
+    fn link_dir(target: &Path, link: &Path) {
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(target, link).unwrap();
+        #[cfg(windows)]
+        {
+            // create a junction, these can be created without special privileges
+            let status = std::process::Command::new("cmd")
+                .args(["/C", "mklink", "/J"])
+                .arg(link)
+                .arg(target)
+                .status()
+                .unwrap();
+            assert!(status.success());
+        }
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn extended_length_paths() {
+        assert_eq!(
+            extended_length_path(Path::new(r"C:\Users\me\.cargo\git\..\registry\.\src")),
+            Path::new(r"\\?\C:\Users\me\.cargo\registry\src")
+        );
+        assert_eq!(
+            extended_length_path(Path::new(r"\\server\share\.cargo\git")),
+            Path::new(r"\\?\UNC\server\share\.cargo\git")
+        );
+        // already extended
+        assert_eq!(
+            extended_length_path(Path::new(r"\\?\C:\.cargo\git")),
+            Path::new(r"\\?\C:\.cargo\git")
+        );
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn extended_length_paths() {
+        assert_eq!(
+            extended_length_path(Path::new("/home/me/.cargo/git/../registry")),
+            Path::new("/home/me/.cargo/git/../registry")
+        );
+    }
+
+    #[test]
+    fn links_are_not_followed() {
+        let tmp = tempfile::tempdir().unwrap();
+        let outside = tmp.path().join("outside");
+        let outside_file = outside.join("precious.txt");
+        fs::create_dir(&outside).unwrap();
+        fs::write(&outside_file, "do not delete").unwrap();
+
+        // a cache directory containing a link that points outside of the cache
+        let cache = tmp.path().join("cache");
+        fs::create_dir(&cache).unwrap();
+        fs::write(cache.join("file"), "bla").unwrap();
+        let link = cache.join("link");
+        link_dir(&outside, &link);
+
+        assert_eq!(classify_path(&cache), PathKind::Directory);
+        assert_eq!(classify_path(&link), PathKind::Link);
+        assert_eq!(classify_path(&outside_file), PathKind::File);
+
+        let mut size_changed = false;
+        // removing the link itself must not touch the target
+        remove_file(
+            &link,
+            false,
+            &mut size_changed,
+            None,
+            &DryRunMessage::None,
+            None,
+        );
+        assert!(size_changed);
+        assert_eq!(classify_path(&link), PathKind::Missing);
+        assert!(outside_file.is_file());
+
+        // recursively removing a directory that contains a link must not touch the target either
+        link_dir(&outside, &link);
+        remove_file(
+            &cache,
+            false,
+            &mut size_changed,
+            None,
+            &DryRunMessage::None,
+            None,
+        );
+        assert_eq!(classify_path(&cache), PathKind::Missing);
+        assert!(outside_file.is_file());
+
+        // a link whose target is gone is still removed
+        let gone = tmp.path().join("gone");
+        fs::create_dir(&gone).unwrap();
+        let dangling = tmp.path().join("dangling");
+        link_dir(&gone, &dangling);
+        fs::remove_dir(&gone).unwrap();
+        assert_eq!(classify_path(&dangling), PathKind::Link);
+        remove_file(
+            &dangling,
+            false,
+            &mut size_changed,
+            None,
+            &DryRunMessage::None,
+            None,
+        );
+        assert_eq!(classify_path(&dangling), PathKind::Missing);
+    }
 }
@@ -0,0 +1,125 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// the end of a run: whatever operations ran, the summary of how the size of the cache changed
+// is printed by the RunReport and only by it, once, after all of them finished.
+// Operations record what they did and their errors are reported after the summary.
+
+use std::process;
+
+use crate::cache::*;
+use crate::dirsizes::DirSizes;
+use crate::error_report;
+use crate::library::{CargoCachePaths, Error};
+use crate::throughput;
+use crate::would_free;
+
+/// collects what the operations of a run did and prints the summary at the end
+#[derive(Debug, Default)]
+pub(crate) struct RunReport {
+    /// whether something was removed, or would have been removed in a dry run
+    pub(crate) size_changed: bool,
+    /// an operation ran that always shows the size difference, even in a dry run
+    summary_requested: bool,
+    /// the first error of an operation, reported after the summary
+    error: Option<Error>,
+}
+
+impl RunReport {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// show the size difference at the end of the run, even if nothing changed
+    pub(crate) fn request_summary(&mut self) {
+        self.summary_requested = true;
+    }
+
+    /// an operation that shows the size difference finished with `result`
+    pub(crate) fn record(&mut self, result: Result<(), Error>) {
+        self.request_summary();
+        if let Err(error) = result {
+            let _ = self.error.get_or_insert(error);
+        }
+    }
+
+    /// whether the size difference is printed at the end of the run
+    fn wants_summary(&self, dry_run: bool) -> bool {
+        self.summary_requested || (self.size_changed && !dry_run)
+    }
+
+    /// print the size difference if needed, then terminate with the recorded error, if any
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn finish(
+        self,
+        dry_run: bool,
+        dir_sizes_original: &DirSizes<'_>,
+        cargo_cache: &CargoCachePaths,
+        bin_cache: &mut bin::BinaryCache,
+        checkouts_cache: &mut git_checkouts::GitCheckoutCache,
+        bare_repos_cache: &mut git_bare_repos::GitRepoCache,
+        registry_pkgs_cache: &mut registry_pkg_cache::RegistryPkgCaches,
+        registry_index_caches: &mut registry_index::RegistryIndicesCache,
+        registry_sources_caches: &mut registry_sources::RegistrySourceCaches,
+    ) {
+        // nothing changed in a dry run, show what it would have freed instead
+        if !would_free::print() && self.wants_summary(dry_run) {
+            DirSizes::print_size_difference(
+                dir_sizes_original,
+                cargo_cache,
+                bin_cache,
+                checkouts_cache,
+                bare_repos_cache,
+                registry_pkgs_cache,
+                registry_index_caches,
+                registry_sources_caches,
+            );
+        }
+        // --stats
+        if !dry_run {
+            throughput::print();
+        }
+
+        if let Some(error) = self.error {
+            error_report::print(&error);
+            process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod report_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn summary_needed() {
+        let mut report = RunReport::new();
+        assert_eq!(report.wants_summary(false), false);
+
+        // removals are only summarized if they happened
+        report.size_changed = true;
+        assert_eq!(report.wants_summary(false), true);
+        assert_eq!(report.wants_summary(true), false);
+
+        // operations that show the difference do so in dry runs as well
+        let mut operation = RunReport::new();
+        operation.record(Ok(()));
+        assert_eq!(operation.wants_summary(true), true);
+        assert!(operation.error.is_none());
+    }
+
+    #[test]
+    fn first_error_is_kept() {
+        let mut report = RunReport::new();
+        report.record(Err(Error::GetCargoHomeFailed));
+        report.record(Err(Error::MalformedPackageName("foo".into())));
+        assert!(matches!(report.error, Some(Error::GetCargoHomeFailed)));
+    }
+}
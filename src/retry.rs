@@ -0,0 +1,272 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// retry filesystem operations that failed with a transient error
+// On network filesystems (NFS, SMB) stat()ing or removing files occasionally fails with errors
+// such as ESTALE or EBUSY that go away if we just try again a little later.
+// The number of retries and the initial backoff (which doubles after every attempt) can be set
+// via --retries and --retry-backoff, errors are only surfaced once all retries are exhausted.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+use once_cell::sync::OnceCell;
+
+use crate::library::Error;
+
+/// the retry policy of this run, set up once in `main()`
+static RETRY_POLICY: OnceCell<RetryPolicy> = OnceCell::new();
+
+/// how often and how long to wait before retrying a failed filesystem operation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct RetryPolicy {
+    /// number of retries after the first attempt failed
+    retries: u32,
+    /// time to wait before the first retry, doubled for every further retry
+    backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            retries: 3,
+            backoff: Duration::from_millis(100),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// get the policy from --retries <N> and --retry-backoff <MILLISECONDS>
+    pub(crate) fn new(retries: Option<&str>, backoff: Option<&str>) -> Result<Self, Error> {
+        let default = Self::default();
+
+        let retries = match retries {
+            Some(retries) => retries
+                .parse()
+                .map_err(|_| Error::InvalidRetryPolicy(retries.to_string()))?,
+            None => default.retries,
+        };
+        let backoff = match backoff {
+            Some(backoff) => Duration::from_millis(
+                backoff
+                    .parse()
+                    .map_err(|_| Error::InvalidRetryPolicy(backoff.to_string()))?,
+            ),
+            None => default.backoff,
+        };
+
+        Ok(Self { retries, backoff })
+    }
+
+    /// run an operation, retrying it as long as it fails with transient errors
+    pub(crate) fn run<T>(&self, mut operation: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+        let mut backoff = self.backoff;
+        let mut retries_left = self.retries;
+        loop {
+            match operation() {
+                Err(error) if retries_left > 0 && is_transient(&error) => {
+                    thread::sleep(backoff);
+                    backoff *= 2;
+                    retries_left -= 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// set the retry policy, needs to be called before any filesystem operations happen
+pub(crate) fn init(policy: RetryPolicy) {
+    let _ = RETRY_POLICY.set(policy);
+}
+
+/// run an operation with the configured retry policy (or the default one if none was configured)
+pub(crate) fn with_retries<T>(operation: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+    RETRY_POLICY
+        .get()
+        .copied()
+        .unwrap_or_default()
+        .run(operation)
+}
+
+/// run a removal with retries
+/// if an attempt failed but did actually remove the path, a retry reports the path as missing;
+/// that is not an error since the path is gone
+pub(crate) fn removal(mut operation: impl FnMut() -> io::Result<()>) -> io::Result<()> {
+    let mut attempts = 0;
+    with_retries(|| {
+        attempts += 1;
+        match operation() {
+            Err(error) if attempts > 1 && error.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    })
+}
+
+/// `fs::metadata` with retries
+pub(crate) fn metadata(path: &Path) -> io::Result<fs::Metadata> {
+    with_retries(|| fs::metadata(path))
+}
+
+/// `fs::read_dir` with retries
+pub(crate) fn read_dir(path: &Path) -> io::Result<fs::ReadDir> {
+    with_retries(|| fs::read_dir(path))
+}
+
+/// the paths of the entries of a directory, sorted by name
+/// `fs::read_dir` returns the entries in the order of the file system, which differs between file
+/// systems, machines and even runs; the caches list their items through this so that every listing
+/// and removal happens in the same order everywhere
+pub(crate) fn read_dir_sorted(path: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut paths = read_dir(path)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<PathBuf>>>()?;
+    paths.sort();
+    Ok(paths)
+}
+
+// raw os errors that are worth retrying
+// std only has stable ErrorKinds for these since rust 1.83 which is newer than our MSRV
+#[cfg(target_os = "linux")]
+const TRANSIENT_OS_ERRORS: &[i32] = &[
+    11,  // EAGAIN
+    16,  // EBUSY
+    116, // ESTALE
+];
+#[cfg(all(unix, not(target_os = "linux")))]
+const TRANSIENT_OS_ERRORS: &[i32] = &[
+    16, // EBUSY
+    35, // EAGAIN
+    70, // ESTALE
+];
+#[cfg(windows)]
+const TRANSIENT_OS_ERRORS: &[i32] = &[
+    32, // ERROR_SHARING_VIOLATION
+    33, // ERROR_LOCK_VIOLATION
+];
+#[cfg(not(any(unix, windows)))]
+const TRANSIENT_OS_ERRORS: &[i32] = &[];
+
+/// whether an error might go away if we try again
+fn is_transient(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    ) || error
+        .raw_os_error()
+        .map_or(false, |code| TRANSIENT_OS_ERRORS.contains(&code))
+}
+
+#[cfg(test)]
+mod retry_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn policy_from_args() {
+        assert_eq!(
+            RetryPolicy::new(None, None).unwrap(),
+            RetryPolicy::default()
+        );
+        assert_eq!(
+            RetryPolicy::new(Some("5"), Some("10")).unwrap(),
+            RetryPolicy {
+                retries: 5,
+                backoff: Duration::from_millis(10)
+            }
+        );
+        assert!(RetryPolicy::new(Some("-1"), None).is_err());
+        assert!(RetryPolicy::new(None, Some("1s")).is_err());
+    }
+
+    #[test]
+    fn retries_transient_errors() {
+        let policy = RetryPolicy {
+            retries: 2,
+            backoff: Duration::from_millis(1),
+        };
+
+        // succeeds on the last retry
+        let mut attempts = 0;
+        let result = policy.run(|| {
+            attempts += 1;
+            if attempts < 3 {
+                Err(io::Error::from(io::ErrorKind::Interrupted))
+            } else {
+                Ok(attempts)
+            }
+        });
+        assert_eq!(result.unwrap(), 3);
+    }
+
+    #[test]
+    fn retries_exhausted() {
+        let policy = RetryPolicy {
+            retries: 2,
+            backoff: Duration::from_millis(1),
+        };
+
+        let mut attempts = 0;
+        let result: io::Result<()> = policy.run(|| {
+            attempts += 1;
+            Err(io::Error::from(io::ErrorKind::TimedOut))
+        });
+        assert!(result.is_err());
+        assert_eq!(attempts, 3);
+    }
+
+    #[test]
+    fn permanent_errors_are_not_retried() {
+        let mut attempts = 0;
+        let result: io::Result<()> = RetryPolicy::default().run(|| {
+            attempts += 1;
+            Err(io::Error::from(io::ErrorKind::PermissionDenied))
+        });
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn removal_that_succeeded_is_not_an_error() {
+        let mut attempts = 0;
+        let result = removal(|| {
+            attempts += 1;
+            if attempts == 1 {
+                Err(io::Error::from(io::ErrorKind::Interrupted))
+            } else {
+                Err(io::Error::from(io::ErrorKind::NotFound))
+            }
+        });
+        assert!(result.is_ok());
+
+        // a path that was missing right away is still an error
+        assert!(removal(|| Err(io::Error::from(io::ErrorKind::NotFound))).is_err());
+    }
+
+    #[test]
+    fn sorted_entries() {
+        let tmp = tempfile::tempdir().unwrap();
+        for name in ["serde-1.0.0", "anyhow-1.0.0", "Zlib-1.0.0", "serde-0.9.0"] {
+            fs::create_dir(tmp.path().join(name)).unwrap();
+        }
+        let names = read_dir_sorted(tmp.path())
+            .unwrap()
+            .iter()
+            .map(|path| path.file_name().unwrap().to_str().unwrap().to_string())
+            .collect::<Vec<String>>();
+        assert_eq!(
+            names,
+            vec!["Zlib-1.0.0", "anyhow-1.0.0", "serde-0.9.0", "serde-1.0.0"]
+        );
+        assert!(read_dir_sorted(&tmp.path().join("missing")).is_err());
+    }
+}
@@ -0,0 +1,777 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// "cargo cache --output-schema" prints a JSON Schema of everything cargo-cache prints with
+// --output-format json and --json-errors, so scripts can validate what they parse. It also covers
+// the json cargo-cache writes elsewhere: the metrics, the verify report, the journal records and
+// what the --approve-with command gets on stdin.
+// The outputs are built with json!() where they are printed, the tests below run the binary and
+// the tests of the other modules check what they build against the schema, so the two can not
+// drift apart.
+// Bump SCHEMA_VERSION when a key is renamed or removed, adding a key is not a breaking change.
+
+use serde_json::{json, Map, Value};
+
+/// version of the json outputs, part of the schema
+const SCHEMA_VERSION: u64 = 1;
+
+/// sizes are in bytes
+fn size() -> Value {
+    json!({ "type": "integer", "minimum": 0 })
+}
+
+/// seconds since the epoch, null if not known
+fn seconds_or_null() -> Value {
+    json!({ "type": ["integer", "null"], "minimum": 0 })
+}
+
+/// an object with exactly these properties, the `required` ones are always printed
+fn object(properties: Value, required: &[&str]) -> Value {
+    let mut object = json!({
+        "type": "object",
+        "required": required,
+        "additionalProperties": false,
+    });
+    object["properties"] = properties;
+    object
+}
+
+fn count_and_size() -> Value {
+    object(
+        json!({ "count": size(), "size": size() }),
+        &["count", "size"],
+    )
+}
+
+/// the properties of the summary, "registry" adds the list of registries to them
+fn summary_properties() -> Map<String, Value> {
+    let crates = || {
+        object(
+            json!({ "count": size(), "crates": size(), "size": size() }),
+            &["count", "crates", "size"],
+        )
+    };
+    let properties = json!({
+        "cargo_home": { "type": "string" },
+        "components": {
+            "description": "the components of --components, only printed if restricted",
+            "type": "string",
+        },
+        "total_size": size(),
+        "binaries": count_and_size(),
+        "registry": object(
+            json!({
+                "size": size(),
+                "indices": object(
+                    json!({ "count": size(), "sparse": size(), "size": size() }),
+                    &["count", "sparse", "size"],
+                ),
+                "crate_archives": crates(),
+                "crate_sources": crates(),
+            }),
+            &["size"],
+        ),
+        "git": object(
+            json!({
+                "size": size(),
+                "bare_repos": count_and_size(),
+                "checkouts": count_and_size(),
+            }),
+            &["size"],
+        ),
+        "other_files": object(
+            json!({
+                "count": size(),
+                "size": size(),
+                "files": {
+                    "type": "array",
+                    "items": object(
+                        json!({ "name": { "type": "string" }, "size": size() }),
+                        &["name", "size"],
+                    ),
+                },
+            }),
+            &["count", "size", "files"],
+        ),
+    });
+    match properties {
+        Value::Object(properties) => properties,
+        _ => unreachable!(),
+    }
+}
+
+/// "cargo cache --output-format json"
+fn summary() -> Value {
+    object(
+        Value::Object(summary_properties()),
+        &["cargo_home", "total_size"],
+    )
+}
+
+/// "cargo cache registry --output-format json"
+fn registry() -> Value {
+    let count_and_size_or_null = json!({
+        "anyOf": [count_and_size(), { "type": "null" }],
+    });
+    let mut properties = summary_properties();
+    let _ = properties.insert(
+        "registries".into(),
+        json!({
+            "type": "array",
+            "items": object(
+                json!({
+                    "name": { "type": "string" },
+                    "configured_name": { "type": ["string", "null"] },
+                    "directory": { "type": "string" },
+                    "size": size(),
+                    "empty": { "type": "boolean" },
+                    "index_size": size(),
+                    "crate_archives": count_and_size_or_null,
+                    "crate_sources": count_and_size_or_null,
+                }),
+                &[
+                    "name",
+                    "configured_name",
+                    "directory",
+                    "size",
+                    "empty",
+                    "index_size",
+                    "crate_archives",
+                    "crate_sources",
+                ],
+            ),
+        }),
+    );
+    object(
+        Value::Object(properties),
+        &["cargo_home", "total_size", "registries"],
+    )
+}
+
+/// "cargo cache --info --output-format json"
+fn info() -> Value {
+    let dir = || {
+        object(
+            json!({ "path": { "type": "string" }, "size": size() }),
+            &["path", "size"],
+        )
+    };
+    object(
+        json!({
+            "cargo_home": { "type": "string" },
+            "cargo_home_from_env": { "type": "boolean" },
+            "cargo_home_from_flag": { "type": "boolean" },
+            "total_size": size(),
+            "binaries": object(
+                json!({ "path": { "type": "string" }, "count": size(), "size": size() }),
+                &["path", "count", "size"],
+            ),
+            "registry": dir(),
+            "registry_index": dir(),
+            "registry_crate_cache": dir(),
+            "registry_sources": dir(),
+            "git_db": dir(),
+            "git_checkouts": dir(),
+        }),
+        &[
+            "cargo_home",
+            "cargo_home_from_env",
+            "cargo_home_from_flag",
+            "total_size",
+            "binaries",
+            "registry",
+            "registry_index",
+            "registry_crate_cache",
+            "registry_sources",
+            "git_db",
+            "git_checkouts",
+        ],
+    )
+}
+
+/// "cargo cache --top-cache-items --output-format json", a component is only printed if it exists
+fn top_cache_items() -> Value {
+    let component = |item: Value| {
+        object(
+            json!({
+                "path": { "type": "string" },
+                "total_size": size(),
+                "items": { "type": "array", "items": item },
+            }),
+            &["path", "total_size", "items"],
+        )
+    };
+    // all versions of a crate or all checkouts of a repo are grouped under one name
+    let group = || {
+        component(object(
+            json!({
+                "name": { "type": "string" },
+                "count": size(),
+                "total_size": size(),
+                "last_used": seconds_or_null(),
+            }),
+            &["name", "count", "total_size", "last_used"],
+        ))
+    };
+    let size_or_null = json!({ "type": ["integer", "null"], "minimum": 0 });
+    object(
+        json!({
+            "binaries": component(object(
+                json!({ "name": { "type": "string" }, "size": size() }),
+                &["name", "size"],
+            )),
+            "registry_sources": group(),
+            "registry_crate_cache": group(),
+            "registry_index": component(object(
+                json!({
+                    "name": { "type": "string" },
+                    "kind": { "enum": ["git", "sparse"] },
+                    "git_size": size_or_null,
+                    "checkout_size": size_or_null,
+                    "cached_files": size(),
+                    "cache_size": size(),
+                    "total_size": size(),
+                }),
+                &[
+                    "name",
+                    "kind",
+                    "git_size",
+                    "checkout_size",
+                    "cached_files",
+                    "cache_size",
+                    "total_size",
+                ],
+            )),
+            "git_db": group(),
+            "git_checkouts": group(),
+        }),
+        &[],
+    )
+}
+
+/// "cargo cache trim --plan --output-format json", least recently used first
+fn trim_plan() -> Value {
+    object(
+        json!({
+            "limit": { "type": ["integer", "null"], "minimum": 0 },
+            "keep_newer_than": seconds_or_null(),
+            "total_size": size(),
+            "items": {
+                "type": "array",
+                "items": object(
+                    json!({
+                        "path": { "type": "string" },
+                        "kind": {
+                            "enum": [
+                                "crate-archive",
+                                "crate-source",
+                                "git-repo",
+                                "git-checkout",
+                                "sccache-entry",
+                            ],
+                        },
+                        "size": size(),
+                        "cumulative_size": size(),
+                        "last_access": size(),
+                    }),
+                    &["path", "kind", "size", "cumulative_size", "last_access"],
+                ),
+            },
+        }),
+        &["limit", "keep_newer_than", "total_size", "items"],
+    )
+}
+
+/// the command run with several --cargo-home
+fn cargo_homes() -> Value {
+    object(
+        json!({
+            "cargo_homes": {
+                "type": "array",
+                "items": object(
+                    json!({
+                        "cargo_home": { "type": "string" },
+                        "output": {
+                            "description": "the json output of the command for this cargo home, \
+                            a string if it printed something else",
+                            "anyOf": [
+                                { "$ref": "#/$defs/summary" },
+                                { "$ref": "#/$defs/registry" },
+                                { "$ref": "#/$defs/info" },
+                                { "$ref": "#/$defs/top_cache_items" },
+                                { "$ref": "#/$defs/trim_plan" },
+                                { "type": "string" },
+                            ],
+                        },
+                    }),
+                    &["cargo_home", "output"],
+                ),
+            },
+            "failed": { "type": "array", "items": { "type": "string" } },
+            "total_size": {
+                "description": "the size of all cargo homes, only printed for the summary",
+                "type": "integer",
+                "minimum": 0,
+            },
+        }),
+        &["cargo_homes", "failed"],
+    )
+}
+
+/// --json-errors, printed to stderr
+fn error() -> Value {
+    let string_or_null = json!({ "type": ["string", "null"] });
+    object(
+        json!({
+            "code": { "type": "string" },
+            "message": { "type": "string" },
+            "path": string_or_null,
+            "component": string_or_null,
+            "io_error": string_or_null,
+        }),
+        &["code", "message", "path", "component", "io_error"],
+    )
+}
+
+/// "cargo cache metrics", the summary without the paths and names that could identify the machine
+fn metrics() -> Value {
+    let mut summary = summary_properties();
+    let _ = summary.remove("cargo_home");
+    let _ = summary.insert("other_files".into(), count_and_size());
+    object(
+        json!({
+            "label": { "type": ["string", "null"] },
+            "cargo_cache_version": { "type": "string" },
+            "summary": object(Value::Object(summary), &["total_size"]),
+        }),
+        &["label", "cargo_cache_version", "summary"],
+    )
+}
+
+/// the file written by "cargo cache verify --report <path>"
+fn verify_report() -> Value {
+    let string_or_null = json!({ "type": ["string", "null"] });
+    let paths = json!({ "type": "array", "items": { "type": "string" } });
+    let checksum_mismatch = object(
+        json!({
+            "expected_checksum": { "type": "string" },
+            "found_checksum": { "type": "string" },
+        }),
+        &["expected_checksum", "found_checksum"],
+    );
+    object(
+        json!({
+            "corrupted": {
+                "type": "array",
+                "items": object(
+                    json!({
+                        "crate": { "type": "string" },
+                        "source": string_or_null,
+                        "archive": string_or_null,
+                        "missing_in_source": paths,
+                        "not_in_archive": paths,
+                        "size_mismatch": {
+                            "type": "array",
+                            "items": object(
+                                json!({
+                                    "path": { "type": "string" },
+                                    "expected_size": size(),
+                                    "found_size": size(),
+                                }),
+                                &["path", "expected_size", "found_size"],
+                            ),
+                        },
+                        "content_mismatch": paths,
+                        "checksum_mismatch": { "anyOf": [checksum_mismatch, { "type": "null" }] },
+                    }),
+                    &[
+                        "crate",
+                        "source",
+                        "archive",
+                        "missing_in_source",
+                        "not_in_archive",
+                        "size_mismatch",
+                        "content_mismatch",
+                        "checksum_mismatch",
+                    ],
+                ),
+            },
+        }),
+        &["corrupted"],
+    )
+}
+
+/// what the command of --approve-with gets on stdin
+fn approve_request() -> Value {
+    object(
+        json!({
+            "operation": { "type": "string" },
+            "dry_run": { "type": "boolean" },
+            "candidates": {
+                "type": "array",
+                "items": object(
+                    json!({ "path": { "type": "string" }, "size": size() }),
+                    &["path", "size"],
+                ),
+            },
+        }),
+        &["operation", "dry_run", "candidates"],
+    )
+}
+
+/// what the command of --approve-with has to print, every other key is ignored
+fn approve_response() -> Value {
+    json!({
+        "type": "object",
+        "required": ["approved"],
+        "properties": { "approved": { "type": "array", "items": { "type": "string" } } },
+    })
+}
+
+/// a line of the journal in the .cargo-cache state dir of the cargo home
+fn journal_record() -> Value {
+    object(
+        json!({
+            "time": size(),
+            "run": size(),
+            "command": { "type": "string" },
+            "path": { "type": "string" },
+            "size": size(),
+        }),
+        &["time", "run", "command", "path", "size"],
+    )
+}
+
+/// the schema document of the outputs with these definitions, by name
+fn document(definitions: Vec<(&str, Value)>) -> Value {
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "cargo-cache json output",
+        "version": SCHEMA_VERSION,
+        "oneOf": definitions
+            .iter()
+            .map(|(name, _)| json!({ "$ref": format!("#/$defs/{name}") }))
+            .collect::<Vec<_>>(),
+        "$defs": definitions
+            .into_iter()
+            .map(|(name, definition)| (name.to_string(), definition))
+            .collect::<Map<_, _>>(),
+    })
+}
+
+/// the schema printed by "cargo cache --output-schema"
+pub(crate) fn output_schema() -> Value {
+    document(vec![
+        ("summary", summary()),
+        ("registry", registry()),
+        ("info", info()),
+        ("top_cache_items", top_cache_items()),
+        ("trim_plan", trim_plan()),
+        ("cargo_homes", cargo_homes()),
+        ("error", error()),
+        ("metrics", metrics()),
+        ("verify_report", verify_report()),
+        ("approve_request", approve_request()),
+        ("approve_response", approve_response()),
+        ("journal_record", journal_record()),
+    ])
+}
+
+#[cfg(test)]
+pub(crate) mod schema_tests {
+    use super::*;
+    use crate::test_helpers::bin_path;
+    use std::fs;
+    use std::path::Path;
+    use std::process::Command;
+
+    /// the part of JSON Schema that `output_schema()` uses, returns where the value does not match
+    fn validate(schema: &Value, root: &Value, value: &Value, at: &str) -> Result<(), String> {
+        if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+            let name = reference.trim_start_matches("#/$defs/");
+            return validate(&root["$defs"][name], root, value, at);
+        }
+        if let Some(any_of) = schema.get("anyOf").and_then(Value::as_array) {
+            return if any_of
+                .iter()
+                .any(|alternative| validate(alternative, root, value, at).is_ok())
+            {
+                Ok(())
+            } else {
+                Err(format!("{at}: {value} matches none of {schema}"))
+            };
+        }
+        if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+            if !allowed.contains(value) {
+                return Err(format!("{at}: {value} is not one of {allowed:?}"));
+            }
+        }
+        if let Some(types) = schema.get("type") {
+            let type_of = match value {
+                Value::Null => "null",
+                Value::Bool(_) => "boolean",
+                Value::Number(number) if number.is_u64() || number.is_i64() => "integer",
+                Value::Number(_) => "number",
+                Value::String(_) => "string",
+                Value::Array(_) => "array",
+                Value::Object(_) => "object",
+            };
+            let matches = match types {
+                Value::Array(types) => types.iter().any(|t| t == type_of),
+                t => t == type_of,
+            };
+            if !matches {
+                return Err(format!("{at}: {value} is not of type {types}"));
+            }
+        }
+        if let (Some(minimum), Some(number)) = (
+            schema.get("minimum").and_then(Value::as_i64),
+            value.as_i64(),
+        ) {
+            if number < minimum {
+                return Err(format!("{at}: {number} is less than {minimum}"));
+            }
+        }
+        if let (Some(items), Value::Array(array)) = (schema.get("items"), value) {
+            for (i, item) in array.iter().enumerate() {
+                validate(items, root, item, &format!("{at}[{i}]"))?;
+            }
+        }
+        if let Value::Object(map) = value {
+            let properties = &schema["properties"];
+            for required in schema["required"].as_array().into_iter().flatten() {
+                let key = required.as_str().unwrap();
+                if !map.contains_key(key) {
+                    return Err(format!("{at}: \"{key}\" is missing"));
+                }
+            }
+            for (key, property_value) in map {
+                match properties.get(key) {
+                    Some(property) => {
+                        validate(property, root, property_value, &format!("{at}.{key}"))?;
+                    }
+                    None if schema["additionalProperties"] == false => {
+                        return Err(format!("{at}: \"{key}\" is not in the schema"));
+                    }
+                    None => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// panics if `value` does not match the definition `def` of the schema
+    pub(crate) fn assert_valid(def: &str, value: &Value) {
+        let schema = output_schema();
+        assert!(schema["$defs"].get(def).is_some(), "{def} is not defined");
+        if let Err(e) = validate(&schema["$defs"][def], &schema, value, def) {
+            panic!("{e}\n{value:#}");
+        }
+    }
+
+    fn assert_matches(def: &str, output: &str) {
+        let value: Value =
+            serde_json::from_str(output).unwrap_or_else(|e| panic!("not json ({e}): {output}"));
+        assert_valid(def, &value);
+    }
+
+    /// a cargo home with a bit of everything
+    fn cargo_home(root: &Path) {
+        let registry = "index.crates.io-6f17d22bba15001f";
+        let files = [
+            String::from("bin/cargo-foo"),
+            format!("registry/cache/{registry}/foo-1.0.0.crate"),
+            format!("registry/src/{registry}/foo-1.0.0/src/lib.rs"),
+            format!("registry/index/{registry}/.cache/3/f/foo"),
+            String::from("git/db/bar-0123456789abcdef/HEAD"),
+            String::from("git/checkouts/bar-0123456789abcdef/abc1234/src/lib.rs"),
+            String::from("stray-file"),
+        ];
+        for file in files {
+            let path = root.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "content").unwrap();
+        }
+    }
+
+    fn run(cargo_homes: &[&Path], args: &[&str]) -> (String, String) {
+        let mut command = Command::new(bin_path());
+        for cargo_home in cargo_homes {
+            let _ = command.arg("--cargo-home").arg(cargo_home);
+        }
+        let output = command.args(args).output().unwrap();
+        (
+            String::from_utf8_lossy(&output.stdout).into_owned(),
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+        )
+    }
+
+    #[test]
+    fn documents() {
+        let schema = document(vec![(
+            "dir",
+            object(
+                json!({
+                    "path": { "type": "string" },
+                    "size": size(),
+                    "last_used": seconds_or_null(),
+                    "files": count_and_size(),
+                }),
+                &["path", "size", "last_used"],
+            ),
+        )]);
+        assert_eq!(schema["version"], SCHEMA_VERSION);
+        assert_eq!(schema["oneOf"], json!([{ "$ref": "#/$defs/dir" }]));
+
+        let check =
+            |value: Value| validate(&json!({ "$ref": "#/$defs/dir" }), &schema, &value, "dir");
+        assert_eq!(
+            check(json!({ "path": "/cargo", "size": 1, "last_used": null })),
+            Ok(())
+        );
+        assert_eq!(
+            check(json!({
+                "path": "/cargo",
+                "size": 1,
+                "last_used": 1_700_000_000,
+                "files": { "count": 2, "size": 1 },
+            })),
+            Ok(())
+        );
+        assert_eq!(
+            check(json!({ "path": "/cargo", "last_used": null })),
+            Err(String::from("dir: \"size\" is missing"))
+        );
+        assert_eq!(
+            check(json!({ "path": "/cargo", "size": 1, "last_used": null, "bytes": 1 })),
+            Err(String::from("dir: \"bytes\" is not in the schema"))
+        );
+        assert_eq!(
+            check(json!({ "path": "/cargo", "size": "1 MB", "last_used": null })),
+            Err(String::from(
+                "dir.size: \"1 MB\" is not of type \"integer\""
+            ))
+        );
+        assert_eq!(
+            check(json!({ "path": "/cargo", "size": -1, "last_used": null })),
+            Err(String::from("dir.size: -1 is less than 0"))
+        );
+        assert_eq!(
+            check(
+                json!({ "path": "/cargo", "size": 1, "last_used": null, "files": { "count": 2 } })
+            ),
+            Err(String::from("dir.files: \"size\" is missing"))
+        );
+    }
+
+    #[test]
+    fn printed_schema() {
+        let (stdout, _) = run(&[], &["--output-schema"]);
+        let printed: Value = serde_json::from_str(&stdout).unwrap();
+        assert_eq!(printed, output_schema());
+        assert_eq!(printed["version"], SCHEMA_VERSION);
+        for reference in printed["oneOf"].as_array().unwrap() {
+            let name = reference["$ref"]
+                .as_str()
+                .unwrap()
+                .trim_start_matches("#/$defs/");
+            assert!(
+                printed["$defs"].get(name).is_some(),
+                "{name} is not defined"
+            );
+        }
+    }
+
+    #[test]
+    fn outputs_match_the_schema() {
+        let tmp = tempfile::tempdir().unwrap();
+        let cargo_home_a = tmp.path().join("a");
+        let cargo_home_b = tmp.path().join("b");
+        cargo_home(&cargo_home_a);
+        cargo_home(&cargo_home_b);
+        let json = ["--output-format", "json"];
+
+        let (summary, _) = run(&[&cargo_home_a], &json);
+        assert_matches("summary", &summary);
+        let (restricted, _) = run(
+            &[&cargo_home_a],
+            &[
+                "--components",
+                "git-db,registry-sources",
+                "--output-format",
+                "json",
+            ],
+        );
+        assert_matches("summary", &restricted);
+        let (registry, _) = run(&[&cargo_home_a], &["registry", "--output-format", "json"]);
+        assert_matches("registry", &registry);
+        let (info, _) = run(&[&cargo_home_a], &["--info", "--output-format", "json"]);
+        assert_matches("info", &info);
+        let (top_cache_items, _) = run(
+            &[&cargo_home_a],
+            &["--top-cache-items", "5", "--output-format", "json"],
+        );
+        assert_matches("top_cache_items", &top_cache_items);
+        let (trim_plan, _) = run(
+            &[&cargo_home_a],
+            &["trim", "--plan", "--limit", "0B", "--output-format", "json"],
+        );
+        assert_matches("trim_plan", &trim_plan);
+        let (cargo_homes, _) = run(&[&cargo_home_a, &cargo_home_b], &json);
+        assert_matches("cargo_homes", &cargo_homes);
+        let (_, error) = run(
+            &[&cargo_home_a],
+            &["--remove-dir", "nonsense", "--json-errors"],
+        );
+        assert_matches("error", error.trim());
+        if cfg!(feature = "metrics") {
+            let (metrics, _) = run(&[&cargo_home_a], &["metrics", "--label", "ci-runner"]);
+            assert_matches("metrics", &metrics);
+        }
+    }
+
+    #[test]
+    fn drift_is_detected() {
+        let schema = output_schema();
+        let check = |value: Value| validate(&schema["$defs"]["info"], &schema, &value, "info");
+        let mut info = json!({
+            "cargo_home": "/cargo",
+            "cargo_home_from_env": true,
+            "cargo_home_from_flag": false,
+            "total_size": 0,
+            "binaries": { "path": "/cargo/bin", "count": 0, "size": 0 },
+        });
+        for dir in [
+            "registry",
+            "registry_index",
+            "registry_crate_cache",
+            "registry_sources",
+            "git_db",
+            "git_checkouts",
+        ] {
+            info[dir] = json!({ "path": "/cargo", "size": 0 });
+        }
+        assert_eq!(check(info.clone()), Ok(()));
+
+        let mut renamed = info.clone();
+        let size = renamed["binaries"].as_object_mut().unwrap().remove("size");
+        renamed["binaries"]["bytes"] = size.unwrap();
+        assert_eq!(
+            check(renamed),
+            Err(String::from("info.binaries: \"size\" is missing"))
+        );
+        info["total_size"] = json!("1 MB");
+        assert_eq!(
+            check(info),
+            Err(String::from(
+                "info.total_size: \"1 MB\" is not of type \"integer\""
+            ))
+        );
+    }
+}
@@ -0,0 +1,350 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// sizes of the crate sources and git checkouts from earlier runs
+// Most of the time of a scan goes into walking the many small files of the extracted crate sources
+// and git checkouts. Cargo never changes these after extracting or checking them out, it removes
+// them and creates them anew, so the mtime of such a directory tells whether its size may have
+// changed. We remember the size of every crate source and checked out repo together with its mtime
+// in the cache dir of the platform (one file per cargo home) and only walk the ones whose mtime
+// changed. Crate archives are single files and the index and the bare repos are changed in place
+// by cargo, these are always scanned completely.
+// Editing a file inside a crate source does not change the mtime of the source directory itself,
+// the modified-source check (see modified.rs) forgets the sizes of the sources it finds so these
+// are scanned again on the next run.
+// Directories modified in the last seconds are not remembered: on file systems with coarse
+// timestamps, files could still be added without changing the mtime.
+// "--no-size-cache" neither reads nor writes the remembered sizes, "--refresh-size-cache" scans
+// everything again and replaces them, for example after editing files inside registry/src.
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use once_cell::sync::OnceCell;
+use rayon::prelude::*;
+use sha2::{Digest, Sha256};
+
+use crate::ignore::is_ignored;
+
+/// directories that were modified more recently than this are always scanned
+const RACY_MTIME: Duration = Duration::from_secs(2);
+
+/// the remembered sizes of the current cargo home, set up once in `main()`
+static SIZE_CACHE: OnceCell<SizeCache> = OnceCell::new();
+
+/// the size of a directory at the time it had the mtime
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Entry {
+    /// seconds and nanoseconds since the unix epoch
+    mtime: (u64, u32),
+    size: u64,
+}
+
+#[derive(Debug)]
+struct SizeCache {
+    /// the file the sizes are remembered in
+    path: PathBuf,
+    entries: Mutex<HashMap<PathBuf, Entry>>,
+    /// whether a size was added or updated since the file was written
+    changed: AtomicBool,
+}
+
+/// "<mtime seconds>\t<mtime nanoseconds>\t<size>\t<path>"
+fn to_lines(entries: &HashMap<PathBuf, Entry>) -> String {
+    let mut entries = entries.iter().collect::<Vec<_>>();
+    entries.sort_by_key(|(path, _)| *path);
+    entries
+        .into_iter()
+        .fold(String::new(), |mut lines, (path, entry)| {
+            let _ = writeln!(
+                lines,
+                "{}\t{}\t{}\t{}",
+                entry.mtime.0,
+                entry.mtime.1,
+                entry.size,
+                path.display()
+            );
+            lines
+        })
+}
+
+/// parse the remembered sizes, lines we do not understand are skipped
+fn parse(content: &str) -> HashMap<PathBuf, Entry> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(4, '\t');
+            let seconds = fields.next()?.parse().ok()?;
+            let nanoseconds = fields.next()?.parse().ok()?;
+            let size = fields.next()?.parse().ok()?;
+            let path = PathBuf::from(fields.next()?);
+            Some((
+                path,
+                Entry {
+                    mtime: (seconds, nanoseconds),
+                    size,
+                },
+            ))
+        })
+        .collect()
+}
+
+/// cargo-cache/sizes-<hash of the cargo home> in the cache directory of the platform:
+/// `$XDG_CACHE_HOME` or `~/.cache` on Linux, `~/Library/Caches` on macOS, `%LOCALAPPDATA%` on Windows
+fn sizes_file(cargo_home: &Path) -> Option<PathBuf> {
+    let hash = format!(
+        "{:x}",
+        Sha256::digest(cargo_home.to_string_lossy().as_bytes())
+    );
+    dirs_next::cache_dir().map(|cache_dir| {
+        cache_dir
+            .join("cargo-cache")
+            .join(format!("sizes-{}", &hash[..16]))
+    })
+}
+
+/// the mtime of a directory, `None` if it was modified too recently to be remembered
+fn settled_mtime(metadata: &fs::Metadata, now: SystemTime) -> Option<(u64, u32)> {
+    let modified = metadata.modified().ok()?;
+    if now
+        .duration_since(modified)
+        .map_or(true, |age| age < RACY_MTIME)
+    {
+        return None;
+    }
+    let since_epoch = modified.duration_since(UNIX_EPOCH).ok()?;
+    Some((since_epoch.as_secs(), since_epoch.subsec_nanos()))
+}
+
+impl SizeCache {
+    /// the size of the directory, `size_of` is only called if it changed since we remembered it
+    fn dir_size(&self, dir: &Path, now: SystemTime, size_of: impl Fn(&Path) -> u64) -> u64 {
+        let mtime = match fs::symlink_metadata(dir) {
+            Ok(metadata) if metadata.is_dir() => settled_mtime(&metadata, now),
+            _ => None,
+        };
+        let mtime = match mtime {
+            Some(mtime) => mtime,
+            None => return size_of(dir),
+        };
+        if let Some(entry) = self.entries.lock().unwrap().get(dir) {
+            if entry.mtime == mtime {
+                return entry.size;
+            }
+        }
+        let size = size_of(dir);
+        let _ = self
+            .entries
+            .lock()
+            .unwrap()
+            .insert(dir.to_path_buf(), Entry { mtime, size });
+        self.changed.store(true, Ordering::SeqCst);
+        size
+    }
+
+    /// the size of `root` as the sum of the sizes of its entries
+    fn sum_of_entries(
+        &self,
+        root: &Path,
+        now: SystemTime,
+        size_of: impl Fn(&Path) -> u64 + Sync,
+    ) -> Option<u64> {
+        let entries = fs::read_dir(root)
+            .ok()?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<PathBuf>, _>>()
+            .ok()?;
+        Some(
+            entries
+                .par_iter()
+                .filter(|path| !is_ignored(path))
+                .map(|path| self.dir_size(path, now, &size_of))
+                .sum(),
+        )
+    }
+
+    /// forget the sizes of `dirs`
+    fn forget(&self, dirs: &[PathBuf]) {
+        let mut entries = self.entries.lock().unwrap();
+        for dir in dirs {
+            if entries.remove(dir).is_some() {
+                self.changed.store(true, Ordering::SeqCst);
+            }
+        }
+    }
+
+    /// forget the directories that are gone and replace the file in one go
+    fn save(&self) {
+        let lines = {
+            let mut entries = self.entries.lock().unwrap();
+            let remembered = entries.len();
+            entries.retain(|path, _| path.is_dir());
+            let changed = self.changed.swap(false, Ordering::SeqCst);
+            if !changed && entries.len() == remembered {
+                return;
+            }
+            to_lines(&entries)
+        };
+        let temporary = self.path.with_extension("tmp");
+        let written = self
+            .path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| fs::write(&temporary, lines))
+            .and_then(|()| fs::rename(&temporary, &self.path));
+        if let Err(error) = written {
+            let _ = fs::remove_file(&temporary);
+            eprintln!(
+                "Warning: failed to remember the sizes of this scan in \"{}\": {}",
+                self.path.display(),
+                error
+            );
+        }
+    }
+}
+
+/// use the sizes remembered for this cargo home, with `refresh` they are all calculated again
+/// not called with --no-size-cache
+pub(crate) fn init(cargo_home: &Path, refresh: bool) {
+    let path = match sizes_file(cargo_home) {
+        Some(path) => path,
+        None => return,
+    };
+    let entries = if refresh {
+        HashMap::new()
+    } else {
+        fs::read_to_string(&path)
+            .map(|content| parse(&content))
+            .unwrap_or_default()
+    };
+    let _ = SIZE_CACHE.set(SizeCache {
+        path,
+        entries: Mutex::new(entries),
+        changed: AtomicBool::new(refresh),
+    });
+}
+
+/// the size of `root` as the sum of the sizes of its entries, `size_of` is only called for the
+/// entries that changed since the last scan
+/// `None` if the remembered sizes are not used or `root` can not be read, scan it completely then
+pub(crate) fn sum_of_entries(root: &Path, size_of: impl Fn(&Path) -> u64 + Sync) -> Option<u64> {
+    SIZE_CACHE
+        .get()?
+        .sum_of_entries(root, SystemTime::now(), size_of)
+}
+
+/// forget the remembered sizes of `dirs` right away, they changed without changing their mtime
+pub(crate) fn forget(dirs: &[PathBuf]) {
+    if let Some(size_cache) = SIZE_CACHE.get() {
+        size_cache.forget(dirs);
+        size_cache.save();
+    }
+}
+
+/// remember the sizes of this scan for the next run
+pub(crate) fn save() {
+    if let Some(size_cache) = SIZE_CACHE.get() {
+        size_cache.save();
+    }
+}
+
+#[cfg(test)]
+mod size_cache_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::sync::atomic::AtomicUsize;
+
+    #[test]
+    fn lines() {
+        let mut entries = HashMap::new();
+        let _ = entries.insert(
+            PathBuf::from("/cargo/registry/src/index.crates.io-6f17d22bba15001f/a\tb-1.0.0"),
+            Entry {
+                mtime: (1_690_000_000, 123),
+                size: 4096,
+            },
+        );
+        let _ = entries.insert(
+            PathBuf::from("/cargo/git/checkouts/cargo-e7ff1db891893a9e"),
+            Entry {
+                mtime: (1_690_000_001, 0),
+                size: 123_456,
+            },
+        );
+        let lines = to_lines(&entries);
+        assert_eq!(
+            lines,
+            "1690000001\t0\t123456\t/cargo/git/checkouts/cargo-e7ff1db891893a9e\n\
+             1690000000\t123\t4096\t/cargo/registry/src/index.crates.io-6f17d22bba15001f/a\tb-1.0.0\n"
+        );
+        assert_eq!(parse(&lines), entries);
+        assert_eq!(parse("x\t1\t2\t/path\n1\t2\n"), HashMap::new());
+    }
+
+    #[test]
+    fn only_changed_dirs_are_scanned() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        let sources = tempfile::tempdir().unwrap();
+        let sources = sources.path();
+        for name in ["a-1.0.0", "b-1.0.0"] {
+            fs::create_dir(sources.join(name)).unwrap();
+            fs::write(sources.join(name).join("lib.rs"), "fn main() {}").unwrap();
+        }
+        fs::write(sources.join("file"), "x").unwrap();
+
+        let size_cache = SizeCache {
+            path: cache_dir.path().join("cargo-cache").join("sizes"),
+            entries: Mutex::new(HashMap::new()),
+            changed: AtomicBool::new(false),
+        };
+        let scans = AtomicUsize::new(0);
+        let size_of = |path: &Path| {
+            let _ = scans.fetch_add(1, Ordering::SeqCst);
+            crate::walk::walk(path)
+                .filter(|entry| entry.file_type().is_file())
+                .map(|entry| entry.metadata().unwrap().len())
+                .sum()
+        };
+
+        // everything was just created, nothing can be remembered yet
+        let now = SystemTime::now();
+        assert_eq!(size_cache.sum_of_entries(sources, now, size_of), Some(25));
+        assert_eq!(scans.swap(0, Ordering::SeqCst), 3);
+        assert!(!size_cache.changed.load(Ordering::SeqCst));
+
+        // a while later, the directories are remembered and not scanned again, files always are
+        let later = now + Duration::from_secs(60);
+        assert_eq!(size_cache.sum_of_entries(sources, later, size_of), Some(25));
+        assert_eq!(scans.swap(0, Ordering::SeqCst), 3);
+        assert_eq!(size_cache.sum_of_entries(sources, later, size_of), Some(25));
+        assert_eq!(scans.swap(0, Ordering::SeqCst), 1);
+
+        size_cache.save();
+        let saved = parse(&fs::read_to_string(&size_cache.path).unwrap());
+        assert_eq!(saved.len(), 2);
+        assert_eq!(saved.get(&sources.join("a-1.0.0")).unwrap().size, 12);
+
+        // a forgotten directory is scanned again
+        size_cache.forget(&[sources.join("a-1.0.0")]);
+        assert!(size_cache.changed.load(Ordering::SeqCst));
+        assert_eq!(size_cache.sum_of_entries(sources, later, size_of), Some(25));
+        assert_eq!(scans.swap(0, Ordering::SeqCst), 2);
+
+        // a directory that is gone is forgotten
+        fs::remove_dir_all(sources.join("b-1.0.0")).unwrap();
+        size_cache.save();
+        let kept = parse(&fs::read_to_string(&size_cache.path).unwrap());
+        assert_eq!(kept.keys().collect::<Vec<_>>(), [&sources.join("a-1.0.0")]);
+    }
+}
@@ -0,0 +1,197 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// snapshots of the cache size
+// If "snapshots = N" is set in the [stats] table of the config file, every run that scans the
+// whole cache appends the sizes it found to a file in the state dir of the cargo home, only the
+// N newest snapshots are kept. "cargo cache stats --history" shows how the cache grew or shrank.
+// Runs that only scan some --components are not recorded, their sizes are not comparable.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+use crate::dirsizes::DirSizes;
+use crate::state_dir;
+
+/// name of the snapshot file inside the state dir
+pub(crate) const SNAPSHOTS_FILE_NAME: &str = "snapshots";
+
+/// the sizes of the cache at one point in time, in bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Snapshot {
+    /// seconds since the unix epoch
+    pub(crate) time: u64,
+    pub(crate) total: u64,
+    pub(crate) binaries: u64,
+    pub(crate) registry_index: u64,
+    pub(crate) crate_archives: u64,
+    pub(crate) crate_sources: u64,
+    pub(crate) git_db: u64,
+    pub(crate) git_checkouts: u64,
+}
+
+impl Snapshot {
+    /// the sizes of a scan
+    pub(crate) fn new(dir_sizes: &DirSizes<'_>, time: u64) -> Self {
+        Self {
+            time,
+            total: dir_sizes.total_size(),
+            binaries: dir_sizes.total_bin_size(),
+            registry_index: dir_sizes.total_reg_index_size(),
+            crate_archives: dir_sizes.total_reg_cache_size(),
+            crate_sources: dir_sizes.total_reg_src_size(),
+            git_db: dir_sizes.total_git_repos_bare_size(),
+            git_checkouts: dir_sizes.total_git_chk_size(),
+        }
+    }
+
+    /// the registry index, crate archives and crate sources
+    pub(crate) fn registry(&self) -> u64 {
+        self.registry_index + self.crate_archives + self.crate_sources
+    }
+
+    /// the bare repos and checkouts
+    pub(crate) fn git(&self) -> u64 {
+        self.git_db + self.git_checkouts
+    }
+
+    /// "<time>\t<total>\t<binaries>\t<index>\t<archives>\t<sources>\t<git db>\t<checkouts>"
+    fn to_line(self) -> String {
+        [
+            self.time,
+            self.total,
+            self.binaries,
+            self.registry_index,
+            self.crate_archives,
+            self.crate_sources,
+            self.git_db,
+            self.git_checkouts,
+        ]
+        .iter()
+        .map(u64::to_string)
+        .collect::<Vec<_>>()
+        .join("\t")
+    }
+
+    /// parse a line of the snapshot file, lines we do not understand are skipped
+    fn parse(line: &str) -> Option<Self> {
+        let numbers = line
+            .split('\t')
+            .map(|field| field.parse().ok())
+            .collect::<Option<Vec<u64>>>()?;
+        match numbers[..] {
+            [time, total, binaries, registry_index, crate_archives, crate_sources, git_db, git_checkouts] => {
+                Some(Self {
+                    time,
+                    total,
+                    binaries,
+                    registry_index,
+                    crate_archives,
+                    crate_sources,
+                    git_db,
+                    git_checkouts,
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// all snapshots of the cargo home, oldest first
+pub(crate) fn read(cargo_home: &Path) -> Vec<Snapshot> {
+    fs::read_to_string(state_dir::state_file(cargo_home, SNAPSHOTS_FILE_NAME))
+        .map(|snapshots| snapshots.lines().filter_map(Snapshot::parse).collect())
+        .unwrap_or_default()
+}
+
+/// add the snapshot to the cargo home and forget all but the `keep` newest ones
+pub(crate) fn record(cargo_home: &Path, new: Snapshot, keep: usize) {
+    let mut snapshots = read(cargo_home);
+    snapshots.push(new);
+    let first_kept = snapshots.len().saturating_sub(keep);
+    let content = snapshots[first_kept..]
+        .iter()
+        .fold(String::new(), |mut content, snapshot| {
+            let _ = writeln!(content, "{}", snapshot.to_line());
+            content
+        });
+
+    let path = state_dir::state_file(cargo_home, SNAPSHOTS_FILE_NAME);
+    let temporary = path.with_extension("tmp");
+    // "stats --history" may read the file at the same time, replace it in one go
+    let written = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|()| fs::write(&temporary, content))
+        .and_then(|()| fs::rename(&temporary, &path));
+    if let Err(error) = written {
+        let _ = fs::remove_file(&temporary);
+        eprintln!(
+            "Warning: failed to add the sizes of this run to \"{}\": {}",
+            path.display(),
+            error
+        );
+    }
+}
+
+#[cfg(test)]
+mod snapshots_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn snapshot(time: u64, total: u64) -> Snapshot {
+        Snapshot {
+            time,
+            total,
+            binaries: 1,
+            registry_index: 2,
+            crate_archives: 3,
+            crate_sources: 4,
+            git_db: 5,
+            git_checkouts: total - 15,
+        }
+    }
+
+    #[test]
+    fn lines() {
+        let snapshot = snapshot(1_690_000_000, 100);
+        assert_eq!(snapshot.to_line(), "1690000000\t100\t1\t2\t3\t4\t5\t85");
+        assert_eq!(Snapshot::parse(&snapshot.to_line()), Some(snapshot));
+        assert_eq!(snapshot.registry(), 9);
+        assert_eq!(snapshot.git(), 90);
+
+        assert_eq!(Snapshot::parse(""), None);
+        assert_eq!(Snapshot::parse("1\t2\t3"), None);
+        assert_eq!(Snapshot::parse("1\t2\t3\t4\t5\t6\t7\t8\t9"), None);
+        assert_eq!(Snapshot::parse("yesterday\t2\t3\t4\t5\t6\t7\t8"), None);
+    }
+
+    #[test]
+    fn only_the_newest_are_kept() {
+        let cargo_home = tempfile::tempdir().unwrap();
+        assert!(read(cargo_home.path()).is_empty());
+
+        for time in 1..=4 {
+            record(cargo_home.path(), snapshot(time, 100 + time), 3);
+        }
+        let path = state_dir::state_file(cargo_home.path(), SNAPSHOTS_FILE_NAME);
+        fs::write(
+            &path,
+            format!("garbage\n{}", fs::read_to_string(&path).unwrap()),
+        )
+        .unwrap();
+        let times = read(cargo_home.path())
+            .iter()
+            .map(|snapshot| snapshot.time)
+            .collect::<Vec<_>>();
+        assert_eq!(times, vec![2, 3, 4]);
+        assert!(!path.with_extension("tmp").exists());
+    }
+}
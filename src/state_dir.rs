@@ -0,0 +1,23 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// the directory cargo-cache keeps its own files in: $CARGO_HOME/.cargo-cache/
+// Everything cargo-cache remembers about a cargo home between runs lives in this one directory
+// instead of next to the directories of cargo, so it is easy to find and to remove.
+// Whoever writes a file here creates the directory first, readers treat a missing file as empty.
+
+use std::path::{Path, PathBuf};
+
+/// name of the directory inside the cargo home
+pub(crate) const STATE_DIR_NAME: &str = ".cargo-cache";
+
+/// `$CARGO_HOME/.cargo-cache/<name>`
+pub(crate) fn state_file(cargo_home: &Path, name: &str) -> PathBuf {
+    cargo_home.join(STATE_DIR_NAME).join(name)
+}
@@ -0,0 +1,155 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// cleanup suggestions below the default summary
+// If the cache is large, the summary ends with what the usual cleanups would free, so it does not
+// only tell how big the cache is but also what to do about it.
+// The estimates are cheap: --autoclean is what the summary already calculated, for -k 1 and the
+// age based removal we only look at the metadata of the crate archives, nothing is scanned again.
+// The strategies overlap, the estimates are alternatives and must not be added up.
+
+use std::fs;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use chrono::{Duration, Local};
+use humansize::{FormatSize, DECIMAL};
+
+use crate::cache::caches::RegistrySuperCache;
+use crate::cache::*;
+use crate::dirsizes::DirSizes;
+use crate::library::KeepBy;
+use crate::remove::duplicate_crates_size;
+
+/// caches smaller than this do not get suggestions
+const LARGE_CACHE: u64 = 5_000_000_000;
+
+/// crate archives that were not used for this long are suggested for removal
+const UNUSED_DAYS: i64 = 182;
+
+/// a cleanup and how much it would free
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Suggestion {
+    size: u64,
+    command: String,
+    explanation: &'static str,
+}
+
+/// the size of the crate archives that were last accessed before `cutoff`
+fn unused_crates_size(crate_archives: &[PathBuf], cutoff: SystemTime) -> u64 {
+    crate_archives
+        .iter()
+        .filter_map(|path| fs::metadata(path).ok())
+        .filter(|metadata| {
+            metadata
+                .accessed()
+                .map_or(false, |accessed| accessed < cutoff)
+        })
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+/// the suggestions block, None if none of the cleanups would free anything
+fn format_suggestions(total_size: u64, suggestions: &[Suggestion]) -> Option<String> {
+    let suggestions = suggestions
+        .iter()
+        .filter(|suggestion| suggestion.size > 0)
+        .collect::<Vec<&Suggestion>>();
+    if suggestions.is_empty() {
+        return None;
+    }
+
+    let mut lines = vec![format!(
+        "\nThe cache is {}, you could free",
+        total_size.format_size(DECIMAL)
+    )];
+    lines.extend(suggestions.iter().map(|suggestion| {
+        format!(
+            "  ~{} by `{}` ({})",
+            suggestion.size.format_size(DECIMAL),
+            suggestion.command,
+            suggestion.explanation
+        )
+    }));
+    lines.push(String::new());
+    Some(lines.join("\n"))
+}
+
+/// what the common cleanups would free if the cache is large
+pub(crate) fn suggestions(
+    dir_sizes: &DirSizes<'_>,
+    registry_pkgs_cache: &mut registry_pkg_cache::RegistryPkgCaches,
+) -> Option<String> {
+    let total_size = dir_sizes.total_size();
+    if total_size < LARGE_CACHE {
+        return None;
+    }
+
+    let cutoff = Local::now() - Duration::days(UNUSED_DAYS);
+    let suggestions = [
+        Suggestion {
+            size: dir_sizes.total_reg_src_size() + dir_sizes.total_git_chk_size(),
+            command: String::from("cargo cache --autoclean"),
+            explanation: "extracted sources and checkouts",
+        },
+        Suggestion {
+            size: duplicate_crates_size(registry_pkgs_cache, 1, KeepBy::Version),
+            command: String::from("cargo cache -k 1"),
+            explanation: "all but the newest version of each crate",
+        },
+        Suggestion {
+            size: unused_crates_size(&registry_pkgs_cache.files(), SystemTime::from(cutoff)),
+            command: format!(
+                "cargo cache --remove-dir registry-crate-cache --remove-if-older-than {}",
+                cutoff.format("%Y.%m.%d")
+            ),
+            explanation: "crate archives not used for 6 months",
+        },
+    ];
+    format_suggestions(total_size, &suggestions)
+}
+
+#[cfg(test)]
+mod suggestions_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn only_useful_suggestions_are_shown() {
+        let suggestions = [
+            Suggestion {
+                size: 1_200_000_000,
+                command: String::from("cargo cache --autoclean"),
+                explanation: "extracted sources and checkouts",
+            },
+            Suggestion {
+                size: 0,
+                command: String::from("cargo cache -k 1"),
+                explanation: "all but the newest version of each crate",
+            },
+        ];
+        assert_eq!(
+            format_suggestions(6_000_000_000, &suggestions).unwrap(),
+            "\nThe cache is 6 GB, you could free\n  ~1.20 GB by `cargo cache --autoclean` (extracted sources and checkouts)\n"
+        );
+        assert_eq!(format_suggestions(6_000_000_000, &suggestions[1..]), None);
+    }
+
+    #[test]
+    fn unused_archives() {
+        let tmp = tempfile::tempdir().unwrap();
+        let archive = tmp.path().join("foo-1.0.0.crate");
+        fs::write(&archive, "12345").unwrap();
+        let archives = vec![archive, tmp.path().join("missing-1.0.0.crate")];
+
+        assert_eq!(unused_crates_size(&archives, SystemTime::UNIX_EPOCH), 0);
+        let future = SystemTime::now() + std::time::Duration::from_secs(60);
+        assert_eq!(unused_crates_size(&archives, future), 5);
+    }
+}
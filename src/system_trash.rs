@@ -0,0 +1,112 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// --use-system-trash: move what we remove to the trash of the desktop (the recycle bin on windows,
+// the trash of the finder on macos, the freedesktop trash on linux and the bsds) so it can be restored
+// from there. The space is only freed once the trash is emptied.
+// A headless server has nobody who would ever empty the trash, there (and on builds without the
+// "trash" feature) we warn and remove permanently like without the flag.
+
+use std::io;
+use std::path::Path;
+
+use once_cell::sync::OnceCell;
+
+/// whether removed items go to the trash in this run, set up once in `main()`
+static USE_TRASH: OnceCell<bool> = OnceCell::new();
+
+/// decide whether removed items go to the trash, warns if they can not
+pub(crate) fn init(use_system_trash: bool) {
+    let use_trash = use_system_trash && {
+        let reason = unavailable_reason();
+        if let Some(reason) = reason {
+            eprintln!("Warning: --use-system-trash: {reason}, removing permanently instead.");
+        }
+        reason.is_none()
+    };
+    let _ = USE_TRASH.set(use_trash);
+}
+
+/// whether removed items go to the trash instead of being deleted
+pub(crate) fn enabled() -> bool {
+    USE_TRASH.get().copied().unwrap_or(false)
+}
+
+/// why there is no trash to move things to, `None` if there is one
+#[cfg(all(
+    feature = "trash",
+    any(
+        windows,
+        target_os = "macos",
+        all(unix, not(target_os = "ios"), not(target_os = "android"))
+    )
+))]
+fn unavailable_reason() -> Option<&'static str> {
+    if cfg!(any(windows, target_os = "macos")) {
+        return None;
+    }
+    // the freedesktop trash is only looked at (and emptied) by a desktop session
+    let desktop_session = [
+        "DISPLAY",
+        "WAYLAND_DISPLAY",
+        "XDG_CURRENT_DESKTOP",
+        "DESKTOP_SESSION",
+    ]
+    .iter()
+    .any(|var| std::env::var_os(var).map_or(false, |value| !value.is_empty()));
+    if !desktop_session {
+        Some("no desktop session with a trash was found")
+    } else if dirs_next::data_dir().is_none() {
+        Some("the trash directory could not be determined")
+    } else {
+        None
+    }
+}
+
+#[cfg(not(all(
+    feature = "trash",
+    any(
+        windows,
+        target_os = "macos",
+        all(unix, not(target_os = "ios"), not(target_os = "android"))
+    )
+)))]
+#[allow(clippy::unnecessary_wraps)] // the same signature as with a trash
+fn unavailable_reason() -> Option<&'static str> {
+    Some("this build of cargo-cache has no system trash support")
+}
+
+/// move a file, directory or link to the trash, links are moved and not what they point to
+#[cfg(all(
+    feature = "trash",
+    any(
+        windows,
+        target_os = "macos",
+        all(unix, not(target_os = "ios"), not(target_os = "android"))
+    )
+))]
+pub(crate) fn move_to_trash(path: &Path) -> io::Result<()> {
+    trash::delete(path).map_err(|error| io::Error::new(io::ErrorKind::Other, error.to_string()))
+}
+
+#[cfg(not(all(
+    feature = "trash",
+    any(
+        windows,
+        target_os = "macos",
+        all(unix, not(target_os = "ios"), not(target_os = "android"))
+    )
+)))]
+pub(crate) fn move_to_trash(path: &Path) -> io::Result<()> {
+    // init() never enables the trash without support for it
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("no system trash to move \"{}\" to", path.display()),
+    ))
+}
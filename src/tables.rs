@@ -7,8 +7,8 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-/// This file provides the `TableLine` struct which is used by
-/// `format_2_row_table()` to create neat-looking 2-column tables.
+// This file provides the `TableLine` struct which is used by
+// `format_2_row_table()` to create neat-looking 2-column tables.
 
 /// struct used to format 2-column tables
 #[derive(Clone, Debug)]
@@ -42,6 +42,11 @@ impl TableLine {
     }
 }
 
+/// the number of columns the text takes up, translated messages are not always ascii
+fn width(text: &str) -> usize {
+    text.chars().count()
+}
+
 /// creates a formatted 2 row table (String) from a `Vec` of `TableLines`
 pub(crate) fn two_row_table(
     // minimal padding between left and right column
@@ -65,27 +70,31 @@ pub(crate) fn two_row_table(
     let total_entries = lines.len();
 
     // get the length of the longest elements
+    // lines that only have a left column do not affect the alignment
     let max_len_left_col: usize = if align_first_line {
         lines
             .iter()
-            .map(|line| line.left_column.len())
+            .filter(|line| !line.right_column.is_empty())
+            .map(|line| width(&line.left_column))
             .max()
             .unwrap_or(0)
     } else {
         lines
             .iter()
             .skip(1)
-            .map(|line| line.left_column.len())
+            .filter(|line| !line.right_column.is_empty())
+            .map(|line| width(&line.left_column))
             .max()
             .unwrap_or(0)
     };
     let max_len_right_col: usize = lines
         .iter()
-        .map(|line| line.right_column.len())
+        .map(|line| width(&line.right_column))
         .max()
         .unwrap_or(0);
     let max_indent_front: usize = lines
         .iter()
+        .filter(|line| !line.right_column.is_empty())
         .map(|line| line.indent_front)
         .max()
         .unwrap_or(0);
@@ -113,12 +122,19 @@ pub(crate) fn two_row_table(
         table.push_str(&" ".repeat(indent_front_len));
         // the right column
         table.push_str(&line.left_column);
+        // do not pad lines that only have a left column with trailing whitespace
+        if line.right_column.is_empty() {
+            table.push('\n');
+            continue;
+        }
         //  max len -(padding + left_column + right_column )   == the amount of spaces needed here
-        let spaces = line_length
-            - (indent_front_len
-                + line.left_column.len()
+        // the line that is skipped when calculating max_len_left_col might be longer than the others
+        let spaces = line_length.saturating_sub(
+            indent_front_len
+                + width(&line.left_column)
                 + min_padding_middle
-                + line.right_column.len());
+                + width(&line.right_column),
+        );
         table.push_str(&" ".repeat(min_padding_middle + spaces));
         table.push_str(&line.right_column);
         table.push('\n');
@@ -151,8 +167,8 @@ pub(crate) fn format_table(table: &[Vec<String>], padding: usize) -> String {
     for row in table {
         for (idx, cell) in row.iter().enumerate() {
             // if the cell is bigger than the max, update the max
-            if cell.len() > max_lengths[idx] {
-                max_lengths[idx] = cell.len();
+            if width(cell) > max_lengths[idx] {
+                max_lengths[idx] = width(cell);
             }
         }
     }
@@ -161,9 +177,9 @@ pub(crate) fn format_table(table: &[Vec<String>], padding: usize) -> String {
     for row in table {
         let mut new_row = String::new();
         for (idx, cell) in row.iter().enumerate() {
-            if cell.len() < max_lengths[idx] {
+            if width(cell) < max_lengths[idx] {
                 // we need to add padding
-                let diff = max_lengths[idx] - cell.len();
+                let diff = max_lengths[idx] - width(cell);
                 let mut cell_new = cell.clone();
                 cell_new.push_str(&" ".repeat(diff)); // pad the string
                 cell_new.push_str(&" ".repeat(padding));
@@ -266,4 +282,15 @@ mod format_table_tests {
          */
         assert_eq!(t, output);
     }
+
+    #[test]
+    fn non_ascii() {
+        // a translated header must not shift the columns
+        let v = vec![
+            vec![String::from("Größe"), String::from("Name")],
+            vec![String::from("1 MB"), String::from("serde")],
+        ];
+        let t = format_table(&v, 0);
+        assert_eq!(t, "Größe Name\n1 MB  serde\n");
+    }
 }
@@ -14,43 +14,28 @@ use rayon::iter::*;
 use walkdir::WalkDir;
 
 #[allow(dead_code)] // only used in tests
-#[allow(clippy::branches_sharing_code)]
 pub(crate) fn bin_path() -> String {
-    let target_dir = cargo_metadata::MetadataCommand::new()
-        .exec()
+    // the test executables are in target/<profile>/deps, the binary is in target/<profile>
+    let target_dir = std::env::current_exe()
         .unwrap()
-        .target_directory;
-
-    // check if we have a release or debug binary to run tests with
-    // we need to take into account that linux and windows have different paths to the executable
-    let path_release = if cfg!(windows) {
-        let mut td = target_dir.clone();
-        td.push("release");
-        td.push("cargo-cache.exe");
-        td
+        .ancestors()
+        .nth(3)
+        .unwrap()
+        .to_path_buf();
+    let executable = if cfg!(windows) {
+        "cargo-cache.exe"
     } else {
-        let mut td = target_dir.clone();
-        td.push("release");
-        td.push("cargo-cache");
-        td
+        "cargo-cache"
     };
 
-    let path_debug = if cfg!(windows) {
-        let mut td = target_dir;
-        td.push("debug");
-        td.push("cargo-cache.exe");
-        td
-    } else {
-        let mut td = target_dir;
-        td.push("debug");
-        td.push("cargo-cache");
-        td
-    };
+    // check if we have a release or debug binary to run tests with
+    let path_release = target_dir.join("release").join(executable);
+    let path_debug = target_dir.join("debug").join(executable);
 
     if path_release.is_file() {
-        path_release.into_string()
+        path_release.display().to_string()
     } else if path_debug.is_file() {
-        path_debug.into_string()
+        path_debug.display().to_string()
     } else {
         panic!("No cargo-cache executable found!");
     }
@@ -73,7 +58,6 @@ pub(crate) fn assert_path_end(path: &Path, wanted_vector: &[&str]) {
 }
 
 #[allow(dead_code)] // only used in tests
-
 /// get the total size and number of files of a directory
 pub(crate) fn dir_size(dir: &Path) -> u64 {
     // Note: using a hashmap to cache dirsizes does apparently not pay out performance-wise
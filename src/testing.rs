@@ -0,0 +1,378 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! the parsers of cargo-cache and proptest strategies to fuzz them, needs the `testing` feature
+//!
+//! A cargo home contains whatever cargo, other tools and users put there, so everything that
+//! takes apart a file name has to cope with any name. The parsers are exposed here as they are
+//! used by the binary, [`strategies`] generates cache-like paths, sizes and dates for them.
+//!
+//! ```
+//! use cargo_cache::testing::{self, strategies};
+//! use proptest::prelude::*;
+//!
+//! proptest!(|(item in strategies::crate_archive())| {
+//!     prop_assert_eq!(
+//!         testing::split_name_version(&item.file_name),
+//!         (item.name.as_str(), item.version.as_str())
+//!     );
+//! });
+//! ```
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use chrono::{Local, TimeZone};
+
+use crate::args::parse::{self, SizeUnits};
+use crate::library::{components_from_groups, Component};
+use crate::names;
+use crate::remove;
+
+/// the name of the last component of the path, invalid UTF-8 is replaced
+#[must_use]
+pub fn file_name(path: &Path) -> String {
+    names::file_name(path).into_owned()
+}
+
+/// split "serde-json-1.0.0.crate" or "serde-json-1.0.0" into name and version,
+/// the version starts with the first segment that starts with a digit
+#[must_use]
+pub fn split_name_version(file_name: &str) -> (&str, &str) {
+    names::split_name_version(file_name)
+}
+
+/// name and version of the crate archive or source directory at `path`,
+/// None if the path has no file name
+#[must_use]
+pub fn parse_version(path: &Path) -> Option<(String, String)> {
+    remove::parse_version(path).ok()
+}
+
+/// "github.com-1ecc6299db9ec823" without the hash: "github.com"
+#[must_use]
+pub fn strip_hash(dir_name: &str) -> &str {
+    names::strip_hash(dir_name)
+}
+
+/// the size in bytes of "500M" or "1.5G" like `trim --limit` (1K = 1024 bytes)
+///
+/// # Errors
+///
+/// the message cargo-cache prints if it is not a size
+pub fn parse_size(size: &str) -> Result<u64, String> {
+    parse::size(size, SizeUnits::Binary).map_err(|error| error.to_string())
+}
+
+/// the duration of "30d", "6w", "3m" (months) or "1y"
+///
+/// # Errors
+///
+/// the message cargo-cache prints if it is not a duration
+pub fn parse_duration(duration: &str) -> Result<Duration, String> {
+    parse::duration(duration).map_err(|error| error.to_string())
+}
+
+/// the point in time of "YYYY.MM.DD" (at the current time of day) or "HH:MM:SS" (today),
+/// in the local time zone like `--remove-if-older-than`
+///
+/// # Errors
+///
+/// the message cargo-cache prints if it is not a date, or if it does not exist in the local
+/// time zone
+pub fn parse_date(date: &str) -> Result<SystemTime, String> {
+    let date_time = parse::date(date).map_err(|error| error.to_string())?;
+    Local
+        .from_local_datetime(&date_time)
+        .earliest()
+        .map(SystemTime::from)
+        .ok_or_else(|| format!("\"{date}\" does not exist in the local time zone"))
+}
+
+/// the cutoff of a duration or date, like `trim --keep-newer-than`
+///
+/// # Errors
+///
+/// the message cargo-cache prints if it is neither a duration nor a date
+pub fn parse_age_cutoff(age: &str) -> Result<SystemTime, String> {
+    parse::age_cutoff(age).map_err(|error| error.to_string())
+}
+
+/// the `--components` name of the component the path inside a cargo home belongs to,
+/// "registry-crate-cache" for `.../registry/cache/...`
+#[must_use]
+pub fn component_of_path(path: &Path) -> Option<&'static str> {
+    Component::of_path(path).as_ref().map(Component::name)
+}
+
+/// the components of a `--components` or `--remove-dir` value such as "registry,git-db",
+/// a component also pulls in the ones that are extracted from it like with `--remove-dir`
+///
+/// # Errors
+///
+/// the message cargo-cache prints for unknown components
+pub fn parse_components(components: &str) -> Result<Vec<&'static str>, String> {
+    components_from_groups(Some(components))
+        .map(|components| components.iter().map(Component::name).collect())
+        .map_err(|error| error.to_string())
+}
+
+/// proptest strategies for the names and paths of a cargo home and for option values
+pub mod strategies {
+    use super::*;
+    use proptest::prelude::*;
+    use proptest::string::string_regex;
+
+    /// an item of the cache as cargo would create it, and what the parsers should find in it
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct CacheItem {
+        /// the path relative to the cargo home, "registry/cache/index.crates.io-.../foo-1.0.0.crate"
+        pub path: PathBuf,
+        /// the last component of the path
+        pub file_name: String,
+        /// the `--components` name of the component the item belongs to, None for binaries
+        pub component: Option<&'static str>,
+        /// the crate, registry or repo name without version or hash
+        pub name: String,
+        /// the version of crate archives and sources, empty for everything else
+        pub version: String,
+    }
+
+    fn regex(pattern: &str) -> BoxedStrategy<String> {
+        string_regex(pattern)
+            .expect("the patterns of the strategies are valid")
+            .boxed()
+    }
+
+    /// a crate name: letters, digits, '_' and '-', no segment starts with a digit
+    pub fn crate_name() -> BoxedStrategy<String> {
+        regex("[a-zA-Z][a-zA-Z0-9_]{0,10}(-[a-zA-Z_][a-zA-Z0-9_]{0,8}){0,3}")
+    }
+
+    /// a semver version, sometimes with a pre-release or build metadata: "1.2.3-beta.1"
+    pub fn crate_version() -> BoxedStrategy<String> {
+        (
+            0..100_u32,
+            0..100_u32,
+            0..1000_u32,
+            prop::option::of(regex("(alpha|beta|rc)(\\.[0-9]{1,2})?")),
+            prop::option::of(regex("[a-z0-9]{1,6}")),
+        )
+            .prop_map(|(major, minor, patch, pre, build)| {
+                let mut version = format!("{major}.{minor}.{patch}");
+                if let Some(pre) = pre {
+                    version.push('-');
+                    version.push_str(&pre);
+                }
+                if let Some(build) = build {
+                    version.push('+');
+                    version.push_str(&build);
+                }
+                version
+            })
+            .boxed()
+    }
+
+    /// the 16 hex digits cargo appends to registry and git directories
+    pub fn hash() -> BoxedStrategy<String> {
+        regex("[0-9a-f]{16}")
+    }
+
+    /// "index.crates.io-<hash>", "github.com-<hash>" or the host of an alternative registry
+    pub fn registry_dir_name() -> BoxedStrategy<(String, String)> {
+        let host = prop_oneof![
+            Just(String::from("index.crates.io")),
+            Just(String::from("github.com")),
+            regex("[a-z][a-z0-9]{0,8}(\\.[a-z][a-z0-9]{0,8}){1,3}"),
+        ];
+        (host, hash())
+            .prop_map(|(host, hash)| (format!("{host}-{hash}"), host))
+            .boxed()
+    }
+
+    /// a `.crate` archive in `registry/cache`
+    pub fn crate_archive() -> BoxedStrategy<CacheItem> {
+        (registry_dir_name(), crate_name(), crate_version())
+            .prop_map(|((registry, _host), name, version)| {
+                let file_name = format!("{name}-{version}.crate");
+                CacheItem {
+                    path: Path::new("registry/cache").join(registry).join(&file_name),
+                    file_name,
+                    component: Some("registry-crate-cache"),
+                    name,
+                    version,
+                }
+            })
+            .boxed()
+    }
+
+    /// an extracted crate in `registry/src`
+    pub fn crate_source() -> BoxedStrategy<CacheItem> {
+        (registry_dir_name(), crate_name(), crate_version())
+            .prop_map(|((registry, _host), name, version)| {
+                let file_name = format!("{name}-{version}");
+                CacheItem {
+                    path: Path::new("registry/src").join(registry).join(&file_name),
+                    file_name,
+                    component: Some("registry-sources"),
+                    name,
+                    version,
+                }
+            })
+            .boxed()
+    }
+
+    /// the index of a registry in `registry/index`
+    pub fn registry_index() -> BoxedStrategy<CacheItem> {
+        registry_dir_name()
+            .prop_map(|(file_name, host)| CacheItem {
+                path: Path::new("registry/index").join(&file_name),
+                file_name,
+                component: Some("registry-index"),
+                name: host,
+                version: String::new(),
+            })
+            .boxed()
+    }
+
+    /// a bare repo in `git/db`
+    pub fn git_db() -> BoxedStrategy<CacheItem> {
+        (crate_name(), hash())
+            .prop_map(|(name, hash)| {
+                let file_name = format!("{name}-{hash}");
+                CacheItem {
+                    path: Path::new("git/db").join(&file_name),
+                    file_name,
+                    component: Some("git-db"),
+                    name,
+                    version: String::new(),
+                }
+            })
+            .boxed()
+    }
+
+    /// the checkout of a revision in `git/checkouts/<repo>-<hash>/<rev>`
+    pub fn git_checkout() -> BoxedStrategy<CacheItem> {
+        (crate_name(), hash(), regex("[0-9a-f]{7}"))
+            .prop_map(|(name, hash, rev)| CacheItem {
+                path: Path::new("git/checkouts")
+                    .join(format!("{name}-{hash}"))
+                    .join(&rev),
+                file_name: rev,
+                component: Some("git-repos"),
+                name,
+                version: String::new(),
+            })
+            .boxed()
+    }
+
+    /// an installed binary in `bin`
+    pub fn binary() -> BoxedStrategy<CacheItem> {
+        regex("[a-z][a-z0-9_-]{0,15}(\\.exe)?")
+            .prop_map(|file_name| CacheItem {
+                path: Path::new("bin").join(&file_name),
+                name: file_name.clone(),
+                file_name,
+                component: None,
+                version: String::new(),
+            })
+            .boxed()
+    }
+
+    /// any item cargo puts into a cargo home
+    pub fn cache_item() -> BoxedStrategy<CacheItem> {
+        prop_oneof![
+            crate_archive(),
+            crate_source(),
+            registry_index(),
+            git_db(),
+            git_checkout(),
+            binary(),
+        ]
+        .boxed()
+    }
+
+    /// file names cargo would never create: no version, no hash, only dashes, dots, any unicode
+    pub fn unexpected_file_name() -> BoxedStrategy<String> {
+        prop_oneof![
+            regex("[-.0-9a-z]{0,12}"),
+            regex("[-.]{0,4}[0-9]{0,3}(\\.crate)?"),
+            any::<String>().prop_map(|name| name.replace(['/', '\\', '\0'], "")),
+        ]
+        .boxed()
+    }
+
+    /// paths with an unexpected name where the cache items would be, on unix also names that
+    /// are not valid UTF-8
+    pub fn unexpected_path() -> BoxedStrategy<PathBuf> {
+        let parent = prop_oneof![
+            Just(PathBuf::new()),
+            Just(PathBuf::from(
+                "registry/cache/index.crates.io-6f17d22bba15001f"
+            )),
+            Just(PathBuf::from("registry/src/github.com-1ecc6299db9ec823")),
+            Just(PathBuf::from("git/checkouts")),
+            Just(PathBuf::from("git/db")),
+            Just(PathBuf::from("/")),
+            Just(PathBuf::from("..")),
+        ];
+        (parent, file_name_bytes())
+            .prop_map(|(parent, name)| parent.join(name))
+            .boxed()
+    }
+
+    #[cfg(unix)]
+    fn file_name_bytes() -> BoxedStrategy<std::ffi::OsString> {
+        use std::os::unix::ffi::OsStringExt;
+        prop_oneof![
+            unexpected_file_name().prop_map(std::ffi::OsString::from),
+            prop::collection::vec(any::<u8>(), 0..16).prop_map(|mut bytes| {
+                bytes.retain(|&byte| byte != b'/' && byte != 0);
+                std::ffi::OsString::from_vec(bytes)
+            }),
+        ]
+        .boxed()
+    }
+
+    #[cfg(not(unix))]
+    fn file_name_bytes() -> BoxedStrategy<std::ffi::OsString> {
+        unexpected_file_name()
+            .prop_map(std::ffi::OsString::from)
+            .boxed()
+    }
+
+    /// values for size options: mostly valid ("1.5G"), some not ("G", "1.2.3K", "-1M")
+    pub fn size_like() -> BoxedStrategy<String> {
+        regex("-?[0-9]{0,25}(\\.[0-9]{0,5}){0,2}[bBkKmMgGtTx]?")
+    }
+
+    /// values for duration options: mostly valid ("30d"), some not ("30", "d", "99999999999999999999y")
+    pub fn duration_like() -> BoxedStrategy<String> {
+        regex("[0-9]{0,22}[dwmyx]?")
+    }
+
+    /// values for date options in the shapes "YYYY.MM.DD" and "HH:MM:SS" with any digits,
+    /// and things that only look similar
+    pub fn date_like() -> BoxedStrategy<String> {
+        prop_oneof![
+            regex("[0-9]{4}\\.[0-9]{2}\\.[0-9]{2}"),
+            regex("[0-9]{2}:[0-9]{2}:[0-9]{2}"),
+            regex("[0-9.:]{0,12}"),
+            any::<String>(),
+        ]
+        .boxed()
+    }
+
+    /// dates that exist: "YYYY.MM.DD" between 1970 and 2100
+    pub fn valid_date() -> BoxedStrategy<String> {
+        (1970..2100_u32, 1..=12_u32, 1..=28_u32)
+            .prop_map(|(year, month, day)| format!("{year:04}.{month:02}.{day:02}"))
+            .boxed()
+    }
+}
@@ -0,0 +1,133 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// how fast files were removed, printed at the end of the run with --stats
+// Removing many small files can be surprisingly slow, for example if a virus scanner looks at every
+// file or the cargo home is on a network file system. With --stats every removal counts the files
+// and bytes it removes and the time it takes, the summary then shows files/s and MB/s.
+// The files are counted before the removal starts, that time is not part of the measurement.
+
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use humansize::{FormatSize, DECIMAL};
+use once_cell::sync::OnceCell;
+
+use crate::walk::walk;
+
+/// the removals of this run, only set up if --stats was passed
+static THROUGHPUT: OnceCell<Throughput> = OnceCell::new();
+
+/// what all removals of the run removed and how long they took
+#[derive(Debug, Default)]
+struct Throughput {
+    files: AtomicU64,
+    bytes: AtomicU64,
+    nanos: AtomicU64,
+}
+
+impl Throughput {
+    fn add(&self, files: u64, bytes: u64, elapsed: Duration) {
+        let _ = self.files.fetch_add(files, Ordering::SeqCst);
+        let _ = self.bytes.fetch_add(bytes, Ordering::SeqCst);
+        let nanos = u64::try_from(elapsed.as_nanos()).unwrap_or(u64::MAX);
+        let _ = self.nanos.fetch_add(nanos, Ordering::SeqCst);
+    }
+
+    /// "Removed 12034 files (1.20 GB) in 3.52s: 3418 files/s, 340.91 MB/s"
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+    )]
+    fn summary(&self) -> String {
+        let files = self.files.load(Ordering::SeqCst);
+        let bytes = self.bytes.load(Ordering::SeqCst);
+        if files == 0 {
+            return String::from("Removed no files.");
+        }
+        let seconds = Duration::from_nanos(self.nanos.load(Ordering::SeqCst)).as_secs_f64();
+        // a removal is never faster than a microsecond, do not divide by 0
+        let seconds_for_rates = seconds.max(0.000_001);
+        format!(
+            "Removed {files} files ({}) in {seconds:.2}s: {:.0} files/s, {}/s",
+            bytes.format_size(DECIMAL),
+            files as f64 / seconds_for_rates,
+            ((bytes as f64 / seconds_for_rates) as u64).format_size(DECIMAL),
+        )
+    }
+}
+
+/// measure the removals of this run, needs to be called before anything is removed
+pub(crate) fn init(enabled: bool) {
+    if enabled {
+        let _ = THROUGHPUT.set(Throughput::default());
+    }
+}
+
+/// a removal that is being measured
+#[derive(Debug)]
+pub(crate) struct Measurement {
+    files: u64,
+    bytes: u64,
+    started: Instant,
+}
+
+impl Measurement {
+    /// count the files and bytes of `path` before it is removed, None unless --stats was passed
+    pub(crate) fn start(path: &Path) -> Option<Self> {
+        let _ = THROUGHPUT.get()?;
+        let (files, bytes) = walk(path).filter(|entry| !entry.file_type().is_dir()).fold(
+            (0, 0),
+            |(files, bytes), entry| {
+                let size = entry.metadata().map_or(0, |metadata| metadata.len());
+                (files + 1, bytes + size)
+            },
+        );
+        Some(Self {
+            files,
+            bytes,
+            started: Instant::now(),
+        })
+    }
+
+    /// the removal succeeded
+    pub(crate) fn finish(self) {
+        if let Some(throughput) = THROUGHPUT.get() {
+            throughput.add(self.files, self.bytes, self.started.elapsed());
+        }
+    }
+}
+
+/// print how fast the removals of this run were, if --stats was passed
+pub(crate) fn print() {
+    if let Some(throughput) = THROUGHPUT.get() {
+        println!("{}", throughput.summary());
+    }
+}
+
+#[cfg(test)]
+mod throughput_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn summaries() {
+        let throughput = Throughput::default();
+        assert_eq!(throughput.summary(), "Removed no files.");
+
+        throughput.add(1000, 2_000_000, Duration::from_millis(1500));
+        throughput.add(500, 1_000_000, Duration::from_millis(500));
+        assert_eq!(
+            throughput.summary(),
+            "Removed 1500 files (3 MB) in 2.00s: 750 files/s, 1.50 MB/s"
+        );
+    }
+}
@@ -13,11 +13,13 @@ use std::path::Path;
 
 use crate::cache::caches::Cache;
 use crate::cache::*;
+use crate::hyperlink;
+use crate::messages::{self, Message};
+use crate::names;
 use crate::tables::format_table;
 use crate::top_items::common::*;
 
 use humansize::{FormatSize, DECIMAL};
-use rayon::prelude::*;
 
 #[derive(Debug)]
 struct BinInfo {
@@ -27,7 +29,7 @@ struct BinInfo {
 
 impl BinInfo {
     fn new(path: &Path) -> Self {
-        let name = path.file_name().unwrap().to_str().unwrap().to_string();
+        let name = names::file_name(path).into_owned();
         let size = fs::metadata(path)
             .unwrap_or_else(|_| panic!("Failed to get metadata of file '{}'", &path.display()))
             .len();
@@ -54,13 +56,15 @@ fn bininfo_list_to_string(limit: u32, mut collections_vec: Vec<BinInfo>) -> Stri
     if collections_vec.is_empty() {
         return String::new();
     }
-    // sort the BinInfo Vec in reverse
-    collections_vec.par_sort_by_key(|b| b.size);
-    collections_vec.reverse();
+    // biggest binary first
+    sort_biggest_first(&mut collections_vec, |b| b.size, |b| &b.name);
 
     let mut table_matrix: Vec<Vec<String>> = Vec::with_capacity(collections_vec.len() + 1);
 
-    table_matrix.push(vec!["Name".into(), "Size".into()]); // table header
+    table_matrix.push(vec![
+        messages::text(Message::TableName),
+        messages::text(Message::TableSize),
+    ]); // table header
 
     for bininfo in collections_vec.into_iter().take(limit as usize) {
         let size = bininfo.size_string();
@@ -81,7 +85,7 @@ pub(crate) fn binary_stats(path: &Path, limit: u32, bin_cache: &mut bin::BinaryC
     writeln!(
         output,
         "\nSummary of: {} ({} total)",
-        path.display(),
+        hyperlink::path(path),
         bin_cache.total_size().format_size(DECIMAL)
     )
     .unwrap();
@@ -94,10 +98,32 @@ pub(crate) fn binary_stats(path: &Path, limit: u32, bin_cache: &mut bin::BinaryC
     output
 }
 
+/// the biggest binaries as json for --top-cache-items --output-format json
+#[cfg(feature = "json")]
+pub(crate) fn binary_json(
+    path: &Path,
+    limit: u32,
+    bin_cache: &mut bin::BinaryCache,
+) -> Option<serde_json::Value> {
+    if !dir_exists(path) {
+        return None;
+    }
+    let mut binaries = bininfo_list_from_path(bin_cache);
+    // biggest binary first, like the table
+    sort_biggest_first(&mut binaries, |b| b.size, |b| &b.name);
+    let items = binaries
+        .into_iter()
+        .take(limit as usize)
+        .map(|bininfo| serde_json::json!({ "name": bininfo.name, "size": bininfo.size }))
+        .collect::<Vec<_>>();
+    Some(component_json(path, bin_cache.total_size(), &items))
+}
+
 #[cfg(test)]
 mod bininfo_struct {
     use super::*;
     use pretty_assertions::assert_eq;
+    use rayon::prelude::*;
 
     #[test]
     fn bininfo_new() {
@@ -359,4 +385,22 @@ mod top_crates_binaries {
         }
         assert_eq!(stats, wanted);
     }
+
+    #[test]
+    fn bininfo_sort_biggest_first() {
+        let bininfo = |name: &str, size| BinInfo {
+            name: String::from(name),
+            size,
+        };
+        let mut v = vec![
+            bininfo("c", 5),
+            bininfo("a", 1),
+            bininfo("b", 5),
+            bininfo("d", 7),
+        ];
+        sort_biggest_first(&mut v, |b| b.size, |b| &b.name);
+        let names = v.iter().map(|b| b.name.as_str()).collect::<Vec<&str>>();
+        // equal sizes by name, not in the order they were found in
+        assert_eq!(names, vec!["d", "b", "c", "a"]);
+    }
 }
@@ -8,6 +8,11 @@
 // except according to those terms.
 
 use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use rayon::slice::ParallelSliceMut;
+
+use crate::last_used::LastUsed;
 
 #[derive(Debug)]
 pub(crate) struct Pair<T> {
@@ -22,6 +27,44 @@ pub(crate) struct FileDesc {
     pub(crate) size: u64,
 }
 
+/// a component in the json output of --top-cache-items, the items are sorted biggest first
+#[cfg(feature = "json")]
+pub(crate) fn component_json(
+    path: &Path,
+    total_size: u64,
+    items: &[serde_json::Value],
+) -> serde_json::Value {
+    serde_json::json!({
+        "path": path.display().to_string(),
+        "total_size": total_size,
+        "items": items,
+    })
+}
+
+/// sort the items biggest first, items of the same size by name
+pub(crate) fn sort_biggest_first<T: Send>(
+    items: &mut [T],
+    size: impl Fn(&T) -> u64 + Sync,
+    name: impl Fn(&T) -> &str + Sync,
+) {
+    items.par_sort_by(|a, b| size(b).cmp(&size(a)).then_with(|| name(a).cmp(name(b))));
+}
+
+/// the most recent use of any item of the group, for example of all versions of a crate
+/// only looked up for the groups that are listed, a directory has to be walked for this
+pub(crate) fn last_used_of(
+    file_descs: &[FileDesc],
+    name: &str,
+    last_used: &LastUsed,
+) -> Option<SystemTime> {
+    last_used.newest(
+        file_descs
+            .iter()
+            .filter(|file_desc| file_desc.name == name)
+            .map(|file_desc| file_desc.path.as_path()),
+    )
+}
+
 pub(crate) fn dir_exists(path: &Path) -> bool {
     // check if a directory exists and print an warning message if not
     if path.exists() {
@@ -10,34 +10,40 @@
 use std::fmt::Write as _;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
 use crate::cache::caches::Cache;
 use crate::cache::*;
+use crate::cancel::is_cancelled;
+use crate::hyperlink;
+#[cfg(feature = "json")]
+use crate::last_used::json_last_used;
+use crate::last_used::{format_last_used, LastUsed};
+use crate::messages::{self, Message};
+use crate::names;
 use crate::tables::format_table;
-use crate::top_items::common::{dir_exists, FileDesc, Pair};
+#[cfg(feature = "json")]
+use crate::top_items::common::component_json;
+use crate::top_items::common::{dir_exists, last_used_of, sort_biggest_first, FileDesc, Pair};
+use crate::walk::walk;
 
 use humansize::{FormatSize, DECIMAL};
 use rayon::prelude::*;
-use walkdir::WalkDir;
+use walkdir::DirEntry;
 
 #[inline]
 fn name_from_path(path: &Path) -> String {
     // path: ~/.cargo/git/db/yaml-rust-07c50cf5815b3a80
-    let filename = path.file_name().unwrap().to_str().unwrap().to_string();
-    // filename: yaml-rust-07c50cf5815b3a80
-    let mut crate_name = filename.split('-').collect::<Vec<&str>>();
-    let _ = crate_name.pop(); // remove the last item
-                              // crate_name: [yaml rust]
-    crate_name.join("-")
+    // yaml-rust-07c50cf5815b3a80 -> yaml-rust
+    names::strip_hash(&names::file_name(path)).to_string()
 }
 
 impl FileDesc {
-    fn new_from_git_bare(path: &Path) -> Self {
+    pub(crate) fn new_from_git_bare(path: &Path) -> Self {
         let name = name_from_path(path);
-        let walkdir = WalkDir::new(path.display().to_string());
-        let size = walkdir
-            .into_iter()
-            .map(|e| e.unwrap().path().to_owned())
+        let size = walk(path)
+            .take_while(|_| !is_cancelled())
+            .map(DirEntry::into_path)
             .filter(|f| f.exists())
             .collect::<Vec<_>>()
             .par_iter()
@@ -65,6 +71,8 @@ pub(crate) struct RepoInfo {
     size: u64,
     counter: u32,
     total_size: u64, // sorted by this
+    /// the most recent use of any item, only looked up for the items that are listed
+    last_used: Option<SystemTime>,
 }
 
 impl RepoInfo {
@@ -73,17 +81,14 @@ impl RepoInfo {
         let name: String;
         if path.exists() {
             // get the string
-            let name_tmp = path.file_name().unwrap().to_str().unwrap().to_string();
             // remove the hash from the path (mdbook-e6b52d90d4246c70 => mdbook)
-            let mut tmp_name = name_tmp.split('-').collect::<Vec<_>>();
-            let _ = tmp_name.pop(); // remove the hash
-            name = tmp_name.join("-"); // rejoin with "-"
+            name = names::strip_hash(&names::file_name(path)).to_string();
             size = fs::metadata(path)
                 .unwrap_or_else(|_| panic!("Failed to get metadata of file '{}'", &path.display()))
                 .len();
         } else {
             // tests
-            name = path.file_name().unwrap().to_str().unwrap().to_string();
+            name = names::file_name(path).into_owned();
             size = 0;
         }
         Self {
@@ -91,6 +96,7 @@ impl RepoInfo {
             size,
             counter,
             total_size,
+            last_used: None,
         }
     }
 }
@@ -186,7 +192,7 @@ fn stats_from_file_desc_list(file_descs: Vec<FileDesc>) -> Vec<RepoInfo> {
                 counter = 0;
                 total_size = 0;
             }
-        };
+        }
 
         // switch and queue next()
         state.previous = state.current;
@@ -196,34 +202,61 @@ fn stats_from_file_desc_list(file_descs: Vec<FileDesc>) -> Vec<RepoInfo> {
     out
 }
 
+/// the `limit` biggest groups, with the last use of each of them
+fn biggest_with_last_used(
+    file_descs: &[FileDesc],
+    limit: u32,
+    last_used: &LastUsed,
+) -> Vec<RepoInfo> {
+    let mut summary = stats_from_file_desc_list(file_descs.to_vec());
+    sort_biggest_first(&mut summary, |info| info.total_size, |info| &info.name);
+    summary.truncate(limit as usize);
+    for info in &mut summary {
+        info.last_used = last_used_of(file_descs, &info.name, last_used);
+    }
+    summary
+}
+
 pub(crate) fn chkout_list_to_string(limit: u32, mut collections_vec: Vec<RepoInfo>) -> String {
     if collections_vec.is_empty() {
         return String::new();
     }
     // sort the RepoInfo Vec in reverse, biggest item first
-    collections_vec.par_sort_by_key(|grb| grb.total_size);
-    collections_vec.reverse();
+    sort_biggest_first(&mut collections_vec, |grb| grb.total_size, |grb| &grb.name);
     let mut table_matrix: Vec<Vec<String>> = Vec::with_capacity(collections_vec.len() + 1);
 
     table_matrix.push(vec![
-        String::from("Name"),
-        String::from("Count"),
-        String::from("Average"),
-        String::from("Total"),
+        messages::text(Message::TableName),
+        messages::text(Message::TableCount),
+        messages::text(Message::TableAverage),
+        messages::text(Message::TableTotal),
     ]);
+    // the column only shows up if we know anything about the listed items
+    let listed = collections_vec
+        .into_iter()
+        .take(limit as usize)
+        .collect::<Vec<_>>();
+    let show_last_used = listed.iter().any(|item| item.last_used.is_some());
+    if show_last_used {
+        table_matrix[0].push(messages::text(Message::TableLastUsed));
+    }
 
-    for repoinfo in collections_vec.into_iter().take(limit as usize) {
+    for repoinfo in listed {
         #[allow(clippy::integer_division)]
         let average_size = (repoinfo.total_size / u64::from(repoinfo.counter)).format_size(DECIMAL);
 
         let total_size = repoinfo.total_size.format_size(DECIMAL);
 
-        table_matrix.push(vec![
+        let mut row = vec![
             repoinfo.name,
             repoinfo.counter.to_string(),
             average_size,
             total_size,
-        ]);
+        ];
+        if show_last_used {
+            row.push(format_last_used(repoinfo.last_used));
+        }
+        table_matrix.push(row);
     }
     format_table(&table_matrix, 0)
 }
@@ -233,6 +266,7 @@ pub(crate) fn git_repos_bare_stats(
     path: &Path,
     limit: u32,
     bare_repos_cache: &mut git_bare_repos::GitRepoCache,
+    last_used: &LastUsed,
 ) -> String {
     let mut output = String::new();
     // don't crash if the directory does not exist (issue #9)
@@ -243,19 +277,45 @@ pub(crate) fn git_repos_bare_stats(
     writeln!(
         output,
         "\nSummary of: {} ({} total)",
-        path.display(),
+        hyperlink::path(path),
         bare_repos_cache.total_size().format_size(DECIMAL)
     )
     .unwrap();
 
     let collections_vec = file_desc_from_path(bare_repos_cache);
-    let summary: Vec<RepoInfo> = stats_from_file_desc_list(collections_vec);
+    let summary = biggest_with_last_used(&collections_vec, limit, last_used);
     let tmp = chkout_list_to_string(limit, summary);
 
     output.push_str(&tmp);
     output
 }
 
+/// the biggest items as json for --top-cache-items --output-format json
+#[cfg(feature = "json")]
+pub(crate) fn git_repos_bare_json(
+    path: &Path,
+    limit: u32,
+    bare_repos_cache: &mut git_bare_repos::GitRepoCache,
+    last_used: &LastUsed,
+) -> Option<serde_json::Value> {
+    if !dir_exists(path) {
+        return None;
+    }
+    // biggest item first, like the table
+    let items = biggest_with_last_used(&file_desc_from_path(bare_repos_cache), limit, last_used)
+        .into_iter()
+        .map(|info| {
+            serde_json::json!({
+                "name": info.name,
+                "count": info.counter,
+                "total_size": info.total_size,
+                "last_used": json_last_used(info.last_used),
+            })
+        })
+        .collect::<Vec<_>>();
+    Some(component_json(path, bare_repos_cache.total_size(), &items))
+}
+
 #[cfg(test)]
 mod top_crates_git_repos_bare {
     use super::*;
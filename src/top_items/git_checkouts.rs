@@ -10,44 +10,42 @@
 use std::fmt::Write as _;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
 use crate::cache::caches::Cache;
 use crate::cache::*;
+use crate::cancel::is_cancelled;
+use crate::hyperlink;
+#[cfg(feature = "json")]
+use crate::last_used::json_last_used;
+use crate::last_used::{format_last_used, LastUsed};
+use crate::messages::{self, Message};
+use crate::names;
 use crate::tables::format_table;
-use crate::top_items::common::{dir_exists, FileDesc, Pair};
+#[cfg(feature = "json")]
+use crate::top_items::common::component_json;
+use crate::top_items::common::{dir_exists, last_used_of, sort_biggest_first, FileDesc, Pair};
+use crate::walk::walk;
 
 use humansize::{FormatSize, DECIMAL};
 use rayon::prelude::*;
-use walkdir::WalkDir;
+use walkdir::DirEntry;
 
 #[inline]
 fn name_from_path(path: &Path) -> String {
     // path:  ~/.cargo/git/checkouts/cargo-cache-16826c8e13331adc/0f9966c
-    let dir = path
-        .iter()
-        .map(|p| p.to_str().unwrap())
-        .rev()
-        .nth(1)
-        .unwrap();
-    // dir: cargo-cache-16826c8e13331adc
-    // skip the last element
-    let mut all_but_last = dir.split('-').rev().skip(1).collect::<Vec<_>>(); //  cannot .rev() again, need to collect again
-                                                                             // vec: cache cargo
-    all_but_last.reverse();
-    // vec: cargo cache
-    all_but_last.join("-")
-    // "cargo-cache"
+    // the parent of the checkout: cargo-cache-16826c8e13331adc -> cargo-cache
+    let dir = path.parent().map(names::file_name).unwrap_or_default();
+    names::strip_hash(&dir).to_string()
 }
 
 impl FileDesc {
-    fn new_from_git_checkouts(path: &Path) -> Self {
+    pub(crate) fn new_from_git_checkouts(path: &Path) -> Self {
         let name = name_from_path(path);
 
-        let walkdir = WalkDir::new(path.display().to_string());
-
-        let size = walkdir
-            .into_iter()
-            .map(|e| e.unwrap().path().to_owned())
+        let size = walk(path)
+            .take_while(|_| !is_cancelled())
+            .map(DirEntry::into_path)
             .filter(|f| f.exists())
             .collect::<Vec<_>>()
             .par_iter()
@@ -75,6 +73,8 @@ pub(crate) struct ChkInfo {
     size: u64,
     counter: u32,
     total_size: u64, // sorted by this
+    /// the most recent use of any item, only looked up for the items that are listed
+    last_used: Option<SystemTime>,
 }
 
 impl ChkInfo {
@@ -87,14 +87,9 @@ impl ChkInfo {
             size = fs::metadata(path)
                 .unwrap_or_else(|_| panic!("Failed to get metadata of file '{}'", &path.display()))
                 .len();
-            let mut p = path.to_path_buf();
-            let _ = p.pop();
-            let name_tmp = p.file_name().unwrap().to_str().unwrap().to_string();
-            let mut tmp = name_tmp.split('-').collect::<Vec<_>>();
-            let _ = tmp.pop();
-            name = tmp.join("-");
+            name = name_from_path(path);
         } else {
-            let name_tmp = path.file_name().unwrap().to_str().unwrap().to_string();
+            let name_tmp = names::file_name(path).into_owned();
             size = 0;
             name = name_tmp;
         }
@@ -103,6 +98,7 @@ impl ChkInfo {
             size,
             counter,
             total_size,
+            last_used: None,
         }
     }
 }
@@ -199,7 +195,7 @@ fn stats_from_file_desc_list(file_descs: Vec<FileDesc>) -> Vec<ChkInfo> {
                 counter = 0;
                 total_size = 0;
             }
-        };
+        }
 
         // switch and queue next()
         state.previous = state.current;
@@ -209,34 +205,61 @@ fn stats_from_file_desc_list(file_descs: Vec<FileDesc>) -> Vec<ChkInfo> {
 }
 
 #[inline] // only used in one place
+/// the `limit` biggest groups, with the last use of each of them
+fn biggest_with_last_used(
+    file_descs: &[FileDesc],
+    limit: u32,
+    last_used: &LastUsed,
+) -> Vec<ChkInfo> {
+    let mut summary = stats_from_file_desc_list(file_descs.to_vec());
+    sort_biggest_first(&mut summary, |info| info.total_size, |info| &info.name);
+    summary.truncate(limit as usize);
+    for info in &mut summary {
+        info.last_used = last_used_of(file_descs, &info.name, last_used);
+    }
+    summary
+}
+
 fn chkout_list_to_string(limit: u32, mut collections_vec: Vec<ChkInfo>) -> String {
     if collections_vec.is_empty() {
         return String::new();
     }
 
     // sort the ChkInfo Vec in reverse
-    collections_vec.par_sort_by_key(|gc| gc.total_size);
-    collections_vec.reverse();
+    sort_biggest_first(&mut collections_vec, |gc| gc.total_size, |gc| &gc.name);
     let mut table_matrix: Vec<Vec<String>> = Vec::with_capacity(collections_vec.len() + 1);
 
     table_matrix.push(vec![
-        String::from("Name"),
-        String::from("Count"),
-        String::from("Average"),
-        String::from("Total"),
+        messages::text(Message::TableName),
+        messages::text(Message::TableCount),
+        messages::text(Message::TableAverage),
+        messages::text(Message::TableTotal),
     ]);
+    // the column only shows up if we know anything about the listed items
+    let listed = collections_vec
+        .into_iter()
+        .take(limit as usize)
+        .collect::<Vec<_>>();
+    let show_last_used = listed.iter().any(|item| item.last_used.is_some());
+    if show_last_used {
+        table_matrix[0].push(messages::text(Message::TableLastUsed));
+    }
 
-    for chkout in collections_vec.into_iter().take(limit as usize) {
+    for chkout in listed {
         #[allow(clippy::integer_division)]
         let average_size = (chkout.total_size / u64::from(chkout.counter)).format_size(DECIMAL);
         let total_size = chkout.total_size.format_size(DECIMAL);
 
-        table_matrix.push(vec![
+        let mut row = vec![
             chkout.name,
             chkout.counter.to_string(),
             average_size,
             total_size,
-        ]);
+        ];
+        if show_last_used {
+            row.push(format_last_used(chkout.last_used));
+        }
+        table_matrix.push(row);
     }
 
     format_table(&table_matrix, 0)
@@ -247,6 +270,7 @@ pub(crate) fn git_checkouts_stats(
     path: &Path,
     limit: u32,
     checkouts_cache: &mut git_checkouts::GitCheckoutCache,
+    last_used: &LastUsed,
 ) -> String {
     let mut output = String::new();
     // don't crash if the directory does not exist (issue #9)
@@ -257,13 +281,13 @@ pub(crate) fn git_checkouts_stats(
     writeln!(
         output,
         "\nSummary of: {} ({} total)",
-        path.display(),
+        hyperlink::path(path),
         checkouts_cache.total_size().format_size(DECIMAL)
     )
     .unwrap();
 
     let collections_vec = file_desc_from_path(checkouts_cache);
-    let summary: Vec<ChkInfo> = stats_from_file_desc_list(collections_vec);
+    let summary = biggest_with_last_used(&collections_vec, limit, last_used);
 
     let tmp = chkout_list_to_string(limit, summary);
     output.push_str(&tmp);
@@ -271,6 +295,32 @@ pub(crate) fn git_checkouts_stats(
     output
 }
 
+/// the biggest items as json for --top-cache-items --output-format json
+#[cfg(feature = "json")]
+pub(crate) fn git_checkouts_json(
+    path: &Path,
+    limit: u32,
+    checkouts_cache: &mut git_checkouts::GitCheckoutCache,
+    last_used: &LastUsed,
+) -> Option<serde_json::Value> {
+    if !dir_exists(path) {
+        return None;
+    }
+    // biggest item first, like the table
+    let items = biggest_with_last_used(&file_desc_from_path(checkouts_cache), limit, last_used)
+        .into_iter()
+        .map(|info| {
+            serde_json::json!({
+                "name": info.name,
+                "count": info.counter,
+                "total_size": info.total_size,
+                "last_used": json_last_used(info.last_used),
+            })
+        })
+        .collect::<Vec<_>>();
+    Some(component_json(path, checkouts_cache.total_size(), &items))
+}
+
 #[cfg(test)]
 mod top_crates_git_checkouts {
     use super::*;
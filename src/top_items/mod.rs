@@ -11,5 +11,6 @@ pub(crate) mod binaries;
 pub(crate) mod common;
 pub(crate) mod git_bare_repos;
 pub(crate) mod git_checkouts;
+pub(crate) mod registry_index;
 pub(crate) mod registry_pkg_cache;
 pub(crate) mod registry_sources;
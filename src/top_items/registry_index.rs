@@ -0,0 +1,247 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// registry indices
+// A git index consists of the git objects in .git, the checked out index files (older cargo
+// versions) and the metadata cargo caches in .cache. A sparse index only has the .cache.
+// Splitting the sizes up shows whether a gc (.git) or removing the index is worth it.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+use crate::cache::caches::{get_cache_name, RegistrySubCache, RegistrySuperCache};
+use crate::cache::*;
+use crate::cancel::is_cancelled;
+use crate::hyperlink;
+use crate::ignore::is_ignored;
+use crate::messages::{self, Message};
+use crate::tables::format_table;
+#[cfg(feature = "json")]
+use crate::top_items::common::component_json;
+use crate::top_items::common::{dir_exists, sort_biggest_first};
+
+use humansize::{FormatSize, DECIMAL};
+use walkdir::WalkDir;
+
+/// the sizes of the parts of a registry index
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct IndexInfo {
+    name: String,
+    /// .git and the checked out index files, None for sparse indices
+    git: Option<(u64, u64)>,
+    /// the number and size of the metadata files in .cache
+    cache_files: usize,
+    cache_size: u64,
+}
+
+impl IndexInfo {
+    fn total_size(&self) -> u64 {
+        self.git.map_or(0, |(objects, files)| objects + files) + self.cache_size
+    }
+}
+
+/// number and size of the files below `path`
+fn files_and_size(path: &Path) -> (usize, u64) {
+    WalkDir::new(path)
+        .into_iter()
+        .filter_entry(|entry| !is_ignored(entry.path()))
+        .take_while(|_| !is_cancelled())
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| entry.metadata().ok())
+        .fold((0, 0), |(files, size), metadata| {
+            (files + 1, size + metadata.len())
+        })
+}
+
+fn index_info(path: &Path) -> IndexInfo {
+    let (cache_files, cache_size) = files_and_size(&path.join(".cache"));
+
+    let git = path.join(".git").is_dir().then(|| {
+        let (_, objects) = files_and_size(&path.join(".git"));
+        // everything that is neither .git nor .cache is part of the checkout
+        let checked_out = fs::read_dir(path)
+            .map(|entries| {
+                entries
+                    .filter_map(Result::ok)
+                    .filter(|entry| entry.file_name() != ".git" && entry.file_name() != ".cache")
+                    .map(|entry| files_and_size(&entry.path()).1)
+                    .sum()
+            })
+            .unwrap_or_default();
+        (objects, checked_out)
+    });
+
+    IndexInfo {
+        name: get_cache_name(path),
+        git,
+        cache_files,
+        cache_size,
+    }
+}
+
+pub(crate) fn index_list_to_string(limit: u32, mut infos: Vec<IndexInfo>) -> String {
+    if infos.is_empty() {
+        return String::new();
+    }
+    // biggest index first
+    sort_biggest_first(&mut infos, IndexInfo::total_size, |info| &info.name);
+
+    let mut table_matrix: Vec<Vec<String>> = Vec::with_capacity(infos.len() + 1);
+    table_matrix.push(vec![
+        messages::text(Message::TableName),
+        messages::text(Message::TableKind),
+        String::from(".git"),
+        messages::text(Message::TableCheckout),
+        messages::text(Message::TableCachedFiles),
+        messages::text(Message::TableCacheSize),
+        messages::text(Message::TableTotal),
+    ]);
+
+    for info in infos.into_iter().take(limit as usize) {
+        let total_size = info.total_size().format_size(DECIMAL);
+        let (kind, objects, checked_out) = match info.git {
+            Some((objects, checked_out)) => (
+                "git",
+                objects.format_size(DECIMAL),
+                checked_out.format_size(DECIMAL),
+            ),
+            None => ("sparse", String::from("-"), String::from("-")),
+        };
+        table_matrix.push(vec![
+            info.name,
+            kind.to_string(),
+            objects,
+            checked_out,
+            info.cache_files.to_string(),
+            info.cache_size.format_size(DECIMAL),
+            total_size,
+        ]);
+    }
+    format_table(&table_matrix, 0)
+}
+
+pub(crate) fn registry_index_stats(
+    path: &Path,
+    limit: u32,
+    registry_index_caches: &mut registry_index::RegistryIndicesCache,
+) -> String {
+    let mut output = String::new();
+    // don't crash if the directory does not exist (issue #9)
+    if !dir_exists(path) {
+        return output;
+    }
+
+    writeln!(
+        output,
+        "\nSummary of: {} ({} total)",
+        hyperlink::path(path),
+        registry_index_caches.total_size().format_size(DECIMAL)
+    )
+    .unwrap();
+
+    let infos = registry_index_caches
+        .caches()
+        .iter()
+        .map(|index| index_info(index.path()))
+        .collect::<Vec<IndexInfo>>();
+    output.push_str(&index_list_to_string(limit, infos));
+    output
+}
+
+/// the biggest indices as json for --top-cache-items --output-format json
+#[cfg(feature = "json")]
+pub(crate) fn registry_index_json(
+    path: &Path,
+    limit: u32,
+    registry_index_caches: &mut registry_index::RegistryIndicesCache,
+) -> Option<serde_json::Value> {
+    if !dir_exists(path) {
+        return None;
+    }
+    let mut infos = registry_index_caches
+        .caches()
+        .iter()
+        .map(|index| index_info(index.path()))
+        .collect::<Vec<IndexInfo>>();
+    // biggest index first, like the table
+    sort_biggest_first(&mut infos, IndexInfo::total_size, |info| &info.name);
+    let items = infos
+        .into_iter()
+        .take(limit as usize)
+        .map(|info| {
+            serde_json::json!({
+                "name": info.name,
+                "kind": if info.git.is_some() { "git" } else { "sparse" },
+                "git_size": info.git.map(|(objects, _)| objects),
+                "checkout_size": info.git.map(|(_, checked_out)| checked_out),
+                "cached_files": info.cache_files,
+                "cache_size": info.cache_size,
+                "total_size": info.total_size(),
+            })
+        })
+        .collect::<Vec<_>>();
+    Some(component_json(
+        path,
+        registry_index_caches.total_size(),
+        &items,
+    ))
+}
+
+#[cfg(test)]
+mod top_crates_registry_index {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn git_and_sparse() {
+        let tmp = tempfile::tempdir().unwrap();
+        let git = tmp.path().join("github.com-1ecc6299db9ec823");
+        fs::create_dir_all(git.join(".git/objects")).unwrap();
+        fs::create_dir_all(git.join(".cache/se/rd")).unwrap();
+        fs::create_dir_all(git.join("se/rd")).unwrap();
+        fs::write(git.join(".git/objects/pack"), vec![0; 1000]).unwrap();
+        fs::write(git.join(".cache/se/rd/serde"), vec![0; 20]).unwrap();
+        fs::write(git.join("se/rd/serde"), vec![0; 300]).unwrap();
+        fs::write(git.join("config.json"), vec![0; 4]).unwrap();
+
+        let sparse = tmp.path().join("index.crates.io-6f17d22bba15001f");
+        fs::create_dir_all(sparse.join(".cache/se/rd")).unwrap();
+        fs::write(sparse.join(".cache/se/rd/serde"), vec![0; 50]).unwrap();
+        fs::write(sparse.join(".cache/se/rd/serde_json"), vec![0; 50]).unwrap();
+
+        let git_info = index_info(&git);
+        assert_eq!(
+            git_info,
+            IndexInfo {
+                name: String::from("github.com"),
+                git: Some((1000, 304)),
+                cache_files: 1,
+                cache_size: 20,
+            }
+        );
+        let sparse_info = index_info(&sparse);
+        assert_eq!(sparse_info.git, None);
+        assert_eq!(sparse_info.cache_files, 2);
+        assert_eq!(sparse_info.total_size(), 100);
+
+        assert_eq!(
+            index_list_to_string(5, vec![sparse_info, git_info]),
+            "Name            Kind   .git Checkout Cached files Cache size Total\n\
+             github.com      git    1 kB 304 B    1            20 B       1.32 kB\n\
+             index.crates.io sparse -    -        2            100 B      100 B\n"
+        );
+    }
+
+    #[test]
+    fn empty() {
+        assert_eq!(index_list_to_string(5, Vec::new()), String::new());
+    }
+}
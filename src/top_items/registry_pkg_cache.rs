@@ -10,19 +10,27 @@
 use std::fmt::Write as _;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
 use crate::cache::caches::RegistrySuperCache;
 use crate::cache::registry_pkg_cache;
+use crate::hyperlink;
+#[cfg(feature = "json")]
+use crate::last_used::json_last_used;
+use crate::last_used::{format_last_used, LastUsed};
+use crate::messages::{self, Message};
+use crate::names;
 use crate::tables::format_table;
-use crate::top_items::common::{dir_exists, FileDesc, Pair};
+#[cfg(feature = "json")]
+use crate::top_items::common::component_json;
+use crate::top_items::common::{dir_exists, last_used_of, sort_biggest_first, FileDesc, Pair};
 
 use humansize::{FormatSize, DECIMAL};
-use rayon::prelude::*;
 
 #[inline]
 fn name_from_path(path: &Path) -> String {
     // path:  .../xz2-0.1.4.crate
-    let last_item = path.file_name().unwrap().to_str().unwrap().to_string();
+    let last_item = names::file_name(path).into_owned();
     // last_item: xz2-0.1.4.crate
     let mut v = last_item.split('-').collect::<Vec<_>>();
     let _ = v.pop(); // remove everything after last "-"
@@ -52,6 +60,8 @@ pub(crate) struct RgchInfo {
     size: u64,
     counter: u32,
     total_size: u64, // sort by this
+    /// the most recent use of any item, only looked up for the items that are listed
+    last_used: Option<SystemTime>,
 }
 
 impl RgchInfo {
@@ -62,12 +72,12 @@ impl RgchInfo {
             size = fs::metadata(path)
                 .unwrap_or_else(|_| panic!("Failed to get metadata of file '{}'", &path.display()))
                 .len();
-            let n = path.file_name().unwrap().to_str().unwrap().to_string();
+            let n = names::file_name(path).into_owned();
             let mut v = n.split('-').collect::<Vec<_>>();
             let _ = v.pop();
             name = v.join("-");
         } else {
-            name = path.file_name().unwrap().to_str().unwrap().to_string();
+            name = names::file_name(path).into_owned();
 
             size = 0;
         }
@@ -77,6 +87,7 @@ impl RgchInfo {
             size,
             counter,
             total_size,
+            last_used: None,
         }
     }
 }
@@ -174,7 +185,7 @@ fn stats_from_file_desc_list(file_descs: Vec<FileDesc>) -> Vec<RgchInfo> {
                 counter = 0;
                 total_size = 0;
             }
-        };
+        }
 
         // switch and queue next()
         state.previous = state.current;
@@ -183,35 +194,62 @@ fn stats_from_file_desc_list(file_descs: Vec<FileDesc>) -> Vec<RgchInfo> {
     out
 }
 
+/// the `limit` biggest groups, with the last use of each of them
+fn biggest_with_last_used(
+    file_descs: &[FileDesc],
+    limit: u32,
+    last_used: &LastUsed,
+) -> Vec<RgchInfo> {
+    let mut summary = stats_from_file_desc_list(file_descs.to_vec());
+    sort_biggest_first(&mut summary, |info| info.total_size, |info| &info.name);
+    summary.truncate(limit as usize);
+    for info in &mut summary {
+        info.last_used = last_used_of(file_descs, &info.name, last_used);
+    }
+    summary
+}
+
 pub(crate) fn regcache_list_to_string(limit: u32, mut collections_vec: Vec<RgchInfo>) -> String {
     if collections_vec.is_empty() {
         return String::new();
     }
 
     // sort the RepoInfo Vec in reverse, biggest item first
-    collections_vec.par_sort_by_key(|rpc| rpc.total_size);
-    collections_vec.reverse();
+    sort_biggest_first(&mut collections_vec, |rpc| rpc.total_size, |rpc| &rpc.name);
     let mut table_matrix: Vec<Vec<String>> = Vec::with_capacity(collections_vec.len() + 1);
 
     table_matrix.push(vec![
-        String::from("Name"),
-        String::from("Count"),
-        String::from("Average"),
-        String::from("Total"),
+        messages::text(Message::TableName),
+        messages::text(Message::TableCount),
+        messages::text(Message::TableAverage),
+        messages::text(Message::TableTotal),
     ]);
+    // the column only shows up if we know anything about the listed items
+    let listed = collections_vec
+        .into_iter()
+        .take(limit as usize)
+        .collect::<Vec<_>>();
+    let show_last_used = listed.iter().any(|item| item.last_used.is_some());
+    if show_last_used {
+        table_matrix[0].push(messages::text(Message::TableLastUsed));
+    }
 
-    for regcache in collections_vec.into_iter().take(limit as usize) {
+    for regcache in listed {
         #[allow(clippy::integer_division)]
         let average_size = (regcache.total_size / u64::from(regcache.counter)).format_size(DECIMAL);
 
         let total_size = regcache.total_size.format_size(DECIMAL);
 
-        table_matrix.push(vec![
+        let mut row = vec![
             regcache.name,
             regcache.counter.to_string(),
             average_size,
             total_size,
-        ]);
+        ];
+        if show_last_used {
+            row.push(format_last_used(regcache.last_used));
+        }
+        table_matrix.push(row);
     }
     format_table(&table_matrix, 0)
 }
@@ -221,6 +259,7 @@ pub(crate) fn registry_pkg_cache_stats(
     path: &Path,
     limit: u32,
     registry_pkg_caches: &mut registry_pkg_cache::RegistryPkgCaches,
+    last_used: &LastUsed,
 ) -> String {
     let mut stdout = String::new();
     // don't crash if the directory does not exist (issue #9)
@@ -231,19 +270,53 @@ pub(crate) fn registry_pkg_cache_stats(
     writeln!(
         stdout,
         "\nSummary of: {} ({} total)",
-        path.display(),
+        hyperlink::path(path),
         registry_pkg_caches.total_size().format_size(DECIMAL)
     )
     .unwrap();
 
     let file_descs: Vec<FileDesc> = file_desc_list_from_path(registry_pkg_caches);
-    let summary: Vec<RgchInfo> = stats_from_file_desc_list(file_descs);
+    let summary = biggest_with_last_used(&file_descs, limit, last_used);
     let string = regcache_list_to_string(limit, summary);
     stdout.push_str(&string);
 
     stdout
 }
 
+/// the biggest items as json for --top-cache-items --output-format json
+#[cfg(feature = "json")]
+pub(crate) fn registry_pkg_cache_json(
+    path: &Path,
+    limit: u32,
+    registry_pkg_caches: &mut registry_pkg_cache::RegistryPkgCaches,
+    last_used: &LastUsed,
+) -> Option<serde_json::Value> {
+    if !dir_exists(path) {
+        return None;
+    }
+    // biggest item first, like the table
+    let items = biggest_with_last_used(
+        &file_desc_list_from_path(registry_pkg_caches),
+        limit,
+        last_used,
+    )
+    .into_iter()
+    .map(|info| {
+        serde_json::json!({
+            "name": info.name,
+            "count": info.counter,
+            "total_size": info.total_size,
+            "last_used": json_last_used(info.last_used),
+        })
+    })
+    .collect::<Vec<_>>();
+    Some(component_json(
+        path,
+        registry_pkg_caches.total_size(),
+        &items,
+    ))
+}
+
 #[cfg(test)]
 mod top_crates_registry_pkg_cache {
     use super::*;
@@ -10,20 +10,31 @@
 use std::fmt::Write as _;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
 use crate::cache::caches::RegistrySuperCache;
 use crate::cache::*;
+use crate::cancel::is_cancelled;
+use crate::hyperlink;
+#[cfg(feature = "json")]
+use crate::last_used::json_last_used;
+use crate::last_used::{format_last_used, LastUsed};
+use crate::messages::{self, Message};
+use crate::names;
 use crate::tables::format_table;
-use crate::top_items::common::{dir_exists, FileDesc, Pair};
+#[cfg(feature = "json")]
+use crate::top_items::common::component_json;
+use crate::top_items::common::{dir_exists, last_used_of, sort_biggest_first, FileDesc, Pair};
+use crate::walk::walk;
 
 use humansize::{FormatSize, DECIMAL};
 use rayon::prelude::*;
-use walkdir::WalkDir;
+use walkdir::DirEntry;
 
 #[inline]
 fn name_from_path(path: &Path) -> String {
     // path:  .../xz2-0.1.4.crate
-    let last_item = path.file_name().unwrap().to_str().unwrap().to_string();
+    let last_item = names::file_name(path).into_owned();
     // last_item: xz2-0.1.4.crate
     let mut v = last_item.split('-').collect::<Vec<_>>();
     let _ = v.pop(); // remove everything after last "-"
@@ -35,11 +46,9 @@ fn name_from_path(path: &Path) -> String {
 impl FileDesc {
     pub(crate) fn new_from_reg_src(path: &Path) -> Self {
         let name = name_from_path(path);
-        let walkdir = WalkDir::new(path.display().to_string());
-
-        let size = walkdir
-            .into_iter()
-            .map(|e| e.unwrap().path().to_owned())
+        let size = walk(path)
+            .take_while(|_| !is_cancelled())
+            .map(DirEntry::into_path)
             .filter(|f| f.exists())
             .collect::<Vec<_>>()
             .par_iter()
@@ -67,6 +76,8 @@ pub(crate) struct RgSrcInfo {
     size: u64,
     counter: u32,
     total_size: u64, // sort by this
+    /// the most recent use of any item, only looked up for the items that are listed
+    last_used: Option<SystemTime>,
 }
 
 impl RgSrcInfo {
@@ -77,12 +88,12 @@ impl RgSrcInfo {
             size = fs::metadata(path)
                 .unwrap_or_else(|_| panic!("Failed to get metadata of file '{}'", &path.display()))
                 .len();
-            let n = path.file_name().unwrap().to_str().unwrap().to_string();
+            let n = names::file_name(path).into_owned();
             let mut v = n.split('-').collect::<Vec<_>>();
             let _ = v.pop();
             name = v.join("-");
         } else {
-            name = path.file_name().unwrap().to_str().unwrap().to_string();
+            name = names::file_name(path).into_owned();
             size = 0;
         }
         Self {
@@ -90,6 +101,7 @@ impl RgSrcInfo {
             size,
             counter,
             total_size,
+            last_used: None,
         }
     }
 }
@@ -187,7 +199,7 @@ fn stats_from_file_desc_list(file_descs: Vec<FileDesc>) -> Vec<RgSrcInfo> {
                 counter = 0;
                 total_size = 0;
             }
-        };
+        }
 
         // switch and queue next()
         state.previous = state.current;
@@ -195,36 +207,63 @@ fn stats_from_file_desc_list(file_descs: Vec<FileDesc>) -> Vec<RgSrcInfo> {
     }
     out
 }
+/// the `limit` biggest groups, with the last use of each of them
+fn biggest_with_last_used(
+    file_descs: &[FileDesc],
+    limit: u32,
+    last_used: &LastUsed,
+) -> Vec<RgSrcInfo> {
+    let mut summary = stats_from_file_desc_list(file_descs.to_vec());
+    sort_biggest_first(&mut summary, |info| info.total_size, |info| &info.name);
+    summary.truncate(limit as usize);
+    for info in &mut summary {
+        info.last_used = last_used_of(file_descs, &info.name, last_used);
+    }
+    summary
+}
+
 pub(crate) fn reg_src_list_to_string(limit: u32, mut collections_vec: Vec<RgSrcInfo>) -> String {
     if collections_vec.is_empty() {
         return String::new();
     }
 
     // sort the RepoImfo Vec in reverse, biggest item first
-    collections_vec.par_sort_by_key(|rs| rs.total_size);
-    collections_vec.reverse();
+    sort_biggest_first(&mut collections_vec, |rs| rs.total_size, |rs| &rs.name);
 
     let mut table_matrix: Vec<Vec<String>> = Vec::with_capacity(collections_vec.len() + 1);
 
     table_matrix.push(vec![
-        String::from("Name"),
-        String::from("Count"),
-        String::from("Average"),
-        String::from("Total"),
+        messages::text(Message::TableName),
+        messages::text(Message::TableCount),
+        messages::text(Message::TableAverage),
+        messages::text(Message::TableTotal),
     ]);
+    // the column only shows up if we know anything about the listed items
+    let listed = collections_vec
+        .into_iter()
+        .take(limit as usize)
+        .collect::<Vec<_>>();
+    let show_last_used = listed.iter().any(|item| item.last_used.is_some());
+    if show_last_used {
+        table_matrix[0].push(messages::text(Message::TableLastUsed));
+    }
 
-    for regsrc in collections_vec.into_iter().take(limit as usize) {
+    for regsrc in listed {
         #[allow(clippy::integer_division)]
         let average_size = (regsrc.total_size / u64::from(regsrc.counter)).format_size(DECIMAL);
 
         let total_size = regsrc.total_size.format_size(DECIMAL);
 
-        table_matrix.push(vec![
+        let mut row = vec![
             regsrc.name,
             regsrc.counter.to_string(),
             average_size,
             total_size,
-        ]);
+        ];
+        if show_last_used {
+            row.push(format_last_used(regsrc.last_used));
+        }
+        table_matrix.push(row);
     }
     format_table(&table_matrix, 0)
 }
@@ -233,6 +272,7 @@ pub(crate) fn registry_source_stats(
     path: &Path,
     limit: u32,
     registry_sources_caches: &mut registry_sources::RegistrySourceCaches,
+    last_used: &LastUsed,
 ) -> String {
     let mut stdout = String::new();
     // don't crash if the directory does not exist (issue #9)
@@ -243,19 +283,53 @@ pub(crate) fn registry_source_stats(
     writeln!(
         stdout,
         "\nSummary of: {} ({} total)",
-        path.display(),
+        hyperlink::path(path),
         registry_sources_caches.total_size().format_size(DECIMAL)
     )
     .unwrap();
 
     let file_descs: Vec<FileDesc> = file_desc_list_from_path(registry_sources_caches);
-    let summary: Vec<RgSrcInfo> = stats_from_file_desc_list(file_descs);
+    let summary = biggest_with_last_used(&file_descs, limit, last_used);
     let string = reg_src_list_to_string(limit, summary);
     stdout.push_str(&string);
 
     stdout
 }
 
+/// the biggest items as json for --top-cache-items --output-format json
+#[cfg(feature = "json")]
+pub(crate) fn registry_source_json(
+    path: &Path,
+    limit: u32,
+    registry_sources_caches: &mut registry_sources::RegistrySourceCaches,
+    last_used: &LastUsed,
+) -> Option<serde_json::Value> {
+    if !dir_exists(path) {
+        return None;
+    }
+    // biggest item first, like the table
+    let items = biggest_with_last_used(
+        &file_desc_list_from_path(registry_sources_caches),
+        limit,
+        last_used,
+    )
+    .into_iter()
+    .map(|info| {
+        serde_json::json!({
+            "name": info.name,
+            "count": info.counter,
+            "total_size": info.total_size,
+            "last_used": json_last_used(info.last_used),
+        })
+    })
+    .collect::<Vec<_>>();
+    Some(component_json(
+        path,
+        registry_sources_caches.total_size(),
+        &items,
+    ))
+}
+
 #[cfg(test)]
 mod top_crates_registry_sources {
     use super::*;
@@ -8,10 +8,12 @@
 // except according to those terms.
 
 use crate::cache::*;
-use crate::library::CargoCachePaths;
+use crate::last_used::LastUsed;
+use crate::library::{CargoCachePaths, Component, ComponentSelection};
 use crate::top_items::binaries::*;
 use crate::top_items::git_bare_repos::*;
 use crate::top_items::git_checkouts::*;
+use crate::top_items::registry_index::*;
 use crate::top_items::registry_pkg_cache::*;
 use crate::top_items::registry_sources::*;
 
@@ -23,46 +25,153 @@ pub(crate) fn get_top_crates(
     checkouts_cache: &mut git_checkouts::GitCheckoutCache,
     bare_repos_cache: &mut git_bare_repos::GitRepoCache,
     registry_pkg_caches: &mut registry_pkg_cache::RegistryPkgCaches,
+    registry_index_caches: &mut registry_index::RegistryIndicesCache,
     registry_sources_caches: &mut registry_sources::RegistrySourceCaches,
+    selection: &ComponentSelection,
 ) -> String {
     let mut reg_src = String::new();
     let mut reg_cache = String::new();
+    let mut reg_index = String::new();
     let mut bare_repos = String::new();
     let mut repo_checkouts = String::new();
     let mut binaries = String::new();
+    let last_used = LastUsed::load(&ccd.cargo_home);
 
     rayon::scope(|s| {
-        s.spawn(|_| {
-            reg_src = registry_source_stats(&ccd.registry_sources, limit, registry_sources_caches);
-        });
+        if selection.contains(&Component::RegistrySources) {
+            s.spawn(|_| {
+                reg_src = registry_source_stats(
+                    &ccd.registry_sources,
+                    limit,
+                    registry_sources_caches,
+                    &last_used,
+                );
+            });
+        }
 
-        s.spawn(|_| {
-            reg_cache =
-                registry_pkg_cache_stats(&ccd.registry_pkg_cache, limit, registry_pkg_caches);
-        });
+        if selection.contains(&Component::RegistryCrateCache) {
+            s.spawn(|_| {
+                reg_cache = registry_pkg_cache_stats(
+                    &ccd.registry_pkg_cache,
+                    limit,
+                    registry_pkg_caches,
+                    &last_used,
+                );
+            });
+        }
 
-        s.spawn(|_| {
-            bare_repos = git_repos_bare_stats(&ccd.git_repos_bare, limit, bare_repos_cache);
-        });
+        if selection.contains(&Component::RegistryIndex) {
+            s.spawn(|_| {
+                reg_index = registry_index_stats(&ccd.registry_index, limit, registry_index_caches);
+            });
+        }
 
-        s.spawn(|_| {
-            repo_checkouts = git_checkouts_stats(&ccd.git_checkouts, limit, checkouts_cache);
-        });
+        if selection.contains(&Component::GitDB) {
+            s.spawn(|_| {
+                bare_repos =
+                    git_repos_bare_stats(&ccd.git_repos_bare, limit, bare_repos_cache, &last_used);
+            });
+        }
 
-        s.spawn(|_| {
-            binaries = binary_stats(&ccd.bin_dir, limit, bin_cache);
-        });
+        if selection.contains(&Component::GitRepos) {
+            s.spawn(|_| {
+                repo_checkouts =
+                    git_checkouts_stats(&ccd.git_checkouts, limit, checkouts_cache, &last_used);
+            });
+        }
+
+        if selection.includes_binaries() {
+            s.spawn(|_| {
+                binaries = binary_stats(&ccd.bin_dir, limit, bin_cache);
+            });
+        }
     });
 
     let mut output = String::with_capacity(
-        binaries.len() + reg_src.len() + reg_cache.len() + bare_repos.len() + repo_checkouts.len(),
+        binaries.len()
+            + reg_src.len()
+            + reg_cache.len()
+            + reg_index.len()
+            + bare_repos.len()
+            + repo_checkouts.len(),
     );
 
     output.push_str(&binaries);
     output.push_str(&reg_src);
     output.push_str(&reg_cache);
+    output.push_str(&reg_index);
     output.push_str(&bare_repos);
     output.push_str(&repo_checkouts);
+    last_used.save();
     // strip newlines at the end and the beginning
     output.trim().to_string()
 }
+
+/// --top-cache-items --output-format json, one object per selected component
+#[cfg(feature = "json")]
+#[allow(clippy::complexity)]
+pub(crate) fn get_top_crates_json(
+    limit: u32,
+    ccd: &CargoCachePaths,
+    bin_cache: &mut bin::BinaryCache,
+    checkouts_cache: &mut git_checkouts::GitCheckoutCache,
+    bare_repos_cache: &mut git_bare_repos::GitRepoCache,
+    registry_pkg_caches: &mut registry_pkg_cache::RegistryPkgCaches,
+    registry_index_caches: &mut registry_index::RegistryIndicesCache,
+    registry_sources_caches: &mut registry_sources::RegistrySourceCaches,
+    selection: &ComponentSelection,
+) -> serde_json::Value {
+    let last_used = LastUsed::load(&ccd.cargo_home);
+    let mut components = serde_json::Map::new();
+    let mut add = |name: &str, component: Option<serde_json::Value>| {
+        if let Some(component) = component {
+            let _ = components.insert(name.into(), component);
+        }
+    };
+
+    if selection.includes_binaries() {
+        add("binaries", binary_json(&ccd.bin_dir, limit, bin_cache));
+    }
+    if selection.contains(&Component::RegistrySources) {
+        add(
+            "registry_sources",
+            registry_source_json(
+                &ccd.registry_sources,
+                limit,
+                registry_sources_caches,
+                &last_used,
+            ),
+        );
+    }
+    if selection.contains(&Component::RegistryCrateCache) {
+        add(
+            "registry_crate_cache",
+            registry_pkg_cache_json(
+                &ccd.registry_pkg_cache,
+                limit,
+                registry_pkg_caches,
+                &last_used,
+            ),
+        );
+    }
+    if selection.contains(&Component::RegistryIndex) {
+        add(
+            "registry_index",
+            registry_index_json(&ccd.registry_index, limit, registry_index_caches),
+        );
+    }
+    if selection.contains(&Component::GitDB) {
+        add(
+            "git_db",
+            git_repos_bare_json(&ccd.git_repos_bare, limit, bare_repos_cache, &last_used),
+        );
+    }
+    if selection.contains(&Component::GitRepos) {
+        add(
+            "git_checkouts",
+            git_checkouts_json(&ccd.git_checkouts, limit, checkouts_cache, &last_used),
+        );
+    }
+    last_used.save();
+    serde_json::Value::Object(components)
+}
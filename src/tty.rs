@@ -0,0 +1,117 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// whether we talk to a terminal
+// Progress lines are only drawn if stderr is a terminal, confirmations are only asked and "ui" only
+// runs if stdin is one and paths only become hyperlinks (--hyperlinks auto) if stdout is one.
+// The detection is wrong in some places: CI systems that render escape sequences of piped output,
+// containers with a pseudo terminal nobody looks at, expect scripts...
+// --assume-tty and --no-tty override it for all of these at once.
+// A TERM of "dumb" still turns off progress lines and hyperlinks, the terminal can't draw them.
+
+use std::env;
+
+use once_cell::sync::OnceCell;
+
+/// whether we trust the detection or were told what to assume
+static MODE: OnceCell<TtyMode> = OnceCell::new();
+
+/// how to decide whether a stream is a terminal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TtyMode {
+    /// ask the operating system
+    Auto,
+    /// --assume-tty: every stream is a terminal
+    Assume,
+    /// --no-tty: no stream is a terminal
+    No,
+}
+
+impl TtyMode {
+    /// the mode of the --assume-tty and --no-tty flags, clap makes sure only one is passed
+    pub(crate) fn new(assume_tty: bool, no_tty: bool) -> Self {
+        match (assume_tty, no_tty) {
+            (true, _) => Self::Assume,
+            (_, true) => Self::No,
+            _ => Self::Auto,
+        }
+    }
+}
+
+/// the standard streams
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Stream {
+    Stdin,
+    Stdout,
+    Stderr,
+}
+
+/// what the operating system says
+#[cfg(unix)]
+fn detected(stream: Stream) -> bool {
+    use std::io;
+    use std::os::unix::io::AsRawFd;
+    let fd = match stream {
+        Stream::Stdin => io::stdin().as_raw_fd(),
+        Stream::Stdout => io::stdout().as_raw_fd(),
+        Stream::Stderr => io::stderr().as_raw_fd(),
+    };
+    nix::unistd::isatty(fd).unwrap_or(false)
+}
+
+/// we can not tell without unsafe code, assume we run in a script
+#[cfg(not(unix))]
+fn detected(_stream: Stream) -> bool {
+    false
+}
+
+fn is_tty_in(mode: TtyMode, stream: Stream) -> bool {
+    match mode {
+        TtyMode::Assume => true,
+        TtyMode::No => false,
+        TtyMode::Auto => detected(stream),
+    }
+}
+
+/// set the mode, needs to be called before anything asks `is_tty()`
+pub(crate) fn init(mode: TtyMode) {
+    let _ = MODE.set(mode);
+}
+
+/// whether the stream is a terminal, or we were told to assume it is
+pub(crate) fn is_tty(stream: Stream) -> bool {
+    is_tty_in(MODE.get().copied().unwrap_or(TtyMode::Auto), stream)
+}
+
+/// whether we can draw escape sequences on the stream: a terminal that is not "dumb"
+pub(crate) fn can_draw(stream: Stream) -> bool {
+    is_tty(stream) && env::var("TERM").map_or(true, |term| term != "dumb")
+}
+
+#[cfg(test)]
+mod tty_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn modes() {
+        assert_eq!(TtyMode::new(false, false), TtyMode::Auto);
+        assert_eq!(TtyMode::new(true, false), TtyMode::Assume);
+        assert_eq!(TtyMode::new(false, true), TtyMode::No);
+    }
+
+    #[test]
+    fn overrides() {
+        for stream in [Stream::Stdin, Stream::Stdout, Stream::Stderr] {
+            assert!(is_tty_in(TtyMode::Assume, stream));
+            assert!(!is_tty_in(TtyMode::No, stream));
+            assert_eq!(is_tty_in(TtyMode::Auto, stream), detected(stream));
+        }
+    }
+}
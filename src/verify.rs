@@ -5,10 +5,16 @@ use std::path::{Path, PathBuf};
 
 use crate::cache::caches::RegistrySuperCache;
 use crate::cache::*;
-use crate::remove::remove_file;
+use crate::cancel::is_cancelled;
+use crate::index::read_checksums;
+use crate::library::{sha256_of_file, Error};
+use crate::progress::Progress;
+use crate::remove::{parse_version, remove_file};
+use crate::walk::guarded;
 
 use flate2::read::GzDecoder;
 use rayon::iter::*;
+use serde_json::{json, Value};
 use tar::Archive;
 use walkdir::WalkDir;
 
@@ -61,7 +67,15 @@ pub(crate) struct FileSizeDifference {
     size_source: u64,
 }
 
+/// checksum of a .crate archive that does not match the one in the registry index
+#[derive(Debug, Clone)]
+pub(crate) struct ChecksumMismatch {
+    expected: String,
+    found: String,
+}
+
 /// The Difference between extracted crate sources and an .crate tar.gz archive
+/// or, with --checksums, between a .crate archive and the registry index
 #[derive(Debug, Clone)]
 pub(crate) struct Diff {
     // the crate we are diffing
@@ -69,7 +83,12 @@ pub(crate) struct Diff {
     files_missing_in_checkout: Vec<PathBuf>,
     additional_files_in_checkout: Vec<PathBuf>,
     files_size_difference: Vec<FileSizeDifference>,
+    // same size but different content, only checked with --content
+    files_content_difference: Vec<PathBuf>,
+    // only checked with --checksums
+    checksum_mismatch: Option<ChecksumMismatch>,
     source_path: Option<PathBuf>,
+    archive_path: Option<PathBuf>,
 }
 
 impl Diff {
@@ -79,7 +98,10 @@ impl Diff {
             files_missing_in_checkout: Vec::new(),
             additional_files_in_checkout: Vec::new(),
             files_size_difference: Vec::new(),
+            files_content_difference: Vec::new(),
+            checksum_mismatch: None,
             source_path: None,
+            archive_path: None,
         }
     }
 
@@ -88,6 +110,8 @@ impl Diff {
         self.files_missing_in_checkout.is_empty()
             && self.additional_files_in_checkout.is_empty()
             && self.files_size_difference.is_empty()
+            && self.files_content_difference.is_empty()
+            && self.checksum_mismatch.is_none()
     }
 
     pub(crate) fn details(&self) -> String {
@@ -131,8 +155,66 @@ impl Diff {
                 })
                 .for_each(|strg| s.push_str(&strg));
         }
+        if !self.files_content_difference.is_empty() {
+            write!(
+                s,
+                "Content differs from archive:\n{}",
+                self.files_content_difference
+                    .iter()
+                    .map(|path| path.display().to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            )
+            .unwrap();
+            s.push('\n');
+        }
+        if let Some(mismatch) = &self.checksum_mismatch {
+            writeln!(
+                s,
+                "Checksum differs from registry index: expected {}, found {}",
+                mismatch.expected, mismatch.found
+            )
+            .unwrap();
+        }
         s
     }
+
+    /// the diff as json object for the --report
+    fn to_json(&self) -> Value {
+        let paths = |paths: &[PathBuf]| {
+            paths
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect::<Vec<String>>()
+        };
+        let optional_path = |path: &Option<PathBuf>| {
+            path.as_ref().map_or(Value::Null, |path| {
+                Value::String(path.display().to_string())
+            })
+        };
+
+        json!({
+            "crate": self.krate_name,
+            "source": optional_path(&self.source_path),
+            "archive": optional_path(&self.archive_path),
+            "missing_in_source": paths(&self.files_missing_in_checkout),
+            "not_in_archive": paths(&self.additional_files_in_checkout),
+            "size_mismatch": self
+                .files_size_difference
+                .iter()
+                .map(|fsd| json!({
+                    "path": fsd.path.display().to_string(),
+                    "expected_size": fsd.size_archive,
+                    "found_size": fsd.size_source,
+                }))
+                .collect::<Vec<Value>>(),
+            "content_mismatch": paths(&self.files_content_difference),
+            "checksum_mismatch": self.checksum_mismatch.as_ref().map_or(Value::Null, |mismatch| json!({
+                "expected_checksum": mismatch.expected,
+                "found_checksum": mismatch.found,
+            })),
+        })
+    }
 }
 
 /// take a path to an extracted .crate source and map it to the corresponding .carte archive path
@@ -162,7 +244,6 @@ fn map_src_path_to_cache_path(src_path: &Path) -> PathBuf {
 }
 
 /// look into the .gz archive and get all the contained files+sizes
-
 fn sizes_of_archive_files(path: &Path) -> Vec<FileWithSize> {
     let tar_gz = File::open(path).unwrap();
     // extract the tar
@@ -179,12 +260,35 @@ fn sizes_of_archive_files(path: &Path) -> Vec<FileWithSize> {
         .collect::<Vec<FileWithSize>>()
 }
 
+/// compare the contents of the files in the .gz archive with the extracted sources
+/// files that are missing or differ in size are already part of the Diff and skipped here
+fn files_with_different_content(krate: &Path, source: &Path) -> Vec<PathBuf> {
+    // archive paths start with the crate directory: bytes-0.4.12/src/lib.rs
+    let registry_dir = source.parent().unwrap();
+    let mut archive = Archive::new(GzDecoder::new(File::open(krate).unwrap()));
+
+    archive
+        .entries()
+        .unwrap()
+        .map(Result::unwrap)
+        .filter(|entry| entry.header().entry_type().is_file())
+        .filter_map(|mut entry| {
+            let path = normalized(entry.path().unwrap().into_owned());
+            let on_disk = std::fs::read(registry_dir.join(&path)).ok()?;
+            if on_disk.len() as u64 != entry.size() {
+                return None;
+            }
+            let mut in_archive = Vec::with_capacity(on_disk.len());
+            let _ = std::io::Read::read_to_end(&mut entry, &mut in_archive).unwrap();
+            (in_archive != on_disk).then_some(path)
+        })
+        .collect()
+}
+
 /// get the files and their sizes of the extracted .crate sources
 fn sizes_of_src_dir(source: &Path) -> Vec<FileWithSize> {
-    let krate_root = source.iter().last().unwrap();
-    WalkDir::new(source)
-        .into_iter()
-        .map(Result::unwrap)
+    let krate_root = source.iter().next_back().unwrap();
+    guarded(WalkDir::new(source), |_| true)
         // need to skip directories since the are only implicitly inside the tar (via file paths)
         .filter(|de| de.file_type().is_file())
         .map(|direntry| {
@@ -201,7 +305,14 @@ fn diff_crate_and_source(krate: &Path, source: &Path) -> Diff {
     let files_of_source: Vec<FileWithSize> = sizes_of_src_dir(source);
     let mut diff = Diff::new();
     diff.source_path = Some(source.to_path_buf());
-    diff.krate_name = source.iter().last().unwrap().to_str().unwrap().to_string();
+    diff.archive_path = Some(krate.to_path_buf());
+    diff.krate_name = source
+        .iter()
+        .next_back()
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_string();
     let files_of_source_paths: Vec<&PathBuf> =
         files_of_source.iter().map(|fws| &fws.path).collect();
     for archive_file in &files_of_archive {
@@ -225,7 +336,7 @@ fn diff_crate_and_source(krate: &Path, source: &Path) -> Diff {
                     }
                 }
                 None => unreachable!(), // we already checked this
-            };
+            }
         }
     }
     let files_of_archive: Vec<&PathBuf> = files_of_archive.iter().map(|fws| &fws.path).collect();
@@ -243,27 +354,40 @@ fn diff_crate_and_source(krate: &Path, source: &Path) -> Diff {
     }
     diff
 }
+/// with `content`, also compare the contents of the files and not only their sizes
 pub(crate) fn verify_crates(
     registry_sources_caches: &mut registry_sources::RegistrySourceCaches,
+    content: bool,
 ) -> Result<(), Vec<Diff>> {
     // iterate over all the extracted sources that we have
+    let sources = registry_sources_caches.items();
+    let progress = Progress::new("Verifying crates", sources.len());
 
-    let bad_sources: Vec<_> = registry_sources_caches
-        .items()
+    let bad_sources: Vec<_> = sources
         .par_iter()
+        // skip the remaining crates once we were cancelled, the crates verified so far are reported
+        .filter(|_| !is_cancelled())
         // get the paths to the source and the .crate for all extracted crates
         .map(|source| (source, map_src_path_to_cache_path(source)))
         // we need both the .crate and the directory to exist for verification
         .filter(|(source, krate)| source.exists() && krate.exists())
         // look into the .gz archive and get all the contained files+sizes
-        .map(|(source, krate)| diff_crate_and_source(&krate, source))
+        .map(|(source, krate)| {
+            let mut diff = diff_crate_and_source(&krate, source);
+            if content {
+                diff.files_content_difference = files_with_different_content(&krate, source);
+            }
+            progress.inc(0);
+            diff
+        })
         // save only the "bad" packages
         .filter(|diff| !diff.is_ok())
         .map(|diff| {
-            eprintln!("Possibly corrupted source: {}", diff.krate_name);
+            progress.suspend(|| eprintln!("Possibly corrupted source: {}", diff.krate_name));
             diff
         })
         .collect::<Vec<_>>();
+    progress.finish();
 
     if bad_sources.is_empty() {
         Ok(())
@@ -272,6 +396,81 @@ pub(crate) fn verify_crates(
     }
 }
 
+/// compare the checksum of a .crate archive with the one in the registry index
+/// None if the registry index does not know the archive
+fn diff_archive_and_index(krate: &Path, registry_index: &Path) -> Option<Diff> {
+    // .cargo/registry/cache/github.com-1ecc6299db9ec823/bytes-0.4.12.crate
+    // is listed in
+    // .cargo/registry/index/github.com-1ecc6299db9ec823/.cache/by/te/bytes
+    let registry = krate.parent()?.file_name()?;
+    let (name, version) = parse_version(krate).ok()?;
+    let expected = read_checksums(&registry_index.join(registry), &name)?.remove(&version)?;
+
+    let mut diff = Diff::new();
+    diff.krate_name = format!("{name}-{version}");
+    diff.archive_path = Some(krate.to_path_buf());
+    // an archive that can not be read is as broken as one with the wrong content
+    let found = sha256_of_file(krate).unwrap_or_else(|_| String::from("unreadable"));
+    if found != expected {
+        diff.checksum_mismatch = Some(ChecksumMismatch { expected, found });
+    }
+    Some(diff)
+}
+
+/// compare the checksums of all .crate archives with the ones in the (sparse or git) registry
+/// index, archives that cargo has no index cache entry for are skipped
+pub(crate) fn verify_checksums(
+    registry_pkg_caches: &mut registry_pkg_cache::RegistryPkgCaches,
+    registry_index: &Path,
+) -> Result<(), Vec<Diff>> {
+    let archives = registry_pkg_caches.files();
+    let progress = Progress::new("Verifying checksums", archives.len());
+
+    let diffs: Vec<Option<Diff>> = archives
+        .par_iter()
+        .filter(|_| !is_cancelled())
+        .map(|krate| {
+            let diff = diff_archive_and_index(krate, registry_index);
+            progress.inc(0);
+            if let Some(diff) = diff.as_ref().filter(|diff| !diff.is_ok()) {
+                progress.suspend(|| eprintln!("Corrupted archive: {}", diff.krate_name));
+            }
+            diff
+        })
+        .collect();
+    progress.finish();
+
+    let unknown = diffs.iter().filter(|diff| diff.is_none()).count();
+    if unknown > 0 {
+        eprintln!(
+            "Warning: the registry index has no checksum for {unknown} archives, they were not verified."
+        );
+    }
+
+    let bad_archives = diffs
+        .into_iter()
+        .flatten()
+        .filter(|diff| !diff.is_ok())
+        .collect::<Vec<Diff>>();
+    if bad_archives.is_empty() {
+        Ok(())
+    } else {
+        Err(bad_archives)
+    }
+}
+
+/// write the list of possibly corrupted sources to a json file for further processing
+pub(crate) fn write_report(path: &Path, diff_list: &[Diff]) -> Result<(), Error> {
+    let report = json!({
+        "corrupted": diff_list.iter().map(Diff::to_json).collect::<Vec<Value>>(),
+    });
+    // serializing a Value can not fail
+    let mut report = serde_json::to_string_pretty(&report).unwrap();
+    report.push('\n');
+    std::fs::write(path, report)
+        .map_err(|error| Error::WriteReportFailed(path.to_path_buf(), error))
+}
+
 pub(crate) fn clean_corrupted(
     registry_sources_caches: &mut registry_sources::RegistrySourceCaches,
     diff_list: &[Diff],
@@ -300,9 +499,39 @@ pub(crate) fn clean_corrupted(
     registry_sources_caches.invalidate();
 }
 
+/// remove the .crate archives whose checksum does not match the registry index,
+/// cargo downloads them again when it needs them
+pub(crate) fn clean_corrupted_archives(
+    registry_pkg_caches: &mut registry_pkg_cache::RegistryPkgCaches,
+    diff_list: &[Diff],
+    dry_run: bool,
+) {
+    // hack because we need a &mut bool in remove_file()
+    let mut bool = false;
+
+    diff_list
+        .iter()
+        .filter(|diff| diff.checksum_mismatch.is_some())
+        .filter_map(|diff| diff.archive_path.as_ref())
+        .filter(|path| path.is_file())
+        .for_each(|path| {
+            remove_file(
+                path,
+                dry_run,
+                &mut bool,
+                Some(format!("removing corrupted archive: {}", path.display())),
+                &crate::remove::DryRunMessage::Default,
+                None,
+            );
+        });
+
+    registry_pkg_caches.invalidate();
+}
+
 #[cfg(test)]
 mod verification_tests {
     use super::*;
+    use crate::schema::schema_tests::assert_valid;
     use pretty_assertions::assert_eq;
 
     #[test]
@@ -318,4 +547,86 @@ mod verification_tests {
 
         assert_eq!(new, new_archive_path);
     }
+
+    #[test]
+    fn report() {
+        let mut diff = Diff::new();
+        diff.krate_name = String::from("bytes-0.4.12");
+        diff.source_path = Some(PathBuf::from("/cargo/registry/src/reg/bytes-0.4.12"));
+        diff.files_missing_in_checkout
+            .push(PathBuf::from("bytes-0.4.12/src/lib.rs"));
+        diff.files_size_difference.push(FileSizeDifference {
+            path: PathBuf::from("bytes-0.4.12/Cargo.toml"),
+            size_archive: 100,
+            size_source: 5,
+        });
+
+        assert_eq!(
+            diff.to_json(),
+            json!({
+                "crate": "bytes-0.4.12",
+                "source": "/cargo/registry/src/reg/bytes-0.4.12",
+                "archive": null,
+                "missing_in_source": ["bytes-0.4.12/src/lib.rs"],
+                "not_in_archive": [],
+                "size_mismatch": [{
+                    "path": "bytes-0.4.12/Cargo.toml",
+                    "expected_size": 100,
+                    "found_size": 5,
+                }],
+                "content_mismatch": [],
+                "checksum_mismatch": null,
+            })
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let report = dir.path().join("report.json");
+        write_report(&report, &[diff]).unwrap();
+        let written: Value =
+            serde_json::from_str(&std::fs::read_to_string(&report).unwrap()).unwrap();
+        assert_eq!(written["corrupted"][0]["crate"], "bytes-0.4.12");
+        assert_valid("verify_report", &written);
+    }
+
+    #[test]
+    fn checksums() {
+        let dir = tempfile::tempdir().unwrap();
+        let registry = "index.crates.io-6f17d22bba15001f";
+        let archives = dir.path().join("cache").join(registry);
+        let index = dir.path().join("index");
+        let index_cache = index.join(registry).join(".cache").join("3").join("f");
+        std::fs::create_dir_all(&archives).unwrap();
+        std::fs::create_dir_all(&index_cache).unwrap();
+        let mut data: Vec<u8> = vec![3, 2, 0, 0, 0];
+        data.extend(b"etag\0");
+        for (version, checksum) in [
+            // sha256 of "abc"
+            (
+                "1.0.0",
+                "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            ),
+            ("1.1.0", "0000"),
+        ] {
+            data.extend(format!("{version}\0{{\"name\":\"foo\",\"vers\":\"{version}\",\"cksum\":\"{checksum}\"}}\0").bytes());
+        }
+        std::fs::write(index_cache.join("foo"), data).unwrap();
+        for archive in ["foo-1.0.0.crate", "foo-1.1.0.crate", "foo-2.0.0.crate"] {
+            std::fs::write(archives.join(archive), "abc").unwrap();
+        }
+
+        let good = diff_archive_and_index(&archives.join("foo-1.0.0.crate"), &index).unwrap();
+        assert!(good.is_ok());
+        let bad = diff_archive_and_index(&archives.join("foo-1.1.0.crate"), &index).unwrap();
+        assert_eq!(bad.krate_name, "foo-1.1.0");
+        assert_eq!(
+            bad.to_json()["checksum_mismatch"],
+            json!({
+                "expected_checksum": "0000",
+                "found_checksum": "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            })
+        );
+        // not in the index cache
+        assert!(diff_archive_and_index(&archives.join("foo-2.0.0.crate"), &index).is_none());
+        assert!(diff_archive_and_index(&archives.join("bar-1.0.0.crate"), &index).is_none());
+    }
 }
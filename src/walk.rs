@@ -0,0 +1,236 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// guarded directory walks
+// Malformed checkouts can be nested absurdly deep, and bind mounts or windows junctions can make a
+// directory show up inside of itself. Symlinks are never followed, but every directory we enter is
+// remembered by device and inode anyway, a directory that shows up a second time is not entered.
+// Walks stop at --max-scan-depth levels below their start. Unreadable entries, cycles and cut off
+// directories are reported once as warnings and skipped, the scan goes on with everything else.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use once_cell::sync::{Lazy, OnceCell};
+use walkdir::{DirEntry, WalkDir};
+
+use crate::ignore::is_ignored;
+use crate::library::Error;
+
+/// how deep walks go if --max-scan-depth is not passed, cargo itself never nests anywhere near that
+pub(crate) const DEFAULT_MAX_SCAN_DEPTH: usize = 100;
+
+/// the maximum depth of this run, set up once in `main()`
+static MAX_SCAN_DEPTH: OnceCell<usize> = OnceCell::new();
+
+/// paths we already warned about, caches may be scanned several times per run
+static REPORTED: Lazy<Mutex<HashSet<PathBuf>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+
+/// parse --max-scan-depth <N>
+pub(crate) fn parse_max_scan_depth(max_scan_depth: Option<&str>) -> Result<usize, Error> {
+    match max_scan_depth {
+        Some(depth) => match depth.parse() {
+            Ok(depth) if depth > 0 => Ok(depth),
+            _ => Err(Error::InvalidMaxScanDepth(depth.to_string())),
+        },
+        None => Ok(DEFAULT_MAX_SCAN_DEPTH),
+    }
+}
+
+/// set the maximum depth of all walks, needs to be called before any scanning happens
+pub(crate) fn init(max_scan_depth: usize) {
+    let _ = MAX_SCAN_DEPTH.set(max_scan_depth);
+}
+
+fn max_scan_depth() -> usize {
+    MAX_SCAN_DEPTH
+        .get()
+        .copied()
+        .unwrap_or(DEFAULT_MAX_SCAN_DEPTH)
+}
+
+/// print a warning about a path, but only once per run
+fn warn(path: &Path, problem: &str) {
+    if REPORTED.lock().unwrap().insert(path.to_path_buf()) {
+        eprintln!("Warning: \"{}\" {problem}", path.display());
+    }
+}
+
+/// the device and inode of a directory
+#[cfg(unix)]
+fn dir_id(entry: &DirEntry) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    entry
+        .metadata()
+        .ok()
+        .map(|metadata| (metadata.dev(), metadata.ino()))
+}
+
+/// there are no inodes on windows, only the depth limit applies
+#[cfg(not(unix))]
+fn dir_id(_entry: &DirEntry) -> Option<(u64, u64)> {
+    None
+}
+
+/// a walk of a directory that terminates no matter how the directory looks like
+/// entries for which `keep` returns false are skipped, directories are not entered
+pub(crate) struct Walk<P> {
+    inner: walkdir::IntoIter,
+    keep: P,
+    max_depth: usize,
+    /// device and inode of the directories we entered
+    visited: HashSet<(u64, u64)>,
+}
+
+impl<P> Iterator for Walk<P>
+where
+    P: FnMut(&DirEntry) -> bool,
+{
+    type Item = DirEntry;
+
+    fn next(&mut self) -> Option<DirEntry> {
+        loop {
+            let entry = match self.inner.next()? {
+                Ok(entry) => entry,
+                Err(error) => {
+                    let path = error.path().map(Path::to_path_buf).unwrap_or_default();
+                    warn(
+                        &path,
+                        &format!("could not be scanned, skipping it: {error}"),
+                    );
+                    continue;
+                }
+            };
+            if !(self.keep)(&entry) {
+                if entry.file_type().is_dir() {
+                    self.inner.skip_current_dir();
+                }
+                continue;
+            }
+
+            if entry.file_type().is_dir() {
+                if let Some(id) = dir_id(&entry) {
+                    if !self.visited.insert(id) {
+                        warn(
+                            entry.path(),
+                            "was already scanned, it is part of a cycle (bind mount?), skipping it",
+                        );
+                        self.inner.skip_current_dir();
+                        continue;
+                    }
+                }
+                if entry.depth() == self.max_depth
+                    && fs::read_dir(entry.path())
+                        .map_or(false, |mut entries| entries.next().is_some())
+                {
+                    warn(
+                        entry.path(),
+                        &format!(
+                            "is nested more than {} levels deep, its contents are not scanned (see --max-scan-depth)",
+                            self.max_depth
+                        ),
+                    );
+                }
+            }
+            return Some(entry);
+        }
+    }
+}
+
+/// walk a configured `WalkDir`, skipping the entries (and the contents of directories) `keep` rejects
+pub(crate) fn guarded<P>(walkdir: WalkDir, keep: P) -> Walk<P>
+where
+    P: FnMut(&DirEntry) -> bool,
+{
+    let max_depth = max_scan_depth();
+    Walk {
+        inner: walkdir.max_depth(max_depth).into_iter(),
+        keep,
+        max_depth,
+        visited: HashSet::new(),
+    }
+}
+
+/// walk a directory, leaving out the paths listed in the .cargocacheignore
+pub(crate) fn walk(path: impl AsRef<Path>) -> Walk<fn(&DirEntry) -> bool> {
+    guarded(WalkDir::new(path), |entry| !is_ignored(entry.path()))
+}
+
+#[cfg(test)]
+mod walk_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn max_depth_argument() {
+        assert_eq!(parse_max_scan_depth(None).unwrap(), DEFAULT_MAX_SCAN_DEPTH);
+        assert_eq!(parse_max_scan_depth(Some("20")).unwrap(), 20);
+        assert!(matches!(
+            parse_max_scan_depth(Some("0")),
+            Err(Error::InvalidMaxScanDepth(_))
+        ));
+        assert!(matches!(
+            parse_max_scan_depth(Some("deep")),
+            Err(Error::InvalidMaxScanDepth(_))
+        ));
+    }
+
+    #[test]
+    fn deep_nesting_is_cut_off() {
+        let tmp = tempfile::tempdir().unwrap();
+        let mut deep = tmp.path().to_path_buf();
+        for level in 0..5 {
+            deep.push(level.to_string());
+        }
+        fs::create_dir_all(&deep).unwrap();
+        fs::write(deep.join("file"), "").unwrap();
+
+        let walk = Walk {
+            inner: WalkDir::new(tmp.path()).max_depth(3).into_iter(),
+            keep: |_: &DirEntry| true,
+            max_depth: 3,
+            visited: HashSet::new(),
+        };
+        let depths = walk.map(|entry| entry.depth()).collect::<Vec<usize>>();
+        assert_eq!(depths, vec![0, 1, 2, 3]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_cycles_terminate() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().join("a/b");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("file"), "").unwrap();
+        std::os::unix::fs::symlink(tmp.path().join("a"), dir.join("loop")).unwrap();
+
+        // even if links are followed, every directory is entered only once
+        let files = guarded(WalkDir::new(tmp.path()).follow_links(true), |_| true)
+            .filter(|entry| entry.file_type().is_file())
+            .count();
+        assert_eq!(files, 1);
+    }
+
+    #[test]
+    fn rejected_directories_are_not_entered() {
+        let tmp = tempfile::tempdir().unwrap();
+        fs::create_dir_all(tmp.path().join("skip/inner")).unwrap();
+        fs::create_dir_all(tmp.path().join("keep")).unwrap();
+
+        let mut names = guarded(WalkDir::new(tmp.path()).min_depth(1), |entry| {
+            entry.file_name() != "skip"
+        })
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .collect::<Vec<String>>();
+        names.sort();
+        assert_eq!(names, vec!["keep"]);
+    }
+}
@@ -0,0 +1,152 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// what a dry run would have freed
+// A dry run removes nothing, so the size difference at the end of the run would not show any
+// change. Instead, every item a dry run would remove is remembered together with its size and the
+// end of the run shows what that adds up to, per component and in total.
+// Items inside of an item that would be removed anyway are only counted once.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use humansize::{FormatSize, DECIMAL};
+use once_cell::sync::Lazy;
+
+use crate::library::{cumulative_dir_size, Component};
+use crate::tables::format_table;
+
+/// the items the dry runs of this run would have removed, with their sizes
+static WOULD_FREE: Lazy<Mutex<BTreeMap<PathBuf, u64>>> = Lazy::new(|| Mutex::new(BTreeMap::new()));
+
+/// a dry run would remove `path`, `size` is calculated if the caller does not know it
+pub(crate) fn record(path: &Path, size: Option<u64>) {
+    let size = size.unwrap_or_else(|| {
+        if path.is_dir() {
+            cumulative_dir_size(path).dir_size
+        } else {
+            path.symlink_metadata().map_or(0, |metadata| metadata.len())
+        }
+    });
+    let _ = WOULD_FREE.lock().unwrap().insert(path.to_path_buf(), size);
+}
+
+/// the size a dry run recorded for `path`
+#[cfg(test)]
+pub(crate) fn recorded(path: &Path) -> Option<u64> {
+    WOULD_FREE.lock().unwrap().get(path).copied()
+}
+
+/// the number of items and their size per component, items outside of the components count as "other"
+/// paths are sorted, so everything inside of a directory directly follows it
+fn per_component(items: &BTreeMap<PathBuf, u64>) -> Vec<(String, usize, u64)> {
+    let mut components: BTreeMap<Option<Component>, (usize, u64)> = BTreeMap::new();
+    let mut outer: Option<&PathBuf> = None;
+    for (path, size) in items {
+        if outer.map_or(false, |outer| path.starts_with(outer)) {
+            continue;
+        }
+        outer = Some(path);
+        let (count, total) = components.entry(Component::of_path(path)).or_default();
+        *count += 1;
+        *total += size;
+    }
+    components
+        .into_iter()
+        .map(|(component, (count, total))| {
+            (
+                component
+                    .map_or("other", |component| component.name())
+                    .to_string(),
+                count,
+                total,
+            )
+        })
+        .collect()
+}
+
+/// the table of what would be freed and the total, None if no dry run would have removed anything
+fn summary(items: &BTreeMap<PathBuf, u64>) -> Option<String> {
+    if items.is_empty() {
+        return None;
+    }
+    let components = per_component(items);
+    let total: u64 = components.iter().map(|(_, _, size)| size).sum();
+
+    let mut table = vec![vec![
+        String::from("Component"),
+        String::from("Items"),
+        String::from("Would free"),
+    ]];
+    table.extend(components.into_iter().map(|(component, count, size)| {
+        vec![component, count.to_string(), size.format_size(DECIMAL)]
+    }));
+    Some(format!(
+        "\n{}\ndry-run: would free approximately {} in total",
+        format_table(&table, 0),
+        total.format_size(DECIMAL)
+    ))
+}
+
+/// print what the dry runs of this run would have freed, returns whether there was anything
+pub(crate) fn print() -> bool {
+    match summary(&WOULD_FREE.lock().unwrap()) {
+        Some(summary) => {
+            println!("{summary}");
+            true
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod would_free_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn summary_per_component() {
+        let mut items = BTreeMap::new();
+        assert_eq!(summary(&items), None);
+
+        let cargo_home = Path::new("/cargo");
+        let sources = cargo_home.join("registry/src/index.crates.io-6f17d22bba15001f");
+        let _ = items.insert(sources.clone(), 3_000_000);
+        // inside of the sources that are removed anyway
+        let _ = items.insert(sources.join("foo-1.0.0"), 1_000_000);
+        let _ = items.insert(
+            cargo_home.join("registry/cache/index.crates.io-6f17d22bba15001f/foo-1.0.0.crate"),
+            500_000,
+        );
+        let _ = items.insert(
+            cargo_home.join("registry/cache/index.crates.io-6f17d22bba15001f/bar-1.0.0.crate"),
+            250_000,
+        );
+        let _ = items.insert(PathBuf::from("/sccache/a/b/c"), 1000);
+
+        assert_eq!(
+            per_component(&items),
+            vec![
+                (String::from("other"), 1, 1000),
+                (String::from("registry-sources"), 1, 3_000_000),
+                (String::from("registry-crate-cache"), 2, 750_000),
+            ]
+        );
+        let summary = summary(&items).unwrap();
+        assert!(
+            summary.starts_with("\nComponent            Items Would free\n"),
+            "{summary}"
+        );
+        assert!(
+            summary.ends_with("\ndry-run: would free approximately 3.75 MB in total"),
+            "{summary}"
+        );
+    }
+}
@@ -146,7 +146,9 @@ cloudsmith = { index = "https://dl.cloudsmith.io/public/matthias-kruger/ccart/ca
   Registry:                     .* kB
     2 registry indices:         .* kB
     2 crate archives:           .* kB
+      2 crates, 2 versions
     2 crate source checkouts:   .* kB
+      2 crates, 2 versions
   Git db:                     .* 0  B
     0 bare git repos:         .* 0  B
     0 git repo checkouts:     .* 0  B",
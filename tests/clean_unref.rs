@@ -164,7 +164,9 @@ fn test_clean_unref() {
   Registry:                     .* MB
     Registry index:             .* MB
     1 crate archives:           .* kB
+      1 crates, 1 versions
     1 crate source checkouts:   .* kB
+      1 crates, 1 versions
   Git db:                       .* kB
     1 bare git repos:           .* kB
     1 git repo checkouts:       .* kB",
@@ -191,3 +193,79 @@ fn test_clean_unref() {
             );
         });
 }
+
+/// a project without dependencies that cargo can resolve offline
+fn project_without_dependencies(dir: &std::path::Path) {
+    std::fs::create_dir_all(dir.join("src")).unwrap();
+    std::fs::write(
+        dir.join("Cargo.toml"),
+        "[package]\nname = \"proj\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+    )
+    .unwrap();
+    std::fs::write(dir.join("src/lib.rs"), "").unwrap();
+    std::fs::write(
+        dir.join("Cargo.lock"),
+        "version = 3\n\n[[package]]\nname = \"proj\"\nversion = \"0.1.0\"\n",
+    )
+    .unwrap();
+}
+
+#[test]
+fn clean_unref_all_projects() {
+    let dir = tempfile::tempdir().unwrap();
+    let cargo_home = dir.path().join("cargo_home");
+    let crate_archive =
+        cargo_home.join("registry/cache/index.crates.io-6f17d22bba15001f/foo-1.0.0.crate");
+    std::fs::create_dir_all(crate_archive.parent().unwrap()).unwrap();
+    std::fs::write(&crate_archive, "foo").unwrap();
+    let project = dir.path().join("project");
+    project_without_dependencies(&project);
+
+    let cargo_cache = |args: &[&str]| {
+        let output = Command::new(bin_path())
+            .args(args)
+            .env("CARGO_HOME", &cargo_home)
+            .output()
+            .unwrap();
+        (
+            output.status.success(),
+            String::from_utf8_lossy(&output.stdout).into_owned(),
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+        )
+    };
+
+    // nothing is registered yet, do not sweep everything away
+    let (success, _, stderr) = cargo_cache(&["clean-unref", "--all-projects"]);
+    assert!(!success, "clean-unref without projects succeeded");
+    assert!(stderr.contains("No projects are registered"), "{stderr}");
+    assert!(crate_archive.exists());
+
+    let (success, stdout, _) = cargo_cache(&["projects", "add", project.to_str().unwrap()]);
+    assert!(success, "projects add failed");
+    assert!(stdout.starts_with("Registered project '"), "{stdout}");
+    let (success, stdout, _) = cargo_cache(&["projects"]);
+    assert!(success, "projects failed");
+    assert!(stdout.contains("Cargo.toml\n"), "{stdout}");
+
+    // the project does not use foo
+    let (success, stdout, stderr) = cargo_cache(&["clean-unref", "--all-projects", "--dry-run"]);
+    assert!(success, "clean-unref --all-projects failed: {stderr}");
+    assert!(stdout.contains("would remove: '"), "{stdout}");
+    assert!(stdout.contains("foo-1.0.0.crate"), "{stdout}");
+    assert!(crate_archive.exists());
+    let (success, _, stderr) = cargo_cache(&["clean-unref", "--all-projects"]);
+    assert!(success, "clean-unref --all-projects failed: {stderr}");
+    assert!(!crate_archive.exists());
+
+    // a registered project that is gone keeps everything
+    std::fs::write(&crate_archive, "foo").unwrap();
+    std::fs::remove_dir_all(&project).unwrap();
+    let (success, _, stderr) = cargo_cache(&["clean-unref", "--all-projects"]);
+    assert!(!success, "clean-unref with a missing project succeeded");
+    assert!(stderr.contains("does not exist anymore"), "{stderr}");
+    assert!(crate_archive.exists());
+
+    let (success, stdout, _) = cargo_cache(&["projects", "remove", project.to_str().unwrap()]);
+    assert!(success, "projects remove failed");
+    assert!(stdout.starts_with("Removed project '"), "{stdout}");
+}
@@ -0,0 +1,98 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// removing checkouts that windows has trouble with: paths longer than 260 characters (MAX_PATH)
+// and junctions (or symlinks elsewhere) that point out of the cache
+
+#[path = "../src/test_helpers.rs"]
+mod test_helpers;
+
+use crate::test_helpers::bin_path;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// a path the test itself can create files at even if it is too long for windows,
+/// canonicalize() returns a "\\?\C:\..." path there
+fn creatable(path: &Path) -> PathBuf {
+    fs::create_dir_all(path).unwrap();
+    path.canonicalize().unwrap()
+}
+
+/// create a link at `link` that points to the directory `target`
+fn link_dir(target: &Path, link: &Path) {
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(target, link).unwrap();
+    #[cfg(windows)]
+    {
+        // create a junction, these can be created without special privileges
+        let status = Command::new("cmd")
+            .args(["/C", "mklink", "/J"])
+            .arg(link)
+            .arg(target)
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+}
+
+fn remove_git_repos(cargo_home: &Path) -> String {
+    let output = Command::new(bin_path())
+        .args(["--remove-dir", "git-repos", "--cargo-home"])
+        .arg(cargo_home)
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+    assert!(output.status.success(), "bad exit status: {stderr}");
+    stderr
+}
+
+#[test]
+fn deeply_nested_checkout_is_removed() {
+    let dir = tempfile::tempdir().unwrap();
+    let cargo_home = dir.path().join("cargo_home");
+    let checkout = creatable(&cargo_home.join("git/checkouts/repo-0123456789abcdef/abc1234"));
+
+    let mut deep = checkout;
+    for level in 0..8 {
+        deep.push(format!(
+            "nested_directory_number_{level}_of_a_really_deep_tree"
+        ));
+    }
+    assert!(deep.as_os_str().len() > 300);
+    fs::create_dir_all(&deep).unwrap();
+    fs::write(deep.join("lib.rs"), "fn main() {}").unwrap();
+
+    let stderr = remove_git_repos(&cargo_home);
+    assert!(!stderr.contains("Warning"), "{stderr}");
+    assert!(!cargo_home
+        .join("git/checkouts")
+        .join("repo-0123456789abcdef")
+        .exists());
+}
+
+#[test]
+fn links_in_checkouts_are_not_followed() {
+    let dir = tempfile::tempdir().unwrap();
+    let outside = creatable(&dir.path().join("outside"));
+    fs::write(outside.join("precious.txt"), "do not delete").unwrap();
+
+    let cargo_home = dir.path().join("cargo_home");
+    let checkout = creatable(&cargo_home.join("git/checkouts/repo-0123456789abcdef/abc1234"));
+    fs::write(checkout.join("lib.rs"), "fn main() {}").unwrap();
+    link_dir(&outside, &checkout.join("vendor"));
+
+    let stderr = remove_git_repos(&cargo_home);
+    assert!(!stderr.contains("Warning"), "{stderr}");
+    assert!(!cargo_home
+        .join("git/checkouts")
+        .join("repo-0123456789abcdef")
+        .exists());
+    assert!(outside.join("precious.txt").is_file());
+}
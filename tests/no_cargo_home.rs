@@ -20,6 +20,8 @@ fn run_tests() {
     // which would be a race condition
     CARGO_HOME_is_nonexisting_dir();
     CARGO_HOME_is_empty();
+    cargo_home_flag_overrides_CARGO_HOME();
+    several_cargo_homes();
 }
 
 #[allow(non_snake_case)]
@@ -62,3 +64,94 @@ fn CARGO_HOME_is_empty() {
     let re = Regex::new(r"Cargo cache.*\.cargo.*:").unwrap();
     assert!(re.is_match(&stdout));
 }
+
+#[allow(non_snake_case)]
+fn cargo_home_flag_overrides_CARGO_HOME() {
+    // --cargo-home wins over a CARGO_HOME that does not exist, also after a subcommand
+    let dir = tempfile::tempdir().unwrap();
+    let cargo_home = dir.path().join("ci-cache");
+    let archive =
+        cargo_home.join("registry/cache/index.crates.io-6f17d22bba15001f/foo-0.1.0.crate");
+    std::fs::create_dir_all(archive.parent().unwrap()).unwrap();
+    std::fs::write(&archive, "x".repeat(100)).unwrap();
+
+    let info = Command::new(bin_path())
+        .args(["--info", "--cargo-home"])
+        .arg(&cargo_home)
+        .env("CARGO_HOME", "./xyxyxxxyyyxxyxyxqwertywasd")
+        .output()
+        .unwrap();
+    assert!(info.status.success(), "bad exit status!");
+    let stdout = String::from_utf8_lossy(&info.stdout).into_owned();
+    assert!(
+        stdout.starts_with(&format!(
+            "--cargo-home set to '{}', using that!",
+            cargo_home.display()
+        )),
+        "{stdout}"
+    );
+
+    let trim = Command::new(bin_path())
+        .args(["trim", "--limit", "0B", "--dry-run", "--cache-dir"])
+        .arg(&cargo_home)
+        .env("CARGO_HOME", "./xyxyxxxyyyxxyxyxqwertywasd")
+        .output()
+        .unwrap();
+    assert!(trim.status.success(), "bad exit status!");
+    let stdout = String::from_utf8_lossy(&trim.stdout).into_owned();
+    assert!(stdout.contains("foo-0.1.0.crate"), "{stdout}");
+    assert!(archive.exists());
+}
+
+fn several_cargo_homes() {
+    // a glob picks up every cargo home, the summary has a line per cargo home and the total
+    let dir = tempfile::tempdir().unwrap();
+    for (user, size) in [("user1", 100), ("user2", 200)] {
+        let archive = dir
+            .path()
+            .join(user)
+            .join("registry/cache/index.crates.io-6f17d22bba15001f/foo-0.1.0.crate");
+        std::fs::create_dir_all(archive.parent().unwrap()).unwrap();
+        std::fs::write(&archive, "x".repeat(size)).unwrap();
+    }
+
+    let summary = Command::new(bin_path())
+        .arg("--cargo-home")
+        .arg(dir.path().join("user*"))
+        .env("CARGO_HOME", "./xyxyxxxyyyxxyxyxqwertywasd")
+        .output()
+        .unwrap();
+    assert!(summary.status.success(), "bad exit status!");
+    let stdout = String::from_utf8_lossy(&summary.stdout).into_owned();
+    assert!(
+        stdout.contains(&format!("{} ", dir.path().join("user1").display())),
+        "{stdout}"
+    );
+    assert!(
+        stdout.contains(&format!("{} ", dir.path().join("user2").display())),
+        "{stdout}"
+    );
+    assert!(stdout.contains("All 2 cargo homes"), "{stdout}");
+    assert!(stdout.contains("300 B"), "{stdout}");
+
+    // the removal commands run on each cargo home
+    let trim = Command::new(bin_path())
+        .args(["trim", "--limit", "0B", "--dry-run", "--cargo-home"])
+        .arg(dir.path().join("user1"))
+        .arg("--cache-dir")
+        .arg(dir.path().join("user2"))
+        .env("CARGO_HOME", "./xyxyxxxyyyxxyxyxqwertywasd")
+        .output()
+        .unwrap();
+    assert!(trim.status.success(), "bad exit status!");
+    let stdout = String::from_utf8_lossy(&trim.stdout).into_owned();
+    assert_eq!(stdout.matches("foo-0.1.0.crate").count(), 2, "{stdout}");
+
+    // a glob without a match is an error
+    let nothing = Command::new(bin_path())
+        .arg("--cargo-home")
+        .arg(dir.path().join("nobody*"))
+        .output()
+        .unwrap();
+    assert!(!nothing.status.success());
+}
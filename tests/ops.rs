@@ -0,0 +1,212 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// the library api for embedding the cleanups into other tools
+// the "ci-autoclean" build has no library
+#![cfg(any(not(feature = "ci-autoclean"), feature = "testing"))]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use cargo_cache::ops::{self, AutocleanOptions, CancelToken, CargoHome, Progress, TrimOptions};
+use pretty_assertions::assert_eq;
+
+/// remembers what it was told
+#[derive(Default)]
+struct Recorder {
+    removing: Vec<PathBuf>,
+}
+
+impl Progress for Recorder {
+    fn removing(&mut self, path: &Path, _size: u64) {
+        self.removing.push(path.to_path_buf());
+    }
+}
+
+/// a cargo home with one crate (archive and source) and one git checkout
+fn cargo_home(root: &Path) -> CargoHome {
+    let registry = "index.crates.io-6f17d22bba15001f";
+    let archives = root.join("registry/cache").join(registry);
+    let source = root.join("registry/src").join(registry).join("foo-1.0.0");
+    let checkout = root.join("git/checkouts/bar-1234/abcdef");
+    fs::create_dir_all(&archives).unwrap();
+    fs::create_dir_all(&source).unwrap();
+    fs::create_dir_all(&checkout).unwrap();
+    fs::write(archives.join("foo-1.0.0.crate"), "archive").unwrap();
+    fs::write(source.join("lib.rs"), "source").unwrap();
+    fs::write(checkout.join("lib.rs"), "checkout").unwrap();
+    CargoHome::at(root).unwrap()
+}
+
+#[test]
+fn autoclean() {
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path();
+    let cargo_home = cargo_home(root);
+    let expected = vec![root.join("registry/src"), root.join("git/checkouts")];
+
+    // a dry run only reports
+    let mut recorder = Recorder::default();
+    let options = AutocleanOptions {
+        dry_run: true,
+        ..AutocleanOptions::default()
+    };
+    let summary = ops::autoclean(&cargo_home, &options, &mut recorder).unwrap();
+    assert_eq!(recorder.removing, expected);
+    assert_eq!(
+        summary
+            .removed
+            .iter()
+            .map(|(path, _)| path)
+            .collect::<Vec<_>>(),
+        expected.iter().collect::<Vec<_>>()
+    );
+    assert!(summary.freed() > 0);
+    assert!(root.join("registry/src").exists());
+
+    let summary = ops::autoclean(&cargo_home, &AutocleanOptions::default(), &mut ()).unwrap();
+    assert_eq!(summary.removed.len(), 2);
+    assert!(summary.failed.is_empty());
+    assert!(!root.join("registry/src").exists());
+    assert!(!root.join("git/checkouts").exists());
+    // the archive is kept
+    assert!(root
+        .join("registry/cache/index.crates.io-6f17d22bba15001f/foo-1.0.0.crate")
+        .exists());
+}
+
+#[test]
+fn trim() {
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path();
+    let cargo_home = cargo_home(root);
+
+    // everything fits
+    let options = TrimOptions {
+        limit: 1024 * 1024 * 1024,
+        dry_run: false,
+    };
+    let summary = ops::trim(&cargo_home, &options, &mut ()).unwrap();
+    assert_eq!(summary, ops::Summary::default());
+
+    // nothing fits
+    let options = TrimOptions {
+        limit: 0,
+        dry_run: false,
+    };
+    let summary = ops::trim(&cargo_home, &options, &mut ()).unwrap();
+    assert_eq!(summary.removed.len(), 3);
+    assert!(!root
+        .join("registry/cache/index.crates.io-6f17d22bba15001f/foo-1.0.0.crate")
+        .exists());
+}
+
+#[test]
+fn no_cargo_home() {
+    let tmp = tempfile::tempdir().unwrap();
+    let error = CargoHome::at(tmp.path().join("missing")).unwrap_err();
+    assert!(!error.to_string().is_empty());
+}
+
+#[test]
+fn background_trim() {
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path().to_path_buf();
+    let cargo_home = cargo_home(&root);
+
+    let options = TrimOptions {
+        limit: 0,
+        dry_run: true,
+    };
+    let task = ops::ScanTask::spawn(move || ops::trim(&cargo_home, &options, &mut ()));
+    let summary = ops::block_on(task).unwrap();
+    assert_eq!(summary.removed.len(), 3);
+    // a dry run removes nothing
+    assert!(root.join("registry/cache").is_dir());
+}
+
+#[test]
+fn cancelled_trim() {
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path().to_path_buf();
+    let cargo_home = cargo_home(&root);
+    let options = TrimOptions {
+        limit: 0,
+        dry_run: false,
+    };
+
+    let token = CancelToken::new();
+    token.cancel();
+    let task = ops::ScanTask::spawn_with_token(token, {
+        let cargo_home = cargo_home.clone();
+        let options = options.clone();
+        move || ops::trim(&cargo_home, &options, &mut ())
+    });
+    assert!(ops::block_on(task).unwrap().removed.is_empty());
+    assert!(root
+        .join("registry/cache/index.crates.io-6f17d22bba15001f/foo-1.0.0.crate")
+        .exists());
+
+    // only that task was cancelled
+    let summary = ops::trim(&cargo_home, &options, &mut ()).unwrap();
+    assert_eq!(summary.removed.len(), 3);
+}
+
+#[test]
+fn ignore_list_of_each_cargo_home() {
+    let tmp = tempfile::tempdir().unwrap();
+    let first_root = tmp.path().join("first");
+    let second_root = tmp.path().join("second");
+    let first = cargo_home(&first_root);
+    // the ignore file of a cargo home is read when it is opened
+    fs::create_dir_all(&second_root).unwrap();
+    fs::write(second_root.join(".cargocacheignore"), "foo-1.0.0*\n").unwrap();
+    let second = cargo_home(&second_root);
+
+    let options = TrimOptions {
+        limit: 0,
+        dry_run: false,
+    };
+    // the first cargo home ignores nothing even though the second one ignores foo-1.0.0
+    let summary = ops::trim(&first, &options, &mut ()).unwrap();
+    assert_eq!(summary.removed.len(), 3);
+    let summary = ops::trim(&second, &options, &mut ()).unwrap();
+    assert_eq!(
+        summary
+            .removed
+            .iter()
+            .map(|(path, _)| path.strip_prefix(&second_root).unwrap())
+            .collect::<Vec<_>>(),
+        vec![Path::new("git/checkouts/bar-1234/abcdef")]
+    );
+    assert!(second_root
+        .join("registry/cache/index.crates.io-6f17d22bba15001f/foo-1.0.0.crate")
+        .exists());
+}
+
+#[test]
+fn unsafe_cargo_home() {
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path();
+    fs::write(root.join("notes.txt"), "not cargo").unwrap();
+    let cargo_home = CargoHome::at(root).unwrap();
+
+    // a dry run is fine
+    let options = AutocleanOptions {
+        dry_run: true,
+        ..AutocleanOptions::default()
+    };
+    assert!(ops::autoclean(&cargo_home, &options, &mut ()).is_ok());
+
+    let error = ops::autoclean(&cargo_home, &AutocleanOptions::default(), &mut ()).unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("neither registry/, git/ nor .crates.toml"));
+    assert!(root.join("notes.txt").exists());
+}
@@ -0,0 +1,102 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// fuzz the parsers of cache item names and option values, needs the "testing" feature:
+// cargo test --features testing --test parsers
+
+use std::time::{Duration, SystemTime};
+
+use cargo_cache::testing::{self, strategies};
+use proptest::prelude::*;
+
+proptest! {
+    #[test]
+    fn crate_names_and_versions(item in prop_oneof![strategies::crate_archive(), strategies::crate_source()]) {
+        prop_assert_eq!(
+            testing::split_name_version(&item.file_name),
+            (item.name.as_str(), item.version.as_str())
+        );
+        prop_assert_eq!(
+            testing::parse_version(&item.path),
+            Some((item.name.clone(), item.version.clone()))
+        );
+    }
+
+    #[test]
+    fn hashes_are_stripped(item in prop_oneof![strategies::registry_index(), strategies::git_db()]) {
+        prop_assert_eq!(testing::strip_hash(&item.file_name), item.name.as_str());
+    }
+
+    #[test]
+    fn components_of_paths(item in strategies::cache_item()) {
+        prop_assert_eq!(testing::file_name(&item.path), item.file_name.clone());
+        prop_assert_eq!(testing::component_of_path(&item.path), item.component);
+        // a cargo home may be anywhere, even inside a directory called "registry"
+        prop_assert_eq!(
+            testing::component_of_path(&std::path::Path::new("/home/registry/.cargo").join(&item.path)),
+            item.component
+        );
+        if let Some(component) = item.component {
+            // like --remove-dir, a component pulls in the ones that are extracted from it
+            prop_assert!(testing::parse_components(component).unwrap().contains(&component));
+        }
+    }
+
+    #[test]
+    fn unexpected_names_do_not_panic(path in strategies::unexpected_path()) {
+        let file_name = testing::file_name(&path);
+        let (name, version) = testing::split_name_version(&file_name);
+        prop_assert!(file_name.starts_with(name) || file_name.is_empty());
+        prop_assert!(version.is_empty() || version.starts_with(|c: char| c.is_ascii_digit()));
+        let _ = testing::strip_hash(&file_name);
+        let _ = testing::parse_version(&path);
+        let _ = testing::component_of_path(&path);
+    }
+
+    #[test]
+    fn option_values_do_not_panic(
+        size in strategies::size_like(),
+        duration in strategies::duration_like(),
+        date in strategies::date_like(),
+        components in "[-a-z,]{0,30}",
+    ) {
+        let _ = testing::parse_size(&size);
+        let _ = testing::parse_duration(&duration);
+        let _ = testing::parse_date(&date);
+        let _ = testing::parse_age_cutoff(&date);
+        let _ = testing::parse_age_cutoff(&duration);
+        let _ = testing::parse_components(&components);
+    }
+
+    #[test]
+    fn sizes(bytes in 0..1_000_000_u64, unit in 0..5_u32) {
+        let suffix = ["B", "K", "M", "G", "T"][unit as usize];
+        prop_assert_eq!(
+            testing::parse_size(&format!("{bytes}{suffix}")),
+            Ok(bytes * 1024_u64.pow(unit))
+        );
+    }
+
+    #[test]
+    fn durations(days in 0..100_000_u64) {
+        prop_assert_eq!(
+            testing::parse_duration(&format!("{days}d")),
+            Ok(Duration::from_secs(days * 24 * 60 * 60))
+        );
+        // the cutoff of a duration is never in the future
+        let cutoff = testing::parse_age_cutoff(&format!("{days}d")).unwrap();
+        prop_assert!(cutoff <= SystemTime::now());
+    }
+
+    #[test]
+    fn valid_dates(date in strategies::valid_date()) {
+        prop_assert!(testing::parse_date(&date).is_ok());
+        prop_assert!(testing::parse_age_cutoff(&date).is_ok());
+    }
+}
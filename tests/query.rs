@@ -0,0 +1,51 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// "cargo cache query <pattern> --delete" must not delete anything with --dry-run, no matter if it is
+// passed to cargo-cache or to the subcommand
+
+#[path = "../src/test_helpers.rs"]
+mod test_helpers;
+
+use crate::test_helpers::bin_path;
+use std::fs;
+use std::process::Command;
+
+#[test]
+fn delete_honors_global_dry_run() {
+    let tmp = tempfile::tempdir().unwrap();
+    let cargo_home = tmp.path();
+    let archive = cargo_home
+        .join("registry/cache/index.crates.io-6f17d22bba15001f")
+        .join("foo-1.0.0.crate");
+    fs::create_dir_all(archive.parent().unwrap()).unwrap();
+    fs::write(&archive, "archive").unwrap();
+
+    let query = |args: &[&str]| {
+        let output = Command::new(bin_path())
+            .env("CARGO_HOME", cargo_home)
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{output:?}");
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+
+    for args in [
+        ["--dry-run", "query", "foo", "--delete"],
+        ["query", "foo", "--delete", "--dry-run"],
+    ] {
+        let stdout = query(&args);
+        assert!(stdout.contains("dry-run"), "{stdout}");
+        assert!(archive.is_file(), "{args:?} deleted {}", archive.display());
+    }
+
+    let _ = query(&["query", "foo", "--delete"]);
+    assert!(!archive.exists());
+}
@@ -0,0 +1,65 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// "cargo cache remove <crate>" exits right after removing, its dry run has to show what it would free
+
+#[path = "../src/test_helpers.rs"]
+mod test_helpers;
+
+use crate::test_helpers::bin_path;
+use std::fs;
+use std::process::Command;
+
+#[test]
+fn dry_run_shows_what_it_would_free() {
+    let tmp = tempfile::tempdir().unwrap();
+    let cargo_home = tmp.path();
+    let registry = "index.crates.io-6f17d22bba15001f";
+    let archive = cargo_home
+        .join("registry/cache")
+        .join(registry)
+        .join("foo-1.0.0.crate");
+    let source = cargo_home
+        .join("registry/src")
+        .join(registry)
+        .join("foo-1.0.0");
+    fs::create_dir_all(archive.parent().unwrap()).unwrap();
+    fs::create_dir_all(&source).unwrap();
+    fs::write(&archive, "archive").unwrap();
+    fs::write(source.join("lib.rs"), "source").unwrap();
+
+    let remove = |dry_run: bool| {
+        let mut command = Command::new(bin_path());
+        let _ = command
+            .env("CARGO_HOME", cargo_home)
+            .args(["remove", "foo"]);
+        if dry_run {
+            let _ = command.arg("--dry-run");
+        }
+        let output = command.output().unwrap();
+        assert!(output.status.success(), "{output:?}");
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+
+    let stdout = remove(true);
+    assert!(stdout.contains("registry-crate-cache 1"), "{stdout}");
+    assert!(stdout.contains("registry-sources     1"), "{stdout}");
+    assert!(
+        stdout.contains("dry-run: would free approximately"),
+        "{stdout}"
+    );
+    assert!(archive.is_file());
+    assert!(source.is_dir());
+
+    let stdout = remove(false);
+    assert!(stdout.contains("Freed"), "{stdout}");
+    assert!(!stdout.contains("would free"), "{stdout}");
+    assert!(!archive.exists());
+    assert!(!source.exists());
+}
@@ -134,3 +134,110 @@ fn remove_dirs() {
         assert!(size_before > size_after);
     } // for param in ..
 }
+
+#[test]
+fn remove_dirs_of_single_registry() {
+    // a cargo home with crates of crates.io and of a private registry
+    let tmp = tempfile::tempdir().unwrap();
+    let cargo_home = tmp.path();
+    let crates_io = "index.crates.io-6f17d22bba15001f";
+    let private = "my-registry.example.com-0123456789abcdef";
+    for registry in [crates_io, private] {
+        let archives = cargo_home.join("registry/cache").join(registry);
+        let source = cargo_home
+            .join("registry/src")
+            .join(registry)
+            .join("foo-1.0.0");
+        std::fs::create_dir_all(&archives).unwrap();
+        std::fs::create_dir_all(&source).unwrap();
+        std::fs::create_dir_all(cargo_home.join("registry/index").join(registry)).unwrap();
+        std::fs::write(archives.join("foo-1.0.0.crate"), "archive").unwrap();
+        std::fs::write(source.join("lib.rs"), "source").unwrap();
+    }
+
+    let cargo_cache = Command::new(bin_path())
+        .env("CARGO_HOME", cargo_home)
+        .args([
+            "--remove-dir",
+            "registry-crate-cache:my-registry.example.com",
+        ])
+        .output()
+        .unwrap();
+    assert!(cargo_cache.status.success(), "{cargo_cache:?}");
+
+    // the archives and sources of the private registry are gone, everything else is kept
+    for component in ["cache", "src"] {
+        let registry = cargo_home.join("registry").join(component);
+        assert!(!registry.join(private).exists());
+        assert!(registry.join(crates_io).exists());
+    }
+    assert!(cargo_home.join("registry/index").join(private).exists());
+
+    // a registry that does not exist is not silently ignored
+    let cargo_cache = Command::new(bin_path())
+        .env("CARGO_HOME", cargo_home)
+        .args(["--remove-dir", "registry-index:my-registry.example.org"])
+        .output()
+        .unwrap();
+    assert!(!cargo_cache.status.success());
+    assert!(String::from_utf8_lossy(&cargo_cache.stderr)
+        .contains("there is no registry \"my-registry.example.org\" in the cache"));
+    assert!(cargo_home.join("registry/index").join(private).exists());
+}
+
+#[test]
+fn max_age_only_removes_old_items() {
+    let tmp = tempfile::tempdir().unwrap();
+    let cargo_home = tmp.path();
+    let crates_io = "index.crates.io-6f17d22bba15001f";
+    let private = "my-registry.example.com-0123456789abcdef";
+    for registry in [crates_io, private] {
+        let archives = cargo_home.join("registry/cache").join(registry);
+        let source = cargo_home
+            .join("registry/src")
+            .join(registry)
+            .join("foo-1.0.0");
+        std::fs::create_dir_all(&archives).unwrap();
+        std::fs::create_dir_all(&source).unwrap();
+        std::fs::write(archives.join("foo-1.0.0.crate"), "archive").unwrap();
+        std::fs::write(source.join("lib.rs"), "source").unwrap();
+    }
+    let archive = |registry: &str| {
+        cargo_home
+            .join("registry/cache")
+            .join(registry)
+            .join("foo-1.0.0.crate")
+    };
+    let source = |registry: &str| {
+        cargo_home
+            .join("registry/src")
+            .join(registry)
+            .join("foo-1.0.0")
+    };
+    let remove_dir = |max_age: &str| {
+        Command::new(bin_path())
+            .env("CARGO_HOME", cargo_home)
+            .env("CARGO_CACHE_MAX_AGE", max_age)
+            .args([
+                "--remove-dir",
+                "registry-crate-cache:my-registry.example.com",
+            ])
+            .output()
+            .unwrap()
+    };
+
+    // everything was used after that date
+    let cargo_cache = remove_dir("2000.01.01");
+    assert!(cargo_cache.status.success(), "{cargo_cache:?}");
+    assert!(archive(private).exists());
+    assert!(source(private).exists());
+
+    // everything was used before that date, only the items of the registry are removed
+    let cargo_cache = remove_dir("2100.01.01");
+    assert!(cargo_cache.status.success(), "{cargo_cache:?}");
+    assert!(!archive(private).exists());
+    assert!(!source(private).exists());
+    assert!(cargo_home.join("registry/cache").join(private).is_dir());
+    assert!(archive(crates_io).exists());
+    assert!(source(crates_io).exists());
+}
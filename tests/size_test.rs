@@ -108,7 +108,9 @@ fn build_and_check_size_test() {
   Registry:                     .* MB
     Registry index:             .* [kM]B
    .. crate archives:           .* kB
+      .* crates, .* versions
    .. crate source checkouts:   .* MB
+      .* crates, .* versions
   Git db:                       .* 0  B
     0 bare git repos:           .* 0  B
     0 git repo checkouts:       .* 0  B",
@@ -151,7 +151,9 @@ fn spurious_files_in_cache_test() {
   Registry:                    .* MB
     Registry index:            .* kB
    .. crate archives:          .* kB
+      .* crates, .* versions
    .. crate source checkouts:  .* MB
+      .* crates, .* versions
   Git db:                            0  B
     0 bare git repos:                0  B
     0 git repo checkouts:            0  B",
@@ -0,0 +1,99 @@
+// Copyright 2017-2020 Matthias Krüger. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// --use-system-trash, only on the freedesktop trash which can be pointed at a temporary directory
+// via XDG_DATA_HOME, we don't want to fill the trash of whoever runs the tests
+#![cfg(all(
+    feature = "trash",
+    unix,
+    not(target_os = "macos"),
+    not(target_os = "ios"),
+    not(target_os = "android")
+))]
+
+#[path = "../src/test_helpers.rs"]
+mod test_helpers;
+
+use crate::test_helpers::bin_path;
+use std::path::Path;
+use std::process::Command;
+
+fn fake_cargo_home(cargo_home: &Path) {
+    let archive =
+        cargo_home.join("registry/cache/index.crates.io-6f17d22bba15001f/foo-0.1.0.crate");
+    std::fs::create_dir_all(archive.parent().unwrap()).unwrap();
+    std::fs::write(&archive, "x".repeat(100)).unwrap();
+    let source = cargo_home.join("registry/src/index.crates.io-6f17d22bba15001f/foo-0.1.0");
+    std::fs::create_dir_all(&source).unwrap();
+    std::fs::write(source.join("lib.rs"), "x".repeat(100)).unwrap();
+}
+
+#[test]
+fn removed_items_go_to_the_trash() {
+    let dir = tempfile::tempdir().unwrap();
+    let cargo_home = dir.path().join("cargo_home");
+    let data_home = dir.path().join("data_home");
+    fake_cargo_home(&cargo_home);
+
+    let trim = Command::new(bin_path())
+        .args([
+            "trim",
+            "--limit",
+            "0B",
+            "--use-system-trash",
+            "--cargo-home",
+        ])
+        .arg(&cargo_home)
+        .env("XDG_DATA_HOME", &data_home)
+        .env("DISPLAY", ":0")
+        .output()
+        .unwrap();
+    assert!(trim.status.success(), "bad exit status!");
+    let stderr = String::from_utf8_lossy(&trim.stderr).into_owned();
+    assert!(!stderr.contains("Warning"), "{stderr}");
+
+    let trash = data_home.join("Trash/files");
+    assert!(trash.join("foo-0.1.0.crate").is_file());
+    assert!(trash.join("foo-0.1.0").join("lib.rs").is_file());
+    assert!(!cargo_home
+        .join("registry/src/index.crates.io-6f17d22bba15001f/foo-0.1.0")
+        .exists());
+}
+
+#[test]
+fn no_desktop_removes_permanently() {
+    let dir = tempfile::tempdir().unwrap();
+    let cargo_home = dir.path().join("cargo_home");
+    let data_home = dir.path().join("data_home");
+    fake_cargo_home(&cargo_home);
+
+    let remove = Command::new(bin_path())
+        .args([
+            "--remove-dir",
+            "registry-sources",
+            "--use-system-trash",
+            "--cargo-home",
+        ])
+        .arg(&cargo_home)
+        .env("XDG_DATA_HOME", &data_home)
+        .env_remove("DISPLAY")
+        .env_remove("WAYLAND_DISPLAY")
+        .env_remove("XDG_CURRENT_DESKTOP")
+        .env_remove("DESKTOP_SESSION")
+        .output()
+        .unwrap();
+    assert!(remove.status.success(), "bad exit status!");
+    let stderr = String::from_utf8_lossy(&remove.stderr).into_owned();
+    assert!(
+        stderr.contains("Warning: --use-system-trash: no desktop session with a trash was found"),
+        "{stderr}"
+    );
+    assert!(!cargo_home.join("registry/src").exists());
+    assert!(!data_home.exists());
+}
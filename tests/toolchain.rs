@@ -36,3 +36,132 @@ fn no_rustup_installed_toolchain() {
         "Could not find any toolchains installed via rustup!\n"
     );
 }
+
+/// a rustup home with two toolchains which both have docs installed
+fn fake_rustup_home(rustup_home: &std::path::Path) {
+    for toolchain in [
+        "stable-x86_64-unknown-linux-gnu",
+        "nightly-2023-01-01-x86_64-unknown-linux-gnu",
+    ] {
+        let toolchain = rustup_home.join("toolchains").join(toolchain);
+        let rustlib = toolchain.join("lib/rustlib");
+        std::fs::create_dir_all(toolchain.join("bin")).unwrap();
+        std::fs::create_dir_all(toolchain.join("share/doc/rust/html")).unwrap();
+        std::fs::create_dir_all(&rustlib).unwrap();
+        std::fs::write(toolchain.join("bin/rustc"), "x".repeat(1000)).unwrap();
+        std::fs::write(
+            toolchain.join("share/doc/rust/html/index.html"),
+            "x".repeat(3000),
+        )
+        .unwrap();
+        std::fs::write(
+            rustlib.join("manifest-rustc-x86_64-unknown-linux-gnu"),
+            "file:bin/rustc\n",
+        )
+        .unwrap();
+        std::fs::write(
+            rustlib.join("manifest-rust-docs-x86_64-unknown-linux-gnu"),
+            "dir:share/doc/rust/html\n",
+        )
+        .unwrap();
+        std::fs::write(
+            rustlib.join("components"),
+            "rustc-x86_64-unknown-linux-gnu\nrust-docs-x86_64-unknown-linux-gnu\n",
+        )
+        .unwrap();
+    }
+}
+
+/// run "cargo cache toolchain" on a rustup home without rustup being around
+fn toolchain_cmd(rustup_home: &std::path::Path, args: &[&str]) -> std::process::Output {
+    Command::new(bin_path())
+        .arg("toolchain")
+        .args(args)
+        .env("RUSTUP_HOME", rustup_home)
+        .env("PATH", "/nonexistent")
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn toolchain_breakdown() {
+    let dir = tempfile::tempdir().unwrap();
+    fake_rustup_home(dir.path());
+
+    let output = toolchain_cmd(dir.path(), &["--breakdown"]);
+    assert!(output.status.success(), "bad exit status!");
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    assert!(
+        stdout.ends_with(
+            "stable-x86_64-unknown-linux-gnu     Size     Percentage
+rust-docs-x86_64-unknown-linux-gnu  3.02 kB  73.67 %
+rustc-x86_64-unknown-linux-gnu      1.01 kB  24.73 %
+other                               66 B     1.61 %
+"
+        ),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn toolchain_remove_docs() {
+    let dir = tempfile::tempdir().unwrap();
+    fake_rustup_home(dir.path());
+    let docs = dir
+        .path()
+        .join("toolchains/stable-x86_64-unknown-linux-gnu/share/doc/rust/html");
+
+    let output = toolchain_cmd(dir.path(), &["--remove-docs", "--dry-run"]);
+    assert!(output.status.success(), "bad exit status!");
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    assert!(
+        stdout.ends_with("dry-run: would remove 6.05 kB of docs\n"),
+        "{stdout}"
+    );
+    assert!(docs.exists());
+
+    let output = toolchain_cmd(dir.path(), &["--remove-docs", "--yes"]);
+    assert!(output.status.success(), "bad exit status!");
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    assert!(stdout.ends_with("Removed 6.05 kB of docs\n"), "{stdout}");
+    assert!(!docs.exists());
+    let components = std::fs::read_to_string(
+        dir.path()
+            .join("toolchains/stable-x86_64-unknown-linux-gnu/lib/rustlib/components"),
+    )
+    .unwrap();
+    assert_eq!(components, "rustc-x86_64-unknown-linux-gnu\n");
+}
+
+#[test]
+fn toolchain_remove_toolchain() {
+    let dir = tempfile::tempdir().unwrap();
+    fake_rustup_home(dir.path());
+    let nightly = dir
+        .path()
+        .join("toolchains/nightly-2023-01-01-x86_64-unknown-linux-gnu");
+
+    let output = toolchain_cmd(dir.path(), &["--remove-toolchain", "beta"]);
+    assert!(!output.status.success(), "unknown toolchain was removed");
+    let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+    assert!(
+        stderr.contains("No single toolchain \"beta\" is installed"),
+        "{stderr}"
+    );
+
+    let output = toolchain_cmd(
+        dir.path(),
+        &["--remove-toolchain", "nightly-2023-01-01", "--yes"],
+    );
+    assert!(output.status.success(), "bad exit status!");
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    assert!(
+        stdout.starts_with("Removed toolchain \"nightly-2023-01-01-x86_64-unknown-linux-gnu\""),
+        "{stdout}"
+    );
+    assert!(!nightly.exists());
+    assert!(dir
+        .path()
+        .join("toolchains/stable-x86_64-unknown-linux-gnu")
+        .exists());
+}